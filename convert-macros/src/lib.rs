@@ -3,7 +3,7 @@ use std::iter;
 use manyhow::{ensure, error_message, manyhow, Result};
 use proc_macro_crate::{crate_name, FoundCrate};
 use quote::{format_ident, quote, ToTokens};
-use syn::{parse_quote, Attribute, DeriveInput, Path};
+use syn::{parse_quote, Attribute, Data, DeriveInput, Path};
 
 /// Tries to resolve the path to `extism_convert` dynamically, falling back to feature flags when unsuccessful.
 fn convert_path() -> Path {
@@ -29,6 +29,14 @@ fn convert_path() -> Path {
     }
 }
 
+/// Structs and enums are supported, the encoding can't represent unions
+fn check_data(data: &Data) -> Result<()> {
+    if let Data::Union(u) = data {
+        manyhow::bail!(u.union_token, "unions are not supported"; help = "use a struct or an enum");
+    }
+    Ok(())
+}
+
 fn extract_encoding(attrs: &[Attribute]) -> Result<Path> {
     let encodings: Vec<_> = attrs
         .iter()
@@ -49,9 +57,11 @@ pub fn to_bytes(
         attrs,
         ident,
         generics,
+        data,
         ..
     }: DeriveInput,
 ) -> Result {
+    check_data(&data)?;
     let encoding = extract_encoding(&attrs)?;
     let convert = convert_path();
 
@@ -86,9 +96,11 @@ pub fn from_bytes(
         attrs,
         ident,
         mut generics,
+        data,
         ..
     }: DeriveInput,
 ) -> Result {
+    check_data(&data)?;
     let encoding = extract_encoding(&attrs)?;
     let convert = convert_path();
     generics
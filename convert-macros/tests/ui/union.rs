@@ -0,0 +1,17 @@
+use extism_convert_macros::{FromBytes, ToBytes};
+
+#[derive(ToBytes)]
+#[encoding(Json)]
+union ToBytesUnion {
+    a: u32,
+    b: f32,
+}
+
+#[derive(FromBytes)]
+#[encoding(Json)]
+union FromBytesUnion {
+    a: u32,
+    b: f32,
+}
+
+fn main() {}
@@ -13,9 +13,45 @@ use base64::Engine;
 ///
 /// This will create a struct `struct MyJson<T>(pub T)` and implement [`ToBytes`] using [`serde_json::to_vec`]
 /// and [`FromBytesOwned`] using [`serde_json::from_slice`]
+///
+/// A function that writes the encoded value to an `&mut std::io::Write` can also be provided, it's used
+/// by [`ToBytes::to_writer`] so values can be encoded into an existing buffer:
+///
+/// ```
+/// extism_convert::encoding!(MyJson, serde_json::to_vec, serde_json::from_slice, serde_json::to_writer);
+/// ```
 #[macro_export]
 macro_rules! encoding {
+    ($pub:vis $name:ident, $to_vec:expr, $from_slice:expr, $to_writer:expr) => {
+        $crate::encoding!(@impl $pub $name, $to_vec, $from_slice);
+
+        impl<'a, T: serde::Serialize> $crate::ToBytes<'a> for $name<T> {
+            type Bytes = Vec<u8>;
+
+            fn to_bytes(&self) -> std::result::Result<Self::Bytes, $crate::Error> {
+                let enc = $to_vec(&self.0)?;
+                std::result::Result::Ok(enc)
+            }
+
+            fn to_writer<W: std::io::Write>(&self, w: &mut W) -> std::result::Result<(), $crate::Error> {
+                $to_writer(w, &self.0)?;
+                std::result::Result::Ok(())
+            }
+        }
+    };
     ($pub:vis $name:ident, $to_vec:expr, $from_slice:expr) => {
+        $crate::encoding!(@impl $pub $name, $to_vec, $from_slice);
+
+        impl<'a, T: serde::Serialize> $crate::ToBytes<'a> for $name<T> {
+            type Bytes = Vec<u8>;
+
+            fn to_bytes(&self) -> std::result::Result<Self::Bytes, $crate::Error> {
+                let enc = $to_vec(&self.0)?;
+                std::result::Result::Ok(enc)
+            }
+        }
+    };
+    (@impl $pub:vis $name:ident, $to_vec:expr, $from_slice:expr) => {
         #[doc = concat!(stringify!($name), " encoding")]
         #[derive(Debug)]
         $pub struct $name<T>(pub T);
@@ -38,22 +74,110 @@ macro_rules! encoding {
                 std::result::Result::Ok($name(x))
             }
         }
+    };
+}
 
-        impl<'a, T: serde::Serialize> $crate::ToBytes<'a> for $name<T> {
+encoding!(pub Json, serde_json::to_vec, serde_json::from_slice, serde_json::to_writer);
+
+#[cfg(feature = "msgpack")]
+encoding!(pub Msgpack, rmp_serde::to_vec, rmp_serde::from_slice, rmp_serde::encode::write);
+
+#[cfg(feature = "cbor")]
+encoding!(pub Cbor, cbor::to_vec, cbor::from_slice, cbor::to_writer);
+
+/// Creates a newtype like `encoding!`, but `FromBytes` is implemented for any `T: serde::Deserialize<'a>`
+/// so the decoded value can borrow from the input
+macro_rules! borrowed_encoding {
+    ($(#[$meta:meta])* $name:ident, $to_vec:expr, $from_slice:expr) => {
+        $(#[$meta])*
+        #[derive(Debug)]
+        pub struct $name<T>(pub T);
+
+        impl<T> $name<T> {
+            pub fn into_inner(self) -> T {
+                self.0
+            }
+        }
+
+        impl<T> From<T> for $name<T> {
+            fn from(data: T) -> Self {
+                Self(data)
+            }
+        }
+
+        impl<'a, T: serde::Serialize> ToBytes<'a> for $name<T> {
             type Bytes = Vec<u8>;
 
-            fn to_bytes(&self) -> std::result::Result<Self::Bytes, $crate::Error> {
-                let enc = $to_vec(&self.0)?;
-                std::result::Result::Ok(enc)
+            fn to_bytes(&self) -> Result<Self::Bytes, Error> {
+                Ok($to_vec(&self.0)?)
+            }
+        }
+
+        impl<'a, T: serde::Deserialize<'a>> FromBytes<'a> for $name<T> {
+            fn from_bytes(data: &'a [u8]) -> Result<Self, Error> {
+                Ok($name($from_slice(data)?))
             }
         }
     };
 }
 
-encoding!(pub Json, serde_json::to_vec, serde_json::from_slice);
+borrowed_encoding!(
+    /// JSON encoding that decodes without copying, like [`Json`] but the inner value can borrow from the
+    /// input, e.g. `&str` fields or types using `#[serde(borrow)]`. When decoding plugin output the value
+    /// borrows from plugin memory, so it has to be dropped before the plugin is called again.
+    ///
+    /// Strings containing escape sequences can't be borrowed, use `Cow<'a, str>` with `#[serde(borrow)]`
+    /// for fields that may contain them.
+    ///
+    /// ```
+    /// use extism_convert::{FromBytes, JsonRef};
+    ///
+    /// #[derive(serde::Deserialize)]
+    /// struct Greeting<'a> {
+    ///     name: &'a str,
+    /// }
+    ///
+    /// let data = br#"{"name":"Benjamin"}"#;
+    /// let JsonRef(greeting): JsonRef<Greeting> = FromBytes::from_bytes(data)?;
+    /// assert_eq!(greeting.name, "Benjamin");
+    /// # Ok::<(), extism_convert::Error>(())
+    /// ```
+    JsonRef,
+    serde_json::to_vec,
+    serde_json::from_slice
+);
 
 #[cfg(feature = "msgpack")]
-encoding!(pub Msgpack, rmp_serde::to_vec, rmp_serde::from_slice);
+borrowed_encoding!(
+    /// Msgpack encoding that decodes without copying, see [`JsonRef`]
+    MsgpackRef,
+    rmp_serde::to_vec,
+    rmp_serde::from_slice
+);
+
+// `ciborium` takes the value before the writer, these match the argument order used by `encoding!`
+#[cfg(feature = "cbor")]
+mod cbor {
+    use crate::Error;
+
+    pub(crate) fn to_vec<T: serde::Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+        let mut buf = vec![];
+        ciborium::into_writer(value, &mut buf)?;
+        Ok(buf)
+    }
+
+    pub(crate) fn from_slice<T: serde::de::DeserializeOwned>(data: &[u8]) -> Result<T, Error> {
+        Ok(ciborium::from_reader(data)?)
+    }
+
+    pub(crate) fn to_writer<W: std::io::Write, T: serde::Serialize>(
+        w: &mut W,
+        value: &T,
+    ) -> Result<(), Error> {
+        ciborium::into_writer(value, w)?;
+        Ok(())
+    }
+}
 
 impl ToBytes<'_> for serde_json::Value {
     type Bytes = Vec<u8>;
@@ -61,6 +185,11 @@ impl ToBytes<'_> for serde_json::Value {
     fn to_bytes(&self) -> Result<Self::Bytes, Error> {
         Ok(serde_json::to_vec(self)?)
     }
+
+    fn to_writer<W: std::io::Write>(&self, w: &mut W) -> Result<(), Error> {
+        serde_json::to_writer(w, self)?;
+        Ok(())
+    }
 }
 
 impl FromBytesOwned for serde_json::Value {
@@ -109,6 +238,49 @@ impl FromBytesOwned for Base64<String> {
     }
 }
 
+/// Text encoding
+///
+/// Values are encoded using their `Display` implementation and decoded using `FromStr`, which works for
+/// types like `std::net::IpAddr` that don't have a serde representation Extism knows about.
+///
+/// ```
+/// use extism_convert::{FromBytes, Text, ToBytes};
+///
+/// let addr: std::net::IpAddr = "127.0.0.1".parse().unwrap();
+/// let bytes = Text(addr).to_bytes().unwrap();
+/// assert_eq!(bytes, "127.0.0.1");
+/// let Text(decoded) = Text::<std::net::IpAddr>::from_bytes(bytes.as_bytes()).unwrap();
+/// assert_eq!(decoded, addr);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Text<T>(pub T);
+
+impl<T> From<T> for Text<T> {
+    fn from(data: T) -> Self {
+        Self(data)
+    }
+}
+
+impl<T: std::fmt::Display> ToBytes<'_> for Text<T> {
+    type Bytes = String;
+
+    fn to_bytes(&self) -> Result<Self::Bytes, Error> {
+        Ok(self.0.to_string())
+    }
+}
+
+impl<T: std::str::FromStr> FromBytesOwned for Text<T>
+where
+    T::Err: std::fmt::Display + std::fmt::Debug + Send + Sync + 'static,
+{
+    fn from_bytes_owned(data: &[u8]) -> Result<Self, Error> {
+        std::str::from_utf8(data)?
+            .parse()
+            .map(Text)
+            .map_err(Error::msg)
+    }
+}
+
 /// Protobuf encoding
 ///
 /// Allows for `prost` Protobuf messages to be used as arguments to Extism plugin calls
@@ -139,6 +311,60 @@ impl<T: Default + prost::Message> FromBytesOwned for Prost<T> {
     }
 }
 
+/// Protobuf encoding for `prost` messages that can be used with `#[derive(ToBytes, FromBytes)]`
+///
+/// The derive macros encode a reference to the value, so unlike [`Prost`] `ToBytes` is implemented for
+/// references to messages, e.g. `Proto(&message)`
+///
+/// ```
+/// use extism_convert::{FromBytes, Proto, ToBytes};
+///
+/// #[derive(Clone, PartialEq, prost::Message, ToBytes, FromBytes)]
+/// #[encoding(Proto)]
+/// struct Greeting {
+///     #[prost(string, tag = "1")]
+///     name: String,
+/// }
+///
+/// let greeting = Greeting { name: "hi".into() };
+/// let bytes = greeting.to_bytes()?;
+/// assert_eq!(Greeting::from_bytes(&bytes)?, greeting);
+/// # Ok::<(), extism_convert::Error>(())
+/// ```
+#[cfg(feature = "prost")]
+#[derive(Debug)]
+pub struct Proto<T>(pub T);
+
+#[cfg(feature = "prost")]
+impl<T> Proto<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+#[cfg(feature = "prost")]
+impl<T> From<T> for Proto<T> {
+    fn from(data: T) -> Self {
+        Self(data)
+    }
+}
+
+#[cfg(feature = "prost")]
+impl<T: prost::Message> ToBytes<'_> for Proto<&T> {
+    type Bytes = Vec<u8>;
+
+    fn to_bytes(&self) -> Result<Self::Bytes, Error> {
+        Ok(self.0.encode_to_vec())
+    }
+}
+
+#[cfg(feature = "prost")]
+impl<T: Default + prost::Message> FromBytesOwned for Proto<T> {
+    fn from_bytes_owned(data: &[u8]) -> Result<Self, Error> {
+        Ok(Proto(T::decode(data)?))
+    }
+}
+
 /// Protobuf encoding
 ///
 /// Allows for `rust-protobuf` Protobuf messages to be used as arguments to Extism plugin calls
@@ -183,5 +409,80 @@ impl<'a, T: bytemuck::Pod> FromBytes<'a> for Raw<'a, T> {
     }
 }
 
+/// `RawValue` copies a plain-old-data value, like [`Raw`], but owns the decoded value, so the input doesn't
+/// need to be aligned. It can be used with `#[derive(ToBytes, FromBytes)]` and also accepts slices,
+/// which are encoded without any conversion.
+///
+/// The type must implement [bytemuck::Pod](https://docs.rs/bytemuck/latest/bytemuck/trait.Pod.html),
+/// deriving it checks that a `#[repr(C)]` struct has no padding at compile time. Values use the native
+/// layout, which is little-endian since the `raw` feature is only available on little-endian targets.
+///
+/// ```
+/// use extism_convert::{bytemuck, FromBytes, RawValue, ToBytes};
+///
+/// #[derive(Clone, Copy, Debug, PartialEq, ToBytes, FromBytes)]
+/// #[encoding(RawValue)]
+/// #[repr(C)]
+/// struct Point {
+///     x: f32,
+///     y: f32,
+/// }
+///
+/// unsafe impl bytemuck::Zeroable for Point {}
+/// unsafe impl bytemuck::Pod for Point {}
+///
+/// let p = Point { x: 1.0, y: 2.0 };
+/// let bytes = p.to_bytes()?;
+/// assert_eq!(bytes.len(), 8);
+/// assert_eq!(Point::from_bytes(&bytes)?, p);
+///
+/// let points = [p, p];
+/// assert_eq!(RawValue(&points[..]).to_bytes()?.len(), 16);
+/// # Ok::<(), extism_convert::Error>(())
+/// ```
+#[cfg(all(feature = "raw", target_endian = "little"))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawValue<T>(pub T);
+
+#[cfg(all(feature = "raw", target_endian = "little"))]
+impl<T> From<T> for RawValue<T> {
+    fn from(data: T) -> Self {
+        Self(data)
+    }
+}
+
+#[cfg(all(feature = "raw", target_endian = "little"))]
+impl<'a, T: bytemuck::Pod> ToBytes<'a> for RawValue<&'a T> {
+    type Bytes = &'a [u8];
+
+    fn to_bytes(&self) -> Result<Self::Bytes, Error> {
+        Ok(bytemuck::bytes_of(self.0))
+    }
+}
+
+#[cfg(all(feature = "raw", target_endian = "little"))]
+impl<'a, T: bytemuck::Pod> ToBytes<'a> for RawValue<&'a [T]> {
+    type Bytes = &'a [u8];
+
+    fn to_bytes(&self) -> Result<Self::Bytes, Error> {
+        Ok(bytemuck::cast_slice(self.0))
+    }
+}
+
+#[cfg(all(feature = "raw", target_endian = "little"))]
+impl<T: bytemuck::Pod> FromBytesOwned for RawValue<T> {
+    fn from_bytes_owned(data: &[u8]) -> Result<Self, Error> {
+        if data.len() != std::mem::size_of::<T>() {
+            anyhow::bail!(
+                "expected {} bytes for {}, found {}",
+                std::mem::size_of::<T>(),
+                std::any::type_name::<T>(),
+                data.len()
+            );
+        }
+        Ok(RawValue(bytemuck::pod_read_unaligned(data)))
+    }
+}
+
 #[cfg(all(feature = "raw", target_endian = "big"))]
 compile_error!("The raw feature is only supported on little endian targets");
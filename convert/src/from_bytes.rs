@@ -22,6 +22,24 @@ pub use extism_convert_macros::FromBytes;
 /// # Ok::<(), extism_convert::Error>(())
 /// ```
 ///
+/// Enums and generic types are supported too, as long as the encoding can decode them.
+///
+/// ```
+/// use extism_convert::{Json, FromBytes};
+/// use serde::Deserialize;
+///
+/// #[derive(FromBytes, Deserialize, PartialEq, Debug)]
+/// #[encoding(Json)]
+/// #[serde(tag = "type", rename_all = "snake_case")]
+/// enum Event<T> {
+///     Created { item: T },
+///     Deleted,
+/// }
+///
+/// assert_eq!(Event::<u32>::from_bytes(br#"{"type":"deleted"}"#)?, Event::Deleted);
+/// # Ok::<(), extism_convert::Error>(())
+/// ```
+///
 /// Custom encodings can also be used, through new-types with a single generic
 /// argument, i.e., `Type<T>(T)`, that implement `FromBytesOwned` for the struct.
 ///
@@ -157,6 +175,38 @@ impl FromBytesOwned for () {
     }
 }
 
+#[cfg(feature = "uuid")]
+impl FromBytesOwned for uuid::Uuid {
+    fn from_bytes_owned(data: &[u8]) -> Result<Self, Error> {
+        Ok(uuid::Uuid::try_parse_ascii(data)?)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl FromBytesOwned for chrono::DateTime<chrono::Utc> {
+    fn from_bytes_owned(data: &[u8]) -> Result<Self, Error> {
+        let t = chrono::DateTime::parse_from_rfc3339(std::str::from_utf8(data)?)?;
+        Ok(t.with_timezone(&chrono::Utc))
+    }
+}
+
+#[cfg(feature = "time")]
+impl FromBytesOwned for time::OffsetDateTime {
+    fn from_bytes_owned(data: &[u8]) -> Result<Self, Error> {
+        Ok(time::OffsetDateTime::parse(
+            std::str::from_utf8(data)?,
+            &time::format_description::well_known::Rfc3339,
+        )?)
+    }
+}
+
+#[cfg(feature = "rust_decimal")]
+impl FromBytesOwned for rust_decimal::Decimal {
+    fn from_bytes_owned(data: &[u8]) -> Result<Self, Error> {
+        Ok(std::str::from_utf8(data)?.parse()?)
+    }
+}
+
 impl<'a, T: FromBytes<'a>> FromBytes<'a> for std::io::Cursor<T> {
     fn from_bytes(data: &'a [u8]) -> Result<Self, Error> {
         Ok(std::io::Cursor::new(T::from_bytes(data)?))
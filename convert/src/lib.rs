@@ -1,9 +1,16 @@
 //! The [extism-convert](https://crates.io/crates/extism-convert) crate is used by the [Rust SDK](https://crates.io/crates/extism) and [Rust PDK](https://crates.io/crates/extism-pdk) to provide a shared interface for
 //! encoding and decoding values that can be passed to Extism function calls.
 //!
-//! A set of types (Json, Msgpack) that can be used to specify a serde encoding are also provided. These are
+//! A set of types (Json, Msgpack, Cbor) that can be used to specify a serde encoding are also provided. These are
 //! similar to [axum extractors](https://docs.rs/axum/latest/axum/extract/index.html#intro) - they are
 //! implemented as a tuple struct with a single field that is meant to be extracted using pattern matching.
+//! [`JsonRef`] and [`MsgpackRef`] work the same way, but decode values that borrow from the input instead of
+//! copying it.
+//!
+//! [`Text`] encodes values using `Display` and `FromStr`. With the `uuid`, `chrono`, `time` and
+//! `rust_decimal` features, `ToBytes` and `FromBytes` are also implemented for `uuid::Uuid`,
+//! `chrono::DateTime<Utc>`, `time::OffsetDateTime` and `rust_decimal::Decimal`, using their usual string
+//! representation. Timestamps are encoded as RFC 3339.
 
 // Makes proc-macros able to resolve `::extism_convert` correctly
 extern crate self as extism_convert;
@@ -16,19 +23,25 @@ mod from_bytes;
 mod memory_handle;
 mod to_bytes;
 
-pub use encoding::{Base64, Json};
+pub use encoding::{Base64, Json, JsonRef, Text};
+
+#[cfg(feature = "cbor")]
+pub use encoding::Cbor;
 
 #[cfg(feature = "msgpack")]
-pub use encoding::Msgpack;
+pub use encoding::{Msgpack, MsgpackRef};
 
 #[cfg(feature = "prost")]
-pub use encoding::Prost;
+pub use encoding::{Prost, Proto};
 
 #[cfg(feature = "protobuf")]
 pub use encoding::Protobuf;
 
 #[cfg(all(feature = "raw", target_endian = "little"))]
-pub use encoding::Raw;
+pub use encoding::{Raw, RawValue};
+
+#[cfg(all(feature = "raw", target_endian = "little"))]
+pub use bytemuck;
 
 pub use from_bytes::{FromBytes, FromBytesOwned};
 pub use memory_handle::MemoryHandle;
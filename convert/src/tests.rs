@@ -19,6 +19,43 @@ fn roundtrip_json() {
     assert_eq!(x, y);
 }
 
+#[test]
+fn borrowed_json() {
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct Borrowed<'a> {
+        a: &'a str,
+        #[serde(borrow)]
+        b: std::borrow::Cow<'a, str>,
+    }
+
+    let bytes = JsonRef(Borrowed {
+        a: "foo",
+        b: "bar".into(),
+    })
+    .to_bytes()
+    .unwrap();
+    let JsonRef(x): JsonRef<Borrowed> = FromBytes::from_bytes(&bytes).unwrap();
+    assert_eq!(x.a, "foo");
+    assert!(matches!(x.b, std::borrow::Cow::Borrowed("bar")));
+
+    // The borrowed strings point into the input
+    let range = bytes.as_ptr_range();
+    assert!(range.contains(&x.a.as_ptr()));
+
+    let JsonRef(s): JsonRef<&str> = FromBytes::from_bytes(br#""hello""#).unwrap();
+    assert_eq!(s, "hello");
+    assert!(JsonRef::<&str>::from_bytes(br#""a\nb""#).is_err());
+}
+
+#[test]
+#[cfg(feature = "msgpack")]
+fn borrowed_msgpack() {
+    let bytes = Msgpack("hello").to_bytes().unwrap();
+    let MsgpackRef(s): MsgpackRef<&str> = FromBytes::from_bytes(&bytes).unwrap();
+    assert_eq!(s, "hello");
+    assert!(bytes.as_ptr_range().contains(&s.as_ptr()));
+}
+
 #[test]
 #[cfg(feature = "msgpack")]
 fn roundtrip_msgpack() {
@@ -32,6 +69,51 @@ fn roundtrip_msgpack() {
     assert_eq!(x, y);
 }
 
+#[test]
+#[cfg(feature = "cbor")]
+fn roundtrip_cbor() {
+    let x = Testing {
+        a: "foobar".to_string(),
+        b: 123,
+        c: 456.7,
+    };
+    let bytes = Cbor(&x).to_bytes().unwrap();
+    let Cbor(y): Cbor<Testing> = FromBytes::from_bytes(&bytes).unwrap();
+    assert_eq!(x, y);
+
+    #[derive(ToBytes, FromBytes, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    #[encoding(Cbor)]
+    struct Derived {
+        hello: String,
+    }
+
+    let x = Derived { hello: "hi".into() };
+    let bytes = x.to_bytes().unwrap();
+    assert_eq!(bytes, Cbor(&x).to_bytes().unwrap());
+    assert_eq!(Derived::from_bytes(&bytes).unwrap(), x);
+}
+
+#[test]
+#[cfg(feature = "prost")]
+fn roundtrip_proto() {
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct Message {
+        #[prost(string, tag = "1")]
+        a: String,
+        #[prost(int64, tag = "2")]
+        b: i64,
+    }
+
+    let x = Message {
+        a: "foobar".to_string(),
+        b: 123,
+    };
+    let bytes = Proto(&x).to_bytes().unwrap();
+    assert_eq!(bytes, Prost(x.clone()).to_bytes().unwrap());
+    let Proto(y): Proto<Message> = FromBytes::from_bytes(&bytes).unwrap();
+    assert_eq!(x, y);
+}
+
 #[test]
 fn roundtrip_base64() {
     let bytes = Base64("this is a test").to_bytes().unwrap();
@@ -72,6 +154,57 @@ fn check_bool() {
     assert_eq!(b, [0]);
 }
 
+#[test]
+fn check_to_writer() {
+    let mut out = vec![];
+    String::from("hello").to_writer(&mut out).unwrap();
+    Some(&b" world"[..]).to_writer(&mut out).unwrap();
+    assert_eq!(out, b"hello world");
+    assert_eq!(Some("hello").encoded_len(), Some(5));
+    assert_eq!(None::<Vec<u8>>.encoded_len(), Some(0));
+
+    // Serde encodings write into the buffer directly
+    let mut out = vec![];
+    assert_eq!(Json(&1).encoded_len(), None);
+    Json(&1).to_writer(&mut out).unwrap();
+    assert_eq!(out, b"1");
+    #[cfg(feature = "msgpack")]
+    {
+        let x = Testing {
+            a: "foobar".to_string(),
+            b: 123,
+            c: 456.7,
+        };
+        let mut out = vec![];
+        Msgpack(&x).to_writer(&mut out).unwrap();
+        assert_eq!(out, Msgpack(&x).to_bytes().unwrap());
+    }
+
+    // Other types fall back to `to_bytes`
+    let mut out = vec![];
+    Base64("hi").to_writer(&mut out).unwrap();
+    assert_eq!(out, b"aGk=");
+}
+
+#[test]
+fn check_with_encoded() {
+    let ptr = Json(&[1, 2, 3])
+        .with_encoded(|data| {
+            assert_eq!(data, b"[1,2,3]");
+            data.as_ptr()
+        })
+        .unwrap();
+
+    // The buffer is reused, including when encoding another value inside `f`
+    let inner = Json("hello")
+        .with_encoded(|data| {
+            assert_eq!(data.as_ptr(), ptr);
+            Json(&1).with_encoded(|x| x.to_vec()).unwrap()
+        })
+        .unwrap();
+    assert_eq!(inner, b"1");
+}
+
 #[cfg(all(feature = "raw", target_endian = "little"))]
 mod raw_tests {
     use crate::*;
@@ -98,4 +231,147 @@ mod raw_tests {
         let y: Result<Raw<[u8; std::mem::size_of::<TestRaw>()]>, Error> = Raw::from_bytes(raw);
         assert!(y.is_ok());
     }
+
+    #[test]
+    fn test_raw_value() {
+        #[derive(Debug, Clone, Copy, PartialEq, ToBytes, FromBytes)]
+        #[encoding(RawValue)]
+        #[repr(C)]
+        struct Sample {
+            t: u64,
+            value: f64,
+        }
+        unsafe impl bytemuck::Pod for Sample {}
+        unsafe impl bytemuck::Zeroable for Sample {}
+
+        let x = Sample { t: 1, value: 2.5 };
+        let bytes = x.to_bytes().unwrap();
+        assert_eq!(&bytes[..8], &1u64.to_le_bytes());
+        assert_eq!(Sample::from_bytes(&bytes).unwrap(), x);
+
+        // The input doesn't need to be aligned
+        let mut unaligned = vec![0];
+        unaligned.extend_from_slice(&bytes);
+        assert_eq!(Sample::from_bytes(&unaligned[1..]).unwrap(), x);
+        assert!(Sample::from_bytes(&bytes[1..]).is_err());
+
+        let values = [1.0f32, 2.0, 3.0];
+        let bytes = RawValue(&values[..]).to_bytes().unwrap();
+        assert_eq!(bytes.len(), 12);
+        assert_eq!(&bytes[4..8], &2.0f32.to_le_bytes());
+    }
+}
+
+#[test]
+fn derive_enum() {
+    #[derive(ToBytes, FromBytes, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    #[encoding(Json)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    enum Request {
+        Get { key: String },
+        Clear,
+    }
+
+    let x = Request::Get { key: "a".into() };
+    let bytes = x.to_bytes().unwrap();
+    assert_eq!(bytes, br#"{"type":"get","key":"a"}"#);
+    assert_eq!(Request::from_bytes(&bytes).unwrap(), x);
+    assert_eq!(
+        Request::from_bytes(br#"{"type":"clear"}"#).unwrap(),
+        Request::Clear
+    );
+}
+
+#[test]
+fn derive_generic() {
+    #[derive(ToBytes, FromBytes, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    #[encoding(Json)]
+    enum Response<T, E = String> {
+        Ok(T),
+        Err(E),
+    }
+
+    // The derived impls apply whenever the encoding supports the type, including in generic code
+    fn encode<T: serde::Serialize>(x: &Response<T>) -> Vec<u8> {
+        x.to_bytes().unwrap()
+    }
+
+    let x: Response<Vec<u32>> = Response::Ok(vec![1, 2]);
+    let bytes = encode(&x);
+    assert_eq!(bytes, br#"{"Ok":[1,2]}"#);
+    assert_eq!(Response::from_bytes(&bytes).unwrap(), x);
+
+    #[derive(ToBytes, serde::Serialize)]
+    #[encoding(Json)]
+    struct Borrowed<'a, T> {
+        name: &'a str,
+        values: &'a [T],
+    }
+
+    let x = Borrowed {
+        name: "a",
+        values: &[1, 2],
+    };
+    assert_eq!(x.to_bytes().unwrap(), br#"{"name":"a","values":[1,2]}"#);
+}
+
+#[test]
+fn text() {
+    let bytes = Text(1.5f64).to_bytes().unwrap();
+    assert_eq!(bytes, "1.5");
+    let Text(x) = Text::<f64>::from_bytes(bytes.as_bytes()).unwrap();
+    assert_eq!(x, 1.5);
+    assert!(Text::<u32>::from_bytes(b"abc").is_err());
+}
+
+#[test]
+#[cfg(feature = "uuid")]
+fn uuid() {
+    let id = uuid::Uuid::from_u128(0x67e55044_10b1_426f_9247_bb680e5fe0c8);
+    let bytes = id.to_bytes().unwrap();
+    assert_eq!(bytes, "67e55044-10b1-426f-9247-bb680e5fe0c8");
+    assert_eq!(
+        <uuid::Uuid as FromBytes>::from_bytes(bytes.as_bytes()).unwrap(),
+        id
+    );
+    assert!(<uuid::Uuid as FromBytes>::from_bytes(b"not a uuid").is_err());
+}
+
+#[test]
+#[cfg(feature = "chrono")]
+fn chrono_datetime() {
+    use chrono::TimeZone;
+    let t = chrono::Utc
+        .with_ymd_and_hms(2024, 2, 29, 12, 30, 0)
+        .unwrap();
+    let bytes = t.to_bytes().unwrap();
+    assert_eq!(bytes, "2024-02-29T12:30:00+00:00");
+    assert_eq!(chrono::DateTime::from_bytes(bytes.as_bytes()).unwrap(), t);
+    let offset: chrono::DateTime<chrono::Utc> =
+        FromBytes::from_bytes(b"2024-02-29T14:30:00+02:00").unwrap();
+    assert_eq!(offset, t);
+}
+
+#[test]
+#[cfg(feature = "time")]
+fn time_datetime() {
+    let t = time::OffsetDateTime::from_unix_timestamp(1_709_209_800).unwrap();
+    let bytes = t.to_bytes().unwrap();
+    assert_eq!(bytes, "2024-02-29T12:30:00Z");
+    assert_eq!(
+        time::OffsetDateTime::from_bytes(bytes.as_bytes()).unwrap(),
+        t
+    );
+}
+
+#[test]
+#[cfg(feature = "rust_decimal")]
+fn decimal() {
+    let x = rust_decimal::Decimal::new(123456, 3);
+    let bytes = x.to_bytes().unwrap();
+    assert_eq!(bytes, "123.456");
+    assert_eq!(
+        rust_decimal::Decimal::from_bytes(bytes.as_bytes()).unwrap(),
+        x
+    );
 }
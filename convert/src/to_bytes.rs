@@ -22,6 +22,26 @@ pub use extism_convert_macros::ToBytes;
 /// # Ok::<(), extism_convert::Error>(())
 /// ```
 ///
+/// Enums and generic types can be derived as well, the implementation is available
+/// whenever the encoding supports the type, e.g., when the type parameters implement
+/// `Serialize` for [`Json`].
+///
+/// ```
+/// use extism_convert::{Json, ToBytes};
+/// use serde::Serialize;
+///
+/// #[derive(ToBytes, Serialize)]
+/// #[encoding(Json)]
+/// #[serde(tag = "type", rename_all = "snake_case")]
+/// enum Event<T> {
+///     Created { item: T },
+///     Deleted,
+/// }
+///
+/// assert_eq!(Event::Created { item: 1 }.to_bytes()?, br#"{"type":"created","item":1}"#);
+/// # Ok::<(), extism_convert::Error>(())
+/// ```
+///
 /// But custom types can also be used, as long as they are new-types with a single
 /// generic argument, i.e., `Type<T>(T)`, that implement `ToBytes` for the struct.
 ///
@@ -59,6 +79,43 @@ pub trait ToBytes<'a> {
 
     /// `to_bytes` converts a value into `Self::Bytes`
     fn to_bytes(&self) -> Result<Self::Bytes, Error>;
+
+    /// The exact length of the encoded value, if it's known without encoding it. When this
+    /// returns `Some`, the value is written straight into Extism memory using `to_writer`
+    fn encoded_len(&self) -> Option<usize> {
+        None
+    }
+
+    /// `to_writer` writes the encoded value to `w`, by default this is the output of `to_bytes`
+    fn to_writer<W: std::io::Write>(&self, w: &mut W) -> Result<(), Error> {
+        w.write_all(self.to_bytes()?.as_ref())?;
+        Ok(())
+    }
+
+    /// Encode the value into a buffer that's reused by each call on the current thread and pass the
+    /// encoded bytes to `f`. For encodings that implement `to_writer`, like [`Json`] and [`Msgpack`],
+    /// this avoids allocating a new `Vec` for every value.
+    fn with_encoded<R>(&self, f: impl FnOnce(&[u8]) -> R) -> Result<R, Error> {
+        ENCODE_BUFFER.with(|buf| match buf.try_borrow_mut() {
+            Ok(mut buf) => {
+                buf.clear();
+                let res = self.to_writer(&mut *buf).map(|()| f(&buf));
+                if buf.capacity() > MAX_ENCODE_BUFFER {
+                    *buf = Vec::new();
+                }
+                res
+            }
+            // `f` is encoding another value
+            Err(_) => Ok(f(self.to_bytes()?.as_ref())),
+        })
+    }
+}
+
+// Buffers larger than this aren't kept after `ToBytes::with_encoded` returns
+const MAX_ENCODE_BUFFER: usize = 1024 * 1024;
+
+thread_local! {
+    static ENCODE_BUFFER: std::cell::RefCell<Vec<u8>> = const { std::cell::RefCell::new(Vec::new()) };
 }
 
 impl ToBytes<'_> for () {
@@ -66,6 +123,10 @@ impl ToBytes<'_> for () {
     fn to_bytes(&self) -> Result<Self::Bytes, Error> {
         Ok([])
     }
+
+    fn encoded_len(&self) -> Option<usize> {
+        Some(0)
+    }
 }
 
 impl ToBytes<'_> for Vec<u8> {
@@ -73,6 +134,15 @@ impl ToBytes<'_> for Vec<u8> {
     fn to_bytes(&self) -> Result<Self::Bytes, Error> {
         Ok(self.clone())
     }
+
+    fn encoded_len(&self) -> Option<usize> {
+        Some(self.len())
+    }
+
+    fn to_writer<W: std::io::Write>(&self, w: &mut W) -> Result<(), Error> {
+        w.write_all(self.as_ref())?;
+        Ok(())
+    }
 }
 
 impl ToBytes<'_> for String {
@@ -80,6 +150,15 @@ impl ToBytes<'_> for String {
     fn to_bytes(&self) -> Result<Self::Bytes, Error> {
         Ok(self.clone())
     }
+
+    fn encoded_len(&self) -> Option<usize> {
+        Some(self.len())
+    }
+
+    fn to_writer<W: std::io::Write>(&self, w: &mut W) -> Result<(), Error> {
+        w.write_all(self.as_ref())?;
+        Ok(())
+    }
 }
 
 impl<'a> ToBytes<'a> for &'a [u8] {
@@ -87,6 +166,15 @@ impl<'a> ToBytes<'a> for &'a [u8] {
     fn to_bytes(&self) -> Result<Self::Bytes, Error> {
         Ok(self)
     }
+
+    fn encoded_len(&self) -> Option<usize> {
+        Some(self.len())
+    }
+
+    fn to_writer<W: std::io::Write>(&self, w: &mut W) -> Result<(), Error> {
+        w.write_all(self.as_ref())?;
+        Ok(())
+    }
 }
 
 impl<'a> ToBytes<'a> for &'a str {
@@ -94,6 +182,15 @@ impl<'a> ToBytes<'a> for &'a str {
     fn to_bytes(&self) -> Result<Self::Bytes, Error> {
         Ok(self)
     }
+
+    fn encoded_len(&self) -> Option<usize> {
+        Some(self.len())
+    }
+
+    fn to_writer<W: std::io::Write>(&self, w: &mut W) -> Result<(), Error> {
+        w.write_all(self.as_ref())?;
+        Ok(())
+    }
 }
 
 impl ToBytes<'_> for f64 {
@@ -152,12 +249,52 @@ impl ToBytes<'_> for bool {
     }
 }
 
+#[cfg(feature = "uuid")]
+impl ToBytes<'_> for uuid::Uuid {
+    type Bytes = String;
+    fn to_bytes(&self) -> Result<Self::Bytes, Error> {
+        Ok(self.hyphenated().to_string())
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl ToBytes<'_> for chrono::DateTime<chrono::Utc> {
+    type Bytes = String;
+    fn to_bytes(&self) -> Result<Self::Bytes, Error> {
+        Ok(self.to_rfc3339())
+    }
+}
+
+#[cfg(feature = "time")]
+impl ToBytes<'_> for time::OffsetDateTime {
+    type Bytes = String;
+    fn to_bytes(&self) -> Result<Self::Bytes, Error> {
+        Ok(self.format(&time::format_description::well_known::Rfc3339)?)
+    }
+}
+
+#[cfg(feature = "rust_decimal")]
+impl ToBytes<'_> for rust_decimal::Decimal {
+    type Bytes = String;
+    fn to_bytes(&self) -> Result<Self::Bytes, Error> {
+        Ok(self.to_string())
+    }
+}
+
 impl<'a, T: ToBytes<'a>> ToBytes<'a> for &'a T {
     type Bytes = T::Bytes;
 
     fn to_bytes(&self) -> Result<Self::Bytes, Error> {
         <T as ToBytes>::to_bytes(self)
     }
+
+    fn encoded_len(&self) -> Option<usize> {
+        <T as ToBytes>::encoded_len(self)
+    }
+
+    fn to_writer<W: std::io::Write>(&self, w: &mut W) -> Result<(), Error> {
+        <T as ToBytes>::to_writer(self, w)
+    }
 }
 
 impl<'a, T: ToBytes<'a>> ToBytes<'a> for Option<T> {
@@ -169,6 +306,20 @@ impl<'a, T: ToBytes<'a>> ToBytes<'a> for Option<T> {
             None => Ok(vec![]),
         }
     }
+
+    fn encoded_len(&self) -> Option<usize> {
+        match self {
+            Some(x) => x.encoded_len(),
+            None => Some(0),
+        }
+    }
+
+    fn to_writer<W: std::io::Write>(&self, w: &mut W) -> Result<(), Error> {
+        match self {
+            Some(x) => x.to_writer(w),
+            None => Ok(()),
+        }
+    }
 }
 
 #[test]
@@ -181,4 +332,9 @@ fn test() {
     struct Struct {
         hello: String,
     }
+
+    assert_eq!(
+        Struct { hello: "hi".into() }.to_bytes().unwrap(),
+        br#"{"hello":"hi"}"#
+    );
 }
@@ -8,3 +8,164 @@ fn test_version() {
     let s = unsafe { std::ffi::CStr::from_ptr(extism_version()) };
     assert!(s.to_bytes() != b"0.0.0");
 }
+
+#[cfg(test)]
+#[test]
+fn test_call_with_host_context() {
+    extern "C" fn count(
+        plugin: *mut extism::CurrentPlugin,
+        _inputs: *const ExtismVal,
+        _n_inputs: Size,
+        _outputs: *mut ExtismVal,
+        _n_outputs: Size,
+        _data: *mut std::ffi::c_void,
+    ) {
+        let ctx = unsafe { extism_current_plugin_host_context(plugin) };
+        if !ctx.is_null() {
+            unsafe { *(ctx as *mut u32) += 1 };
+        }
+    }
+
+    let wasm = br#"(module
+        (import "extism:host/user" "count" (func $count))
+        (func (export "run") (result i32)
+            (call $count)
+            (call $count)
+            (i32.const 0))
+    )"#;
+    unsafe {
+        let f = extism_function_new(
+            c"count".as_ptr(),
+            std::ptr::null(),
+            0,
+            std::ptr::null(),
+            0,
+            count,
+            std::ptr::null_mut(),
+            None,
+        );
+        let mut functions = [f as *const ExtismFunction];
+        let mut err = std::ptr::null_mut();
+        let plugin = extism_plugin_new(
+            wasm.as_ptr(),
+            wasm.len() as Size,
+            functions.as_mut_ptr(),
+            1,
+            false,
+            &mut err,
+        );
+        assert!(!plugin.is_null(), "{:?}", std::ffi::CStr::from_ptr(err));
+
+        let mut n: u32 = 0;
+        let ctx = &mut n as *mut u32 as *mut std::ffi::c_void;
+        let rc =
+            extism_plugin_call_with_host_context(plugin, c"run".as_ptr(), std::ptr::null(), 0, ctx);
+        assert_eq!(rc, 0);
+        assert_eq!(n, 2);
+
+        // Host functions get a null pointer when no context is passed in
+        assert_eq!(
+            extism_plugin_call(plugin, c"run".as_ptr(), std::ptr::null(), 0),
+            0
+        );
+        assert_eq!(n, 2);
+
+        extism_plugin_free(plugin);
+        extism_function_free(f);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_plugin_error_code() {
+    let wasm = br#"(module
+        (func (export "ok") (result i32) (i32.const 0))
+        (func (export "trap") (result i32) unreachable)
+    )"#;
+    unsafe {
+        let mut err = std::ptr::null_mut();
+        let plugin = extism_plugin_new(
+            wasm.as_ptr(),
+            wasm.len() as Size,
+            std::ptr::null_mut(),
+            0,
+            false,
+            &mut err,
+        );
+        assert!(!plugin.is_null(), "{:?}", std::ffi::CStr::from_ptr(err));
+
+        assert_ne!(
+            extism_plugin_call(plugin, c"trap".as_ptr(), std::ptr::null(), 0),
+            0
+        );
+        assert_eq!(extism_plugin_error_code(plugin), 5);
+        assert_ne!(
+            extism_plugin_call(plugin, c"missing".as_ptr(), std::ptr::null(), 0),
+            0
+        );
+        assert_eq!(extism_plugin_error_code(plugin), 10);
+        assert_eq!(
+            extism_plugin_call(plugin, c"ok".as_ptr(), std::ptr::null(), 0),
+            0
+        );
+        assert_eq!(extism_plugin_error_code(plugin), 0);
+
+        extism_plugin_free(plugin);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_call_result() {
+    let wasm = br#"(module
+        (import "extism:host/env" "input_offset" (func $input_offset (result i64)))
+        (import "extism:host/env" "input_length" (func $input_length (result i64)))
+        (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+        (func (export "echo") (result i32)
+            (call $output_set (call $input_offset) (call $input_length))
+            (i32.const 0))
+        (func (export "fail") (result i32)
+            unreachable)
+    )"#;
+    unsafe {
+        let mut err = std::ptr::null_mut();
+        let plugin = extism_plugin_new(
+            wasm.as_ptr(),
+            wasm.len() as Size,
+            std::ptr::null_mut(),
+            0,
+            false,
+            &mut err,
+        );
+        assert!(!plugin.is_null(), "{:?}", std::ffi::CStr::from_ptr(err));
+
+        let input = b"hello";
+        let res = extism_plugin_call_result(
+            plugin,
+            c"echo".as_ptr(),
+            input.as_ptr(),
+            input.len() as Size,
+            std::ptr::null_mut(),
+        );
+        assert_eq!(res.rc, 0);
+        assert_eq!(res.error_code, 0);
+        assert!(res.error.is_null());
+        let output = std::slice::from_raw_parts(res.output, res.output_length as usize);
+        assert_eq!(output, b"hello");
+
+        // Failed calls return the error and its kind instead of the output
+        let res = extism_plugin_call_result(
+            plugin,
+            c"fail".as_ptr(),
+            std::ptr::null(),
+            0,
+            std::ptr::null_mut(),
+        );
+        assert_ne!(res.rc, 0);
+        assert_eq!(res.error_code, 5);
+        assert!(res.output.is_null());
+        assert!(!std::ffi::CStr::from_ptr(res.error).to_bytes().is_empty());
+
+        extism_plugin_free(plugin);
+    }
+}
@@ -1,6 +1,9 @@
 use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
+mod validate;
+pub use validate::{ManifestBuilder, ManifestError};
+
 #[deprecated]
 pub type ManifestMemory = MemoryOptions;
 
@@ -17,8 +20,9 @@ pub struct MemoryOptions {
     pub max_http_response_bytes: Option<u64>,
 
     /// The maximum number of bytes allowed to be used by plugin vars. Setting this to 0
-    /// will disable Extism vars. The default value is 1mb.
-    #[serde(default = "default_var_bytes")]
+    /// will disable Extism vars. The default value is 1mb, it isn't filled in when the manifest is
+    /// deserialized so a manifest is unchanged by a serde round-trip.
+    #[serde(default)]
     pub max_var_bytes: Option<u64>,
 }
 
@@ -47,10 +51,6 @@ impl MemoryOptions {
     }
 }
 
-fn default_var_bytes() -> Option<u64> {
-    Some(1024 * 1024)
-}
-
 /// Generic HTTP request structure
 #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 #[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
@@ -90,16 +90,167 @@ impl HttpRequest {
     }
 }
 
+/// Restrictions for HTTP requests to hosts matching a pattern in `Manifest::http_policies`, a policy
+/// doesn't allow access to hosts that aren't in `allowed_hosts`
+#[derive(Default, Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct HttpPolicy {
+    /// Allowed request methods, any method is allowed if this isn't set
+    #[serde(default)]
+    pub allowed_methods: Option<Vec<String>>,
+
+    /// The max number of request body bytes
+    #[serde(default)]
+    pub max_request_bytes: Option<u64>,
+
+    /// The max number of response body bytes, this takes precedence over `memory.max_http_response_bytes`
+    #[serde(default)]
+    pub max_response_bytes: Option<u64>,
+
+    /// Headers that are removed from requests before they're sent and from responses before the plugin
+    /// can read them, names are case-insensitive
+    #[serde(default)]
+    pub strip_headers: Vec<String>,
+}
+
+impl HttpPolicy {
+    /// Create a new `HttpPolicy` without any restrictions
+    pub fn new() -> HttpPolicy {
+        HttpPolicy::default()
+    }
+
+    /// Set `allowed_methods`
+    pub fn with_allowed_methods(
+        mut self,
+        methods: impl IntoIterator<Item = impl Into<String>>,
+    ) -> HttpPolicy {
+        self.allowed_methods = Some(methods.into_iter().map(|x| x.into()).collect());
+        self
+    }
+
+    /// Set `max_request_bytes`
+    pub fn with_max_request_bytes(mut self, bytes: u64) -> HttpPolicy {
+        self.max_request_bytes = Some(bytes);
+        self
+    }
+
+    /// Set `max_response_bytes`
+    pub fn with_max_response_bytes(mut self, bytes: u64) -> HttpPolicy {
+        self.max_response_bytes = Some(bytes);
+        self
+    }
+
+    /// Add a header to `strip_headers`
+    pub fn with_stripped_header(mut self, name: impl Into<String>) -> HttpPolicy {
+        self.strip_headers.push(name.into());
+        self
+    }
+
+    /// Returns `true` if `method` is allowed
+    pub fn method_allowed(&self, method: &str) -> bool {
+        match &self.allowed_methods {
+            Some(methods) => methods.iter().any(|x| x.eq_ignore_ascii_case(method)),
+            None => true,
+        }
+    }
+
+    /// Returns `true` if the header should be removed
+    pub fn strips_header(&self, name: &str) -> bool {
+        self.strip_headers
+            .iter()
+            .any(|x| x.eq_ignore_ascii_case(name))
+    }
+}
+
+/// A reference to a Wasm module stored as an OCI artifact in a container registry
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct OciReference {
+    /// The artifact reference, for example `ghcr.io/org/plugin:1.2.0` or `ghcr.io/org/plugin@sha256:<digest>`
+    pub oci: String,
+
+    /// Bearer token used to authenticate with the registry, anonymous tokens are requested when this
+    /// isn't set
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+impl OciReference {
+    /// Create a new `OciReference`
+    pub fn new(reference: impl Into<String>) -> OciReference {
+        OciReference {
+            oci: reference.into(),
+            token: None,
+        }
+    }
+
+    /// Set the registry token
+    pub fn with_token(mut self, token: impl Into<String>) -> OciReference {
+        self.token = Some(token.into());
+        self
+    }
+}
+
 /// Provides additional metadata about a Webassembly module
 #[derive(Default, Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 #[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
 #[serde(deny_unknown_fields)]
 pub struct WasmMetadata {
-    /// Module name, this is used by Extism to determine which is the `main` module
+    /// Module name, this is used by Extism to determine which is the `main` module. Other modules in the
+    /// manifest can import this module's exports using the name as the import module
     pub name: Option<String>,
 
     /// Module hash, if the data loaded from disk or via HTTP doesn't match an error will be raised
     pub hash: Option<String>,
+
+    /// Module signature, when this is set the module is only loaded if the signature is valid
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<WasmSignature>,
+}
+
+/// A signature of the module data, checked when the module is loaded. Keys and signatures are base64
+/// encoded.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum WasmSignature {
+    /// An Ed25519 signature of the module data
+    Ed25519 {
+        /// The 32 byte public key
+        public_key: String,
+        /// The 64 byte signature
+        signature: String,
+    },
+
+    /// A Sigstore bundle for the module data that was signed using a key pair, for example using
+    /// `cosign sign-blob --key cosign.key --bundle plugin.sigstore.json --new-bundle-format`. Keyless
+    /// bundles, which are signed using a certificate issued by Fulcio, aren't supported.
+    Sigstore {
+        /// The bundle JSON
+        bundle: String,
+        /// The PEM encoded ECDSA P-256 public key the bundle was signed with
+        public_key: String,
+    },
+}
+
+impl WasmSignature {
+    /// Create an Ed25519 signature from the base64 encoded public key and signature
+    pub fn ed25519(public_key: impl Into<String>, signature: impl Into<String>) -> Self {
+        WasmSignature::Ed25519 {
+            public_key: public_key.into(),
+            signature: signature.into(),
+        }
+    }
+
+    /// Create a Sigstore signature from the bundle JSON and PEM encoded public key
+    pub fn sigstore(bundle: impl Into<String>, public_key: impl Into<String>) -> Self {
+        WasmSignature::Sigstore {
+            bundle: bundle.into(),
+            public_key: public_key.into(),
+        }
+    }
 }
 
 impl From<HttpRequest> for Wasm {
@@ -111,6 +262,15 @@ impl From<HttpRequest> for Wasm {
     }
 }
 
+impl From<OciReference> for Wasm {
+    fn from(oci: OciReference) -> Self {
+        Wasm::Oci {
+            oci,
+            meta: WasmMetadata::default(),
+        }
+    }
+}
+
 impl From<std::path::PathBuf> for Wasm {
     fn from(path: std::path::PathBuf) -> Self {
         Wasm::File {
@@ -161,6 +321,14 @@ pub enum Wasm {
         #[serde(flatten)]
         meta: WasmMetadata,
     },
+
+    /// From an OCI registry
+    Oci {
+        #[serde(flatten)]
+        oci: OciReference,
+        #[serde(flatten)]
+        meta: WasmMetadata,
+    },
 }
 
 impl Wasm {
@@ -200,12 +368,30 @@ impl Wasm {
         }
     }
 
+    /// Load Wasm from an OCI registry, the artifact is verified against its digest and cached locally
+    pub fn oci(reference: impl Into<String>) -> Self {
+        Wasm::Oci {
+            oci: OciReference::new(reference),
+            meta: Default::default(),
+        }
+    }
+
+    /// Set the token used to authenticate with the registry, this has no effect unless the module is
+    /// loaded using `Wasm::oci`
+    pub fn with_registry_token(mut self, token: impl Into<String>) -> Self {
+        if let Wasm::Oci { oci, .. } = &mut self {
+            oci.token = Some(token.into());
+        }
+        self
+    }
+
     /// Get the metadata
     pub fn meta(&self) -> &WasmMetadata {
         match self {
             Wasm::File { path: _, meta } => meta,
             Wasm::Data { data: _, meta } => meta,
             Wasm::Url { req: _, meta } => meta,
+            Wasm::Oci { oci: _, meta } => meta,
         }
     }
 
@@ -215,6 +401,7 @@ impl Wasm {
             Wasm::File { path: _, meta } => meta,
             Wasm::Data { data: _, meta } => meta,
             Wasm::Url { req: _, meta } => meta,
+            Wasm::Oci { oci: _, meta } => meta,
         }
     }
 
@@ -229,6 +416,12 @@ impl Wasm {
         self.meta_mut().hash = Some(hash.into());
         self
     }
+
+    /// Set the signature the module is verified with when it's loaded
+    pub fn with_signature(mut self, signature: WasmSignature) -> Self {
+        self.meta_mut().signature = Some(signature);
+        self
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
@@ -266,7 +459,9 @@ pub struct Manifest {
     #[serde(default)]
     pub memory: MemoryOptions,
 
-    /// Config values are made accessible using the PDK `extism_config_get` function
+    /// Config values are made accessible using the PDK `extism_config_get` function. Values of the
+    /// form `secret://name` are resolved by the host when they are accessed, so the secret itself never
+    /// needs to be stored in the manifest.
     #[serde(default)]
     pub config: BTreeMap<String, String>,
 
@@ -275,12 +470,29 @@ pub struct Manifest {
     /// no hosts may be accessed. Wildcards may be used.
     pub allowed_hosts: Option<Vec<String>>,
 
+    /// Per-host restrictions for HTTP requests, keyed by hostname. Wildcards may be used, an exact match
+    /// takes precedence, otherwise the longest matching pattern is used.
+    #[serde(default)]
+    pub http_policies: Option<BTreeMap<String, HttpPolicy>>,
+
+    /// Specifies which addresses may be connected to using TCP sockets, in the form `host:port`.
+    /// Wildcards may be used for the host and port. The `extism:host/net` functions are only available
+    /// when this is set.
+    #[serde(default)]
+    pub allowed_sockets: Option<Vec<String>>,
+
     /// Specifies which paths should be made available on disk when using WASI. This is a mapping from
     /// the path on disk to the path it should be available inside the plugin.
-    /// For example, `".": "/tmp"` would mount the current directory as `/tmp` inside the module
+    /// For example, `".": "/tmp"` would mount the current directory as `/tmp` inside the module.
+    /// Paths on disk prefixed with `ro:` are mounted read-only, for example `"ro:./data": "/data"`
     #[serde(default)]
     pub allowed_paths: Option<BTreeMap<String, PathBuf>>,
 
+    /// Capabilities granted to the plugin, host functions that were registered with a capability are only
+    /// linked when it's listed here. Host functions without a capability are always available.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<String>,
+
     /// The plugin timeout in milliseconds
     #[serde(default)]
     pub timeout_ms: Option<u64>,
@@ -330,12 +542,34 @@ impl Manifest {
         self
     }
 
+    /// Add an `HttpPolicy` for hosts matching `host`
+    pub fn with_http_policy(mut self, host: impl Into<String>, policy: HttpPolicy) -> Self {
+        self.http_policies
+            .get_or_insert_with(BTreeMap::new)
+            .insert(host.into(), policy);
+        self
+    }
+
     /// Set `allowed_hosts`
     pub fn with_allowed_hosts(mut self, hosts: impl Iterator<Item = String>) -> Self {
         self.allowed_hosts = Some(hosts.collect());
         self
     }
 
+    /// Grant a capability to the plugin, see `capabilities`
+    pub fn with_capability(mut self, capability: impl Into<String>) -> Self {
+        self.capabilities.push(capability.into());
+        self
+    }
+
+    /// Add an address to `allowed_sockets`, for example `db.example.com:5432` or `*.internal:*`
+    pub fn with_allowed_socket(mut self, addr: impl Into<String>) -> Self {
+        self.allowed_sockets
+            .get_or_insert_with(Vec::new)
+            .push(addr.into());
+        self
+    }
+
     /// Add a path to `allowed_paths`
     pub fn with_allowed_path(mut self, src: String, dest: impl AsRef<Path>) -> Self {
         let dest = dest.as_ref().to_path_buf();
@@ -353,6 +587,12 @@ impl Manifest {
         self
     }
 
+    /// Add a read-only path to `allowed_paths`, the plugin can list and read files in `src` but can't
+    /// modify them
+    pub fn with_readonly_path(self, src: impl Into<String>, dest: impl AsRef<Path>) -> Self {
+        self.with_allowed_path(format!("ro:{}", src.into()), dest)
+    }
+
     /// Set `allowed_paths`
     pub fn with_allowed_paths(mut self, paths: impl Iterator<Item = (String, PathBuf)>) -> Self {
         self.allowed_paths = Some(paths.collect());
@@ -0,0 +1,329 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::{HttpPolicy, Manifest, MemoryOptions, Wasm};
+
+// The max number of pages a 32-bit memory can have
+const MAX_PAGES: u32 = 65536;
+
+/// A problem with a `Manifest` found by `Manifest::validate`, modules are identified by their index in
+/// `Manifest::wasm`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ManifestError {
+    /// The manifest doesn't contain any modules
+    NoWasm,
+
+    /// More than one module has the same name
+    DuplicateName { index: usize, name: String },
+
+    /// More than one module has the same hash
+    DuplicateHash { index: usize, hash: String },
+
+    /// A hash isn't a lowercase hex-encoded SHA-256 hash
+    InvalidHash { index: usize, hash: String },
+
+    /// A module URL isn't an `http` or `https` URL with a host
+    InvalidUrl { index: usize, url: String },
+
+    /// A module OCI reference is empty
+    InvalidOciReference { index: usize, reference: String },
+
+    /// The timeout was set to more than one value using `ManifestBuilder::with_timeout`
+    ConflictingTimeout { first_ms: u64, second_ms: u64 },
+
+    /// `timeout_ms` is 0, which would interrupt every call immediately
+    ZeroTimeout,
+
+    /// `memory.max_pages` is larger than the max size of a 32-bit memory
+    InvalidMaxPages(u32),
+
+    /// An entry in `allowed_hosts` or `http_policies` is empty
+    InvalidHost(String),
+
+    /// An entry in `allowed_sockets` isn't in the form `host:port`
+    InvalidSocket(String),
+}
+
+impl std::fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ManifestError::NoWasm => write!(f, "manifest doesn't contain any modules"),
+            ManifestError::DuplicateName { index, name } => {
+                write!(f, "module {index} has a duplicate name: {name}")
+            }
+            ManifestError::DuplicateHash { index, hash } => {
+                write!(f, "module {index} has a duplicate hash: {hash}")
+            }
+            ManifestError::InvalidHash { index, hash } => {
+                write!(f, "module {index} has an invalid SHA-256 hash: {hash}")
+            }
+            ManifestError::InvalidUrl { index, url } => {
+                write!(f, "module {index} has an invalid URL: {url}")
+            }
+            ManifestError::InvalidOciReference { index, reference } => {
+                write!(
+                    f,
+                    "module {index} has an invalid OCI reference: {reference:?}"
+                )
+            }
+            ManifestError::ConflictingTimeout {
+                first_ms,
+                second_ms,
+            } => write!(
+                f,
+                "conflicting timeouts, set to {first_ms}ms and then {second_ms}ms"
+            ),
+            ManifestError::ZeroTimeout => write!(f, "timeout_ms must be greater than 0"),
+            ManifestError::InvalidMaxPages(pages) => {
+                write!(f, "max_pages must be at most {MAX_PAGES}, found {pages}")
+            }
+            ManifestError::InvalidHost(host) => write!(f, "invalid host: {host:?}"),
+            ManifestError::InvalidSocket(addr) => {
+                write!(f, "invalid socket address, expected host:port: {addr:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+fn valid_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|x| matches!(x, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn valid_url(url: &str) -> bool {
+    let Some(rest) = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+    else {
+        return false;
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    let host = match host.rsplit_once(':') {
+        // Ports are only checked when the host isn't an IPv6 address
+        Some((h, port)) if !port.contains(']') => {
+            if port.is_empty() || port.parse::<u16>().is_err() {
+                return false;
+            }
+            h
+        }
+        _ => host,
+    };
+    !host.is_empty() && !host.contains(char::is_whitespace)
+}
+
+fn valid_socket(addr: &str) -> bool {
+    match addr.rsplit_once(':') {
+        Some((host, port)) => {
+            !host.is_empty() && (port == "*" || port.parse::<u16>().is_ok_and(|x| x > 0))
+        }
+        None => false,
+    }
+}
+
+impl Manifest {
+    /// Create a `ManifestBuilder`
+    pub fn builder() -> ManifestBuilder {
+        ManifestBuilder::new()
+    }
+
+    /// Check the manifest for problems that would otherwise only be found when a plugin is created from
+    /// it, an empty list is returned when the manifest is valid. Modules aren't loaded, so hashes are only
+    /// checked against each other.
+    pub fn validate(&self) -> Vec<ManifestError> {
+        let mut errors = vec![];
+        if self.wasm.is_empty() {
+            errors.push(ManifestError::NoWasm);
+        }
+
+        let mut names = BTreeSet::new();
+        let mut hashes = BTreeSet::new();
+        for (index, wasm) in self.wasm.iter().enumerate() {
+            let meta = wasm.meta();
+            if let Some(name) = &meta.name {
+                if !names.insert(name) {
+                    errors.push(ManifestError::DuplicateName {
+                        index,
+                        name: name.clone(),
+                    });
+                }
+            }
+            if let Some(hash) = &meta.hash {
+                if !valid_hash(hash) {
+                    errors.push(ManifestError::InvalidHash {
+                        index,
+                        hash: hash.clone(),
+                    });
+                } else if !hashes.insert(hash) {
+                    errors.push(ManifestError::DuplicateHash {
+                        index,
+                        hash: hash.clone(),
+                    });
+                }
+            }
+            match wasm {
+                Wasm::Url { req, .. } if !valid_url(&req.url) => {
+                    errors.push(ManifestError::InvalidUrl {
+                        index,
+                        url: req.url.clone(),
+                    })
+                }
+                Wasm::Oci { oci, .. } if oci.oci.trim().is_empty() => {
+                    errors.push(ManifestError::InvalidOciReference {
+                        index,
+                        reference: oci.oci.clone(),
+                    })
+                }
+                _ => (),
+            }
+        }
+
+        if self.timeout_ms == Some(0) {
+            errors.push(ManifestError::ZeroTimeout);
+        }
+        if let Some(pages) = self.memory.max_pages {
+            if pages > MAX_PAGES {
+                errors.push(ManifestError::InvalidMaxPages(pages));
+            }
+        }
+
+        let hosts = self.allowed_hosts.iter().flatten();
+        let policies = self.http_policies.iter().flat_map(|x| x.keys());
+        for host in hosts.chain(policies) {
+            if host.trim().is_empty() {
+                errors.push(ManifestError::InvalidHost(host.clone()));
+            }
+        }
+        for addr in self.allowed_sockets.iter().flatten() {
+            if !valid_socket(addr) {
+                errors.push(ManifestError::InvalidSocket(addr.clone()));
+            }
+        }
+        errors
+    }
+}
+
+/// Builds a `Manifest`, the manifest is validated by `ManifestBuilder::build` so any problems are reported
+/// before a plugin is created
+///
+/// ```
+/// use extism_manifest::{Manifest, Wasm};
+///
+/// let manifest = Manifest::builder()
+///     .with_wasm(Wasm::url("https://example.com/plugin.wasm"))
+///     .with_allowed_host("api.example.com")
+///     .with_timeout(std::time::Duration::from_secs(5))
+///     .build()
+///     .unwrap();
+/// assert_eq!(manifest.timeout_ms, Some(5000));
+/// ```
+#[derive(Default, Debug, Clone)]
+pub struct ManifestBuilder {
+    manifest: Manifest,
+    errors: Vec<ManifestError>,
+}
+
+impl ManifestBuilder {
+    /// Create a new builder for an empty manifest
+    pub fn new() -> ManifestBuilder {
+        ManifestBuilder::default()
+    }
+
+    /// Start from an existing manifest
+    pub fn from_manifest(manifest: Manifest) -> ManifestBuilder {
+        ManifestBuilder {
+            manifest,
+            errors: vec![],
+        }
+    }
+
+    /// Add a module
+    pub fn with_wasm(mut self, wasm: impl Into<Wasm>) -> Self {
+        self.manifest.wasm.push(wasm.into());
+        self
+    }
+
+    /// Set memory options
+    pub fn with_memory_options(mut self, memory: MemoryOptions) -> Self {
+        self.manifest.memory = memory;
+        self
+    }
+
+    /// Set `memory.max_pages`
+    pub fn with_memory_max(mut self, max: u32) -> Self {
+        self.manifest.memory.max_pages = Some(max);
+        self
+    }
+
+    /// Add a hostname to `allowed_hosts`
+    pub fn with_allowed_host(mut self, host: impl Into<String>) -> Self {
+        self.manifest = self.manifest.with_allowed_host(host);
+        self
+    }
+
+    /// Add an `HttpPolicy` for hosts matching `host`
+    pub fn with_http_policy(mut self, host: impl Into<String>, policy: HttpPolicy) -> Self {
+        self.manifest = self.manifest.with_http_policy(host, policy);
+        self
+    }
+
+    /// Add an address to `allowed_sockets`
+    pub fn with_allowed_socket(mut self, addr: impl Into<String>) -> Self {
+        self.manifest = self.manifest.with_allowed_socket(addr);
+        self
+    }
+
+    /// Add a capability to `capabilities`
+    pub fn with_capability(mut self, capability: impl Into<String>) -> Self {
+        self.manifest = self.manifest.with_capability(capability);
+        self
+    }
+
+    /// Add a path to `allowed_paths`
+    pub fn with_allowed_path(mut self, src: impl Into<String>, dest: impl AsRef<Path>) -> Self {
+        self.manifest = self.manifest.with_allowed_path(src.into(), dest);
+        self
+    }
+
+    /// Add a read-only path to `allowed_paths`
+    pub fn with_readonly_path(mut self, src: impl Into<String>, dest: impl AsRef<Path>) -> Self {
+        self.manifest = self.manifest.with_readonly_path(src, dest);
+        self
+    }
+
+    /// Set a single `config` key
+    pub fn with_config_key(mut self, k: impl Into<String>, v: impl Into<String>) -> Self {
+        self.manifest.config.insert(k.into(), v.into());
+        self
+    }
+
+    /// Set `timeout_ms`, setting a different timeout more than once is reported as a
+    /// `ManifestError::ConflictingTimeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        let ms = timeout.as_millis() as u64;
+        match self.manifest.timeout_ms {
+            Some(first_ms) if first_ms != ms => {
+                self.errors.push(ManifestError::ConflictingTimeout {
+                    first_ms,
+                    second_ms: ms,
+                });
+            }
+            _ => self.manifest.timeout_ms = Some(ms),
+        }
+        self
+    }
+
+    /// Validate and return the manifest
+    pub fn build(self) -> Result<Manifest, Vec<ManifestError>> {
+        let mut errors = self.errors;
+        errors.extend(self.manifest.validate());
+        if errors.is_empty() {
+            Ok(self.manifest)
+        } else {
+            Err(errors)
+        }
+    }
+}
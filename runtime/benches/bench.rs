@@ -259,6 +259,7 @@ pub fn reflect_linked(c: &mut Criterion) {
             meta: WasmMetadata {
                 name: Some("extism:host/user".to_string()),
                 hash: None,
+                signature: None,
             },
         },
         Wasm::Data {
@@ -266,6 +267,7 @@ pub fn reflect_linked(c: &mut Criterion) {
             meta: WasmMetadata {
                 name: Some("main".to_string()),
                 hash: None,
+                signature: None,
             },
         },
     ]);
@@ -295,6 +297,45 @@ pub fn reflect_linked(c: &mut Criterion) {
     }
 }
 
+// Calls the `step` host function `HOST_CALLS` times
+const CHATTY: &str = r#"(module
+    (import "extism:host/user" "step" (func $step (param i64) (result i64)))
+    (func (export "run") (result i32)
+        (local $i i32)
+        (loop $continue
+            (drop (call $step (i64.const 0)))
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            (br_if $continue (i32.lt_u (local.get $i) (i32.const 10000))))
+        (i32.const 0)))"#;
+const HOST_CALLS: u64 = 10000;
+
+pub fn host_calls(c: &mut Criterion) {
+    let mut g = c.benchmark_group("host_calls");
+    g.throughput(criterion::Throughput::Elements(HOST_CALLS));
+    let step = Function::new(
+        "step",
+        [PTR],
+        [PTR],
+        UserData::new(()),
+        |plugin: &mut CurrentPlugin, inputs, outputs, _| {
+            // Looks up the length of the handle using the kernel, like most host functions
+            let _ = plugin.memory_from_val(&inputs[0]);
+            outputs[0] = inputs[0];
+            Ok(())
+        },
+    );
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(CHATTY)]))
+        .with_host_function_limits(HostFunctionLimits::new().with_max_calls_per_call(HOST_CALLS))
+        .with_functions([step])
+        .build()
+        .unwrap();
+    g.bench_function("host_calls", |b| {
+        b.iter(|| {
+            plugin.call::<(), ()>("run", ()).unwrap();
+        })
+    });
+}
+
 criterion_group!(
     benches,
     allocations,
@@ -307,6 +348,7 @@ criterion_group!(
     create_plugin_compiled,
     create_plugin_no_cache,
     create_compiled,
-    count_vowels
+    count_vowels,
+    host_calls
 );
 criterion_main!(benches);
@@ -10,6 +10,7 @@ fn main() {
             meta: WasmMetadata {
                 name: Some("extism:host/user".to_string()),
                 hash: None,
+                signature: None,
             },
         },
         // reflect expects host_reflect to be imported: https://github.com/extism/plugins/blob/e5578bbbdd87f9936a0a8d36df629768b2eff6bb/reflect/src/lib.rs#L5
@@ -21,6 +22,7 @@ fn main() {
             meta: WasmMetadata {
                 name: Some("main".to_string()),
                 hash: None,
+                signature: None,
             },
         },
     ]);
@@ -48,7 +48,8 @@ typedef enum {
 } ExtismValType;
 
 /**
- * A `CancelHandle` can be used to cancel a running plugin from another thread
+ * A `CancelHandle` can be used to cancel a running plugin from another thread, the call is
+ * interrupted and returns a `Cancelled` error
  */
 typedef struct ExtismCancelHandle ExtismCancelHandle;
 
@@ -99,12 +100,42 @@ typedef void (*ExtismFunctionType)(ExtismCurrentPlugin *plugin,
                                    ExtismSize n_outputs,
                                    void *data);
 
+/**
+ * `ExtismCallResult` holds the return code, error and output of a call made using
+ * `extism_plugin_call_result`
+ */
+typedef struct {
+  /**
+   * The return code, `0` when the call was successful
+   */
+  int32_t rc;
+  /**
+   * The kind of error, see `extism_plugin_error_code`
+   */
+  int32_t error_code;
+  /**
+   * The output data, this is `NULL` when the call failed
+   */
+  const uint8_t *output;
+  /**
+   * The length of `output`
+   */
+  ExtismSize output_length;
+  /**
+   * The error message, this is `NULL` when the call was successful
+   */
+  const char *error;
+} ExtismCallResult;
+
 /**
  * Log drain callback
  */
 typedef void (*ExtismLogDrainFunctionType)(const char *data, ExtismSize size);
 
-
+/**
+ * A wrapper around `ValType::I64` to specify arguments that are pointers to memory blocks
+ */
+#define PTR ExtismValType_I64
 
 #ifdef __cplusplus
 extern "C" {
@@ -300,6 +331,22 @@ int32_t extism_plugin_call_with_host_context(ExtismPlugin *plugin,
                                              ExtismSize data_len,
                                              void *host_context);
 
+/**
+ * Call a function with host context and return the result, instead of reading it using
+ * `extism_plugin_error` and `extism_plugin_output_data` afterwards. The output and error are owned by the
+ * plugin and are valid until the next call or until the plugin is freed.
+ *
+ * `func_name`: is the function to call
+ * `data`: is the input data
+ * `data_len`: is the length of `data`
+ * `host_context`: a pointer to context data that will be available in host functions, this can be `NULL`
+ */
+ExtismCallResult extism_plugin_call_result(ExtismPlugin *plugin,
+                                           const char *func_name,
+                                           const uint8_t *data,
+                                           ExtismSize data_len,
+                                           void *host_context);
+
 /**
  * Get the error associated with a `Plugin`
  */
@@ -310,6 +357,24 @@ const char *extism_error(ExtismPlugin *plugin);
  */
 const char *extism_plugin_error(ExtismPlugin *plugin);
 
+/**
+ * Get the kind of error returned by the last call, this is `0` for errors that don't have a more specific
+ * kind and after successful calls. The codes are stable, see `ErrorKind::code`:
+ *
+ * - 1: timeout
+ * - 2: cancelled
+ * - 3: out of memory
+ * - 4: out of fuel
+ * - 5: trap
+ * - 6: WASI exit with a non-zero code
+ * - 7: host function error
+ * - 8: error returned by the plugin
+ * - 9: encoding error
+ * - 10: function not found
+ * - 11: invalid signature
+ */
+int32_t extism_plugin_error_code(ExtismPlugin *plugin);
+
 /**
  * Get the length of a plugin's output data
  */
@@ -0,0 +1,160 @@
+use std::sync::{Arc, Mutex};
+
+use crate::*;
+
+/// `AsyncPlugin` calls a plugin from async code, each call runs on the tokio blocking thread pool so
+/// the async runtime isn't blocked while the plugin is running. A tokio runtime is required.
+///
+/// Calls are made one at a time, an `AsyncPlugin` can be cloned to share the plugin between tasks.
+/// Dropping the future returned by `AsyncPlugin::call` cancels the call, if the call hasn't started
+/// yet it's skipped.
+///
+/// There's no `Plugin::call_async`, `Plugin::call` borrows the plugin mutably and an async call has to
+/// move the plugin to the blocking thread pool, so `plugin.into_async().call(name, input).await` is used
+/// instead.
+#[derive(Clone)]
+pub struct AsyncPlugin {
+    plugin: Arc<Mutex<Plugin>>,
+    cancel_handle: CancelHandle,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CallState {
+    Queued,
+    Running,
+    Abandoned,
+    Done,
+}
+
+fn lock_state(state: &Mutex<CallState>) -> std::sync::MutexGuard<'_, CallState> {
+    match state.lock() {
+        Ok(x) => x,
+        Err(e) => e.into_inner(),
+    }
+}
+
+// Cancels the call when the future is dropped before it completes. Clones share the plugin's
+// `CancelHandle`, so the handle is only used while this future's call is running, a call that's still
+// waiting for the plugin is skipped instead
+struct CancelOnDrop {
+    cancel_handle: Option<CancelHandle>,
+    state: Arc<Mutex<CallState>>,
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(handle) = self.cancel_handle.take() {
+            let mut state = lock_state(&self.state);
+            match *state {
+                CallState::Queued => *state = CallState::Abandoned,
+                CallState::Running => {
+                    let _ = handle.cancel();
+                }
+                CallState::Abandoned | CallState::Done => (),
+            }
+        }
+    }
+}
+
+impl AsyncPlugin {
+    /// Create a new `AsyncPlugin`
+    pub fn new(plugin: Plugin) -> Self {
+        AsyncPlugin {
+            cancel_handle: plugin.cancel_handle(),
+            plugin: Arc::new(Mutex::new(plugin)),
+        }
+    }
+
+    /// Call a function on the tokio blocking thread pool, the input is converted using `ToBytes` and the
+    /// output using `FromBytesOwned`
+    pub async fn call<I, O>(&self, name: impl AsRef<str>, input: I) -> Result<O, Error>
+    where
+        I: ToBytes<'static> + Send + 'static,
+        O: FromBytesOwned + Send + 'static,
+    {
+        let name = name.as_ref().to_string();
+        let plugin = self.plugin.clone();
+        let state = Arc::new(Mutex::new(CallState::Queued));
+        let mut guard = CancelOnDrop {
+            cancel_handle: Some(self.cancel_handle.clone()),
+            state: state.clone(),
+        };
+        let task = tokio::task::spawn_blocking(move || {
+            let mut plugin = plugin
+                .lock()
+                .map_err(|_| Error::msg("plugin lock was poisoned"))?;
+            {
+                let mut state = lock_state(&state);
+                if *state == CallState::Abandoned {
+                    anyhow::bail!("call to {name} was cancelled before it started");
+                }
+                *state = CallState::Running;
+            }
+            let res = plugin.call(&name, input);
+            // The state is updated before the plugin is unlocked, so the next call can't be cancelled
+            *lock_state(&state) = CallState::Done;
+            res
+        });
+        let res = task.await;
+        guard.cancel_handle = None;
+        res?
+    }
+
+    /// Returns `true` if the given function exists, this waits for any call in progress
+    pub async fn function_exists(&self, name: impl AsRef<str>) -> bool {
+        let name = name.as_ref().to_string();
+        let plugin = self.plugin.clone();
+        tokio::task::spawn_blocking(move || {
+            plugin
+                .lock()
+                .map(|x| x.function_exists(&name))
+                .unwrap_or(false)
+        })
+        .await
+        .unwrap_or(false)
+    }
+
+    /// Get a `CancelHandle`, which can be used to cancel a running call from another thread
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel_handle.clone()
+    }
+
+    /// Get the plugin back, this fails if the `AsyncPlugin` has been cloned or a call is in progress
+    pub fn into_plugin(self) -> Result<Plugin, Self> {
+        let cancel_handle = self.cancel_handle;
+        match Arc::try_unwrap(self.plugin) {
+            Ok(plugin) => Ok(plugin.into_inner().unwrap_or_else(|e| e.into_inner())),
+            Err(plugin) => Err(AsyncPlugin {
+                plugin,
+                cancel_handle,
+            }),
+        }
+    }
+}
+
+impl From<Plugin> for AsyncPlugin {
+    fn from(plugin: Plugin) -> Self {
+        AsyncPlugin::new(plugin)
+    }
+}
+
+impl Plugin {
+    /// Convert the plugin into an `AsyncPlugin`, which can be called from async code, this is the async
+    /// version of `Plugin::call`: `plugin.into_async().call(name, input).await`
+    pub fn into_async(self) -> AsyncPlugin {
+        AsyncPlugin::new(self)
+    }
+}
+
+impl PluginBuilder<'_> {
+    /// Compile and instantiate the plugin on the tokio blocking thread pool. Any borrowed Wasm data or
+    /// manifest is copied first.
+    pub async fn build_async(self) -> Result<AsyncPlugin, Error> {
+        let builder = PluginBuilder {
+            source: self.source.into_owned(),
+            config: self.config,
+            options: self.options,
+        };
+        tokio::task::spawn_blocking(move || builder.build().map(AsyncPlugin::new)).await?
+    }
+}
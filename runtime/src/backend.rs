@@ -0,0 +1,76 @@
+use crate::*;
+
+/// Execution backend used to run a plugin, see `PluginBuilder::with_backend`
+///
+/// The default is `Backend::Compiler`, except on wasm32 hosts where only `Backend::Interpreter` is
+/// available.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Compile plugins to native code, this is the fastest option
+    Compiler,
+
+    /// Compile plugins to portable bytecode that runs on wasmtime's Pulley interpreter. This doesn't
+    /// require executable memory, so it can be used on platforms where JIT compilation isn't allowed,
+    /// at the cost of slower execution. Requires the `interpreter` feature on native hosts.
+    Interpreter,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        if cfg!(target_family = "wasm") {
+            Backend::Interpreter
+        } else {
+            Backend::Compiler
+        }
+    }
+}
+
+impl Backend {
+    pub(crate) fn configure(&self, config: &mut Config) -> Result<(), Error> {
+        match self {
+            Backend::Compiler => {
+                if cfg!(target_family = "wasm") {
+                    anyhow::bail!("the compiler backend isn't available on wasm32 hosts");
+                }
+                Ok(())
+            }
+            Backend::Interpreter => {
+                if cfg!(not(any(feature = "interpreter", target_family = "wasm"))) {
+                    anyhow::bail!("the interpreter backend requires the `interpreter` feature");
+                }
+                let target = match (
+                    cfg!(target_pointer_width = "64"),
+                    cfg!(target_endian = "big"),
+                ) {
+                    (true, false) => "pulley64",
+                    (true, true) => "pulley64be",
+                    (false, false) => "pulley32",
+                    (false, true) => "pulley32be",
+                };
+                config.target(target)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Backend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "compiler" => Ok(Backend::Compiler),
+            "interpreter" => Ok(Backend::Interpreter),
+            _ => anyhow::bail!("invalid backend: {s}"),
+        }
+    }
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::Compiler => f.write_str("compiler"),
+            Backend::Interpreter => f.write_str("interpreter"),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::future::Future;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Poll, Waker};
+
+use crate::*;
+
+#[derive(Default)]
+struct State {
+    result: Option<Result<Plugin, Error>>,
+    wakers: Vec<Waker>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    done: Condvar,
+}
+
+impl Shared {
+    // A panic in a `with_plugin` callback poisons the lock, the state is still valid after that
+    fn lock(&self) -> MutexGuard<'_, State> {
+        match self.state.lock() {
+            Ok(x) => x,
+            Err(e) => e.into_inner(),
+        }
+    }
+}
+
+// Get the message from a panic payload
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(s) => s,
+        None => payload
+            .downcast_ref::<String>()
+            .map_or("unknown error", |s| s.as_str()),
+    }
+}
+
+/// A plugin that's being compiled on a background thread, returned by `PluginBuilder::build_background`
+///
+/// Calls made before compilation finishes wait for it, unless `BackgroundPlugin::with_fail_fast` is
+/// enabled, in which case they fail immediately.
+pub struct BackgroundPlugin {
+    shared: Arc<Shared>,
+    fail_fast: bool,
+}
+
+impl BackgroundPlugin {
+    pub(crate) fn spawn(builder: PluginBuilder<'static>) -> Result<Self, Error> {
+        let shared = Arc::new(Shared::default());
+        let s = shared.clone();
+        std::thread::Builder::new()
+            .name("extism-compile".to_string())
+            .spawn(move || {
+                // The result is always set, even if compilation panics, so waiting callers are woken up
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    CompiledPlugin::new(builder).and_then(|x| Plugin::new_from_compiled(&x))
+                }))
+                .unwrap_or_else(|e| {
+                    Err(Error::msg(format!(
+                        "plugin compilation panicked: {}",
+                        panic_message(e.as_ref())
+                    )))
+                });
+                let mut state = s.lock();
+                state.result = Some(result);
+                for waker in state.wakers.drain(..) {
+                    waker.wake();
+                }
+                s.done.notify_all();
+            })?;
+        Ok(BackgroundPlugin {
+            shared,
+            fail_fast: false,
+        })
+    }
+
+    /// When enabled, calls made before compilation finishes return an error instead of waiting
+    pub fn with_fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Returns `true` once compilation has finished, whether or not it succeeded
+    pub fn is_ready(&self) -> bool {
+        self.shared.lock().result.is_some()
+    }
+
+    /// Block until compilation finishes, returning the compilation error if it failed
+    pub fn wait(&self) -> Result<(), Error> {
+        let mut state = self.shared.lock();
+        while state.result.is_none() {
+            state = match self.shared.done.wait(state) {
+                Ok(x) => x,
+                Err(e) => e.into_inner(),
+            };
+        }
+        check(&state)
+    }
+
+    /// A future that resolves when compilation finishes, this doesn't depend on any particular
+    /// async runtime
+    pub fn ready(&self) -> impl Future<Output = Result<(), Error>> + '_ {
+        std::future::poll_fn(|cx| {
+            let mut state = self.shared.lock();
+            if state.result.is_some() {
+                return Poll::Ready(check(&state));
+            }
+            if !state.wakers.iter().any(|x| x.will_wake(cx.waker())) {
+                state.wakers.push(cx.waker().clone());
+            }
+            Poll::Pending
+        })
+    }
+
+    /// Call a function, see `Plugin::call`
+    pub fn call<'a, I: ToBytes<'a>, O: FromBytesOwned>(
+        &mut self,
+        name: impl AsRef<str>,
+        input: I,
+    ) -> Result<O, Error> {
+        self.with_plugin(|plugin| plugin.call(name, input))
+    }
+
+    /// Access the plugin in a callback function, waiting for compilation to finish if needed
+    pub fn with_plugin<T>(
+        &mut self,
+        f: impl FnOnce(&mut Plugin) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        if self.fail_fast && !self.is_ready() {
+            anyhow::bail!("plugin is still compiling");
+        }
+        self.wait()?;
+        let mut state = self.shared.lock();
+        match &mut state.result {
+            Some(Ok(plugin)) => f(plugin),
+            _ => anyhow::bail!("plugin is not available"),
+        }
+    }
+
+    /// Wait for compilation to finish and take the plugin
+    pub fn into_plugin(self) -> Result<Plugin, Error> {
+        self.wait()?;
+        match self.shared.lock().result.take() {
+            Some(res) => res,
+            None => anyhow::bail!("plugin is not available"),
+        }
+    }
+}
+
+// The compilation error is copied so it can be returned more than once
+fn check(state: &State) -> Result<(), Error> {
+    match &state.result {
+        Some(Err(e)) => Err(Error::msg(format!("{e:#}"))),
+        _ => Ok(()),
+    }
+}
+
+impl PluginBuilder<'_> {
+    /// Compile and instantiate the plugin on a background thread, the returned handle can be used to
+    /// wait for it to be ready. Any borrowed Wasm data or manifest is copied first.
+    pub fn build_background(self) -> Result<BackgroundPlugin, Error> {
+        BackgroundPlugin::spawn(PluginBuilder {
+            source: self.source.into_owned(),
+            config: self.config,
+            options: self.options,
+        })
+    }
+}
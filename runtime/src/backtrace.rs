@@ -0,0 +1,135 @@
+use crate::*;
+
+/// The guest stack when a call trapped, with each frame resolved to the manifest name of its module and a
+/// function name from the module's name section. When `PluginBuilder::with_backtrace_details` is used,
+/// frames also include a source location from the module's DWARF debug info.
+///
+/// It's attached to the error returned by the call, so plugin authors can be told which function failed:
+///
+/// ```no_run
+/// # use extism::*;
+/// # fn example(plugin: &mut Plugin, input: &str) {
+/// if let Err(e) = plugin.call::<&str, &str>("run", input) {
+///     if let Some(bt) = GuestBacktrace::of(&e) {
+///         eprintln!("{bt}");
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct GuestBacktrace {
+    message: String,
+    frames: Vec<GuestFrame>,
+}
+
+/// A single frame of a `GuestBacktrace`, the innermost frame comes first
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct GuestFrame {
+    /// The name of the module in the manifest, `None` when the module isn't part of the plugin
+    pub module: Option<String>,
+
+    /// The function name, from the name section or DWARF debug info
+    pub function: Option<String>,
+
+    /// The index of the function in its module
+    pub func_index: u32,
+
+    /// The offset of the instruction in the module's binary
+    pub offset: Option<usize>,
+
+    /// The source file from the DWARF debug info
+    pub file: Option<String>,
+
+    /// The source line from the DWARF debug info
+    pub line: Option<u32>,
+
+    /// The source column from the DWARF debug info
+    pub column: Option<u32>,
+}
+
+impl GuestBacktrace {
+    /// Resolve the backtrace wasmtime attached to `err`, `None` if there wasn't one
+    pub(crate) fn new(err: &Error, modules: &BTreeMap<String, Module>) -> Option<Self> {
+        let backtrace = err.downcast_ref::<wasmtime::WasmBacktrace>()?;
+        let frames = backtrace
+            .frames()
+            .iter()
+            .map(|frame| {
+                let module = modules
+                    .iter()
+                    .find(|(_, m)| Module::same(m, frame.module()))
+                    .map(|(name, _)| name.clone());
+                let symbol = frame.symbols().first();
+                GuestFrame {
+                    module,
+                    function: frame
+                        .func_name()
+                        .or_else(|| symbol.and_then(|s| s.name()))
+                        .map(String::from),
+                    func_index: frame.func_index(),
+                    offset: frame.module_offset(),
+                    file: symbol.and_then(|s| s.file()).map(String::from),
+                    line: symbol.and_then(|s| s.line()),
+                    column: symbol.and_then(|s| s.column()),
+                }
+            })
+            .collect();
+        Some(GuestBacktrace {
+            message: err.root_cause().to_string(),
+            frames,
+        })
+    }
+
+    /// Get the backtrace attached to an error returned by a call, `None` if the call didn't trap in the
+    /// guest
+    pub fn of(err: &Error) -> Option<&GuestBacktrace> {
+        err.downcast_ref::<GuestBacktrace>()
+    }
+
+    /// The trap message, or the error returned by a host function
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The guest stack, starting with the innermost frame
+    pub fn frames(&self) -> &[GuestFrame] {
+        &self.frames
+    }
+}
+
+impl std::fmt::Display for GuestBacktrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\nguest backtrace:", self.message)?;
+        for (i, frame) in self.frames.iter().enumerate() {
+            write!(f, "\n{i:>5}: {frame}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for GuestFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.module {
+            Some(module) => write!(f, "{module}!")?,
+            None => f.write_str("<unknown>!")?,
+        }
+        match &self.function {
+            Some(function) => f.write_str(function)?,
+            None => write!(f, "<wasm function {}>", self.func_index)?,
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " @ {offset:#x}")?;
+        }
+        if let Some(file) = &self.file {
+            write!(f, "\n         at {file}")?;
+            if let Some(line) = self.line {
+                write!(f, ":{line}")?;
+                if let Some(column) = self.column {
+                    write!(f, ":{column}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+
+use sha2::Digest;
+
+use crate::*;
+
+// `prev_hash` of the first record in a log
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// A single entry in a `CallLog`
+///
+/// Each record contains the hash of the previous record, so modifying, removing or re-ordering
+/// records breaks the chain and can be detected using `CallLog::verify`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct CallRecord {
+    /// Position of the record in the log, starting at 0
+    pub sequence: u64,
+
+    /// SHA-256 hash of the plugin's main Wasm module
+    pub plugin_hash: String,
+
+    /// Name of the function that was called
+    pub function: String,
+
+    /// SHA-256 hash of the input
+    pub input_hash: String,
+
+    /// SHA-256 hash of the output, `None` if the call failed
+    pub output_hash: Option<String>,
+
+    /// Time the call started, in milliseconds since the Unix epoch
+    pub started_at_ms: u64,
+
+    /// Time the call finished, in milliseconds since the Unix epoch
+    pub finished_at_ms: u64,
+
+    /// Hash of the previous record
+    pub prev_hash: String,
+
+    /// Hash of this record
+    pub hash: String,
+}
+
+impl CallRecord {
+    /// Compute the hash of this record, this covers every field except `hash`
+    pub fn compute_hash(&self) -> String {
+        let data = serde_json::to_vec(&(
+            self.sequence,
+            &self.plugin_hash,
+            &self.function,
+            &self.input_hash,
+            &self.output_hash,
+            self.started_at_ms,
+            self.finished_at_ms,
+            &self.prev_hash,
+        ))
+        .expect("call record should serialize");
+        sha256(&data)
+    }
+}
+
+fn sha256(data: &[u8]) -> String {
+    manifest::hex(&sha2::Sha256::digest(data))
+}
+
+fn unix_ms(t: std::time::SystemTime) -> u64 {
+    t.duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+enum Sink {
+    Memory(Vec<CallRecord>),
+    File(std::fs::File),
+}
+
+struct CallLogInner {
+    sequence: u64,
+    prev_hash: String,
+    sink: Sink,
+}
+
+/// `CallLog` is an append-only, hash-chained log of plugin calls. A `CallLog` can be shared between
+/// multiple plugins using `PluginBuilder::with_call_log`.
+#[derive(Clone)]
+pub struct CallLog {
+    inner: Arc<Mutex<CallLogInner>>,
+}
+
+impl std::fmt::Debug for CallLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CallLog")
+    }
+}
+
+impl CallLog {
+    /// Create a new `CallLog` that keeps all records in memory
+    pub fn in_memory() -> Self {
+        Self::new(0, GENESIS_HASH.to_string(), Sink::Memory(vec![]))
+    }
+
+    /// Open a `CallLog` stored as JSON lines at the given path, new records are appended to the end of the
+    /// file. If the file already contains records they're verified before the chain is continued.
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let (sequence, prev_hash) = if path.exists() {
+            let records = Self::read(path)?;
+            Self::verify(&records)?;
+            match records.last() {
+                Some(r) => (r.sequence + 1, r.hash.clone()),
+                None => (0, GENESIS_HASH.to_string()),
+            }
+        } else {
+            (0, GENESIS_HASH.to_string())
+        };
+
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(Self::new(sequence, prev_hash, Sink::File(file)))
+    }
+
+    fn new(sequence: u64, prev_hash: String, sink: Sink) -> Self {
+        CallLog {
+            inner: Arc::new(Mutex::new(CallLogInner {
+                sequence,
+                prev_hash,
+                sink,
+            })),
+        }
+    }
+
+    /// Read all records from a JSON lines log file
+    pub fn read(path: impl AsRef<std::path::Path>) -> Result<Vec<CallRecord>, Error> {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        let mut records = vec![];
+        for line in file.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            records.push(serde_json::from_str(&line)?);
+        }
+        Ok(records)
+    }
+
+    /// Get a copy of all records, this is only available for in-memory logs, use `CallLog::read` to load
+    /// records from a file
+    pub fn records(&self) -> Option<Vec<CallRecord>> {
+        match &self.lock().sink {
+            Sink::Memory(records) => Some(records.clone()),
+            Sink::File(_) => None,
+        }
+    }
+
+    /// Verify the hash chain for a list of records, this should be the full log starting from the first record
+    pub fn verify(records: &[CallRecord]) -> Result<(), Error> {
+        let mut prev_hash = GENESIS_HASH;
+        for (i, record) in records.iter().enumerate() {
+            if record.sequence != i as u64 {
+                anyhow::bail!(
+                    "call log record {i} has an unexpected sequence number: {}",
+                    record.sequence
+                );
+            }
+
+            if record.prev_hash != prev_hash {
+                anyhow::bail!("call log record {i} does not follow the previous record");
+            }
+
+            if record.compute_hash() != record.hash {
+                anyhow::bail!("call log record {i} has been modified");
+            }
+
+            prev_hash = &record.hash;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn append(
+        &self,
+        plugin_hash: &str,
+        function: &str,
+        input: &[u8],
+        output: Option<&[u8]>,
+        started_at: std::time::SystemTime,
+    ) -> Result<(), Error> {
+        let finished_at = std::time::SystemTime::now();
+        let mut inner = self.lock();
+        let mut record = CallRecord {
+            sequence: inner.sequence,
+            plugin_hash: plugin_hash.to_string(),
+            function: function.to_string(),
+            input_hash: sha256(input),
+            output_hash: output.map(sha256),
+            started_at_ms: unix_ms(started_at),
+            finished_at_ms: unix_ms(finished_at),
+            prev_hash: inner.prev_hash.clone(),
+            hash: String::new(),
+        };
+        record.hash = record.compute_hash();
+
+        match &mut inner.sink {
+            Sink::Memory(records) => records.push(record.clone()),
+            Sink::File(file) => {
+                let mut line = serde_json::to_vec(&record)?;
+                line.push(b'\n');
+                file.write_all(&line)?;
+                file.flush()?;
+            }
+        }
+
+        inner.sequence += 1;
+        inner.prev_hash = record.hash;
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CallLogInner> {
+        match self.inner.lock() {
+            Ok(x) => x,
+            Err(e) => e.into_inner(),
+        }
+    }
+}
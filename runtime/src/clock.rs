@@ -0,0 +1,162 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// A `WasiClock` replaces the system clock for WASI plugins, see `PluginBuilder::with_wasi_clock`
+///
+/// Implementations can be used to run plugins at a fixed time or to limit how long plugins are able to
+/// sleep.
+pub trait WasiClock: Send + Sync {
+    /// The current time, as the duration since the Unix epoch
+    fn wall_clock(&self) -> Duration;
+
+    /// The time since an arbitrary starting point, this should never decrease
+    fn monotonic_clock(&self) -> Duration;
+
+    /// Called when the plugin sleeps, by the time this returns `monotonic_clock` should have advanced
+    /// by at least `duration`. The default implementation blocks the current thread
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+}
+
+impl<T: WasiClock + ?Sized> WasiClock for Arc<T> {
+    fn wall_clock(&self) -> Duration {
+        (**self).wall_clock()
+    }
+
+    fn monotonic_clock(&self) -> Duration {
+        (**self).monotonic_clock()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration)
+    }
+}
+
+/// A `WasiClock` that starts at a fixed time and only advances when the plugin sleeps, sleeping
+/// returns immediately. Clones share the same time
+#[derive(Debug, Clone)]
+pub struct FixedClock {
+    start: Duration,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl FixedClock {
+    /// Create a new `FixedClock` starting at `time`
+    pub fn new(time: std::time::SystemTime) -> Self {
+        FixedClock {
+            start: time.duration_since(UNIX_EPOCH).unwrap_or_default(),
+            elapsed: Default::default(),
+        }
+    }
+
+    /// Move the clock forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        *self.lock() += duration;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Duration> {
+        match self.elapsed.lock() {
+            Ok(x) => x,
+            Err(e) => e.into_inner(),
+        }
+    }
+}
+
+impl WasiClock for FixedClock {
+    fn wall_clock(&self) -> Duration {
+        self.start + *self.lock()
+    }
+
+    fn monotonic_clock(&self) -> Duration {
+        *self.lock()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration)
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub(crate) use self::wasi::{wasi_clocks, ClockSched};
+
+// Adapters for `wasi-common`, which isn't available on wasm32 hosts
+#[cfg(not(target_family = "wasm"))]
+mod wasi {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant, UNIX_EPOCH};
+
+    use wasi_common::sched::Poll;
+    use wasi_common::{WasiMonotonicClock, WasiSched, WasiSystemClock};
+
+    use super::WasiClock;
+
+    /// Build the `wasi-common` clocks for a `WasiClock`
+    pub(crate) fn wasi_clocks(clock: &Arc<dyn WasiClock>) -> wasi_common::WasiClocks {
+        wasi_common::WasiClocks::new()
+            .with_system(SystemClock(clock.clone()))
+            .with_monotonic(MonotonicClock {
+                clock: clock.clone(),
+                start: Instant::now(),
+            })
+    }
+
+    struct SystemClock(Arc<dyn WasiClock>);
+
+    impl WasiSystemClock for SystemClock {
+        fn resolution(&self) -> Duration {
+            Duration::from_nanos(1)
+        }
+
+        fn now(&self, _precision: Duration) -> cap_std::time::SystemTime {
+            cap_std::time::SystemTime::from_std(UNIX_EPOCH + self.0.wall_clock())
+        }
+    }
+
+    struct MonotonicClock {
+        clock: Arc<dyn WasiClock>,
+        start: Instant,
+    }
+
+    impl WasiMonotonicClock for MonotonicClock {
+        fn resolution(&self) -> Duration {
+            Duration::from_nanos(1)
+        }
+
+        fn now(&self, _precision: Duration) -> cap_std::time::Instant {
+            cap_std::time::Instant::from_std(self.start + self.clock.monotonic_clock())
+        }
+    }
+
+    /// A `wasi-common` scheduler that sleeps using a `WasiClock`
+    pub(crate) struct ClockSched {
+        pub(crate) clock: Arc<dyn WasiClock>,
+        pub(crate) inner: Box<dyn WasiSched>,
+    }
+
+    #[async_trait::async_trait]
+    impl WasiSched for ClockSched {
+        async fn poll_oneoff<'a>(&self, poll: &mut Poll<'a>) -> Result<(), wasi_common::Error> {
+            // Polls that wait on files use the system clock for their timeout
+            if poll.rw_subscriptions().next().is_some() {
+                return self.inner.poll_oneoff(poll).await;
+            }
+            let duration = poll
+                .earliest_clock_deadline()
+                .and_then(|t| t.duration_until());
+            if let Some(duration) = duration {
+                self.clock.sleep(duration);
+            }
+            Ok(())
+        }
+
+        async fn sched_yield(&self) -> Result<(), wasi_common::Error> {
+            self.inner.sched_yield().await
+        }
+
+        async fn sleep(&self, duration: Duration) -> Result<(), wasi_common::Error> {
+            self.clock.sleep(duration);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::*;
+
+/// Run `f` on each item using one thread per core, the results are in the same order as `items`.
+/// wasm32 hosts don't have threads, so the items are processed one at a time.
+pub(crate) fn par_map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = if cfg!(target_family = "wasm") {
+        1
+    } else {
+        std::thread::available_parallelism()
+            .map(|x| x.get())
+            .unwrap_or(1)
+            .min(items.len())
+    };
+    if threads <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = std::thread::scope(|s| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut results = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(i) else {
+                            return results;
+                        };
+                        results.push((i, f(item)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|x| x.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, x)| x).collect()
+}
+
+/// Combine the errors from loading several modules or plugins, a single error is returned unchanged
+pub(crate) fn combine_errors(kind: &str, errors: Vec<(usize, Error)>) -> Option<Error> {
+    if errors.len() <= 1 {
+        return errors.into_iter().next().map(|(_, e)| e);
+    }
+    let mut msg = format!("{} {kind}s failed to load", errors.len());
+    for (i, e) in errors {
+        msg.push_str(&format!("\n  {kind} {i}: {e:#}"));
+    }
+    Some(Error::msg(msg))
+}
+
+/// The result of `CompiledPlugin::compile_all`
+pub struct CompileReport {
+    /// Compiled plugins or errors, in the same order as the builders
+    pub plugins: Vec<Result<CompiledPlugin, Error>>,
+
+    /// Total time spent compiling
+    pub elapsed: std::time::Duration,
+}
+
+impl CompileReport {
+    /// Errors along with the index of the builder that failed
+    pub fn errors(&self) -> impl Iterator<Item = (usize, &Error)> {
+        self.plugins
+            .iter()
+            .enumerate()
+            .filter_map(|(i, x)| x.as_ref().err().map(|e| (i, e)))
+    }
+
+    /// Get the compiled plugins, or a single error describing every plugin that failed
+    pub fn into_result(self) -> Result<Vec<CompiledPlugin>, Error> {
+        let mut plugins = vec![];
+        let mut errors = vec![];
+        for (i, x) in self.plugins.into_iter().enumerate() {
+            match x {
+                Ok(x) => plugins.push(x),
+                Err(e) => errors.push((i, e)),
+            }
+        }
+        match combine_errors("plugin", errors) {
+            Some(e) => Err(e),
+            None => Ok(plugins),
+        }
+    }
+}
+
+impl CompiledPlugin {
+    /// Compile many plugins in parallel, using one thread per core
+    pub fn compile_all<'a>(builders: impl IntoIterator<Item = PluginBuilder<'a>>) -> CompileReport {
+        Self::compile_all_with_progress(builders, |_, _| ())
+    }
+
+    /// Like `CompiledPlugin::compile_all`, `progress` is called with the number of plugins that have
+    /// finished compiling and the total number of plugins each time a plugin is done
+    pub fn compile_all_with_progress<'a>(
+        builders: impl IntoIterator<Item = PluginBuilder<'a>>,
+        progress: impl Fn(usize, usize) + Sync,
+    ) -> CompileReport {
+        let start = std::time::Instant::now();
+        let builders: Vec<_> = builders
+            .into_iter()
+            .map(|x| std::sync::Mutex::new(Some(x)))
+            .collect();
+        let total = builders.len();
+        let done = AtomicUsize::new(0);
+        let plugins = par_map(&builders, |builder| {
+            let builder = builder.lock().unwrap().take().unwrap();
+            let res = CompiledPlugin::new(builder);
+            progress(done.fetch_add(1, Ordering::SeqCst) + 1, total);
+            res
+        });
+        CompileReport {
+            plugins,
+            elapsed: start.elapsed(),
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::sync::Arc;
+
+use wasmtime::component::types::ComponentItem;
+use wasmtime::component::{Component, InstancePre, Linker, ResourceTable, Type, Val};
+use wasmtime_wasi::{DirPerms, FilePerms, WasiCtx, WasiCtxView, WasiView};
+
+use crate::plugin_builder::PluginBuilderOptions;
+use crate::*;
+
+struct ComponentState {
+    wasi: WasiCtx,
+    table: ResourceTable,
+}
+
+impl WasiView for ComponentState {
+    fn ctx(&mut self) -> WasiCtxView<'_> {
+        WasiCtxView {
+            ctx: &mut self.wasi,
+            table: &mut self.table,
+        }
+    }
+}
+
+/// A component that's used as the main module, calls are forwarded to a `ComponentPlugin` created from it
+#[derive(Clone)]
+pub(crate) struct ComponentConfig {
+    pre: InstancePre<ComponentState>,
+    exports: Arc<Vec<String>>,
+    manifest: Arc<Manifest>,
+    wasi: bool,
+    fuel: Option<u64>,
+}
+
+impl ComponentConfig {
+    pub(crate) fn new(
+        engine: &Engine,
+        component: &Component,
+        manifest: &Manifest,
+        options: &PluginBuilderOptions,
+    ) -> Result<Self, Error> {
+        let mut linker = Linker::new(engine);
+        if options.wasi {
+            wasmtime_wasi::p2::add_to_linker_sync(&mut linker)?;
+        }
+        let pre = linker.instantiate_pre(component)?;
+
+        // Functions in exported interfaces are called using `interface#function`
+        let mut exports = vec![];
+        for (name, item) in component.component_type().exports(engine) {
+            match item {
+                ComponentItem::ComponentFunc(f) if supported(&f) => exports.push(name.to_string()),
+                ComponentItem::ComponentInstance(instance) => {
+                    for (func, item) in instance.exports(engine) {
+                        if let ComponentItem::ComponentFunc(f) = item {
+                            if supported(&f) {
+                                exports.push(format!("{name}#{func}"));
+                            }
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(ComponentConfig {
+            pre,
+            exports: Arc::new(exports),
+            manifest: Arc::new(manifest.clone()),
+            wasi: options.wasi,
+            fuel: options.fuel,
+        })
+    }
+
+    /// Instantiate the component
+    pub(crate) fn start(&self) -> Result<ComponentPlugin, Error> {
+        let mut plugin = ComponentPlugin {
+            config: self.clone(),
+            store: self.new_store()?,
+            instance: None,
+            output: vec![],
+        };
+        plugin.instantiate()?;
+        Ok(plugin)
+    }
+
+    fn new_store(&self) -> Result<Store<ComponentState>, Error> {
+        let mut wasi = WasiCtx::builder();
+        if self.wasi {
+            let env: Vec<_> = self.manifest.config.iter().collect();
+            wasi.envs(&env);
+            if let Some(paths) = &self.manifest.allowed_paths {
+                for (k, v) in paths.iter() {
+                    let (dir, dir_perms, file_perms) = match k.strip_prefix("ro:") {
+                        Some(dir) => (dir, DirPerms::READ, FilePerms::READ),
+                        None => (k.as_str(), DirPerms::all(), FilePerms::all()),
+                    };
+                    wasi.preopened_dir(dir, v.to_string_lossy(), dir_perms, file_perms)?;
+                }
+            }
+            if std::env::var("EXTISM_ENABLE_WASI_OUTPUT").is_ok() {
+                wasi.inherit_stdout().inherit_stderr();
+            }
+        }
+        let mut store = Store::new(
+            self.pre.engine(),
+            ComponentState {
+                wasi: wasi.build(),
+                table: ResourceTable::new(),
+            },
+        );
+
+        // The engine uses epoch interruption, but timeouts aren't supported for components
+        store.epoch_deadline_callback(|_| Ok(UpdateDeadline::Continue(1)));
+        Ok(store)
+    }
+}
+
+// Exported functions take no arguments, a `list<u8>` or a `string` and return nothing, a `list<u8>`, a
+// `string` or a `result` of those with an error
+fn supported(f: &wasmtime::component::types::ComponentFunc) -> bool {
+    fn bytes(ty: &Type) -> bool {
+        match ty {
+            Type::String => true,
+            Type::List(l) => l.ty() == Type::U8,
+            _ => false,
+        }
+    }
+
+    let mut params = f.params();
+    let params = match params.len() {
+        0 => true,
+        1 => params.next().is_some_and(|(_, ty)| bytes(&ty)),
+        _ => false,
+    };
+    let mut results = f.results();
+    let results = match results.len() {
+        0 => true,
+        1 => match results.next() {
+            Some(Type::Result(r)) => {
+                r.ok().as_ref().is_none_or(bytes) && r.err().as_ref().is_none_or(bytes)
+            }
+            Some(ty) => bytes(&ty),
+            None => false,
+        },
+        _ => false,
+    };
+    params && results
+}
+
+fn to_bytes(val: Val) -> Result<Vec<u8>, Error> {
+    match val {
+        Val::String(s) => Ok(s.into_bytes()),
+        Val::List(items) => items
+            .into_iter()
+            .map(|x| match x {
+                Val::U8(b) => Ok(b),
+                _ => anyhow::bail!("expected a list<u8>"),
+            })
+            .collect(),
+        _ => anyhow::bail!("expected a list<u8> or string"),
+    }
+}
+
+/// An instance of a component plugin
+pub(crate) struct ComponentPlugin {
+    config: ComponentConfig,
+    store: Store<ComponentState>,
+    instance: Option<wasmtime::component::Instance>,
+    pub(crate) output: Vec<u8>,
+}
+
+impl ComponentPlugin {
+    fn instantiate(&mut self) -> Result<(), Error> {
+        self.instance = Some(self.config.pre.instantiate(&mut self.store)?);
+        Ok(())
+    }
+
+    pub(crate) fn function_exists(&self, name: &str) -> bool {
+        self.config.exports.iter().any(|x| x == name)
+    }
+
+    /// Create a new instance, so the next call starts from the component's initial state
+    pub(crate) fn reset(&mut self) -> Result<(), Error> {
+        self.store = self.config.new_store()?;
+        self.instance = None;
+        self.instantiate()
+    }
+
+    pub(crate) fn call(&mut self, name: &str, input: &[u8]) -> Result<i32, (Error, i32)> {
+        self.output.clear();
+        self.call_inner(name, input)
+            .map_err(|e| (e, -1))?
+            .map_err(|e| (e, 1))
+    }
+
+    // The outer error is returned when the function can't be called, the inner error when the function
+    // returns an error
+    fn call_inner(&mut self, name: &str, input: &[u8]) -> Result<Result<i32, Error>, Error> {
+        if !self.function_exists(name) {
+            anyhow::bail!("function not found: {name}");
+        }
+        if self.instance.is_none() {
+            self.instantiate()?;
+        }
+        let instance = self.instance.unwrap();
+        let func = match name.split_once('#') {
+            Some((interface, func)) => {
+                let index = instance.get_export_index(&mut self.store, None, interface);
+                index
+                    .and_then(|x| instance.get_export_index(&mut self.store, Some(&x), func))
+                    .and_then(|x| instance.get_func(&mut self.store, x))
+            }
+            None => instance.get_func(&mut self.store, name),
+        };
+        let Some(func) = func else {
+            anyhow::bail!("function not found: {name}");
+        };
+        if let Some(fuel) = self.config.fuel {
+            self.store.set_fuel(fuel)?;
+        }
+
+        let ty = func.ty(&self.store);
+        let params = match ty.params().next() {
+            Some((_, Type::String)) => vec![Val::String(String::from_utf8(input.to_vec())?)],
+            Some(_) => vec![Val::List(input.iter().map(|x| Val::U8(*x)).collect())],
+            None => vec![],
+        };
+        let mut results = vec![Val::Bool(false); ty.results().len()];
+        if let Err(e) = func.call(&mut self.store, &params, &mut results) {
+            // A trap leaves the instance in an unknown state, so the next call uses a new one
+            self.instance = None;
+            self.store = self.config.new_store()?;
+            return Err(e.into());
+        }
+        match results.pop() {
+            Some(Val::Result(Err(e))) => {
+                let msg = match e {
+                    Some(e) => String::from_utf8_lossy(&to_bytes(*e)?).into_owned(),
+                    None => format!("{name} returned an error"),
+                };
+                Ok(Err(Error::msg(msg)))
+            }
+            Some(Val::Result(Ok(x))) => {
+                if let Some(x) = x {
+                    self.output = to_bytes(*x)?;
+                }
+                Ok(Ok(0))
+            }
+            Some(x) => {
+                self.output = to_bytes(x)?;
+                Ok(Ok(0))
+            }
+            None => Ok(Ok(0)),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+use crate::*;
+
+/// `ConcurrentPlugin` runs calls on several instances of the same compiled plugin, each call is
+/// dispatched to an idle instance and new instances are created as needed, up to `max_instances`.
+/// A `ConcurrentPlugin` can be cloned and shared between threads, the instances are shared by all
+/// clones.
+///
+/// Each instance has its own memory and variables, so calls can't share state through the plugin.
+/// Use a `Pool` directly when a plugin has to be checked out for several related calls.
+#[derive(Clone)]
+pub struct ConcurrentPlugin {
+    compiled: Arc<CompiledPlugin>,
+    pool: Pool,
+    max_instances: usize,
+}
+
+impl ConcurrentPlugin {
+    /// Create a new `ConcurrentPlugin` that runs at most `max_instances` calls at the same time
+    pub fn new(compiled: CompiledPlugin, max_instances: usize) -> Self {
+        let compiled = Arc::new(compiled);
+        let source = compiled.clone();
+        let pool = PoolBuilder::new()
+            .with_max_instances(max_instances)
+            .build(move || Plugin::new_from_compiled(&source));
+        ConcurrentPlugin {
+            compiled,
+            pool,
+            max_instances,
+        }
+    }
+
+    /// Call a function on an idle instance, this blocks until an instance is available
+    pub fn call<'a, I: ToBytes<'a>, O: FromBytesOwned>(
+        &self,
+        name: impl AsRef<str>,
+        input: I,
+    ) -> Result<O, Error> {
+        self.get()?.call(name, input)
+    }
+
+    /// Like `ConcurrentPlugin::call`, but `Ok(None)` is returned if no instance becomes available
+    /// before `timeout`
+    pub fn call_timeout<'a, I: ToBytes<'a>, O: FromBytesOwned>(
+        &self,
+        name: impl AsRef<str>,
+        input: I,
+        timeout: std::time::Duration,
+    ) -> Result<Option<O>, Error> {
+        match self.pool.get(timeout)? {
+            Some(mut plugin) => plugin.call(name, input).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns `true` if the given function exists, otherwise `false`
+    pub fn function_exists(&self, name: impl AsRef<str>) -> bool {
+        match self.get() {
+            Ok(plugin) => plugin.function_exists(name),
+            Err(_) => false,
+        }
+    }
+
+    /// The number of instances that have been created, including instances running a call
+    pub fn instances(&self) -> usize {
+        self.pool.count()
+    }
+
+    /// The max number of instances
+    pub fn max_instances(&self) -> usize {
+        self.max_instances
+    }
+
+    /// The compiled plugin used to create new instances
+    pub fn compiled(&self) -> &CompiledPlugin {
+        &self.compiled
+    }
+
+    // Wait for an idle instance
+    fn get(&self) -> Result<PoolPlugin, Error> {
+        loop {
+            if let Some(plugin) = self.pool.get(std::time::Duration::from_secs(60))? {
+                return Ok(plugin);
+            }
+        }
+    }
+}
+
+impl CompiledPlugin {
+    /// Create a `ConcurrentPlugin` that runs calls on up to `max_instances` instances of this plugin
+    pub fn into_concurrent(self, max_instances: usize) -> ConcurrentPlugin {
+        ConcurrentPlugin::new(self, max_instances)
+    }
+}
@@ -8,30 +8,185 @@ pub struct CurrentPlugin {
     /// Plugin variables
     pub(crate) vars: std::collections::BTreeMap<String, Vec<u8>>,
 
+    /// Interned manifest config, read by `config_get`
+    pub(crate) config: Config,
+
     /// Extism manifest
     pub(crate) manifest: extism_manifest::Manifest,
     pub(crate) store: *mut Store<CurrentPlugin>,
     pub(crate) linker: *mut wasmtime::Linker<CurrentPlugin>,
     pub(crate) wasi: Option<Wasi>,
+    pub(crate) wasi_sources: WasiSources,
+    #[cfg(feature = "wasi-nn")]
+    pub(crate) wasi_nn: Option<wasmtime_wasi_nn::witx::WasiNnCtx>,
     pub(crate) http_status: u16,
     pub(crate) http_headers: Option<std::collections::BTreeMap<String, String>>,
-    pub(crate) available_pages: Option<u32>,
+    #[cfg(feature = "http")]
+    pub(crate) http_streams: pdk::HttpStreams,
+    #[cfg(feature = "guest-profiler")]
+    pub(crate) profiler: Option<profiler::Sampler>,
     pub(crate) memory_limiter: Option<MemoryLimiter>,
     pub(crate) id: uuid::Uuid,
     pub(crate) start_time: std::time::Instant,
+    pub(crate) deadline: Option<std::time::Instant>,
+    pub(crate) host_calls: quota::HostCallCounter,
+    pub(crate) host_usage: usage::HostUsage,
+    pub(crate) host_stats: usage::HostCallStats,
+    pub(crate) kernel: Kernel,
+    pub(crate) secrets: secrets::Secrets,
+    pub(crate) redactor: Option<std::sync::Arc<dyn Redactor>>,
+    pub(crate) log_handler: Option<std::sync::Arc<dyn LogHandler>>,
+
+    /// The exported function that's being called
+    pub(crate) function: String,
+    pub(crate) kv: Option<std::sync::Arc<dyn KvStore>>,
+    pub(crate) var_store: Option<std::sync::Arc<dyn VarStore>>,
+    #[cfg(feature = "http")]
+    pub(crate) http_agent: Option<ureq::Agent>,
+    pub(crate) sql: Option<(std::sync::Arc<dyn SqlDatabase>, String)>,
+    pub(crate) objects: Option<(std::sync::Arc<dyn ObjectStore>, String)>,
+    pub(crate) msg: Option<msg::MsgState>,
+    pub(crate) net: Option<net::NetState>,
+    #[cfg(feature = "websocket")]
+    pub(crate) ws: Option<ws::WsState>,
+    pub(crate) events: Option<EventBus>,
+    pub(crate) metrics: Option<std::sync::Arc<dyn Metrics>>,
+    pub(crate) io: std::sync::Arc<resources::IoCounters>,
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) mock: Option<testing::MockHost>,
+    /// Limits the guest threads spawned using `wasi::thread-spawn`, set when wasm threads are enabled
+    pub(crate) threads: Option<std::sync::Arc<threads::ThreadPool>>,
 }
 
 unsafe impl Send for CurrentPlugin {}
 
+/// Replacements for the system clock, random number generator and filesystem used by WASI
+#[derive(Clone, Default)]
+pub(crate) struct WasiSources {
+    pub(crate) clock: Option<std::sync::Arc<dyn WasiClock>>,
+    pub(crate) random: Option<std::sync::Arc<dyn WasiRandom>>,
+    /// Use a `FixedClock` and `SeededRandom` when `clock` and `random` aren't set
+    pub(crate) deterministic: bool,
+    /// Preopened at `/` instead of the manifest's `allowed_paths`
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) memory_fs: Option<memory_fs::MemoryFs>,
+    pub(crate) stdin: Option<Stdin>,
+    pub(crate) stdout: Option<stdio::SharedWriter>,
+    pub(crate) stderr: Option<stdio::SharedWriter>,
+    /// Give each plugin its own `captured` buffers
+    pub(crate) capture_output: bool,
+    /// The max number of bytes captured from each stream, `stdio::DEFAULT_CAPTURE_LIMIT` when it isn't set
+    pub(crate) capture_limit: Option<usize>,
+    pub(crate) captured: Option<std::sync::Arc<stdio::Captured>>,
+}
+
+/// Exports of the Extism kernel, these are resolved after linking so host functions don't look them up
+/// by name each time they access plugin memory
+#[derive(Default, Clone, Copy)]
+pub(crate) struct Kernel {
+    pub(crate) memory: Option<Memory>,
+    pub(crate) context: Option<Global>,
+    pub(crate) alloc: Option<Func>,
+    pub(crate) free: Option<Func>,
+    pub(crate) length: Option<Func>,
+    pub(crate) length_unsafe: Option<Func>,
+    pub(crate) error_set: Option<Func>,
+    pub(crate) error_get: Option<Func>,
+    pub(crate) input_set: Option<Func>,
+    pub(crate) output_offset: Option<Func>,
+    pub(crate) output_length: Option<Func>,
+    pub(crate) reset: Option<Func>,
+}
+
+impl Kernel {
+    pub(crate) fn resolve(
+        linker: &Linker<CurrentPlugin>,
+        store: &mut Store<CurrentPlugin>,
+    ) -> Self {
+        let mut get = |name| linker.get(&mut *store, EXTISM_ENV_MODULE, name);
+        Kernel {
+            memory: get("memory").and_then(Extern::into_memory),
+            context: get("extism_context").and_then(Extern::into_global),
+            alloc: get("alloc").and_then(Extern::into_func),
+            free: get("free").and_then(Extern::into_func),
+            length: get("length").and_then(Extern::into_func),
+            length_unsafe: get("length_unsafe").and_then(Extern::into_func),
+            error_set: get("error_set").and_then(Extern::into_func),
+            error_get: get("error_get").and_then(Extern::into_func),
+            input_set: get("input_set").and_then(Extern::into_func),
+            output_offset: get("output_offset").and_then(Extern::into_func),
+            output_length: get("output_length").and_then(Extern::into_func),
+            reset: get("reset").and_then(Extern::into_func),
+        }
+    }
+}
+
+/// Manifest config with shared keys and values, so `config_get` can copy a value into plugin memory
+/// without cloning it or holding a borrow of the manifest
+#[derive(Default)]
+pub(crate) struct Config(BTreeMap<std::sync::Arc<str>, std::sync::Arc<str>>);
+
+impl Config {
+    pub(crate) fn new(config: &BTreeMap<String, String>) -> Self {
+        Config(
+            config
+                .iter()
+                .map(|(k, v)| (k.as_str().into(), v.as_str().into()))
+                .collect(),
+        )
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<std::sync::Arc<str>> {
+        self.0.get(key).cloned()
+    }
+
+    pub(crate) fn insert(&mut self, key: &str, value: &str) {
+        self.0.insert(key.into(), value.into());
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) {
+        self.0.remove(key);
+    }
+}
+
 pub(crate) struct MemoryLimiter {
     bytes_left: usize,
     max_bytes: usize,
+    events: Option<(EventBus, uuid::Uuid)>,
+    metrics: Option<(std::sync::Arc<dyn Metrics>, uuid::Uuid)>,
 }
 
 impl MemoryLimiter {
+    pub(crate) fn new(max_bytes: usize) -> Self {
+        MemoryLimiter {
+            max_bytes,
+            bytes_left: max_bytes,
+            events: None,
+            metrics: None,
+        }
+    }
+
     pub(crate) fn reset(&mut self) {
         self.bytes_left = self.max_bytes;
     }
+
+    /// The max number of bytes the plugin's memories can use, `None` when there's no limit
+    pub(crate) fn limit(&self) -> Option<usize> {
+        (self.max_bytes != usize::MAX).then_some(self.max_bytes)
+    }
+
+    fn emit_grown(&self, from: usize, to: usize) {
+        if let Some((events, id)) = &self.events {
+            events.emit(PluginEvent::MemoryGrown {
+                plugin: *id,
+                from,
+                to,
+            });
+        }
+        if let Some((metrics, id)) = &self.metrics {
+            metrics.memory_grown(*id, from, to);
+        }
+    }
 }
 
 impl wasmtime::ResourceLimiter for MemoryLimiter {
@@ -41,18 +196,29 @@ impl wasmtime::ResourceLimiter for MemoryLimiter {
         desired: usize,
         maximum: Option<usize>,
     ) -> Result<bool> {
+        // Without a configured limit the limiter is only used to observe memory growth, so keep
+        // the default Wasmtime behavior
+        if self.max_bytes == usize::MAX {
+            let ok = maximum.is_none_or(|max| desired <= max);
+            if ok {
+                self.emit_grown(current, desired);
+            }
+            return Ok(ok);
+        }
+
         if let Some(max) = maximum {
             if desired >= max {
-                return Err(wasmtime::Error::msg("oom"));
+                return Err(wasmtime::Error::new(OutOfMemory));
             }
         }
 
         let d = desired - current;
         if d > self.bytes_left {
-            return Err(wasmtime::Error::msg("oom"));
+            return Err(wasmtime::Error::new(OutOfMemory));
         }
 
         self.bytes_left -= d;
+        self.emit_grown(current, desired);
         Ok(true)
     }
 
@@ -115,15 +281,44 @@ impl CurrentPlugin {
 
     /// Allocate a handle large enough for the encoded Rust type and copy it into Extism memory
     pub fn memory_new<'a, T: ToBytes<'a>>(&mut self, t: T) -> Result<MemoryHandle, Error> {
-        let data = t.to_bytes()?;
-        let data = data.as_ref();
-        if data.is_empty() {
-            return Ok(MemoryHandle::null());
+        self.memory_new_from(&t)
+    }
+
+    /// Like `memory_new`, but borrows the value. Types that know their encoded length are written
+    /// directly into the allocated memory instead of being encoded into a host buffer first.
+    pub(crate) fn memory_new_from<'a, T: ToBytes<'a>>(
+        &mut self,
+        t: &T,
+    ) -> Result<MemoryHandle, Error> {
+        if let Some(len) = t.encoded_len() {
+            if len == 0 {
+                return Ok(MemoryHandle::null());
+            }
+            let handle = self.memory_alloc(len as u64)?;
+            let mut bytes = self.memory_bytes_mut(handle)?;
+            let res = t.to_writer(&mut bytes).and_then(|()| {
+                if !bytes.is_empty() {
+                    anyhow::bail!("encoded value is shorter than its encoded length");
+                }
+                Ok(())
+            });
+            if let Err(e) = res {
+                self.memory_free(handle)?;
+                return Err(e);
+            }
+            return Ok(handle);
         }
-        let handle = self.memory_alloc(data.len() as u64)?;
-        let bytes = self.memory_bytes_mut(handle)?;
-        bytes.copy_from_slice(data.as_ref());
-        Ok(handle)
+
+        // Otherwise the value is encoded into a reusable buffer and copied into memory
+        t.with_encoded(|data| {
+            if data.is_empty() {
+                return Ok(MemoryHandle::null());
+            }
+            let handle = self.memory_alloc(data.len() as u64)?;
+            let bytes = self.memory_bytes_mut(handle)?;
+            bytes.copy_from_slice(data);
+            Ok(handle)
+        })?
     }
 
     /// Decode a Rust type from Extism memory
@@ -168,9 +363,9 @@ impl CurrentPlugin {
     }
 
     pub fn memory_bytes_mut(&mut self, handle: MemoryHandle) -> Result<&mut [u8], Error> {
-        let (linker, store) = self.linker_and_store();
-        if let Some(mem) = linker.get(&mut *store, EXTISM_ENV_MODULE, "memory") {
-            let mem = mem.into_memory().unwrap();
+        let memory = self.kernel.memory;
+        let (_, store) = self.linker_and_store();
+        if let Some(mem) = memory {
             let ptr = unsafe { mem.data_ptr(&*store).add(handle.offset() as usize) };
             if ptr.is_null() {
                 return Ok(&mut []);
@@ -182,13 +377,14 @@ impl CurrentPlugin {
     }
 
     pub fn memory_bytes(&mut self, handle: MemoryHandle) -> Result<&[u8], Error> {
-        let (linker, store) = self.linker_and_store();
-        if let Some(mem) = linker.get(&mut *store, EXTISM_ENV_MODULE, "memory") {
-            let mem = mem.into_memory().unwrap();
+        let memory = self.kernel.memory;
+        let (_, store) = self.linker_and_store();
+        if let Some(mem) = memory {
             let ptr = unsafe { mem.data_ptr(&*store).add(handle.offset() as usize) };
             if ptr.is_null() {
                 return Ok(&[]);
             }
+            self.host_stats.read(handle.length);
             return Ok(unsafe { std::slice::from_raw_parts(ptr, handle.len()) });
         }
 
@@ -196,9 +392,9 @@ impl CurrentPlugin {
     }
 
     pub fn host_context<T: 'static>(&mut self) -> Result<&mut T, Error> {
-        let (linker, store) = self.linker_and_store();
-        let Some(Extern::Global(xs)) = linker.get(&mut *store, EXTISM_ENV_MODULE, "extism_context")
-        else {
+        let context = self.kernel.context;
+        let (_, store) = self.linker_and_store();
+        let Some(xs) = context else {
             anyhow::bail!("unable to locate an extism kernel global: extism_context",)
         };
 
@@ -226,14 +422,13 @@ impl CurrentPlugin {
                 length: 0,
             });
         }
-        let (linker, mut store) = self.linker_and_store();
+        let alloc = self.kernel.alloc;
+        let (_, store) = self.linker_and_store();
         let output = &mut [Val::I64(0)];
-        if let Some(f) = linker.get(&mut store, EXTISM_ENV_MODULE, "alloc") {
+        if let Some(f) = alloc {
             catch_out_of_fuel!(
                 &store,
-                f.into_func()
-                    .unwrap()
-                    .call(&mut *store, &[Val::I64(n as i64)], output)
+                f.call(&mut *store, &[Val::I64(n as i64)], output)
                     .context("failed to allocate extism memory")
             )?;
         } else {
@@ -249,6 +444,7 @@ impl CurrentPlugin {
             offs,
             n
         );
+        self.host_stats.written(n);
         Ok(MemoryHandle {
             offset: offs,
             length: n,
@@ -257,13 +453,12 @@ impl CurrentPlugin {
 
     /// Free a block of Extism plugin memory
     pub fn memory_free(&mut self, handle: MemoryHandle) -> Result<(), Error> {
-        let (linker, store) = self.linker_and_store();
-        if let Some(f) = linker.get(&mut *store, EXTISM_ENV_MODULE, "free") {
+        let free = self.kernel.free;
+        let (_, store) = self.linker_and_store();
+        if let Some(f) = free {
             catch_out_of_fuel!(
                 &store,
-                f.into_func()
-                    .unwrap()
-                    .call(&mut *store, &[Val::I64(handle.offset as i64)], &mut [])
+                f.call(&mut *store, &[Val::I64(handle.offset as i64)], &mut [])
                     .context("failed to free extism memory")
             )?;
         } else {
@@ -273,14 +468,13 @@ impl CurrentPlugin {
     }
 
     pub fn memory_length(&mut self, offs: u64) -> Result<u64, Error> {
-        let (linker, store) = self.linker_and_store();
+        let length = self.kernel.length;
+        let (_, store) = self.linker_and_store();
         let output = &mut [Val::I64(0)];
-        if let Some(f) = linker.get(&mut *store, EXTISM_ENV_MODULE, "length") {
+        if let Some(f) = length {
             catch_out_of_fuel!(
                 &store,
-                f.into_func()
-                    .unwrap()
-                    .call(&mut *store, &[Val::I64(offs as i64)], output)
+                f.call(&mut *store, &[Val::I64(offs as i64)], output)
                     .context("failed to get length of extism memory handle")
             )?;
         } else {
@@ -297,14 +491,13 @@ impl CurrentPlugin {
     }
 
     pub fn memory_length_unsafe(&mut self, offs: u64) -> Result<u64, Error> {
-        let (linker, store) = self.linker_and_store();
+        let length_unsafe = self.kernel.length_unsafe;
+        let (_, store) = self.linker_and_store();
         let output = &mut [Val::I64(0)];
-        if let Some(f) = linker.get(&mut *store, EXTISM_ENV_MODULE, "length_unsafe") {
+        if let Some(f) = length_unsafe {
             catch_out_of_fuel!(
                 &store,
-                f.into_func()
-                    .unwrap()
-                    .call(&mut *store, &[Val::I64(offs as i64)], output)
+                f.call(&mut *store, &[Val::I64(offs as i64)], output)
                     .context("failed to get length of extism memory using length_unsafe")
             )?;
         } else {
@@ -320,7 +513,30 @@ impl CurrentPlugin {
         Ok(len)
     }
 
-    /// Access a plugin's variables
+    // Clear everything that belongs to the current store and instance, so this `CurrentPlugin` can be
+    // moved into a new store by `Plugin::reset_store`. Host state like the event bus, key/value store and
+    // host function counters is kept.
+    pub(crate) fn recycle(&mut self) -> Result<(), Error> {
+        if self.wasi.is_some() {
+            self.wasi = Some(new_wasi(&self.manifest, &self.wasi_sources, &self.io)?);
+        }
+        self.vars.clear();
+        self.http_status = 0;
+        if let Some(headers) = &mut self.http_headers {
+            headers.clear();
+        }
+        #[cfg(feature = "http")]
+        self.http_streams.clear();
+        if let Some(limiter) = &mut self.memory_limiter {
+            limiter.reset();
+        }
+        self.kernel = Kernel::default();
+        self.store = std::ptr::null_mut();
+        self.linker = std::ptr::null_mut();
+        Ok(())
+    }
+
+    /// Access a plugin's variables, this is empty when a `VarStore` is configured
     pub fn vars(&self) -> &std::collections::BTreeMap<String, Vec<u8>> {
         &self.vars
     }
@@ -338,70 +554,76 @@ impl CurrentPlugin {
     pub(crate) fn new(
         manifest: extism_manifest::Manifest,
         wasi: bool,
+        wasi_sources: WasiSources,
         available_pages: Option<u32>,
         allow_http_response_headers: bool,
         id: uuid::Uuid,
+        io: std::sync::Arc<resources::IoCounters>,
     ) -> Result<Self, Error> {
+        let mut wasi_sources = wasi_sources;
+        if wasi_sources.capture_output {
+            let limit = wasi_sources
+                .capture_limit
+                .unwrap_or(stdio::DEFAULT_CAPTURE_LIMIT);
+            wasi_sources.captured = Some(std::sync::Arc::new(stdio::Captured::new(limit)));
+        }
         let wasi = if wasi {
-            let auth = wasi_common::sync::ambient_authority();
-            let random = wasi_common::sync::random_ctx();
-            let clocks = wasi_common::sync::clocks_ctx();
-            let sched = wasi_common::sync::sched_ctx();
-            let table = wasi_common::Table::new();
-            let ctx = wasi_common::WasiCtx::new(random, clocks, sched, table);
-
-            if let Some(a) = &manifest.allowed_paths {
-                for (k, v) in a.iter() {
-                    let readonly = k.starts_with("ro:");
-
-                    let dir_path = if readonly { &k[3..] } else { k };
-
-                    let dir = wasi_common::sync::dir::Dir::from_cap_std(
-                        wasi_common::sync::Dir::open_ambient_dir(dir_path, auth)?,
-                    );
-
-                    let file: Box<dyn wasi_common::dir::WasiDir> = if readonly {
-                        Box::new(readonly_dir::ReadOnlyDir::new(dir))
-                    } else {
-                        Box::new(dir)
-                    };
-
-                    ctx.push_preopened_dir(file, v)?;
-                }
-            }
-
-            // Enable WASI output, typically used for debugging purposes
-            if std::env::var("EXTISM_ENABLE_WASI_OUTPUT").is_ok() {
-                ctx.set_stderr(Box::new(wasi_common::sync::stdio::stderr()));
-                ctx.set_stdout(Box::new(wasi_common::sync::stdio::stdout()));
-            }
-
-            Some(Wasi { ctx })
+            Some(new_wasi(&manifest, &wasi_sources, &io)?)
         } else {
             None
         };
 
-        let memory_limiter = if let Some(pgs) = available_pages {
-            let n = pgs as usize * 65536;
-            Some(MemoryLimiter {
-                max_bytes: n,
-                bytes_left: n,
-            })
-        } else {
-            None
-        };
+        let memory_limiter = available_pages.map(|pgs| MemoryLimiter::new(pgs as usize * 65536));
+
+        let config = Config::new(&manifest.config);
+        let net = manifest
+            .allowed_sockets
+            .as_ref()
+            .map(|_| net::NetState::default());
 
         Ok(CurrentPlugin {
             wasi,
+            wasi_sources,
+            #[cfg(feature = "wasi-nn")]
+            wasi_nn: None,
             manifest,
             http_status: 0,
+            #[cfg(feature = "http")]
+            http_streams: Default::default(),
+            #[cfg(feature = "guest-profiler")]
+            profiler: None,
             vars: BTreeMap::new(),
+            config,
             linker: std::ptr::null_mut(),
             store: std::ptr::null_mut(),
-            available_pages,
             memory_limiter,
             id,
             start_time: std::time::Instant::now(),
+            deadline: None,
+            host_calls: Default::default(),
+            host_usage: Default::default(),
+            host_stats: Default::default(),
+            kernel: Default::default(),
+            secrets: Default::default(),
+            redactor: None,
+            log_handler: None,
+            function: String::new(),
+            kv: None,
+            var_store: None,
+            #[cfg(feature = "http")]
+            http_agent: None,
+            sql: None,
+            objects: None,
+            msg: None,
+            net,
+            #[cfg(feature = "websocket")]
+            ws: None,
+            events: None,
+            metrics: None,
+            io,
+            #[cfg(not(target_family = "wasm"))]
+            mock: None,
+            threads: None,
             http_headers: if allow_http_response_headers {
                 Some(BTreeMap::new())
             } else {
@@ -410,6 +632,77 @@ impl CurrentPlugin {
         })
     }
 
+    /// Set the `EventBus` used to publish lifecycle events, a `MemoryLimiter` without a limit is
+    /// installed if needed so memory growth can be observed
+    pub(crate) fn set_event_bus(&mut self, events: Option<EventBus>) {
+        if let Some(events) = &events {
+            let limiter = self
+                .memory_limiter
+                .get_or_insert_with(|| MemoryLimiter::new(usize::MAX));
+            limiter.events = Some((events.clone(), self.id));
+        }
+        self.events = events;
+    }
+
+    /// Set the `Metrics` that measurements are reported to, like `CurrentPlugin::set_event_bus` a
+    /// `MemoryLimiter` is installed if needed
+    pub(crate) fn set_metrics(&mut self, metrics: Option<std::sync::Arc<dyn Metrics>>) {
+        if let Some(metrics) = &metrics {
+            let limiter = self
+                .memory_limiter
+                .get_or_insert_with(|| MemoryLimiter::new(usize::MAX));
+            limiter.metrics = Some((metrics.clone(), self.id));
+        }
+        self.metrics = metrics;
+    }
+
+    /// Files and directories used by the plugin's stores, which host functions need to access when
+    /// `Hardening` is enabled
+    pub(crate) fn local_paths(&self) -> Vec<std::path::PathBuf> {
+        let mut paths = vec![];
+        if let Some(kv) = &self.kv {
+            paths.extend(kv.local_paths());
+        }
+        if let Some(vars) = &self.var_store {
+            paths.extend(vars.local_paths());
+        }
+        if let Some((db, _)) = &self.sql {
+            paths.extend(db.local_paths());
+        }
+        paths
+    }
+
+    /// Called before every host function call to track usage and enforce `HostFunctionLimits`
+    pub(crate) fn record_host_call(&mut self, index: usize, args: &[Val]) -> Result<(), Error> {
+        self.host_stats.begin(index);
+        if tracing::enabled!(tracing::Level::TRACE) {
+            let name = self.host_usage.name(index).to_string();
+            let name = name.as_str();
+            let args = self.redact(
+                RedactTarget::HostFunctionArgs { function: name },
+                &format!("{args:?}"),
+            );
+            trace!(
+                plugin = self.id.to_string(),
+                "calling host function {name} with arguments: {args}"
+            );
+        }
+        if let Some((name, msg)) = self.host_usage.record(index) {
+            warn!(
+                plugin = self.id.to_string(),
+                "call to deprecated host function {name}: {msg}"
+            );
+            if let Some(events) = &self.events {
+                events.emit(PluginEvent::DeprecatedHostFunction {
+                    plugin: self.id,
+                    function: name.to_string(),
+                    message: msg.to_string(),
+                });
+            }
+        }
+        self.host_calls.record(index)
+    }
+
     /// Get a pointer to the plugin memory
     pub(crate) fn memory_ptr(&mut self) -> *mut u8 {
         if let Some(mem) = self.memory() {
@@ -422,10 +715,7 @@ impl CurrentPlugin {
 
     /// Get extism memory
     pub(crate) fn memory(&mut self) -> Option<wasmtime::Memory> {
-        let (linker, mut store) = self.linker_and_store();
-        linker
-            .get(&mut store, EXTISM_ENV_MODULE, "memory")?
-            .into_memory()
+        self.kernel.memory
     }
 
     /// Get a `MemoryHandle` from a `Val` reference - this can be used to convert a host function's
@@ -451,12 +741,10 @@ impl CurrentPlugin {
     /// Clear the current plugin error
     pub fn clear_error(&mut self) {
         trace!(plugin = self.id.to_string(), "CurrentPlugin::clear_error");
-        let (linker, store) = self.linker_and_store();
-        if let Some(f) = linker.get(&mut *store, EXTISM_ENV_MODULE, "error_set") {
-            let res = f
-                .into_func()
-                .unwrap()
-                .call(&mut *store, &[Val::I64(0)], &mut []);
+        let error_set = self.kernel.error_set;
+        let (_, store) = self.linker_and_store();
+        if let Some(f) = error_set {
+            let res = f.call(&mut *store, &[Val::I64(0)], &mut []);
             if let Err(e) = res {
                 error!(
                     plugin = self.id.to_string(),
@@ -485,13 +773,12 @@ impl CurrentPlugin {
         let s = s.as_ref();
         debug!(plugin = self.id.to_string(), "set error: {:?}", s);
         let handle = self.memory_new(s)?;
-        let (linker, store) = self.linker_and_store();
-        if let Some(f) = linker.get(&mut *store, EXTISM_ENV_MODULE, "error_set") {
+        let error_set = self.kernel.error_set;
+        let (_, store) = self.linker_and_store();
+        if let Some(f) = error_set {
             catch_out_of_fuel!(
                 &store,
-                f.into_func()
-                    .unwrap()
-                    .call(&mut *store, &[Val::I64(handle.offset() as i64)], &mut [])
+                f.call(&mut *store, &[Val::I64(handle.offset() as i64)], &mut [])
                     .context("failed to set extism error")
             )?;
             Ok((handle.offset(), s.len() as u64))
@@ -501,13 +788,11 @@ impl CurrentPlugin {
     }
 
     pub(crate) fn get_error_position(&mut self) -> (u64, u64) {
-        let (linker, store) = self.linker_and_store();
+        let error_get = self.kernel.error_get;
+        let (_, store) = self.linker_and_store();
         let output = &mut [Val::I64(0)];
-        if let Some(f) = linker.get(&mut *store, EXTISM_ENV_MODULE, "error_get") {
-            if let Err(e) = catch_out_of_fuel!(
-                &store,
-                f.into_func().unwrap().call(&mut *store, &[], output)
-            ) {
+        if let Some(f) = error_get {
+            if let Err(e) = catch_out_of_fuel!(&store, f.call(&mut *store, &[], output)) {
                 error!(
                     plugin = self.id.to_string(),
                     "unable to call extism:host/env::error_get: {:?}", e
@@ -520,16 +805,72 @@ impl CurrentPlugin {
         (offs, length)
     }
 
+    /// Returns the number of bytes currently allocated by the Extism kernel, including allocations that
+    /// have been freed but not reused yet. This is reset to `0` along with the kernel before each call.
+    pub fn kernel_memory_used(&mut self) -> Result<u64, Error> {
+        Ok(self.kernel_root()?.0)
+    }
+
+    /// Returns the number of bytes that can still be allocated by the Extism kernel before memory runs
+    /// out, taking the memory limit from `Manifest::with_memory_max` into account. The kernel memory
+    /// shares the limit with the plugin's own memory.
+    pub fn kernel_memory_remaining(&mut self) -> Result<u64, Error> {
+        let (position, length) = self.kernel_root()?;
+        let Some(mem) = self.memory() else {
+            anyhow::bail!("unable to locate extism memory");
+        };
+        let (_, store) = self.linker_and_store();
+        let size = mem.data_size(&*store) as u64;
+        let max_pages = mem.ty(&*store).maximum().unwrap_or(65536);
+        let mut growable = (max_pages * 65536).saturating_sub(size);
+        if let Some(limiter) = &self.memory_limiter {
+            if limiter.max_bytes != usize::MAX {
+                growable = growable.min(limiter.bytes_left as u64);
+            }
+        }
+        Ok(length.saturating_sub(position) + growable)
+    }
+
+    // Read the allocator position and length from the kernel's `MemoryRoot`, which is `repr(C)` and stored at
+    // offset 1 in the kernel memory: `initialized` is followed by `position` and `length` at 8 byte offsets
+    fn kernel_root(&mut self) -> Result<(u64, u64), Error> {
+        const POSITION: usize = 1 + 8;
+        const LENGTH: usize = 1 + 16;
+        let Some(mem) = self.memory() else {
+            anyhow::bail!("unable to locate extism memory");
+        };
+        let (_, store) = self.linker_and_store();
+        let data = mem.data(&*store);
+        let read = |offs: usize| {
+            data.get(offs..offs + 8)
+                .map(|x| u64::from_le_bytes(x.try_into().unwrap()))
+                .unwrap_or_default()
+        };
+        Ok((read(POSITION), read(LENGTH)))
+    }
+
     /// Returns the remaining time before a plugin will timeout, or
-    /// `None` if no timeout is configured in the manifest
+    /// `None` if no timeout is configured in the manifest and no deadline was set using
+    /// `Plugin::set_deadline`
     pub fn time_remaining(&self) -> Option<std::time::Duration> {
-        if let Some(x) = &self.manifest.timeout_ms {
+        let timeout = self.manifest.timeout_ms.map(|x| {
             let elapsed = &self.start_time.elapsed().as_millis();
             let ms_left = x.saturating_sub(*elapsed as u64);
-            return Some(std::time::Duration::from_millis(ms_left));
+            std::time::Duration::from_millis(ms_left)
+        });
+        let deadline = self
+            .deadline
+            .map(|x| x.saturating_duration_since(std::time::Instant::now()));
+        match (timeout, deadline) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
         }
+    }
 
-        None
+    /// Limit `timeout` to the time left before the call times out, so host functions that block don't
+    /// keep running after the plugin's timeout or deadline
+    pub(crate) fn clamp_timeout(&self, timeout: std::time::Duration) -> std::time::Duration {
+        self.time_remaining().map_or(timeout, |x| timeout.min(x))
     }
 }
 
@@ -546,3 +887,100 @@ impl Internal for CurrentPlugin {
         unsafe { (&mut *self.linker, &mut *self.store) }
     }
 }
+
+#[cfg(not(target_family = "wasm"))]
+fn new_wasi(
+    manifest: &Manifest,
+    wasi_sources: &WasiSources,
+    io: &std::sync::Arc<resources::IoCounters>,
+) -> Result<Wasi, Error> {
+    let auth = wasi_common::sync::ambient_authority();
+    let random: Box<dyn wasi_common::RngCore + Send + Sync> = match &wasi_sources.random {
+        Some(r) => Box::new(random::Rng(r.clone())),
+        None if wasi_sources.deterministic => {
+            Box::new(random::Rng(std::sync::Arc::new(SeededRandom::new(0))))
+        }
+        None => wasi_common::sync::random_ctx(),
+    };
+    let clock = wasi_sources.clock.clone().or_else(|| {
+        wasi_sources.deterministic.then(|| {
+            std::sync::Arc::new(FixedClock::new(std::time::UNIX_EPOCH))
+                as std::sync::Arc<dyn WasiClock>
+        })
+    });
+    let (clocks, sched): (_, Box<dyn wasi_common::WasiSched>) = match &clock {
+        Some(c) => (
+            clock::wasi_clocks(c),
+            Box::new(clock::ClockSched {
+                clock: c.clone(),
+                inner: wasi_common::sync::sched_ctx(),
+            }),
+        ),
+        None => (
+            wasi_common::sync::clocks_ctx(),
+            wasi_common::sync::sched_ctx(),
+        ),
+    };
+    use wasi_common::pipe::WritePipe;
+
+    let table = wasi_common::Table::new();
+    let ctx = wasi_common::WasiCtx::new(random, clocks, sched, table);
+
+    if let Some(fs) = &wasi_sources.memory_fs {
+        let dir = Box::new(resources::CountingDir::new(Box::new(fs.root()), io.clone()));
+        ctx.push_preopened_dir(dir, "/")?;
+    } else if let Some(a) = &manifest.allowed_paths {
+        for (k, v) in a.iter() {
+            let readonly = k.starts_with("ro:");
+
+            let dir_path = if readonly { &k[3..] } else { k };
+
+            let dir = wasi_common::sync::dir::Dir::from_cap_std(
+                wasi_common::sync::Dir::open_ambient_dir(dir_path, auth)?,
+            );
+
+            let file: Box<dyn wasi_common::dir::WasiDir> = if readonly {
+                Box::new(readonly_dir::ReadOnlyDir::new(dir))
+            } else {
+                Box::new(dir)
+            };
+
+            let file = Box::new(resources::CountingDir::new(file, io.clone()));
+            ctx.push_preopened_dir(file, v)?;
+        }
+    }
+
+    if let Some(stdin) = &wasi_sources.stdin {
+        ctx.set_stdin(stdin.file());
+    }
+
+    // Captured output takes precedence over writers set on the builder, which take precedence over
+    // `EXTISM_ENABLE_WASI_OUTPUT`
+    if let Some(captured) = &wasi_sources.captured {
+        ctx.set_stdout(Box::new(WritePipe::new(captured.stdout())));
+        ctx.set_stderr(Box::new(WritePipe::new(captured.stderr())));
+    } else {
+        // Enable WASI output, typically used for debugging purposes
+        if std::env::var("EXTISM_ENABLE_WASI_OUTPUT").is_ok() {
+            ctx.set_stderr(Box::new(wasi_common::sync::stdio::stderr()));
+            ctx.set_stdout(Box::new(wasi_common::sync::stdio::stdout()));
+        }
+        if let Some(w) = &wasi_sources.stdout {
+            ctx.set_stdout(Box::new(WritePipe::new(w.clone())));
+        }
+        if let Some(w) = &wasi_sources.stderr {
+            ctx.set_stderr(Box::new(WritePipe::new(w.clone())));
+        }
+    }
+
+    Ok(Wasi { ctx })
+}
+
+#[cfg(target_family = "wasm")]
+fn new_wasi(
+    _manifest: &Manifest,
+    _wasi_sources: &WasiSources,
+    _io: &std::sync::Arc<resources::IoCounters>,
+) -> Result<Wasi, Error> {
+    anyhow::bail!("WASI is not supported on wasm32 hosts")
+}
@@ -0,0 +1,205 @@
+use crate::*;
+
+/// The error returned by a call that ran for longer than the manifest's `timeout_ms` or the deadline set
+/// using `Plugin::set_deadline`, it can be detected using `err.is::<Timeout>()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeout;
+
+impl std::fmt::Display for Timeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("timeout")
+    }
+}
+
+impl std::error::Error for Timeout {}
+
+/// The error returned by a call that used all of its fuel, see `PluginBuilder::with_fuel_limit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfFuel;
+
+impl std::fmt::Display for OutOfFuel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("plugin ran out of fuel")
+    }
+}
+
+impl std::error::Error for OutOfFuel {}
+
+/// The error returned when calling a function that isn't exported by the plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionNotFound(pub String);
+
+impl std::fmt::Display for FunctionNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Function not found: {}", self.0)
+    }
+}
+
+impl std::error::Error for FunctionNotFound {}
+
+/// Wraps an error returned by a host function, it's displayed the same as the original error, which is
+/// available using `source` or `Error::root_cause`
+#[derive(Debug)]
+pub struct HostFunctionError {
+    function: String,
+    error: Error,
+}
+
+impl HostFunctionError {
+    pub(crate) fn new(function: &str, error: Error) -> Self {
+        HostFunctionError {
+            function: function.to_string(),
+            error,
+        }
+    }
+
+    /// The name of the host function that failed
+    pub fn function(&self) -> &str {
+        &self.function
+    }
+}
+
+impl std::fmt::Display for HostFunctionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl std::error::Error for HostFunctionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
+
+/// The error message set by a plugin using the PDK's `error_set`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct GuestError(pub(crate) String);
+
+impl std::fmt::Display for GuestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for GuestError {}
+
+/// The class of an `Error`, so failures can be handled without matching on error messages. Errors are
+/// still `anyhow::Error`s, the kind is found by looking through the error's chain.
+///
+/// ```no_run
+/// # use extism::*;
+/// # fn retry_later() {}
+/// # fn handle(_: Result<&str, Error>) {}
+/// # fn example(plugin: &mut Plugin, input: &str) {
+/// match plugin.call::<&str, &str>("run", input) {
+///     Err(e) if ErrorKind::of(&e) == ErrorKind::Timeout => retry_later(),
+///     res => handle(res),
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Any error that doesn't fit into one of the other kinds
+    Other,
+
+    /// The call exceeded its timeout or deadline, see `Timeout`
+    Timeout,
+
+    /// The call was stopped using `CancelHandle::cancel`, see `Cancelled`
+    Cancelled,
+
+    /// The plugin tried to grow memory past its limit, see `OutOfMemory`
+    OutOfMemory,
+
+    /// The call used all of its fuel, see `OutOfFuel`
+    OutOfFuel,
+
+    /// The plugin trapped, for example by executing `unreachable`
+    Trap,
+
+    /// A WASI command exited with a non-zero exit code
+    Exit,
+
+    /// A host function returned an error, see `HostFunctionError`
+    HostFunction,
+
+    /// The plugin returned an error message
+    Guest,
+
+    /// The input or output couldn't be encoded or decoded
+    Encoding,
+
+    /// The function isn't exported by the plugin, see `FunctionNotFound`
+    FunctionNotFound,
+
+    /// A module's signature couldn't be verified, see `InvalidSignature`
+    InvalidSignature,
+}
+
+impl ErrorKind {
+    /// Find the kind of `err`
+    pub fn of(err: &Error) -> Self {
+        let has = |f: fn(&(dyn std::error::Error + 'static)) -> bool| err.chain().any(f);
+        if has(|e| e.is::<Cancelled>()) {
+            ErrorKind::Cancelled
+        } else if has(|e| e.is::<Timeout>()) {
+            ErrorKind::Timeout
+        } else if has(|e| e.is::<OutOfMemory>()) {
+            ErrorKind::OutOfMemory
+        } else if has(|e| e.is::<OutOfFuel>()) {
+            ErrorKind::OutOfFuel
+        } else if has(|e| e.is::<HostFunctionError>()) {
+            ErrorKind::HostFunction
+        } else if has(|e| e.is::<GuestError>()) {
+            ErrorKind::Guest
+        } else if has(|e| e.is::<wasmtime::Trap>()) {
+            ErrorKind::Trap
+        } else if has(is_exit) {
+            ErrorKind::Exit
+        } else if has(|e| e.is::<FunctionNotFound>()) {
+            ErrorKind::FunctionNotFound
+        } else if has(|e| e.is::<InvalidSignature>()) {
+            ErrorKind::InvalidSignature
+        } else if has(|e| {
+            e.is::<serde_json::Error>()
+                || e.is::<std::str::Utf8Error>()
+                || e.is::<std::string::FromUtf8Error>()
+                || e.is::<std::array::TryFromSliceError>()
+        }) {
+            ErrorKind::Encoding
+        } else {
+            ErrorKind::Other
+        }
+    }
+
+    /// A stable code for the kind, used by `extism_plugin_error_code` in the C API. `0` is used for
+    /// `ErrorKind::Other`.
+    pub fn code(self) -> i32 {
+        match self {
+            ErrorKind::Other => 0,
+            ErrorKind::Timeout => 1,
+            ErrorKind::Cancelled => 2,
+            ErrorKind::OutOfMemory => 3,
+            ErrorKind::OutOfFuel => 4,
+            ErrorKind::Trap => 5,
+            ErrorKind::Exit => 6,
+            ErrorKind::HostFunction => 7,
+            ErrorKind::Guest => 8,
+            ErrorKind::Encoding => 9,
+            ErrorKind::FunctionNotFound => 10,
+            ErrorKind::InvalidSignature => 11,
+        }
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn is_exit(e: &(dyn std::error::Error + 'static)) -> bool {
+    e.downcast_ref::<wasi_common::I32Exit>()
+        .is_some_and(|x| x.0 != 0)
+}
+
+#[cfg(target_family = "wasm")]
+fn is_exit(_e: &(dyn std::error::Error + 'static)) -> bool {
+    false
+}
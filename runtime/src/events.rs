@@ -0,0 +1,119 @@
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+/// Lifecycle events emitted by plugins that have been configured with an `EventBus`
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum PluginEvent {
+    /// A new plugin instance was created
+    Created { plugin: uuid::Uuid },
+
+    /// A call into an exported function has started
+    CallStarted {
+        plugin: uuid::Uuid,
+        function: String,
+    },
+
+    /// A call into an exported function has returned, `success` is `false` if the call returned an error
+    CallFinished {
+        plugin: uuid::Uuid,
+        function: String,
+        duration: Duration,
+        success: bool,
+    },
+
+    /// A call was aborted by a Wasm trap
+    Trap {
+        plugin: uuid::Uuid,
+        function: String,
+        message: String,
+    },
+
+    /// A call was interrupted because it exceeded its timeout
+    Timeout {
+        plugin: uuid::Uuid,
+        function: String,
+    },
+
+    /// A call was interrupted using a `CancelHandle`
+    Cancelled {
+        plugin: uuid::Uuid,
+        function: String,
+    },
+
+    /// A call has been running for longer than the stall threshold, the call continues running
+    Stalled {
+        plugin: uuid::Uuid,
+        function: String,
+        elapsed: Duration,
+        fuel_consumed: Option<u64>,
+        backtrace: String,
+    },
+
+    /// A plugin's linear memory has grown, sizes are in bytes
+    MemoryGrown {
+        plugin: uuid::Uuid,
+        from: usize,
+        to: usize,
+    },
+
+    /// A plugin called a deprecated host function, this is only emitted for the first call to each
+    /// deprecated function
+    DeprecatedHostFunction {
+        plugin: uuid::Uuid,
+        function: String,
+        message: String,
+    },
+
+    /// A plugin was dropped
+    Dropped { plugin: uuid::Uuid },
+}
+
+/// `EventBus` distributes `PluginEvent`s to any number of subscribers. It can be cloned and shared
+/// between many plugins (for example, all the plugins in a `Pool`).
+#[derive(Default, Clone)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<mpsc::Sender<PluginEvent>>>>,
+}
+
+impl std::fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventBus")
+            .field("subscribers", &self.subscriber_count())
+            .finish()
+    }
+}
+
+impl EventBus {
+    /// Create a new `EventBus` with no subscribers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribe to events, all events emitted after this call will be sent to the returned receiver.
+    /// Dropping the receiver unsubscribes.
+    pub fn subscribe(&self) -> mpsc::Receiver<PluginEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.lock().push(tx);
+        rx
+    }
+
+    /// Get the number of active subscribers
+    pub fn subscriber_count(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Send an event to all subscribers
+    pub fn emit(&self, event: PluginEvent) {
+        // Subscribers whose receiver has been dropped are removed
+        self.lock().retain(|tx| tx.send(event.clone()).is_ok());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<mpsc::Sender<PluginEvent>>> {
+        match self.subscribers.lock() {
+            Ok(x) => x,
+            Err(e) => e.into_inner(),
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 use wasmtime::Caller;
 
-use crate::{error, trace, CurrentPlugin, Error};
+use crate::{error, trace, CurrentPlugin, Error, FromBytesOwned, ToBytes};
 
 /// An enumeration of all possible value types in WebAssembly.
 /// cbindgen:prefix-with-name
@@ -179,6 +179,9 @@ pub struct Function {
     /// Module name
     pub(crate) namespace: Option<String>,
 
+    /// The capability a manifest has to grant before the function is linked
+    pub(crate) capability: Option<String>,
+
     pub(crate) params: Vec<ValType>,
     pub(crate) results: Vec<ValType>,
 
@@ -187,6 +190,9 @@ pub struct Function {
 
     /// UserData
     pub(crate) _user_data: UserDataHandle,
+
+    /// Deprecation message
+    pub(crate) deprecated: Option<String>,
 }
 
 impl Function {
@@ -220,13 +226,48 @@ impl Function {
                 },
             ),
             namespace: None,
+            capability: None,
             _user_data: match &user_data {
                 UserData::C(ptr) => UserDataHandle::C(ptr.clone()),
                 UserData::Rust(x) => UserDataHandle::Rust(x.clone()),
             },
+            deprecated: None,
         }
     }
 
+    /// Create a host function that takes one value and returns one value, the input is decoded using
+    /// `FromBytesOwned` and the output is encoded using `ToBytes` so `f` doesn't have to deal with `Val`s
+    /// or memory handles
+    ///
+    /// ```
+    /// # use extism::{convert::Json, Error, Function};
+    /// # #[derive(serde::Deserialize)]
+    /// # struct Request { id: u64 }
+    /// # #[derive(serde::Serialize)]
+    /// # struct User { id: u64 }
+    /// # fn lookup(req: Request) -> Result<User, Error> { Ok(User { id: req.id }) }
+    /// let f = Function::typed("lookup", |Json(req): Json<Request>| Ok(Json(lookup(req)?)));
+    /// ```
+    pub fn typed<I, O, F>(name: impl Into<String>, f: F) -> Function
+    where
+        I: FromBytesOwned + 'static,
+        O: ToBytes<'static> + 'static,
+        F: 'static + Fn(I) -> Result<O, Error> + Sync + Send,
+    {
+        Function::new(
+            name,
+            [PTR],
+            [PTR],
+            UserData::new(()),
+            move |plugin: &mut CurrentPlugin, inputs: &[Val], outputs: &mut [Val], _| {
+                let input: I = plugin.memory_get_val(&inputs[0])?;
+                let handle = plugin.memory_new(f(input)?)?;
+                outputs[0] = plugin.memory_to_val(handle);
+                Ok(())
+            },
+        )
+    }
+
     pub(crate) fn ty(&self, engine: &wasmtime::Engine) -> wasmtime::FuncType {
         wasmtime::FuncType::new(
             engine,
@@ -266,6 +307,39 @@ impl Function {
         self
     }
 
+    /// Only link the host function for plugins whose manifest grants `capability`, see
+    /// `Manifest::capabilities`. Plugins that import it without the capability fail to instantiate.
+    pub fn set_capability(&mut self, capability: impl Into<String>) {
+        self.capability = Some(capability.into());
+    }
+
+    /// Require a capability to use the host function
+    pub fn with_capability(mut self, capability: impl Into<String>) -> Self {
+        self.set_capability(capability);
+        self
+    }
+
+    /// Get the capability required to use the host function
+    pub fn capability(&self) -> Option<&str> {
+        self.capability.as_deref()
+    }
+
+    /// Mark the host function as deprecated, a warning is logged the first time each plugin calls it
+    pub fn set_deprecated(&mut self, message: impl Into<String>) {
+        self.deprecated = Some(message.into());
+    }
+
+    /// Mark the host function as deprecated
+    pub fn with_deprecated(mut self, message: impl Into<String>) -> Self {
+        self.set_deprecated(message);
+        self
+    }
+
+    /// Get the deprecation message, if the host function has been deprecated
+    pub fn deprecated(&self) -> Option<&str> {
+        self.deprecated.as_deref()
+    }
+
     /// Get param types
     pub fn params(&self) -> &[ValType] {
         &self.params
@@ -0,0 +1,143 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::*;
+
+/// `PluginGroup` calls the same function on several plugins with the same input, for example to deliver
+/// an event to every plugin that subscribes to it. Plugins are identified by a key and results are
+/// returned in the order the plugins were added.
+///
+/// ```no_run
+/// # use extism::{convert::Json, *};
+/// # fn example(audit_plugin: Plugin, notify_plugin: Plugin, event: serde_json::Value) {
+/// let mut group = PluginGroup::new().with_max_parallelism(4);
+/// group.insert("audit", audit_plugin);
+/// group.insert("notify", notify_plugin);
+/// for (key, res) in group.broadcast::<_, String>("on_event", Json(&event)) {
+///     println!("{key}: {res:?}");
+/// }
+/// # }
+/// ```
+pub struct PluginGroup {
+    plugins: Vec<(String, Mutex<Plugin>)>,
+    max_parallelism: usize,
+}
+
+impl Default for PluginGroup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PluginGroup {
+    /// Create an empty group, calls run on up to one thread per CPU
+    pub fn new() -> Self {
+        PluginGroup {
+            plugins: vec![],
+            max_parallelism: std::thread::available_parallelism()
+                .map(|x| x.get())
+                .unwrap_or(1),
+        }
+    }
+
+    /// Set the max number of plugins that are called at the same time, `1` calls plugins one after
+    /// another on the calling thread
+    pub fn with_max_parallelism(mut self, n: usize) -> Self {
+        self.max_parallelism = n.max(1);
+        self
+    }
+
+    /// Add `plugin` to the group, a plugin that was already added with the same key is replaced and
+    /// returned
+    pub fn insert(&mut self, key: impl Into<String>, plugin: Plugin) -> Option<Plugin> {
+        let key = key.into();
+        match self.plugins.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, p)) => Some(std::mem::replace(p.get_mut().unwrap(), plugin)),
+            None => {
+                self.plugins.push((key, Mutex::new(plugin)));
+                None
+            }
+        }
+    }
+
+    /// Remove the plugin with the given key from the group
+    pub fn remove(&mut self, key: impl AsRef<str>) -> Option<Plugin> {
+        let index = self.plugins.iter().position(|(k, _)| k == key.as_ref())?;
+        let (_, plugin) = self.plugins.remove(index);
+        Some(plugin.into_inner().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// The keys of all plugins in the group
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.plugins.iter().map(|(k, _)| k.as_str())
+    }
+
+    /// The number of plugins in the group
+    pub fn len(&self) -> usize {
+        self.plugins.len()
+    }
+
+    /// Returns `true` if the group doesn't contain any plugins
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    /// Call `name` on every plugin in the group, using at most `max_parallelism` threads. The input is
+    /// encoded once and a result is returned for each plugin, including plugins that don't export `name`.
+    pub fn broadcast<'a, I: ToBytes<'a>, O: FromBytesOwned + Send>(
+        &self,
+        name: impl AsRef<str>,
+        input: I,
+    ) -> Vec<(String, Result<O, Error>)> {
+        let name = name.as_ref();
+        let input = match input.to_bytes() {
+            Ok(x) => x,
+            Err(e) => {
+                let msg = format!("unable to encode input: {e:?}");
+                return self
+                    .plugins
+                    .iter()
+                    .map(|(k, _)| (k.clone(), Err(Error::msg(msg.clone()))))
+                    .collect();
+            }
+        };
+        let input = input.as_ref();
+        let call = |plugin: &Mutex<Plugin>| -> Result<O, Error> {
+            let mut plugin = plugin
+                .lock()
+                .map_err(|_| Error::msg("plugin lock was poisoned"))?;
+            plugin.call(name, input)
+        };
+
+        let threads = self.max_parallelism.min(self.plugins.len());
+        if threads <= 1 {
+            return self
+                .plugins
+                .iter()
+                .map(|(k, p)| (k.clone(), call(p)))
+                .collect();
+        }
+
+        // Each thread takes the next plugin that hasn't been called yet
+        let next = AtomicUsize::new(0);
+        let results: Vec<Mutex<Option<Result<O, Error>>>> =
+            self.plugins.iter().map(|_| Mutex::new(None)).collect();
+        std::thread::scope(|s| {
+            for _ in 0..threads {
+                s.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let Some((_, plugin)) = self.plugins.get(index) else {
+                        break;
+                    };
+                    *results[index].lock().unwrap() = Some(call(plugin));
+                });
+            }
+        });
+        // Panics are propagated by `scope`, so every plugin has a result
+        self.plugins
+            .iter()
+            .zip(results)
+            .map(|((k, _), res)| (k.clone(), res.into_inner().unwrap().unwrap()))
+            .collect()
+    }
+}
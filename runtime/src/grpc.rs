@@ -0,0 +1,279 @@
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::{Buf, BufMut, Bytes};
+use tonic::codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder};
+use tonic::server::UnaryService;
+use tonic::service::Routes;
+use tonic::{Code, Status};
+
+use crate::*;
+
+type Validate = fn(&[u8]) -> Result<(), Error>;
+
+fn validate<T: prost::Message + Default>(data: &[u8]) -> Result<(), Error> {
+    extism_convert::Prost::<T>::from_bytes_owned(data).map(|_| ())
+}
+
+struct Method {
+    service: String,
+    method: String,
+    function: String,
+    types: Option<(Validate, Validate)>,
+}
+
+/// `GrpcServer` maps unary gRPC methods to plugin functions, each request is handled by a plugin from a
+/// `Pool`
+///
+/// The encoded request message is passed to the function as input and the output is returned as the
+/// response message. When a function fails the error message is returned with the `INTERNAL` status,
+/// unless the function returned an exit code between `1` and `16` in which case it is used as the gRPC
+/// status code. `UNAVAILABLE` is returned if no plugin instance is available before the pool timeout.
+///
+/// The `grpc.health.v1.Health` service is always included and reports each configured service as
+/// serving, the reflection service is included when a file descriptor set is registered.
+///
+/// ```no_run
+/// # async fn example(pool: extism::Pool) -> Result<(), extism::Error> {
+/// extism::GrpcServer::new(pool)
+///     .method("example.Greeter", "SayHello", "say_hello")
+///     .serve("127.0.0.1:50051".parse()?)
+///     .await
+/// # }
+/// ```
+pub struct GrpcServer {
+    pool: Pool,
+    methods: Vec<Method>,
+    file_descriptor_sets: Vec<Vec<u8>>,
+    pool_timeout: Duration,
+}
+
+impl GrpcServer {
+    /// Create a new `GrpcServer` with no methods
+    pub fn new(pool: Pool) -> Self {
+        GrpcServer {
+            pool,
+            methods: vec![],
+            file_descriptor_sets: vec![],
+            pool_timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Call `function` for the `method` of the fully-qualified `service`, messages are passed through
+    /// without being decoded
+    pub fn method(
+        mut self,
+        service: impl Into<String>,
+        method: impl Into<String>,
+        function: impl Into<String>,
+    ) -> Self {
+        self.methods.push(Method {
+            service: service.into(),
+            method: method.into(),
+            function: function.into(),
+            types: None,
+        });
+        self
+    }
+
+    /// Like `GrpcServer::method`, but requests are checked to be valid `Req` messages before calling the
+    /// plugin (returning `INVALID_ARGUMENT` otherwise) and the output is checked to be a valid `Res` message
+    /// (returning `INTERNAL` otherwise), using the `Prost` encoding
+    pub fn typed_method<Req, Res>(
+        mut self,
+        service: impl Into<String>,
+        method: impl Into<String>,
+        function: impl Into<String>,
+    ) -> Self
+    where
+        Req: prost::Message + Default,
+        Res: prost::Message + Default,
+    {
+        self.methods.push(Method {
+            service: service.into(),
+            method: method.into(),
+            function: function.into(),
+            types: Some((validate::<Req>, validate::<Res>)),
+        });
+        self
+    }
+
+    /// Register an encoded `FileDescriptorSet` describing the services, this enables the gRPC reflection
+    /// service
+    pub fn with_file_descriptor_set(mut self, encoded: impl Into<Vec<u8>>) -> Self {
+        self.file_descriptor_sets.push(encoded.into());
+        self
+    }
+
+    /// Set how long a request waits for an instance from the `Pool`, the default is 10 seconds
+    pub fn with_pool_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_timeout = timeout;
+        self
+    }
+
+    /// Convert into `tonic::service::Routes`, this can be used to add other services or serve using a
+    /// custom `tonic::transport::Server`
+    pub async fn into_routes(self) -> Result<Routes, Error> {
+        let (reporter, health) = tonic_health::server::health_reporter();
+        let mut router = axum::Router::new();
+        let mut paths = BTreeSet::new();
+        for m in self.methods {
+            let path = format!("/{}/{}", m.service, m.method);
+            if !paths.insert(path.clone()) {
+                anyhow::bail!("duplicate gRPC method: {path}");
+            }
+
+            reporter
+                .set_service_status(&m.service, tonic_health::ServingStatus::Serving)
+                .await;
+            let handler = Handler {
+                pool: self.pool.clone(),
+                function: m.function.into(),
+                timeout: self.pool_timeout,
+                types: m.types,
+            };
+            router = router.route(
+                &path,
+                axum::routing::post(move |req: axum::extract::Request| async move {
+                    tonic::server::Grpc::new(BytesCodec)
+                        .unary(handler, req)
+                        .await
+                }),
+            );
+        }
+
+        // Keep the default fallback, which returns `UNIMPLEMENTED` for unknown methods
+        let mut routes = Routes::default();
+        let fallback = std::mem::take(routes.axum_router_mut());
+        *routes.axum_router_mut() = router.merge(fallback);
+        let mut routes = routes.add_service(health);
+
+        if !self.file_descriptor_sets.is_empty() {
+            let mut v1 = tonic_reflection::server::Builder::configure();
+            let mut v1alpha = tonic_reflection::server::Builder::configure();
+            for fds in &self.file_descriptor_sets {
+                v1 = v1.register_encoded_file_descriptor_set(fds);
+                v1alpha = v1alpha.register_encoded_file_descriptor_set(fds);
+            }
+            routes = routes
+                .add_service(v1.build_v1()?)
+                .add_service(v1alpha.build_v1alpha()?);
+        }
+        Ok(routes)
+    }
+
+    /// Listen on `addr` and handle requests until an error occurs
+    pub async fn serve(self, addr: std::net::SocketAddr) -> Result<(), Error> {
+        let routes = self.into_routes().await?;
+        tonic::transport::Server::builder()
+            .add_routes(routes)
+            .serve(addr)
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct Handler {
+    pool: Pool,
+    function: Arc<str>,
+    timeout: Duration,
+    types: Option<(Validate, Validate)>,
+}
+
+impl Handler {
+    async fn call(self, input: Bytes) -> Result<Bytes, Status> {
+        let Handler {
+            pool,
+            function,
+            timeout,
+            types,
+        } = self;
+        if let Some((request, _)) = types {
+            request(&input).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        }
+
+        // Plugin calls block, so they're run outside of the async runtime
+        let res = tokio::task::spawn_blocking(move || match pool.get(timeout) {
+            Ok(Some(mut plugin)) => {
+                Some(plugin.call_get_error_code::<&[u8], Vec<u8>>(&function, &input))
+            }
+            Ok(None) => None,
+            Err(e) => Some(Err((e, -1))),
+        })
+        .await;
+
+        match res {
+            Ok(Some(Ok(output))) => {
+                if let Some((_, response)) = types {
+                    response(&output)
+                        .map_err(|e| Status::internal(format!("invalid response message: {e}")))?;
+                }
+                Ok(output.into())
+            }
+            Ok(Some(Err((e, code)))) => {
+                let code = match code {
+                    1..=16 => Code::from_i32(code),
+                    _ => Code::Internal,
+                };
+                Err(Status::new(code, e.to_string()))
+            }
+            Ok(None) => Err(Status::unavailable("no plugin instance available")),
+            Err(e) => Err(Status::internal(e.to_string())),
+        }
+    }
+}
+
+impl UnaryService<Bytes> for Handler {
+    type Response = Bytes;
+    type Future = Pin<Box<dyn Future<Output = Result<tonic::Response<Bytes>, Status>> + Send>>;
+
+    fn call(&mut self, request: tonic::Request<Bytes>) -> Self::Future {
+        let handler = self.clone();
+        Box::pin(async move {
+            let output = handler.call(request.into_inner()).await?;
+            Ok(tonic::Response::new(output))
+        })
+    }
+}
+
+/// Passes the encoded messages through unchanged
+#[derive(Clone, Copy)]
+struct BytesCodec;
+
+impl Codec for BytesCodec {
+    type Encode = Bytes;
+    type Decode = Bytes;
+    type Encoder = BytesCodec;
+    type Decoder = BytesCodec;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        *self
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        *self
+    }
+}
+
+impl Encoder for BytesCodec {
+    type Item = Bytes;
+    type Error = Status;
+
+    fn encode(&mut self, item: Bytes, dst: &mut EncodeBuf<'_>) -> Result<(), Status> {
+        dst.put_slice(&item);
+        Ok(())
+    }
+}
+
+impl Decoder for BytesCodec {
+    type Item = Bytes;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Bytes>, Status> {
+        Ok(Some(src.copy_to_bytes(src.remaining())))
+    }
+}
@@ -0,0 +1,392 @@
+use crate::*;
+
+/// `Hardening` enables additional operating system restrictions while a plugin is executing, as
+/// defense-in-depth in case a plugin is able to escape the Wasm sandbox.
+///
+/// When enabled, each call runs on a short-lived thread which has the following restrictions applied
+/// before any Wasm code is executed:
+///
+/// - `filesystem`: Landlock is used to block access to any files outside of the manifest's `allowed_paths`,
+///   paths prefixed with `ro:` are read-only. Files used by the `KvStore` and `SqlDatabase`, see
+///   `KvStore::local_paths`, stay writable. If the manifest has `allowed_hosts` or `allowed_sockets` then
+///   the files needed to resolve host names and verify certificates, like `/etc/resolv.conf`,
+///   `/etc/hosts`, the CA certificates and the system libraries, can also be read
+/// - `network`: seccomp is used to block socket creation, if the manifest has `allowed_hosts` or
+///   `allowed_sockets` then IPv4 and IPv6 sockets are still permitted so the host can make connections
+///
+/// These restrictions also apply to host functions, since they execute on the same thread as the plugin.
+/// Hardening is currently only available on Linux (x86_64 and aarch64 for network restrictions).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hardening {
+    /// Restrict filesystem access to the manifest's `allowed_paths`
+    pub filesystem: bool,
+
+    /// Restrict socket creation
+    pub network: bool,
+
+    /// When `true`, restrictions that aren't supported by the current system are skipped instead of
+    /// causing the call to fail
+    pub best_effort: bool,
+}
+
+impl Default for Hardening {
+    fn default() -> Self {
+        Hardening {
+            filesystem: true,
+            network: true,
+            best_effort: false,
+        }
+    }
+}
+
+impl Hardening {
+    /// Create a new `Hardening` with filesystem and network restrictions enabled
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Enable or disable filesystem restrictions
+    pub fn with_filesystem(mut self, filesystem: bool) -> Self {
+        self.filesystem = filesystem;
+        self
+    }
+
+    /// Enable or disable network restrictions
+    pub fn with_network(mut self, network: bool) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Skip restrictions that aren't supported by the current system instead of returning an error
+    pub fn with_best_effort(mut self, best_effort: bool) -> Self {
+        self.best_effort = best_effort;
+        self
+    }
+
+    /// Apply the restrictions to the current thread, this cannot be undone. `paths` are files and
+    /// directories the host needs to read and write while the plugin is running
+    pub(crate) fn apply(
+        &self,
+        manifest: &Manifest,
+        paths: &[std::path::PathBuf],
+    ) -> Result<(), Error> {
+        let non_empty = |x: &Option<Vec<String>>| x.as_ref().is_some_and(|x| !x.is_empty());
+        let allow_http = non_empty(&manifest.allowed_hosts) || non_empty(&manifest.allowed_sockets);
+
+        if self.filesystem {
+            self.handle(
+                sys::restrict_filesystem(manifest, paths, allow_http),
+                "filesystem",
+            )?;
+        }
+
+        if self.network {
+            self.handle(sys::restrict_network(allow_http), "network")?;
+        }
+
+        Ok(())
+    }
+
+    fn handle(&self, res: Result<bool, Error>, kind: &str) -> Result<(), Error> {
+        match res {
+            Ok(true) => Ok(()),
+            Ok(false) if self.best_effort => {
+                warn!("{kind} hardening is not supported on this system, skipping");
+                Ok(())
+            }
+            Ok(false) => anyhow::bail!("{kind} hardening is not supported on this system"),
+            Err(e) => Err(e.context(format!("unable to apply {kind} hardening"))),
+        }
+    }
+}
+
+// The `restrict_*` functions return `Ok(false)` when the restriction isn't supported
+#[cfg(target_os = "linux")]
+mod sys {
+    use crate::*;
+
+    const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1;
+    const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+
+    const LANDLOCK_ACCESS_FS_EXECUTE: u64 = 1 << 0;
+    const LANDLOCK_ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const LANDLOCK_ACCESS_FS_READ_FILE: u64 = 1 << 2;
+    const LANDLOCK_ACCESS_FS_READ_DIR: u64 = 1 << 3;
+
+    // All filesystem access rights available in the first version of the Landlock ABI
+    const LANDLOCK_ACCESS_FS_ALL: u64 = (1 << 13) - 1;
+    const LANDLOCK_ACCESS_FS_READ: u64 =
+        LANDLOCK_ACCESS_FS_EXECUTE | LANDLOCK_ACCESS_FS_READ_FILE | LANDLOCK_ACCESS_FS_READ_DIR;
+
+    // Rules for files can only contain the rights that apply to files
+    const LANDLOCK_ACCESS_FS_FILE: u64 =
+        LANDLOCK_ACCESS_FS_EXECUTE | LANDLOCK_ACCESS_FS_WRITE_FILE | LANDLOCK_ACCESS_FS_READ_FILE;
+
+    // Read by the resolver, through NSS modules loaded from the system libraries, and by TLS clients
+    // that use the system CA certificates. Paths that don't exist are skipped
+    const NETWORK_PATHS: &[&str] = &[
+        "/etc/resolv.conf",
+        "/etc/hosts",
+        "/etc/nsswitch.conf",
+        "/etc/host.conf",
+        "/etc/gai.conf",
+        "/etc/services",
+        "/etc/ssl",
+        "/etc/pki",
+        "/etc/ca-certificates",
+        "/usr/share/ca-certificates",
+        "/lib",
+        "/lib64",
+        "/usr/lib",
+        "/usr/lib64",
+    ];
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    struct Fd(libc::c_int);
+
+    impl Drop for Fd {
+        fn drop(&mut self) {
+            unsafe {
+                libc::close(self.0);
+            }
+        }
+    }
+
+    fn no_new_privs() -> Result<(), Error> {
+        if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    // Allow `access` beneath `path`, if `required` isn't set a missing path is skipped
+    fn add_rule(
+        ruleset: &Fd,
+        path: impl AsRef<std::path::Path>,
+        access: u64,
+        required: bool,
+    ) -> Result<(), Error> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = path.as_ref();
+        let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+        if fd < 0 {
+            let err = std::io::Error::last_os_error();
+            if !required && err.kind() == std::io::ErrorKind::NotFound {
+                return Ok(());
+            }
+            return Err(Error::from(err).context(format!("unable to open {}", path.display())));
+        }
+        let fd = Fd(fd);
+
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        if unsafe { libc::fstat(fd.0, &mut stat) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let access = if stat.st_mode & libc::S_IFMT == libc::S_IFDIR {
+            access
+        } else {
+            access & LANDLOCK_ACCESS_FS_FILE
+        };
+
+        let rule = PathBeneathAttr {
+            allowed_access: access,
+            parent_fd: fd.0,
+        };
+        let rc = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset.0,
+                LANDLOCK_RULE_PATH_BENEATH,
+                &rule as *const PathBeneathAttr,
+                0,
+            )
+        };
+        if rc != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    pub(super) fn restrict_filesystem(
+        manifest: &Manifest,
+        paths: &[std::path::PathBuf],
+        allow_http: bool,
+    ) -> Result<bool, Error> {
+        let abi = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<RulesetAttr>(),
+                0,
+                LANDLOCK_CREATE_RULESET_VERSION,
+            )
+        };
+        if abi < 1 {
+            return Ok(false);
+        }
+
+        let attr = RulesetAttr {
+            handled_access_fs: LANDLOCK_ACCESS_FS_ALL,
+        };
+        let ruleset = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0,
+            )
+        };
+        if ruleset < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let ruleset = Fd(ruleset as libc::c_int);
+
+        if let Some(allowed) = &manifest.allowed_paths {
+            for k in allowed.keys() {
+                match k.strip_prefix("ro:") {
+                    Some(path) => add_rule(&ruleset, path, LANDLOCK_ACCESS_FS_READ, true)?,
+                    None => add_rule(&ruleset, k, LANDLOCK_ACCESS_FS_ALL, true)?,
+                }
+            }
+        }
+
+        for path in paths {
+            add_rule(&ruleset, path, LANDLOCK_ACCESS_FS_ALL, true)?;
+        }
+
+        if allow_http {
+            let cert_paths = ["SSL_CERT_FILE", "SSL_CERT_DIR"]
+                .into_iter()
+                .filter_map(std::env::var_os);
+            let network_paths = NETWORK_PATHS.iter().map(std::ffi::OsString::from);
+            for path in network_paths.chain(cert_paths) {
+                add_rule(&ruleset, path, LANDLOCK_ACCESS_FS_READ, false)?;
+            }
+        }
+
+        no_new_privs()?;
+        if unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset.0, 0) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        Ok(true)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xc000003e);
+
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xc00000b7);
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const AUDIT_ARCH: Option<u32> = None;
+
+    // Offsets into `struct seccomp_data`
+    const SECCOMP_DATA_NR: u32 = 0;
+    const SECCOMP_DATA_ARCH: u32 = 4;
+    #[cfg(target_endian = "little")]
+    const SECCOMP_DATA_ARG0: u32 = 16;
+    #[cfg(target_endian = "big")]
+    const SECCOMP_DATA_ARG0: u32 = 20;
+
+    fn stmt(code: u32, k: u32) -> libc::sock_filter {
+        libc::sock_filter {
+            code: code as u16,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+
+    fn jeq(k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+        libc::sock_filter {
+            code: (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16,
+            jt,
+            jf,
+            k,
+        }
+    }
+
+    pub(super) fn restrict_network(allow_http: bool) -> Result<bool, Error> {
+        let Some(arch) = AUDIT_ARCH else {
+            return Ok(false);
+        };
+
+        let load = libc::BPF_LD | libc::BPF_W | libc::BPF_ABS;
+        let ret = libc::BPF_RET | libc::BPF_K;
+        let deny = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+        let allow = libc::SECCOMP_RET_ALLOW;
+
+        let mut filter = vec![
+            // Deny all syscalls from other architectures
+            stmt(load, SECCOMP_DATA_ARCH),
+            jeq(arch, 1, 0),
+            stmt(ret, deny),
+            stmt(load, SECCOMP_DATA_NR),
+            // io_uring can be used to bypass seccomp
+            jeq(libc::SYS_io_uring_setup as u32, 0, 1),
+            stmt(ret, deny),
+            jeq(libc::SYS_socket as u32, 1, 0),
+            stmt(ret, allow),
+        ];
+
+        if allow_http {
+            filter.extend([
+                stmt(load, SECCOMP_DATA_ARG0),
+                jeq(libc::AF_INET as u32, 2, 0),
+                jeq(libc::AF_INET6 as u32, 1, 0),
+                stmt(ret, deny),
+                stmt(ret, allow),
+            ]);
+        } else {
+            filter.push(stmt(ret, deny));
+        }
+
+        let prog = libc::sock_fprog {
+            len: filter.len() as libc::c_ushort,
+            filter: filter.as_mut_ptr(),
+        };
+
+        no_new_privs()?;
+        let rc = unsafe {
+            libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &prog as *const libc::sock_fprog,
+            )
+        };
+        if rc != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use crate::*;
+
+    pub(super) fn restrict_filesystem(
+        _manifest: &Manifest,
+        _paths: &[std::path::PathBuf],
+        _allow_http: bool,
+    ) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    pub(super) fn restrict_network(_allow_http: bool) -> Result<bool, Error> {
+        Ok(false)
+    }
+}
@@ -0,0 +1,65 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::Error;
+
+/// Callbacks for plugin lifecycle events, configured using `PluginBuilder::with_hooks`. Every method has
+/// an empty default implementation, so only the events that are needed have to be implemented. Hooks are
+/// called on the thread making the call, so they should return quickly.
+///
+/// A single implementation can be shared between plugins using an `Arc`.
+pub trait PluginHooks: Send + Sync + 'static {
+    /// A new instance of the plugin was created, instances are created lazily before the first call.
+    /// `reinstantiated` is `true` when the instance replaces one that was discarded, for example after a
+    /// call to a WASI command's `_start` or a failed call to a plugin using copy-on-write reset.
+    fn on_instantiate(&self, _plugin: uuid::Uuid, _reinstantiated: bool) {}
+
+    /// A call into an exported function is about to start
+    fn on_call_start(&self, _plugin: uuid::Uuid, _function: &str) {}
+
+    /// A call into an exported function has returned, `success` is `false` if the call returned an error
+    fn on_call_end(
+        &self,
+        _plugin: uuid::Uuid,
+        _function: &str,
+        _duration: Duration,
+        _success: bool,
+    ) {
+    }
+
+    /// A call was aborted by a Wasm trap, `on_call_end` is also called
+    fn on_trap(&self, _plugin: uuid::Uuid, _function: &str, _error: &Error) {}
+
+    /// A call was interrupted because it exceeded its timeout, `on_call_end` is also called
+    fn on_timeout(&self, _plugin: uuid::Uuid, _function: &str) {}
+
+    /// Memory allocated by the Extism kernel was freed by `Plugin::reset`, plugins built with
+    /// `PluginBuilder::with_copy_on_write_reset` also had their memory restored
+    fn on_memory_reset(&self, _plugin: uuid::Uuid) {}
+}
+
+impl<T: PluginHooks + ?Sized> PluginHooks for Arc<T> {
+    fn on_instantiate(&self, plugin: uuid::Uuid, reinstantiated: bool) {
+        (**self).on_instantiate(plugin, reinstantiated)
+    }
+
+    fn on_call_start(&self, plugin: uuid::Uuid, function: &str) {
+        (**self).on_call_start(plugin, function)
+    }
+
+    fn on_call_end(&self, plugin: uuid::Uuid, function: &str, duration: Duration, success: bool) {
+        (**self).on_call_end(plugin, function, duration, success)
+    }
+
+    fn on_trap(&self, plugin: uuid::Uuid, function: &str, error: &Error) {
+        (**self).on_trap(plugin, function, error)
+    }
+
+    fn on_timeout(&self, plugin: uuid::Uuid, function: &str) {
+        (**self).on_timeout(plugin, function)
+    }
+
+    fn on_memory_reset(&self, plugin: uuid::Uuid) {
+        (**self).on_memory_reset(plugin)
+    }
+}
@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use crate::*;
+
+/// Configures the HTTP client used by `extism:host/env::http_request`, see
+/// `PluginBuilder::with_http_client`. Settings that aren't set use the `ureq` defaults. The manifest
+/// timeout still applies to each request.
+#[derive(Debug, Clone, Default)]
+pub struct HttpClientOptions {
+    proxy: Option<String>,
+    root_certs: Vec<Vec<u8>>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    max_redirects: Option<u32>,
+}
+
+impl HttpClientOptions {
+    /// Create an `HttpClientOptions` with the default settings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send requests through a proxy, for example `http://proxy.example.com:8080` or
+    /// `socks5://127.0.0.1:1080`. Credentials can be included in the URL.
+    pub fn with_proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.proxy = Some(proxy.into());
+        self
+    }
+
+    /// Trust the PEM-encoded certificates in `pem` instead of the bundled Mozilla root certificates, this
+    /// can be called more than once to trust certificates from multiple files
+    pub fn with_root_certificates(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.root_certs.push(pem.into());
+        self
+    }
+
+    /// Limit the time spent resolving and connecting to the host
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit the time spent waiting for the response headers and for the response body
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Follow at most `n` redirects, `0` disables redirects so the redirect response is returned to the
+    /// plugin. Redirects are followed without checking the new host against `allowed_hosts`.
+    pub fn with_max_redirects(mut self, n: u32) -> Self {
+        self.max_redirects = Some(n);
+        self
+    }
+
+    /// Create the `ureq::Agent`, invalid proxies and certificates are reported here
+    pub(crate) fn agent(&self) -> Result<ureq::Agent, Error> {
+        let mut config = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .timeout_connect(self.connect_timeout)
+            .timeout_recv_response(self.read_timeout)
+            .timeout_recv_body(self.read_timeout);
+        if let Some(proxy) = &self.proxy {
+            config = config.proxy(Some(ureq::Proxy::new(proxy)?));
+        }
+        if let Some(n) = self.max_redirects {
+            config = config.max_redirects(n).max_redirects_will_error(false);
+        }
+        if !self.root_certs.is_empty() {
+            let mut certs = vec![];
+            for pem in &self.root_certs {
+                for item in ureq::tls::parse_pem(pem) {
+                    if let ureq::tls::PemItem::Certificate(cert) = item? {
+                        certs.push(cert);
+                    }
+                }
+            }
+            if certs.is_empty() {
+                anyhow::bail!("no certificates found in root certificate PEM data");
+            }
+            let tls = ureq::tls::TlsConfig::builder()
+                .root_certs(ureq::tls::RootCerts::new_with_certs(&certs))
+                .build();
+            config = config.tls_config(tls);
+        }
+        Ok(config.build().new_agent())
+    }
+}
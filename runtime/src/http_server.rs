@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use axum::body::Bytes;
+use axum::http::{Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{MethodFilter, MethodRouter};
+
+use crate::*;
+
+/// `HttpServer` maps HTTP routes to plugin functions, each request is handled by a plugin from a `Pool`
+///
+/// The request body is passed to the function as input and the output is returned as the response body.
+/// When a function fails the error message is returned with a `500` status, unless the function returned
+/// an exit code between `400` and `599` in which case that is used as the status code. `503` is returned
+/// if no plugin instance is available before the pool timeout.
+///
+/// ```no_run
+/// # async fn example(pool: extism::Pool) -> Result<(), extism::Error> {
+/// use axum::http::Method;
+///
+/// extism::HttpServer::new(pool)
+///     .route(Method::POST, "/count", "count_vowels")
+///     .serve("127.0.0.1:8080")
+///     .await
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct HttpServer {
+    pool: Pool,
+    routes: Vec<(Method, String, String)>,
+    pool_timeout: Duration,
+}
+
+impl HttpServer {
+    /// Create a new `HttpServer` with no routes
+    pub fn new(pool: Pool) -> Self {
+        HttpServer {
+            pool,
+            routes: vec![],
+            pool_timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Call `function` for requests with the given method and path, `path` uses the axum route syntax
+    pub fn route(
+        mut self,
+        method: Method,
+        path: impl Into<String>,
+        function: impl Into<String>,
+    ) -> Self {
+        self.routes.push((method, path.into(), function.into()));
+        self
+    }
+
+    /// Set how long a request waits for an instance from the `Pool`, the default is 10 seconds
+    pub fn with_pool_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_timeout = timeout;
+        self
+    }
+
+    /// Convert into an `axum::Router`, this can be used to add middleware or merge the routes with
+    /// other handlers
+    pub fn into_router(self) -> Result<axum::Router, Error> {
+        let mut paths: BTreeMap<String, MethodRouter> = BTreeMap::new();
+        for (method, path, function) in self.routes {
+            let filter = match MethodFilter::try_from(method.clone()) {
+                Ok(x) => x,
+                Err(_) => anyhow::bail!("unsupported HTTP method: {method}"),
+            };
+            let pool = self.pool.clone();
+            let timeout = self.pool_timeout;
+            let handler = move |body: Bytes| call(pool, function, timeout, body);
+            let router = paths.remove(&path).unwrap_or_default();
+            paths.insert(path, router.on(filter, handler));
+        }
+
+        let mut router = axum::Router::new();
+        for (path, methods) in paths {
+            router = router.route(&path, methods);
+        }
+        Ok(router)
+    }
+
+    /// Listen on `addr` and handle requests until an error occurs
+    pub async fn serve(self, addr: impl tokio::net::ToSocketAddrs) -> Result<(), Error> {
+        let router = self.into_router()?;
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        axum::serve(listener, router).await?;
+        Ok(())
+    }
+}
+
+async fn call(pool: Pool, function: String, timeout: Duration, body: Bytes) -> Response {
+    // Plugin calls block, so they're run outside of the async runtime
+    let res = tokio::task::spawn_blocking(move || match pool.get(timeout) {
+        Ok(Some(mut plugin)) => {
+            Some(plugin.call_get_error_code::<&[u8], Vec<u8>>(&function, &body))
+        }
+        Ok(None) => None,
+        Err(e) => Some(Err((e, -1))),
+    })
+    .await;
+
+    match res {
+        Ok(Some(Ok(output))) => output.into_response(),
+        Ok(Some(Err((e, code)))) => {
+            let status = u16::try_from(code)
+                .ok()
+                .filter(|x| (400..600).contains(x))
+                .and_then(|x| StatusCode::from_u16(x).ok())
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            (status, e.to_string()).into_response()
+        }
+        Ok(None) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "no plugin instance available",
+        )
+            .into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
@@ -1,11 +1,16 @@
 use crate::*;
 
 /// WASI context
+#[cfg(not(target_family = "wasm"))]
 pub struct Wasi {
     /// wasi
     pub ctx: wasi_common::WasiCtx,
 }
 
+/// WASI isn't available on wasm32 hosts
+#[cfg(target_family = "wasm")]
+pub enum Wasi {}
+
 /// InternalExt provides a unified way of acessing `memory`, `store` and `internal` values
 pub(crate) trait Internal {
     fn store(&self) -> &Store<CurrentPlugin>;
@@ -0,0 +1,459 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::pdk::args;
+use crate::*;
+
+/// Namespace for the key/value host functions
+pub const EXTISM_KV_MODULE: &str = "extism:host/kv";
+
+/// A `KvStore` provides persistent storage for the `extism:host/kv` host functions, see
+/// `PluginBuilder::with_kv_store`
+///
+/// The same store may be shared by many plugins, so implementations must be thread-safe.
+pub trait KvStore: Send + Sync {
+    /// Get the value for `key`, `Ok(None)` should be returned if the key doesn't exist or has expired
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Set the value for `key`, if `ttl` is set the key should expire after that amount of time
+    fn set(&self, key: &str, value: &[u8], ttl: Option<Duration>) -> Result<(), Error>;
+
+    /// Remove `key`, returning `true` if it existed
+    fn delete(&self, key: &str) -> Result<bool, Error>;
+
+    /// List all keys that start with `prefix`
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Error>;
+
+    /// Add `delta` to the number stored as a decimal string at `key`, returning the new value. Missing
+    /// keys are treated as `0`
+    ///
+    /// The default implementation isn't atomic, stores that support it should override this.
+    fn increment(&self, key: &str, delta: u64) -> Result<u64, Error> {
+        let n = parse_counter(self.get(key)?.as_deref())?.saturating_add(delta);
+        self.set(key, n.to_string().as_bytes(), None)?;
+        Ok(n)
+    }
+
+    /// Files and directories the store reads and writes, they stay accessible to host functions when
+    /// `Hardening` restricts filesystem access
+    fn local_paths(&self) -> Vec<std::path::PathBuf> {
+        vec![]
+    }
+}
+
+fn parse_counter(value: Option<&[u8]>) -> Result<u64, Error> {
+    match value {
+        Some(v) => Ok(std::str::from_utf8(v)?.parse()?),
+        None => Ok(0),
+    }
+}
+
+impl<T: KvStore + ?Sized> KvStore for Arc<T> {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        (**self).get(key)
+    }
+
+    fn set(&self, key: &str, value: &[u8], ttl: Option<Duration>) -> Result<(), Error> {
+        (**self).set(key, value, ttl)
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, Error> {
+        (**self).delete(key)
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        (**self).list(prefix)
+    }
+
+    fn increment(&self, key: &str, delta: u64) -> Result<u64, Error> {
+        (**self).increment(key, delta)
+    }
+
+    fn local_paths(&self) -> Vec<std::path::PathBuf> {
+        (**self).local_paths()
+    }
+}
+
+// Values and their expiration time. Expired values are removed when they're accessed, and the whole map
+// is swept once it has doubled in size since the last sweep, so keys that are never read again don't build
+// up
+#[derive(Default)]
+pub(crate) struct Entries {
+    pub(crate) values: BTreeMap<String, (Vec<u8>, Option<Instant>)>,
+    sweep_at: usize,
+}
+
+impl Entries {
+    // The smallest map that's swept for expired values
+    const MIN_SWEEP: usize = 1024;
+
+    fn is_live(expires: &Option<Instant>, now: Instant) -> bool {
+        expires.is_none_or(|x| x > now)
+    }
+
+    // Get a value, removing it if it has expired
+    fn get(&mut self, key: &str) -> Option<&(Vec<u8>, Option<Instant>)> {
+        let now = Instant::now();
+        if self
+            .values
+            .get(key)
+            .is_some_and(|(_, expires)| !Self::is_live(expires, now))
+        {
+            self.values.remove(key);
+        }
+        self.values.get(key)
+    }
+
+    fn insert(&mut self, key: &str, value: Vec<u8>, expires: Option<Instant>) {
+        self.values.insert(key.to_string(), (value, expires));
+        if self.values.len() > self.sweep_at.max(Self::MIN_SWEEP) {
+            let now = Instant::now();
+            self.values
+                .retain(|_, (_, expires)| Self::is_live(expires, now));
+            self.sweep_at = self.values.len() * 2;
+        }
+    }
+}
+
+/// An in-memory `KvStore`, clones share the same data
+#[derive(Default, Clone)]
+pub struct MemoryKvStore {
+    pub(crate) data: Arc<Mutex<Entries>>,
+}
+
+impl std::fmt::Debug for MemoryKvStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MemoryKvStore")
+    }
+}
+
+impl MemoryKvStore {
+    /// Create a new, empty `MemoryKvStore`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        match self.data.lock() {
+            Ok(x) => x,
+            Err(e) => e.into_inner(),
+        }
+    }
+}
+
+impl KvStore for MemoryKvStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.lock().get(key).map(|(v, _)| v.clone()))
+    }
+
+    fn set(&self, key: &str, value: &[u8], ttl: Option<Duration>) -> Result<(), Error> {
+        let expires = ttl.map(|x| Instant::now() + x);
+        self.lock().insert(key, value.to_vec(), expires);
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, Error> {
+        let now = Instant::now();
+        Ok(self
+            .lock()
+            .values
+            .remove(key)
+            .is_some_and(|(_, expires)| Entries::is_live(&expires, now)))
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let now = Instant::now();
+        Ok(self
+            .lock()
+            .values
+            .range(prefix.to_string()..)
+            .take_while(|(k, _)| k.starts_with(prefix))
+            .filter(|(_, (_, expires))| Entries::is_live(expires, now))
+            .map(|(k, _)| k.clone())
+            .collect())
+    }
+
+    fn increment(&self, key: &str, delta: u64) -> Result<u64, Error> {
+        let mut data = self.lock();
+        let (n, expires) = match data.get(key) {
+            Some((value, expires)) => (parse_counter(Some(value))?, *expires),
+            None => (0, None),
+        };
+        let n = n.saturating_add(delta);
+        data.insert(key, n.to_string().into_bytes(), expires);
+        Ok(n)
+    }
+}
+
+/// A `KvStore` backed by a `sled` tree
+///
+/// Values are stored with an 8 byte prefix containing the expiration time in milliseconds since the
+/// Unix epoch, or `0` if the key doesn't expire.
+#[cfg(feature = "kv-sled")]
+#[derive(Clone)]
+pub struct SledKvStore {
+    tree: sled::Tree,
+    path: Option<std::path::PathBuf>,
+}
+
+#[cfg(feature = "kv-sled")]
+impl SledKvStore {
+    /// Open or create a `sled` database at the given path
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, Error> {
+        let db = sled::open(&path)?;
+        Ok(SledKvStore {
+            tree: db.open_tree("extism")?,
+            path: Some(path.as_ref().to_path_buf()),
+        })
+    }
+
+    /// Use an existing `sled` tree. The database path isn't known, so it isn't returned by
+    /// `KvStore::local_paths`
+    pub fn new(tree: sled::Tree) -> Self {
+        SledKvStore { tree, path: None }
+    }
+
+    fn now_ms() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|x| x.as_millis() as u64)
+            .unwrap_or_default()
+    }
+
+    // Returns `None` for expired values
+    fn decode(value: &[u8]) -> Option<&[u8]> {
+        let (expires, value) = value.split_at_checked(8)?;
+        let expires = u64::from_le_bytes(expires.try_into().ok()?);
+        if expires != 0 && expires <= Self::now_ms() {
+            return None;
+        }
+        Some(value)
+    }
+}
+
+#[cfg(feature = "kv-sled")]
+impl KvStore for SledKvStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        let Some(value) = self.tree.get(key)? else {
+            return Ok(None);
+        };
+        match Self::decode(&value) {
+            Some(x) => Ok(Some(x.to_vec())),
+            None => {
+                self.tree.remove(key)?;
+                Ok(None)
+            }
+        }
+    }
+
+    fn set(&self, key: &str, value: &[u8], ttl: Option<Duration>) -> Result<(), Error> {
+        let expires = ttl
+            .map(|x| Self::now_ms() + x.as_millis() as u64)
+            .unwrap_or_default();
+        let mut data = Vec::with_capacity(value.len() + 8);
+        data.extend_from_slice(&expires.to_le_bytes());
+        data.extend_from_slice(value);
+        self.tree.insert(key, data)?;
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, Error> {
+        Ok(self
+            .tree
+            .remove(key)?
+            .is_some_and(|x| Self::decode(&x).is_some()))
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let mut keys = vec![];
+        for item in self.tree.scan_prefix(prefix) {
+            let (k, v) = item?;
+            if Self::decode(&v).is_some() {
+                keys.push(String::from_utf8_lossy(&k).into_owned());
+            }
+        }
+        Ok(keys)
+    }
+
+    fn local_paths(&self) -> Vec<std::path::PathBuf> {
+        self.path.iter().cloned().collect()
+    }
+}
+
+/// A `KvStore` backed by Redis, expiration is handled by Redis
+#[cfg(feature = "kv-redis")]
+pub struct RedisKvStore {
+    conn: Mutex<redis::Connection>,
+}
+
+#[cfg(feature = "kv-redis")]
+impl RedisKvStore {
+    /// Connect to the Redis server at `url`, for example `redis://127.0.0.1/`
+    pub fn open(url: &str) -> Result<Self, Error> {
+        let client = redis::Client::open(url)?;
+        Ok(RedisKvStore {
+            conn: Mutex::new(client.get_connection()?),
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, redis::Connection> {
+        match self.conn.lock() {
+            Ok(x) => x,
+            Err(e) => e.into_inner(),
+        }
+    }
+}
+
+#[cfg(feature = "kv-redis")]
+impl KvStore for RedisKvStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(redis::cmd("GET").arg(key).query(&mut *self.lock())?)
+    }
+
+    fn set(&self, key: &str, value: &[u8], ttl: Option<Duration>) -> Result<(), Error> {
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(key).arg(value);
+        if let Some(ttl) = ttl {
+            cmd.arg("PX").arg(ttl.as_millis().max(1) as u64);
+        }
+        cmd.query::<()>(&mut *self.lock())?;
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, Error> {
+        let n: u64 = redis::cmd("DEL").arg(key).query(&mut *self.lock())?;
+        Ok(n > 0)
+    }
+
+    fn increment(&self, key: &str, delta: u64) -> Result<u64, Error> {
+        let delta = i64::try_from(delta)?;
+        let n: i64 = redis::cmd("INCRBY")
+            .arg(key)
+            .arg(delta)
+            .query(&mut *self.lock())?;
+        Ok(u64::try_from(n)?)
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        // Escape glob characters in the prefix
+        let mut pattern = String::with_capacity(prefix.len() + 1);
+        for c in prefix.chars() {
+            if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+        pattern.push('*');
+
+        let mut conn = self.lock();
+        let keys = redis::cmd("SCAN")
+            .cursor_arg(0)
+            .arg("MATCH")
+            .arg(pattern)
+            .clone()
+            .iter::<String>(&mut *conn)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(keys)
+    }
+}
+
+// Host functions, these are linked into the `extism:host/kv` namespace when a `KvStore` is configured
+
+fn store(data: &CurrentPlugin) -> Result<Arc<dyn KvStore>, Error> {
+    match &data.kv {
+        Some(x) => Ok(x.clone()),
+        None => anyhow::bail!("no key/value store is configured"),
+    }
+}
+
+fn read_key(data: &mut CurrentPlugin, offset: u64) -> Result<String, Error> {
+    let handle = match data.memory_handle(offset) {
+        Some(h) => h,
+        None => anyhow::bail!("invalid handle offset for key: {offset}"),
+    };
+    let key = data.memory_str(handle)?.to_string();
+    data.memory_free(handle)?;
+    Ok(key)
+}
+
+/// Get a value
+/// Params: i64 (key offset)
+/// Returns: i64 (value offset), or 0 if the key doesn't exist
+/// **Note**: this function takes ownership of the handle passed in
+/// the caller should not `free` this value
+pub(crate) fn get(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let key = read_key(data, args!(input, 0, i64) as u64)?;
+    output[0] = match store(data)?.get(&key)? {
+        Some(value) => Val::I64(data.memory_new(value)?.offset() as i64),
+        None => Val::I64(0),
+    };
+    Ok(())
+}
+
+/// Set a value
+/// Params: i64 (key offset), i64 (value offset), i64 (TTL in milliseconds, 0 for no expiration)
+/// Returns: none
+/// **Note**: this function takes ownership of the handles passed in
+/// the caller should not `free` these values
+pub(crate) fn set(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    _output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let key = read_key(data, args!(input, 0, i64) as u64)?;
+    let voffset = args!(input, 1, i64) as u64;
+    let ttl = args!(input, 2, i64);
+    let handle = match data.memory_handle(voffset) {
+        Some(h) => h,
+        None => anyhow::bail!("invalid handle offset for value: {voffset}"),
+    };
+    let value = data.memory_bytes(handle)?.to_vec();
+    data.memory_free(handle)?;
+    let ttl = if ttl > 0 {
+        Some(Duration::from_millis(ttl as u64))
+    } else {
+        None
+    };
+    store(data)?.set(&key, &value, ttl)
+}
+
+/// Delete a value
+/// Params: i64 (key offset)
+/// Returns: i32 (1 if the key existed, otherwise 0)
+/// **Note**: this function takes ownership of the handle passed in
+/// the caller should not `free` this value
+pub(crate) fn delete(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let key = read_key(data, args!(input, 0, i64) as u64)?;
+    let deleted = store(data)?.delete(&key)?;
+    output[0] = Val::I32(deleted as i32);
+    Ok(())
+}
+
+/// List keys
+/// Params: i64 (prefix offset)
+/// Returns: i64 (offset of a JSON array containing the matching keys)
+/// **Note**: this function takes ownership of the handle passed in
+/// the caller should not `free` this value
+pub(crate) fn list(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let prefix = read_key(data, args!(input, 0, i64) as u64)?;
+    let keys = store(data)?.list(&prefix)?;
+    let json = serde_json::to_vec(&keys)?;
+    output[0] = Val::I64(data.memory_new(json)?.offset() as i64);
+    Ok(())
+}
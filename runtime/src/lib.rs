@@ -5,13 +5,32 @@ macro_rules! catch_out_of_fuel {
     ($store: expr, $x:expr) => {{
         let y = $x;
         if y.is_err() && $store.get_fuel().is_ok_and(|x| x == 0) {
-            Err(wasmtime::Error::msg("plugin ran out of fuel"))
+            Err(wasmtime::Error::new($crate::OutOfFuel))
         } else {
             y
         }
     }};
 }
 
+// Enter a `tracing` span with the given name and fields, this is a no-op unless the `tracing`
+// feature is enabled
+#[cfg(feature = "tracing")]
+macro_rules! span {
+    ($name:literal $(, $($fields:tt)*)?) => {
+        tracing::info_span!($name $(, $($fields)*)?).entered()
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! span {
+    ($($x:tt)*) => {
+        $crate::NoSpan
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
+
 pub(crate) use extism_convert::*;
 pub(crate) use std::collections::BTreeMap;
 use std::str::FromStr;
@@ -22,32 +41,192 @@ pub use extism_convert as convert;
 
 pub use anyhow::Error;
 
+#[cfg(feature = "tokio")]
+mod async_plugin;
+mod backend;
+#[cfg(not(target_family = "wasm"))]
+mod background;
+mod backtrace;
+mod call_log;
+mod clock;
+mod compile;
+#[cfg(feature = "component-model")]
+mod component;
+mod concurrent;
 mod current_plugin;
+mod error;
+mod events;
 mod function;
+mod group;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod hardening;
+mod hooks;
+#[cfg(feature = "http")]
+mod http_client;
+#[cfg(feature = "http-server")]
+mod http_server;
 mod internal;
+mod kv;
+mod log_handler;
 pub(crate) mod manifest;
+#[cfg(not(target_family = "wasm"))]
+mod memory_fs;
+mod memory_policy;
+mod metrics;
+mod msg;
+mod net;
+mod object;
+#[cfg(feature = "register-oci")]
+mod oci;
+#[cfg(unix)]
+mod out_of_process;
 pub(crate) mod pdk;
+// The wasmtime platform functions aren't part of the C API
+/// cbindgen:ignore
+#[cfg(target_family = "wasm")]
+mod platform;
 mod plugin;
 mod plugin_builder;
+mod policy;
 mod pool;
+#[cfg(not(target_family = "wasm"))]
+mod pooling;
+mod profiler;
+pub mod quick;
+mod quota;
+mod random;
+#[cfg(not(target_family = "wasm"))]
 mod readonly_dir;
+mod redact;
+mod resolver;
+mod resources;
+mod sandbox;
+mod scheduler;
+mod secrets;
+#[cfg(feature = "tower")]
+mod service;
+mod signature;
+mod snapshot;
+mod sql;
+mod stdio;
+mod streaming;
+mod telemetry;
+#[cfg(not(target_family = "wasm"))]
+pub mod testing;
+mod threads;
 mod timer;
+mod typed_function;
+mod usage;
+mod var_store;
+#[cfg(feature = "wasi-http")]
+mod wasi_http;
+#[cfg(feature = "wasi-keyvalue")]
+mod wasi_keyvalue;
+#[cfg(feature = "wasi-nn")]
+mod wasi_nn;
+mod watchdog;
+#[cfg(feature = "wit")]
+pub mod wit;
+#[cfg(feature = "websocket")]
+mod ws;
 
 /// Extism C API
 pub mod sdk;
 
+#[cfg(feature = "tokio")]
+pub use async_plugin::AsyncPlugin;
+pub use backend::Backend;
+#[cfg(not(target_family = "wasm"))]
+pub use background::BackgroundPlugin;
+pub use backtrace::{GuestBacktrace, GuestFrame};
+pub use call_log::{CallLog, CallRecord};
+pub use clock::{FixedClock, WasiClock};
+pub use compile::CompileReport;
+pub use concurrent::ConcurrentPlugin;
 pub use current_plugin::CurrentPlugin;
+pub use error::{ErrorKind, FunctionNotFound, HostFunctionError, OutOfFuel, Timeout};
+pub use events::{EventBus, PluginEvent};
 pub use extism_convert::{FromBytes, FromBytesOwned, ToBytes};
-pub use extism_manifest::{Manifest, Wasm, WasmMetadata};
+pub use extism_manifest::{
+    HttpPolicy, HttpRequest, Manifest, ManifestBuilder, ManifestError, Wasm, WasmMetadata,
+    WasmSignature,
+};
 pub use function::{Function, UserData, Val, ValType, PTR};
+pub use group::PluginGroup;
+#[cfg(feature = "grpc")]
+pub use grpc::GrpcServer;
+pub use hardening::Hardening;
+pub use hooks::PluginHooks;
+#[cfg(feature = "http")]
+pub use http_client::HttpClientOptions;
+#[cfg(feature = "http-server")]
+pub use http_server::HttpServer;
+#[cfg(feature = "kv-redis")]
+pub use kv::RedisKvStore;
+#[cfg(feature = "kv-sled")]
+pub use kv::SledKvStore;
+pub use kv::{KvStore, MemoryKvStore, EXTISM_KV_MODULE};
+pub use log_handler::{LogHandler, LogRecord};
+pub use memory_policy::KernelMemoryPolicy;
+pub use metrics::Metrics;
+#[cfg(feature = "prometheus")]
+pub use metrics::PrometheusMetrics;
+pub use msg::{MemoryBroker, MessageBroker, Subscription, EXTISM_MSG_MODULE};
+pub use net::EXTISM_NET_MODULE;
+#[cfg(feature = "object-s3")]
+pub use object::S3ObjectStore;
+pub use object::{MemoryObjectStore, ObjectStore, EXTISM_OBJECT_MODULE};
+#[cfg(unix)]
+pub use out_of_process::{OutOfProcess, ProcessLimits};
 pub use plugin::{
-    CancelHandle, CompiledPlugin, Plugin, WasmInput, EXTISM_ENV_MODULE, EXTISM_USER_MODULE,
+    CancelHandle, Cancelled, CompiledPlugin, OutOfMemory, Plugin, WasmInput, EXTISM_ENV_MODULE,
+    EXTISM_USER_MODULE,
 };
 pub use plugin_builder::{DebugOptions, PluginBuilder};
+pub use policy::ModulePolicy;
 pub use pool::{Pool, PoolBuilder, PoolPlugin};
+#[cfg(not(target_family = "wasm"))]
+pub use pooling::PoolingAllocator;
+pub use profiler::Profiling;
+pub use quota::{HostFunctionLimit, HostFunctionLimits};
+pub use random::{SeededRandom, WasiRandom};
+pub use redact::{RedactTarget, Redactor};
+pub use resolver::WasmResolver;
+pub use resources::{CallStats, ResourceReport};
+pub use sandbox::SandboxProfile;
+pub use scheduler::{
+    CronExpr, Job, OverlapPolicy, RunOutcome, RunRecord, Schedule, Scheduler, SchedulerBuilder,
+};
+pub use secrets::{EnvSecretsProvider, SecretsProvider, SECRET_PREFIX};
+#[cfg(feature = "tower")]
+pub use service::PluginService;
+pub use signature::InvalidSignature;
+pub use snapshot::PluginSnapshot;
+#[cfg(feature = "sql-postgres")]
+pub use sql::PostgresDatabase;
+#[cfg(feature = "sql-sqlite")]
+pub use sql::SqliteDatabase;
+pub use sql::{SqlDatabase, SqlRows, SqlValue, EXTISM_SQL_MODULE};
+pub use stdio::Stdin;
+pub use streaming::{OutputReader, StreamingCall};
+#[cfg(feature = "http")]
+pub use telemetry::HttpSink;
+pub use telemetry::{FileSink, TcpSink, TelemetryExporter, TelemetryOptions, TelemetrySink};
+pub use typed_function::TypedFunction;
+pub use usage::{HostFunctionStats, HostFunctionUsage};
+pub use var_store::{KvVarStore, VarStore};
+#[cfg(feature = "wasi-http")]
+pub use wasi_http::{HttpHandler, HttpHandlerResponse};
+#[cfg(feature = "wasi-nn")]
+pub use wasi_nn::WasiNn;
+#[cfg(feature = "websocket")]
+pub use ws::{WebSocketLimits, EXTISM_WS_MODULE};
 
+pub(crate) use current_plugin::WasiSources;
+pub(crate) use error::GuestError;
 pub(crate) use internal::{Internal, Wasi};
-pub(crate) use timer::{Timer, TimerAction};
+pub(crate) use timer::{Timer, TimerAction, TimerTx};
 pub(crate) use tracing::{debug, error, trace, warn};
 
 #[cfg(test)]
@@ -0,0 +1,28 @@
+/// A message logged by a plugin using `extism:host/env::log_*`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LogRecord<'a> {
+    /// The ID of the plugin that logged the message
+    pub plugin: uuid::Uuid,
+
+    /// The exported function that was being called
+    pub function: &'a str,
+
+    /// The log level used by the plugin
+    pub level: tracing::Level,
+
+    /// The message, after secrets have been removed and the `Redactor` has been applied
+    pub message: &'a str,
+}
+
+/// A `LogHandler` receives every message logged by a plugin, see `PluginBuilder::with_log_handler`
+pub trait LogHandler: Send + Sync {
+    /// Handle a log message
+    fn log(&self, record: &LogRecord);
+}
+
+impl<F: Fn(&LogRecord) + Send + Sync> LogHandler for F {
+    fn log(&self, record: &LogRecord) {
+        self(record)
+    }
+}
@@ -1,13 +1,12 @@
 use std::collections::BTreeMap;
 use std::fmt::Write as FmtWrite;
-use std::io::Read;
 
 use sha2::Digest;
 
 use crate::plugin::{WasmInput, MAIN_KEY};
 use crate::*;
 
-fn hex(data: &[u8]) -> String {
+pub(crate) fn hex(data: &[u8]) -> String {
     let mut s = String::new();
     for &byte in data {
         write!(&mut s, "{byte:02x}").unwrap();
@@ -15,7 +14,7 @@ fn hex(data: &[u8]) -> String {
     s
 }
 
-fn check_hash(hash: &Option<String>, data: &[u8]) -> Result<Option<String>, Error> {
+pub(crate) fn check_hash(hash: &Option<String>, data: &[u8]) -> Result<Option<String>, Error> {
     match hash {
         None => Ok(None),
         Some(hash) => {
@@ -33,10 +32,106 @@ fn check_hash(hash: &Option<String>, data: &[u8]) -> Result<Option<String>, Erro
     }
 }
 
+// Check the module data against the manifest hash, signature and `ModulePolicy`. The SHA-256 hash is only
+// computed when the manifest or policy needs it, or when `hash` is set, and returned if it was
+fn verify(
+    meta: &WasmMetadata,
+    policy: &ModulePolicy,
+    hash: bool,
+    data: &[u8],
+) -> Result<Option<String>, Error> {
+    let hex = match check_hash(&meta.hash, data)? {
+        None if hash || !policy.is_empty() => Some(self::hex(&sha2::Sha256::digest(data))),
+        hex => hex,
+    };
+    if let Some(sig) = &meta.signature {
+        signature::verify(meta.name.as_deref().unwrap_or(MAIN_KEY), sig, data)?;
+    }
+    if let Some(hex) = &hex {
+        policy.check_hash(hex)?;
+    }
+    Ok(hex)
+}
+
 const WASM: &[u8] = include_bytes!("extism-runtime.wasm");
 
+// Modules serialized by wasmtime are ELF files
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+
+fn is_precompiled(data: &[u8]) -> bool {
+    data.len() >= 4 && data[0..4] == ELF_MAGIC
+}
+
+// Check the header of a Wasm file without reading the rest of it
+fn file_is_precompiled(path: &std::path::Path) -> bool {
+    use std::io::Read;
+    let mut header = [0; 4];
+    std::fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut header))
+        .is_ok()
+        && is_precompiled(&header)
+}
+
+// Components use layer 1 in the header, core modules use layer 0
+const COMPONENT_HEADER: [u8; 8] = [0x00, 0x61, 0x73, 0x6d, 0x0d, 0x00, 0x01, 0x00];
+
+/// Returns `true` if `data` is a component binary or a component in the WebAssembly text format
+pub(crate) fn is_component(data: &[u8]) -> bool {
+    if data.starts_with(&COMPONENT_HEADER) {
+        return true;
+    }
+    std::str::from_utf8(data).is_ok_and(|s| {
+        s.trim_start()
+            .strip_prefix('(')
+            .is_some_and(|s| s.trim_start().starts_with("component"))
+    })
+}
+
+/// A compiled module or component
+pub(crate) enum Compiled {
+    Module(Module),
+    #[cfg(feature = "component-model")]
+    Component(wasmtime::component::Component),
+}
+
+impl From<Module> for Compiled {
+    fn from(m: Module) -> Self {
+        Compiled::Module(m)
+    }
+}
+
+// Compile a module or component, or load a precompiled module when `precompiled` is enabled
+fn compile(engine: &Engine, data: &[u8], precompiled: bool) -> Result<Compiled, Error> {
+    if is_component(data) {
+        #[cfg(feature = "component-model")]
+        return Ok(Compiled::Component(wasmtime::component::Component::new(
+            engine, data,
+        )?));
+        #[cfg(not(feature = "component-model"))]
+        anyhow::bail!("Loading components requires the `component-model` feature");
+    }
+    if !is_precompiled(data) {
+        return Ok(Module::new(engine, data)?.into());
+    }
+    if !precompiled {
+        anyhow::bail!(
+            "Module is precompiled, precompiled modules are only loaded when \
+            `PluginBuilder::with_precompiled_modules` is enabled"
+        );
+    }
+    // Safety: `with_precompiled_modules` requires precompiled modules to be trusted
+    Ok(unsafe { Module::deserialize(engine, data)? }.into())
+}
+
 /// Convert from manifest to a wasmtime Module
-fn to_module(engine: &Engine, wasm: &extism_manifest::Wasm) -> Result<(String, Module), Error> {
+fn to_module(
+    engine: &Engine,
+    policy: &ModulePolicy,
+    resolvers: &resolver::WasmResolvers,
+    precompiled: bool,
+    hash: bool,
+    wasm: &extism_manifest::Wasm,
+) -> Result<(String, Compiled, Option<String>), Error> {
     match wasm {
         extism_manifest::Wasm::File { path, meta } => {
             if cfg!(not(feature = "register-filesystem")) {
@@ -46,7 +141,18 @@ fn to_module(engine: &Engine, wasm: &extism_manifest::Wasm) -> Result<(String, M
             // Use the configured name or `MAIN_KEY`
             let name = meta.name.as_deref().unwrap_or(MAIN_KEY).to_string();
 
-            // Load file
+            // Precompiled files with nothing to check them against are trusted by
+            // `with_precompiled_modules`, so they're mapped directly by wasmtime instead of being read
+            let checked =
+                hash || meta.hash.is_some() || meta.signature.is_some() || !policy.is_empty();
+            if precompiled && !checked && file_is_precompiled(path) {
+                // Safety: `with_precompiled_modules` requires precompiled modules to be trusted
+                let module = unsafe { Module::deserialize_file(engine, path)? };
+                return Ok((name, module.into(), None));
+            }
+
+            // Everything else is read once and hashed, verified and compiled from that copy, so the
+            // file can't change between the checks and loading it
             let buf = std::fs::read(path).map_err(|err| {
                 Error::msg(format!(
                     "Unable to load Wasm file \"{}\": {}",
@@ -55,20 +161,21 @@ fn to_module(engine: &Engine, wasm: &extism_manifest::Wasm) -> Result<(String, M
                 ))
             })?;
 
-            check_hash(&meta.hash, &buf)?;
-            Ok((name, Module::new(engine, buf)?))
+            let hash = verify(meta, policy, hash, &buf)?;
+            Ok((name, compile(engine, &buf, precompiled)?, hash))
         }
         extism_manifest::Wasm::Data { meta, data } => {
-            check_hash(&meta.hash, data)?;
+            let hash = verify(meta, policy, hash, data)?;
             Ok((
                 meta.name.as_deref().unwrap_or(MAIN_KEY).to_string(),
-                Module::new(engine, data)?,
+                compile(engine, data, precompiled)?,
+                hash,
             ))
         }
         #[allow(unused)]
         extism_manifest::Wasm::Url {
             req:
-                extism_manifest::HttpRequest {
+                req @ extism_manifest::HttpRequest {
                     url,
                     headers,
                     method,
@@ -78,6 +185,15 @@ fn to_module(engine: &Engine, wasm: &extism_manifest::Wasm) -> Result<(String, M
             // Use the configured name or `MAIN_KEY`
             let name = meta.name.as_deref().unwrap_or(MAIN_KEY).to_string();
 
+            // Custom URL schemes are fetched using the registered `WasmResolver`
+            if let Some(resolver) = resolver::find(resolvers, url) {
+                let data = resolver
+                    .resolve(req)
+                    .map_err(|e| e.context(format!("unable to resolve Wasm module {url}")))?;
+                let hash = verify(meta, policy, hash, &data)?;
+                return Ok((name, compile(engine, &data, precompiled)?, hash));
+            }
+
             #[cfg(not(feature = "register-http"))]
             {
                 return anyhow::bail!("HTTP registration is disabled");
@@ -85,6 +201,8 @@ fn to_module(engine: &Engine, wasm: &extism_manifest::Wasm) -> Result<(String, M
 
             #[cfg(feature = "register-http")]
             {
+                use std::io::Read;
+
                 // Setup request
                 let mut req = ureq::http::request::Builder::new()
                     .method(method.as_deref().unwrap_or("GET").to_uppercase().as_str())
@@ -100,12 +218,29 @@ fn to_module(engine: &Engine, wasm: &extism_manifest::Wasm) -> Result<(String, M
                 r.read_to_end(&mut data)?;
 
                 // Check hash against manifest
-                check_hash(&meta.hash, &data)?;
+                let hash = verify(meta, policy, hash, &data)?;
 
                 // Convert fetched data to module
-                let module = Module::new(engine, data)?;
+                let module = compile(engine, &data, precompiled)?;
 
-                Ok((name.to_string(), module))
+                Ok((name.to_string(), module, hash))
+            }
+        }
+        #[allow(unused)]
+        extism_manifest::Wasm::Oci { oci, meta } => {
+            // Use the configured name or `MAIN_KEY`
+            let name = meta.name.as_deref().unwrap_or(MAIN_KEY).to_string();
+
+            #[cfg(not(feature = "register-oci"))]
+            {
+                return anyhow::bail!("OCI registration is disabled");
+            }
+
+            #[cfg(feature = "register-oci")]
+            {
+                let data = oci::fetch(oci)?;
+                let hash = verify(meta, policy, hash, &data)?;
+                Ok((name, compile(engine, &data, precompiled)?, hash))
             }
         }
     }
@@ -113,63 +248,174 @@ fn to_module(engine: &Engine, wasm: &extism_manifest::Wasm) -> Result<(String, M
 
 const WASM_MAGIC: [u8; 4] = [0x00, 0x61, 0x73, 0x6d];
 
+/// Modules loaded from a `WasmInput`
+pub(crate) struct Loaded {
+    pub(crate) manifest: extism_manifest::Manifest,
+    pub(crate) modules: BTreeMap<String, Module>,
+
+    /// SHA-256 hashes of the module data, keyed by module name (the Extism kernel isn't included). Hashes
+    /// are only computed when they're needed, see `verify`
+    pub(crate) hashes: BTreeMap<String, String>,
+
+    /// The main component, `modules` contains an empty main module in its place
+    #[cfg(feature = "component-model")]
+    pub(crate) component: Option<wasmtime::component::Component>,
+}
+
+// An empty main module, used in place of a component
+#[cfg(feature = "component-model")]
+const EMPTY_MODULE: &[u8] = b"\0asm\x01\0\0\0";
+
+impl Loaded {
+    #[cfg_attr(not(feature = "component-model"), allow(unused_variables))]
+    fn new(
+        engine: &Engine,
+        manifest: extism_manifest::Manifest,
+        compiled: BTreeMap<String, Compiled>,
+        hashes: BTreeMap<String, String>,
+    ) -> Result<Self, Error> {
+        let mut modules = BTreeMap::new();
+        #[cfg(feature = "component-model")]
+        let mut component = None;
+        for (name, c) in compiled {
+            match c {
+                Compiled::Module(m) => {
+                    modules.insert(name, m);
+                }
+                #[cfg(feature = "component-model")]
+                Compiled::Component(c) => {
+                    if name != MAIN_KEY {
+                        anyhow::bail!("Components can only be used as the main module: {name}");
+                    }
+                    modules.insert(name, Module::new(engine, EMPTY_MODULE)?);
+                    component = Some(c);
+                }
+            }
+        }
+        Ok(Loaded {
+            manifest,
+            modules,
+            hashes,
+            #[cfg(feature = "component-model")]
+            component,
+        })
+    }
+}
+
 pub(crate) fn load(
     engine: &Engine,
+    policy: &ModulePolicy,
+    resolvers: &resolver::WasmResolvers,
+    precompiled: bool,
+    hash: bool,
     input: WasmInput<'_>,
-) -> Result<(extism_manifest::Manifest, BTreeMap<String, Module>), Error> {
+) -> Result<Loaded, Error> {
+    let mut hashes = BTreeMap::new();
     let mut mods = BTreeMap::new();
-    mods.insert(EXTISM_ENV_MODULE.to_string(), Module::new(engine, WASM)?);
+    mods.insert(
+        EXTISM_ENV_MODULE.to_string(),
+        Module::new(engine, WASM)?.into(),
+    );
 
     match input {
         WasmInput::Data(data) => {
-            let has_magic = data.len() >= 4 && data[0..4] == WASM_MAGIC;
+            let has_magic = data.len() >= 4 && data[0..4] == WASM_MAGIC || is_precompiled(&data);
             let s = std::str::from_utf8(&data);
             let is_wat = s.is_ok_and(|s| {
                 let s = s.trim_start();
                 let starts_with_module = s.len() > 2
                     && data[0] == b'('   // First character is `(`
                     && s[1..].trim_start().starts_with("module"); // Then `module` (after any whitespace)
-                starts_with_module || s.starts_with(";;") || s.starts_with("(;")
+                starts_with_module
+                    || is_component(&data)
+                    || s.starts_with(";;")
+                    || s.starts_with("(;")
             });
             if !has_magic && !is_wat {
                 trace!("Loading manifest");
                 if let Ok(s) = s {
                     let t = if let Ok(t) = toml::from_str::<extism_manifest::Manifest>(s) {
                         trace!("Manifest is TOML");
-                        modules(engine, &t, &mut mods)?;
+                        modules(
+                            engine,
+                            policy,
+                            resolvers,
+                            precompiled,
+                            hash,
+                            &t,
+                            &mut mods,
+                            &mut hashes,
+                        )?;
                         t
                     } else if let Ok(t) = serde_json::from_str::<extism_manifest::Manifest>(s) {
                         trace!("Manifest is JSON");
-                        modules(engine, &t, &mut mods)?;
+                        modules(
+                            engine,
+                            policy,
+                            resolvers,
+                            precompiled,
+                            hash,
+                            &t,
+                            &mut mods,
+                            &mut hashes,
+                        )?;
                         t
                     } else {
                         anyhow::bail!("Unknown manifest format");
                     };
-                    return Ok((t, mods));
+                    return Loaded::new(engine, t, mods, hashes);
                 }
             }
 
-            let m = Module::new(engine, data)?;
+            let hash = verify(&WasmMetadata::default(), policy, hash, &data)?;
+            let m = compile(engine, &data, precompiled)?;
             mods.insert(MAIN_KEY.to_string(), m);
-            Ok((Default::default(), mods))
+            if let Some(hash) = hash {
+                hashes.insert(MAIN_KEY.to_string(), hash);
+            }
+            Loaded::new(engine, Default::default(), mods, hashes)
         }
         WasmInput::Manifest(m) => {
             trace!("Loading from existing manifest");
-            modules(engine, &m, &mut mods)?;
-            Ok((m, mods))
+            modules(
+                engine,
+                policy,
+                resolvers,
+                precompiled,
+                hash,
+                &m,
+                &mut mods,
+                &mut hashes,
+            )?;
+            Loaded::new(engine, m, mods, hashes)
         }
         WasmInput::ManifestRef(m) => {
             trace!("Loading from existing manifest");
-            modules(engine, m, &mut mods)?;
-            Ok((m.clone(), mods))
+            modules(
+                engine,
+                policy,
+                resolvers,
+                precompiled,
+                hash,
+                m,
+                &mut mods,
+                &mut hashes,
+            )?;
+            Loaded::new(engine, m.clone(), mods, hashes)
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn modules(
     engine: &Engine,
+    policy: &ModulePolicy,
+    resolvers: &resolver::WasmResolvers,
+    precompiled: bool,
+    hash: bool,
     manifest: &extism_manifest::Manifest,
-    modules: &mut BTreeMap<String, Module>,
+    modules: &mut BTreeMap<String, Compiled>,
+    hashes: &mut BTreeMap<String, String>,
 ) -> Result<(), Error> {
     if manifest.wasm.is_empty() {
         return Err(anyhow::format_err!(
@@ -179,13 +425,38 @@ pub(crate) fn modules(
 
     // If there's only one module, it should be called `main`
     if manifest.wasm.len() == 1 {
-        let (_, m) = to_module(engine, &manifest.wasm[0])?;
+        let (_, m, hash) = to_module(
+            engine,
+            policy,
+            resolvers,
+            precompiled,
+            hash,
+            &manifest.wasm[0],
+        )?;
         modules.insert(MAIN_KEY.to_string(), m);
+        if let Some(hash) = hash {
+            hashes.insert(MAIN_KEY.to_string(), hash);
+        }
         return Ok(());
     }
 
-    for (i, f) in manifest.wasm.iter().enumerate() {
-        let (mut name, m) = to_module(engine, f)?;
+    // Modules are fetched and compiled in parallel, then added in manifest order
+    let mut loaded = vec![];
+    let mut errors = vec![];
+    let results = compile::par_map(&manifest.wasm, |f| {
+        to_module(engine, policy, resolvers, precompiled, hash, f)
+    });
+    for (i, res) in results.into_iter().enumerate() {
+        match res {
+            Ok(x) => loaded.push(x),
+            Err(e) => errors.push((i, e)),
+        }
+    }
+    if let Some(e) = compile::combine_errors("module", errors) {
+        return Err(e);
+    }
+
+    for (i, (mut name, m, hash)) in loaded.into_iter().enumerate() {
         // Rename the last module to `main` if no main is defined already
         if i == manifest.wasm.len() - 1 && !modules.contains_key(MAIN_KEY) {
             name = MAIN_KEY.to_string();
@@ -194,6 +465,9 @@ pub(crate) fn modules(
             anyhow::bail!("Duplicate module name found in Extism manifest: {name}");
         }
         trace!("Found module {}", name);
+        if let Some(hash) = hash {
+            hashes.insert(name.clone(), hash);
+        }
         modules.insert(name, m);
     }
 
@@ -0,0 +1,439 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::SeekFrom;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use wasi_common::dir::{OpenResult, ReaddirCursor, ReaddirEntity};
+use wasi_common::file::{FdFlags, FileType, Filestat, OFlags};
+use wasi_common::snapshots::preview_1::types::Errno;
+use wasi_common::{Error, ErrorExt, WasiDir, WasiFile};
+
+#[derive(Default)]
+struct Tree {
+    files: BTreeMap<String, Vec<u8>>,
+    // Directories that were created explicitly, parents of files always exist
+    dirs: BTreeSet<String>,
+}
+
+impl Tree {
+    fn is_dir(&self, path: &str) -> bool {
+        if path.is_empty() || self.dirs.contains(path) {
+            return true;
+        }
+        let prefix = format!("{path}/");
+        self.files.keys().any(|k| k.starts_with(&prefix))
+            || self.dirs.iter().any(|k| k.starts_with(&prefix))
+    }
+
+    /// Names and types of the direct children of a directory
+    fn children(&self, path: &str) -> BTreeMap<String, FileType> {
+        let prefix = if path.is_empty() {
+            String::new()
+        } else {
+            format!("{path}/")
+        };
+        let mut children = BTreeMap::new();
+        let files = self.files.keys().map(|k| (k, FileType::RegularFile));
+        let dirs = self.dirs.iter().map(|k| (k, FileType::Directory));
+        for (k, t) in files.chain(dirs) {
+            if let Some(rest) = k.strip_prefix(&prefix) {
+                match rest.split_once('/') {
+                    Some((dir, _)) => children.insert(dir.to_string(), FileType::Directory),
+                    None => children.insert(rest.to_string(), t),
+                };
+            }
+        }
+        children
+    }
+}
+
+/// An in-memory filesystem that can be preopened for WASI, paths are stored without a leading `/`
+#[derive(Clone, Default)]
+pub(crate) struct MemoryFs(Arc<Mutex<Tree>>);
+
+impl MemoryFs {
+    pub(crate) fn write(&self, path: &str, data: Vec<u8>) {
+        if let Ok(path) = join("", path) {
+            self.0.lock().unwrap().files.insert(path, data);
+        }
+    }
+
+    pub(crate) fn read(&self, path: &str) -> Option<Vec<u8>> {
+        let path = join("", path).ok()?;
+        self.0.lock().unwrap().files.get(&path).cloned()
+    }
+
+    /// Paths of all files
+    pub(crate) fn files(&self) -> Vec<String> {
+        self.0.lock().unwrap().files.keys().cloned().collect()
+    }
+
+    /// The root directory
+    pub(crate) fn root(&self) -> MemoryDir {
+        MemoryDir {
+            fs: self.clone(),
+            path: String::new(),
+        }
+    }
+}
+
+/// Resolve `path` relative to `dir`, paths can't escape the root directory
+fn join(dir: &str, path: &str) -> Result<String, Error> {
+    let mut parts: Vec<&str> = dir.split('/').filter(|x| !x.is_empty()).collect();
+    for part in path.split('/') {
+        match part {
+            "" | "." => (),
+            ".." => {
+                if parts.pop().is_none() {
+                    return Err(Error::perm());
+                }
+            }
+            x => parts.push(x),
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map(|(p, _)| p).unwrap_or_default()
+}
+
+fn stat(filetype: FileType, size: u64) -> Filestat {
+    Filestat {
+        device_id: 0,
+        inode: 0,
+        filetype,
+        nlink: 1,
+        size,
+        atim: None,
+        mtim: None,
+        ctim: None,
+    }
+}
+
+pub(crate) struct MemoryDir {
+    fs: MemoryFs,
+    path: String,
+}
+
+#[async_trait]
+impl WasiDir for MemoryDir {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    async fn open_file(
+        &self,
+        _symlink_follow: bool,
+        path: &str,
+        oflags: OFlags,
+        _read: bool,
+        write: bool,
+        fdflags: FdFlags,
+    ) -> Result<OpenResult, Error> {
+        let path = join(&self.path, path)?;
+        let mut tree = self.fs.0.lock().unwrap();
+        let exclusive = oflags.contains(OFlags::CREATE | OFlags::EXCLUSIVE);
+        if tree.is_dir(&path) {
+            if exclusive {
+                return Err(Error::exist());
+            }
+            return Ok(OpenResult::Dir(Box::new(MemoryDir {
+                fs: self.fs.clone(),
+                path,
+            })));
+        }
+        if oflags.contains(OFlags::DIRECTORY) {
+            return Err(Error::not_dir());
+        }
+
+        match tree.files.get_mut(&path) {
+            Some(_) if exclusive => return Err(Error::exist()),
+            Some(data) => {
+                if oflags.contains(OFlags::TRUNCATE) && write {
+                    data.clear();
+                }
+            }
+            None if oflags.contains(OFlags::CREATE) => {
+                if !tree.is_dir(parent(&path)) {
+                    return Err(Error::not_found());
+                }
+                tree.files.insert(path.clone(), vec![]);
+            }
+            None => return Err(Error::not_found()),
+        }
+        Ok(OpenResult::File(Box::new(MemoryFile {
+            fs: self.fs.clone(),
+            path,
+            position: Mutex::new(0),
+            append: fdflags.contains(FdFlags::APPEND),
+        })))
+    }
+
+    async fn create_dir(&self, path: &str) -> Result<(), Error> {
+        let path = join(&self.path, path)?;
+        let mut tree = self.fs.0.lock().unwrap();
+        if tree.is_dir(&path) || tree.files.contains_key(&path) {
+            return Err(Error::exist());
+        }
+        if !tree.is_dir(parent(&path)) {
+            return Err(Error::not_found());
+        }
+        tree.dirs.insert(path);
+        Ok(())
+    }
+
+    async fn readdir(
+        &self,
+        cursor: ReaddirCursor,
+    ) -> Result<Box<dyn Iterator<Item = Result<ReaddirEntity, Error>> + Send>, Error> {
+        let children = self.fs.0.lock().unwrap().children(&self.path);
+        let entries = [
+            (".".to_string(), FileType::Directory),
+            ("..".to_string(), FileType::Directory),
+        ]
+        .into_iter()
+        .chain(children)
+        .enumerate()
+        .skip(u64::from(cursor) as usize)
+        .map(|(i, (name, filetype))| {
+            Ok(ReaddirEntity {
+                next: ReaddirCursor::from(i as u64 + 1),
+                inode: 0,
+                name,
+                filetype,
+            })
+        })
+        .collect::<Vec<_>>();
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    async fn remove_dir(&self, path: &str) -> Result<(), Error> {
+        let path = join(&self.path, path)?;
+        let mut tree = self.fs.0.lock().unwrap();
+        if path.is_empty() || !tree.is_dir(&path) {
+            return Err(Error::not_found());
+        }
+        if !tree.children(&path).is_empty() {
+            return Err(Errno::Notempty.into());
+        }
+        tree.dirs.remove(&path);
+        Ok(())
+    }
+
+    async fn unlink_file(&self, path: &str) -> Result<(), Error> {
+        let path = join(&self.path, path)?;
+        match self.fs.0.lock().unwrap().files.remove(&path) {
+            Some(_) => Ok(()),
+            None => Err(Error::not_found()),
+        }
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        Ok(stat(FileType::Directory, 0))
+    }
+
+    async fn get_path_filestat(
+        &self,
+        path: &str,
+        _follow_symlinks: bool,
+    ) -> Result<Filestat, Error> {
+        let path = join(&self.path, path)?;
+        let tree = self.fs.0.lock().unwrap();
+        if let Some(data) = tree.files.get(&path) {
+            Ok(stat(FileType::RegularFile, data.len() as u64))
+        } else if tree.is_dir(&path) {
+            Ok(stat(FileType::Directory, 0))
+        } else {
+            Err(Error::not_found())
+        }
+    }
+
+    async fn rename(
+        &self,
+        path: &str,
+        dest_dir: &dyn WasiDir,
+        dest_path: &str,
+    ) -> Result<(), Error> {
+        let dest_dir = match dest_dir.as_any().downcast_ref::<MemoryDir>() {
+            Some(d) if Arc::ptr_eq(&d.fs.0, &self.fs.0) => d,
+            _ => return Err(Errno::Xdev.into()),
+        };
+        let from = join(&self.path, path)?;
+        let to = join(&dest_dir.path, dest_path)?;
+        let mut tree = self.fs.0.lock().unwrap();
+        if !tree.is_dir(parent(&to)) {
+            return Err(Error::not_found());
+        }
+        if let Some(data) = tree.files.remove(&from) {
+            tree.files.insert(to, data);
+            return Ok(());
+        }
+        if from.is_empty() || !tree.is_dir(&from) {
+            return Err(Error::not_found());
+        }
+
+        // Move everything inside of the directory
+        let prefix = format!("{from}/");
+        let rename = |k: &String| match k.strip_prefix(&prefix) {
+            Some(rest) => format!("{to}/{rest}"),
+            None => to.clone(),
+        };
+        let files: Vec<_> = tree
+            .files
+            .keys()
+            .filter(|k| k.starts_with(&prefix))
+            .cloned()
+            .collect();
+        for k in files {
+            let data = tree.files.remove(&k).unwrap();
+            tree.files.insert(rename(&k), data);
+        }
+        let dirs: Vec<_> = tree
+            .dirs
+            .iter()
+            .filter(|k| **k == from || k.starts_with(&prefix))
+            .cloned()
+            .collect();
+        for k in dirs {
+            tree.dirs.remove(&k);
+            tree.dirs.insert(rename(&k));
+        }
+        Ok(())
+    }
+}
+
+struct MemoryFile {
+    fs: MemoryFs,
+    path: String,
+    position: Mutex<u64>,
+    append: bool,
+}
+
+impl MemoryFile {
+    fn with_data<T>(&self, f: impl FnOnce(&mut Vec<u8>) -> T) -> Result<T, Error> {
+        match self.fs.0.lock().unwrap().files.get_mut(&self.path) {
+            Some(data) => Ok(f(data)),
+            // The file was removed while it was open
+            None => Err(Error::badf()),
+        }
+    }
+
+    fn read_at(&self, bufs: &mut [std::io::IoSliceMut<'_>], offset: u64) -> Result<u64, Error> {
+        self.with_data(|data| {
+            let mut pos = (offset as usize).min(data.len());
+            let start = pos;
+            for buf in bufs.iter_mut() {
+                let n = buf.len().min(data.len() - pos);
+                buf[..n].copy_from_slice(&data[pos..pos + n]);
+                pos += n;
+            }
+            (pos - start) as u64
+        })
+    }
+
+    fn write_at(&self, bufs: &[std::io::IoSlice<'_>], offset: Option<u64>) -> Result<u64, Error> {
+        self.with_data(|data| {
+            let mut pos = offset.map(|x| x as usize).unwrap_or(data.len());
+            let start = pos;
+            for buf in bufs {
+                if data.len() < pos + buf.len() {
+                    data.resize(pos + buf.len(), 0);
+                }
+                data[pos..pos + buf.len()].copy_from_slice(buf);
+                pos += buf.len();
+            }
+            (pos - start) as u64
+        })
+    }
+}
+
+#[async_trait]
+impl WasiFile for MemoryFile {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::RegularFile)
+    }
+
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        if self.append {
+            Ok(FdFlags::APPEND)
+        } else {
+            Ok(FdFlags::empty())
+        }
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        let size = self.with_data(|data| data.len() as u64)?;
+        Ok(stat(FileType::RegularFile, size))
+    }
+
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        self.with_data(|data| data.resize(size as usize, 0))
+    }
+
+    async fn read_vectored<'a>(&self, bufs: &mut [std::io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let mut position = self.position.lock().unwrap();
+        let n = self.read_at(bufs, *position)?;
+        *position += n;
+        Ok(n)
+    }
+
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [std::io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.read_at(bufs, offset)
+    }
+
+    async fn write_vectored<'a>(&self, bufs: &[std::io::IoSlice<'a>]) -> Result<u64, Error> {
+        let mut position = self.position.lock().unwrap();
+        let offset = if self.append { None } else { Some(*position) };
+        let n = self.write_at(bufs, offset)?;
+        *position = match offset {
+            Some(x) => x + n,
+            None => self.with_data(|data| data.len() as u64)?,
+        };
+        Ok(n)
+    }
+
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[std::io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.write_at(bufs, Some(offset))
+    }
+
+    async fn seek(&self, pos: SeekFrom) -> Result<u64, Error> {
+        let mut position = self.position.lock().unwrap();
+        let len = self.with_data(|data| data.len() as i64)?;
+        let new = match pos {
+            SeekFrom::Start(x) => x as i64,
+            SeekFrom::Current(x) => *position as i64 + x,
+            SeekFrom::End(x) => len + x,
+        };
+        if new < 0 {
+            return Err(Error::invalid_argument());
+        }
+        *position = new as u64;
+        Ok(*position)
+    }
+
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        let position = *self.position.lock().unwrap();
+        self.with_data(|data| (data.len() as u64).saturating_sub(position))
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+/// Controls when the memory used by the Extism kernel is released, see
+/// `PluginBuilder::with_kernel_memory_policy`.
+///
+/// Kernel allocations are freed at the start of every call, but the kernel memory keeps the size it grew
+/// to for the largest call since Wasm memory can't shrink. Releasing it means the next call starts from a
+/// new instance, the same as after calling `_start`, so globals and guest memory are reset too.
+///
+/// ```no_run
+/// # use extism::*;
+/// # fn example(manifest: Manifest) -> Result<(), Error> {
+/// let plugin = PluginBuilder::new(manifest)
+///     .with_kernel_memory_policy(KernelMemoryPolicy::new().with_shrink_threshold(16 * 1024 * 1024))
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelMemoryPolicy {
+    /// Release the kernel memory after every call
+    pub reset_after_call: bool,
+
+    /// Release the kernel memory after a call when it has grown past this many bytes
+    pub shrink_threshold: Option<u64>,
+}
+
+impl KernelMemoryPolicy {
+    /// Create an empty `KernelMemoryPolicy`, kernel memory is only released when the plugin's store is
+    /// reset
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Release the kernel memory after every call
+    pub fn with_reset_after_call(mut self, reset: bool) -> Self {
+        self.reset_after_call = reset;
+        self
+    }
+
+    /// Release the kernel memory after a call when it's larger than `bytes`, this is the high-water mark
+    /// a long-lived plugin is allowed to keep between calls
+    pub fn with_shrink_threshold(mut self, bytes: u64) -> Self {
+        self.shrink_threshold = Some(bytes);
+        self
+    }
+
+    /// Returns `true` when kernel memory of `size` bytes should be released
+    pub(crate) fn should_release(&self, size: u64) -> bool {
+        self.reset_after_call || self.shrink_threshold.is_some_and(|x| size > x)
+    }
+}
@@ -0,0 +1,179 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Receives measurements from plugins configured using `PluginBuilder::with_metrics` and pools configured
+/// using `PoolBuilder::with_metrics`. Every method has an empty default implementation, so only the
+/// measurements that are needed have to be implemented. Methods are called on the thread making the call,
+/// so they should return quickly.
+///
+/// A single implementation can be shared between plugins and pools using an `Arc`.
+pub trait Metrics: Send + Sync + 'static {
+    /// A call into an exported function has started
+    fn call_started(&self, _plugin: uuid::Uuid, _function: &str) {}
+
+    /// A call into an exported function has returned, `success` is `false` if the call returned an error
+    fn call_finished(
+        &self,
+        _plugin: uuid::Uuid,
+        _function: &str,
+        _duration: Duration,
+        _success: bool,
+    ) {
+    }
+
+    /// A call was aborted by a Wasm trap, `call_finished` is also called
+    fn trap(&self, _plugin: uuid::Uuid, _function: &str) {}
+
+    /// A call was interrupted because it exceeded its timeout, `call_finished` is also called
+    fn timeout(&self, _plugin: uuid::Uuid, _function: &str) {}
+
+    /// A plugin's linear memory has grown, sizes are in bytes
+    fn memory_grown(&self, _plugin: uuid::Uuid, _from: usize, _to: usize) {}
+
+    /// `Pool::get` or `Pool::get_async` returned after waiting for `wait`, `success` is `false` if no
+    /// instance was available before the timeout
+    fn pool_checkout(&self, _wait: Duration, _success: bool) {}
+}
+
+impl<T: Metrics + ?Sized> Metrics for Arc<T> {
+    fn call_started(&self, plugin: uuid::Uuid, function: &str) {
+        (**self).call_started(plugin, function)
+    }
+
+    fn call_finished(&self, plugin: uuid::Uuid, function: &str, duration: Duration, success: bool) {
+        (**self).call_finished(plugin, function, duration, success)
+    }
+
+    fn trap(&self, plugin: uuid::Uuid, function: &str) {
+        (**self).trap(plugin, function)
+    }
+
+    fn timeout(&self, plugin: uuid::Uuid, function: &str) {
+        (**self).timeout(plugin, function)
+    }
+
+    fn memory_grown(&self, plugin: uuid::Uuid, from: usize, to: usize) {
+        (**self).memory_grown(plugin, from, to)
+    }
+
+    fn pool_checkout(&self, wait: Duration, success: bool) {
+        (**self).pool_checkout(wait, success)
+    }
+}
+
+/// `Metrics` implementation that records measurements using Prometheus collectors. Metrics are labelled by
+/// function name, plugin IDs aren't used as labels since they're unique for every instance.
+///
+/// ```no_run
+/// # use extism::*;
+/// # fn example(manifest: Manifest) -> Result<(), Error> {
+/// let registry = prometheus::Registry::new();
+/// let metrics = std::sync::Arc::new(PrometheusMetrics::new(&registry)?);
+/// let plugin = PluginBuilder::new(manifest).with_metrics(metrics.clone()).build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "prometheus")]
+#[derive(Clone)]
+pub struct PrometheusMetrics {
+    calls: prometheus::IntCounterVec,
+    call_duration: prometheus::HistogramVec,
+    traps: prometheus::IntCounterVec,
+    timeouts: prometheus::IntCounterVec,
+    memory_grown: prometheus::IntCounter,
+    pool_checkouts: prometheus::IntCounterVec,
+    pool_wait: prometheus::Histogram,
+}
+
+#[cfg(feature = "prometheus")]
+impl PrometheusMetrics {
+    /// Create the collectors and register them with `registry`
+    pub fn new(registry: &prometheus::Registry) -> Result<Self, crate::Error> {
+        use prometheus::{Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts};
+
+        let metrics = PrometheusMetrics {
+            calls: IntCounterVec::new(
+                Opts::new("extism_calls_total", "Number of plugin calls"),
+                &["function", "result"],
+            )?,
+            call_duration: HistogramVec::new(
+                HistogramOpts::new("extism_call_duration_seconds", "Duration of plugin calls"),
+                &["function"],
+            )?,
+            traps: IntCounterVec::new(
+                Opts::new("extism_traps_total", "Number of calls aborted by a trap"),
+                &["function"],
+            )?,
+            timeouts: IntCounterVec::new(
+                Opts::new("extism_timeouts_total", "Number of calls that timed out"),
+                &["function"],
+            )?,
+            memory_grown: IntCounter::new(
+                "extism_memory_grown_bytes_total",
+                "Number of bytes plugin memories have grown by",
+            )?,
+            pool_checkouts: IntCounterVec::new(
+                Opts::new("extism_pool_checkouts_total", "Number of pool checkouts"),
+                &["result"],
+            )?,
+            pool_wait: Histogram::with_opts(HistogramOpts::new(
+                "extism_pool_wait_seconds",
+                "Time spent waiting for a pooled instance",
+            ))?,
+        };
+        registry.register(Box::new(metrics.calls.clone()))?;
+        registry.register(Box::new(metrics.call_duration.clone()))?;
+        registry.register(Box::new(metrics.traps.clone()))?;
+        registry.register(Box::new(metrics.timeouts.clone()))?;
+        registry.register(Box::new(metrics.memory_grown.clone()))?;
+        registry.register(Box::new(metrics.pool_checkouts.clone()))?;
+        registry.register(Box::new(metrics.pool_wait.clone()))?;
+        Ok(metrics)
+    }
+}
+
+#[cfg(feature = "prometheus")]
+fn result_label(success: bool) -> &'static str {
+    if success {
+        "ok"
+    } else {
+        "error"
+    }
+}
+
+#[cfg(feature = "prometheus")]
+impl Metrics for PrometheusMetrics {
+    fn call_finished(
+        &self,
+        _plugin: uuid::Uuid,
+        function: &str,
+        duration: Duration,
+        success: bool,
+    ) {
+        self.calls
+            .with_label_values(&[function, result_label(success)])
+            .inc();
+        self.call_duration
+            .with_label_values(&[function])
+            .observe(duration.as_secs_f64());
+    }
+
+    fn trap(&self, _plugin: uuid::Uuid, function: &str) {
+        self.traps.with_label_values(&[function]).inc();
+    }
+
+    fn timeout(&self, _plugin: uuid::Uuid, function: &str) {
+        self.timeouts.with_label_values(&[function]).inc();
+    }
+
+    fn memory_grown(&self, _plugin: uuid::Uuid, from: usize, to: usize) {
+        self.memory_grown.inc_by(to.saturating_sub(from) as u64);
+    }
+
+    fn pool_checkout(&self, wait: Duration, success: bool) {
+        self.pool_checkouts
+            .with_label_values(&[result_label(success)])
+            .inc();
+        self.pool_wait.observe(wait.as_secs_f64());
+    }
+}
@@ -0,0 +1,215 @@
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use crate::pdk::args;
+use crate::*;
+
+/// Namespace for the publish/subscribe host functions
+pub const EXTISM_MSG_MODULE: &str = "extism:host/msg";
+
+/// A `MessageBroker` delivers messages for the `extism:host/msg` host functions, see
+/// `PluginBuilder::with_message_broker`
+///
+/// This can be implemented on top of NATS, Kafka or any other messaging system, `MemoryBroker` can be used
+/// for plugins running in the same process.
+pub trait MessageBroker: Send + Sync {
+    /// Publish a message to `topic`
+    fn publish(&self, topic: &str, payload: &[u8]) -> Result<(), Error>;
+
+    /// Subscribe to `topic`, only messages published after this call need to be delivered
+    fn subscribe(&self, topic: &str) -> Result<Box<dyn Subscription>, Error>;
+}
+
+impl<T: MessageBroker + ?Sized> MessageBroker for Arc<T> {
+    fn publish(&self, topic: &str, payload: &[u8]) -> Result<(), Error> {
+        (**self).publish(topic, payload)
+    }
+
+    fn subscribe(&self, topic: &str) -> Result<Box<dyn Subscription>, Error> {
+        (**self).subscribe(topic)
+    }
+}
+
+/// A subscription returned by `MessageBroker::subscribe`, dropping it unsubscribes
+pub trait Subscription: Send {
+    /// Get the next message, waiting for up to `timeout`. `Ok(None)` is returned if there are no messages.
+    fn poll(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, Error>;
+}
+
+impl Subscription for mpsc::Receiver<Vec<u8>> {
+    fn poll(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, Error> {
+        let res = if timeout.is_zero() {
+            self.try_recv().map_err(|e| e == mpsc::TryRecvError::Empty)
+        } else {
+            self.recv_timeout(timeout)
+                .map_err(|e| e == mpsc::RecvTimeoutError::Timeout)
+        };
+        match res {
+            Ok(x) => Ok(Some(x)),
+            Err(true) => Ok(None),
+            Err(false) => anyhow::bail!("subscription closed"),
+        }
+    }
+}
+
+type Subscribers = BTreeMap<String, Vec<mpsc::Sender<Vec<u8>>>>;
+
+/// An in-process `MessageBroker`, clones share the same subscribers
+#[derive(Default, Clone)]
+pub struct MemoryBroker {
+    subscribers: Arc<Mutex<Subscribers>>,
+}
+
+impl std::fmt::Debug for MemoryBroker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MemoryBroker")
+    }
+}
+
+impl MemoryBroker {
+    /// Create a new `MemoryBroker` with no subscribers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Subscribers> {
+        match self.subscribers.lock() {
+            Ok(x) => x,
+            Err(e) => e.into_inner(),
+        }
+    }
+}
+
+impl MessageBroker for MemoryBroker {
+    fn publish(&self, topic: &str, payload: &[u8]) -> Result<(), Error> {
+        let mut subscribers = self.lock();
+        if let Some(txs) = subscribers.get_mut(topic) {
+            // Subscriptions that have been dropped are removed
+            txs.retain(|tx| tx.send(payload.to_vec()).is_ok());
+            if txs.is_empty() {
+                subscribers.remove(topic);
+            }
+        }
+        Ok(())
+    }
+
+    fn subscribe(&self, topic: &str) -> Result<Box<dyn Subscription>, Error> {
+        let (tx, rx) = mpsc::channel();
+        self.lock().entry(topic.to_string()).or_default().push(tx);
+        Ok(Box::new(rx))
+    }
+}
+
+/// The broker and active subscriptions for a plugin
+pub(crate) struct MsgState {
+    broker: Arc<dyn MessageBroker>,
+    subscriptions: BTreeMap<u64, Box<dyn Subscription>>,
+    next_id: u64,
+}
+
+impl MsgState {
+    pub(crate) fn new(broker: Arc<dyn MessageBroker>) -> Self {
+        MsgState {
+            broker,
+            subscriptions: BTreeMap::new(),
+            next_id: 1,
+        }
+    }
+}
+
+// Host functions, these are linked into the `extism:host/msg` namespace when a `MessageBroker` is
+// configured
+
+fn state(data: &mut CurrentPlugin) -> Result<&mut MsgState, Error> {
+    match &mut data.msg {
+        Some(x) => Ok(x),
+        None => anyhow::bail!("no message broker is configured"),
+    }
+}
+
+fn read_bytes(data: &mut CurrentPlugin, offset: u64, what: &str) -> Result<Vec<u8>, Error> {
+    let handle = match data.memory_handle(offset) {
+        Some(h) => h,
+        None => anyhow::bail!("invalid handle offset for {what}: {offset}"),
+    };
+    let bytes = data.memory_bytes(handle)?.to_vec();
+    data.memory_free(handle)?;
+    Ok(bytes)
+}
+
+fn read_topic(data: &mut CurrentPlugin, offset: u64) -> Result<String, Error> {
+    Ok(String::from_utf8(read_bytes(data, offset, "topic")?)?)
+}
+
+/// Publish a message
+/// Params: i64 (topic offset), i64 (payload offset)
+/// Returns: none
+/// **Note**: this function takes ownership of the handles passed in
+/// the caller should not `free` these values
+pub(crate) fn publish(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    _output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let topic = read_topic(data, args!(input, 0, i64) as u64)?;
+    let payload = read_bytes(data, args!(input, 1, i64) as u64, "payload")?;
+    state(data)?.broker.publish(&topic, &payload)
+}
+
+/// Subscribe to a topic
+/// Params: i64 (topic offset)
+/// Returns: i64 (subscription ID)
+/// **Note**: this function takes ownership of the handle passed in
+/// the caller should not `free` this value
+pub(crate) fn subscribe(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let topic = read_topic(data, args!(input, 0, i64) as u64)?;
+    let state = state(data)?;
+    let sub = state.broker.subscribe(&topic)?;
+    let id = state.next_id;
+    state.next_id += 1;
+    state.subscriptions.insert(id, sub);
+    output[0] = Val::I64(id as i64);
+    Ok(())
+}
+
+/// Get the next message for a subscription
+/// Params: i64 (subscription ID), i64 (timeout in milliseconds, 0 to return immediately)
+/// Returns: i64 (payload offset), or 0 if there are no messages
+pub(crate) fn poll(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let id = args!(input, 0, i64) as u64;
+    let timeout = data.clamp_timeout(Duration::from_millis(args!(input, 1, i64).max(0) as u64));
+    let sub = match state(data)?.subscriptions.get_mut(&id) {
+        Some(x) => x,
+        None => anyhow::bail!("invalid subscription ID: {id}"),
+    };
+    output[0] = match sub.poll(timeout)? {
+        Some(payload) => Val::I64(data.memory_new(payload)?.offset() as i64),
+        None => Val::I64(0),
+    };
+    Ok(())
+}
+
+/// Remove a subscription
+/// Params: i64 (subscription ID)
+/// Returns: none
+pub(crate) fn unsubscribe(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    _output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let id = args!(input, 0, i64) as u64;
+    state(data)?.subscriptions.remove(&id);
+    Ok(())
+}
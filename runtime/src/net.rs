@@ -0,0 +1,195 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::pdk::args;
+use crate::*;
+
+/// Namespace for the TCP socket host functions, these are only available when the manifest has
+/// `allowed_sockets`
+pub const EXTISM_NET_MODULE: &str = "extism:host/net";
+
+/// Maximum number of sockets a plugin can have open at once
+const MAX_SOCKETS: usize = 64;
+
+/// Maximum number of bytes returned by a single `recv` call
+const MAX_RECV: usize = 1024 * 1024;
+
+/// Connections time out after this long, or when the call is out of time if that's sooner
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Socket timeouts can't be zero, so the shortest timeout is used once the call is out of time
+const MIN_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// Open sockets for a plugin
+#[derive(Default)]
+pub(crate) struct NetState {
+    sockets: BTreeMap<u64, TcpStream>,
+    next_id: u64,
+}
+
+/// Check `addr` (`host:port`) against the `allowed_sockets` patterns
+fn socket_allowed(allowed_sockets: &[String], addr: &str) -> bool {
+    let Some((host, port)) = addr.rsplit_once(':') else {
+        return false;
+    };
+    let matches = |pattern: &str, s: &str| match glob::Pattern::new(pattern) {
+        Ok(x) => x.matches(s),
+        Err(_) => pattern == s,
+    };
+    allowed_sockets.iter().any(|allowed| {
+        allowed
+            .rsplit_once(':')
+            .is_some_and(|(h, p)| matches(h, host) && matches(p, port))
+    })
+}
+
+// Host functions, these are linked into the `extism:host/net` namespace when the manifest has
+// `allowed_sockets`
+
+fn read_string(data: &mut CurrentPlugin, offset: u64, what: &str) -> Result<String, Error> {
+    let handle = match data.memory_handle(offset) {
+        Some(h) => h,
+        None => anyhow::bail!("invalid handle offset for {what}: {offset}"),
+    };
+    let s = data.memory_str(handle)?.to_string();
+    data.memory_free(handle)?;
+    Ok(s)
+}
+
+fn socket(data: &mut CurrentPlugin, id: u64) -> Result<&mut TcpStream, Error> {
+    let state = match &mut data.net {
+        Some(x) => x,
+        None => anyhow::bail!("sockets are not enabled"),
+    };
+    match state.sockets.get_mut(&id) {
+        Some(x) => Ok(x),
+        None => anyhow::bail!("invalid socket ID: {id}"),
+    }
+}
+
+/// Open a TCP connection
+/// Params: i64 (address offset, `host:port`)
+/// Returns: i64 (socket ID)
+/// **Note**: this function takes ownership of the handle passed in
+/// the caller should not `free` this value
+pub(crate) fn connect(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let addr = read_string(data, args!(input, 0, i64) as u64, "address")?;
+    let allowed = data.manifest.allowed_sockets.as_deref().unwrap_or_default();
+    if !socket_allowed(allowed, &addr) {
+        anyhow::bail!("connection to {addr} is not allowed");
+    }
+
+    let timeout = data.clamp_timeout(CONNECT_TIMEOUT).max(MIN_TIMEOUT);
+    let state = match &mut data.net {
+        Some(x) => x,
+        None => anyhow::bail!("sockets are not enabled"),
+    };
+    if state.sockets.len() >= MAX_SOCKETS {
+        anyhow::bail!("too many open sockets");
+    }
+
+    let mut last_err = None;
+    let mut stream = None;
+    for a in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&a, timeout) {
+            Ok(s) => {
+                stream = Some(s);
+                break;
+            }
+            Err(e) => last_err = Some(e),
+        }
+    }
+    let stream = match (stream, last_err) {
+        (Some(s), _) => s,
+        (None, Some(e)) => {
+            return Err(Error::from(e).context(format!("unable to connect to {addr}")))
+        }
+        (None, None) => anyhow::bail!("unable to resolve {addr}"),
+    };
+    stream.set_nodelay(true)?;
+
+    state.next_id += 1;
+    let id = state.next_id;
+    state.sockets.insert(id, stream);
+    output[0] = Val::I64(id as i64);
+    Ok(())
+}
+
+/// Write data to a socket
+/// Params: i64 (socket ID), i64 (data offset)
+/// Returns: i64 (number of bytes written)
+/// **Note**: this function takes ownership of the handle passed in
+/// the caller should not `free` this value
+pub(crate) fn send(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let id = args!(input, 0, i64) as u64;
+    let offset = args!(input, 1, i64) as u64;
+    let handle = match data.memory_handle(offset) {
+        Some(h) => h,
+        None => anyhow::bail!("invalid handle offset for socket data: {offset}"),
+    };
+    let bytes = data.memory_bytes(handle)?.to_vec();
+    data.memory_free(handle)?;
+    socket(data, id)?.write_all(&bytes)?;
+    output[0] = Val::I64(bytes.len() as i64);
+    Ok(())
+}
+
+/// Read data from a socket
+/// Params: i64 (socket ID), i64 (maximum number of bytes), i64 (timeout in milliseconds, 0 to return
+/// immediately)
+/// Returns: i64 (data offset), 0 if the timeout elapsed or -1 if the connection was closed
+pub(crate) fn recv(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let id = args!(input, 0, i64) as u64;
+    let max = (args!(input, 1, i64).max(1) as usize).min(MAX_RECV);
+    let timeout = args!(input, 2, i64).max(0) as u64;
+    let clamped = data.clamp_timeout(Duration::from_millis(timeout));
+
+    let stream = socket(data, id)?;
+    stream.set_nonblocking(timeout == 0)?;
+    if timeout > 0 {
+        stream.set_read_timeout(Some(clamped.max(MIN_TIMEOUT)))?;
+    }
+    let mut buf = vec![0; max];
+    output[0] = match stream.read(&mut buf) {
+        Ok(0) => Val::I64(-1),
+        Ok(n) => {
+            buf.truncate(n);
+            Val::I64(data.memory_new(buf)?.offset() as i64)
+        }
+        Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Val::I64(0),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(())
+}
+
+/// Close a socket
+/// Params: i64 (socket ID)
+/// Returns: none
+pub(crate) fn close(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    _output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let id = args!(input, 0, i64) as u64;
+    if let Some(state) = &mut data.net {
+        state.sockets.remove(&id);
+    }
+    Ok(())
+}
@@ -0,0 +1,537 @@
+use std::sync::{Arc, Mutex};
+
+use crate::pdk::args;
+use crate::*;
+
+/// Namespace for the object storage host functions
+pub const EXTISM_OBJECT_MODULE: &str = "extism:host/object";
+
+/// An `ObjectStore` provides blob storage for the `extism:host/object` host functions, see
+/// `PluginBuilder::with_object_store`
+///
+/// Keys passed to the store already include the plugin's prefix. The same store may be shared by many
+/// plugins, so implementations must be thread-safe.
+pub trait ObjectStore: Send + Sync {
+    /// Get the contents of an object, `Ok(None)` should be returned if the object doesn't exist
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Get up to `length` bytes of an object, starting at `offset`
+    fn get_range(&self, key: &str, offset: u64, length: u64) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.get(key)?.map(|data| {
+            let start = (offset as usize).min(data.len());
+            let end = start.saturating_add(length as usize).min(data.len());
+            data[start..end].to_vec()
+        }))
+    }
+
+    /// Get the size of an object in bytes
+    fn size(&self, key: &str) -> Result<Option<u64>, Error> {
+        Ok(self.get(key)?.map(|x| x.len() as u64))
+    }
+
+    /// Create or replace an object
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), Error>;
+
+    /// List all keys that start with `prefix`
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Error>;
+}
+
+impl<T: ObjectStore + ?Sized> ObjectStore for Arc<T> {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        (**self).get(key)
+    }
+
+    fn get_range(&self, key: &str, offset: u64, length: u64) -> Result<Option<Vec<u8>>, Error> {
+        (**self).get_range(key, offset, length)
+    }
+
+    fn size(&self, key: &str) -> Result<Option<u64>, Error> {
+        (**self).size(key)
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), Error> {
+        (**self).put(key, data)
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        (**self).list(prefix)
+    }
+}
+
+/// An in-memory `ObjectStore`, clones share the same data
+#[derive(Default, Clone)]
+pub struct MemoryObjectStore {
+    data: Arc<Mutex<BTreeMap<String, Arc<[u8]>>>>,
+}
+
+impl std::fmt::Debug for MemoryObjectStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MemoryObjectStore")
+    }
+}
+
+impl MemoryObjectStore {
+    /// Create a new, empty `MemoryObjectStore`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, Arc<[u8]>>> {
+        match self.data.lock() {
+            Ok(x) => x,
+            Err(e) => e.into_inner(),
+        }
+    }
+
+    // Objects are reference counted so ranged reads don't copy the whole object
+    fn object(&self, key: &str) -> Option<Arc<[u8]>> {
+        self.lock().get(key).cloned()
+    }
+}
+
+impl ObjectStore for MemoryObjectStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.object(key).map(|x| x.to_vec()))
+    }
+
+    fn get_range(&self, key: &str, offset: u64, length: u64) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.object(key).map(|data| {
+            let start = (offset as usize).min(data.len());
+            let end = start.saturating_add(length as usize).min(data.len());
+            data[start..end].to_vec()
+        }))
+    }
+
+    fn size(&self, key: &str) -> Result<Option<u64>, Error> {
+        Ok(self.object(key).map(|x| x.len() as u64))
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), Error> {
+        self.lock().insert(key.to_string(), data.into());
+        Ok(())
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        Ok(self
+            .lock()
+            .range(prefix.to_string()..)
+            .map(|(k, _)| k)
+            .take_while(|k| k.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+}
+
+// The max response size used when `S3ObjectStore::with_max_response_bytes` isn't set, this is the same
+// as the default for HTTP requests
+#[cfg(feature = "object-s3")]
+const DEFAULT_MAX_RESPONSE_BYTES: u64 = 1024 * 1024 * 50;
+
+/// An `ObjectStore` backed by an S3-compatible service, requests are signed using AWS Signature
+/// Version 4 and buckets are accessed using path-style URLs (`<endpoint>/<bucket>/<key>`)
+#[cfg(feature = "object-s3")]
+pub struct S3ObjectStore {
+    endpoint: url::Url,
+    region: String,
+    bucket: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+    max_response_bytes: u64,
+    agent: ureq::Agent,
+}
+
+#[cfg(feature = "object-s3")]
+impl S3ObjectStore {
+    /// Create a new `S3ObjectStore` for `bucket`, for example
+    /// `S3ObjectStore::new("https://s3.us-east-1.amazonaws.com", "us-east-1", "my-bucket")`
+    pub fn new(
+        endpoint: &str,
+        region: impl Into<String>,
+        bucket: impl Into<String>,
+    ) -> Result<Self, Error> {
+        let endpoint = url::Url::parse(endpoint)?;
+        if endpoint.host_str().is_none() {
+            anyhow::bail!("invalid S3 endpoint: {endpoint}");
+        }
+        Ok(S3ObjectStore {
+            endpoint,
+            region: region.into(),
+            bucket: bucket.into(),
+            access_key: String::new(),
+            secret_key: String::new(),
+            session_token: None,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            agent: ureq::Agent::new_with_defaults(),
+        })
+    }
+
+    /// Set the credentials used to sign requests
+    pub fn with_credentials(
+        mut self,
+        access_key: impl Into<String>,
+        secret_key: impl Into<String>,
+    ) -> Self {
+        self.access_key = access_key.into();
+        self.secret_key = secret_key.into();
+        self
+    }
+
+    /// Set the session token used with temporary credentials
+    pub fn with_session_token(mut self, token: impl Into<String>) -> Self {
+        self.session_token = Some(token.into());
+        self
+    }
+
+    /// Set the max size of a response, reading a larger object or listing fails. This defaults to 50MiB.
+    pub fn with_max_response_bytes(mut self, bytes: u64) -> Self {
+        self.max_response_bytes = bytes;
+        self
+    }
+
+    // Percent-encode a string as described in the SigV4 documentation, `/` is only kept for paths
+    fn encode(s: &str, path: bool) -> String {
+        let mut out = String::with_capacity(s.len());
+        for b in s.bytes() {
+            if b.is_ascii_alphanumeric()
+                || matches!(b, b'-' | b'_' | b'.' | b'~')
+                || (path && b == b'/')
+            {
+                out.push(b as char);
+            } else {
+                out.push_str(&format!("%{b:02X}"));
+            }
+        }
+        out
+    }
+
+    fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+        use hmac::Mac;
+        let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(key).expect("any key length");
+        mac.update(data.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    // Returns (`YYYYMMDD`, `YYYYMMDDTHHMMSSZ`)
+    fn timestamp() -> (String, String) {
+        let secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .unwrap_or_default();
+        let (days, rem) = (secs / 86400, secs % 86400);
+        let (year, month, day) = crate::scheduler::civil_from_days(days as i64);
+
+        let date = format!("{year:04}{month:02}{day:02}");
+        let time = format!(
+            "{date}T{:02}{:02}{:02}Z",
+            rem / 3600,
+            (rem % 3600) / 60,
+            rem % 60
+        );
+        (date, time)
+    }
+
+    fn request(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, String)],
+        body: &[u8],
+    ) -> Result<Option<ureq::http::Response<ureq::Body>>, Error> {
+        let path = format!(
+            "{}/{}/{}",
+            self.endpoint.path().trim_end_matches('/'),
+            Self::encode(&self.bucket, false),
+            Self::encode(key, true)
+        );
+        let mut query: Vec<_> = query
+            .iter()
+            .map(|(k, v)| (Self::encode(k, false), Self::encode(v, false)))
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let mut host = self.endpoint.host_str().unwrap_or_default().to_string();
+        if let Some(port) = self.endpoint.port() {
+            host.push_str(&format!(":{port}"));
+        }
+        let (date, timestamp) = Self::timestamp();
+        let payload_hash = crate::manifest::hex(&sha2::Sha256::digest(body));
+
+        let mut signed = vec![
+            ("host", host.clone()),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", timestamp.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            signed.push(("x-amz-security-token", token.clone()));
+        }
+        let canonical_headers: String = signed.iter().map(|(k, v)| format!("{k}:{v}\n")).collect();
+        let signed_headers = signed.iter().map(|(k, _)| *k).collect::<Vec<_>>().join(";");
+        let canonical_request = format!(
+            "{method}\n{path}\n{query}\n{canonical_headers}\n{signed_headers}\n{payload_hash}"
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+            crate::manifest::hex(&sha2::Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut signing_key = Self::hmac(format!("AWS4{}", self.secret_key).as_bytes(), &date);
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            signing_key = Self::hmac(&signing_key, part);
+        }
+        let signature = crate::manifest::hex(&Self::hmac(&signing_key, &string_to_sign));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.access_key
+        );
+
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+        url.set_query(if query.is_empty() { None } else { Some(&query) });
+        let mut req = ureq::http::Request::builder()
+            .method(method)
+            .uri(url.as_str())
+            .header("authorization", authorization);
+        for (k, v) in signed.iter().chain(headers.iter()) {
+            req = req.header(*k, v);
+        }
+
+        match self.agent.run(req.body(body)?) {
+            Ok(res) => Ok(Some(res)),
+            Err(ureq::Error::StatusCode(404)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn read_body(&self, res: ureq::http::Response<ureq::Body>) -> Result<Vec<u8>, Error> {
+        Ok(res
+            .into_body()
+            .into_with_config()
+            .limit(self.max_response_bytes)
+            .read_to_vec()?)
+    }
+
+    // Extract the text of every `<tag>` element, this is enough to parse `ListObjectsV2` responses
+    fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+        let open = format!("<{tag}>");
+        let close = format!("</{tag}>");
+        xml.split(open.as_str())
+            .skip(1)
+            .filter_map(|x| x.split(close.as_str()).next())
+            .map(|x| {
+                x.replace("&lt;", "<")
+                    .replace("&gt;", ">")
+                    .replace("&quot;", "\"")
+                    .replace("&apos;", "'")
+                    .replace("&amp;", "&")
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "object-s3")]
+use sha2::Digest;
+
+#[cfg(feature = "object-s3")]
+impl ObjectStore for S3ObjectStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        self.request("GET", key, &[], &[], &[])?
+            .map(|res| self.read_body(res))
+            .transpose()
+    }
+
+    fn get_range(&self, key: &str, offset: u64, length: u64) -> Result<Option<Vec<u8>>, Error> {
+        if length == 0 {
+            return Ok(self.size(key)?.map(|_| vec![]));
+        }
+        let range = format!("bytes={offset}-{}", offset.saturating_add(length - 1));
+        match self.request("GET", key, &[], &[("range", range)], &[]) {
+            Ok(res) => res.map(|res| self.read_body(res)).transpose(),
+            // The range starts after the end of the object
+            Err(e) if matches!(e.downcast_ref(), Some(ureq::Error::StatusCode(416))) => {
+                Ok(Some(vec![]))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn size(&self, key: &str) -> Result<Option<u64>, Error> {
+        let Some(res) = self.request("HEAD", key, &[], &[], &[])? else {
+            return Ok(None);
+        };
+        match res
+            .headers()
+            .get("content-length")
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.parse().ok())
+        {
+            Some(x) => Ok(Some(x)),
+            None => anyhow::bail!("missing content-length for S3 object: {key}"),
+        }
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), Error> {
+        self.request("PUT", key, &[], &[], data)?;
+        Ok(())
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let mut keys = vec![];
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token));
+            }
+            let Some(res) = self.request("GET", "", &query, &[], &[])? else {
+                anyhow::bail!("S3 bucket not found: {}", self.bucket);
+            };
+            let xml = String::from_utf8(self.read_body(res)?)?;
+            keys.extend(Self::xml_values(&xml, "Key"));
+            token = Self::xml_values(&xml, "NextContinuationToken").pop();
+            if token.is_none() {
+                break;
+            }
+        }
+        Ok(keys)
+    }
+}
+
+// Host functions, these are linked into the `extism:host/object` namespace when an `ObjectStore` is
+// configured
+
+// Keys are relative to the plugin's prefix, `.` and `..` segments are rejected because some services
+// normalize them
+fn check_key(key: &str, allow_empty: bool) -> Result<(), Error> {
+    if (key.is_empty() && !allow_empty)
+        || key.starts_with('/')
+        || key.split('/').any(|x| x == "." || x == "..")
+    {
+        anyhow::bail!("invalid object key: {key:?}");
+    }
+    Ok(())
+}
+
+fn read_key(
+    data: &mut CurrentPlugin,
+    offset: u64,
+    allow_empty: bool,
+) -> Result<(Arc<dyn ObjectStore>, String, String), Error> {
+    let handle = match data.memory_handle(offset) {
+        Some(h) => h,
+        None => anyhow::bail!("invalid handle offset for object key: {offset}"),
+    };
+    let key = data.memory_str(handle)?.to_string();
+    data.memory_free(handle)?;
+    check_key(&key, allow_empty)?;
+    match &data.objects {
+        Some((store, prefix)) => Ok((store.clone(), prefix.clone(), format!("{prefix}{key}"))),
+        None => anyhow::bail!("no object store is configured"),
+    }
+}
+
+/// Get an object
+/// Params: i64 (key offset)
+/// Returns: i64 (value offset), or 0 if the object doesn't exist
+/// **Note**: this function takes ownership of the handle passed in
+/// the caller should not `free` this value
+pub(crate) fn get(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let (store, _, key) = read_key(data, args!(input, 0, i64) as u64, false)?;
+    output[0] = match store.get(&key)? {
+        Some(value) => Val::I64(data.memory_new(value)?.offset() as i64),
+        None => Val::I64(0),
+    };
+    Ok(())
+}
+
+/// Get part of an object, this can be used to process large objects in chunks
+/// Params: i64 (key offset), i64 (start offset), i64 (maximum length)
+/// Returns: i64 (value offset), or 0 if the object doesn't exist
+/// **Note**: this function takes ownership of the handle passed in
+/// the caller should not `free` this value
+pub(crate) fn get_range(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let (store, _, key) = read_key(data, args!(input, 0, i64) as u64, false)?;
+    let offset = args!(input, 1, i64).max(0) as u64;
+    let length = args!(input, 2, i64).max(0) as u64;
+    output[0] = match store.get_range(&key, offset, length)? {
+        Some(value) => Val::I64(data.memory_new(value)?.offset() as i64),
+        None => Val::I64(0),
+    };
+    Ok(())
+}
+
+/// Get the size of an object
+/// Params: i64 (key offset)
+/// Returns: i64 (size in bytes), or -1 if the object doesn't exist
+/// **Note**: this function takes ownership of the handle passed in
+/// the caller should not `free` this value
+pub(crate) fn size(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let (store, _, key) = read_key(data, args!(input, 0, i64) as u64, false)?;
+    output[0] = Val::I64(store.size(&key)?.map(|x| x as i64).unwrap_or(-1));
+    Ok(())
+}
+
+/// Create or replace an object
+/// Params: i64 (key offset), i64 (value offset)
+/// Returns: none
+/// **Note**: this function takes ownership of the handles passed in
+/// the caller should not `free` these values
+pub(crate) fn put(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    _output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let (store, _, key) = read_key(data, args!(input, 0, i64) as u64, false)?;
+    let voffset = args!(input, 1, i64) as u64;
+    let handle = match data.memory_handle(voffset) {
+        Some(h) => h,
+        None => anyhow::bail!("invalid handle offset for object value: {voffset}"),
+    };
+    store.put(&key, data.memory_bytes(handle)?)?;
+    data.memory_free(handle)?;
+    Ok(())
+}
+
+/// List objects
+/// Params: i64 (prefix offset)
+/// Returns: i64 (offset of a JSON array containing the matching keys, relative to the plugin's prefix)
+/// **Note**: this function takes ownership of the handle passed in
+/// the caller should not `free` this value
+pub(crate) fn list(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let (store, prefix, full) = read_key(data, args!(input, 0, i64) as u64, true)?;
+    let keys = store.list(&full)?;
+    let keys: Vec<&str> = keys
+        .iter()
+        .filter_map(|k| k.strip_prefix(prefix.as_str()))
+        .collect();
+    let json = serde_json::to_vec(&keys)?;
+    output[0] = Val::I64(data.memory_new(json)?.offset() as i64);
+    Ok(())
+}
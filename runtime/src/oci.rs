@@ -0,0 +1,287 @@
+use std::path::PathBuf;
+
+use sha2::Digest;
+
+use crate::*;
+
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+
+// Layer media types used by `wasm-to-oci`, `oras` and the Wasm OCI artifact layout
+const WASM_LAYER_TYPES: &[&str] = &[
+    "application/vnd.wasm.content.layer.v1+wasm",
+    "application/vnd.module.wasm.content.layer.v1+wasm",
+    "application/wasm",
+];
+
+/// A parsed OCI artifact reference
+#[derive(Debug, PartialEq)]
+pub(crate) struct Reference {
+    registry: String,
+    repository: String,
+
+    /// A tag, or a digest when the reference contains `@`
+    reference: String,
+}
+
+impl Reference {
+    pub(crate) fn parse(s: &str) -> Result<Self, Error> {
+        let s = s.strip_prefix("oci://").unwrap_or(s);
+        let (name, reference) = match s.split_once('@') {
+            Some((name, digest)) => (name, digest.to_string()),
+            None => match s.rsplit_once(':') {
+                Some((name, tag)) if !tag.contains('/') => (name, tag.to_string()),
+                _ => (s, "latest".to_string()),
+            },
+        };
+        if name.is_empty() || reference.is_empty() {
+            anyhow::bail!("invalid OCI reference: {s}");
+        }
+
+        // The first component is only a registry if it looks like a host name, otherwise it's Docker Hub
+        let (registry, repository) = match name.split_once('/') {
+            Some((host, rest)) if host.contains(['.', ':']) || host == "localhost" => {
+                (host.to_string(), rest.to_string())
+            }
+            Some(_) => ("docker.io".to_string(), name.to_string()),
+            None => ("docker.io".to_string(), format!("library/{name}")),
+        };
+        let registry = if registry == "docker.io" {
+            "registry-1.docker.io".to_string()
+        } else {
+            registry
+        };
+        Ok(Reference {
+            registry,
+            repository,
+            reference,
+        })
+    }
+
+    fn digest(&self) -> Option<&str> {
+        self.reference
+            .starts_with("sha256:")
+            .then_some(self.reference.as_str())
+    }
+
+    // Registries running on the local machine are accessed using plain HTTP
+    fn url(&self, path: &str) -> String {
+        let host = self.registry.split(':').next().unwrap_or_default();
+        let scheme = if host == "localhost" || host == "127.0.0.1" {
+            "http"
+        } else {
+            "https"
+        };
+        format!("{scheme}://{}/v2/{}/{path}", self.registry, self.repository)
+    }
+}
+
+/// Artifacts are cached by digest in `EXTISM_OCI_CACHE_DIR`, or `extism/oci` in the user's cache directory
+fn cache_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("EXTISM_OCI_CACHE_DIR") {
+        return PathBuf::from(dir);
+    }
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|x| PathBuf::from(x).join(".cache")))
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .unwrap_or_else(std::env::temp_dir);
+    base.join("extism").join("oci")
+}
+
+fn cache_path(digest: &str) -> Option<PathBuf> {
+    let hex = digest.strip_prefix("sha256:")?;
+    if hex.len() != 64 || !hex.bytes().all(|x| x.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(cache_dir().join("sha256").join(hex))
+}
+
+// Cached entries are verified when they're read, so a corrupted entry is downloaded again
+fn cache_get(digest: &str) -> Option<Vec<u8>> {
+    let data = std::fs::read(cache_path(digest)?).ok()?;
+    verify_digest(digest, &data).ok()?;
+    Some(data)
+}
+
+fn cache_put(digest: &str, data: &[u8]) {
+    let Some(path) = cache_path(digest) else {
+        return;
+    };
+    let res = path
+        .parent()
+        .map(std::fs::create_dir_all)
+        .unwrap_or(Ok(()))
+        .and_then(|_| {
+            // Write to a temporary file first so other processes never read a partial entry
+            let tmp = path.with_extension(format!("tmp-{}", uuid::Uuid::new_v4()));
+            std::fs::write(&tmp, data)?;
+            std::fs::rename(&tmp, &path)
+        });
+    if let Err(e) = res {
+        warn!("unable to cache OCI artifact {digest}: {e}");
+    }
+}
+
+fn verify_digest(digest: &str, data: &[u8]) -> Result<(), Error> {
+    let Some(expected) = digest.strip_prefix("sha256:") else {
+        anyhow::bail!("unsupported OCI digest: {digest}");
+    };
+    let found = manifest::hex(&sha2::Sha256::digest(data));
+    if found != expected {
+        anyhow::bail!("OCI digest mismatch, found sha256:{found} but expected {digest}");
+    }
+    Ok(())
+}
+
+struct Client<'a> {
+    agent: ureq::Agent,
+    reference: &'a Reference,
+    token: Option<String>,
+}
+
+impl Client<'_> {
+    fn get(&mut self, path: &str, accept: &str) -> Result<Vec<u8>, Error> {
+        let url = self.reference.url(path);
+        let mut res = self.send(&url, accept)?;
+
+        // Request an anonymous token using the challenge returned by the registry, then retry
+        if res.status() == 401 && self.token.is_none() {
+            let challenge = res
+                .headers()
+                .get("www-authenticate")
+                .and_then(|x| x.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            self.token = Some(self.anonymous_token(&challenge)?);
+            res = self.send(&url, accept)?;
+        }
+        if !res.status().is_success() {
+            anyhow::bail!("unable to fetch OCI artifact from {url}: {}", res.status());
+        }
+        Ok(res
+            .into_body()
+            .into_with_config()
+            .limit(u64::MAX)
+            .read_to_vec()?)
+    }
+
+    fn send(&self, url: &str, accept: &str) -> Result<ureq::http::Response<ureq::Body>, Error> {
+        let mut req = ureq::http::Request::builder()
+            .method("GET")
+            .uri(url)
+            .header("accept", accept);
+        if let Some(token) = &self.token {
+            req = req.header("authorization", format!("Bearer {token}"));
+        }
+        Ok(self.agent.run(req.body(())?)?)
+    }
+
+    fn anonymous_token(&self, challenge: &str) -> Result<String, Error> {
+        let Some(params) = challenge.strip_prefix("Bearer ") else {
+            anyhow::bail!("OCI registry requires authentication, set a registry token");
+        };
+        let mut realm = None;
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        for param in params.split(',') {
+            let Some((k, v)) = param.trim().split_once('=') else {
+                continue;
+            };
+            let v = v.trim_matches('"');
+            match k {
+                "realm" => realm = Some(v),
+                "service" | "scope" => {
+                    query.append_pair(k, v);
+                }
+                _ => (),
+            }
+        }
+        let Some(realm) = realm else {
+            anyhow::bail!("OCI registry authentication challenge is missing a realm");
+        };
+        let url = format!("{realm}?{}", query.finish());
+        let req = ureq::http::Request::builder()
+            .method("GET")
+            .uri(&url)
+            .body(())?;
+        let res = self.agent.run(req)?;
+        if !res.status().is_success() {
+            anyhow::bail!("unable to get OCI registry token: {}", res.status());
+        }
+        let body: serde_json::Value = serde_json::from_slice(&res.into_body().read_to_vec()?)?;
+        match body
+            .get("token")
+            .or_else(|| body.get("access_token"))
+            .and_then(|x| x.as_str())
+        {
+            Some(token) => Ok(token.to_string()),
+            None => anyhow::bail!("OCI registry token response doesn't contain a token"),
+        }
+    }
+}
+
+// Find the digest of the Wasm layer, an artifact with a single layer is assumed to be Wasm
+fn wasm_layer(manifest: &[u8]) -> Result<String, Error> {
+    let manifest: serde_json::Value = serde_json::from_slice(manifest)?;
+    let layers = manifest
+        .get("layers")
+        .and_then(|x| x.as_array())
+        .map(|x| x.as_slice())
+        .unwrap_or_default();
+    let layer = layers
+        .iter()
+        .find(|x| {
+            x.get("mediaType")
+                .and_then(|x| x.as_str())
+                .is_some_and(|x| WASM_LAYER_TYPES.contains(&x))
+        })
+        .or(if layers.len() == 1 {
+            layers.first()
+        } else {
+            None
+        });
+    match layer.and_then(|x| x.get("digest")).and_then(|x| x.as_str()) {
+        Some(digest) => Ok(digest.to_string()),
+        None => anyhow::bail!("OCI artifact doesn't contain a Wasm layer"),
+    }
+}
+
+/// Pull the Wasm module referenced by `oci`, verifying it against its digest. Manifests pinned by digest
+/// and layers are cached, so a module pinned by digest can be loaded without contacting the registry.
+pub(crate) fn fetch(oci: &extism_manifest::OciReference) -> Result<Vec<u8>, Error> {
+    let reference = Reference::parse(&oci.oci)?;
+    let mut client = Client {
+        agent: ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .build()
+            .new_agent(),
+        reference: &reference,
+        token: oci.token.clone(),
+    };
+
+    let manifest = match reference.digest() {
+        Some(digest) => match cache_get(digest) {
+            Some(manifest) => manifest,
+            None => {
+                let manifest = client.get(&format!("manifests/{digest}"), MANIFEST_TYPES)?;
+                verify_digest(digest, &manifest)?;
+                cache_put(digest, &manifest);
+                manifest
+            }
+        },
+        None => client.get(
+            &format!("manifests/{}", reference.reference),
+            MANIFEST_TYPES,
+        )?,
+    };
+
+    let digest = wasm_layer(&manifest)?;
+    if let Some(data) = cache_get(&digest) {
+        trace!("loaded OCI artifact {} from cache", oci.oci);
+        return Ok(data);
+    }
+    let data = client.get(&format!("blobs/{digest}"), "*/*")?;
+    verify_digest(&digest, &data)?;
+    cache_put(&digest, &data);
+    Ok(data)
+}
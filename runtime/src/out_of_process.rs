@@ -0,0 +1,709 @@
+use std::ffi::OsString;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+use crate::plugin::MAIN_KEY;
+use crate::plugin_builder::PluginBuilderOptions;
+use crate::*;
+
+/// Environment variable used to pass the socket path to a helper process
+const HELPER_ENV: &str = "EXTISM_PLUGIN_HELPER";
+
+/// How long to wait for a helper process to connect after it's started
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Largest reply header accepted from a helper process
+const MAX_HEADER_BYTES: usize = 1024 * 1024;
+
+/// How often a read from the helper checks whether it's still running
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long past the manifest timeout to wait for a reply before the helper is killed, the helper
+/// enforces the timeout itself so this is only reached when it has stalled
+const TIMEOUT_GRACE: Duration = Duration::from_secs(5);
+
+/// Empty Wasm module, used as the main module of the local `Plugin` that calls are forwarded from
+pub(crate) const SHELL_MODULE: &[u8] = b"\0asm\x01\0\0\0";
+
+/// `OutOfProcess` runs a plugin in a separate helper process, see `PluginBuilder::with_out_of_process`.
+///
+/// The helper is a program that calls `OutOfProcess::serve_if_helper` at the start of `main`, usually
+/// the host program itself:
+///
+/// ```no_run
+/// # use extism::*;
+/// fn main() -> Result<(), Error> {
+///     extism::OutOfProcess::serve_if_helper();
+///
+///     let manifest = Manifest::new([Wasm::file("plugin.wasm")]);
+///     let plugin = PluginBuilder::new(manifest)
+///         .with_out_of_process(OutOfProcess::current_exe()?)
+///         .build()?;
+///     Ok(())
+/// }
+/// ```
+///
+/// Calls are forwarded over a unix socket, so a crash or memory blow-up while a plugin is running only
+/// affects the helper process. If the helper exits the call fails and a new helper is started for the
+/// next call, plugin state from the previous process is lost.
+///
+/// `ProcessLimits` can be used to apply OS-level memory and CPU limits to each helper process, for
+/// example when running untrusted third-party plugins.
+#[derive(Debug, Clone)]
+pub struct OutOfProcess {
+    program: PathBuf,
+    args: Vec<OsString>,
+    limits: ProcessLimits,
+    max_restarts: Option<usize>,
+}
+
+/// OS-level limits for helper processes, see `OutOfProcess::with_limits`
+///
+/// When `cgroup` is set a cgroup v2 group is created inside of it for each helper process, this requires
+/// write access to the directory (for example a delegated systemd slice) and is only available on Linux.
+/// Otherwise the limits are applied using `setrlimit`, where `cpus` isn't supported.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProcessLimits {
+    /// Maximum amount of memory in bytes, only private writable mappings are counted by `setrlimit`
+    pub memory_bytes: Option<u64>,
+
+    /// Maximum number of CPUs the helper can use, for example `0.5` for half of a CPU, this requires
+    /// `cgroup`
+    pub cpus: Option<f64>,
+
+    /// Maximum total CPU time, the helper is killed once it has been used
+    pub cpu_time: Option<Duration>,
+
+    /// cgroup v2 directory the helper cgroups are created in
+    pub cgroup: Option<PathBuf>,
+}
+
+impl ProcessLimits {
+    /// Create a new `ProcessLimits` without any limits
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the memory limit in bytes
+    pub fn with_memory_bytes(mut self, bytes: u64) -> Self {
+        self.memory_bytes = Some(bytes);
+        self
+    }
+
+    /// Set the number of CPUs the helper can use, this requires a cgroup
+    pub fn with_cpus(mut self, cpus: f64) -> Self {
+        self.cpus = Some(cpus);
+        self
+    }
+
+    /// Set the total CPU time the helper can use
+    pub fn with_cpu_time(mut self, cpu_time: Duration) -> Self {
+        self.cpu_time = Some(cpu_time);
+        self
+    }
+
+    /// Enforce the limits using cgroups created in `dir`
+    pub fn with_cgroup(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cgroup = Some(dir.into());
+        self
+    }
+}
+
+impl OutOfProcess {
+    /// Use `program` as the helper
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        OutOfProcess {
+            program: program.into(),
+            args: vec![],
+            limits: ProcessLimits::default(),
+            max_restarts: None,
+        }
+    }
+
+    /// Use the current executable as the helper
+    pub fn current_exe() -> Result<Self, Error> {
+        Ok(Self::new(std::env::current_exe()?))
+    }
+
+    /// Arguments passed to the helper program
+    pub fn with_args(mut self, args: impl IntoIterator<Item = impl Into<OsString>>) -> Self {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Apply OS-level limits to each helper process
+    pub fn with_limits(mut self, limits: ProcessLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Stop restarting the helper after it has exited unexpectedly `n` times, calls fail from then on.
+    /// By default the helper is always restarted
+    pub fn with_max_restarts(mut self, n: usize) -> Self {
+        self.max_restarts = Some(n);
+        self
+    }
+
+    /// When the current process was started as a helper this serves plugin calls and then exits,
+    /// otherwise it returns immediately
+    pub fn serve_if_helper() {
+        let Some(path) = std::env::var_os(HELPER_ENV) else {
+            return;
+        };
+        let code = match serve(PathBuf::from(path)) {
+            Ok(()) => 0,
+            Err(e) => {
+                error!("plugin helper failed: {e:?}");
+                1
+            }
+        };
+        std::process::exit(code)
+    }
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    /// Build the plugin, the payload contains the Wasm data when there's no manifest
+    Init {
+        manifest: Option<Box<Manifest>>,
+        wasi: bool,
+        fuel: Option<u64>,
+    },
+    /// Call a function, the payload contains the input
+    Call {
+        name: String,
+    },
+    Reset,
+}
+
+/// Reply to a `Request`, the payload contains the output of a call
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct Reply {
+    rc: i32,
+    error: Option<String>,
+    #[serde(default)]
+    exports: Vec<String>,
+}
+
+// Messages are a JSON header followed by a payload, each prefixed with their length as a u64
+
+fn write_message(
+    w: &mut impl Write,
+    header: &impl serde::Serialize,
+    payload: &[u8],
+) -> Result<(), Error> {
+    let header = serde_json::to_vec(header)?;
+    w.write_all(&(header.len() as u64).to_le_bytes())?;
+    w.write_all(&header)?;
+    w.write_all(&(payload.len() as u64).to_le_bytes())?;
+    w.write_all(payload)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Returns `None` if the stream was closed before the start of a message. The lengths are checked
+/// against `max_header` and `max_payload` before anything is allocated
+fn read_message<T: serde::de::DeserializeOwned>(
+    r: &mut impl Read,
+    max_header: usize,
+    max_payload: usize,
+) -> Result<Option<(T, Vec<u8>)>, Error> {
+    let mut len = [0; 8];
+    match r.read_exact(&mut len) {
+        Ok(()) => (),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let mut header = vec![0; read_len(len, max_header, "header")?];
+    r.read_exact(&mut header)?;
+    r.read_exact(&mut len)?;
+    let mut payload = vec![0; read_len(len, max_payload, "payload")?];
+    r.read_exact(&mut payload)?;
+    Ok(Some((serde_json::from_slice(&header)?, payload)))
+}
+
+fn read_len(len: [u8; 8], max: usize, what: &str) -> Result<usize, Error> {
+    let len = u64::from_le_bytes(len);
+    if len > max as u64 {
+        anyhow::bail!("message {what} is too large: {len} bytes, the limit is {max} bytes");
+    }
+    Ok(len as usize)
+}
+
+/// Reads from a helper process, failing once the helper has exited or `deadline` has passed instead
+/// of blocking forever
+struct HelperReader<'a> {
+    stream: &'a mut UnixStream,
+    child: &'a mut Child,
+    deadline: Option<Instant>,
+}
+
+impl Read for HelperReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            match self.stream.read(buf) {
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => (),
+                res => return res,
+            }
+            if let Some(status) = self.child.try_wait()? {
+                return Err(std::io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    format!("plugin helper exited: {status}"),
+                ));
+            }
+            if self.deadline.is_some_and(|x| Instant::now() >= x) {
+                return Err(std::io::Error::new(
+                    ErrorKind::TimedOut,
+                    "timed out waiting for plugin helper to reply",
+                ));
+            }
+        }
+    }
+}
+
+/// A cgroup for a single helper process, removed once the process has exited
+#[cfg(target_os = "linux")]
+struct Cgroup(PathBuf);
+
+#[cfg(target_os = "linux")]
+impl Cgroup {
+    fn new(parent: &std::path::Path, limits: &ProcessLimits) -> Result<Cgroup, Error> {
+        let path = parent.join(format!("extism-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&path)
+            .with_context(|| format!("unable to create cgroup in {parent:?}"))?;
+        let cgroup = Cgroup(path);
+        if let Some(bytes) = limits.memory_bytes {
+            cgroup.write("memory.max", &bytes.to_string())?;
+            // Swap isn't always enabled, in which case the file doesn't exist
+            let _ = cgroup.write("memory.swap.max", "0");
+        }
+        if let Some(cpus) = limits.cpus {
+            const PERIOD: u64 = 100_000;
+            let quota = ((cpus * PERIOD as f64) as u64).max(1000);
+            cgroup.write("cpu.max", &format!("{quota} {PERIOD}"))?;
+        }
+        Ok(cgroup)
+    }
+
+    fn write(&self, file: &str, value: &str) -> Result<(), Error> {
+        std::fs::write(self.0.join(file), value)
+            .with_context(|| format!("unable to set {file} for cgroup {:?}", self.0))
+    }
+
+    /// Returns `true` if a process in the cgroup was killed for using too much memory
+    fn oom_killed(&self) -> bool {
+        std::fs::read_to_string(self.0.join("memory.events"))
+            .map(|events| {
+                events.lines().any(|line| {
+                    line.strip_prefix("oom_kill ")
+                        .is_some_and(|n| n.trim() != "0")
+                })
+            })
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir(&self.0);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+struct Cgroup;
+
+#[cfg(not(target_os = "linux"))]
+impl Cgroup {
+    fn new(_parent: &std::path::Path, _limits: &ProcessLimits) -> Result<Cgroup, Error> {
+        anyhow::bail!("cgroups are only supported on Linux")
+    }
+
+    fn write(&self, _file: &str, _value: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn oom_killed(&self) -> bool {
+        false
+    }
+}
+
+/// Set the `setrlimit` limits in the helper process, this runs after `fork` so it only uses `libc`
+fn set_rlimits(memory_bytes: Option<u64>, cpu_time: Option<Duration>) -> std::io::Result<()> {
+    let set = |resource, value: u64| {
+        let limit = libc::rlimit {
+            rlim_cur: value as libc::rlim_t,
+            rlim_max: value as libc::rlim_t,
+        };
+        if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    };
+    if let Some(bytes) = memory_bytes {
+        set(libc::RLIMIT_DATA, bytes)?;
+    }
+    if let Some(cpu_time) = cpu_time {
+        set(libc::RLIMIT_CPU, cpu_time.as_secs().max(1))?;
+    }
+    Ok(())
+}
+
+struct Process {
+    child: Child,
+    stream: UnixStream,
+    // Dropped after `child` has been killed
+    cgroup: Option<Cgroup>,
+}
+
+impl Process {
+    fn spawn(spec: &OutOfProcess) -> Result<Process, Error> {
+        // The socket is created in a directory only the current user can access
+        let dir = std::env::temp_dir().join(format!("extism-{}", uuid::Uuid::new_v4()));
+        std::fs::DirBuilder::new().mode(0o700).create(&dir)?;
+        let res = Self::spawn_in(spec, &dir);
+        let _ = std::fs::remove_dir_all(&dir);
+        res
+    }
+
+    fn spawn_in(spec: &OutOfProcess, dir: &std::path::Path) -> Result<Process, Error> {
+        let path = dir.join("helper.sock");
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+        let limits = &spec.limits;
+        let cgroup = match &limits.cgroup {
+            Some(parent) => Some(Cgroup::new(parent, limits)?),
+            None if limits.cpus.is_some() => anyhow::bail!("CPU limits require a cgroup"),
+            None => None,
+        };
+
+        let mut command = Command::new(&spec.program);
+        command.args(&spec.args).env(HELPER_ENV, &path);
+        let memory_bytes = if cgroup.is_some() {
+            None
+        } else {
+            limits.memory_bytes
+        };
+        let cpu_time = limits.cpu_time;
+        if memory_bytes.is_some() || cpu_time.is_some() {
+            unsafe {
+                command.pre_exec(move || set_rlimits(memory_bytes, cpu_time));
+            }
+        }
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("unable to start plugin helper {:?}", spec.program))?;
+
+        // The plugin isn't loaded until the helper connects, so it's fine if the helper starts running
+        // before it's been moved to the cgroup
+        if let Some(cgroup) = &cgroup {
+            if let Err(e) = cgroup.write("cgroup.procs", &child.id().to_string()) {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(e);
+            }
+        }
+
+        let start = Instant::now();
+        let stream = loop {
+            match listener.accept() {
+                Ok((stream, _)) => break stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => (),
+                Err(e) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(e.into());
+                }
+            }
+            if let Some(status) = child.try_wait()? {
+                anyhow::bail!("plugin helper exited before connecting: {status}");
+            }
+            if start.elapsed() > CONNECT_TIMEOUT {
+                let _ = child.kill();
+                let _ = child.wait();
+                anyhow::bail!("timed out waiting for plugin helper to connect");
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        };
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        Ok(Process {
+            child,
+            stream,
+            cgroup,
+        })
+    }
+
+    /// Send a request and wait for the reply, at most until `timeout` has passed
+    fn request(
+        &mut self,
+        request: &Request,
+        payload: &[u8],
+        max_output: usize,
+        timeout: Option<Duration>,
+    ) -> Result<(Reply, Vec<u8>), Error> {
+        write_message(&mut self.stream, request, payload)?;
+        let mut reader = HelperReader {
+            stream: &mut self.stream,
+            child: &mut self.child,
+            deadline: timeout.map(|x| Instant::now() + x),
+        };
+        match read_message(&mut reader, MAX_HEADER_BYTES, max_output)? {
+            Some(x) => Ok(x),
+            None => anyhow::bail!("connection to plugin helper closed"),
+        }
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Everything a helper process needs to build the plugin
+#[derive(Clone)]
+pub(crate) struct HelperConfig {
+    spec: OutOfProcess,
+    init: Request,
+    data: std::sync::Arc<[u8]>,
+    max_output: usize,
+    timeout: Option<Duration>,
+}
+
+impl HelperConfig {
+    /// Only the manifest, WASI, allowed paths, memory limit and fuel settings are sent to the helper
+    pub(crate) fn new(
+        spec: OutOfProcess,
+        source: WasmInput<'_>,
+        options: &PluginBuilderOptions,
+    ) -> Result<HelperConfig, Error> {
+        if !options.functions.is_empty() {
+            anyhow::bail!("host functions aren't supported by out-of-process plugins");
+        }
+        let (mut manifest, mut data) = match source {
+            WasmInput::Data(data) => (None, data.into_owned()),
+            WasmInput::Manifest(m) => (Some(m), vec![]),
+            WasmInput::ManifestRef(m) => (Some(m.clone()), vec![]),
+        };
+        if options.modifies_manifest() {
+            let manifest = manifest
+                .get_or_insert_with(|| Manifest::new([Wasm::data(std::mem::take(&mut data))]));
+            options.apply_manifest(manifest);
+        }
+
+        // Output is read from the plugin's memory, so it can't be larger than the memory limit
+        let max_pages = manifest.as_ref().and_then(|m| m.memory.max_pages);
+        let max_output = max_pages.map_or(u32::MAX as u64, |x| x as u64 * 65536);
+        let timeout = manifest
+            .as_ref()
+            .and_then(|m| m.timeout_ms)
+            .map(|x| Duration::from_millis(x) + TIMEOUT_GRACE);
+        Ok(HelperConfig {
+            spec,
+            init: Request::Init {
+                manifest: manifest.map(Box::new),
+                wasi: options.wasi,
+                fuel: options.fuel,
+            },
+            data: data.into(),
+            max_output: max_output.min(usize::MAX as u64) as usize,
+            timeout,
+        })
+    }
+
+    /// Start a helper process and build the plugin
+    pub(crate) fn start(&self) -> Result<Helper, Error> {
+        let mut helper = Helper {
+            config: self.clone(),
+            process: None,
+            restarts: 0,
+            exports: vec![],
+            output: vec![],
+        };
+        helper.start()?;
+        Ok(helper)
+    }
+}
+
+/// The helper process a `Plugin` forwards calls to
+pub(crate) struct Helper {
+    config: HelperConfig,
+    process: Option<Process>,
+    restarts: usize,
+    exports: Vec<String>,
+    pub(crate) output: Vec<u8>,
+}
+
+impl Helper {
+    /// Start the helper process, if it isn't already running
+    fn start(&mut self) -> Result<(), Error> {
+        if self.process.is_none() {
+            if let Some(max) = self.config.spec.max_restarts {
+                if self.restarts > max {
+                    anyhow::bail!(
+                        "plugin helper exited unexpectedly {} times, it won't be restarted again",
+                        self.restarts
+                    );
+                }
+            }
+            let mut process = Process::spawn(&self.config.spec)?;
+            let (res, _) = process.request(&self.config.init, &self.config.data, 0, None)?;
+            if let Some(e) = res.error {
+                return Err(Error::msg(e));
+            }
+            self.exports = res.exports;
+            self.process = Some(process);
+        }
+        Ok(())
+    }
+
+    /// Process ID of the running helper
+    pub(crate) fn id(&self) -> Option<u32> {
+        self.process.as_ref().map(|x| x.child.id())
+    }
+
+    pub(crate) fn function_exists(&self, name: &str) -> bool {
+        self.exports.iter().any(|x| x == name)
+    }
+
+    fn request(&mut self, request: Request, payload: &[u8]) -> Result<Reply, Error> {
+        self.start()?;
+        let (max_output, timeout) = (self.config.max_output, self.config.timeout);
+        match self
+            .process
+            .as_mut()
+            .unwrap()
+            .request(&request, payload, max_output, timeout)
+        {
+            Ok((res, output)) => {
+                self.output = output;
+                Ok(res)
+            }
+            Err(e) => {
+                // The helper is restarted by the next request
+                let mut process = self.process.take().unwrap();
+                let _ = process.child.kill();
+                let status = process.child.wait()?;
+                self.restarts += 1;
+                if process.cgroup.as_ref().is_some_and(|x| x.oom_killed()) {
+                    return Err(e.context(format!(
+                        "plugin helper exceeded its memory limit and was killed: {status}"
+                    )));
+                }
+                Err(e.context(format!("plugin helper exited: {status}")))
+            }
+        }
+    }
+
+    pub(crate) fn call(&mut self, name: &str, input: &[u8]) -> Result<i32, (Error, i32)> {
+        let name = name.to_string();
+        let res = self
+            .request(Request::Call { name }, input)
+            .map_err(|e| (e, -1))?;
+        match res.error {
+            Some(e) => Err((Error::msg(e), res.rc)),
+            None => Ok(res.rc),
+        }
+    }
+
+    pub(crate) fn reset(&mut self) -> Result<(), Error> {
+        let res = self.request(Request::Reset, &[])?;
+        match res.error {
+            Some(e) => Err(Error::msg(e)),
+            None => Ok(()),
+        }
+    }
+}
+
+// Helper process side
+
+fn init(request: Request, data: Vec<u8>) -> Result<Plugin, Error> {
+    let Request::Init {
+        manifest,
+        wasi,
+        fuel,
+    } = request
+    else {
+        anyhow::bail!("expected an init request");
+    };
+    let source = match manifest {
+        Some(m) => WasmInput::Manifest(*m),
+        None => WasmInput::Data(data.into()),
+    };
+    let mut builder = PluginBuilder::new(source).with_wasi(wasi);
+    if let Some(fuel) = fuel {
+        builder = builder.with_fuel_limit(fuel);
+    }
+    builder.build()
+}
+
+fn serve(path: PathBuf) -> Result<(), Error> {
+    let mut stream = UnixStream::connect(path)?;
+    // Requests come from the host, which is trusted, the manifest, Wasm data and inputs aren't limited
+    let Some((request, data)) = read_message(&mut stream, usize::MAX, usize::MAX)? else {
+        return Ok(());
+    };
+    let mut plugin = match init(request, data) {
+        Ok(plugin) => {
+            let exports = plugin.modules[MAIN_KEY]
+                .exports()
+                .map(|x| x.name().to_string())
+                .filter(|x| plugin.function_exists(x))
+                .collect();
+            let res = Reply {
+                exports,
+                ..Default::default()
+            };
+            write_message(&mut stream, &res, &[])?;
+            plugin
+        }
+        Err(e) => {
+            let res = Reply {
+                rc: -1,
+                error: Some(e.to_string()),
+                ..Default::default()
+            };
+            return write_message(&mut stream, &res, &[]);
+        }
+    };
+
+    while let Some((request, input)) = read_message::<Request>(&mut stream, usize::MAX, usize::MAX)?
+    {
+        let mut res = Reply::default();
+        let mut output: &[u8] = &[];
+        match request {
+            Request::Call { name } => {
+                let lock = plugin.instance.clone();
+                let mut lock = lock.lock().unwrap();
+                match plugin.raw_call(&mut lock, &name, input, None::<()>) {
+                    Ok(rc) => {
+                        res.rc = rc;
+                        output = plugin.output()?;
+                    }
+                    Err((e, rc)) => {
+                        res.rc = rc;
+                        res.error = Some(e.to_string());
+                    }
+                }
+            }
+            Request::Reset => {
+                if let Err(e) = plugin.reset() {
+                    res.error = Some(e.to_string());
+                }
+            }
+            Request::Init { .. } => anyhow::bail!("plugin helper is already initialized"),
+        }
+        write_message(&mut stream, &res, output)?;
+    }
+    Ok(())
+}
@@ -16,6 +16,7 @@ macro_rules! args {
         )*)
     };
 }
+pub(crate) use args;
 
 /// Get a configuration value
 /// Params: i64 (offset)
@@ -38,14 +39,22 @@ pub(crate) fn config_get(
     let key = unsafe {
         std::str::from_utf8_unchecked(std::slice::from_raw_parts(key.as_ptr(), key.len()))
     };
-    let val = data.manifest.config.get(key);
-    let ptr = val.map(|x| (x.len(), x.as_ptr()));
+    let val = data.config.get(key);
+
+    // Values referencing a secret are resolved on each access and never stored in the manifest
+    if let Some(v) = val.as_ref().filter(|v| v.starts_with(SECRET_PREFIX)) {
+        let secret = data.secrets.resolve(v)?;
+        data.memory_free(handle)?;
+        output[0] = match secret {
+            Some(x) => Val::I64(data.memory_new(x)?.offset() as i64),
+            None => Val::I64(0),
+        };
+        return Ok(());
+    }
+
     data.memory_free(handle)?;
-    let mem = match ptr {
-        Some((len, ptr)) => {
-            let bytes = unsafe { std::slice::from_raw_parts(ptr, len) };
-            data.memory_new(bytes)?
-        }
+    let mem = match val {
+        Some(val) => data.memory_new(&*val)?,
         None => {
             output[0] = Val::I64(0);
             return Ok(());
@@ -77,6 +86,16 @@ pub(crate) fn var_get(
     let key = unsafe {
         std::str::from_utf8_unchecked(std::slice::from_raw_parts(key.as_ptr(), key.len()))
     };
+    if let Some(store) = data.var_store.clone() {
+        let val = store.get(key)?;
+        data.memory_free(handle)?;
+        output[0] = match val {
+            Some(val) => Val::I64(data.memory_new(val)?.offset() as i64),
+            None => Val::I64(0),
+        };
+        return Ok(());
+    }
+
     let val = data.vars.get(key);
     let ptr = val.map(|x| (x.len(), x.as_ptr()));
     data.memory_free(handle)?;
@@ -127,7 +146,12 @@ pub(crate) fn var_set(
 
     // Remove if the value offset is 0
     if voffset == 0 {
-        data.vars.remove(key);
+        match &data.var_store {
+            Some(store) => store.delete(key)?,
+            None => {
+                data.vars.remove(key);
+            }
+        }
         data.memory_free(key_handle)?;
         return Ok(());
     }
@@ -137,6 +161,22 @@ pub(crate) fn var_set(
         None => anyhow::bail!("invalid handle offset for var value: {voffset}"),
     };
 
+    if let Some(store) = data.var_store.clone() {
+        // The existing value for the key is replaced, so it doesn't count towards the limit
+        let existing = store
+            .get(key)?
+            .map(|x| key.len() + x.len())
+            .unwrap_or_default();
+        let size = store.size()?.saturating_sub(existing as u64) + key.len() as u64 + handle.length;
+        if size > data.manifest.memory.max_var_bytes.unwrap_or(1024 * 1024) {
+            return Err(Error::msg("Variable store is full"));
+        }
+        store.set(key, data.memory_bytes(handle)?)?;
+        data.memory_free(handle)?;
+        data.memory_free(key_handle)?;
+        return Ok(());
+    }
+
     let mut size = std::mem::size_of::<String>()
         + std::mem::size_of::<Vec<u8>>()
         + key.len()
@@ -153,17 +193,259 @@ pub(crate) fn var_set(
         return Err(Error::msg("Variable store is full"));
     }
 
-    let value = data.memory_bytes(handle)?.to_vec();
+    // Existing values are overwritten in place, so setting the same variables on every call reuses the
+    // key and the value's buffer
+    let (key, mut value) = data
+        .vars
+        .remove_entry(key)
+        .unwrap_or_else(|| (key.to_string(), Vec::new()));
+    value.clear();
+    value.extend_from_slice(data.memory_bytes(handle)?);
+    data.vars.insert(key, value);
 
     data.memory_free(handle)?;
     data.memory_free(key_handle)?;
 
-    // Insert the value from memory into the `vars` map
-    data.vars.insert(key.to_string(), value);
-
     Ok(())
 }
 
+/// Check `host` against `allowed_hosts`, which may contain glob patterns. No hosts are allowed when
+/// `allowed_hosts` is `None`.
+#[cfg(any(feature = "http", feature = "wasi-http", feature = "websocket"))]
+pub(crate) fn host_allowed(allowed_hosts: &Option<Vec<String>>, host: &str) -> bool {
+    let Some(allowed_hosts) = allowed_hosts else {
+        return false;
+    };
+    allowed_hosts.iter().any(|url| {
+        let pat = match glob::Pattern::new(url) {
+            Ok(x) => x,
+            Err(_) => return url == host,
+        };
+
+        pat.matches(host)
+    })
+}
+
+/// Get the `HttpPolicy` for `host`, an exact match takes precedence over the longest matching pattern
+#[cfg(feature = "http")]
+fn http_policy<'a>(
+    manifest: &'a extism_manifest::Manifest,
+    host: &str,
+) -> Option<&'a extism_manifest::HttpPolicy> {
+    let policies = manifest.http_policies.as_ref()?;
+    if let Some(policy) = policies.get(host) {
+        return Some(policy);
+    }
+    policies
+        .iter()
+        .filter(|(pattern, _)| glob::Pattern::new(pattern).is_ok_and(|x| x.matches(host)))
+        .max_by_key(|(pattern, _)| pattern.len())
+        .map(|(_, policy)| policy)
+}
+
+// The max response size used when neither the manifest or an `HttpPolicy` sets one
+#[cfg(feature = "http")]
+const DEFAULT_MAX_HTTP_RESPONSE_BYTES: u64 = 1024 * 1024 * 50;
+
+// The max number of `http_stream_open` responses a plugin can have open at the same time
+#[cfg(feature = "http")]
+const MAX_HTTP_STREAMS: usize = 16;
+
+/// The body of an HTTP response that hasn't been read yet, `max` is the max number of bytes that can be
+/// read
+#[cfg(feature = "http")]
+pub(crate) struct HttpBody {
+    reader: Box<dyn std::io::Read + Send>,
+    max: u64,
+}
+
+/// Responses opened using `http_stream_open`, they're closed when they've been read completely, by
+/// `http_stream_close` or when the next call starts
+#[cfg(feature = "http")]
+#[derive(Default)]
+pub(crate) struct HttpStreams {
+    next: u64,
+    open: BTreeMap<u64, (HttpBody, u64)>,
+}
+
+#[cfg(feature = "http")]
+impl HttpStreams {
+    pub(crate) fn clear(&mut self) {
+        self.open.clear();
+    }
+}
+
+// Send the request described by the `HttpRequest` and body handles in `input`, updating the status code and
+// response headers. `None` is returned when there's no response body to read.
+#[cfg(feature = "http")]
+fn send_http_request(data: &mut CurrentPlugin, input: &[Val]) -> Result<Option<HttpBody>, Error> {
+    let http_req_offset = args!(input, 0, i64) as u64;
+    data.http_headers.iter_mut().for_each(|x| x.clear());
+    data.http_status = 0;
+
+    let handle = match data.memory_handle(http_req_offset) {
+        Some(h) => h,
+        None => anyhow::bail!("invalid handle offset for http request: {http_req_offset}"),
+    };
+    let mut req: extism_manifest::HttpRequest = serde_json::from_slice(data.memory_bytes(handle)?)?;
+    data.memory_free(handle)?;
+
+    let body_offset = args!(input, 1, i64) as u64;
+
+    let url = match url::Url::parse(&req.url) {
+        Ok(u) => u,
+        Err(e) => return Err(Error::msg(format!("Invalid URL: {e:?}"))),
+    };
+    let host_str = url.host_str().unwrap_or_default();
+    if !host_allowed(&data.manifest.allowed_hosts, host_str) {
+        return Err(Error::msg(format!(
+            "HTTP request to {} is not allowed",
+            req.url
+        )));
+    }
+
+    // Apply the host's `HttpPolicy`
+    let policy = http_policy(&data.manifest, host_str).cloned();
+    if let Some(policy) = &policy {
+        let method = req.method.as_deref().unwrap_or("GET");
+        if !policy.method_allowed(method) {
+            anyhow::bail!(
+                "HTTP {} request to {} is not allowed",
+                method.to_uppercase(),
+                req.url
+            );
+        }
+        if let (Some(max), Some(handle)) =
+            (policy.max_request_bytes, data.memory_handle(body_offset))
+        {
+            if handle.length > max {
+                data.memory_free(handle)?;
+                anyhow::bail!(
+                    "HTTP request body exceeds the configured maximum number of bytes: {max}"
+                );
+            }
+        }
+        req.headers.retain(|k, _| !policy.strips_header(k));
+    }
+
+    let max = policy
+        .as_ref()
+        .and_then(|x| x.max_response_bytes)
+        .or(data.manifest.memory.max_http_response_bytes)
+        .unwrap_or(DEFAULT_MAX_HTTP_RESPONSE_BYTES);
+
+    if let Some(mock) = data.mock.clone() {
+        let body = match data.memory_handle(body_offset) {
+            Some(handle) => {
+                let body = data.memory_bytes(handle)?.to_vec();
+                data.memory_free(handle)?;
+                Some(body)
+            }
+            None => None,
+        };
+        let res = mock.http_request(&req, body)?;
+        if let Some(headers) = &mut data.http_headers {
+            headers.extend(
+                res.headers
+                    .into_iter()
+                    .filter(|(k, _)| !policy.as_ref().is_some_and(|p| p.strips_header(k))),
+            );
+        }
+        data.http_status = res.status;
+        return Ok(Some(HttpBody {
+            reader: Box::new(std::io::Cursor::new(res.body)),
+            max,
+        }));
+    }
+
+    let _span = span!(
+        "extism.http_request",
+        plugin = %data.id,
+        method = req.method.as_deref().unwrap_or("GET"),
+        url = %url,
+    );
+    let mut r = ureq::http::request::Builder::new()
+        .method(
+            req.method
+                .as_deref()
+                .unwrap_or("GET")
+                .to_uppercase()
+                .as_str(),
+        )
+        .uri(&req.url);
+
+    for (k, v) in req.headers.iter() {
+        r = r.header(k, v);
+    }
+
+    // Set HTTP timeout to respect the manifest timeout
+    let timeout = data.time_remaining();
+    let agent = data.http_agent.clone().unwrap_or_else(ureq::agent);
+    let res = if body_offset > 0 {
+        let handle = match data.memory_handle(body_offset) {
+            Some(h) => h,
+            None => {
+                anyhow::bail!("invalid handle offset for http request body: {http_req_offset}")
+            }
+        };
+        let io = data.io.clone();
+        let buf: &[u8] = data.memory_bytes(handle)?;
+        resources::IoCounters::add(&io.http_sent, buf.len() as u64);
+        let config = agent
+            .configure_request(r.body(buf)?)
+            .http_status_as_error(false);
+        let req = config.timeout_global(timeout).build();
+        agent.run(req)
+    } else {
+        let config = agent
+            .configure_request(r.body(())?)
+            .http_status_as_error(false);
+        let req = config.timeout_global(timeout).build();
+        agent.run(req)
+    };
+
+    if let Some(handle) = data.memory_handle(body_offset) {
+        data.memory_free(handle)?;
+    }
+
+    let reader = match res {
+        Ok(res) => {
+            if let Some(headers) = &mut data.http_headers {
+                for (name, h) in res.headers() {
+                    if policy
+                        .as_ref()
+                        .is_some_and(|p| p.strips_header(name.as_str()))
+                    {
+                        continue;
+                    }
+                    if let Ok(h) = h.to_str() {
+                        headers.insert(name.as_str().to_string(), h.to_string());
+                    }
+                }
+            }
+            data.http_status = res.status().as_u16();
+            Some(Box::new(res.into_body().into_reader()) as Box<dyn std::io::Read + Send>)
+        }
+        Err(e) => {
+            // Catch timeout and return
+            if let Some(d) = data.time_remaining() {
+                if matches!(e, ureq::Error::Timeout(_)) && d.as_nanos() == 0 {
+                    return Err(Error::new(Timeout));
+                }
+            }
+            let msg = e.to_string();
+            if let ureq::Error::StatusCode(res) = e {
+                data.http_status = res;
+                None
+            } else {
+                return Err(Error::msg(msg));
+            }
+        }
+    };
+
+    Ok(reader.map(|reader| HttpBody { reader, max }))
+}
+
 /// Make an HTTP request
 /// Params: i64 (offset to JSON encoded HttpRequest), i64 (offset to body or 0)
 /// Returns: i64 (offset)
@@ -176,9 +458,9 @@ pub(crate) fn http_request(
     output: &mut [Val],
 ) -> Result<(), Error> {
     let data: &mut CurrentPlugin = caller.data_mut();
-    let http_req_offset = args!(input, 0, i64) as u64;
     #[cfg(not(feature = "http"))]
     {
+        let http_req_offset = args!(input, 0, i64) as u64;
         let handle = match data.memory_handle(http_req_offset) {
             Some(h) => h,
             None => anyhow::bail!("http_request input is invalid: {http_req_offset}"),
@@ -194,139 +476,111 @@ pub(crate) fn http_request(
 
     #[cfg(feature = "http")]
     {
-        data.http_headers.iter_mut().for_each(|x| x.clear());
-        data.http_status = 0;
-
         use std::io::Read;
-        let handle = match data.memory_handle(http_req_offset) {
-            Some(h) => h,
-            None => anyhow::bail!("invalid handle offset for http request: {http_req_offset}"),
-        };
-        let req: extism_manifest::HttpRequest = serde_json::from_slice(data.memory_bytes(handle)?)?;
-        data.memory_free(handle)?;
-
-        let body_offset = args!(input, 1, i64) as u64;
-
-        let url = match url::Url::parse(&req.url) {
-            Ok(u) => u,
-            Err(e) => return Err(Error::msg(format!("Invalid URL: {e:?}"))),
-        };
-        let allowed_hosts = &data.manifest.allowed_hosts;
-        let host_str = url.host_str().unwrap_or_default();
-        let host_matches = if let Some(allowed_hosts) = allowed_hosts {
-            allowed_hosts.iter().any(|url| {
-                let pat = match glob::Pattern::new(url) {
-                    Ok(x) => x,
-                    Err(_) => return url == host_str,
-                };
-
-                pat.matches(host_str)
-            })
-        } else {
-            false
+        let Some(body) = send_http_request(data, input)? else {
+            output[0] = Val::I64(0);
+            return Ok(());
         };
 
-        if !host_matches {
-            return Err(Error::msg(format!(
-                "HTTP request to {} is not allowed",
-                req.url
-            )));
-        }
-
-        let mut r = ureq::http::request::Builder::new()
-            .method(
-                req.method
-                    .as_deref()
-                    .unwrap_or("GET")
-                    .to_uppercase()
-                    .as_str(),
+        let mut buf = Vec::new();
+        body.reader.take(body.max + 1).read_to_end(&mut buf)?;
+        resources::IoCounters::add(&data.io.http_received, buf.len() as u64);
+        if buf.len() as u64 > body.max {
+            anyhow::bail!(
+                "HTTP response exceeds the configured maximum number of bytes: {}",
+                body.max
             )
-            .uri(&req.url);
-
-        for (k, v) in req.headers.iter() {
-            r = r.header(k, v);
-        }
-
-        // Set HTTP timeout to respect the manifest timeout
-        let timeout = data.time_remaining();
-        let res = if body_offset > 0 {
-            let handle = match data.memory_handle(body_offset) {
-                Some(h) => h,
-                None => {
-                    anyhow::bail!("invalid handle offset for http request body: {http_req_offset}")
-                }
-            };
-            let buf: &[u8] = data.memory_bytes(handle)?;
-            let agent = ureq::agent();
-            let config = agent
-                .configure_request(r.body(buf)?)
-                .http_status_as_error(false);
-            let req = config.timeout_global(timeout).build();
-            ureq::run(req)
-        } else {
-            let agent = ureq::agent();
-            let config = agent
-                .configure_request(r.body(())?)
-                .http_status_as_error(false);
-            let req = config.timeout_global(timeout).build();
-            ureq::run(req)
-        };
-
-        if let Some(handle) = data.memory_handle(body_offset) {
-            data.memory_free(handle)?;
         }
 
-        let reader = match res {
-            Ok(res) => {
-                if let Some(headers) = &mut data.http_headers {
-                    for (name, h) in res.headers() {
-                        if let Ok(h) = h.to_str() {
-                            headers.insert(name.as_str().to_string(), h.to_string());
-                        }
-                    }
-                }
-                data.http_status = res.status().as_u16();
-                Some(res.into_body().into_reader())
-            }
-            Err(e) => {
-                // Catch timeout and return
-                if let Some(d) = data.time_remaining() {
-                    if matches!(e, ureq::Error::Timeout(_)) && d.as_nanos() == 0 {
-                        anyhow::bail!("timeout");
-                    }
-                }
-                let msg = e.to_string();
-                if let ureq::Error::StatusCode(res) = e {
-                    data.http_status = res;
-                    None
-                } else {
-                    return Err(Error::msg(msg));
-                }
-            }
-        };
+        let mem = data.memory_new(&buf)?;
+        output[0] = Val::I64(mem.offset() as i64);
+        Ok(())
+    }
+}
 
-        if let Some(reader) = reader {
-            let mut buf = Vec::new();
-            let max = if let Some(max) = &data.manifest.memory.max_http_response_bytes {
-                reader.take(*max + 1).read_to_end(&mut buf)?;
-                *max
-            } else {
-                reader.take(1024 * 1024 * 50 + 1).read_to_end(&mut buf)?;
-                1024 * 1024 * 50
-            };
+/// Make an HTTP request, like `http_request`, but the response body is read in chunks using
+/// `http_stream_read`. The status code and headers are available using `http_status_code` and
+/// `http_headers` as soon as this returns.
+/// Params: i64 (offset to JSON encoded HttpRequest), i64 (offset to body or 0)
+/// Returns: i64 (stream handle, or 0 if there's no response body)
+/// **Note**: this function takes ownership of the handles passed in
+/// the caller should not `free` these values
+#[cfg(feature = "http")]
+pub(crate) fn http_stream_open(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    if data.http_streams.open.len() >= MAX_HTTP_STREAMS {
+        anyhow::bail!("too many open HTTP streams, the limit is {MAX_HTTP_STREAMS}");
+    }
+    let Some(body) = send_http_request(data, input)? else {
+        output[0] = Val::I64(0);
+        return Ok(());
+    };
+    let streams = &mut data.http_streams;
+    streams.next += 1;
+    streams.open.insert(streams.next, (body, 0));
+    output[0] = Val::I64(streams.next as i64);
+    Ok(())
+}
 
-            if buf.len() > max as usize {
-                anyhow::bail!("HTTP response exceeds the configured maximum number of bytes: {max}")
-            }
+/// Read the next chunk of a response opened using `http_stream_open`, the stream is closed once the whole
+/// body has been read
+/// Params: i64 (stream handle), i64 (max number of bytes to read)
+/// Returns: i64 (offset), or 0 when the whole body has been read
+#[cfg(feature = "http")]
+pub(crate) fn http_stream_read(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    use std::io::Read;
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let id = args!(input, 0, i64) as u64;
+    let len = args!(input, 1, i64).max(1) as u64;
+    let Some((body, received)) = data.http_streams.open.get_mut(&id) else {
+        anyhow::bail!("invalid HTTP stream: {id}");
+    };
 
-            let mem = data.memory_new(&buf)?;
-            output[0] = Val::I64(mem.offset() as i64);
-        } else {
-            output[0] = Val::I64(0);
-        }
+    // Read one byte past the limit, so a body that's exactly `max` bytes long isn't an error
+    let len = len.min(body.max + 1 - *received);
+    let mut buf = Vec::new();
+    let res = (&mut body.reader).take(len).read_to_end(&mut buf);
+    *received += buf.len() as u64;
+    let (max, received) = (body.max, *received);
+    if res.is_err() || buf.is_empty() || received > max {
+        data.http_streams.open.remove(&id);
+    }
+    res?;
+    resources::IoCounters::add(&data.io.http_received, buf.len() as u64);
+    if received > max {
+        anyhow::bail!("HTTP response exceeds the configured maximum number of bytes: {max}")
+    }
 
-        Ok(())
+    if buf.is_empty() {
+        output[0] = Val::I64(0);
+    } else {
+        let mem = data.memory_new(&buf)?;
+        output[0] = Val::I64(mem.offset() as i64);
     }
+    Ok(())
+}
+
+/// Close a response opened using `http_stream_open` before it has been read completely
+/// Params: i64 (stream handle)
+/// Returns: none
+#[cfg(feature = "http")]
+pub(crate) fn http_stream_close(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    _output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let id = args!(input, 0, i64) as u64;
+    data.http_streams.open.remove(&id);
+    Ok(())
 }
 
 /// Get the status code of the last HTTP request
@@ -370,6 +624,28 @@ pub fn log(
 
     let offset = args!(input, 0, i64) as u64;
 
+    // Logs are always captured by a `MockHost`
+    #[cfg(not(target_family = "wasm"))]
+    if let Some(mock) = data.mock.clone() {
+        if let Some(handle) = data.memory_handle(offset) {
+            let message = data.memory_str(handle)?.to_string();
+            mock.log(level, data.redact(RedactTarget::Log { level }, &message));
+        }
+    }
+
+    if let Some(handler) = data.log_handler.clone() {
+        if let Some(handle) = data.memory_handle(offset) {
+            let message = data.memory_str(handle)?.to_string();
+            let message = data.redact(RedactTarget::Log { level }, &message);
+            handler.log(&LogRecord {
+                plugin: data.id,
+                function: &data.function,
+                level,
+                message: &message,
+            });
+        }
+    }
+
     // Check if the current log level should be logged
     let global_log_level = tracing::level_filters::LevelFilter::current();
     if global_log_level == tracing::level_filters::LevelFilter::OFF || level > global_log_level {
@@ -384,27 +660,33 @@ pub fn log(
         None => anyhow::bail!("invalid handle offset for log message: {offset}"),
     };
     let id = data.id.to_string();
-    let buf = data.memory_str(handle);
-
+    let function = data.function.clone();
+    let buf = data
+        .memory_str(handle)
+        .map(|buf| buf.to_string())
+        .map(|buf| data.redact(RedactTarget::Log { level }, &buf));
+
+    // The plugin ID, function name and level are attached as fields so plugin logs can be filtered
+    // and routed by subscribers
     match buf {
         Ok(buf) => match level {
             tracing::Level::ERROR => {
-                tracing::error!(plugin = id, "{}", buf)
+                tracing::error!(plugin = id, function, level = "error", "{}", buf)
             }
             tracing::Level::DEBUG => {
-                tracing::debug!(plugin = id, "{}", buf)
+                tracing::debug!(plugin = id, function, level = "debug", "{}", buf)
             }
             tracing::Level::WARN => {
-                tracing::warn!(plugin = id, "{}", buf)
+                tracing::warn!(plugin = id, function, level = "warn", "{}", buf)
             }
             tracing::Level::INFO => {
-                tracing::info!(plugin = id, "{}", buf)
+                tracing::info!(plugin = id, function, level = "info", "{}", buf)
             }
             tracing::Level::TRACE => {
-                tracing::trace!(plugin = id, "{}", buf)
+                tracing::trace!(plugin = id, function, level = "trace", "{}", buf)
             }
         },
-        Err(_) => tracing::error!(plugin = id, "unable to log message: {:?}", buf),
+        Err(_) => tracing::error!(plugin = id, function, "unable to log message: {:?}", buf),
     }
 
     data.memory_free(handle)?;
@@ -476,14 +758,45 @@ pub(crate) fn log_trace(
     log(tracing::Level::TRACE, caller, input, _output)
 }
 
+/// Get the number of bytes currently allocated by the Extism kernel
+/// Params: none
+/// Returns: i64 (bytes)
+pub(crate) fn memory_used(
+    mut caller: Caller<CurrentPlugin>,
+    _input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    output[0] = Val::I64(data.kernel_memory_used()? as i64);
+    Ok(())
+}
+
+/// Get the number of bytes that can still be allocated by the Extism kernel before memory runs out
+/// Params: none
+/// Returns: i64 (bytes)
+pub(crate) fn memory_remaining(
+    mut caller: Caller<CurrentPlugin>,
+    _input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    output[0] = Val::I64(data.kernel_memory_remaining()? as i64);
+    Ok(())
+}
+
 /// Get the log level
 /// Params: none
 /// Returns: i32 (log level)
 pub(crate) fn get_log_level(
-    mut _caller: Caller<CurrentPlugin>,
+    caller: Caller<CurrentPlugin>,
     _input: &[Val],
     output: &mut [Val],
 ) -> Result<(), Error> {
+    // A `LogHandler` receives messages at every level
+    if caller.data().log_handler.is_some() {
+        output[0] = Val::I32(log_level_to_int(tracing::Level::TRACE));
+        return Ok(());
+    }
     let level = tracing::level_filters::LevelFilter::current();
     if level == tracing::level_filters::LevelFilter::OFF {
         output[0] = Val::I32(i32::MAX)
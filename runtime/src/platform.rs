@@ -0,0 +1,100 @@
+//! Wasmtime's custom platform API for wasm32 hosts
+//!
+//! wasm32 has no virtual memory or signal handlers, so "mappings" are plain heap allocations,
+//! protections are ignored and memory images are never created. Wasmtime is configured to allocate
+//! linear memories without any reservation or guard pages on these hosts, see `CompiledPlugin::new`.
+
+use std::alloc::Layout;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Mutex;
+
+const PAGE_SIZE: usize = 4096;
+
+/// Size of each live allocation, by address
+static MAPPINGS: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+/// wasm32 hosts are single-threaded, so the TLS slot is a plain static
+static TLS: AtomicPtr<u8> = AtomicPtr::new(std::ptr::null_mut());
+
+fn layout(size: usize) -> Option<Layout> {
+    Layout::from_size_align(size.max(1), PAGE_SIZE).ok()
+}
+
+#[no_mangle]
+extern "C" fn wasmtime_mmap_new(size: usize, _prot_flags: u32, ret: &mut *mut u8) -> i32 {
+    let Some(layout) = layout(size) else {
+        return -1;
+    };
+    let ptr = unsafe { std::alloc::alloc_zeroed(layout) };
+    if ptr.is_null() {
+        return -1;
+    }
+    MAPPINGS.lock().unwrap().insert(ptr as usize, size);
+    *ret = ptr;
+    0
+}
+
+#[no_mangle]
+extern "C" fn wasmtime_mmap_remap(addr: *mut u8, size: usize, _prot_flags: u32) -> i32 {
+    // The region is already allocated, it only needs to look like a fresh mapping
+    unsafe { std::ptr::write_bytes(addr, 0, size) };
+    0
+}
+
+#[no_mangle]
+extern "C" fn wasmtime_munmap(ptr: *mut u8, size: usize) -> i32 {
+    let mut mappings = MAPPINGS.lock().unwrap();
+
+    // Only whole allocations can be freed, unmapping part of one leaves it allocated
+    if mappings.get(&(ptr as usize)) == Some(&size) {
+        mappings.remove(&(ptr as usize));
+        if let Some(layout) = layout(size) {
+            unsafe { std::alloc::dealloc(ptr, layout) };
+        }
+    }
+    0
+}
+
+#[no_mangle]
+extern "C" fn wasmtime_mprotect(_ptr: *mut u8, _size: usize, _prot_flags: u32) -> i32 {
+    0
+}
+
+#[no_mangle]
+extern "C" fn wasmtime_page_size() -> usize {
+    PAGE_SIZE
+}
+
+#[no_mangle]
+extern "C" fn wasmtime_memory_image_new(
+    _ptr: *const u8,
+    _len: usize,
+    ret: &mut *mut std::ffi::c_void,
+) -> i32 {
+    // No image, linear memories are initialized by copying data segments instead
+    *ret = std::ptr::null_mut();
+    0
+}
+
+#[no_mangle]
+extern "C" fn wasmtime_memory_image_map_at(
+    _image: *mut std::ffi::c_void,
+    _addr: *mut u8,
+    _len: usize,
+) -> i32 {
+    unreachable!("memory images are never created on wasm32 hosts")
+}
+
+#[no_mangle]
+extern "C" fn wasmtime_memory_image_free(_image: *mut std::ffi::c_void) {}
+
+#[no_mangle]
+extern "C" fn wasmtime_tls_get() -> *mut u8 {
+    TLS.load(Ordering::Relaxed)
+}
+
+#[no_mangle]
+extern "C" fn wasmtime_tls_set(ptr: *mut u8) {
+    TLS.store(ptr, Ordering::Relaxed)
+}
@@ -1,7 +1,6 @@
 use std::{
     any::Any,
     collections::{BTreeMap, BTreeSet},
-    path::PathBuf,
     sync::TryLockError,
 };
 
@@ -20,12 +19,53 @@ pub(crate) struct Output {
     pub(crate) length: u64,
     pub(crate) error_offset: u64,
     pub(crate) error_length: u64,
+    pub(crate) input_offset: u64,
+    pub(crate) input_length: u64,
 }
 
-/// A `CancelHandle` can be used to cancel a running plugin from another thread
+/// The error returned by a call that was stopped using `CancelHandle::cancel`, it can be detected
+/// using `err.is::<Cancelled>()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// The error returned by a call that tried to grow memory past the manifest's `memory.max_pages` or
+/// `PluginBuilder::with_memory_limit`, it can be detected using `err.is::<OutOfMemory>()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfMemory;
+
+impl std::fmt::Display for OutOfMemory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("oom")
+    }
+}
+
+impl std::error::Error for OutOfMemory {}
+
+impl OutOfMemory {
+    // Replace errors caused by the `MemoryLimiter` with `OutOfMemory`, without the context wasmtime adds
+    pub(crate) fn normalize(e: Error) -> Error {
+        if e.chain().any(|x| x.is::<OutOfMemory>()) {
+            Error::new(OutOfMemory)
+        } else {
+            e
+        }
+    }
+}
+
+/// A `CancelHandle` can be used to cancel a running plugin from another thread, the call is
+/// interrupted and returns a `Cancelled` error
 #[derive(Clone)]
 pub struct CancelHandle {
-    pub(crate) timer_tx: std::sync::mpsc::Sender<TimerAction>,
+    pub(crate) timer_tx: TimerTx,
+    pub(crate) cancelled: std::sync::Arc<std::sync::atomic::AtomicBool>,
     pub id: uuid::Uuid,
 }
 
@@ -35,6 +75,8 @@ unsafe impl Send for CancelHandle {}
 impl CancelHandle {
     pub fn cancel(&self) -> Result<(), Error> {
         debug!(plugin = self.id.to_string(), "sending cancel event");
+        self.cancelled
+            .store(true, std::sync::atomic::Ordering::SeqCst);
         self.timer_tx.send(TimerAction::Cancel { id: self.id })?;
         Ok(())
     }
@@ -44,10 +86,37 @@ impl CancelHandle {
 pub struct CompiledPlugin {
     pub(crate) manifest: Manifest,
     pub(crate) modules: BTreeMap<String, Module>,
+    pub(crate) hashes: BTreeMap<String, String>,
     pub(crate) options: PluginBuilderOptions,
     pub(crate) engine: wasmtime::Engine,
+    #[cfg(unix)]
+    pub(crate) helper: Option<out_of_process::HelperConfig>,
+    #[cfg(feature = "component-model")]
+    pub(crate) component: Option<component::ComponentConfig>,
+    #[cfg(feature = "wasi-nn")]
+    pub(crate) wasi_nn: Option<wasi_nn::Graphs>,
+
+    /// An empty plugin used in place of this one until the first call, when lazy instantiation is enabled
+    pub(crate) lazy: Option<Box<CompiledPlugin>>,
+
+    /// Linker with the host functions, created by the first plugin and shared by the rest
+    pub(crate) host_linker: std::sync::Arc<std::sync::Mutex<Option<std::sync::Arc<HostLinker>>>>,
+}
+
+/// A linker with the host functions and the names of those functions, indexed by the
+/// index each function passes to `CurrentPlugin::record_host_call`
+pub(crate) struct HostLinker {
+    pub(crate) linker: Linker<CurrentPlugin>,
+    pub(crate) names: std::sync::Arc<usage::HostFunctionNames>,
+
+    /// Host functions that weren't linked because the manifest doesn't grant their capability, keyed by
+    /// module and function name
+    pub(crate) ungranted: BTreeMap<(String, String), String>,
 }
 
+// An empty main module, used by plugins that haven't been instantiated yet
+const LAZY_MODULE: &[u8] = b"\0asm\x01\0\0\0";
+
 impl CompiledPlugin {
     /// Create a new pre-compiled plugin
     pub fn new(builder: PluginBuilder) -> Result<CompiledPlugin, Error> {
@@ -55,6 +124,11 @@ impl CompiledPlugin {
         config
             .epoch_interruption(true)
             .debug_info(builder.options.debug_options.debug_info)
+            .wasm_backtrace_details(if builder.options.debug_options.backtrace_details {
+                wasmtime::WasmBacktraceDetails::Enable
+            } else {
+                wasmtime::WasmBacktraceDetails::Environment
+            })
             .coredump_on_trap(builder.options.debug_options.coredump.is_some())
             .profiler(builder.options.debug_options.profiling_strategy)
             .wasm_tail_call(true)
@@ -69,29 +143,209 @@ impl CompiledPlugin {
             config.consume_fuel(true);
         }
 
-        config.cache(Self::configure_cache(&builder.options.cache_config)?);
+        if builder.options.wasm_threads {
+            if builder.options.deterministic {
+                anyhow::bail!("wasm threads can't be used with deterministic plugins");
+            }
+            if cfg!(target_family = "wasm") {
+                anyhow::bail!("wasm threads aren't supported on wasm32 hosts");
+            }
+            #[cfg(feature = "wasmtime-default-features")]
+            config.wasm_threads(true).shared_memory(true);
+            #[cfg(not(feature = "wasmtime-default-features"))]
+            anyhow::bail!("wasm threads require the `wasmtime-default-features` feature");
+        }
+
+        if builder.options.deterministic {
+            config
+                .cranelift_nan_canonicalization(true)
+                .relaxed_simd_deterministic(true);
+            #[cfg(feature = "wasmtime-default-features")]
+            config.wasm_threads(false);
+        }
+        builder.options.backend.configure(&mut config)?;
+
+        #[cfg(not(target_family = "wasm"))]
+        config.cache(Self::configure_cache(
+            &builder.options.cache_config,
+            builder.options.cache_dir.as_deref(),
+        )?);
+
+        #[cfg(not(target_family = "wasm"))]
+        if let Some(pooling) = &builder.options.pooling_allocator {
+            pooling.configure(&mut config);
+        }
+
+        // There's no virtual memory on wasm32 hosts, so linear memories are allocated up front and
+        // moved when they grow
+        #[cfg(target_family = "wasm")]
+        config
+            .memory_reservation(0)
+            .memory_guard_size(0)
+            .memory_reservation_for_growth(0);
 
         let engine = Engine::new(&config)?;
 
-        let (manifest, modules) = manifest::load(&engine, builder.source)?;
+        // Out-of-process plugins are loaded by the helper, the local plugin only has an empty main module
+        #[cfg(unix)]
+        let (source, helper) = match &builder.options.out_of_process {
+            Some(spec) => (
+                WasmInput::Data(out_of_process::SHELL_MODULE.into()),
+                Some(out_of_process::HelperConfig::new(
+                    spec.clone(),
+                    builder.source,
+                    &builder.options,
+                )?),
+            ),
+            None => (builder.source, None),
+        };
+        #[cfg(not(unix))]
+        let source = builder.source;
+
+        let manifest::Loaded {
+            mut manifest,
+            modules,
+            hashes,
+            #[cfg(feature = "component-model")]
+            component,
+        } = manifest::load(
+            &engine,
+            &builder.options.module_policy,
+            &builder.options.wasm_resolvers,
+            builder.options.precompiled,
+            builder.options.call_log.is_some(),
+            source,
+        )?;
+        builder.options.apply_manifest(&mut manifest);
+        if let Some(profile) = &builder.options.sandbox_profile {
+            profile.apply_manifest(&mut manifest);
+        }
+        if builder.options.deterministic {
+            let non_empty = |x: &Option<Vec<String>>| x.as_ref().is_some_and(|x| !x.is_empty());
+            if non_empty(&manifest.allowed_hosts) || non_empty(&manifest.allowed_sockets) {
+                anyhow::bail!(
+                    "deterministic plugins can't make network requests, the manifest has allowed_hosts or allowed_sockets set"
+                );
+            }
+        }
+
         if modules.len() <= 1 {
             anyhow::bail!("No wasm modules provided");
         } else if !modules.contains_key(MAIN_KEY) {
             anyhow::bail!("No main module provided");
         }
 
+        if let Some((_, namespace)) = &builder.options.sql_database {
+            sql::check_namespace(namespace)?;
+        }
+        threads::check(&modules, &manifest, builder.options.wasm_threads)?;
+
+        // Calls to component plugins are forwarded to the component, the main module is empty
+        #[cfg(feature = "component-model")]
+        let component = component
+            .map(|c| component::ComponentConfig::new(&engine, &c, &manifest, &builder.options))
+            .transpose()?;
+
+        #[cfg(feature = "wasi-nn")]
+        let wasi_nn = builder
+            .options
+            .wasi_nn
+            .as_ref()
+            .map(|x| x.load())
+            .transpose()?;
+
+        // Lazy plugins start out as an empty plugin without WASI, linked modules or the Extism kernel.
+        // Out-of-process plugins already load everything in the helper
+        #[cfg(unix)]
+        let lazy = builder.options.lazy && helper.is_none();
+        #[cfg(not(unix))]
+        let lazy = builder.options.lazy;
+        #[cfg(feature = "component-model")]
+        let lazy = lazy && component.is_none();
+        let lazy = if lazy {
+            let mut options = PluginBuilder::new(Manifest::default()).options;
+            options.event_bus = builder.options.event_bus.clone();
+            options.metrics = builder.options.metrics.clone();
+            options.hooks = builder.options.hooks.clone();
+            Some(Box::new(CompiledPlugin {
+                manifest: Manifest::default(),
+                modules: BTreeMap::from([(
+                    MAIN_KEY.to_string(),
+                    Module::new(&engine, LAZY_MODULE)?,
+                )]),
+                hashes: BTreeMap::new(),
+                options,
+                engine: engine.clone(),
+                #[cfg(unix)]
+                helper: None,
+                #[cfg(feature = "component-model")]
+                component: None,
+                #[cfg(feature = "wasi-nn")]
+                wasi_nn: None,
+                lazy: None,
+                host_linker: Default::default(),
+            }))
+        } else {
+            None
+        };
+
         Ok(CompiledPlugin {
             manifest,
             modules,
+            hashes,
             options: builder.options,
             engine,
+            #[cfg(unix)]
+            helper,
+            #[cfg(feature = "component-model")]
+            component,
+            #[cfg(feature = "wasi-nn")]
+            wasi_nn,
+            lazy,
+            host_linker: Default::default(),
         })
     }
 
+    /// Serialize a compiled module so it can be loaded later using
+    /// `PluginBuilder::with_precompiled_modules`, the main module is named `main`
+    pub fn serialize_module(&self, name: impl AsRef<str>) -> Result<Vec<u8>, Error> {
+        match self.modules.get(name.as_ref()) {
+            Some(module) if name.as_ref() != EXTISM_ENV_MODULE => Ok(module.serialize()?),
+            _ => anyhow::bail!("module not found: {}", name.as_ref()),
+        }
+    }
+
+    // Get the shared host linker, creating it if this is the first plugin
+    fn host_linker(&self, data: &CurrentPlugin) -> Result<std::sync::Arc<HostLinker>, Error> {
+        let mut cached = self.host_linker.lock().unwrap();
+        if let Some(linker) = &*cached {
+            return Ok(linker.clone());
+        }
+        let linker = std::sync::Arc::new(host_linker(
+            &self.engine,
+            data,
+            &self.options.functions,
+            self.options.wasi,
+        )?);
+        *cached = Some(linker.clone());
+        Ok(linker)
+    }
+
     /// Return optional cache according to builder options.
+    #[cfg(not(target_family = "wasm"))]
     fn configure_cache(
         cache_opt: &Option<Option<std::path::PathBuf>>,
+        cache_dir: Option<&std::path::Path>,
     ) -> Result<Option<wasmtime::Cache>, Error> {
+        // Explicit cache directory, wasmtime requires an absolute path
+        if let Some(dir) = cache_dir {
+            let dir = std::path::absolute(dir)?;
+            std::fs::create_dir_all(&dir)?;
+            let mut cache_config = wasmtime::CacheConfig::new();
+            cache_config.with_directory(dir);
+            return Ok(Some(wasmtime::Cache::new(cache_config)?));
+        }
+
         match cache_opt {
             // Explicitly disabled
             Some(None) => Ok(None),
@@ -110,7 +364,7 @@ impl CompiledPlugin {
                             // Disable cache if env var exists but is empty
                             Ok(None)
                         } else {
-                            let p = PathBuf::from(val);
+                            let p = std::path::PathBuf::from(val);
                             let cache = wasmtime::Cache::from_file(Some(p.as_path()))?;
                             Ok(Some(cache))
                         }
@@ -153,15 +407,58 @@ pub struct Plugin {
     /// actually cleaned up along with a `Store`
     instantiations: usize,
 
+    /// Set after the first instance is created, so `PluginHooks::on_instantiate` can report
+    /// re-instantiation
+    instantiated: bool,
+
+    /// Lifecycle callbacks set using `PluginBuilder::with_hooks`
+    pub(crate) hooks: Option<std::sync::Arc<dyn PluginHooks>>,
+
     /// Runtime determines any initialization functions needed
     /// to run a module
     pub(crate) runtime: Option<GuestRuntime>,
 
-    /// Keep a reference to the host functions
-    _functions: Vec<Function>,
+    /// OS-level restrictions applied while calling into the plugin
+    pub(crate) hardening: Option<Hardening>,
+
+    /// Resources used by the plugin, I/O counters are stored in `CurrentPlugin`
+    pub(crate) resources: ResourceReport,
+
+    /// Resources used by the most recent call
+    pub(crate) last_call: CallStats,
+
+    /// Log used to record each call
+    pub(crate) call_log: Option<CallLog>,
+
+    /// SHA-256 hash of the main module, only computed when the manifest, `ModulePolicy` or `CallLog`
+    /// needs it
+    pub(crate) main_hash: Option<String>,
+
+    /// When `true` plugin memory is overwritten with zeroes before it's released
+    pub(crate) zeroize: bool,
+
+    /// When the kernel memory is released after a call, see `PluginBuilder::with_kernel_memory_policy`
+    pub(crate) kernel_memory_policy: KernelMemoryPolicy,
+
+    /// Reports calls that exceed the stall threshold
+    pub(crate) watchdog: Option<watchdog::Watchdog>,
+
+    /// Sampling interval for the guest profiler
+    #[cfg(feature = "guest-profiler")]
+    pub(crate) profile_interval: Option<std::time::Duration>,
+
+    /// Profile collected during the most recent call
+    #[cfg(feature = "guest-profiler")]
+    pub(crate) profile: Option<Vec<u8>>,
+
+    /// WASI stdin for the next call, set by `Plugin::call_with_stdin`
+    pub(crate) call_stdin: Option<Vec<u8>>,
+
+    /// Linker with the host functions, cloned for each new store
+    pub(crate) host_linker: std::sync::Arc<HostLinker>,
 
     /// Communication with the timer thread
-    pub(crate) timer_tx: std::sync::mpsc::Sender<TimerAction>,
+    pub(crate) timer_tx: TimerTx,
 
     /// Information that gets populated after a call
     pub(crate) output: Output,
@@ -170,13 +467,41 @@ pub struct Plugin {
     /// in this case we need to re-initialize the entire module.
     pub(crate) store_needs_reset: bool,
 
+    /// Input written to plugin memory by a `StreamingCall`, used by the next call instead of encoding the input
+    pub(crate) streamed_input: Option<MemoryHandle>,
+
     pub(crate) debug_options: DebugOptions,
 
     pub(crate) error_msg: Option<Vec<u8>>,
 
+    /// `ErrorKind::code` of the error in `error_msg`, used by the C API
+    pub(crate) error_code: i32,
+
     pub(crate) fuel: Option<u64>,
 
+    /// Fuel given to the most recent call, this differs from `fuel` after `Plugin::call_with_fuel`
+    pub(crate) call_fuel: Option<u64>,
+
     pub(crate) host_context: Rooted<ExternRef>,
+
+    /// Helper process that calls are forwarded to, for out-of-process plugins
+    #[cfg(unix)]
+    pub(crate) helper: Option<out_of_process::Helper>,
+
+    /// Component that calls are forwarded to, when the main module is a component
+    #[cfg(feature = "component-model")]
+    pub(crate) component: Option<component::ComponentPlugin>,
+
+    /// The plugin that's instantiated by the first call, when lazy instantiation is enabled
+    pub(crate) pending: Option<CompiledPlugin>,
+
+    /// When `true` the main instance's memory is restored from `snapshot` by `Plugin::reset`
+    pub(crate) cow_reset: bool,
+    pub(crate) snapshot: Option<snapshot::MemorySnapshot>,
+
+    /// Set by `Plugin::set_deadline`, calls are interrupted at this time or when the manifest timeout
+    /// expires, whichever is first
+    pub(crate) deadline: Option<std::time::Instant>,
 }
 
 unsafe impl Send for Plugin {}
@@ -188,6 +513,23 @@ impl std::fmt::Debug for Plugin {
     }
 }
 
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        if let Some(threads) = self.current_plugin().threads.clone() {
+            threads.stop(self.store.engine());
+        }
+        if self.zeroize {
+            let instance = match self.instance.lock() {
+                Ok(x) => *x,
+                Err(e) => *e.into_inner(),
+            };
+            self.zeroize_memory(instance);
+        }
+        let id = self.id;
+        self.emit(|| PluginEvent::Dropped { plugin: id });
+    }
+}
+
 impl Internal for Plugin {
     fn store(&self) -> &Store<CurrentPlugin> {
         &self.store
@@ -219,6 +561,7 @@ pub(crate) fn profiling_strategy() -> ProfilingStrategy {
 ///
 /// Types that implement `Into<WasmInput>` can be passed directly into `Plugin::new`
 #[derive(Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum WasmInput<'a> {
     /// Raw Wasm module
     Data(std::borrow::Cow<'a, [u8]>),
@@ -228,6 +571,17 @@ pub enum WasmInput<'a> {
     ManifestRef(&'a Manifest),
 }
 
+impl WasmInput<'_> {
+    /// Copy any borrowed data so the input can outlive it
+    pub fn into_owned(self) -> WasmInput<'static> {
+        match self {
+            WasmInput::Data(data) => WasmInput::Data(std::borrow::Cow::Owned(data.into_owned())),
+            WasmInput::Manifest(m) => WasmInput::Manifest(m),
+            WasmInput::ManifestRef(m) => WasmInput::Manifest(m.clone()),
+        }
+    }
+}
+
 impl From<Manifest> for WasmInput<'_> {
     fn from(value: Manifest) -> Self {
         WasmInput::Manifest(value)
@@ -270,10 +624,13 @@ impl<'a> From<&'a Vec<u8>> for WasmInput<'a> {
     }
 }
 
+// Link `module` as `name`, linking the modules it imports from first. `path` holds the modules that are
+// currently being linked, so circular imports are reported instead of recursing forever.
 fn add_module<T: 'static>(
     store: &mut Store<T>,
     linker: &mut Linker<T>,
     linked: &mut BTreeSet<String>,
+    path: &mut Vec<String>,
     modules: &BTreeMap<String, Module>,
     name: String,
     module: &Module,
@@ -281,6 +638,7 @@ fn add_module<T: 'static>(
     if linked.contains(&name) {
         return Ok(());
     }
+    path.push(name.clone());
 
     for import in module.imports() {
         let module = import.module();
@@ -289,52 +647,66 @@ fn add_module<T: 'static>(
             anyhow::bail!("linked modules cannot access non-function exports of extism kernel");
         }
 
-        if !linked.contains(import.module()) {
-            if let Some(m) = modules.get(import.module()) {
-                add_module(
-                    store,
-                    linker,
-                    linked,
-                    modules,
-                    import.module().to_string(),
-                    m,
-                )?;
+        if linked.contains(module) {
+            continue;
+        }
+        if let Some(m) = modules.get(module) {
+            if path.iter().any(|x| x == module) {
+                anyhow::bail!(
+                    "circular import between modules: {} -> {module}",
+                    path.join(" -> ")
+                );
+            }
+            if m.get_export(import.name()).is_none() {
+                anyhow::bail!(
+                    "module {name} imports {module}::{}, which isn't exported by {module}",
+                    import.name()
+                );
             }
+            add_module(store, linker, linked, path, modules, module.to_string(), m)?;
         }
     }
 
     linker.module(store, name.as_str(), module)?;
+    path.pop();
     linked.insert(name);
 
     Ok(())
 }
 
-#[allow(clippy::type_complexity)]
-fn relink(
+// Define the Extism PDK, WASI and host functions. These don't depend on a store, so the linker is
+// created once for each `CompiledPlugin` and cloned by `relink`. Each host function is given an index
+// up front, which is used to track calls instead of the function name.
+fn host_linker(
     engine: &Engine,
-    mut store: &mut Store<CurrentPlugin>,
+    data: &CurrentPlugin,
     imports: &[Function],
-    modules: &BTreeMap<String, Module>,
     with_wasi: bool,
-) -> Result<
-    (
-        InstancePre<CurrentPlugin>,
-        Linker<CurrentPlugin>,
-        Rooted<ExternRef>,
-    ),
-    Error,
-> {
+) -> Result<HostLinker, Error> {
     let mut linker = Linker::new(engine);
     linker.allow_shadowing(true);
+    let mut names = usage::HostFunctionNames::default();
 
     // Define PDK functions
     use wasmtime::error::ToWasmtimeResult as _;
     macro_rules! add_funcs {
-            ($($name:ident($($args:expr),*) $(-> $($r:expr),*)?);* $(;)?) => {
+            ($ns:expr, $m:ident, $prefix:literal; $($name:ident($($args:expr),*) $(-> $($r:expr),*)?);* $(;)?) => {
                 $(
                     let t = FuncType::new(&engine, [$($args),*], [$($($r),*)?]);
-                    linker.func_new(EXTISM_ENV_MODULE, stringify!($name), t, |c, i, o| {
-                        pdk::$name(c, i, o).to_wasmtime_result()
+                    let index = names.add(concat!($prefix, stringify!($name)));
+                    linker.func_new($ns, stringify!($name), t, move |mut c: Caller<CurrentPlugin>, i, o| {
+                        let _span = span!(
+                            "extism.host_function",
+                            plugin = %c.data().id,
+                            function = concat!($prefix, stringify!($name))
+                        );
+                        // The caller is moved into the host function, the plugin data outlives the call
+                        let data: *mut CurrentPlugin = c.data_mut();
+                        let res = c.data_mut()
+                            .record_host_call(index, i)
+                            .and_then(|_| $m::$name(c, i, o));
+                        unsafe { (*data).host_stats.end() };
+                        res.to_wasmtime_result()
                     })?;
                 )*
             };
@@ -343,6 +715,7 @@ fn relink(
     // Add builtins
     use wasmtime::ValType::*;
     add_funcs!(
+        EXTISM_ENV_MODULE, pdk, "";
         config_get(I64) -> I64;
         var_get(I64) -> I64;
         var_set(I64, I64);
@@ -355,14 +728,162 @@ fn relink(
         log_error(I64);
         log_trace(I64);
         get_log_level() -> I32;
+        memory_used() -> I64;
+        memory_remaining() -> I64;
     );
 
+    #[cfg(feature = "http")]
+    {
+        add_funcs!(
+            EXTISM_ENV_MODULE, pdk, "";
+            http_stream_open(I64, I64) -> I64;
+            http_stream_read(I64, I64) -> I64;
+            http_stream_close(I64);
+        );
+    }
+
+    // Key/value functions are recorded as `kv_get`, `kv_set`, ... for `HostFunctionLimits` and usage tracking
+    if data.kv.is_some() {
+        add_funcs!(
+            EXTISM_KV_MODULE, kv, "kv_";
+            get(I64) -> I64;
+            set(I64, I64, I64);
+            delete(I64) -> I32;
+            list(I64) -> I64;
+        );
+    }
+
+    if data.sql.is_some() {
+        add_funcs!(
+            EXTISM_SQL_MODULE, sql, "sql_";
+            query(I64) -> I64;
+            execute(I64) -> I64;
+        );
+    }
+
+    if data.msg.is_some() {
+        add_funcs!(
+            EXTISM_MSG_MODULE, msg, "msg_";
+            publish(I64, I64);
+            subscribe(I64) -> I64;
+            poll(I64, I64) -> I64;
+            unsubscribe(I64);
+        );
+    }
+
+    if data.objects.is_some() {
+        add_funcs!(
+            EXTISM_OBJECT_MODULE, object, "object_";
+            get(I64) -> I64;
+            get_range(I64, I64, I64) -> I64;
+            size(I64) -> I64;
+            put(I64, I64);
+            list(I64) -> I64;
+        );
+    }
+
+    if data.net.is_some() {
+        add_funcs!(
+            EXTISM_NET_MODULE, net, "net_";
+            connect(I64) -> I64;
+            send(I64, I64) -> I64;
+            recv(I64, I64, I64) -> I64;
+            close(I64);
+        );
+    }
+
+    #[cfg(feature = "websocket")]
+    if data.ws.is_some() {
+        add_funcs!(
+            EXTISM_WS_MODULE, ws, "ws_";
+            connect(I64) -> I64;
+            send(I64, I64, I32);
+            recv(I64, I64) -> I64;
+            close(I64);
+        );
+    }
+
+    // If wasi is enabled then add it to the linker, WASI isn't available on wasm32 hosts so
+    // `CurrentPlugin::new` fails before this point
+    #[cfg(target_family = "wasm")]
+    let _ = with_wasi;
+    #[cfg(not(target_family = "wasm"))]
+    if with_wasi {
+        wasi_common::sync::add_to_linker(&mut linker, |x: &mut CurrentPlugin| {
+            &mut x.wasi.as_mut().unwrap().ctx
+        })?;
+    }
+
+    #[cfg(feature = "wasi-nn")]
+    if data.wasi_nn.is_some() {
+        wasmtime_wasi_nn::witx::add_to_linker(&mut linker, |x: &mut CurrentPlugin| {
+            x.wasi_nn.as_mut().unwrap()
+        })?;
+    }
+
+    let mut ungranted = BTreeMap::new();
+    for f in imports {
+        let name = f.name();
+        let ns = f.namespace().unwrap_or(EXTISM_USER_MODULE);
+        if let Some(capability) = f.capability() {
+            if !data.manifest.capabilities.iter().any(|x| x == capability) {
+                ungranted.insert((ns.to_string(), name.to_string()), capability.to_string());
+                continue;
+            }
+        }
+        let func = f.f.clone();
+        let fname = f.name.clone();
+        let index = names.add(&fname);
+        linker.func_new(ns, name, f.ty(engine).clone(), move |mut c, i, o| {
+            let _span = span!("extism.host_function", plugin = %c.data().id, function = %fname);
+            let data: *mut CurrentPlugin = c.data_mut();
+            let res = c.data_mut().record_host_call(index, i).and_then(|_| {
+                func(c, i, o).map_err(|e| Error::new(HostFunctionError::new(&fname, e)))
+            });
+            unsafe { (*data).host_stats.end() };
+            res.to_wasmtime_result()
+        })?;
+    }
+
+    Ok(HostLinker {
+        linker,
+        names: std::sync::Arc::new(names),
+        ungranted,
+    })
+}
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn relink(
+    mut store: &mut Store<CurrentPlugin>,
+    host_linker: &std::sync::Arc<HostLinker>,
+    modules: &BTreeMap<String, Module>,
+    memories: Option<threads::SharedMemories>,
+) -> Result<
+    (
+        InstancePre<CurrentPlugin>,
+        Linker<CurrentPlugin>,
+        Rooted<ExternRef>,
+    ),
+    Error,
+> {
+    let mut linker = host_linker.linker.clone();
+
     for (name, module) in modules.iter() {
         if name == EXTISM_ENV_MODULE {
             continue;
         }
 
         for import in module.imports() {
+            let key = (import.module().to_string(), import.name().to_string());
+            if let Some(capability) = host_linker.ungranted.get(&key) {
+                anyhow::bail!(
+                    "module {name} imports {}::{}, which requires the `{capability}` capability, it must be \
+                    added to the manifest's `capabilities` to use this function",
+                    import.module(),
+                    import.name(),
+                );
+            }
+
             if import.module() == EXTISM_ENV_MODULE
                 && modules[EXTISM_ENV_MODULE]
                     .get_export(import.name())
@@ -388,26 +909,13 @@ fn relink(
         }
     }
 
-    let mut linked = BTreeSet::new();
-    linker.module(&mut store, EXTISM_ENV_MODULE, &modules[EXTISM_ENV_MODULE])?;
-    linked.insert(EXTISM_ENV_MODULE.to_string());
-
-    // If wasi is enabled then add it to the linker
-    if with_wasi {
-        wasi_common::sync::add_to_linker(&mut linker, |x: &mut CurrentPlugin| {
-            &mut x.wasi.as_mut().unwrap().ctx
-        })?;
-    }
+    threads::link(store, &mut linker, host_linker, modules, memories)?;
 
-    for f in imports {
-        let name = f.name();
-        let ns = f.namespace().unwrap_or(EXTISM_USER_MODULE);
-        unsafe {
-            let func: &'static function::FunctionInner = &*(f.f.as_ref() as *const _);
-            linker.func_new(ns, name, f.ty(engine).clone(), move |c, i, o| {
-                func(c, i, o).to_wasmtime_result()
-            })?;
-        }
+    // The kernel is only missing for plugins that haven't been instantiated yet
+    let mut linked = BTreeSet::new();
+    if let Some(kernel) = modules.get(EXTISM_ENV_MODULE) {
+        linker.module(&mut store, EXTISM_ENV_MODULE, kernel)?;
+        linked.insert(EXTISM_ENV_MODULE.to_string());
     }
 
     for (name, module) in modules.iter() {
@@ -415,12 +923,16 @@ fn relink(
             store,
             &mut linker,
             &mut linked,
+            &mut vec![],
             modules,
             name.clone(),
             module,
         )?;
     }
 
+    let kernel = crate::current_plugin::Kernel::resolve(&linker, &mut *store);
+    store.data_mut().kernel = kernel;
+
     let inner: Box<dyn std::any::Any + Send + Sync> = Box::new(());
     let host_context = ExternRef::new(store, inner)?;
 
@@ -446,33 +958,129 @@ impl Plugin {
 
     /// Create a new plugin from a pre-compiled plugin
     pub fn new_from_compiled(compiled: &CompiledPlugin) -> Result<Plugin, Error> {
+        let id = uuid::Uuid::new_v4();
+        let plugin = match &compiled.lazy {
+            Some(empty) => {
+                let mut plugin = Self::create(empty, id)?;
+                plugin.modules = compiled.modules.clone();
+                plugin.pending = Some(compiled.clone());
+                plugin
+            }
+            None => Self::create(compiled, id)?,
+        };
+        debug!("{} created", plugin.id);
+        plugin.emit(|| PluginEvent::Created { plugin: id });
+        Ok(plugin)
+    }
+
+    // Replace a lazy plugin with the fully instantiated plugin, keeping the ID, cancel handle and
+    // instance lock that may already be in use
+    pub(crate) fn initialize(&mut self) -> Result<(), Error> {
+        let Some(compiled) = self.pending.take() else {
+            return Ok(());
+        };
+        let mut plugin = match Self::create(&compiled, self.id) {
+            Ok(x) => x,
+            Err(e) => {
+                self.pending = Some(compiled);
+                return Err(e);
+            }
+        };
+        debug!(plugin = self.id.to_string(), "instantiated lazy plugin");
+        plugin.instance = self.instance.clone();
+        plugin.timer_tx = self.timer_tx.clone();
+        plugin.cancel_handle = self.cancel_handle.clone();
+        if let Some(threads) = &plugin.current_plugin().threads {
+            threads.set_cancelled(self.cancel_handle.cancelled.clone());
+        }
+        plugin.deadline = self.deadline;
+
+        // The empty plugin is dropped without emitting `PluginEvent::Dropped`
+        self.current_plugin_mut().set_event_bus(None);
+        *self = plugin;
+        Ok(())
+    }
+
+    fn create(compiled: &CompiledPlugin, id: uuid::Uuid) -> Result<Plugin, Error> {
         let available_pages = compiled.manifest.memory.max_pages;
         debug!("Available pages: {available_pages:?}");
 
-        let id = uuid::Uuid::new_v4();
-        let mut store = Store::new(
-            &compiled.engine,
-            CurrentPlugin::new(
-                compiled.manifest.clone(),
-                compiled.options.wasi,
-                available_pages,
-                compiled.options.http_response_headers,
-                id,
-            )?,
-        );
+        let mut current_plugin = CurrentPlugin::new(
+            compiled.manifest.clone(),
+            compiled.options.wasi,
+            compiled.options.wasi_sources.clone(),
+            available_pages,
+            compiled.options.http_response_headers,
+            id,
+            Default::default(),
+        )?;
+        current_plugin.secrets = secrets::Secrets::new(compiled.options.secrets_provider.clone());
+        current_plugin.redactor = compiled.options.redactor.clone();
+        current_plugin.log_handler = compiled.options.log_handler.clone();
+        #[cfg(not(target_family = "wasm"))]
+        {
+            current_plugin.mock = compiled.options.mock_host.clone();
+        }
+        current_plugin.kv = compiled.options.kv_store.clone();
+        current_plugin.var_store = compiled.options.var_store.clone();
+        #[cfg(feature = "http")]
+        {
+            current_plugin.http_agent = compiled
+                .options
+                .http_client
+                .as_ref()
+                .map(|x| x.agent())
+                .transpose()?;
+        }
+        current_plugin.sql = compiled.options.sql_database.clone();
+        current_plugin.objects = compiled.options.object_store.clone();
+        current_plugin.msg = compiled
+            .options
+            .message_broker
+            .clone()
+            .map(msg::MsgState::new);
+        #[cfg(feature = "websocket")]
+        {
+            current_plugin.ws = compiled.options.websockets.map(ws::WsState::new);
+        }
+        #[cfg(feature = "wasi-nn")]
+        {
+            current_plugin.wasi_nn = compiled.wasi_nn.as_ref().map(|x| x.ctx());
+        }
+        current_plugin.set_event_bus(compiled.options.event_bus.clone());
+        current_plugin.set_metrics(compiled.options.metrics.clone());
+        let cancelled = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        if compiled.options.wasm_threads {
+            let max = compiled
+                .options
+                .max_wasm_threads
+                .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |x| x.get()));
+            current_plugin.threads = Some(std::sync::Arc::new(threads::ThreadPool::new(
+                max,
+                cancelled.clone(),
+            )));
+        }
+        let mut deprecated = compiled.options.deprecated_functions.clone();
+        for f in compiled.options.functions.iter() {
+            if let Some(msg) = &f.deprecated {
+                deprecated.insert(f.name.clone(), msg.clone());
+            }
+        }
+        let mut store = Store::new(&compiled.engine, current_plugin);
         store.set_epoch_deadline(1);
         if let Some(fuel) = compiled.options.fuel {
             store.set_fuel(fuel)?;
         }
 
-        let imports: Vec<Function> = compiled.options.functions.to_vec();
-        let (instance_pre, linker, host_context) = relink(
-            &compiled.engine,
-            &mut store,
-            &imports,
-            &compiled.modules,
-            compiled.options.wasi,
-        )?;
+        let host_linker = compiled.host_linker(store.data())?;
+        store.data_mut().host_calls = quota::HostCallCounter::new(
+            compiled.options.host_function_limits.clone(),
+            host_linker.names.clone(),
+        );
+        store.data_mut().host_usage = usage::HostUsage::new(host_linker.names.clone(), deprecated);
+        store.data_mut().host_stats = usage::HostCallStats::new(host_linker.names.clone());
+        let (instance_pre, linker, host_context) =
+            relink(&mut store, &host_linker, &compiled.modules, None)?;
         let timer_tx = Timer::tx();
         let mut plugin = Plugin {
             modules: compiled.modules.clone(),
@@ -483,25 +1091,57 @@ impl Plugin {
             runtime: None,
             id,
             timer_tx: timer_tx.clone(),
-            cancel_handle: CancelHandle { id, timer_tx },
+            cancel_handle: CancelHandle {
+                id,
+                timer_tx,
+                cancelled,
+            },
             instantiations: 0,
             output: Output::default(),
             store_needs_reset: false,
+            streamed_input: None,
             debug_options: compiled.options.debug_options.clone(),
-            _functions: imports,
+            host_linker,
             error_msg: None,
+            error_code: 0,
             fuel: compiled.options.fuel,
+            call_fuel: compiled.options.fuel,
+            hardening: compiled.options.hardening,
+            resources: ResourceReport::default(),
+            last_call: CallStats::default(),
+            call_log: compiled.options.call_log.clone(),
+            main_hash: compiled.hashes.get(MAIN_KEY).cloned(),
+            zeroize: compiled.options.zeroize,
+            instantiated: false,
+            hooks: compiled.options.hooks.clone(),
+            kernel_memory_policy: compiled.options.kernel_memory_policy,
+            watchdog: compiled
+                .options
+                .stall_threshold
+                .map(watchdog::Watchdog::new),
+            #[cfg(feature = "guest-profiler")]
+            profile_interval: compiled.options.guest_profiler,
+            #[cfg(feature = "guest-profiler")]
+            profile: None,
+            call_stdin: None,
             host_context,
+            #[cfg(unix)]
+            helper: compiled.helper.as_ref().map(|x| x.start()).transpose()?,
+            #[cfg(feature = "component-model")]
+            component: compiled.component.as_ref().map(|x| x.start()).transpose()?,
+            pending: None,
+            cow_reset: compiled.options.cow_reset,
+            snapshot: None,
+            deadline: None,
         };
 
         plugin.current_plugin_mut().store = &mut plugin.store;
         plugin.current_plugin_mut().linker = &mut plugin.linker;
-        if available_pages.is_some() {
+        if plugin.current_plugin().memory_limiter.is_some() {
             plugin
                 .store
                 .limiter(|internal| internal.memory_limiter.as_mut().unwrap());
         }
-        debug!("{} created", plugin.id);
         Ok(plugin)
     }
 
@@ -511,32 +1151,32 @@ impl Plugin {
         instance_lock: &mut std::sync::MutexGuard<Option<Instance>>,
     ) -> Result<(), Error> {
         if self.store_needs_reset {
-            let engine = self.store.engine().clone();
-            let internal = self.current_plugin_mut();
-            let with_wasi = internal.wasi.is_some();
-            self.store = Store::new(
-                &engine,
-                CurrentPlugin::new(
-                    internal.manifest.clone(),
-                    internal.wasi.is_some(),
-                    internal.available_pages,
-                    internal.http_headers.is_some(),
-                    self.id,
-                )?,
-            );
+            if self.zeroize {
+                self.zeroize_memory(**instance_lock);
+            }
+            // The `CurrentPlugin` is moved into the new store instead of being rebuilt, only the state tied
+            // to the old instance is cleared
+            self.current_plugin_mut().recycle()?;
+            let placeholder = CurrentPlugin::new(
+                Default::default(),
+                false,
+                Default::default(),
+                None,
+                false,
+                self.id,
+                Default::default(),
+            )?;
+            let mut store = Store::new(self.store.engine(), placeholder);
+            std::mem::swap(store.data_mut(), self.store.data_mut());
+            self.store = store;
             self.store.set_epoch_deadline(1);
 
             if let Some(fuel) = self.fuel {
                 self.store.set_fuel(fuel)?;
             }
 
-            let (instance_pre, linker, host_context) = relink(
-                &engine,
-                &mut self.store,
-                &self._functions,
-                &self.modules,
-                with_wasi,
-            )?;
+            let (instance_pre, linker, host_context) =
+                relink(&mut self.store, &self.host_linker, &self.modules, None)?;
             self.linker = linker;
             self.instance_pre = instance_pre;
             self.host_context = host_context;
@@ -545,13 +1185,14 @@ impl Plugin {
             let current_plugin = self.current_plugin_mut();
             current_plugin.store = store;
             current_plugin.linker = linker;
-            if current_plugin.available_pages.is_some() {
+            if current_plugin.memory_limiter.is_some() {
                 self.store
                     .limiter(|internal| internal.memory_limiter.as_mut().unwrap());
             }
 
             self.instantiations = 0;
             **instance_lock = None;
+            self.snapshot = None;
             self.store_needs_reset = false;
         }
         Ok(())
@@ -567,18 +1208,29 @@ impl Plugin {
             return Ok(());
         }
 
-        let instance = self.instance_pre.instantiate(&mut self.store)?;
+        let _span = span!("extism.instantiate", plugin = %self.id);
+        let instance = self
+            .instance_pre
+            .instantiate(&mut self.store)
+            .map_err(|e| OutOfMemory::normalize(e.into()))?;
         trace!(
             plugin = self.id.to_string(),
             "Plugin::instance is none, instantiating"
         );
         **instance_lock = Some(instance);
         self.instantiations += 1;
+        if let Some(hooks) = &self.hooks {
+            hooks.on_instantiate(self.id, self.instantiated);
+        }
+        self.instantiated = true;
         if let Some(limiter) = &mut self.current_plugin_mut().memory_limiter {
             limiter.reset();
         }
         self.detect_guest_runtime(instance_lock);
         self.initialize_guest_runtime()?;
+        if self.cow_reset {
+            self.snapshot = Some(snapshot::MemorySnapshot::take(&mut self.store, instance)?);
+        }
         Ok(())
     }
 
@@ -597,6 +1249,14 @@ impl Plugin {
 
     /// Returns `true` if the given function exists, otherwise `false`
     pub fn function_exists(&self, function: impl AsRef<str>) -> bool {
+        #[cfg(unix)]
+        if let Some(helper) = &self.helper {
+            return helper.function_exists(function.as_ref());
+        }
+        #[cfg(feature = "component-model")]
+        if let Some(component) = &self.component {
+            return component.function_exists(function.as_ref());
+        }
         self.modules[MAIN_KEY]
             .get_export(function.as_ref())
             .map(|x| {
@@ -614,21 +1274,25 @@ impl Plugin {
             .unwrap_or(false)
     }
 
+    /// Get a handle to the exported function `name`, which is checked once here instead of on every call,
+    /// see `TypedFunction`
+    pub fn typed_function<I, O>(
+        &self,
+        name: impl AsRef<str>,
+    ) -> Result<TypedFunction<I, O>, Error> {
+        TypedFunction::new(self, name.as_ref())
+    }
+
     // Store input in memory and re-initialize `Internal` pointer
-    pub(crate) fn set_input(
+    pub(crate) fn set_input<'a, I: ToBytes<'a>>(
         &mut self,
-        input: *const u8,
-        mut len: usize,
+        input: &I,
         host_context: Option<Rooted<ExternRef>>,
     ) -> Result<(), Error> {
         self.output = Output::default();
         self.clear_error()?;
         let id = self.id.to_string();
 
-        if input.is_null() {
-            len = 0;
-        }
-
         {
             let store = &mut self.store as *mut _;
             let linker = &mut self.linker as *mut _;
@@ -637,33 +1301,32 @@ impl Plugin {
             current_plugin.linker = linker;
         }
 
-        let bytes = unsafe { std::slice::from_raw_parts(input, len) };
-        debug!(plugin = &id, "input size: {}", bytes.len());
-
-        self.reset()?;
-        let handle = self.current_plugin_mut().memory_new(bytes)?;
+        let handle = match self.streamed_input.take() {
+            Some(handle) => handle,
+            None => {
+                self.reset_kernel()?;
+                self.current_plugin_mut().memory_new_from(input)?
+            }
+        };
+        let len = handle.len();
+        debug!(plugin = &id, "input size: {}", len);
+        self.output.input_offset = handle.offset();
+        self.output.input_length = handle.len() as u64;
 
-        if let Some(f) = self
-            .linker
-            .get(&mut self.store, EXTISM_ENV_MODULE, "input_set")
-        {
+        let kernel = self.current_plugin().kernel;
+        if let Some(f) = kernel.input_set {
             catch_out_of_fuel!(
                 &self.store,
-                f.into_func()
-                    .unwrap()
-                    .call(
-                        &mut self.store,
-                        &[Val::I64(handle.offset() as i64), Val::I64(len as i64)],
-                        &mut [],
-                    )
-                    .context("unable to set extism input")
+                f.call(
+                    &mut self.store,
+                    &[Val::I64(handle.offset() as i64), Val::I64(len as i64)],
+                    &mut [],
+                )
+                .context("unable to set extism input")
             )?;
         }
 
-        if let Some(Extern::Global(ctxt)) =
-            self.linker
-                .get(&mut self.store, EXTISM_ENV_MODULE, "extism_context")
-        {
+        if let Some(ctxt) = kernel.context {
             ctxt.set(&mut self.store, Val::ExternRef(host_context))
                 .context("unable to set extism host context")?;
         }
@@ -671,27 +1334,140 @@ impl Plugin {
         Ok(())
     }
 
-    /// Reset Extism runtime, this will invalidate all allocated memory
+    /// Reset Extism runtime, this will invalidate all allocated memory. Plugins built with
+    /// `PluginBuilder::with_copy_on_write_reset` also have their memory restored.
     pub fn reset(&mut self) -> Result<(), Error> {
-        let id = self.id.to_string();
+        // There's nothing to reset before the first call
+        if self.pending.is_some() {
+            return Ok(());
+        }
 
-        if let Some(f) = self.linker.get(&mut self.store, EXTISM_ENV_MODULE, "reset") {
-            catch_out_of_fuel!(
-                &self.store,
-                f.into_func()
-                    .unwrap()
-                    .call(&mut self.store, &[], &mut [])
-                    .context("extism reset failed")
-            )?;
-        } else {
-            error!(plugin = &id, "call to extism:host/env::reset failed");
+        #[cfg(unix)]
+        if let Some(helper) = &mut self.helper {
+            return helper.reset();
         }
+        #[cfg(feature = "component-model")]
+        if let Some(component) = &mut self.component {
+            return component.reset();
+        }
+
+        if self.zeroize {
+            // Zeroing the guest memory breaks the instance, so the store is reset and the plugin is
+            // instantiated again by the next call
+            let instance = self.instance.clone();
+            let mut lock = match instance.lock() {
+                Ok(x) => x,
+                Err(e) => e.into_inner(),
+            };
+            self.store_needs_reset = true;
+            self.reset_store(&mut lock)?;
+        } else {
+            self.reset_kernel()?;
+            let restored = match &self.snapshot {
+                Some(snapshot) => snapshot.restore(&mut self.store)?,
+                None => true,
+            };
 
+            // Memory that has grown can't be restored, the plugin is instantiated again instead
+            if !restored {
+                self.store_needs_reset = true;
+                self.main_instance()?;
+            }
+        }
+        if let Some(hooks) = &self.hooks {
+            hooks.on_memory_reset(self.id);
+        }
         Ok(())
     }
 
+    // Free all memory allocated by the Extism kernel
+    pub(crate) fn reset_kernel(&mut self) -> Result<(), Error> {
+        let id = self.id.to_string();
+        if self.zeroize {
+            self.zeroize_kernel();
+        }
+        if let Some(f) = self.current_plugin().kernel.reset {
+            catch_out_of_fuel!(
+                &self.store,
+                f.call(&mut self.store, &[], &mut [])
+                    .context("extism reset failed")
+            )?;
+        } else {
+            error!(plugin = &id, "call to extism:host/env::reset failed");
+        }
+
+        Ok(())
+    }
+
+    // Overwrite the kernel memory, every memory exported by a linked module or the main instance and any
+    // plugin variables with zeroes
+    pub(crate) fn zeroize_memory(&mut self, instance: Option<Instance>) {
+        use zeroize::Zeroize;
+
+        let mut memories: Vec<Memory> = self
+            .linker
+            .iter(&mut self.store)
+            .filter_map(|(_, _, x)| x.into_memory())
+            .collect();
+        if let Some(instance) = instance {
+            let exports: Vec<Extern> = instance
+                .exports(&mut self.store)
+                .map(|x| x.into_extern())
+                .collect();
+            memories.extend(exports.into_iter().filter_map(|x| x.into_memory()));
+        }
+        for mem in memories {
+            mem.data_mut(&mut self.store).zeroize();
+        }
+
+        let vars = &mut self.current_plugin_mut().vars;
+        for v in vars.values_mut() {
+            v.zeroize();
+        }
+        vars.clear();
+        if let Some(msg) = &mut self.error_msg {
+            msg.zeroize();
+        }
+        trace!(plugin = self.id.to_string(), "zeroized plugin memory");
+    }
+
+    // Overwrite the kernel memory with zeroes, it holds the input, output and error of earlier calls
+    fn zeroize_kernel(&mut self) {
+        use zeroize::Zeroize;
+
+        if let Some(mem) = self.current_plugin().kernel.memory {
+            mem.data_mut(&mut self.store).zeroize();
+        }
+    }
+
+    // Overwrite the copy of the input stored in kernel memory with zeroes
+    fn zeroize_input(&mut self) {
+        use zeroize::Zeroize;
+
+        let (offset, len) = (
+            self.output.input_offset as usize,
+            self.output.input_length as usize,
+        );
+        if len == 0 {
+            return;
+        }
+        if let Some(mem) = self
+            .linker
+            .get(&mut self.store, EXTISM_ENV_MODULE, "memory")
+            .and_then(|x| x.into_memory())
+        {
+            if let Some(data) = mem.data_mut(&mut self.store).get_mut(offset..offset + len) {
+                data.zeroize();
+            }
+        }
+        self.output.input_length = 0;
+    }
+
     /// Determine if wasi is enabled
     pub fn has_wasi(&self) -> bool {
+        if let Some(compiled) = &self.pending {
+            return compiled.options.wasi;
+        }
         self.current_plugin().wasi.is_some()
     }
 
@@ -804,30 +1580,21 @@ impl Plugin {
     fn output_memory_position(&mut self) -> Result<(u64, u64), Error> {
         let out = &mut [Val::I64(0)];
         let out_len = &mut [Val::I64(0)];
+        let kernel = self.current_plugin().kernel;
         let store = &mut self.store;
-        if let Some(f) = self
-            .linker
-            .get(&mut *store, EXTISM_ENV_MODULE, "output_offset")
-        {
+        if let Some(f) = kernel.output_offset {
             catch_out_of_fuel!(
                 &store,
-                f.into_func()
-                    .unwrap()
-                    .call(&mut *store, &[], out)
+                f.call(&mut *store, &[], out)
                     .context("call to set extism output offset failed")
             )?;
         } else {
             anyhow::bail!("unable to set output")
         }
-        if let Some(f) = self
-            .linker
-            .get(&mut *store, EXTISM_ENV_MODULE, "output_length")
-        {
+        if let Some(f) = kernel.output_length {
             catch_out_of_fuel!(
                 &store,
-                f.into_func()
-                    .unwrap()
-                    .call(&mut *store, &[], out_len)
+                f.call(&mut *store, &[], out_len)
                     .context("call to set extism output length failed")
             )?;
         } else {
@@ -840,7 +1607,15 @@ impl Plugin {
     }
 
     // Get the output data after a call has returned
-    fn output<'a, T: FromBytes<'a>>(&'a mut self) -> Result<T, Error> {
+    pub(crate) fn output<'a, T: FromBytes<'a>>(&'a mut self) -> Result<T, Error> {
+        #[cfg(unix)]
+        if self.helper.is_some() {
+            return T::from_bytes(&self.helper.as_ref().unwrap().output);
+        }
+        #[cfg(feature = "component-model")]
+        if self.component.is_some() {
+            return T::from_bytes(&self.component.as_ref().unwrap().output);
+        }
         let offs = self.output.offset;
         let len = self.output.length;
         let x = self
@@ -869,20 +1644,256 @@ impl Plugin {
         Ok(())
     }
 
+    // Send an event to the plugin's `EventBus`, `f` is only called when an `EventBus` has been configured
+    pub(crate) fn emit(&self, f: impl FnOnce() -> PluginEvent) {
+        if let Some(events) = &self.current_plugin().events {
+            events.emit(f());
+        }
+    }
+
     // Implements the build of the `call` function, `raw_call` is also used in the SDK
     // code
-    pub(crate) fn raw_call<T: 'static + Send + Sync>(
+    pub(crate) fn raw_call<'a, I: ToBytes<'a>, T: 'static + Send + Sync>(
         &mut self,
         lock: &mut std::sync::MutexGuard<Option<Instance>>,
         name: impl AsRef<str>,
-        input: impl AsRef<[u8]>,
+        input: I,
         host_context: Option<T>,
     ) -> Result<i32, (Error, i32)> {
         let name = name.as_ref();
-        let input = input.as_ref();
+        self.initialize().map_err(|e| (e, -1))?;
+
+        // The encoded input is only kept on the host when it's needed after the call, otherwise
+        // `set_input` writes it directly into plugin memory
+        let encoded = if self.call_log.is_some() || tracing::enabled!(tracing::Level::TRACE) {
+            Some(input.to_bytes().map_err(|e| (e, -1))?)
+        } else {
+            None
+        };
+        let encoded = encoded.as_ref().map(|x| x.as_ref());
+        let _span = span!("extism.call", plugin = %self.id, function = name);
+        let id = self.id;
+        let events = self.current_plugin().events.is_some();
+        if events {
+            self.emit(|| PluginEvent::CallStarted {
+                plugin: id,
+                function: name.to_string(),
+            });
+        }
+        let metrics = self.current_plugin().metrics.clone();
+        if let Some(metrics) = &metrics {
+            metrics.call_started(id, name);
+        }
+        let hooks = self.hooks.clone();
+        if let Some(hooks) = &hooks {
+            hooks.on_call_start(id, name);
+        }
+
+        let started_at = std::time::SystemTime::now();
+        let start = std::time::Instant::now();
+        let mut res = match encoded {
+            Some(data) => self.raw_call_inner(lock, name, &data, host_context),
+            None => self.raw_call_inner(lock, name, &input, host_context),
+        };
+
+        // A failed call can leave globals that aren't part of the snapshot in any state, so the next
+        // call starts from a new instance
+        if res.is_err() && self.snapshot.is_some() {
+            self.store_needs_reset = true;
+        }
+        let duration = start.elapsed();
+        self.update_resource_report(lock, duration);
+        self.apply_kernel_memory_policy(name);
+
+        if let Some(log) = self.call_log.clone() {
+            let main_hash = self.main_hash.clone().unwrap_or_default();
+            let output = if matches!(res, Ok(0)) {
+                self.output::<&[u8]>().ok()
+            } else {
+                None
+            };
+            if let Err(e) = log.append(
+                &main_hash,
+                name,
+                encoded.unwrap_or_default(),
+                output,
+                started_at,
+            ) {
+                error!(
+                    plugin = id.to_string(),
+                    "unable to write to call log: {e:?}"
+                );
+                res = Err((e.context("unable to write to call log"), -1));
+            }
+        }
+
+        if tracing::enabled!(tracing::Level::TRACE) {
+            let input = encoded.unwrap_or_default();
+            self.trace_call_data(name, input, matches!(res, Ok(0)));
+        }
+
+        if self.zeroize {
+            self.zeroize_input();
+        }
+
+        // How the call failed, running out of memory or fuel is reported as a trap
+        let failure = match &res {
+            Err((e, rc)) => match ErrorKind::of(e) {
+                ErrorKind::Timeout => Some(ErrorKind::Timeout),
+                ErrorKind::Cancelled => Some(ErrorKind::Cancelled),
+                kind if kind == ErrorKind::Trap || *rc == 134 => Some(ErrorKind::Trap),
+                _ => None,
+            },
+            Ok(_) => None,
+        };
+
+        if let Some(metrics) = &metrics {
+            match failure {
+                Some(ErrorKind::Timeout) => metrics.timeout(id, name),
+                Some(ErrorKind::Trap) => metrics.trap(id, name),
+                _ => (),
+            }
+            metrics.call_finished(id, name, duration, matches!(res, Ok(0)));
+        }
+
+        if let Some(hooks) = &hooks {
+            match (failure, &res) {
+                (Some(ErrorKind::Timeout), _) => hooks.on_timeout(id, name),
+                (Some(ErrorKind::Trap), Err((e, _))) => hooks.on_trap(id, name, e),
+                _ => (),
+            }
+            hooks.on_call_end(id, name, duration, matches!(res, Ok(0)));
+        }
+
+        if !events {
+            return res;
+        }
+
+        if let Err((e, _)) = &res {
+            match failure {
+                Some(ErrorKind::Cancelled) => self.emit(|| PluginEvent::Cancelled {
+                    plugin: id,
+                    function: name.to_string(),
+                }),
+                Some(ErrorKind::Timeout) => self.emit(|| PluginEvent::Timeout {
+                    plugin: id,
+                    function: name.to_string(),
+                }),
+                Some(ErrorKind::Trap) => self.emit(|| PluginEvent::Trap {
+                    plugin: id,
+                    function: name.to_string(),
+                    message: e.to_string(),
+                }),
+                _ => (),
+            }
+        }
+
+        self.emit(|| PluginEvent::CallFinished {
+            plugin: id,
+            function: name.to_string(),
+            duration,
+            success: matches!(res, Ok(0)),
+        });
+        res
+    }
+
+    // Log the input and output of a call, after redaction
+    fn trace_call_data(&mut self, name: &str, input: &[u8], success: bool) {
+        let id = self.id.to_string();
+        let input = self.current_plugin().redact(
+            RedactTarget::Input { function: name },
+            &String::from_utf8_lossy(input),
+        );
+        trace!(plugin = id, "call to {name} input: {input}");
+
+        if success {
+            if let Ok(output) = self.output::<&[u8]>() {
+                let output = String::from_utf8_lossy(output).into_owned();
+                let output = self
+                    .current_plugin()
+                    .redact(RedactTarget::Output { function: name }, &output);
+                trace!(plugin = id, "call to {name} output: {output}");
+            }
+        }
+    }
+
+    // Update the `ResourceReport` after a call
+    // Wasm memory can't shrink, so kernel memory is released by starting the next call from a new store.
+    // The output of this call is still available until then.
+    fn apply_kernel_memory_policy(&mut self, name: &str) {
+        if self.store_needs_reset {
+            return;
+        }
+        let Some(mem) = self
+            .linker
+            .get(&mut self.store, EXTISM_ENV_MODULE, "memory")
+            .and_then(|x| x.into_memory())
+        else {
+            return;
+        };
+        let size = mem.data_size(&self.store) as u64;
+        if self.kernel_memory_policy.should_release(size) {
+            debug!(
+                plugin = self.id.to_string(),
+                "releasing {size} bytes of kernel memory after call to {name}"
+            );
+            self.store_needs_reset = true;
+        }
+    }
+
+    fn update_resource_report(
+        &mut self,
+        lock: &mut std::sync::MutexGuard<Option<Instance>>,
+        duration: std::time::Duration,
+    ) {
+        let fuel = self.fuel_consumed().unwrap_or_default();
+        let mut memory = self
+            .linker
+            .get(&mut self.store, EXTISM_ENV_MODULE, "memory")
+            .and_then(|x| x.into_memory())
+            .map(|mem| mem.data_size(&self.store))
+            .unwrap_or_default();
+        if let Some(instance) = &**lock {
+            if let Some(mem) = instance.get_memory(&mut self.store, "memory") {
+                memory += mem.data_size(&self.store);
+            }
+        }
+
+        self.last_call = CallStats {
+            wall_time: duration,
+            fuel_consumed: fuel,
+            memory_pages: memory.div_ceil(65536) as u64,
+            host_calls: self.current_plugin().host_stats.total_calls(),
+        };
+
+        let report = &mut self.resources;
+        report.calls += 1;
+        report.fuel_consumed += fuel;
+        report.wall_time += duration;
+        report.peak_memory = report.peak_memory.max(memory as u64);
+    }
+
+    fn raw_call_inner<'a, I: ToBytes<'a>, T: 'static + Send + Sync>(
+        &mut self,
+        lock: &mut std::sync::MutexGuard<Option<Instance>>,
+        name: &str,
+        input: &I,
+        host_context: Option<T>,
+    ) -> Result<i32, (Error, i32)> {
+        #[cfg(unix)]
+        if let Some(helper) = &mut self.helper {
+            let input = input.to_bytes().map_err(|e| (e, -1))?;
+            return helper.call(name, input.as_ref());
+        }
+        #[cfg(feature = "component-model")]
+        if let Some(component) = &mut self.component {
+            let input = input.to_bytes().map_err(|e| (e, -1))?;
+            return component.call(name, input.as_ref());
+        }
 
         if let Some(fuel) = self.fuel {
             self.store.set_fuel(fuel).map_err(|x| (x.into(), -1))?;
+            self.call_fuel = Some(fuel);
         }
 
         catch_out_of_fuel!(
@@ -913,12 +1924,11 @@ impl Plugin {
             None
         };
 
-        self.set_input(input.as_ptr(), input.len(), r)
-            .map_err(|x| (x, -1))?;
+        self.set_input(input, r).map_err(|x| (x, -1))?;
 
         let func = match self.get_func(lock, name) {
             Some(x) => x,
-            None => return Err((anyhow::anyhow!("Function not found: {name}"), -1)),
+            None => return Err((Error::new(FunctionNotFound(name.to_string())), -1)),
         };
 
         // Check the number of results, reject functions with more than 1 result
@@ -931,24 +1941,123 @@ impl Plugin {
         }
 
         // Start timer
+        self.cancel_handle
+            .cancelled
+            .store(false, std::sync::atomic::Ordering::SeqCst);
+        let now = std::time::Instant::now();
+        let duration = self
+            .current_plugin()
+            .manifest
+            .timeout_ms
+            .map(std::time::Duration::from_millis);
+        let deadline = self.deadline.map(|x| x.saturating_duration_since(now));
+        let duration = match (duration, deadline) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        let stall = match &self.watchdog {
+            Some(watchdog) => {
+                watchdog.install(
+                    &mut self.store,
+                    name,
+                    duration,
+                    self.cancel_handle.cancelled.clone(),
+                    self.fuel,
+                );
+                Some((watchdog.threshold, watchdog.stalled.clone()))
+            }
+            #[cfg(feature = "guest-profiler")]
+            None if self.profile_interval.is_some() => {
+                profiler::install(
+                    &mut self.store,
+                    duration,
+                    self.cancel_handle.cancelled.clone(),
+                );
+                None
+            }
+            None => {
+                self.store.epoch_deadline_trap();
+                None
+            }
+        };
+        // The epoch deadline is set before the timer starts, otherwise a timer that fires immediately could
+        // increment the epoch before the deadline is set and the call would never be interrupted
+        self.store.set_epoch_deadline(1);
+        #[cfg(feature = "guest-profiler")]
+        let ticker = match self.profile_interval {
+            Some(interval) => {
+                let sampler =
+                    profiler::Sampler::new(self.store.engine(), name, interval, &self.modules)
+                        .map_err(|e| (e, -1))?;
+                self.current_plugin_mut().profiler = Some(sampler);
+                Some(profiler::Ticker::start(
+                    self.store.engine().clone(),
+                    interval,
+                ))
+            }
+            None => None,
+        };
         self.timer_tx
             .send(TimerAction::Start {
                 id: self.id,
                 engine: self.store.engine().clone(),
-                duration: self
-                    .current_plugin()
-                    .manifest
-                    .timeout_ms
-                    .map(std::time::Duration::from_millis),
+                duration,
+                stall,
             })
             .expect("Timer should start");
-        self.store.epoch_deadline_trap();
-        self.store.set_epoch_deadline(1);
-        self.current_plugin_mut().start_time = std::time::Instant::now();
+        self.current_plugin_mut().start_time = now;
+        self.current_plugin_mut().deadline = self.deadline;
+        self.current_plugin_mut().host_calls.start_call();
+        {
+            let function = &mut self.current_plugin_mut().function;
+            function.clear();
+            function.push_str(name);
+        }
+        self.current_plugin_mut().host_stats.reset();
+        self.current_plugin_mut().secrets.clear();
+        if let Some(captured) = &self.current_plugin().wasi_sources.captured {
+            captured.clear();
+        }
+        #[cfg(not(target_family = "wasm"))]
+        let call_stdin = self.call_stdin.take();
+        #[cfg(not(target_family = "wasm"))]
+        if let Some(wasi) = &self.current_plugin().wasi {
+            let default = self.current_plugin().wasi_sources.stdin.as_ref();
+            match call_stdin {
+                Some(data) => wasi.ctx.set_stdin(Stdin::bytes(data).file()),
+                None => {
+                    if let Some(stdin) = default.filter(|x| x.is_bytes()) {
+                        wasi.ctx.set_stdin(stdin.file());
+                    }
+                }
+            }
+        }
+        #[cfg(feature = "http")]
+        self.current_plugin_mut().http_streams.clear();
 
         // Call the function
         let mut results = vec![wasmtime::Val::I32(0); n_results];
-        let mut res = func.call(self.store_mut(), &[], results.as_mut_slice());
+        let mut res = match self.hardening {
+            Some(hardening) => {
+                // Restrictions can't be removed once applied, so hardened calls are made on a
+                // separate thread
+                let manifest = self.current_plugin().manifest.clone();
+                let paths = self.current_plugin().local_paths();
+                let store = &mut self.store;
+                let results = results.as_mut_slice();
+                std::thread::scope(|s| {
+                    s.spawn(move || {
+                        hardening
+                            .apply(&manifest, &paths)
+                            .map_err(wasmtime::Error::from_anyhow)?;
+                        func.call(store, &[], results)
+                    })
+                    .join()
+                    .unwrap_or_else(|e| std::panic::resume_unwind(e))
+                })
+            }
+            None => func.call(self.store_mut(), &[], results.as_mut_slice()),
+        };
 
         // Reset host context
         if let Ok(Some(inner)) = self.host_context.data_mut(&mut self.store) {
@@ -962,11 +2071,27 @@ impl Plugin {
         self.store
             .epoch_deadline_callback(|_| Ok(UpdateDeadline::Continue(1)));
         let _ = self.timer_tx.send(TimerAction::Stop { id: self.id });
+        if let Some(threads) = self.current_plugin().threads.clone() {
+            threads.stop(self.store.engine());
+        }
+        #[cfg(feature = "guest-profiler")]
+        {
+            drop(ticker);
+            if let Some(sampler) = self.current_plugin_mut().profiler.take() {
+                match sampler.finish() {
+                    Ok(profile) => self.profile = Some(profile),
+                    Err(e) => warn!(
+                        plugin = self.id.to_string(),
+                        "unable to write guest profile: {e}"
+                    ),
+                }
+            }
+        }
         self.store_needs_reset = name == "_start";
 
         let mut rc = -1;
         if self.store.get_fuel().is_ok_and(|x| x == 0) {
-            res = Err(wasmtime::Error::msg("plugin ran out of fuel"));
+            res = Err(wasmtime::Error::new(OutOfFuel));
         } else {
             // Get extism error
             let output_res = self.get_output_after_call().map_err(|x| (x, -1));
@@ -986,17 +2111,23 @@ impl Plugin {
                     offset: self.output.error_offset,
                     length: self.output.error_length,
                 };
-                match self.current_plugin_mut().memory_str(handle) {
+                match self
+                    .current_plugin_mut()
+                    .memory_str(handle)
+                    .map(|e| e.to_string())
+                {
                     Ok(e) => {
-                        let x = e.to_string();
+                        let x = self
+                            .current_plugin()
+                            .redact(RedactTarget::Error { function: name }, &e);
                         error!(
                             plugin = self.id.to_string(),
                             "call to {name} returned with error message: {}", x
                         );
                         if let Err(e) = res {
-                            res = Err(wasmtime::Error::msg(x).context(e));
+                            res = Err(wasmtime::Error::new(GuestError(x)).context(e));
                         } else {
-                            res = Err(wasmtime::Error::msg(x))
+                            res = Err(wasmtime::Error::new(GuestError(x)))
                         }
                     }
                     Err(msg) => {
@@ -1061,7 +2192,10 @@ impl Plugin {
                     }
                 }
 
+                #[cfg(not(target_family = "wasm"))]
                 let wasi_exit_code = e.downcast_ref::<wasi_common::I32Exit>().map(|e| e.0);
+                #[cfg(target_family = "wasm")]
+                let wasi_exit_code: Option<i32> = None;
                 if let Some(exit_code) = wasi_exit_code {
                     debug!(
                         plugin = self.id.to_string(),
@@ -1077,25 +2211,53 @@ impl Plugin {
 
                 // Handle timeout interrupts
                 if let Some(wasmtime::Trap::Interrupt) = e.downcast_ref::<wasmtime::Trap>() {
+                    if self
+                        .cancel_handle
+                        .cancelled
+                        .load(std::sync::atomic::Ordering::SeqCst)
+                    {
+                        debug!(plugin = self.id.to_string(), "call to {name} was cancelled");
+                        return Err((Error::new(Cancelled), rc));
+                    }
                     debug!(plugin = self.id.to_string(), "call to {name} timed out");
-                    return Err((Error::msg("timeout"), rc));
+                    return Err((Error::new(Timeout), rc));
                 }
 
                 // Handle out-of-memory error from `MemoryLimiter`
-                let cause = e.root_cause().to_string();
-                if cause == "oom" {
+                if e.root_cause().is::<OutOfMemory>() {
                     debug!(
                         plugin = self.id.to_string(),
                         "call to {name} ran out of memory"
                     );
-                    return Err((Error::msg(cause), rc));
+                    return Err((Error::new(OutOfMemory), rc));
+                }
+
+                // Resolve the guest stack so the error says which function trapped
+                let e: Error = e.into();
+                let e = match GuestBacktrace::new(&e, &self.modules) {
+                    Some(backtrace) => e.context(backtrace),
+                    None => e,
+                };
+
+                // Make sure resolved secrets and redacted data don't leak through error messages
+                let msg = format!("{e:?}");
+                let redacted = self
+                    .current_plugin()
+                    .redact(RedactTarget::Error { function: name }, &msg);
+                if redacted != msg {
+                    let msg = redacted;
+                    error!(
+                        plugin = self.id.to_string(),
+                        "call to {name} encountered an error: {msg}"
+                    );
+                    return Err((Error::msg(msg), rc));
                 }
 
                 error!(
                     plugin = self.id.to_string(),
                     "call to {name} encountered an error: {e:?}"
                 );
-                Err((e.into(), rc))
+                Err((e, rc))
             }
         }
     }
@@ -1133,8 +2295,7 @@ impl Plugin {
             ),
             TryLockError::WouldBlock => anyhow::anyhow!("cannot make reentrant calls into plugin"),
         })?;
-        let data = input.to_bytes()?;
-        self.raw_call(&mut lock, name, data, None::<()>)
+        self.raw_call(&mut lock, name, input, None::<()>)
             .map_err(|e| e.0)
             .and_then(move |rc| {
                 if rc != 0 {
@@ -1163,12 +2324,212 @@ impl Plugin {
             ),
             TryLockError::WouldBlock => anyhow::anyhow!("cannot make reentrant calls into plugin"),
         })?;
-        let data = input.to_bytes()?;
-        self.raw_call(&mut lock, name, data, Some(host_context))
+        self.raw_call(&mut lock, name, input, Some(host_context))
             .map_err(|e| e.0)
             .and_then(move |_| self.output())
     }
 
+    /// Like `Plugin::call`, but `timeout` is used in place of the manifest's `timeout_ms` for this call
+    /// only. This can be used to give exported functions different deadlines, `CurrentPlugin::time_remaining`
+    /// reports the time left using the new timeout.
+    pub fn call_with_timeout<'a, 'b, T: ToBytes<'a>, U: FromBytes<'b>>(
+        &'b mut self,
+        name: impl AsRef<str>,
+        input: T,
+        timeout: std::time::Duration,
+    ) -> Result<U, Error> {
+        let lock = self.instance.clone();
+        let mut lock = lock.try_lock().map_err(|e| match e {
+            TryLockError::Poisoned(_) => anyhow::anyhow!(
+                "instance lock was poisoned; previous thread panicked while calling into wasm"
+            ),
+            TryLockError::WouldBlock => anyhow::anyhow!("cannot make reentrant calls into plugin"),
+        })?;
+
+        // Lazy plugins are instantiated first, otherwise the timeout would be set on the placeholder
+        self.initialize()?;
+        let timeout_ms = timeout.as_millis().try_into().unwrap_or(u64::MAX);
+        let manifest_timeout = self
+            .current_plugin_mut()
+            .manifest
+            .timeout_ms
+            .replace(timeout_ms);
+        let res = self.raw_call(&mut lock, name, input, None::<()>);
+        self.current_plugin_mut().manifest.timeout_ms = manifest_timeout;
+        let rc = res.map_err(|e| e.0)?;
+        if rc != 0 {
+            return Err(Error::msg(format!("Returned non-zero exit code: {rc}")));
+        }
+        self.output()
+    }
+
+    /// Take the guest profile collected during the most recent call, see `Profiling::Guest`. The profile
+    /// is JSON in the Firefox profiler's processed format, it can be opened at <https://profiler.firefox.com>
+    /// or using `samply load`.
+    #[cfg(feature = "guest-profiler")]
+    pub fn take_profile(&mut self) -> Option<Vec<u8>> {
+        self.profile.take()
+    }
+
+    /// Returns the WASI stdout output of the most recent call and clears it, this is always empty unless
+    /// `PluginBuilder::with_output_capture` is enabled
+    pub fn take_stdout(&mut self) -> Vec<u8> {
+        match &self.current_plugin().wasi_sources.captured {
+            Some(captured) => captured.take_stdout(),
+            None => vec![],
+        }
+    }
+
+    /// Returns the WASI stderr output of the most recent call and clears it, see `Plugin::take_stdout`
+    pub fn take_stderr(&mut self) -> Vec<u8> {
+        match &self.current_plugin().wasi_sources.captured {
+            Some(captured) => captured.take_stderr(),
+            None => vec![],
+        }
+    }
+
+    /// Like `Plugin::call`, but the resources used by the call are returned along with the output
+    ///
+    /// ```no_run
+    /// # fn example(plugin: &mut extism::Plugin) -> Result<(), extism::Error> {
+    /// let (output, stats) = plugin.call_with_stats::<&str, &str>("greet", "Benjamin")?;
+    /// println!("{output} took {:?} and made {} host calls", stats.wall_time, stats.host_calls);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_with_stats<'a, 'b, T: ToBytes<'a>, U: FromBytes<'b>>(
+        &'b mut self,
+        name: impl AsRef<str>,
+        input: T,
+    ) -> Result<(U, CallStats), Error> {
+        let lock = self.instance.clone();
+        let mut lock = lock.try_lock().map_err(|e| match e {
+            TryLockError::Poisoned(_) => anyhow::anyhow!(
+                "instance lock was poisoned; previous thread panicked while calling into wasm"
+            ),
+            TryLockError::WouldBlock => anyhow::anyhow!("cannot make reentrant calls into plugin"),
+        })?;
+        let rc = self
+            .raw_call(&mut lock, name, input, None::<()>)
+            .map_err(|e| e.0)?;
+        if rc != 0 {
+            return Err(Error::msg(format!("Returned non-zero exit code: {rc}")));
+        }
+        let stats = self.last_call;
+        Ok((self.output()?, stats))
+    }
+
+    /// Call `name` once for each of `inputs`, returning a result for each input in order. The instance lock
+    /// is only taken once and the instance is reused between calls, each input is written to the same
+    /// region of plugin memory after the kernel is reset. Like `Plugin::call`, the store is only reset
+    /// after a call fails, so a failing input doesn't affect the inputs after it.
+    ///
+    /// ```no_run
+    /// # use extism::*;
+    /// # fn example(plugin: &mut Plugin) {
+    /// let outputs: Vec<Result<String, Error>> = plugin.call_batch("count_vowels", ["a", "bb", "ccc"]);
+    /// # }
+    /// ```
+    pub fn call_batch<'a, T: ToBytes<'a>, U: FromBytesOwned>(
+        &mut self,
+        name: impl AsRef<str>,
+        inputs: impl IntoIterator<Item = T>,
+    ) -> Vec<Result<U, Error>> {
+        let name = name.as_ref();
+        let lock = self.instance.clone();
+        let mut lock = match lock.try_lock() {
+            Ok(lock) => lock,
+            Err(e) => {
+                let msg = match e {
+                    TryLockError::Poisoned(_) => {
+                        "instance lock was poisoned; previous thread panicked while calling into wasm"
+                    }
+                    TryLockError::WouldBlock => "cannot make reentrant calls into plugin",
+                };
+                return inputs.into_iter().map(|_| Err(Error::msg(msg))).collect();
+            }
+        };
+        inputs
+            .into_iter()
+            .map(|input| {
+                let rc = self
+                    .raw_call(&mut lock, name, input, None::<()>)
+                    .map_err(|e| e.0)?;
+                if rc != 0 {
+                    return Err(Error::msg(format!("Returned non-zero exit code: {rc}")));
+                }
+                self.output()
+            })
+            .collect()
+    }
+
+    /// Like `Plugin::call`, but the call is limited to `fuel` instead of the limit set using
+    /// `PluginBuilder::with_fuel_limit`. The plugin must have been built with a fuel limit, since fuel
+    /// metering is configured when the plugin is compiled.
+    pub fn call_with_fuel<'a, 'b, T: ToBytes<'a>, U: FromBytes<'b>>(
+        &'b mut self,
+        name: impl AsRef<str>,
+        input: T,
+        fuel: u64,
+    ) -> Result<U, Error> {
+        let lock = self.instance.clone();
+        let mut lock = lock.try_lock().map_err(|e| match e {
+            TryLockError::Poisoned(_) => anyhow::anyhow!(
+                "instance lock was poisoned; previous thread panicked while calling into wasm"
+            ),
+            TryLockError::WouldBlock => anyhow::anyhow!("cannot make reentrant calls into plugin"),
+        })?;
+        self.initialize()?;
+        if self.fuel.is_none() {
+            anyhow::bail!("fuel metering isn't enabled, use `PluginBuilder::with_fuel_limit`");
+        }
+        let default_fuel = self.fuel.replace(fuel);
+        let res = self.raw_call(&mut lock, name, input, None::<()>);
+        self.fuel = default_fuel;
+        let rc = res.map_err(|e| e.0)?;
+        if rc != 0 {
+            return Err(Error::msg(format!("Returned non-zero exit code: {rc}")));
+        }
+        self.output()
+    }
+
+    /// Like `Plugin::call`, but WASI stdin contains `stdin` instead of the stdin set using
+    /// `PluginBuilder::with_stdin`, so modules that read their input from stdin can be called. Later calls
+    /// use the builder's stdin again, or an empty stdin if it isn't set.
+    pub fn call_with_stdin<'a, 'b, T: ToBytes<'a>, U: FromBytes<'b>>(
+        &'b mut self,
+        name: impl AsRef<str>,
+        input: T,
+        stdin: impl Into<Vec<u8>>,
+    ) -> Result<U, Error> {
+        let lock = self.instance.clone();
+        let mut lock = lock.try_lock().map_err(|e| match e {
+            TryLockError::Poisoned(_) => anyhow::anyhow!(
+                "instance lock was poisoned; previous thread panicked while calling into wasm"
+            ),
+            TryLockError::WouldBlock => anyhow::anyhow!("cannot make reentrant calls into plugin"),
+        })?;
+        if self.current_plugin().wasi.is_none() {
+            anyhow::bail!("WASI isn't enabled, use `PluginBuilder::with_wasi`");
+        }
+        self.call_stdin = Some(stdin.into());
+        let res = self.raw_call(&mut lock, name, input, None::<()>);
+        self.call_stdin = None;
+        #[cfg(not(target_family = "wasm"))]
+        if let Some(wasi) = &self.current_plugin().wasi {
+            let stdin = match &self.current_plugin().wasi_sources.stdin {
+                Some(stdin) => stdin.file(),
+                None => Stdin::bytes([]).file(),
+            };
+            wasi.ctx.set_stdin(stdin);
+        }
+        let rc = res.map_err(|e| e.0)?;
+        if rc != 0 {
+            return Err(Error::msg(format!("Returned non-zero exit code: {rc}")));
+        }
+        self.output()
+    }
+
     /// Similar to `Plugin::call`, but returns the Extism error code along with the
     /// `Error`. It is assumed if `Ok(_)` is returned that the error code was `0`.
     ///
@@ -1193,19 +2554,100 @@ impl Plugin {
                 -1,
             ),
         })?;
-        let data = input.to_bytes().map_err(|e| (e, -1))?;
-        self.raw_call(&mut lock, name, data, None::<()>)
+        self.raw_call(&mut lock, name, input, None::<()>)
             .and_then(move |_| self.output().map_err(|e| (e, -1)))
     }
 
+    /// Similar to `Plugin::call`, but copies the output into `output`, replacing its contents. Reusing
+    /// the same `Vec` for many calls avoids allocating a new buffer for each output.
+    ///
+    /// `output` is left unchanged when the call fails.
+    pub fn call_into<'a, T: ToBytes<'a>>(
+        &mut self,
+        name: impl AsRef<str>,
+        input: T,
+        output: &mut Vec<u8>,
+    ) -> Result<(), Error> {
+        let data = self.call::<T, &[u8]>(name, input)?;
+        output.clear();
+        output.extend_from_slice(data);
+        Ok(())
+    }
+
+    /// Similar to `Plugin::call_into`, but decodes the output from `buf` once it has been copied.
+    /// The result can borrow from `buf` instead of the plugin, so the plugin can be called again
+    /// while it's still in use.
+    ///
+    /// ```no_run
+    /// # fn example(plugin: &mut extism::Plugin, names: Vec<&str>) -> Result<(), extism::Error> {
+    /// let mut buf = Vec::new();
+    /// for name in names {
+    ///     let output: &str = plugin.call_with_buffer("greet", name, &mut buf)?;
+    ///     println!("{output}");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_with_buffer<'a, 'b, T: ToBytes<'a>, U: FromBytes<'b>>(
+        &mut self,
+        name: impl AsRef<str>,
+        input: T,
+        buf: &'b mut Vec<u8>,
+    ) -> Result<U, Error> {
+        self.call_into(name, input, buf)?;
+        U::from_bytes(buf)
+    }
+
+    /// Interrupt calls that are still running at `deadline`, this applies to every call until
+    /// `Plugin::clear_deadline` is called. When the manifest also has a timeout the call is interrupted by
+    /// whichever expires first, and calls made after the deadline has passed are interrupted immediately.
+    /// Interrupted calls return the same error as a timeout. Deadlines aren't supported by out-of-process
+    /// or component plugins.
+    ///
+    /// ```no_run
+    /// # fn example(plugin: &mut extism::Plugin, received_at: std::time::Instant, body: &str) {
+    /// plugin.set_deadline(received_at + std::time::Duration::from_secs(2));
+    /// let res = plugin.call::<&str, &str>("handle", body);
+    /// plugin.clear_deadline();
+    /// # }
+    /// ```
+    pub fn set_deadline(&mut self, deadline: std::time::Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    /// Remove the deadline set using `Plugin::set_deadline`, only the manifest timeout applies to calls
+    /// made after this
+    pub fn clear_deadline(&mut self) {
+        self.deadline = None;
+    }
+
+    /// Returns the deadline set using `Plugin::set_deadline`
+    pub fn deadline(&self) -> Option<std::time::Instant> {
+        self.deadline
+    }
+
     /// Get a `CancelHandle`, which can be used from another thread to cancel a running plugin
     pub fn cancel_handle(&self) -> CancelHandle {
         self.cancel_handle.clone()
     }
 
+    /// Returns the process ID of the helper process for out-of-process plugins, `None` if the plugin
+    /// runs in the current process or the helper isn't running
+    #[cfg(unix)]
+    pub fn helper_process_id(&self) -> Option<u32> {
+        self.helper.as_ref().and_then(|x| x.id())
+    }
+
     pub(crate) fn clear_error(&mut self) -> Result<(), Error> {
         trace!(plugin = self.id.to_string(), "clearing error");
+        if self.zeroize {
+            use zeroize::Zeroize;
+            if let Some(msg) = &mut self.error_msg {
+                msg.zeroize();
+            }
+        }
         self.error_msg = None;
+        self.error_code = 0;
         let (linker, mut store) = self.linker_and_store();
         #[allow(clippy::needless_borrows_for_generic_args)]
         if let Some(f) = linker.get(&mut *store, EXTISM_ENV_MODULE, "error_set") {
@@ -1231,7 +2673,7 @@ impl Plugin {
     /// * `Some(u64)` - The amount of fuel consumed.
     /// * `None` - If the initial fuel or remaining fuel is not set.
     pub fn fuel_consumed(&self) -> Option<u64> {
-        self.fuel.map(|x| {
+        self.call_fuel.map(|x| {
             x.saturating_sub(
                 self.store
                     .get_fuel()
@@ -1239,6 +2681,37 @@ impl Plugin {
             )
         })
     }
+
+    /// Get the resources used by the plugin since it was created, or since the last call to
+    /// `Plugin::reset_resource_report`
+    pub fn resource_report(&self) -> ResourceReport {
+        let mut report = self.resources;
+        self.current_plugin().io.report(&mut report);
+        report
+    }
+
+    /// Get the host functions that have been called by the plugin, sorted by name
+    pub fn host_function_usage(&self) -> Vec<HostFunctionUsage> {
+        self.current_plugin().host_usage.summary()
+    }
+
+    /// Get the number of calls, time spent and memory transferred by each host function during the most
+    /// recent call, sorted by name. This can be used to tell whether a slow call spent its time in the
+    /// guest or in specific host functions.
+    pub fn host_call_stats(&self) -> Vec<HostFunctionStats> {
+        self.current_plugin().host_stats.summary()
+    }
+
+    /// Reset the host function call counts returned by `Plugin::host_function_usage`
+    pub fn reset_host_function_usage(&mut self) {
+        self.current_plugin_mut().host_usage.reset();
+    }
+
+    /// Reset all `ResourceReport` counters to zero
+    pub fn reset_resource_report(&mut self) {
+        self.resources = ResourceReport::default();
+        self.current_plugin().io.reset();
+    }
 }
 
 // Enumerates the PDK languages that need some additional initialization
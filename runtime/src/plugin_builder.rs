@@ -8,6 +8,8 @@ pub struct DebugOptions {
     pub coredump: Option<std::path::PathBuf>,
     pub memdump: Option<std::path::PathBuf>,
     pub debug_info: bool,
+    /// Resolve source locations in trap backtraces using DWARF, see `PluginBuilder::with_backtrace_details`
+    pub backtrace_details: bool,
 }
 
 impl Default for DebugOptions {
@@ -28,6 +30,7 @@ impl Default for DebugOptions {
             coredump,
             memdump,
             debug_info,
+            backtrace_details: false,
         }
     }
 }
@@ -43,11 +46,81 @@ pub struct PluginBuilder<'a> {
 #[derive(Clone)]
 pub(crate) struct PluginBuilderOptions {
     pub(crate) wasi: bool,
+    pub(crate) wasi_sources: WasiSources,
+    pub(crate) allowed_paths: Vec<(String, PathBuf)>,
+    pub(crate) memory_limit: Option<u64>,
     pub(crate) functions: Vec<Function>,
     pub(crate) debug_options: DebugOptions,
+    pub(crate) backend: Backend,
     pub(crate) cache_config: Option<Option<PathBuf>>,
+    pub(crate) cache_dir: Option<PathBuf>,
     pub(crate) fuel: Option<u64>,
     pub(crate) http_response_headers: bool,
+    #[cfg(feature = "http")]
+    pub(crate) http_client: Option<HttpClientOptions>,
+    pub(crate) host_function_limits: HostFunctionLimits,
+    pub(crate) secrets_provider: Option<std::sync::Arc<dyn SecretsProvider>>,
+    pub(crate) redactor: Option<std::sync::Arc<dyn Redactor>>,
+    pub(crate) log_handler: Option<std::sync::Arc<dyn LogHandler>>,
+    pub(crate) kv_store: Option<std::sync::Arc<dyn KvStore>>,
+    pub(crate) var_store: Option<std::sync::Arc<dyn VarStore>>,
+    pub(crate) sql_database: Option<(std::sync::Arc<dyn SqlDatabase>, String)>,
+    pub(crate) object_store: Option<(std::sync::Arc<dyn ObjectStore>, String)>,
+    pub(crate) message_broker: Option<std::sync::Arc<dyn MessageBroker>>,
+    pub(crate) module_policy: ModulePolicy,
+    pub(crate) wasm_resolvers: resolver::WasmResolvers,
+    pub(crate) precompiled: bool,
+    pub(crate) event_bus: Option<EventBus>,
+    pub(crate) metrics: Option<std::sync::Arc<dyn Metrics>>,
+    pub(crate) hooks: Option<std::sync::Arc<dyn PluginHooks>>,
+    pub(crate) hardening: Option<Hardening>,
+    pub(crate) call_log: Option<CallLog>,
+    pub(crate) sandbox_profile: Option<SandboxProfile>,
+    pub(crate) deprecated_functions: BTreeMap<String, String>,
+    pub(crate) zeroize: bool,
+    pub(crate) stall_threshold: Option<std::time::Duration>,
+    pub(crate) kernel_memory_policy: KernelMemoryPolicy,
+    pub(crate) wasm_threads: bool,
+    pub(crate) max_wasm_threads: Option<usize>,
+    #[cfg(feature = "guest-profiler")]
+    pub(crate) guest_profiler: Option<std::time::Duration>,
+    pub(crate) lazy: bool,
+    pub(crate) cow_reset: bool,
+    pub(crate) deterministic: bool,
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) pooling_allocator: Option<PoolingAllocator>,
+    #[cfg(unix)]
+    pub(crate) out_of_process: Option<OutOfProcess>,
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) mock_host: Option<testing::MockHost>,
+    #[cfg(feature = "websocket")]
+    pub(crate) websockets: Option<WebSocketLimits>,
+    #[cfg(feature = "wasi-nn")]
+    pub(crate) wasi_nn: Option<WasiNn>,
+}
+
+impl PluginBuilderOptions {
+    /// Returns `true` if any builder options need to be applied to the manifest
+    pub(crate) fn modifies_manifest(&self) -> bool {
+        !self.allowed_paths.is_empty() || self.memory_limit.is_some()
+    }
+
+    // Add the paths from `PluginBuilder::with_allowed_path` and the memory limit to the manifest
+    pub(crate) fn apply_manifest(&self, manifest: &mut Manifest) {
+        if !self.allowed_paths.is_empty() {
+            let paths = manifest.allowed_paths.get_or_insert_with(BTreeMap::new);
+            for (host, guest) in self.allowed_paths.iter() {
+                paths.insert(host.clone(), guest.clone());
+            }
+        }
+
+        // The lower of the two limits is used
+        if let Some(bytes) = self.memory_limit {
+            let pages = (bytes / 65536).min(u32::MAX as u64) as u32;
+            let max = &mut manifest.memory.max_pages;
+            *max = Some(max.map_or(pages, |x| x.min(pages)));
+        }
+    }
 }
 
 impl<'a> PluginBuilder<'a> {
@@ -58,11 +131,57 @@ impl<'a> PluginBuilder<'a> {
             config: None,
             options: PluginBuilderOptions {
                 wasi: false,
+                wasi_sources: WasiSources::default(),
+                allowed_paths: vec![],
+                memory_limit: None,
                 functions: vec![],
                 debug_options: DebugOptions::default(),
+                backend: Backend::default(),
                 cache_config: None,
+                cache_dir: None,
                 fuel: None,
                 http_response_headers: false,
+                #[cfg(feature = "http")]
+                http_client: None,
+                host_function_limits: HostFunctionLimits::default(),
+                secrets_provider: None,
+                redactor: None,
+                log_handler: None,
+                kv_store: None,
+                var_store: None,
+                sql_database: None,
+                object_store: None,
+                message_broker: None,
+                module_policy: ModulePolicy::default(),
+                wasm_resolvers: Default::default(),
+                precompiled: false,
+                event_bus: None,
+                metrics: None,
+                hooks: None,
+                hardening: None,
+                call_log: None,
+                sandbox_profile: None,
+                deprecated_functions: BTreeMap::new(),
+                zeroize: false,
+                stall_threshold: None,
+                kernel_memory_policy: KernelMemoryPolicy::default(),
+                wasm_threads: false,
+                max_wasm_threads: None,
+                #[cfg(feature = "guest-profiler")]
+                guest_profiler: None,
+                lazy: false,
+                cow_reset: false,
+                deterministic: false,
+                #[cfg(not(target_family = "wasm"))]
+                pooling_allocator: None,
+                #[cfg(unix)]
+                out_of_process: None,
+                #[cfg(not(target_family = "wasm"))]
+                mock_host: None,
+                #[cfg(feature = "websocket")]
+                websockets: None,
+                #[cfg(feature = "wasi-nn")]
+                wasi_nn: None,
             },
         }
     }
@@ -73,6 +192,101 @@ impl<'a> PluginBuilder<'a> {
         self
     }
 
+    /// Use `clock` instead of the system clock for WASI time functions and sleeps, for example a
+    /// `FixedClock` to make plugin output reproducible
+    pub fn with_wasi_clock(mut self, clock: impl WasiClock + 'static) -> Self {
+        self.options.wasi_sources.clock = Some(std::sync::Arc::new(clock));
+        self
+    }
+
+    /// Use `random` instead of the system random number generator for WASI `random_get`, for example a
+    /// `SeededRandom` to make plugin output reproducible
+    pub fn with_wasi_random(mut self, random: impl WasiRandom + 'static) -> Self {
+        self.options.wasi_sources.random = Some(std::sync::Arc::new(random));
+        self
+    }
+
+    /// Provide WASI stdin for command-style modules that read their input from stdin, `stdin` can be bytes
+    /// that are read from the start on every call or a `Stdin::reader`. Use `Plugin::call_with_stdin` to
+    /// provide stdin for a single call.
+    pub fn with_stdin(mut self, stdin: impl Into<Stdin>) -> Self {
+        self.options.wasi_sources.stdin = Some(stdin.into());
+        self
+    }
+
+    /// Write guest output on WASI stdout to `w`, it's shared by every plugin created from this builder,
+    /// so output from plugins that are called concurrently may be interleaved
+    pub fn with_stdout(mut self, w: impl std::io::Write + Send + 'static) -> Self {
+        self.options.wasi_sources.stdout = Some(stdio::SharedWriter::new(w));
+        self
+    }
+
+    /// Write guest output on WASI stderr to `w`, see `PluginBuilder::with_stdout`
+    pub fn with_stderr(mut self, w: impl std::io::Write + Send + 'static) -> Self {
+        self.options.wasi_sources.stderr = Some(stdio::SharedWriter::new(w));
+        self
+    }
+
+    /// Buffer guest output on WASI stdout and stderr for each call, so it can be read using
+    /// `Plugin::take_stdout` and `Plugin::take_stderr`. The buffers are cleared when a call starts, and
+    /// writers set using `with_stdout` and `with_stderr` aren't used while capture is enabled. At most 1MiB
+    /// is kept from each stream, see `PluginBuilder::with_output_capture_limit`.
+    pub fn with_output_capture(mut self, enable: bool) -> Self {
+        self.options.wasi_sources.capture_output = enable;
+        self
+    }
+
+    /// Set the max number of bytes captured from each of stdout and stderr during a call when
+    /// `PluginBuilder::with_output_capture` is enabled, output written past the limit is dropped
+    pub fn with_output_capture_limit(mut self, bytes: usize) -> Self {
+        self.options.wasi_sources.capture_limit = Some(bytes);
+        self
+    }
+
+    /// Make the directory `host` available to the plugin at `guest` when WASI is enabled, this is added
+    /// to the manifest's `allowed_paths`
+    pub fn with_allowed_path(mut self, host: impl Into<String>, guest: impl Into<PathBuf>) -> Self {
+        self.options.allowed_paths.push((host.into(), guest.into()));
+        self
+    }
+
+    /// Like `PluginBuilder::with_allowed_path`, but the plugin can't create, modify or remove anything in
+    /// the directory
+    pub fn with_readonly_path(
+        mut self,
+        host: impl Into<String>,
+        guest: impl Into<PathBuf>,
+    ) -> Self {
+        self.options
+            .allowed_paths
+            .push((format!("ro:{}", host.into()), guest.into()));
+        self
+    }
+
+    /// Limit the linear memory of each plugin instance to `bytes`, rounded down to a multiple of the 64KiB
+    /// page size. This is combined with the manifest's `memory.max_pages` and the lower limit is used,
+    /// calls that try to grow memory past the limit fail with an `OutOfMemory` error
+    pub fn with_memory_limit(mut self, bytes: u64) -> Self {
+        self.options.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Enables the `wasi_ephemeral_nn` host functions, this allows plugins to run inference using the
+    /// ML backends available on the host
+    #[cfg(feature = "wasi-nn")]
+    pub fn with_wasi_nn(mut self, wasi_nn: WasiNn) -> Self {
+        self.options.wasi_nn = Some(wasi_nn);
+        self
+    }
+
+    /// Enables the `extism:host/ws` functions, which allow plugins to open WebSocket connections to the
+    /// manifest's `allowed_hosts`
+    #[cfg(feature = "websocket")]
+    pub fn with_websockets(mut self, limits: WebSocketLimits) -> Self {
+        self.options.websockets = Some(limits);
+        self
+    }
+
     /// Add a single host function
     pub fn with_function<T: 'static, F>(
         mut self,
@@ -116,6 +330,18 @@ impl<'a> PluginBuilder<'a> {
         self
     }
 
+    /// Add a host function that takes one value and returns one value, converted using `FromBytesOwned`
+    /// and `ToBytes`, see `Function::typed`
+    pub fn with_typed_function<I, O, F>(mut self, name: impl Into<String>, f: F) -> Self
+    where
+        I: FromBytesOwned + 'static,
+        O: ToBytes<'static> + 'static,
+        F: 'static + Fn(I) -> Result<O, Error> + Sync + Send,
+    {
+        self.options.functions.push(Function::typed(name, f));
+        self
+    }
+
     /// Add multiple host functions
     pub fn with_functions(mut self, f: impl IntoIterator<Item = Function>) -> Self {
         self.options.functions.extend(f);
@@ -128,6 +354,18 @@ impl<'a> PluginBuilder<'a> {
         self
     }
 
+    /// Profile plugin calls, `Profiling::Native` is the same as `with_profiling_strategy`.
+    /// `Profiling::Guest` samples each call using wasmtime's in-process profiler, the profile for the most
+    /// recent call is available from `Plugin::take_profile`
+    pub fn with_profiling(mut self, profiling: Profiling) -> Self {
+        match profiling {
+            Profiling::Native(p) => self.options.debug_options.profiling_strategy = p,
+            #[cfg(feature = "guest-profiler")]
+            Profiling::Guest { interval } => self.options.guest_profiler = Some(interval),
+        }
+        self
+    }
+
     /// Enable Wasmtime coredump on trap
     pub fn with_coredump(mut self, path: impl Into<std::path::PathBuf>) -> Self {
         self.options.debug_options.coredump = Some(path.into());
@@ -146,6 +384,14 @@ impl<'a> PluginBuilder<'a> {
         self
     }
 
+    /// Include source locations from the module's DWARF debug info in the `GuestBacktrace` attached to errors
+    /// when a call traps, this makes compilation slower. It can also be enabled by setting
+    /// `WASMTIME_BACKTRACE_DETAILS=1`.
+    pub fn with_backtrace_details(mut self) -> Self {
+        self.options.debug_options.backtrace_details = true;
+        self
+    }
+
     /// Configure debug options
     pub fn with_debug_options(mut self, options: DebugOptions) -> Self {
         self.options.debug_options = options;
@@ -155,12 +401,38 @@ impl<'a> PluginBuilder<'a> {
     /// Set wasmtime compilation cache config path
     pub fn with_cache_config(mut self, dir: impl Into<PathBuf>) -> Self {
         self.options.cache_config = Some(Some(dir.into()));
+        self.options.cache_dir = None;
+        self
+    }
+
+    /// Cache compiled modules in `dir`, the cache can be shared by plugins in other processes. Entries are
+    /// keyed by a hash of the module, the engine configuration and the wasmtime version, so changing any of
+    /// them causes the module to be recompiled instead of loading a stale entry. This takes precedence over
+    /// `EXTISM_CACHE_CONFIG` and the system cache config
+    pub fn with_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.cache_dir = Some(dir.into());
+        self.options.cache_config = None;
         self
     }
 
     /// Turn wasmtime compilation caching off
     pub fn with_cache_disabled(mut self) -> Self {
         self.options.cache_config = Some(None);
+        self.options.cache_dir = None;
+        self
+    }
+
+    /// Allocate instances using wasmtime's pooling allocator, which reuses pre-allocated memory instead
+    /// of mapping new memory for each instance, see `PoolingAllocator`
+    #[cfg(not(target_family = "wasm"))]
+    pub fn with_pooling_allocator(mut self, pooling: PoolingAllocator) -> Self {
+        self.options.pooling_allocator = Some(pooling);
+        self
+    }
+
+    /// Select the execution backend, the default is `Backend::Compiler`
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.options.backend = backend;
         self
     }
 
@@ -194,6 +466,283 @@ impl<'a> PluginBuilder<'a> {
         self
     }
 
+    /// Configure the HTTP client used by `extism:host/env::http_request`, for example to use a proxy or
+    /// custom root certificates
+    #[cfg(feature = "http")]
+    pub fn with_http_client(mut self, options: HttpClientOptions) -> Self {
+        self.options.http_client = Some(options);
+        self
+    }
+
+    /// Limit how often the plugin may invoke host functions, see [HostFunctionLimits]
+    pub fn with_host_function_limits(mut self, limits: HostFunctionLimits) -> Self {
+        self.options.host_function_limits = limits;
+        self
+    }
+
+    /// Set the `SecretsProvider` used to resolve `secret://` config values
+    pub fn with_secrets_provider(mut self, provider: impl SecretsProvider + 'static) -> Self {
+        self.options.secrets_provider = Some(std::sync::Arc::new(provider));
+        self
+    }
+
+    /// Set the `Redactor` used to sanitize plugin inputs, outputs, log messages, host function arguments
+    /// and error messages before they're logged or returned
+    pub fn with_redactor(mut self, redactor: impl Redactor + 'static) -> Self {
+        self.options.redactor = Some(std::sync::Arc::new(redactor));
+        self
+    }
+
+    /// Send messages logged by the plugin to `handler` in addition to `tracing`. Plugins are told to log
+    /// at every level while a handler is set, since the handler isn't limited by the `tracing` filter.
+    pub fn with_log_handler(mut self, handler: impl LogHandler + 'static) -> Self {
+        self.options.log_handler = Some(std::sync::Arc::new(handler));
+        self
+    }
+
+    /// Enable the `extism:host/kv` host functions using the given `KvStore`, the same store can be shared
+    /// between plugins by passing an `Arc`
+    pub fn with_kv_store(mut self, store: impl KvStore + 'static) -> Self {
+        self.options.kv_store = Some(std::sync::Arc::new(store));
+        self
+    }
+
+    /// Store plugin variables in `store` instead of in memory, for example a `KvVarStore` to persist them
+    /// across restarts. `memory.max_var_bytes` limits the size of the store.
+    pub fn with_var_store(mut self, store: impl VarStore + 'static) -> Self {
+        self.options.var_store = Some(std::sync::Arc::new(store));
+        self
+    }
+
+    /// Enable the `extism:host/sql` host functions using the given `SqlDatabase`, all statements are
+    /// executed in `namespace`, which must only contain ASCII letters, digits and underscores. Plugins
+    /// that should share data need to use the same namespace.
+    pub fn with_sql_database(
+        mut self,
+        database: impl SqlDatabase + 'static,
+        namespace: impl Into<String>,
+    ) -> Self {
+        self.options.sql_database = Some((std::sync::Arc::new(database), namespace.into()));
+        self
+    }
+
+    /// Enable the `extism:host/object` host functions using the given `ObjectStore`. Keys used by the
+    /// plugin are prepended with `prefix` (for example `"tenant-a/"`), so plugins with different prefixes
+    /// can share a store or bucket without seeing each other's objects.
+    pub fn with_object_store(
+        mut self,
+        store: impl ObjectStore + 'static,
+        prefix: impl Into<String>,
+    ) -> Self {
+        self.options.object_store = Some((std::sync::Arc::new(store), prefix.into()));
+        self
+    }
+
+    /// Enable the `extism:host/msg` host functions using the given `MessageBroker`, plugins can publish
+    /// messages and poll their subscriptions. Subscriptions are kept until the plugin is dropped or calls
+    /// `unsubscribe`. `poll` blocks the calling plugin while it waits, so long poll timeouts delay
+    /// cancellation and timeouts until it returns.
+    pub fn with_message_broker(mut self, broker: impl MessageBroker + 'static) -> Self {
+        self.options.message_broker = Some(std::sync::Arc::new(broker));
+        self
+    }
+
+    /// Set the `ModulePolicy` used to accept or reject Wasm modules by hash
+    pub fn with_module_policy(mut self, policy: ModulePolicy) -> Self {
+        self.options.module_policy = policy;
+        self
+    }
+
+    /// Fetch `Wasm::url` modules with a URL that starts with `scheme:` using `resolver`, for example
+    /// `s3` for `s3://bucket/plugin.wasm`. Schemes are case-insensitive, registering a resolver for
+    /// `http` or `https` replaces the built-in HTTP client for those URLs.
+    pub fn with_wasm_resolver(
+        mut self,
+        scheme: impl AsRef<str>,
+        resolver: impl WasmResolver + 'static,
+    ) -> Self {
+        self.options.wasm_resolvers.insert(
+            scheme.as_ref().to_ascii_lowercase(),
+            std::sync::Arc::new(resolver),
+        );
+        self
+    }
+
+    /// Load modules that were serialized using `CompiledPlugin::serialize_module` instead of compiling
+    /// them. Precompiled files are read and checked against the manifest hash, signature and
+    /// `ModulePolicy` before they're loaded, they must be created with the same version of Extism and the
+    /// same engine configuration. Precompiled files without a hash or signature are memory mapped instead
+    /// of being read when there's no `ModulePolicy` or call log, they must not be modified while the
+    /// plugin is loaded.
+    ///
+    /// # Safety
+    ///
+    /// Precompiled modules contain native code which is executed without being validated, they must
+    /// only be loaded from trusted sources. A `ModulePolicy` can be used to restrict them by hash.
+    pub unsafe fn with_precompiled_modules(mut self, enabled: bool) -> Self {
+        self.options.precompiled = enabled;
+        self
+    }
+
+    /// Publish lifecycle events to the given `EventBus`, the same `EventBus` can be shared between many plugins
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.options.event_bus = Some(events);
+        self
+    }
+
+    /// Report call counts, durations, traps, timeouts and memory growth to `metrics`, an `Arc` can be used
+    /// to share the same `Metrics` between many plugins
+    pub fn with_metrics(mut self, metrics: impl Metrics) -> Self {
+        self.options.metrics = Some(std::sync::Arc::new(metrics));
+        self
+    }
+
+    /// Call `hooks` when the plugin is instantiated, when calls start and end, on traps and timeouts, and
+    /// when its memory is reset, see `PluginHooks`
+    pub fn with_hooks(mut self, hooks: impl PluginHooks) -> Self {
+        self.options.hooks = Some(std::sync::Arc::new(hooks));
+        self
+    }
+
+    /// Apply OS-level `Hardening` restrictions while the plugin is executing
+    pub fn with_hardening(mut self, hardening: Hardening) -> Self {
+        self.options.hardening = Some(hardening);
+        self
+    }
+
+    /// Record every call made to the plugin in a tamper-evident `CallLog`
+    pub fn with_call_log(mut self, log: CallLog) -> Self {
+        self.options.call_log = Some(log);
+        self
+    }
+
+    /// Apply a `SandboxProfile`, this configures WASI, fuel, hardening and any limits that aren't set
+    /// in the manifest. Builder methods called after this will override settings from the profile.
+    pub fn with_sandbox_profile(mut self, profile: SandboxProfile) -> Self {
+        profile.apply_options(&mut self.options);
+        self.options.sandbox_profile = Some(profile);
+        self
+    }
+
+    /// Mark a host function as deprecated, this can be used for `extism:host/env` functions and
+    /// user-defined host functions. A warning is logged the first time each plugin calls the function.
+    pub fn with_deprecated_host_function(
+        mut self,
+        name: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        self.options
+            .deprecated_functions
+            .insert(name.into(), message.into());
+        self
+    }
+
+    /// Overwrite plugin memory with zeroes when it's no longer needed, this should be enabled for plugins
+    /// that handle credentials or other sensitive data. When enabled:
+    /// - the copy of the input stored in kernel memory is zeroed as soon as each call returns
+    /// - kernel memory is zeroed before each call, so earlier outputs and errors are removed
+    /// - plugin memory, kernel memory and plugin variables are zeroed when the plugin is dropped, when
+    ///   its store is reset or when `Plugin::reset` is called, the plugin is instantiated again by the
+    ///   next call after `Plugin::reset`
+    ///
+    /// The output of a call is kept until the next call, or until the plugin is dropped, and buffers
+    /// passed to `Plugin::call` are owned by the caller and are not modified.
+    pub fn with_zeroize_memory(mut self, zeroize: bool) -> Self {
+        self.options.zeroize = zeroize;
+        self
+    }
+
+    /// Defer instantiation until the first call, `Plugin::new` only compiles and validates the plugin.
+    /// WASI, linked modules and plugin memory aren't set up until the plugin is called, so idle plugins
+    /// use very little memory. Errors from instantiation are returned by the first call instead.
+    pub fn with_lazy_instantiation(mut self, lazy: bool) -> Self {
+        self.options.lazy = lazy;
+        self
+    }
+
+    /// Make `Plugin::reset` restore the plugin's memory and exported globals to their state right after
+    /// instantiation, plugins returned to a `Pool` are also reset. Memory is copied back from a snapshot
+    /// taken after instantiation, so the module's start and initialization functions don't run again.
+    /// Memory can't shrink, so a plugin whose memory has grown is instantiated again instead, which maps
+    /// the module's data segments from wasmtime's copy-on-write memory image.
+    ///
+    /// Globals that aren't exported can't be restored, so the plugin is instantiated again after any
+    /// failed call.
+    pub fn with_copy_on_write_reset(mut self, enable: bool) -> Self {
+        self.options.cow_reset = enable;
+        self
+    }
+
+    /// Run plugins deterministically, so the same inputs always produce the same outputs:
+    ///
+    /// - WASI clocks start at the Unix epoch and only advance when the plugin sleeps, and WASI random
+    ///   bytes are generated from a fixed seed. Both are reset along with WASI, a clock or random number
+    ///   generator set using `with_wasi_clock` or `with_wasi_random` is used as-is
+    /// - NaN results of floating point operations are canonicalized
+    /// - relaxed SIMD instructions use their deterministic semantics
+    /// - the threads proposal is disabled
+    /// - manifests that allow HTTP requests or sockets are rejected
+    pub fn with_deterministic(mut self, enable: bool) -> Self {
+        self.options.deterministic = enable;
+        self.options.wasi_sources.deterministic = enable;
+        self
+    }
+
+    /// Log a warning, including a Wasm backtrace and the amount of fuel consumed, when a call runs for longer
+    /// than `threshold`. The call is not interrupted, `PluginEvent::Stalled` is also emitted if an `EventBus`
+    /// has been configured.
+    pub fn with_stall_threshold(mut self, threshold: std::time::Duration) -> Self {
+        self.options.stall_threshold = Some(threshold);
+        self
+    }
+
+    /// Release the memory used by the Extism kernel after calls according to `policy`, so long-lived
+    /// plugins don't keep the memory used by their largest call
+    pub fn with_kernel_memory_policy(mut self, policy: KernelMemoryPolicy) -> Self {
+        self.options.kernel_memory_policy = policy;
+        self
+    }
+
+    /// Enable the Wasm threads proposal and shared memories, and link `wasi::thread-spawn` so plugins can
+    /// spawn guest threads. Modules that use shared memory or spawn threads are only loaded when the
+    /// manifest grants the `threads` capability, see `Manifest::with_capability`.
+    ///
+    /// Spawned threads have their own instance of the plugin and Extism kernel, they share the plugin's
+    /// shared memories and WASI context. Spawned threads use the timeout, deadline and `CancelHandle` of
+    /// the call that spawned them, and threads that are still running when the call returns are
+    /// interrupted. When a fuel limit is set each thread is given half of the remaining fuel of the thread
+    /// that spawned it.
+    pub fn with_wasm_threads(mut self, enable: bool) -> Self {
+        self.options.wasm_threads = enable;
+        self
+    }
+
+    /// Set the max number of guest threads that can run at the same time, `wasi::thread-spawn` returns an
+    /// error when the limit is reached. This defaults to the number of available CPUs.
+    pub fn with_max_wasm_threads(mut self, n: usize) -> Self {
+        self.options.max_wasm_threads = Some(n);
+        self
+    }
+
+    /// Run the plugin in a separate helper process, calls are forwarded to the helper so a crash or
+    /// memory blow-up in the plugin doesn't affect the current process. Host functions aren't supported
+    /// and only the manifest, WASI and fuel settings apply to the helper, see `OutOfProcess`
+    #[cfg(unix)]
+    pub fn with_out_of_process(mut self, helper: OutOfProcess) -> Self {
+        self.options.out_of_process = Some(helper);
+        self
+    }
+
+    /// Use a `testing::MockHost` for HTTP requests, logs, host functions and the filesystem (when WASI is
+    /// enabled), so plugins can be tested without network or disk access
+    #[cfg(not(target_family = "wasm"))]
+    pub fn with_mock_host(mut self, host: &testing::MockHost) -> Self {
+        self.options.functions.extend(host.functions());
+        self.options.wasi_sources.memory_fs = Some(host.memory_fs().clone());
+        self.options.mock_host = Some(host.clone());
+        self
+    }
+
     /// Generate a new plugin with the configured settings
     pub fn build(self) -> Result<Plugin, Error> {
         Plugin::new_from_compiled(&CompiledPlugin::new(self)?)
@@ -0,0 +1,91 @@
+use std::collections::BTreeSet;
+
+use sha2::Digest;
+
+use crate::*;
+
+/// `ModulePolicy` determines which Wasm modules may be loaded, based on the SHA-256 hash of the module
+/// bytes.
+///
+/// The policy is applied to every module a plugin loads, regardless of whether it came from a file, a URL
+/// or was passed in directly as bytes. Denied hashes always take precedence over allowed hashes, which makes
+/// it possible to revoke a specific plugin version.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct ModulePolicy {
+    /// When set, only modules with one of these hashes may be loaded
+    pub allowed: Option<BTreeSet<String>>,
+
+    /// Modules with these hashes will never be loaded
+    pub denied: BTreeSet<String>,
+}
+
+impl ModulePolicy {
+    /// Create a new `ModulePolicy` that accepts all modules
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add a hash to the allow-list, once a hash has been allowed all other modules will be rejected
+    pub fn with_allowed_hash(mut self, hash: impl AsRef<str>) -> Self {
+        self.allowed
+            .get_or_insert_with(Default::default)
+            .insert(hash.as_ref().to_lowercase());
+        self
+    }
+
+    /// Add multiple hashes to the allow-list
+    pub fn with_allowed_hashes(
+        mut self,
+        hashes: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Self {
+        for hash in hashes {
+            self = self.with_allowed_hash(hash);
+        }
+        self
+    }
+
+    /// Add a hash to the deny-list
+    pub fn with_denied_hash(mut self, hash: impl AsRef<str>) -> Self {
+        self.denied.insert(hash.as_ref().to_lowercase());
+        self
+    }
+
+    /// Add multiple hashes to the deny-list
+    pub fn with_denied_hashes(mut self, hashes: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        for hash in hashes {
+            self = self.with_denied_hash(hash);
+        }
+        self
+    }
+
+    /// Returns `true` if the policy accepts every module
+    pub fn is_empty(&self) -> bool {
+        self.allowed.is_none() && self.denied.is_empty()
+    }
+
+    /// Check a hex-encoded SHA-256 hash against the policy
+    pub fn check_hash(&self, hash: &str) -> Result<(), Error> {
+        let hash = hash.to_lowercase();
+        if self.denied.contains(&hash) {
+            anyhow::bail!("Wasm module {hash} has been denied by the module policy");
+        }
+
+        if let Some(allowed) = &self.allowed {
+            if !allowed.contains(&hash) {
+                anyhow::bail!("Wasm module {hash} is not allowed by the module policy");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check the module data against the policy
+    pub fn check(&self, data: &[u8]) -> Result<(), Error> {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let digest = sha2::Sha256::digest(data);
+        self.check_hash(&manifest::hex(&digest))
+    }
+}
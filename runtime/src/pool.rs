@@ -1,16 +1,61 @@
-use crate::{Error, FromBytesOwned, Plugin, ToBytes};
+use crate::{Error, FromBytesOwned, Metrics, Plugin, ToBytes};
 
 use std::{
     collections::{HashMap, VecDeque},
-    sync::{Arc, Condvar, Mutex, RwLock, Weak},
+    sync::{Arc, Condvar, Mutex, MutexGuard, RwLock, Weak},
+    task::Waker,
+    time::Instant,
 };
 
+type HealthCheck = dyn Fn(&mut Plugin) -> Result<(), Error> + Send + Sync;
+type RolloverCallback = dyn Fn(u64) + Send + Sync;
+
 /// `PoolBuilder` is used to configure and create `Pool`s
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct PoolBuilder {
     /// Max number of concurrent instances for a plugin - by default this is set to the output of
     /// `std::thread::available_parallelism`
     pub max_instances: usize,
+
+    /// Number of instances created in the background when the pool is created, so the first calls don't
+    /// have to wait for a plugin to be instantiated - by default this is `0`
+    pub min_instances: usize,
+
+    /// Number of calls after which an instance is discarded instead of being returned to the pool - by
+    /// default instances are reused indefinitely
+    pub max_calls_per_instance: Option<u64>,
+
+    /// How long an instance is kept after it was created, older instances are discarded instead of
+    /// being returned to the pool or checked out - by default there's no limit
+    pub max_instance_age: Option<std::time::Duration>,
+
+    /// When `true`, callers waiting for an instance are served in the order they called `Pool::get`, see
+    /// `PoolBuilder::with_fair_checkout` - by default this is `false`
+    pub fair: bool,
+
+    /// The priority of waiting callers is raised by one each time this much time has passed, so callers with
+    /// a low priority aren't starved - by default this is one second
+    pub priority_aging: Option<std::time::Duration>,
+
+    health_check: Option<Arc<HealthCheck>>,
+    on_rollover: Option<Arc<RolloverCallback>>,
+    metrics: Option<Arc<dyn Metrics>>,
+}
+
+impl std::fmt::Debug for PoolBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PoolBuilder")
+            .field("max_instances", &self.max_instances)
+            .field("min_instances", &self.min_instances)
+            .field("max_calls_per_instance", &self.max_calls_per_instance)
+            .field("max_instance_age", &self.max_instance_age)
+            .field("fair", &self.fair)
+            .field("priority_aging", &self.priority_aging)
+            .field("health_check", &self.health_check.is_some())
+            .field("on_rollover", &self.on_rollover.is_some())
+            .field("metrics", &self.metrics.is_some())
+            .finish()
+    }
 }
 
 impl PoolBuilder {
@@ -25,6 +70,67 @@ impl PoolBuilder {
         self
     }
 
+    /// Set the number of instances to create when the pool is created, see `Pool::warm`. On wasm32
+    /// hosts the instances are created before `PoolBuilder::build` returns.
+    pub fn with_min_instances(mut self, n: usize) -> Self {
+        self.min_instances = n;
+        self
+    }
+
+    /// Discard instances after they've been called `n` times, a new instance is created the next time
+    /// one is needed
+    pub fn with_max_calls_per_instance(mut self, n: u64) -> Self {
+        self.max_calls_per_instance = Some(n);
+        self
+    }
+
+    /// Discard instances once they're older than `age`, a new instance is created the next time one is
+    /// needed
+    pub fn with_max_instance_age(mut self, age: std::time::Duration) -> Self {
+        self.max_instance_age = Some(age);
+        self
+    }
+
+    /// Serve callers waiting for an instance in the order they arrived, by default any waiting caller may
+    /// get the next instance that's returned. Callers with a higher priority are still served first, see
+    /// `Pool::get_with_priority`
+    pub fn with_fair_checkout(mut self, fair: bool) -> Self {
+        self.fair = fair;
+        self
+    }
+
+    /// Raise the priority of waiting callers by one each time `interval` has passed, `None` disables
+    /// aging so callers with a low priority can wait indefinitely while higher priority work is queued
+    pub fn with_priority_aging(mut self, interval: Option<std::time::Duration>) -> Self {
+        self.priority_aging = interval;
+        self
+    }
+
+    /// Run `f` each time an instance is returned to the pool, instances are discarded when it returns an
+    /// error. `f` runs on the thread that returns the instance, before it can be checked out again.
+    pub fn with_health_check(
+        mut self,
+        f: impl Fn(&mut Plugin) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        self.health_check = Some(Arc::new(f));
+        self
+    }
+
+    /// Run `f` once every instance created before a call to `Pool::replace_source` has been discarded, `f`
+    /// is passed the generation returned by `Pool::replace_source`. If the source is replaced again before
+    /// the old instances are gone, `f` is only called for the latest generation.
+    pub fn with_rollover_callback(mut self, f: impl Fn(u64) + Send + Sync + 'static) -> Self {
+        self.on_rollover = Some(Arc::new(f));
+        self
+    }
+
+    /// Report how long `Pool::get` and `Pool::get_async` wait for an instance to `metrics`, plugin metrics
+    /// are configured using `PluginBuilder::with_metrics` in the pool's source function
+    pub fn with_metrics(mut self, metrics: impl Metrics) -> Self {
+        self.metrics = Some(Arc::new(metrics));
+        self
+    }
+
     /// Create a new `Pool` with the given configuration
     pub fn build<F: 'static + Fn() -> Result<Plugin, Error> + Send + Sync>(
         self,
@@ -40,20 +146,186 @@ impl Default for PoolBuilder {
             max_instances: std::thread::available_parallelism()
                 .expect("available parallelism")
                 .into(),
+            min_instances: 0,
+            max_calls_per_instance: None,
+            max_instance_age: None,
+            fair: false,
+            priority_aging: Some(std::time::Duration::from_secs(1)),
+            health_check: None,
+            on_rollover: None,
+            metrics: None,
         }
     }
 }
 
 type PluginSource = dyn Fn() -> Result<Plugin, Error> + Send + Sync;
 
+/// An instance owned by the pool, along with the counters used to decide when it's recycled
+struct Pooled {
+    plugin: Plugin,
+    created: Instant,
+    calls: u64,
+    /// The source generation the instance was created from
+    generation: u64,
+}
+
+impl Pooled {
+    fn new(plugin: Plugin, generation: u64) -> Self {
+        Pooled {
+            plugin,
+            created: Instant::now(),
+            calls: 0,
+            generation,
+        }
+    }
+}
+
+/// The `PoolBuilder` options that decide when instances are discarded
+struct Recycle {
+    max_calls: Option<u64>,
+    max_age: Option<std::time::Duration>,
+    health_check: Option<Arc<HealthCheck>>,
+    on_rollover: Option<Arc<RolloverCallback>>,
+}
+
+impl Recycle {
+    // Called without holding the pool lock, once the last instance from an older generation is gone
+    fn rolled_over(&self, generation: Option<u64>) {
+        if let (Some(f), Some(generation)) = (&self.on_rollover, generation) {
+            f(generation)
+        }
+    }
+
+    fn expired(&self, created: Instant) -> bool {
+        self.max_age.is_some_and(|age| created.elapsed() >= age)
+    }
+
+    // Get the reason an instance that's being returned to the pool should be discarded
+    fn check(&self, pooled: &mut Pooled) -> Option<String> {
+        if self.max_calls.is_some_and(|n| pooled.calls >= n) {
+            return Some(format!("reached {} calls", pooled.calls));
+        }
+        if self.expired(pooled.created) {
+            return Some("reached max age".to_string());
+        }
+        if let Some(f) = &self.health_check {
+            if let Err(e) = f(&mut pooled.plugin) {
+                return Some(format!("failed health check: {e:#}"));
+            }
+        }
+        None
+    }
+}
+
+/// A caller waiting for an instance
+struct Waiter {
+    ticket: u64,
+    priority: i32,
+    since: Instant,
+}
+
 struct PoolInner {
-    plugin_source: Box<PluginSource>,
+    plugin_source: Arc<PluginSource>,
     /// Available plugins ready to be checked out
-    available: VecDeque<Plugin>,
+    available: VecDeque<Pooled>,
     /// Current number of plugins (checked out + available)
     current_size: usize,
     /// Maximum number of plugins
     max_size: usize,
+    /// Tasks waiting in `Pool::get_async`
+    wakers: Vec<Waker>,
+    /// Incremented by `Pool::replace_source`
+    generation: u64,
+    /// Number of instances (checked out or being created) from older generations
+    draining: usize,
+    /// Callers in `Pool::get` and `Pool::get_async`, in the order they arrived
+    waiters: Vec<Waiter>,
+    next_ticket: u64,
+    fair: bool,
+    priority_aging: Option<std::time::Duration>,
+}
+
+impl PoolInner {
+    // Remove an instance from an older generation, the current generation is returned if it was the last
+    // one
+    fn drained(&mut self) -> Option<u64> {
+        self.current_size -= 1;
+        self.draining -= 1;
+        (self.draining == 0).then_some(self.generation)
+    }
+
+    fn enqueue(&mut self, priority: i32) -> u64 {
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        self.waiters.push(Waiter {
+            ticket,
+            priority,
+            since: Instant::now(),
+        });
+        ticket
+    }
+
+    fn dequeue(&mut self, ticket: u64) {
+        self.waiters.retain(|w| w.ticket != ticket);
+    }
+
+    // The priority of a waiter, including the increase from waiting
+    fn priority(&self, waiter: &Waiter, now: Instant) -> i64 {
+        let aged = match self.priority_aging {
+            Some(interval) if !interval.is_zero() => {
+                (now.duration_since(waiter.since).as_nanos() / interval.as_nanos()) as i64
+            }
+            _ => 0,
+        };
+        waiter.priority as i64 + aged
+    }
+
+    // Returns `true` if no other waiter should be served before `ticket`: callers with a higher priority go
+    // first and, when fairness is enabled, callers with the same priority are served in the order they
+    // arrived
+    fn may_checkout(&self, ticket: u64) -> bool {
+        let now = Instant::now();
+        let Some(me) = self.waiters.iter().find(|w| w.ticket == ticket) else {
+            return true;
+        };
+        let priority = self.priority(me, now);
+        !self.waiters.iter().any(|w| {
+            let p = self.priority(w, now);
+            p > priority || (self.fair && p == priority && w.ticket < ticket)
+        })
+    }
+
+    // Returns `true` if a waiter may be able to check out an instance
+    fn ready(&self) -> bool {
+        !self.waiters.is_empty()
+            && (!self.available.is_empty() || self.current_size < self.max_size)
+    }
+
+    // Remove an instance from `generation` that was discarded or couldn't be created
+    fn discard(&mut self, generation: u64) -> Option<u64> {
+        if generation != self.generation {
+            return self.drained();
+        }
+        self.current_size -= 1;
+        None
+    }
+}
+
+// Wake a thread waiting in `Pool::get` and all tasks waiting in `Pool::get_async`, the tasks are woken to
+// check the pool again since any of them may have stopped waiting. When more than one caller is waiting all
+// threads are woken, since only the caller that should be served next will check out the instance
+fn notify(mut inner: MutexGuard<PoolInner>, cond: &Condvar) {
+    let wakers = std::mem::take(&mut inner.wakers);
+    let all = inner.waiters.len() > 1;
+    drop(inner);
+    if all {
+        cond.notify_all();
+    } else {
+        cond.notify_one();
+    }
+    for waker in wakers {
+        waker.wake();
+    }
 }
 
 /// `Pool` manages threadsafe access to a limited number of instances of multiple plugins
@@ -62,6 +334,8 @@ pub struct Pool {
     inner: Arc<Mutex<PoolInner>>,
     cond: Arc<Condvar>,
     existing_functions: Arc<RwLock<HashMap<String, bool>>>,
+    recycle: Arc<Recycle>,
+    metrics: Option<Arc<dyn Metrics>>,
 }
 
 impl Pool {
@@ -76,16 +350,102 @@ impl Pool {
         builder: PoolBuilder,
     ) -> Self {
         let cond = Arc::new(Condvar::new());
-        Pool {
+        let pool = Pool {
             inner: Arc::new(Mutex::new(PoolInner {
-                plugin_source: Box::new(source),
+                plugin_source: Arc::new(source),
                 available: VecDeque::new(),
                 current_size: 0,
                 max_size: builder.max_instances,
+                wakers: Vec::new(),
+                generation: 0,
+                draining: 0,
+                waiters: Vec::new(),
+                next_ticket: 0,
+                fair: builder.fair,
+                priority_aging: builder.priority_aging,
             })),
             cond,
             existing_functions: Arc::new(RwLock::new(HashMap::new())),
+            recycle: Arc::new(Recycle {
+                max_calls: builder.max_calls_per_instance,
+                max_age: builder.max_instance_age,
+                health_check: builder.health_check,
+                on_rollover: builder.on_rollover,
+            }),
+            metrics: builder.metrics,
+        };
+        if builder.min_instances > 0 {
+            #[cfg(not(target_family = "wasm"))]
+            pool.warm(builder.min_instances);
+            #[cfg(target_family = "wasm")]
+            if let Err(e) = pool.warm_blocking(builder.min_instances) {
+                crate::warn!("unable to warm up plugin pool: {e:#}");
+            }
         }
+        pool
+    }
+
+    /// Create instances until the pool has at least `n` (limited by `max_instances`), the instances are
+    /// created on a background thread and can be checked out as soon as each one is ready. The returned
+    /// handle can be joined to wait for all of them, errors are also logged.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn warm(&self, n: usize) -> std::thread::JoinHandle<Result<usize, Error>> {
+        let pool = self.clone();
+        std::thread::Builder::new()
+            .name("extism-pool-warm".to_string())
+            .spawn(move || {
+                let res = pool.warm_blocking(n);
+                if let Err(e) = &res {
+                    crate::warn!("unable to warm up plugin pool: {e:#}");
+                }
+                res
+            })
+            .expect("failed to spawn pool warm-up thread")
+    }
+
+    /// Like `Pool::warm`, but blocks until the instances have been created. Returns the number of
+    /// instances that were created.
+    pub fn warm_blocking(&self, n: usize) -> Result<usize, Error> {
+        let _span = span!("extism.pool.warm", instances = n);
+        let mut created = 0;
+        loop {
+            // Reserve a slot so the instance counts towards `max_instances` while it's being created,
+            // the lock isn't held while the plugin is instantiated
+            let (source, generation) = {
+                let mut inner = self.inner.lock().unwrap();
+                if inner.current_size >= n.min(inner.max_size) {
+                    return Ok(created);
+                }
+                inner.current_size += 1;
+                (inner.plugin_source.clone(), inner.generation)
+            };
+            let res = source();
+            let mut inner = self.inner.lock().unwrap();
+            match res {
+                // The source was replaced while the instance was being created
+                Ok(_) if generation != inner.generation => {
+                    let rolled_over = inner.drained();
+                    notify(inner, &self.cond);
+                    self.recycle.rolled_over(rolled_over);
+                }
+                Ok(plugin) => {
+                    inner.available.push_back(Pooled::new(plugin, generation));
+                    created += 1;
+                    notify(inner, &self.cond);
+                }
+                Err(e) => {
+                    let rolled_over = inner.discard(generation);
+                    notify(inner, &self.cond);
+                    self.recycle.rolled_over(rolled_over);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Get the number of instances that are ready to be checked out
+    pub fn available(&self) -> usize {
+        self.inner.lock().unwrap().available.len()
     }
 
     /// Get the number of live instances for a plugin (both checked out and available)
@@ -97,48 +457,258 @@ impl Pool {
     /// max_instances). `Ok(None)` is returned if the timeout is reached before an available plugin could be
     /// acquired
     pub fn get(&self, timeout: std::time::Duration) -> Result<Option<PoolPlugin>, Error> {
+        self.get_with_priority(timeout, 0)
+    }
+
+    /// Like `Pool::get`, but callers with a higher `priority` are given the next available instance before
+    /// callers with a lower priority, `Pool::get` uses a priority of `0`. For example latency-sensitive
+    /// calls can use a positive priority and batch work a negative one. The priority of waiting callers is
+    /// raised over time, see `PoolBuilder::with_priority_aging`.
+    pub fn get_with_priority(
+        &self,
+        timeout: std::time::Duration,
+        priority: i32,
+    ) -> Result<Option<PoolPlugin>, Error> {
+        let _span = span!("extism.pool.get", priority = priority);
         let start = std::time::Instant::now();
+        let res = self.get_inner(start, timeout, priority);
+        if let Some(metrics) = &self.metrics {
+            metrics.pool_checkout(start.elapsed(), matches!(res, Ok(Some(_))));
+        }
+        res
+    }
 
+    fn get_inner(
+        &self,
+        start: Instant,
+        timeout: std::time::Duration,
+        priority: i32,
+    ) -> Result<Option<PoolPlugin>, Error> {
         // Hold lock throughout except when waiting on condition variable
         let mut inner = self.inner.lock().unwrap();
-
-        loop {
-            // Try to pop an available plugin from the queue
-            if let Some(plugin) = inner.available.pop_front() {
-                return Ok(Some(PoolPlugin {
-                    plugin: Some(plugin),
-                    pool: Arc::downgrade(&self.inner),
-                    cond: self.cond.clone(),
-                }));
-            }
-
-            // Create new plugin if under capacity
-            if inner.current_size < inner.max_size {
-                let plugin = (*inner.plugin_source)()?;
-                inner.current_size += 1;
-                return Ok(Some(PoolPlugin {
-                    plugin: Some(plugin),
-                    pool: Arc::downgrade(&self.inner),
-                    cond: self.cond.clone(),
-                }));
+        let ticket = inner.enqueue(priority);
+
+        let res = loop {
+            // Callers with a higher priority, or that arrived first when fairness is enabled, are served
+            // first
+            if inner.may_checkout(ticket) {
+                // Try to pop an available plugin from the queue, instances that expired while they were
+                // waiting are discarded
+                if let Some(pooled) = inner.available.pop_front() {
+                    if self.recycle.expired(pooled.created) {
+                        crate::debug!(
+                            plugin = pooled.plugin.id.to_string(),
+                            "discarding pooled plugin: reached max age"
+                        );
+                        inner.current_size -= 1;
+                        continue;
+                    }
+                    break Ok(Some(self.checkout(pooled)));
+                }
+
+                // Create new plugin if under capacity
+                if inner.current_size < inner.max_size {
+                    let _span = span!("extism.pool.create", size = inner.current_size);
+                    match (*inner.plugin_source)() {
+                        Ok(plugin) => {
+                            inner.current_size += 1;
+                            let generation = inner.generation;
+                            break Ok(Some(self.checkout(Pooled::new(plugin, generation))));
+                        }
+                        Err(e) => break Err(e),
+                    }
+                }
             }
 
-            // All plugins busy and at capacity. Check if we should keep waiting.
+            // All plugins busy and at capacity, or other callers are served first. Check if we should keep
+            // waiting.
             let elapsed = std::time::Instant::now() - start;
             if elapsed >= timeout {
-                return Ok(None);
+                break Ok(None);
             }
 
             // Wait for a plugin to be returned. wait_timeout releases the lock while
-            // waiting and re-acquires it when woken. Loop back to check availability.
-            let remaining = timeout - elapsed;
-            let (guard, wait_result) = self.cond.wait_timeout(inner, remaining).unwrap();
+            // waiting and re-acquires it when woken. Loop back to check availability. Aging can change
+            // which caller is served next without a notification, so the pool is checked again at least
+            // once per aging interval
+            let mut remaining = timeout - elapsed;
+            if let Some(interval) = inner.priority_aging.filter(|x| !x.is_zero()) {
+                remaining = remaining.min(interval);
+            }
+            let (guard, _) = self.cond.wait_timeout(inner, remaining).unwrap();
             inner = guard;
+        };
+
+        // Let the next caller check the pool, it may have been waiting behind this one
+        inner.dequeue(ticket);
+        if inner.ready() {
+            notify(inner, &self.cond);
+        }
+        res
+    }
 
-            if wait_result.timed_out() {
-                return Ok(None);
+    /// Like `Pool::get`, but returns a future that resolves once an instance is available instead of
+    /// blocking the thread. New instances are created on the tokio blocking thread pool. Dropping the
+    /// future stops waiting, `tokio::time::timeout` can be used to limit how long to wait.
+    #[cfg(feature = "tokio")]
+    pub async fn get_async(&self) -> Result<PoolPlugin, Error> {
+        let start = Instant::now();
+        let res = self.get_async_inner().await;
+        if let Some(metrics) = &self.metrics {
+            metrics.pool_checkout(start.elapsed(), res.is_ok());
+        }
+        res
+    }
+
+    #[cfg(feature = "tokio")]
+    async fn get_async_inner(&self) -> Result<PoolPlugin, Error> {
+        enum Checkout {
+            Available(Box<PoolPlugin>),
+            Create(Arc<PluginSource>, u64),
+        }
+
+        // Removes the caller from the queue if the future is dropped while it's waiting
+        struct Ticket<'a> {
+            pool: &'a Pool,
+            ticket: Option<u64>,
+        }
+
+        impl Drop for Ticket<'_> {
+            fn drop(&mut self) {
+                if let Some(ticket) = self.ticket {
+                    let mut inner = self.pool.inner.lock().unwrap();
+                    inner.dequeue(ticket);
+                    if inner.ready() {
+                        notify(inner, &self.pool.cond);
+                    }
+                }
             }
         }
+
+        let mut ticket = Ticket {
+            pool: self,
+            ticket: None,
+        };
+        let checkout = std::future::poll_fn(|cx| {
+            let mut inner = self.inner.lock().unwrap();
+            let id = *ticket.ticket.get_or_insert_with(|| inner.enqueue(0));
+            let mut res = None;
+            if inner.may_checkout(id) {
+                while let Some(pooled) = inner.available.pop_front() {
+                    if self.recycle.expired(pooled.created) {
+                        crate::debug!(
+                            plugin = pooled.plugin.id.to_string(),
+                            "discarding pooled plugin: reached max age"
+                        );
+                        inner.current_size -= 1;
+                        continue;
+                    }
+                    res = Some(Checkout::Available(Box::new(self.checkout(pooled))));
+                    break;
+                }
+
+                // Reserve a slot for the new instance, like `Pool::warm_blocking`
+                if res.is_none() && inner.current_size < inner.max_size {
+                    inner.current_size += 1;
+                    res = Some(Checkout::Create(
+                        inner.plugin_source.clone(),
+                        inner.generation,
+                    ));
+                }
+            }
+
+            match res {
+                Some(checkout) => {
+                    inner.dequeue(id);
+                    ticket.ticket = None;
+                    if inner.ready() {
+                        notify(inner, &self.cond);
+                    }
+                    std::task::Poll::Ready(checkout)
+                }
+                None => {
+                    if !inner.wakers.iter().any(|x| x.will_wake(cx.waker())) {
+                        inner.wakers.push(cx.waker().clone());
+                    }
+                    std::task::Poll::Pending
+                }
+            }
+        })
+        .await;
+        let (source, generation) = match checkout {
+            Checkout::Available(plugin) => return Ok(*plugin),
+            Checkout::Create(source, generation) => (source, generation),
+        };
+
+        // The instance is checked out on the blocking thread, so it's returned to the pool if this future
+        // is dropped while it's being created
+        let pool = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let _span = span!("extism.pool.create");
+            match source() {
+                Ok(plugin) => Ok(pool.checkout(Pooled::new(plugin, generation))),
+                Err(e) => {
+                    let mut inner = pool.inner.lock().unwrap();
+                    let rolled_over = inner.discard(generation);
+                    notify(inner, &pool.cond);
+                    pool.recycle.rolled_over(rolled_over);
+                    Err(e)
+                }
+            }
+        })
+        .await?
+    }
+
+    fn checkout(&self, pooled: Pooled) -> PoolPlugin {
+        PoolPlugin {
+            start_calls: pooled.plugin.resources.calls,
+            plugin: Some(pooled.plugin),
+            created: pooled.created,
+            calls: pooled.calls,
+            generation: pooled.generation,
+            pool: Arc::downgrade(&self.inner),
+            cond: self.cond.clone(),
+            recycle: self.recycle.clone(),
+        }
+    }
+
+    /// Replace the function used to create instances, for example to deploy a new version of a plugin.
+    /// Available instances are discarded immediately, instances that are checked out keep running and are
+    /// discarded when they're returned, so calls that are already running aren't interrupted. New instances
+    /// are created using `source`, the rollover callback set using `PoolBuilder::with_rollover_callback`
+    /// is called once every instance from an older source is gone.
+    ///
+    /// The returned generation starts at `1` and is incremented each time the source is replaced.
+    pub fn replace_source<F: 'static + Fn() -> Result<Plugin, Error> + Send + Sync>(
+        &self,
+        source: F,
+    ) -> u64 {
+        let mut inner = self.inner.lock().unwrap();
+        inner.plugin_source = Arc::new(source);
+        inner.generation += 1;
+        let old = std::mem::take(&mut inner.available);
+        inner.current_size -= old.len();
+        inner.draining = inner.current_size;
+        let generation = inner.generation;
+        let rolled_over = (inner.draining == 0).then_some(generation);
+
+        // The new source may export different functions
+        self.existing_functions.write().unwrap().clear();
+        notify(inner, &self.cond);
+        drop(old);
+        self.recycle.rolled_over(rolled_over);
+        generation
+    }
+
+    /// Get the number of times the source has been replaced using `Pool::replace_source`
+    pub fn generation(&self) -> u64 {
+        self.inner.lock().unwrap().generation
+    }
+
+    /// Get the number of instances created before the last call to `Pool::replace_source` that haven't
+    /// been returned yet
+    pub fn draining(&self) -> usize {
+        self.inner.lock().unwrap().draining
     }
 
     /// Access a plugin in a callback function. This calls `Pool::get` then the provided callback. `Ok(None)`
@@ -189,12 +759,20 @@ impl Pool {
 pub struct PoolPlugin {
     /// The checked-out plugin. Wrapped in `Option` so it can be moved out on drop.
     plugin: Option<Plugin>,
+    created: Instant,
+    /// Calls made before this checkout
+    calls: u64,
+    /// `ResourceReport::calls` when the plugin was checked out
+    start_calls: u64,
+    /// The source generation the plugin was created from
+    generation: u64,
     /// Weak reference to the pool, used to return the plugin on drop. Using `Weak` allows the pool
     /// to be fully dropped even if plugins are still checked out; when those plugins are dropped,
     /// they'll see the pool is gone and simply drop themselves.
     pool: Weak<Mutex<PoolInner>>,
     /// Condition variable to notify waiters when this plugin is returned.
     cond: Arc<Condvar>,
+    recycle: Arc<Recycle>,
 }
 
 impl std::fmt::Debug for PoolPlugin {
@@ -247,12 +825,45 @@ impl std::ops::DerefMut for PoolPlugin {
 
 impl Drop for PoolPlugin {
     fn drop(&mut self) {
-        if let Some(plugin) = self.plugin.take() {
+        if let Some(mut plugin) = self.plugin.take() {
+            // Plugins that can't be restored to their initial state aren't reused, which frees up
+            // room for a new instance
+            let reusable = !plugin.cow_reset || plugin.reset().is_ok();
             if let Some(inner) = self.pool.upgrade() {
+                // The report may have been reset while the plugin was checked out
+                let calls = plugin.resources.calls;
+                let calls = self.calls
+                    + if calls >= self.start_calls {
+                        calls - self.start_calls
+                    } else {
+                        calls
+                    };
+                let mut pooled = Pooled {
+                    plugin,
+                    created: self.created,
+                    calls,
+                    generation: self.generation,
+                };
+                let reusable = reusable
+                    && match self.recycle.check(&mut pooled) {
+                        Some(reason) => {
+                            crate::debug!(
+                                plugin = pooled.plugin.id.to_string(),
+                                "discarding pooled plugin: {reason}"
+                            );
+                            false
+                        }
+                        None => true,
+                    };
                 let mut guard = inner.lock().unwrap();
-                guard.available.push_back(plugin);
-                drop(guard);
-                self.cond.notify_one();
+                let rolled_over = if reusable && pooled.generation == guard.generation {
+                    guard.available.push_back(pooled);
+                    None
+                } else {
+                    guard.discard(pooled.generation)
+                };
+                notify(guard, &self.cond);
+                self.recycle.rolled_over(rolled_over);
             }
             // If pool is gone, just drop the plugin
         }
@@ -0,0 +1,70 @@
+use crate::*;
+
+/// Settings for wasmtime's pooling instance allocator, see `PluginBuilder::with_pooling_allocator`
+///
+/// The pooling allocator reserves memory for a fixed number of instances when the engine is created
+/// and reuses those slots, instead of mapping and unmapping memory for every instance. The engine
+/// belongs to a `CompiledPlugin`, so the slots are shared by all plugins created from the same
+/// `CompiledPlugin` using `Plugin::new_from_compiled`.
+///
+/// Each plugin uses several instances: the Extism kernel, the modules linked from its manifest and
+/// the main instance created by the first call. Creating an instance fails once all slots are in use,
+/// so `max_instances` should leave room for a few instances per plugin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolingAllocator {
+    max_instances: u32,
+    max_memory_size: Option<usize>,
+    max_table_elements: Option<usize>,
+}
+
+impl Default for PoolingAllocator {
+    fn default() -> Self {
+        PoolingAllocator {
+            max_instances: 1000,
+            max_memory_size: None,
+            max_table_elements: None,
+        }
+    }
+}
+
+impl PoolingAllocator {
+    /// Create a new `PoolingAllocator` with room for 1000 instances
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the max number of instances that can exist at the same time
+    pub fn with_max_instances(mut self, n: u32) -> Self {
+        self.max_instances = n;
+        self
+    }
+
+    /// Set the max size of each linear memory in bytes, this should be at least as large as the
+    /// manifest's memory limit
+    pub fn with_max_memory_size(mut self, bytes: usize) -> Self {
+        self.max_memory_size = Some(bytes);
+        self
+    }
+
+    /// Set the max number of elements in each table
+    pub fn with_max_table_elements(mut self, n: usize) -> Self {
+        self.max_table_elements = Some(n);
+        self
+    }
+
+    pub(crate) fn configure(&self, config: &mut Config) {
+        let mut pooling = PoolingAllocationConfig::new();
+        pooling
+            .total_core_instances(self.max_instances)
+            .total_memories(self.max_instances)
+            .total_tables(self.max_instances)
+            .total_gc_heaps(self.max_instances);
+        if let Some(bytes) = self.max_memory_size {
+            pooling.max_memory_size(bytes);
+        }
+        if let Some(n) = self.max_table_elements {
+            pooling.table_elements(n);
+        }
+        config.allocation_strategy(InstanceAllocationStrategy::Pooling(pooling));
+    }
+}
@@ -0,0 +1,111 @@
+#[cfg(feature = "guest-profiler")]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "guest-profiler")]
+use std::sync::Arc;
+#[cfg(feature = "guest-profiler")]
+use std::time::{Duration, Instant};
+
+use crate::*;
+
+/// Selects how plugin code is profiled, see `PluginBuilder::with_profiling`
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum Profiling {
+    /// Use one of wasmtime's native profiling strategies, the profile is collected by an external tool
+    /// like `perf` or VTune
+    Native(wasmtime::ProfilingStrategy),
+
+    /// Sample the guest stack every `interval` using wasmtime's in-process profiler, the profile for the
+    /// most recent call can be read using `Plugin::take_profile`
+    #[cfg(feature = "guest-profiler")]
+    Guest { interval: Duration },
+}
+
+/// The guest profiler for the current call, stored in `CurrentPlugin` so it can be sampled from the epoch
+/// callback
+#[cfg(feature = "guest-profiler")]
+pub(crate) struct Sampler {
+    profiler: GuestProfiler,
+    last: Instant,
+}
+
+#[cfg(feature = "guest-profiler")]
+impl Sampler {
+    pub(crate) fn new(
+        engine: &Engine,
+        function: &str,
+        interval: Duration,
+        modules: &BTreeMap<String, Module>,
+    ) -> Result<Self, Error> {
+        let modules = modules.iter().map(|(k, v)| (k.clone(), v.clone()));
+        Ok(Sampler {
+            profiler: GuestProfiler::new(engine, function, interval, modules)?,
+            last: Instant::now(),
+        })
+    }
+
+    /// Write the profile as JSON in the Firefox profiler's processed profile format
+    pub(crate) fn finish(self) -> Result<Vec<u8>, Error> {
+        let mut out = vec![];
+        self.profiler.finish(&mut out)?;
+        Ok(out)
+    }
+}
+
+/// Record a sample if the guest profiler is enabled, this is called from the epoch callback
+pub(crate) fn sample(ctx: &mut StoreContextMut<CurrentPlugin>) {
+    #[cfg(feature = "guest-profiler")]
+    if let Some(mut sampler) = ctx.data_mut().profiler.take() {
+        let now = Instant::now();
+        sampler.profiler.sample(&*ctx, now - sampler.last);
+        sampler.last = now;
+        ctx.data_mut().profiler = Some(sampler);
+    }
+    #[cfg(not(feature = "guest-profiler"))]
+    let _ = ctx;
+}
+
+/// Install an epoch callback that samples the guest, calls are only interrupted when they're cancelled or
+/// the timeout has passed. When a `Watchdog` is used its callback samples the guest instead.
+#[cfg(feature = "guest-profiler")]
+pub(crate) fn install(
+    store: &mut Store<CurrentPlugin>,
+    timeout: Option<Duration>,
+    cancelled: Arc<AtomicBool>,
+) {
+    let start = Instant::now();
+    store.epoch_deadline_callback(move |mut ctx| {
+        if cancelled.load(Ordering::SeqCst) || timeout.is_some_and(|t| start.elapsed() >= t) {
+            return Err(wasmtime::Trap::Interrupt.into());
+        }
+        sample(&mut ctx);
+        Ok(UpdateDeadline::Continue(1))
+    });
+}
+
+/// Increments the engine epoch every `interval` until it's dropped, which triggers the epoch callback so
+/// the guest can be sampled
+#[cfg(feature = "guest-profiler")]
+pub(crate) struct Ticker(Arc<AtomicBool>);
+
+#[cfg(feature = "guest-profiler")]
+impl Ticker {
+    pub(crate) fn start(engine: Engine, interval: Duration) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let s = stop.clone();
+        std::thread::spawn(move || {
+            while !s.load(Ordering::SeqCst) {
+                std::thread::sleep(interval);
+                engine.increment_epoch();
+            }
+        });
+        Ticker(stop)
+    }
+}
+
+#[cfg(feature = "guest-profiler")]
+impl Drop for Ticker {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
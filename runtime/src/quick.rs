@@ -0,0 +1,28 @@
+//! Run a single function from a manifest without setting up a `Plugin`, for tools that only have a manifest
+//! and a function name
+//!
+//! ```no_run
+//! # fn main() -> Result<(), extism::Error> {
+//! let output = extism::quick::call(r#"{"wasm": [{"path": "count_vowels.wasm"}]}"#, "count_vowels", "hello")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use anyhow::Context;
+
+use crate::*;
+
+/// Parse `manifest_json`, create a plugin with WASI enabled and call `func` with `input`, returning the
+/// output. The plugin is sandboxed: the manifest's `allowed_hosts`, `allowed_sockets` and `allowed_paths`
+/// are ignored, so it can't make HTTP requests, open sockets or access the filesystem.
+pub fn call(manifest_json: &str, func: &str, input: impl AsRef<[u8]>) -> Result<Vec<u8>, Error> {
+    let mut manifest: Manifest =
+        serde_json::from_str(manifest_json).context("unable to parse manifest")?;
+    manifest.allowed_hosts = None;
+    manifest.allowed_sockets = None;
+    manifest.allowed_paths = None;
+    let mut plugin = PluginBuilder::new(manifest).with_wasi(true).build()?;
+    plugin
+        .call::<&[u8], &[u8]>(func, input.as_ref())
+        .map(<[u8]>::to_vec)
+}
@@ -0,0 +1,174 @@
+use std::time::{Duration, Instant};
+
+use crate::*;
+
+/// Limits the number of times a single host function (or all host functions combined)
+/// may be invoked
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HostFunctionLimit {
+    /// Maximum number of invocations during a single plugin call
+    pub max_per_call: Option<u64>,
+
+    /// Maximum number of invocations in any one second window
+    pub max_per_second: Option<u64>,
+}
+
+impl HostFunctionLimit {
+    /// Create an empty `HostFunctionLimit`
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the max number of invocations for a single plugin call
+    pub fn with_max_per_call(mut self, n: u64) -> Self {
+        self.max_per_call = Some(n);
+        self
+    }
+
+    /// Set the max number of invocations per second
+    pub fn with_max_per_second(mut self, n: u64) -> Self {
+        self.max_per_second = Some(n);
+        self
+    }
+
+    fn is_empty(&self) -> bool {
+        self.max_per_call.is_none() && self.max_per_second.is_none()
+    }
+}
+
+/// Configures quotas for host function invocations made by a plugin, this includes
+/// the `extism:host/env` functions provided by Extism and any user-defined host functions.
+///
+/// When a quota is exceeded the host function is not executed and the plugin call fails
+/// with an error message starting with `host function quota exceeded`.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct HostFunctionLimits {
+    /// Limit applied to all host function calls combined
+    pub all: HostFunctionLimit,
+
+    /// Limits for specific host functions, keyed by function name
+    pub functions: BTreeMap<String, HostFunctionLimit>,
+}
+
+impl HostFunctionLimits {
+    /// Create an empty `HostFunctionLimits`, no limits are enforced by default
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the max number of host function calls during a single plugin call
+    pub fn with_max_calls_per_call(mut self, n: u64) -> Self {
+        self.all.max_per_call = Some(n);
+        self
+    }
+
+    /// Set the max number of host function calls per second
+    pub fn with_max_calls_per_second(mut self, n: u64) -> Self {
+        self.all.max_per_second = Some(n);
+        self
+    }
+
+    /// Set the limit for a specific host function
+    pub fn with_function_limit(
+        mut self,
+        name: impl Into<String>,
+        limit: HostFunctionLimit,
+    ) -> Self {
+        self.functions.insert(name.into(), limit);
+        self
+    }
+
+    /// Returns `true` when no limits are configured
+    pub fn is_empty(&self) -> bool {
+        self.all.is_empty() && self.functions.values().all(HostFunctionLimit::is_empty)
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct Counter {
+    call: u64,
+    second: u64,
+}
+
+impl Counter {
+    fn increment(&mut self, name: &str, limit: &HostFunctionLimit) -> Result<(), Error> {
+        self.call += 1;
+        self.second += 1;
+        if let Some(max) = limit.max_per_call {
+            if self.call > max {
+                anyhow::bail!("host function quota exceeded: {name} ({max} calls per plugin call)");
+            }
+        }
+        if let Some(max) = limit.max_per_second {
+            if self.second > max {
+                anyhow::bail!("host function quota exceeded: {name} ({max} calls per second)");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Tracks host function invocations for a plugin and enforces `HostFunctionLimits`
+#[derive(Default)]
+pub(crate) struct HostCallCounter {
+    enabled: bool,
+    names: std::sync::Arc<usage::HostFunctionNames>,
+    limit: HostFunctionLimit,
+    all: Counter,
+    // Limits and counters for each host function, by index
+    functions: Vec<Option<(HostFunctionLimit, Counter)>>,
+    window_start: Option<Instant>,
+}
+
+impl HostCallCounter {
+    pub(crate) fn new(
+        limits: HostFunctionLimits,
+        names: std::sync::Arc<usage::HostFunctionNames>,
+    ) -> Self {
+        HostCallCounter {
+            enabled: !limits.is_empty(),
+            functions: names
+                .iter()
+                .map(|x| limits.functions.get(x).map(|l| (*l, Counter::default())))
+                .collect(),
+            names,
+            limit: limits.all,
+            all: Counter::default(),
+            window_start: None,
+        }
+    }
+
+    /// Reset the per-call counters, this should be called before each plugin call
+    pub(crate) fn start_call(&mut self) {
+        self.all.call = 0;
+        for (_, c) in self.functions.iter_mut().flatten() {
+            c.call = 0;
+        }
+    }
+
+    /// Record a host function invocation, returning an error if a quota has been exceeded
+    pub(crate) fn record(&mut self, index: usize) -> Result<(), Error> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let expired = self
+            .window_start
+            .is_none_or(|start| now.duration_since(start) >= Duration::from_secs(1));
+        if expired {
+            self.window_start = Some(now);
+            self.all.second = 0;
+            for (_, c) in self.functions.iter_mut().flatten() {
+                c.second = 0;
+            }
+        }
+
+        let name = self.names.get(index);
+        self.all.increment(name, &self.limit)?;
+        if let Some(Some((limit, c))) = self.functions.get_mut(index) {
+            c.increment(name, limit)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use std::sync::{Arc, Mutex};
+
+use cap_rand::{RngCore, SeedableRng};
+
+/// A `WasiRandom` replaces the random number generator for WASI plugins, see
+/// `PluginBuilder::with_wasi_random`
+pub trait WasiRandom: Send + Sync {
+    /// Fill `buf` with random bytes
+    fn fill_bytes(&self, buf: &mut [u8]);
+}
+
+impl<T: WasiRandom + ?Sized> WasiRandom for Arc<T> {
+    fn fill_bytes(&self, buf: &mut [u8]) {
+        (**self).fill_bytes(buf)
+    }
+}
+
+/// A `WasiRandom` that generates the same sequence of bytes for a given seed, this should only be
+/// used when reproducible output is more important than unpredictable values. Clones share the same
+/// generator
+#[derive(Clone)]
+pub struct SeededRandom {
+    rng: Arc<Mutex<cap_rand::rngs::StdRng>>,
+}
+
+impl std::fmt::Debug for SeededRandom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SeededRandom")
+    }
+}
+
+impl SeededRandom {
+    /// Create a new `SeededRandom` from `seed`
+    pub fn new(seed: u64) -> Self {
+        SeededRandom {
+            rng: Arc::new(Mutex::new(cap_rand::rngs::StdRng::seed_from_u64(seed))),
+        }
+    }
+}
+
+impl WasiRandom for SeededRandom {
+    fn fill_bytes(&self, buf: &mut [u8]) {
+        match self.rng.lock() {
+            Ok(mut x) => x.fill_bytes(buf),
+            Err(e) => e.into_inner().fill_bytes(buf),
+        }
+    }
+}
+
+/// Adapts a `WasiRandom` to the `RngCore` trait used by WASI implementations
+#[cfg(not(target_family = "wasm"))]
+pub(crate) struct Rng(pub(crate) Arc<dyn WasiRandom>);
+
+#[cfg(not(target_family = "wasm"))]
+impl RngCore for Rng {
+    fn next_u32(&mut self) -> u32 {
+        let mut buf = [0; 4];
+        self.0.fill_bytes(&mut buf);
+        u32::from_le_bytes(buf)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut buf = [0; 8];
+        self.0.fill_bytes(&mut buf);
+        u64::from_le_bytes(buf)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), cap_rand::Error> {
+        self.0.fill_bytes(dest);
+        Ok(())
+    }
+}
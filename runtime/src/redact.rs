@@ -0,0 +1,47 @@
+use crate::*;
+
+/// Describes the data passed to a `Redactor`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RedactTarget<'a> {
+    /// Input passed to an exported function, logged at the `trace` level
+    Input { function: &'a str },
+
+    /// Output returned by an exported function, logged at the `trace` level
+    Output { function: &'a str },
+
+    /// Arguments passed to a host function, logged at the `trace` level
+    HostFunctionArgs { function: &'a str },
+
+    /// An error message, this is applied before the error is logged or returned to the caller
+    Error { function: &'a str },
+
+    /// A message logged by the plugin using `extism:host/env::log_*`
+    Log { level: tracing::Level },
+}
+
+/// A `Redactor` sanitizes plugin data before it is written to logs, traces or error messages
+///
+/// Resolved secrets are always replaced with `[REDACTED]` before the `Redactor` is called. `CallLog`
+/// records only contain hashes of inputs and outputs, so they aren't passed through the `Redactor`.
+pub trait Redactor: Send + Sync {
+    /// Return a sanitized copy of `data`
+    fn redact(&self, target: RedactTarget, data: &str) -> String;
+}
+
+impl<F: Fn(RedactTarget, &str) -> String + Send + Sync> Redactor for F {
+    fn redact(&self, target: RedactTarget, data: &str) -> String {
+        self(target, data)
+    }
+}
+
+impl CurrentPlugin {
+    /// Remove resolved secrets from `data` and apply the configured `Redactor`
+    pub(crate) fn redact(&self, target: RedactTarget, data: &str) -> String {
+        let data = self.secrets.redact(data);
+        match &self.redactor {
+            Some(r) => r.redact(target, &data),
+            None => data,
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use crate::*;
+
+/// A `WasmResolver` fetches modules for `Wasm::url` entries with a custom URL scheme, like `s3://` or
+/// `vault://`, see `PluginBuilder::with_wasm_resolver`. The hash and signature from the manifest are
+/// checked against the returned data, the same as for modules fetched using HTTP.
+///
+/// ```no_run
+/// # use extism::*;
+/// # fn fetch_from_s3(url: &str) -> Result<Vec<u8>, Error> { unimplemented!("{url}") }
+/// # fn main() -> Result<(), Error> {
+/// let plugin = PluginBuilder::new(Manifest::new([Wasm::url("s3://plugins/greet.wasm")]))
+///     .with_wasm_resolver("s3", |req: &HttpRequest| fetch_from_s3(&req.url))
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait WasmResolver: Send + Sync {
+    /// Fetch the module data for `req`, `req.url` includes the scheme
+    fn resolve(&self, req: &HttpRequest) -> Result<Vec<u8>, Error>;
+}
+
+impl<F: Fn(&HttpRequest) -> Result<Vec<u8>, Error> + Send + Sync> WasmResolver for F {
+    fn resolve(&self, req: &HttpRequest) -> Result<Vec<u8>, Error> {
+        self(req)
+    }
+}
+
+impl<T: WasmResolver + ?Sized> WasmResolver for Arc<T> {
+    fn resolve(&self, req: &HttpRequest) -> Result<Vec<u8>, Error> {
+        (**self).resolve(req)
+    }
+}
+
+/// Resolvers registered using `PluginBuilder::with_wasm_resolver`, keyed by lowercase URL scheme
+pub(crate) type WasmResolvers = BTreeMap<String, Arc<dyn WasmResolver>>;
+
+/// Find the resolver for the scheme of `url`
+pub(crate) fn find<'a>(resolvers: &'a WasmResolvers, url: &str) -> Option<&'a dyn WasmResolver> {
+    let (scheme, _) = url.split_once(':')?;
+    resolvers
+        .get(&scheme.to_ascii_lowercase())
+        .map(|x| x.as_ref())
+}
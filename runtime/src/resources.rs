@@ -0,0 +1,322 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(not(target_family = "wasm"))]
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(not(target_family = "wasm"))]
+use async_trait::async_trait;
+
+/// `ResourceReport` contains the resources used by a plugin instance since it was created (or since the
+/// last call to `Plugin::reset_resource_report`)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceReport {
+    /// Number of calls into the plugin
+    pub calls: u64,
+
+    /// Total fuel consumed, this is always `0` unless a fuel limit has been configured
+    pub fuel_consumed: u64,
+
+    /// Total time spent executing calls
+    pub wall_time: Duration,
+
+    /// Number of bytes read from files in `allowed_paths` using WASI
+    pub fs_bytes_read: u64,
+
+    /// Number of bytes written to files in `allowed_paths` using WASI
+    pub fs_bytes_written: u64,
+
+    /// Number of request body bytes sent using `extism:host/env::http_request`
+    pub http_bytes_sent: u64,
+
+    /// Number of response body bytes received using `extism:host/env::http_request`
+    pub http_bytes_received: u64,
+
+    /// The largest amount of linear memory, in bytes, used by a single instance of the plugin
+    pub peak_memory: u64,
+}
+
+/// `CallStats` contains the resources used by a single call, returned by `Plugin::call_with_stats`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CallStats {
+    /// Time spent executing the call
+    pub wall_time: Duration,
+
+    /// Fuel consumed by the call, this is always `0` unless a fuel limit has been configured
+    pub fuel_consumed: u64,
+
+    /// Number of 64KiB pages of linear memory used by the plugin when the call returned, including memory
+    /// used by the Extism kernel. Memory can't shrink, so this is also the largest amount used during the
+    /// call.
+    pub memory_pages: u64,
+
+    /// Number of host function calls made during the call, including Extism host functions such as
+    /// `var_get` and `http_request`
+    pub host_calls: u64,
+}
+
+/// I/O counters that are updated from host functions and WASI
+#[derive(Default, Debug)]
+pub(crate) struct IoCounters {
+    pub(crate) fs_read: AtomicU64,
+    pub(crate) fs_written: AtomicU64,
+    pub(crate) http_sent: AtomicU64,
+    pub(crate) http_received: AtomicU64,
+}
+
+impl IoCounters {
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) fn add(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn report(&self, report: &mut ResourceReport) {
+        report.fs_bytes_read = self.fs_read.load(Ordering::Relaxed);
+        report.fs_bytes_written = self.fs_written.load(Ordering::Relaxed);
+        report.http_bytes_sent = self.http_sent.load(Ordering::Relaxed);
+        report.http_bytes_received = self.http_received.load(Ordering::Relaxed);
+    }
+
+    pub(crate) fn reset(&self) {
+        self.fs_read.store(0, Ordering::Relaxed);
+        self.fs_written.store(0, Ordering::Relaxed);
+        self.http_sent.store(0, Ordering::Relaxed);
+        self.http_received.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+use wasi_common::dir::{OpenResult, ReaddirCursor, ReaddirEntity};
+#[cfg(not(target_family = "wasm"))]
+use wasi_common::file::{Advice, FdFlags, FileType, Filestat, OFlags};
+#[cfg(not(target_family = "wasm"))]
+use wasi_common::{Error, WasiDir, WasiFile};
+
+/// Wraps a preopened directory, counting the bytes read from and written to files opened through it
+#[cfg(not(target_family = "wasm"))]
+pub(crate) struct CountingDir {
+    inner: Box<dyn WasiDir>,
+    counters: Arc<IoCounters>,
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl CountingDir {
+    pub(crate) fn new(inner: Box<dyn WasiDir>, counters: Arc<IoCounters>) -> Self {
+        CountingDir { inner, counters }
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+#[async_trait]
+impl WasiDir for CountingDir {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self.inner.as_any()
+    }
+
+    async fn open_file(
+        &self,
+        symlink_follow: bool,
+        path: &str,
+        oflags: OFlags,
+        read: bool,
+        write: bool,
+        fdflags: FdFlags,
+    ) -> Result<OpenResult, Error> {
+        let res = self
+            .inner
+            .open_file(symlink_follow, path, oflags, read, write, fdflags)
+            .await?;
+        Ok(match res {
+            OpenResult::File(f) => OpenResult::File(Box::new(CountingFile {
+                inner: f,
+                counters: self.counters.clone(),
+            })),
+            OpenResult::Dir(d) => {
+                OpenResult::Dir(Box::new(CountingDir::new(d, self.counters.clone())))
+            }
+        })
+    }
+
+    async fn create_dir(&self, path: &str) -> Result<(), Error> {
+        self.inner.create_dir(path).await
+    }
+
+    async fn readdir(
+        &self,
+        cursor: ReaddirCursor,
+    ) -> Result<Box<dyn Iterator<Item = Result<ReaddirEntity, Error>> + Send>, Error> {
+        self.inner.readdir(cursor).await
+    }
+
+    async fn symlink(&self, old_path: &str, new_path: &str) -> Result<(), Error> {
+        self.inner.symlink(old_path, new_path).await
+    }
+
+    async fn remove_dir(&self, path: &str) -> Result<(), Error> {
+        self.inner.remove_dir(path).await
+    }
+
+    async fn unlink_file(&self, path: &str) -> Result<(), Error> {
+        self.inner.unlink_file(path).await
+    }
+
+    async fn read_link(&self, path: &str) -> Result<std::path::PathBuf, Error> {
+        self.inner.read_link(path).await
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+
+    async fn get_path_filestat(
+        &self,
+        path: &str,
+        follow_symlinks: bool,
+    ) -> Result<Filestat, Error> {
+        self.inner.get_path_filestat(path, follow_symlinks).await
+    }
+
+    async fn rename(
+        &self,
+        path: &str,
+        dest_dir: &dyn WasiDir,
+        dest_path: &str,
+    ) -> Result<(), Error> {
+        self.inner.rename(path, dest_dir, dest_path).await
+    }
+
+    async fn hard_link(
+        &self,
+        path: &str,
+        target_dir: &dyn WasiDir,
+        target_path: &str,
+    ) -> Result<(), Error> {
+        self.inner.hard_link(path, target_dir, target_path).await
+    }
+
+    async fn set_times(
+        &self,
+        path: &str,
+        atime: Option<wasi_common::SystemTimeSpec>,
+        mtime: Option<wasi_common::SystemTimeSpec>,
+        follow_symlinks: bool,
+    ) -> Result<(), Error> {
+        self.inner
+            .set_times(path, atime, mtime, follow_symlinks)
+            .await
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+struct CountingFile {
+    inner: Box<dyn WasiFile>,
+    counters: Arc<IoCounters>,
+}
+
+#[cfg(not(target_family = "wasm"))]
+#[async_trait]
+impl WasiFile for CountingFile {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self.inner.as_any()
+    }
+
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<std::os::fd::BorrowedFd<'_>> {
+        self.inner.pollable()
+    }
+
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+
+    async fn datasync(&self) -> Result<(), Error> {
+        self.inner.datasync().await
+    }
+
+    async fn sync(&self) -> Result<(), Error> {
+        self.inner.sync().await
+    }
+
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.inner.set_fdflags(flags).await
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        self.inner.set_filestat_size(size).await
+    }
+
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        self.inner.advise(offset, len, advice).await
+    }
+
+    async fn set_times(
+        &self,
+        atime: Option<wasi_common::SystemTimeSpec>,
+        mtime: Option<wasi_common::SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.inner.set_times(atime, mtime).await
+    }
+
+    async fn read_vectored<'a>(&self, bufs: &mut [std::io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let n = self.inner.read_vectored(bufs).await?;
+        IoCounters::add(&self.counters.fs_read, n);
+        Ok(n)
+    }
+
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [std::io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let n = self.inner.read_vectored_at(bufs, offset).await?;
+        IoCounters::add(&self.counters.fs_read, n);
+        Ok(n)
+    }
+
+    async fn write_vectored<'a>(&self, bufs: &[std::io::IoSlice<'a>]) -> Result<u64, Error> {
+        let n = self.inner.write_vectored(bufs).await?;
+        IoCounters::add(&self.counters.fs_written, n);
+        Ok(n)
+    }
+
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[std::io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let n = self.inner.write_vectored_at(bufs, offset).await?;
+        IoCounters::add(&self.counters.fs_written, n);
+        Ok(n)
+    }
+
+    async fn seek(&self, pos: std::io::SeekFrom) -> Result<u64, Error> {
+        self.inner.seek(pos).await
+    }
+
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.inner.peek(buf).await
+    }
+
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.inner.num_ready_bytes()
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+
+    async fn writable(&self) -> Result<(), Error> {
+        self.inner.writable().await
+    }
+}
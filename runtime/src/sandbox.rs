@@ -0,0 +1,105 @@
+use crate::*;
+
+/// Named security presets for `PluginBuilder::with_sandbox_profile`
+///
+/// A profile bundles the WASI, HTTP, memory, fuel and timeout settings into a single option. Builder
+/// methods called after `with_sandbox_profile` override the settings from the profile, and limits that are
+/// already set in the `Manifest` are never replaced.
+///
+/// | Setting               | `Strict`          | `Default` | `Permissive` |
+/// |-----------------------|-------------------|-----------|--------------|
+/// | WASI                  | disabled          | disabled  | enabled      |
+/// | HTTP response headers | disabled          | disabled  | enabled      |
+/// | Fuel limit            | 1,000,000,000     | none      | none         |
+/// | Timeout               | 5s                | 30s       | none         |
+/// | Max memory            | 16MiB             | 256MiB    | none         |
+/// | Max HTTP response     | 1MiB              | 50MiB     | 50MiB        |
+/// | Max var bytes         | 64KiB             | 1MiB      | 1MiB         |
+/// | Hardening             | yes (best effort) | no        | no           |
+///
+/// HTTP requests are always limited to the manifest's `allowed_hosts`, and filesystem access to
+/// `allowed_paths`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SandboxProfile {
+    /// For untrusted plugins, everything that isn't required to run a plugin is disabled
+    Strict,
+
+    /// Reasonable limits that don't get in the way of most plugins
+    #[default]
+    Default,
+
+    /// For trusted plugins, enables WASI and doesn't apply any additional limits
+    Permissive,
+}
+
+impl std::str::FromStr for SandboxProfile {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "strict" => Ok(SandboxProfile::Strict),
+            "default" => Ok(SandboxProfile::Default),
+            "permissive" => Ok(SandboxProfile::Permissive),
+            _ => anyhow::bail!("invalid sandbox profile: {s}"),
+        }
+    }
+}
+
+impl std::fmt::Display for SandboxProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SandboxProfile::Strict => f.write_str("strict"),
+            SandboxProfile::Default => f.write_str("default"),
+            SandboxProfile::Permissive => f.write_str("permissive"),
+        }
+    }
+}
+
+const PAGE_SIZE: u32 = 65536;
+const MB: u64 = 1024 * 1024;
+
+impl SandboxProfile {
+    // Apply the builder-level settings
+    pub(crate) fn apply_options(&self, options: &mut plugin_builder::PluginBuilderOptions) {
+        match self {
+            SandboxProfile::Strict => {
+                options.wasi = false;
+                options.http_response_headers = false;
+                options.fuel = Some(1_000_000_000);
+                options.hardening = Some(Hardening::new().with_best_effort(true));
+            }
+            SandboxProfile::Default => {
+                options.wasi = false;
+                options.http_response_headers = false;
+                options.fuel = None;
+                options.hardening = None;
+            }
+            SandboxProfile::Permissive => {
+                options.wasi = true;
+                options.http_response_headers = true;
+                options.fuel = None;
+                options.hardening = None;
+            }
+        }
+    }
+
+    // Fill in any limits that aren't already set in the manifest
+    pub(crate) fn apply_manifest(&self, manifest: &mut Manifest) {
+        let (timeout_ms, max_bytes, max_http_response_bytes, max_var_bytes) = match self {
+            SandboxProfile::Strict => (5_000, 16 * MB, MB, 64 * 1024),
+            SandboxProfile::Default => (30_000, 256 * MB, 50 * MB, MB),
+            SandboxProfile::Permissive => return,
+        };
+
+        manifest.timeout_ms.get_or_insert(timeout_ms);
+
+        let memory = &mut manifest.memory;
+        memory
+            .max_pages
+            .get_or_insert((max_bytes / PAGE_SIZE as u64) as u32);
+        memory
+            .max_http_response_bytes
+            .get_or_insert(max_http_response_bytes);
+        memory.max_var_bytes.get_or_insert(max_var_bytes);
+    }
+}
@@ -0,0 +1,518 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{Error, Pool};
+
+/// When a `Job` should run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schedule {
+    /// Run at a fixed interval, the first run happens one interval after the scheduler starts
+    Interval(Duration),
+
+    /// Run according to a cron expression
+    Cron(CronExpr),
+}
+
+impl Schedule {
+    /// Run every `interval`
+    pub fn every(interval: Duration) -> Self {
+        Schedule::Interval(interval)
+    }
+
+    /// Run according to a cron expression, see `CronExpr`
+    pub fn cron(expr: &str) -> Result<Self, Error> {
+        Ok(Schedule::Cron(expr.parse()?))
+    }
+
+    // Get the next run time after `after`
+    fn next(&self, after: Instant) -> Option<Instant> {
+        match self {
+            Schedule::Interval(d) => Some(after + (*d).max(Duration::from_millis(1))),
+            Schedule::Cron(expr) => {
+                // Cron expressions are evaluated using the system clock
+                let now = Instant::now();
+                let system_now = SystemTime::now();
+                let after = system_now + after.saturating_duration_since(now);
+                let secs = after.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs();
+                let next = SystemTime::UNIX_EPOCH + Duration::from_secs(expr.next_after(secs)?);
+                Some(now + next.duration_since(system_now).unwrap_or_default())
+            }
+        }
+    }
+}
+
+/// A standard five field cron expression (`minute hour day-of-month month day-of-week`), evaluated in UTC
+///
+/// Each field can be `*`, a number, a range (`1-5`) or a comma-separated list of these, optionally followed
+/// by a step (`*/15`, `0-30/10`). Day-of-week uses `0` or `7` for Sunday. When both day fields are
+/// restricted a day matches if either of them matches. The `@yearly`, `@monthly`, `@weekly`, `@daily` and
+/// `@hourly` shortcuts are also supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpr {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl std::str::FromStr for CronExpr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = match s.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            s => s,
+        };
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        if fields.len() != 5 {
+            anyhow::bail!("invalid cron expression, expected 5 fields: {s}");
+        }
+
+        let mut weekdays = Self::field(fields[4], 0, 7)?;
+        // Sunday can be either 0 or 7
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(CronExpr {
+            minutes: Self::field(fields[0], 0, 59)?,
+            hours: Self::field(fields[1], 0, 23)?,
+            days: Self::field(fields[2], 1, 31)?,
+            months: Self::field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+}
+
+impl CronExpr {
+    // Parse a single field into a bitset
+    fn field(s: &str, min: u64, max: u64) -> Result<u64, Error> {
+        let mut bits = 0;
+        for item in s.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u64>()?),
+                None => (item, 1),
+            };
+            let (start, end) = if range == "*" {
+                (min, max)
+            } else if let Some((a, b)) = range.split_once('-') {
+                (a.parse()?, b.parse()?)
+            } else {
+                let n = range.parse()?;
+                // `n/step` means every `step` starting at `n`
+                (n, if item.contains('/') { max } else { n })
+            };
+            if step == 0 || start < min || end > max || start > end {
+                anyhow::bail!("invalid cron field: {s}");
+            }
+            for n in (start..=end).step_by(step as usize) {
+                bits |= 1 << n;
+            }
+        }
+        Ok(bits)
+    }
+
+    fn day_matches(&self, day: u64, weekday: u64) -> bool {
+        let day = self.days & (1 << day) != 0;
+        let weekday = self.weekdays & (1 << weekday) != 0;
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /// Get the next matching time after `secs` (seconds since the Unix epoch), `None` is returned if the
+    /// expression never matches
+    pub fn next_after(&self, secs: u64) -> Option<u64> {
+        let mut t = (secs / 60 + 1) * 60;
+
+        // Each iteration skips at least one minute, and usually a whole month, day or hour
+        for _ in 0..100_000 {
+            let days = t / 86400;
+            let (year, month, day) = civil_from_days(days as i64);
+            if self.months & (1 << month) == 0 {
+                let (year, month) = if month == 12 {
+                    (year + 1, 1)
+                } else {
+                    (year, month + 1)
+                };
+                t = days_from_civil(year, month, 1) as u64 * 86400;
+                continue;
+            }
+
+            if !self.day_matches(day, (days + 4) % 7) {
+                t = (days + 1) * 86400;
+                continue;
+            }
+
+            let hour = (t % 86400) / 3600;
+            if self.hours & (1 << hour) == 0 {
+                t = days * 86400 + (hour + 1) * 3600;
+                continue;
+            }
+
+            let minute = (t % 3600) / 60;
+            if self.minutes & (1 << minute) == 0 {
+                t += 60;
+                continue;
+            }
+
+            return Some(t);
+        }
+        None
+    }
+}
+
+// Convert days since the Unix epoch to (year, month, day)
+pub(crate) fn civil_from_days(z: i64) -> (i64, u64, u64) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (
+        yoe + era * 400 + (month <= 2) as i64,
+        month as u64,
+        day as u64,
+    )
+}
+
+// Convert (year, month, day) to days since the Unix epoch
+fn days_from_civil(year: i64, month: u64, day: u64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// What to do when a job is due while a previous run is still in progress
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverlapPolicy {
+    /// Skip the run, `RunOutcome::Skipped` is reported
+    #[default]
+    Skip,
+
+    /// Start another run, using another instance from the `Pool`
+    Allow,
+}
+
+/// A named plugin function that runs on a `Schedule`
+#[derive(Debug, Clone)]
+pub struct Job {
+    name: String,
+    function: String,
+    input: Vec<u8>,
+    schedule: Schedule,
+    overlap: OverlapPolicy,
+    jitter: Duration,
+    backoff: Option<(Duration, Duration)>,
+}
+
+impl Job {
+    /// Create a new job that calls `function` on the given schedule, by default the function is called
+    /// with empty input
+    pub fn new(name: impl Into<String>, function: impl Into<String>, schedule: Schedule) -> Self {
+        Job {
+            name: name.into(),
+            function: function.into(),
+            input: vec![],
+            schedule,
+            overlap: OverlapPolicy::default(),
+            jitter: Duration::ZERO,
+            backoff: None,
+        }
+    }
+
+    /// Set the input passed to the function on each run
+    pub fn with_input(mut self, input: impl Into<Vec<u8>>) -> Self {
+        self.input = input.into();
+        self
+    }
+
+    /// Set the `OverlapPolicy`
+    pub fn with_overlap_policy(mut self, policy: OverlapPolicy) -> Self {
+        self.overlap = policy;
+        self
+    }
+
+    /// Delay each run by a random amount of time up to `jitter`, this can be used to avoid many jobs
+    /// starting at the same moment
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// After a failed run, wait at least `initial` before the next run, doubling the delay for each
+    /// consecutive failure up to `max`. The delay is reset after a successful run.
+    pub fn with_error_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = Some((initial, max));
+        self
+    }
+
+    /// Get the job name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// The result of a single scheduled run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// The function returned successfully
+    Success,
+
+    /// The function returned an error
+    Error(String),
+
+    /// The run was skipped because a previous run was still in progress
+    Skipped,
+
+    /// No plugin instance could be acquired from the `Pool` before the pool timeout
+    Unavailable,
+}
+
+/// Information about a scheduled run, passed to the callback set using `SchedulerBuilder::on_run`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunRecord {
+    /// Job name
+    pub job: String,
+
+    /// Function name
+    pub function: String,
+
+    /// When the run started
+    pub started: SystemTime,
+
+    /// How long the run took, this includes waiting for a plugin instance
+    pub duration: Duration,
+
+    /// Number of consecutive failed runs, including this one
+    pub failures: u32,
+
+    /// The result of the run
+    pub outcome: RunOutcome,
+}
+
+type RunCallback = dyn Fn(&RunRecord) + Send + Sync;
+
+/// `SchedulerBuilder` is used to configure and start a `Scheduler`
+#[derive(Default)]
+pub struct SchedulerBuilder {
+    jobs: Vec<Job>,
+    on_run: Option<Arc<RunCallback>>,
+    pool_timeout: Option<Duration>,
+}
+
+impl SchedulerBuilder {
+    /// Create a `SchedulerBuilder` with no jobs
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a job
+    pub fn with_job(mut self, job: Job) -> Self {
+        self.jobs.push(job);
+        self
+    }
+
+    /// Set a callback that's called after every run, including skipped runs
+    pub fn on_run(mut self, f: impl Fn(&RunRecord) + Send + Sync + 'static) -> Self {
+        self.on_run = Some(Arc::new(f));
+        self
+    }
+
+    /// Set how long a run waits for an instance from the `Pool`, the default is 10 seconds
+    pub fn with_pool_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_timeout = Some(timeout);
+        self
+    }
+
+    /// Start running jobs using plugins from `pool`
+    pub fn build(self, pool: Pool) -> Scheduler {
+        Scheduler::new(pool, self)
+    }
+}
+
+enum Message {
+    Finished { job: usize, success: bool },
+    Stop,
+}
+
+struct JobState {
+    job: Job,
+    // The next time according to the schedule, `due` includes jitter and backoff
+    next: Option<Instant>,
+    due: Option<Instant>,
+    failures: u32,
+    running: Arc<AtomicUsize>,
+}
+
+impl JobState {
+    fn schedule(&mut self, after: Instant) {
+        self.next = self.job.schedule.next(after);
+        self.due = self.next.map(|x| x + jitter(self.job.jitter));
+    }
+}
+
+fn jitter(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    let n = uuid::Uuid::new_v4().as_u64_pair().0;
+    Duration::from_nanos(n % max.as_nanos().min(u64::MAX as u128) as u64)
+}
+
+/// `Scheduler` runs plugin functions from a `Pool` on cron expressions or fixed intervals, jobs run until
+/// the scheduler is shut down or dropped
+pub struct Scheduler {
+    tx: mpsc::Sender<Message>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Scheduler {
+    fn new(pool: Pool, builder: SchedulerBuilder) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let sender = tx.clone();
+        let pool_timeout = builder.pool_timeout.unwrap_or(Duration::from_secs(10));
+        let on_run = builder.on_run;
+        let now = Instant::now();
+        let mut jobs: Vec<JobState> = builder
+            .jobs
+            .into_iter()
+            .map(|job| {
+                let mut state = JobState {
+                    job,
+                    next: None,
+                    due: None,
+                    failures: 0,
+                    running: Arc::new(AtomicUsize::new(0)),
+                };
+                state.schedule(now);
+                state
+            })
+            .collect();
+
+        let thread = std::thread::spawn(move || loop {
+            let now = Instant::now();
+            for (index, state) in jobs.iter_mut().enumerate() {
+                if state.due.is_none_or(|x| x > now) {
+                    continue;
+                }
+                let after = state.next.unwrap_or(now).max(now);
+                state.schedule(after);
+
+                let record = |outcome, duration, failures| RunRecord {
+                    job: state.job.name.clone(),
+                    function: state.job.function.clone(),
+                    started: SystemTime::now(),
+                    duration,
+                    failures,
+                    outcome,
+                };
+                if state.job.overlap == OverlapPolicy::Skip
+                    && state.running.load(Ordering::SeqCst) > 0
+                {
+                    if let Some(f) = &on_run {
+                        f(&record(RunOutcome::Skipped, Duration::ZERO, state.failures));
+                    }
+                    continue;
+                }
+
+                let mut record = record(RunOutcome::Success, Duration::ZERO, state.failures);
+                let running = state.running.clone();
+                let input = state.job.input.clone();
+                let pool = pool.clone();
+                let on_run = on_run.clone();
+                let tx = sender.clone();
+                running.fetch_add(1, Ordering::SeqCst);
+                std::thread::spawn(move || {
+                    let start = Instant::now();
+                    record.outcome = match pool.get(pool_timeout) {
+                        Ok(Some(mut plugin)) => {
+                            match plugin.call::<_, Vec<u8>>(&record.function, input) {
+                                Ok(_) => RunOutcome::Success,
+                                Err(e) => RunOutcome::Error(e.to_string()),
+                            }
+                        }
+                        Ok(None) => RunOutcome::Unavailable,
+                        Err(e) => RunOutcome::Error(e.to_string()),
+                    };
+                    record.duration = start.elapsed();
+                    let success = record.outcome == RunOutcome::Success;
+                    record.failures = if success { 0 } else { record.failures + 1 };
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    if let Some(f) = &on_run {
+                        f(&record);
+                    }
+                    let _ = tx.send(Message::Finished {
+                        job: index,
+                        success,
+                    });
+                });
+            }
+
+            let wait = jobs
+                .iter()
+                .filter_map(|x| x.due)
+                .min()
+                .map(|x| x.saturating_duration_since(Instant::now()))
+                .unwrap_or(Duration::from_secs(3600));
+            match rx.recv_timeout(wait) {
+                Ok(Message::Finished { job, success }) => {
+                    let state = &mut jobs[job];
+                    if success {
+                        state.failures = 0;
+                    } else {
+                        state.failures += 1;
+                        if let (Some((initial, max)), Some(due)) = (state.job.backoff, state.due) {
+                            let delay = initial
+                                .saturating_mul(2u32.saturating_pow(state.failures - 1))
+                                .min(max);
+                            state.due = Some(due.max(Instant::now() + delay));
+                        }
+                    }
+                }
+                Ok(Message::Stop) | Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                Err(mpsc::RecvTimeoutError::Timeout) => (),
+            }
+        });
+
+        Scheduler {
+            tx,
+            thread: Some(thread),
+        }
+    }
+
+    /// Stop scheduling new runs, runs that have already started are not interrupted
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        let _ = self.tx.send(Message::Stop);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -34,6 +34,22 @@ pub struct ExtismVal {
     v: ValUnion,
 }
 
+/// `ExtismCallResult` holds the return code, error and output of a call made using
+/// `extism_plugin_call_result`
+#[repr(C)]
+pub struct ExtismCallResult {
+    /// The return code, `0` when the call was successful
+    pub rc: i32,
+    /// The kind of error, see `extism_plugin_error_code`
+    pub error_code: i32,
+    /// The output data, this is `NULL` when the call failed
+    pub output: *const u8,
+    /// The length of `output`
+    pub output_length: Size,
+    /// The error message, this is `NULL` when the call was successful
+    pub error: *const c_char,
+}
+
 /// Host function signature
 pub type ExtismFunctionType = extern "C" fn(
     plugin: *mut CurrentPlugin,
@@ -697,16 +713,18 @@ pub unsafe extern "C" fn extism_plugin_config(
         };
 
     let id = plugin.id;
-    let config = &mut plugin.current_plugin_mut().manifest.config;
+    let current_plugin = plugin.current_plugin_mut();
     for (k, v) in json.into_iter() {
         match v {
             Some(v) => {
                 trace!(plugin = id.to_string(), "config, adding {k}");
-                config.insert(k, v);
+                current_plugin.config.insert(&k, &v);
+                current_plugin.manifest.config.insert(k, v);
             }
             None => {
                 trace!(plugin = id.to_string(), "config, removing {k}");
-                config.remove(&k);
+                current_plugin.config.remove(&k);
+                current_plugin.manifest.config.remove(&k);
             }
         }
     }
@@ -793,6 +811,7 @@ pub unsafe extern "C" fn extism_plugin_call_with_host_context(
         Ok(name) => name,
         Err(e) => {
             plugin.error_msg = Some(make_error_msg(e.to_string()));
+            plugin.error_code = 0;
             return -1;
         }
     };
@@ -802,7 +821,11 @@ pub unsafe extern "C" fn extism_plugin_call_with_host_context(
         "calling function {} using extism_plugin_call",
         name
     );
-    let input = std::slice::from_raw_parts(data, data_len as usize);
+    let input = if data.is_null() {
+        &[]
+    } else {
+        std::slice::from_raw_parts(data, data_len as usize)
+    };
     let r = if host_context.is_null() {
         None
     } else {
@@ -812,12 +835,50 @@ pub unsafe extern "C" fn extism_plugin_call_with_host_context(
     match res {
         Err((e, rc)) => {
             plugin.error_msg = Some(make_error_msg(e.to_string()));
+            plugin.error_code = ErrorKind::of(&e).code();
             rc
         }
         Ok(x) => x,
     }
 }
 
+/// Call a function with host context and return the result, instead of reading it using
+/// `extism_plugin_error` and `extism_plugin_output_data` afterwards. The output and error are owned by the
+/// plugin and are valid until the next call or until the plugin is freed.
+///
+/// `func_name`: is the function to call
+/// `data`: is the input data
+/// `data_len`: is the length of `data`
+/// `host_context`: a pointer to context data that will be available in host functions, this can be `NULL`
+#[no_mangle]
+pub unsafe extern "C" fn extism_plugin_call_result(
+    plugin: *mut Plugin,
+    func_name: *const c_char,
+    data: *const u8,
+    data_len: Size,
+    host_context: *mut std::ffi::c_void,
+) -> ExtismCallResult {
+    let rc = extism_plugin_call_with_host_context(plugin, func_name, data, data_len, host_context);
+    let mut result = ExtismCallResult {
+        rc,
+        error_code: extism_plugin_error_code(plugin),
+        output: std::ptr::null(),
+        output_length: 0,
+        error: std::ptr::null(),
+    };
+    if plugin.is_null() {
+        return result;
+    }
+
+    if rc == 0 {
+        result.output = extism_plugin_output_data(plugin);
+        result.output_length = extism_plugin_output_length(plugin);
+    } else {
+        result.error = extism_plugin_error(plugin);
+    }
+    result
+}
+
 /// Get the error associated with a `Plugin`
 #[no_mangle]
 #[deprecated]
@@ -858,6 +919,31 @@ pub unsafe extern "C" fn extism_plugin_error(plugin: *mut Plugin) -> *const c_ch
     plugin.error_msg.as_ref().unwrap().as_ptr() as *const _
 }
 
+/// Get the kind of error returned by the last call, this is `0` for errors that don't have a more specific
+/// kind and after successful calls. The codes are stable, see `ErrorKind::code`:
+///
+/// - 1: timeout
+/// - 2: cancelled
+/// - 3: out of memory
+/// - 4: out of fuel
+/// - 5: trap
+/// - 6: WASI exit with a non-zero code
+/// - 7: host function error
+/// - 8: error returned by the plugin
+/// - 9: encoding error
+/// - 10: function not found
+/// - 11: invalid signature
+#[no_mangle]
+pub unsafe extern "C" fn extism_plugin_error_code(plugin: *mut Plugin) -> i32 {
+    if plugin.is_null() {
+        return 0;
+    }
+    let plugin = &mut *plugin;
+    let _lock = plugin.instance.clone();
+    let _lock = _lock.lock().unwrap();
+    plugin.error_code
+}
+
 /// Get the length of a plugin's output data
 #[no_mangle]
 pub unsafe extern "C" fn extism_plugin_output_length(plugin: *mut Plugin) -> Size {
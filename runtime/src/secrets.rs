@@ -0,0 +1,107 @@
+use crate::*;
+
+/// Config values starting with this prefix are resolved using the plugin's `SecretsProvider`
+pub const SECRET_PREFIX: &str = "secret://";
+
+/// Replacement text used when a secret value would otherwise appear in an error message
+pub(crate) const REDACTED: &str = "[REDACTED]";
+
+/// A `SecretsProvider` resolves manifest config values of the form `secret://name`.
+///
+/// Secrets are resolved lazily, when the plugin calls `extism:host/env::config_get`, and the resolved
+/// value is never written back into the `Manifest`.
+pub trait SecretsProvider: Send + Sync {
+    /// Look up the secret with the given name (without the `secret://` prefix), `Ok(None)` should be
+    /// returned if the secret doesn't exist
+    fn get_secret(&self, name: &str) -> Result<Option<String>, Error>;
+}
+
+impl<F: Fn(&str) -> Result<Option<String>, Error> + Send + Sync> SecretsProvider for F {
+    fn get_secret(&self, name: &str) -> Result<Option<String>, Error> {
+        self(name)
+    }
+}
+
+/// A `SecretsProvider` that reads secrets from environment variables
+///
+/// For example, with a prefix of `APP_` the config value `secret://db_password` will be resolved
+/// using the `APP_DB_PASSWORD` environment variable.
+#[derive(Default, Debug, Clone)]
+pub struct EnvSecretsProvider {
+    prefix: String,
+}
+
+impl EnvSecretsProvider {
+    /// Create a new `EnvSecretsProvider`, the prefix is prepended to the upper-cased secret name
+    pub fn new(prefix: impl Into<String>) -> Self {
+        EnvSecretsProvider {
+            prefix: prefix.into(),
+        }
+    }
+}
+
+impl SecretsProvider for EnvSecretsProvider {
+    fn get_secret(&self, name: &str) -> Result<Option<String>, Error> {
+        let var = format!("{}{}", self.prefix, name.to_uppercase());
+        match std::env::var(var) {
+            Ok(x) => Ok(Some(x)),
+            Err(std::env::VarError::NotPresent) => Ok(None),
+            Err(e) => Err(Error::msg(e.to_string())),
+        }
+    }
+}
+
+/// Per-plugin secret state, keeps track of resolved values so they can be redacted from errors
+#[derive(Default)]
+pub(crate) struct Secrets {
+    provider: Option<std::sync::Arc<dyn SecretsProvider>>,
+    revealed: Vec<String>,
+}
+
+impl Secrets {
+    pub(crate) fn new(provider: Option<std::sync::Arc<dyn SecretsProvider>>) -> Self {
+        Secrets {
+            provider,
+            revealed: vec![],
+        }
+    }
+
+    /// Resolve a `secret://` config value, returning `Ok(None)` if the secret doesn't exist
+    pub(crate) fn resolve(&mut self, value: &str) -> Result<Option<String>, Error> {
+        let name = value.strip_prefix(SECRET_PREFIX).unwrap_or(value);
+
+        let Some(provider) = &self.provider else {
+            anyhow::bail!("no secrets provider is configured to resolve {SECRET_PREFIX}{name}");
+        };
+
+        match provider.get_secret(name) {
+            Ok(Some(x)) => {
+                if !x.is_empty() && !self.revealed.contains(&x) {
+                    self.revealed.push(x.clone());
+                }
+                Ok(Some(x))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => {
+                debug!("unable to resolve secret {SECRET_PREFIX}{name}: {e:?}");
+                anyhow::bail!("unable to resolve secret {SECRET_PREFIX}{name}")
+            }
+        }
+    }
+
+    /// Remove all resolved secrets from the given string
+    pub(crate) fn redact(&self, s: &str) -> String {
+        let mut s = s.to_string();
+        for secret in self.revealed.iter() {
+            if s.contains(secret.as_str()) {
+                s = s.replace(secret.as_str(), REDACTED);
+            }
+        }
+        s
+    }
+
+    /// Forget resolved secrets, this is called at the start of each plugin call
+    pub(crate) fn clear(&mut self) {
+        self.revealed.clear();
+    }
+}
@@ -0,0 +1,91 @@
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use crate::*;
+
+/// `PluginService` implements `tower::Service` for a single plugin function, so calls can be combined
+/// with tower middleware for retries, timeouts, rate limiting and load shedding
+///
+/// Each call checks out a plugin from the `Pool`, converts the request using `ToBytes`, and converts the
+/// output using `FromBytesOwned`. Calls run on the tokio blocking thread pool, so a tokio runtime is
+/// required.
+pub struct PluginService<I, O> {
+    pool: Pool,
+    function: Arc<str>,
+    pool_timeout: Duration,
+    _types: PhantomData<fn(I) -> O>,
+}
+
+impl<I, O> Clone for PluginService<I, O> {
+    fn clone(&self) -> Self {
+        PluginService {
+            pool: self.pool.clone(),
+            function: self.function.clone(),
+            pool_timeout: self.pool_timeout,
+            _types: PhantomData,
+        }
+    }
+}
+
+impl<I, O> std::fmt::Debug for PluginService<I, O> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PluginService")
+            .field("function", &self.function)
+            .field("pool_timeout", &self.pool_timeout)
+            .finish()
+    }
+}
+
+impl<I, O> PluginService<I, O> {
+    /// Create a new `PluginService` that calls `function` using plugins from `pool`
+    pub fn new(pool: Pool, function: impl AsRef<str>) -> Self {
+        PluginService {
+            pool,
+            function: function.as_ref().into(),
+            pool_timeout: Duration::from_secs(10),
+            _types: PhantomData,
+        }
+    }
+
+    /// Set how long a call waits for an instance from the `Pool`, the default is 10 seconds. An error is
+    /// returned if no instance is available in time.
+    pub fn with_pool_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_timeout = timeout;
+        self
+    }
+}
+
+impl<I, O> tower_service::Service<I> for PluginService<I, O>
+where
+    I: for<'a> ToBytes<'a> + Send + 'static,
+    O: FromBytesOwned + Send + 'static,
+{
+    type Response = O;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<O, Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Plugins are checked out from the pool when the call starts
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, input: I) -> Self::Future {
+        let service = self.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let Some(mut plugin) = service.pool.get(service.pool_timeout)? else {
+                    anyhow::bail!(
+                        "timed out waiting for a plugin instance to call {}",
+                        service.function
+                    );
+                };
+                plugin.call(&*service.function, input)
+            })
+            .await?
+        })
+    }
+}
@@ -0,0 +1,133 @@
+use crate::*;
+
+/// The error returned when a module's `WasmSignature` doesn't match the module data, it can be detected
+/// using `err.is::<InvalidSignature>()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidSignature {
+    /// The module name, `main` if the module isn't named
+    pub module: String,
+
+    /// Why the signature was rejected
+    pub reason: String,
+}
+
+impl std::fmt::Display for InvalidSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid signature for Wasm module {}: {}",
+            self.module, self.reason
+        )
+    }
+}
+
+impl std::error::Error for InvalidSignature {}
+
+/// Check `data` against `signature`, `name` is only used for errors
+#[cfg(feature = "signatures")]
+pub(crate) fn verify(name: &str, signature: &WasmSignature, data: &[u8]) -> Result<(), Error> {
+    let res = match signature {
+        WasmSignature::Ed25519 {
+            public_key,
+            signature,
+        } => verify_ed25519(public_key, signature, data),
+        WasmSignature::Sigstore { bundle, public_key } => verify_sigstore(bundle, public_key, data),
+    };
+    res.map_err(|reason| {
+        Error::new(InvalidSignature {
+            module: name.to_string(),
+            reason,
+        })
+    })
+}
+
+#[cfg(not(feature = "signatures"))]
+pub(crate) fn verify(name: &str, _signature: &WasmSignature, _data: &[u8]) -> Result<(), Error> {
+    anyhow::bail!(
+        "Wasm module {name} has a signature, signatures can only be verified when the `signatures` feature is enabled"
+    )
+}
+
+#[cfg(feature = "signatures")]
+fn base64(name: &str, s: &str) -> Result<Vec<u8>, String> {
+    use base64::Engine;
+    base64::engine::general_purpose::STANDARD
+        .decode(s.trim())
+        .map_err(|e| format!("{name} isn't valid base64: {e}"))
+}
+
+#[cfg(feature = "signatures")]
+fn verify_ed25519(public_key: &str, signature: &str, data: &[u8]) -> Result<(), String> {
+    let key: [u8; 32] = base64("public key", public_key)?
+        .try_into()
+        .map_err(|_| "public key must be 32 bytes".to_string())?;
+    let key = ed25519_dalek::VerifyingKey::from_bytes(&key).map_err(|e| e.to_string())?;
+    let signature = ed25519_dalek::Signature::from_slice(&base64("signature", signature)?)
+        .map_err(|_| "signature must be 64 bytes".to_string())?;
+    key.verify_strict(data, &signature)
+        .map_err(|_| "signature doesn't match the module data".to_string())
+}
+
+#[cfg(feature = "signatures")]
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SigstoreBundle {
+    #[serde(default)]
+    verification_material: serde_json::Map<String, serde_json::Value>,
+    message_signature: Option<MessageSignature>,
+}
+
+#[cfg(feature = "signatures")]
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MessageSignature {
+    message_digest: Option<MessageDigest>,
+    signature: String,
+}
+
+#[cfg(feature = "signatures")]
+#[derive(serde::Deserialize)]
+struct MessageDigest {
+    algorithm: String,
+    digest: String,
+}
+
+// Only the message signature is checked, transparency log entries in the bundle aren't verified
+#[cfg(feature = "signatures")]
+fn verify_sigstore(bundle: &str, public_key: &str, data: &[u8]) -> Result<(), String> {
+    use p256::ecdsa::signature::Verifier;
+    use p256::pkcs8::DecodePublicKey;
+    use sha2::Digest;
+
+    let bundle: SigstoreBundle =
+        serde_json::from_str(bundle).map_err(|e| format!("invalid Sigstore bundle: {e}"))?;
+    if bundle
+        .verification_material
+        .keys()
+        .any(|x| x == "certificate" || x == "x509CertificateChain")
+    {
+        return Err("keyless Sigstore bundles aren't supported".to_string());
+    }
+    let Some(message) = bundle.message_signature else {
+        return Err("Sigstore bundle doesn't contain a message signature".to_string());
+    };
+
+    if let Some(digest) = message.message_digest {
+        if digest.algorithm != "SHA2_256" {
+            return Err(format!(
+                "unsupported digest algorithm: {}",
+                digest.algorithm
+            ));
+        }
+        if base64("digest", &digest.digest)? != sha2::Sha256::digest(data).as_slice() {
+            return Err("Sigstore bundle digest doesn't match the module data".to_string());
+        }
+    }
+
+    let key = p256::ecdsa::VerifyingKey::from_public_key_pem(public_key.trim())
+        .map_err(|e| format!("invalid ECDSA P-256 public key: {e}"))?;
+    let signature = p256::ecdsa::Signature::from_der(&base64("signature", &message.signature)?)
+        .map_err(|e| format!("invalid ECDSA signature: {e}"))?;
+    key.verify(data, &signature)
+        .map_err(|_| "signature doesn't match the module data".to_string())
+}
@@ -0,0 +1,196 @@
+use crate::*;
+
+/// The state of an instance's exported memories and mutable globals after instantiation, see
+/// `PluginBuilder::with_copy_on_write_reset`
+///
+/// Restoring the snapshot copies each memory back using `Memory::data_mut`. Memory can't shrink, so the
+/// snapshot isn't restored into a memory that has grown since it was taken, the plugin is instantiated
+/// again instead.
+pub(crate) struct MemorySnapshot {
+    memories: Vec<(Memory, Vec<u8>)>,
+    globals: Vec<(Global, Val)>,
+}
+
+impl MemorySnapshot {
+    pub(crate) fn take(
+        store: &mut Store<CurrentPlugin>,
+        instance: Instance,
+    ) -> Result<Self, Error> {
+        let exports: Vec<Extern> = instance
+            .exports(&mut *store)
+            .map(|x| x.into_extern())
+            .collect();
+        let mut memories = vec![];
+        let mut globals = vec![];
+        for export in exports {
+            match export {
+                Extern::Memory(mem) => {
+                    let data = mem.data(&*store).to_vec();
+                    memories.push((mem, data));
+                }
+                Extern::Global(global) if global.ty(&*store).mutability() == Mutability::Var => {
+                    let value = global.get(&mut *store);
+                    globals.push((global, value));
+                }
+                _ => (),
+            }
+        }
+        Ok(MemorySnapshot { memories, globals })
+    }
+
+    // Returns `false` without changing anything when a memory has grown since the snapshot was taken
+    pub(crate) fn restore(&self, store: &mut Store<CurrentPlugin>) -> Result<bool, Error> {
+        if self
+            .memories
+            .iter()
+            .any(|(mem, data)| mem.data_size(&*store) != data.len())
+        {
+            return Ok(false);
+        }
+        for (mem, data) in self.memories.iter() {
+            mem.data_mut(&mut *store).copy_from_slice(data);
+        }
+        for (global, value) in self.globals.iter() {
+            global.set(&mut *store, *value)?;
+        }
+        Ok(true)
+    }
+}
+
+/// A copy of a plugin's exported memories and mutable globals, created by `Plugin::snapshot`
+///
+/// A snapshot can be restored into any instance of the same module using `Plugin::restore`, so state
+/// that's expensive to build can be created once and shared. Plugins created from the same `CompiledPlugin`
+/// share their module, other plugins are compared by the hash of their main module, which is only known
+/// when the manifest sets a hash or a `ModulePolicy` or `CallLog` is used. Memory managed by the Extism kernel, like
+/// inputs, outputs and variables, isn't included.
+#[derive(Clone)]
+pub struct PluginSnapshot {
+    module: Module,
+    hash: Option<String>,
+    memories: Vec<(String, Vec<u8>)>,
+    globals: Vec<(String, Val)>,
+}
+
+impl PluginSnapshot {
+    /// Total size of the memories in the snapshot, in bytes
+    pub fn memory_size(&self) -> usize {
+        self.memories.iter().map(|(_, data)| data.len()).sum()
+    }
+}
+
+impl std::fmt::Debug for PluginSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PluginSnapshot")
+            .field("hash", &self.hash)
+            .field("memory_size", &self.memory_size())
+            .field("globals", &self.globals.len())
+            .finish()
+    }
+}
+
+impl Plugin {
+    /// Capture the plugin's exported memories and mutable globals, the plugin is instantiated first if
+    /// needed. Globals that hold references aren't included.
+    pub fn snapshot(&mut self) -> Result<PluginSnapshot, Error> {
+        let instance = self.main_instance()?;
+        let exports: Vec<(String, Extern)> = instance
+            .exports(&mut self.store)
+            .map(|x| (x.name().to_string(), x.into_extern()))
+            .collect();
+        let mut memories = vec![];
+        let mut globals = vec![];
+        for (name, export) in exports {
+            match export {
+                Extern::Memory(mem) => memories.push((name, mem.data(&self.store).to_vec())),
+                Extern::Global(global)
+                    if global.ty(&self.store).mutability() == Mutability::Var =>
+                {
+                    let value = global.get(&mut self.store);
+                    if matches!(
+                        value,
+                        Val::I32(_) | Val::I64(_) | Val::F32(_) | Val::F64(_) | Val::V128(_)
+                    ) {
+                        globals.push((name, value));
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(PluginSnapshot {
+            module: self.modules[crate::plugin::MAIN_KEY].clone(),
+            hash: self.main_hash.clone(),
+            memories,
+            globals,
+        })
+    }
+
+    /// Restore a snapshot created by `Plugin::snapshot`, the snapshot must be taken from a plugin with the
+    /// same main module. Memories that have grown since the snapshot was taken keep their size, the
+    /// remaining bytes are zeroed.
+    ///
+    /// The restored state is lost when the instance is replaced, for example after a failed call, and
+    /// `Plugin::reset` still restores the state from after instantiation when copy-on-write reset is
+    /// enabled.
+    pub fn restore(&mut self, snapshot: &PluginSnapshot) -> Result<(), Error> {
+        let instance = self.main_instance()?;
+        let same_hash = snapshot.hash.is_some() && snapshot.hash == self.main_hash;
+        if !same_hash && !Module::same(&snapshot.module, &self.modules[crate::plugin::MAIN_KEY]) {
+            anyhow::bail!("snapshot was taken from a different module");
+        }
+        for (name, data) in snapshot.memories.iter() {
+            let Some(mem) = instance.get_memory(&mut self.store, name) else {
+                anyhow::bail!("memory not found: {name}");
+            };
+            let size = mem.data_size(&self.store);
+            if size < data.len() {
+                let page_size = mem.page_size(&self.store);
+                mem.grow(
+                    &mut self.store,
+                    ((data.len() - size) as u64).div_ceil(page_size),
+                )
+                .map_err(|e| OutOfMemory::normalize(e.into()))?;
+            }
+            let dest = mem.data_mut(&mut self.store);
+            dest[..data.len()].copy_from_slice(data);
+            dest[data.len()..].fill(0);
+        }
+        for (name, value) in snapshot.globals.iter() {
+            let Some(global) = instance.get_global(&mut self.store, name) else {
+                anyhow::bail!("global not found: {name}");
+            };
+            global.set(&mut self.store, *value)?;
+        }
+        Ok(())
+    }
+
+    // Get the main instance, it's created first if needed
+    pub(crate) fn main_instance(&mut self) -> Result<Instance, Error> {
+        #[cfg(unix)]
+        if self.helper.is_some() {
+            anyhow::bail!("snapshots aren't supported by out-of-process plugins");
+        }
+        #[cfg(feature = "component-model")]
+        if self.component.is_some() {
+            anyhow::bail!("snapshots aren't supported by component plugins");
+        }
+        self.initialize()?;
+        let lock = self.instance.clone();
+        let mut lock = lock
+            .try_lock()
+            .map_err(|_| Error::msg("cannot make reentrant calls into plugin"))?;
+        if let Some(fuel) = self.fuel {
+            self.store.set_fuel(fuel)?;
+        }
+        self.reset_store(&mut lock)?;
+        self.instantiate(&mut lock)?;
+        {
+            let store = &mut self.store as *mut _;
+            let linker = &mut self.linker as *mut _;
+            let current_plugin = self.current_plugin_mut();
+            current_plugin.store = store;
+            current_plugin.linker = linker;
+        }
+        Ok(lock.expect("plugin was instantiated"))
+    }
+}
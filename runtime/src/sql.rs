@@ -0,0 +1,586 @@
+use std::sync::Arc;
+#[cfg(any(feature = "sql-sqlite", feature = "sql-postgres"))]
+use std::sync::Mutex;
+
+use crate::pdk::args;
+use crate::*;
+
+/// Namespace for the SQL host functions
+pub const EXTISM_SQL_MODULE: &str = "extism:host/sql";
+
+/// A single SQL parameter or column value, values are encoded as plain JSON values when they're passed
+/// to or from a plugin
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum SqlValue {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Real(f64),
+    Text(String),
+}
+
+/// The result of a query
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SqlRows {
+    /// Column names
+    pub columns: Vec<String>,
+
+    /// Row values, in the same order as `columns`
+    pub rows: Vec<Vec<SqlValue>>,
+}
+
+/// A `SqlDatabase` executes statements on behalf of the `extism:host/sql` host functions, see
+/// `PluginBuilder::with_sql_database`
+///
+/// Every statement is executed in a namespace, implementations must make sure a plugin can't access
+/// data that belongs to other namespaces. `namespace` is always a non-empty string containing only ASCII
+/// letters, digits and underscores.
+pub trait SqlDatabase: Send + Sync {
+    /// Run a statement that returns rows
+    fn query(&self, namespace: &str, sql: &str, params: &[SqlValue]) -> Result<SqlRows, Error>;
+
+    /// Run a statement, returning the number of rows affected
+    fn execute(&self, namespace: &str, sql: &str, params: &[SqlValue]) -> Result<u64, Error>;
+
+    /// Files and directories the database reads and writes, they stay accessible to host functions when
+    /// `Hardening` restricts filesystem access
+    fn local_paths(&self) -> Vec<std::path::PathBuf> {
+        vec![]
+    }
+}
+
+impl<T: SqlDatabase + ?Sized> SqlDatabase for Arc<T> {
+    fn query(&self, namespace: &str, sql: &str, params: &[SqlValue]) -> Result<SqlRows, Error> {
+        (**self).query(namespace, sql, params)
+    }
+
+    fn execute(&self, namespace: &str, sql: &str, params: &[SqlValue]) -> Result<u64, Error> {
+        (**self).execute(namespace, sql, params)
+    }
+
+    fn local_paths(&self) -> Vec<std::path::PathBuf> {
+        (**self).local_paths()
+    }
+}
+
+pub(crate) fn check_namespace(namespace: &str) -> Result<(), Error> {
+    if namespace.is_empty()
+        || !namespace
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        anyhow::bail!("invalid SQL namespace: {namespace:?}");
+    }
+    Ok(())
+}
+
+/// A `SqlDatabase` backed by SQLite, each namespace is stored in a separate database. Statements can't
+/// attach other databases or run pragmas, so a plugin can only access its own database.
+#[cfg(feature = "sql-sqlite")]
+pub struct SqliteDatabase {
+    dir: Option<std::path::PathBuf>,
+    connections: Mutex<BTreeMap<String, rusqlite::Connection>>,
+}
+
+#[cfg(feature = "sql-sqlite")]
+impl SqliteDatabase {
+    /// Store databases in `dir`, using `<namespace>.db` as the filename
+    pub fn open(dir: impl Into<std::path::PathBuf>) -> Result<Self, Error> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(SqliteDatabase {
+            dir: Some(dir),
+            connections: Default::default(),
+        })
+    }
+
+    /// Use a new in-memory database for each namespace
+    pub fn in_memory() -> Self {
+        SqliteDatabase {
+            dir: None,
+            connections: Default::default(),
+        }
+    }
+
+    fn with_connection<T>(
+        &self,
+        namespace: &str,
+        f: impl FnOnce(&rusqlite::Connection) -> Result<T, rusqlite::Error>,
+    ) -> Result<T, Error> {
+        check_namespace(namespace)?;
+        let mut connections = match self.connections.lock() {
+            Ok(x) => x,
+            Err(e) => e.into_inner(),
+        };
+        let conn = match connections.entry(namespace.to_string()) {
+            std::collections::btree_map::Entry::Occupied(x) => x.into_mut(),
+            std::collections::btree_map::Entry::Vacant(x) => {
+                let conn = match &self.dir {
+                    Some(dir) => rusqlite::Connection::open(dir.join(format!("{namespace}.db")))?,
+                    None => rusqlite::Connection::open_in_memory()?,
+                };
+                Self::restrict(&conn)?;
+                x.insert(conn)
+            }
+        };
+        Ok(f(conn)?)
+    }
+
+    // Deny statements that could reach other databases: `ATTACH` would open any file on disk, including
+    // the databases for other namespaces, and pragmas can change how the connection behaves
+    fn restrict(conn: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        use rusqlite::hooks::{AuthAction, AuthContext, Authorization};
+
+        conn.set_limit(rusqlite::limits::Limit::SQLITE_LIMIT_ATTACHED, 0)?;
+        conn.authorizer(Some(|ctx: AuthContext<'_>| match ctx.action {
+            AuthAction::Attach { .. } | AuthAction::Detach { .. } | AuthAction::Pragma { .. } => {
+                Authorization::Deny
+            }
+            _ => Authorization::Allow,
+        }))
+    }
+}
+
+#[cfg(feature = "sql-sqlite")]
+impl rusqlite::ToSql for SqlValue {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        use rusqlite::types::{ToSqlOutput, Value};
+        Ok(match self {
+            SqlValue::Null => ToSqlOutput::Owned(Value::Null),
+            SqlValue::Bool(x) => ToSqlOutput::Owned(Value::Integer(*x as i64)),
+            SqlValue::Integer(x) => ToSqlOutput::Owned(Value::Integer(*x)),
+            SqlValue::Real(x) => ToSqlOutput::Owned(Value::Real(*x)),
+            SqlValue::Text(x) => ToSqlOutput::Borrowed(x.as_str().into()),
+        })
+    }
+}
+
+#[cfg(feature = "sql-sqlite")]
+impl SqlDatabase for SqliteDatabase {
+    fn query(&self, namespace: &str, sql: &str, params: &[SqlValue]) -> Result<SqlRows, Error> {
+        self.with_connection(namespace, |conn| {
+            use rusqlite::types::ValueRef;
+
+            let mut stmt = conn.prepare(sql)?;
+            let columns: Vec<String> = stmt.column_names().iter().map(|x| x.to_string()).collect();
+            let mut rows = vec![];
+            let mut query = stmt.query(rusqlite::params_from_iter(params))?;
+            while let Some(row) = query.next()? {
+                let mut values = Vec::with_capacity(columns.len());
+                for (i, column) in columns.iter().enumerate() {
+                    values.push(match row.get_ref(i)? {
+                        ValueRef::Null => SqlValue::Null,
+                        ValueRef::Integer(x) => SqlValue::Integer(x),
+                        ValueRef::Real(x) => SqlValue::Real(x),
+                        ValueRef::Text(x) => {
+                            SqlValue::Text(String::from_utf8_lossy(x).into_owned())
+                        }
+                        ValueRef::Blob(_) => {
+                            return Err(rusqlite::Error::InvalidColumnType(
+                                i,
+                                column.clone(),
+                                rusqlite::types::Type::Blob,
+                            ))
+                        }
+                    });
+                }
+                rows.push(values);
+            }
+            Ok(SqlRows { columns, rows })
+        })
+    }
+
+    fn execute(&self, namespace: &str, sql: &str, params: &[SqlValue]) -> Result<u64, Error> {
+        self.with_connection(namespace, |conn| {
+            conn.execute(sql, rusqlite::params_from_iter(params))
+                .map(|n| n as u64)
+        })
+    }
+
+    fn local_paths(&self) -> Vec<std::path::PathBuf> {
+        self.dir.iter().cloned().collect()
+    }
+}
+
+/// A `SqlDatabase` backed by a pool of PostgreSQL connections, each namespace is mapped to a schema
+/// with the same name, which is created if it doesn't exist
+///
+/// Statements run as a role named `extism_<namespace>` that can only use its own schema, so the
+/// connection's user needs the `CREATEROLE` privilege. Statements that could leave the role or the
+/// transaction, such as `SET`, `RESET`, `COMMIT` or more than one statement at a time, are rejected.
+#[cfg(feature = "sql-postgres")]
+pub struct PostgresDatabase {
+    url: String,
+    max_idle: usize,
+    idle: Mutex<Vec<postgres::Client>>,
+    schemas: Mutex<std::collections::BTreeSet<String>>,
+}
+
+#[cfg(feature = "sql-postgres")]
+impl PostgresDatabase {
+    /// Create a new connection pool for the given connection string, connections are opened when needed
+    /// and at most `max_idle` idle connections are kept open
+    pub fn new(url: impl Into<String>, max_idle: usize) -> Self {
+        PostgresDatabase {
+            url: url.into(),
+            max_idle,
+            idle: Default::default(),
+            schemas: Default::default(),
+        }
+    }
+
+    fn with_transaction<T>(
+        &self,
+        namespace: &str,
+        f: impl FnOnce(&mut postgres::Transaction) -> Result<T, postgres::Error>,
+    ) -> Result<T, Error> {
+        check_namespace(namespace)?;
+        let client = match self.idle.lock() {
+            Ok(mut x) => x.pop(),
+            Err(e) => e.into_inner().pop(),
+        };
+        let mut client = match client {
+            Some(x) => x,
+            None => postgres::Client::connect(&self.url, postgres::NoTls)?,
+        };
+
+        let res = (|| {
+            let created = match self.schemas.lock() {
+                Ok(x) => x.contains(namespace),
+                Err(e) => e.into_inner().contains(namespace),
+            };
+            let role = format!("extism_{namespace}");
+            if !created {
+                client.batch_execute(&format!(
+                    "CREATE SCHEMA IF NOT EXISTS \"{namespace}\";
+                    DO $$ BEGIN
+                        IF NOT EXISTS (SELECT FROM pg_roles WHERE rolname = '{role}') THEN
+                            CREATE ROLE \"{role}\" NOLOGIN;
+                        END IF;
+                    END $$;
+                    GRANT \"{role}\" TO CURRENT_USER;
+                    REVOKE ALL ON SCHEMA \"{namespace}\" FROM PUBLIC;
+                    GRANT USAGE, CREATE ON SCHEMA \"{namespace}\" TO \"{role}\";"
+                ))?;
+                if let Ok(mut x) = self.schemas.lock() {
+                    x.insert(namespace.to_string());
+                }
+            }
+
+            let mut tx = client.transaction()?;
+            tx.batch_execute(&format!(
+                "SET LOCAL search_path TO \"{namespace}\"; SET LOCAL ROLE \"{role}\""
+            ))?;
+            let x = f(&mut tx)?;
+            tx.commit()?;
+            Ok::<T, postgres::Error>(x)
+        })();
+
+        if !client.is_closed() {
+            if let Ok(mut idle) = self.idle.lock() {
+                if idle.len() < self.max_idle {
+                    idle.push(client);
+                }
+            }
+        }
+
+        Ok(res?)
+    }
+
+    fn params(params: &[SqlValue]) -> Vec<&(dyn postgres::types::ToSql + Sync)> {
+        params
+            .iter()
+            .map(|x| x as &(dyn postgres::types::ToSql + Sync))
+            .collect()
+    }
+}
+
+#[cfg(feature = "sql-postgres")]
+impl postgres::types::ToSql for SqlValue {
+    fn to_sql(
+        &self,
+        ty: &postgres::types::Type,
+        out: &mut postgres::types::private::BytesMut,
+    ) -> Result<postgres::types::IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        use postgres::types::{IsNull, Type};
+
+        // Integers and floats are converted to the width expected by the statement
+        match self {
+            SqlValue::Null => Ok(IsNull::Yes),
+            SqlValue::Bool(x) => x.to_sql(ty, out),
+            SqlValue::Integer(x) => match *ty {
+                Type::INT2 => i16::try_from(*x)?.to_sql(ty, out),
+                Type::INT4 => i32::try_from(*x)?.to_sql(ty, out),
+                Type::FLOAT4 => (*x as f32).to_sql(ty, out),
+                Type::FLOAT8 => (*x as f64).to_sql(ty, out),
+                _ => x.to_sql(ty, out),
+            },
+            SqlValue::Real(x) => match *ty {
+                Type::FLOAT4 => (*x as f32).to_sql(ty, out),
+                _ => x.to_sql(ty, out),
+            },
+            SqlValue::Text(x) => x.to_sql(ty, out),
+        }
+    }
+
+    fn accepts(_ty: &postgres::types::Type) -> bool {
+        true
+    }
+
+    postgres::types::to_sql_checked!();
+}
+
+#[cfg(feature = "sql-postgres")]
+impl SqlDatabase for PostgresDatabase {
+    fn query(&self, namespace: &str, sql: &str, params: &[SqlValue]) -> Result<SqlRows, Error> {
+        check_postgres_statement(sql)?;
+        self.with_transaction(namespace, |tx| {
+            use postgres::types::Type;
+
+            let stmt = tx.prepare(sql)?;
+            let columns: Vec<String> = stmt
+                .columns()
+                .iter()
+                .map(|c| c.name().to_string())
+                .collect();
+            let mut rows = vec![];
+            for row in tx.query(&stmt, &Self::params(params))? {
+                let mut values = Vec::with_capacity(columns.len());
+                for (i, column) in stmt.columns().iter().enumerate() {
+                    let value = match *column.type_() {
+                        Type::BOOL => row.try_get::<_, Option<bool>>(i)?.map(SqlValue::Bool),
+                        Type::INT2 => row
+                            .try_get::<_, Option<i16>>(i)?
+                            .map(|x| SqlValue::Integer(x as i64)),
+                        Type::INT4 => row
+                            .try_get::<_, Option<i32>>(i)?
+                            .map(|x| SqlValue::Integer(x as i64)),
+                        Type::INT8 => row.try_get::<_, Option<i64>>(i)?.map(SqlValue::Integer),
+                        Type::FLOAT4 => row
+                            .try_get::<_, Option<f32>>(i)?
+                            .map(|x| SqlValue::Real(x as f64)),
+                        Type::FLOAT8 => row.try_get::<_, Option<f64>>(i)?.map(SqlValue::Real),
+                        _ => row.try_get::<_, Option<String>>(i)?.map(SqlValue::Text),
+                    };
+                    values.push(value.unwrap_or(SqlValue::Null));
+                }
+                rows.push(values);
+            }
+            Ok(SqlRows { columns, rows })
+        })
+    }
+
+    fn execute(&self, namespace: &str, sql: &str, params: &[SqlValue]) -> Result<u64, Error> {
+        check_postgres_statement(sql)?;
+        self.with_transaction(namespace, |tx| tx.execute(sql, &Self::params(params)))
+    }
+}
+
+/// Reject SQL that could leave the namespace's role or transaction: more than one statement,
+/// transaction control, `SET`/`RESET`, `set_config` and code that runs with other privileges
+#[cfg(feature = "sql-postgres")]
+pub(crate) fn check_postgres_statement(sql: &str) -> Result<(), Error> {
+    const DENIED: &[&str] = &[
+        "abort",
+        "begin",
+        "commit",
+        "discard",
+        "do",
+        "end",
+        "load",
+        "prepare",
+        "release",
+        "reset",
+        "rollback",
+        "savepoint",
+        "set",
+        "start",
+    ];
+
+    let mut words = vec![];
+    let mut end = false;
+    let mut chars = sql.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if end {
+            anyhow::bail!("only a single SQL statement can be executed at a time");
+        }
+        match c {
+            ';' => end = true,
+            '-' if sql[i..].starts_with("--") => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if sql[i..].starts_with("/*") => {
+                // Block comments can be nested
+                chars.next();
+                let mut depth = 1;
+                while depth > 0 {
+                    match chars.next() {
+                        Some((j, '*')) if sql[j..].starts_with("*/") => {
+                            chars.next();
+                            depth -= 1;
+                        }
+                        Some((j, '/')) if sql[j..].starts_with("/*") => {
+                            chars.next();
+                            depth += 1;
+                        }
+                        Some(_) => (),
+                        None => anyhow::bail!("unterminated comment in SQL statement"),
+                    }
+                }
+            }
+            '\'' | '"' => {
+                // Strings prefixed with `E` use backslash escapes
+                let escapes = c == '\''
+                    && words
+                        .last()
+                        .is_some_and(|(w, j): &(String, usize)| w == "e" && j + 1 == i);
+                if escapes {
+                    words.pop();
+                }
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '\\')) if escapes => {
+                            chars.next();
+                        }
+                        Some((_, x)) if x == c => {
+                            if chars.peek().is_some_and(|(_, x)| *x == c) {
+                                chars.next();
+                                value.push(c);
+                            } else {
+                                break;
+                            }
+                        }
+                        Some((_, x)) => value.push(x),
+                        None => anyhow::bail!("unterminated quote in SQL statement"),
+                    }
+                }
+                if c == '"' {
+                    words.push((value.to_lowercase(), i));
+                }
+            }
+            '$' => {
+                // Skip dollar-quoted strings, `$1` is a parameter
+                let rest = &sql[i + 1..];
+                let n = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                if !rest[n..].starts_with('$') || rest.starts_with(|c: char| c.is_ascii_digit()) {
+                    continue;
+                }
+                let tag = &sql[i..i + n + 2];
+                let body = i + tag.len();
+                match sql[body..].find(tag) {
+                    Some(n) => {
+                        let stop = body + n + tag.len();
+                        while chars.peek().is_some_and(|(j, _)| *j < stop) {
+                            chars.next();
+                        }
+                    }
+                    None => anyhow::bail!("unterminated dollar-quoted string in SQL statement"),
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut word = c.to_lowercase().to_string();
+                while let Some((_, x)) = chars.peek() {
+                    if !(x.is_alphanumeric() || *x == '_' || *x == '$') {
+                        break;
+                    }
+                    word.extend(x.to_lowercase());
+                    chars.next();
+                }
+                words.push((word, i));
+            }
+            _ => (),
+        }
+    }
+
+    let mut words = words.iter().map(|(w, _)| w.as_str());
+    let first = words.next().unwrap_or_default();
+    if DENIED.contains(&first) {
+        anyhow::bail!("{} statements are not allowed", first.to_uppercase());
+    }
+    if first == "create" {
+        if let Some(kind) = words.clone().find(|w| *w != "or" && *w != "replace") {
+            if matches!(kind, "function" | "procedure" | "trigger" | "event") {
+                anyhow::bail!("creating functions, procedures and triggers is not allowed");
+            }
+        }
+    }
+    if words.any(|w| w == "set_config") {
+        anyhow::bail!("set_config is not allowed");
+    }
+    Ok(())
+}
+
+// Host functions, these are linked into the `extism:host/sql` namespace when a `SqlDatabase` is
+// configured
+
+#[derive(serde::Deserialize)]
+struct SqlRequest {
+    sql: String,
+    #[serde(default)]
+    params: Vec<SqlValue>,
+}
+
+fn read_request(data: &mut CurrentPlugin, offset: u64) -> Result<SqlRequest, Error> {
+    let handle = match data.memory_handle(offset) {
+        Some(h) => h,
+        None => anyhow::bail!("invalid handle offset for SQL request: {offset}"),
+    };
+    let req = serde_json::from_slice(data.memory_bytes(handle)?)?;
+    data.memory_free(handle)?;
+    Ok(req)
+}
+
+fn database(data: &CurrentPlugin) -> Result<(Arc<dyn SqlDatabase>, String), Error> {
+    match &data.sql {
+        Some((db, namespace)) => Ok((db.clone(), namespace.clone())),
+        None => anyhow::bail!("no SQL database is configured"),
+    }
+}
+
+/// Run a query
+/// Params: i64 (offset of a JSON request: `{"sql": "...", "params": [...]}`)
+/// Returns: i64 (offset of a JSON response: `{"columns": [...], "rows": [[...]]}`)
+/// **Note**: this function takes ownership of the handle passed in
+/// the caller should not `free` this value
+pub(crate) fn query(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let req = read_request(data, args!(input, 0, i64) as u64)?;
+    let (db, namespace) = database(data)?;
+    let rows = db.query(&namespace, &req.sql, &req.params)?;
+    let json = serde_json::to_vec(&rows)?;
+    output[0] = Val::I64(data.memory_new(json)?.offset() as i64);
+    Ok(())
+}
+
+/// Execute a statement
+/// Params: i64 (offset of a JSON request: `{"sql": "...", "params": [...]}`)
+/// Returns: i64 (number of rows affected)
+/// **Note**: this function takes ownership of the handle passed in
+/// the caller should not `free` this value
+pub(crate) fn execute(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let req = read_request(data, args!(input, 0, i64) as u64)?;
+    let (db, namespace) = database(data)?;
+    let n = db.execute(&namespace, &req.sql, &req.params)?;
+    output[0] = Val::I64(n as i64);
+    Ok(())
+}
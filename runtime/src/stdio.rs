@@ -0,0 +1,183 @@
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+
+/// A writer set using `PluginBuilder::with_stdout` or `PluginBuilder::with_stderr`, it's shared by every
+/// plugin created from the builder
+#[derive(Clone)]
+pub(crate) struct SharedWriter(Arc<Mutex<dyn Write + Send>>);
+
+impl SharedWriter {
+    pub(crate) fn new(w: impl Write + Send + 'static) -> Self {
+        SharedWriter(Arc::new(Mutex::new(w)))
+    }
+}
+
+impl Write for SharedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[std::io::IoSlice<'_>]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.lock().unwrap().flush()
+    }
+}
+
+/// Guest input on WASI stdin, see `PluginBuilder::with_stdin`
+///
+/// ```no_run
+/// # use extism::*;
+/// # fn example(manifest: Manifest) -> Result<(), Error> {
+/// let plugin = PluginBuilder::new(manifest)
+///     .with_wasi(true)
+///     .with_stdin("hello")
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Stdin(StdinSource);
+
+#[derive(Clone)]
+enum StdinSource {
+    Bytes(Arc<[u8]>),
+    Reader(Arc<Mutex<dyn Read + Send>>),
+}
+
+impl Stdin {
+    /// Every call reads `data` from the start
+    pub fn bytes(data: impl Into<Vec<u8>>) -> Self {
+        Stdin(StdinSource::Bytes(data.into().into()))
+    }
+
+    /// Read from `r`, it's shared by every plugin created from the builder and isn't rewound between
+    /// calls
+    pub fn reader(r: impl Read + Send + 'static) -> Self {
+        Stdin(StdinSource::Reader(Arc::new(Mutex::new(r))))
+    }
+
+    /// Returns `true` when each call starts reading from the beginning again
+    pub(crate) fn is_bytes(&self) -> bool {
+        matches!(self.0, StdinSource::Bytes(_))
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) fn file(&self) -> Box<dyn wasi_common::WasiFile> {
+        use wasi_common::pipe::ReadPipe;
+        match &self.0 {
+            StdinSource::Bytes(b) => Box::new(ReadPipe::from(b.to_vec())),
+            StdinSource::Reader(r) => Box::new(ReadPipe::new(SharedReader(r.clone()))),
+        }
+    }
+}
+
+impl From<Vec<u8>> for Stdin {
+    fn from(data: Vec<u8>) -> Self {
+        Stdin::bytes(data)
+    }
+}
+
+impl From<&[u8]> for Stdin {
+    fn from(data: &[u8]) -> Self {
+        Stdin::bytes(data)
+    }
+}
+
+impl From<String> for Stdin {
+    fn from(data: String) -> Self {
+        Stdin::bytes(data)
+    }
+}
+
+impl From<&str> for Stdin {
+    fn from(data: &str) -> Self {
+        Stdin::bytes(data)
+    }
+}
+
+struct SharedReader(Arc<Mutex<dyn Read + Send>>);
+
+impl Read for SharedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().read(buf)
+    }
+}
+
+/// The default max number of bytes captured from each of stdout and stderr during a call
+pub(crate) const DEFAULT_CAPTURE_LIMIT: usize = 1024 * 1024;
+
+/// Guest output for the current call, when `PluginBuilder::with_output_capture` is enabled. Each plugin
+/// has its own buffers and they're kept when WASI is reset.
+pub(crate) struct Captured {
+    stdout: Mutex<Vec<u8>>,
+    stderr: Mutex<Vec<u8>>,
+    limit: usize,
+}
+
+impl Captured {
+    /// Keep at most `limit` bytes of each stream, anything written past the limit is dropped
+    pub(crate) fn new(limit: usize) -> Self {
+        Captured {
+            stdout: Default::default(),
+            stderr: Default::default(),
+            limit,
+        }
+    }
+
+    pub(crate) fn clear(&self) {
+        self.stdout.lock().unwrap().clear();
+        self.stderr.lock().unwrap().clear();
+    }
+
+    pub(crate) fn take_stdout(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.stdout.lock().unwrap())
+    }
+
+    pub(crate) fn take_stderr(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.stderr.lock().unwrap())
+    }
+
+    pub(crate) fn stdout(self: &Arc<Self>) -> CaptureWriter {
+        CaptureWriter {
+            captured: self.clone(),
+            stderr: false,
+        }
+    }
+
+    pub(crate) fn stderr(self: &Arc<Self>) -> CaptureWriter {
+        CaptureWriter {
+            captured: self.clone(),
+            stderr: true,
+        }
+    }
+}
+
+/// Appends to one of the `Captured` buffers
+pub(crate) struct CaptureWriter {
+    captured: Arc<Captured>,
+    stderr: bool,
+}
+
+impl Write for CaptureWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let buffer = if self.stderr {
+            &self.captured.stderr
+        } else {
+            &self.captured.stdout
+        };
+        // Output past the limit is dropped instead of returning an error, so the guest keeps running
+        let mut buffer = buffer.lock().unwrap();
+        let n = buf
+            .len()
+            .min(self.captured.limit.saturating_sub(buffer.len()));
+        buffer.extend_from_slice(&buf[..n]);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+use std::io::{Read, Write};
+
+use crate::*;
+
+// The smallest block allocated for streamed input, the block doubles in size whenever it's full
+const MIN_CAPACITY: u64 = 64 * 1024;
+
+/// A call started by `Plugin::call_streaming`. The input is written in chunks directly into plugin memory
+/// using `std::io::Write`, so it's never buffered on the host, then `StreamingCall::finish` calls the
+/// function.
+///
+/// The input isn't recorded by `PluginBuilder::with_call_log`.
+pub struct StreamingCall<'a> {
+    plugin: &'a mut Plugin,
+    name: String,
+    block: MemoryHandle,
+    len: u64,
+}
+
+impl<'a> StreamingCall<'a> {
+    /// Number of input bytes written so far
+    pub fn input_len(&self) -> u64 {
+        self.len
+    }
+
+    /// Call the function with the input that has been written, the output can be read from the
+    /// returned `OutputReader`
+    pub fn finish(self) -> Result<OutputReader<'a>, Error> {
+        let StreamingCall {
+            plugin,
+            name,
+            block,
+            len,
+        } = self;
+        plugin.streamed_input = Some(unsafe { MemoryHandle::new(block.offset, len) });
+        let lock = plugin.instance.clone();
+        let mut lock = lock
+            .try_lock()
+            .map_err(|_| Error::msg("cannot make reentrant calls into plugin"))?;
+        let res = plugin.raw_call(&mut lock, &name, &[][..], None::<()>);
+        plugin.streamed_input = None;
+        res.map_err(|e| e.0)?;
+        Ok(OutputReader {
+            offset: plugin.output.offset,
+            remaining: plugin.output.length,
+            plugin,
+        })
+    }
+
+    // Make sure there's room for `n` more bytes, a larger block is allocated and the input written so far is
+    // copied into it when needed
+    fn reserve(&mut self, n: u64) -> Result<(), Error> {
+        let needed = self.len + n;
+        if needed <= self.block.length {
+            return Ok(());
+        }
+        let capacity = needed.max(self.block.length * 2).max(MIN_CAPACITY);
+        let current_plugin = self.plugin.current_plugin_mut();
+        let block = current_plugin.memory_alloc(capacity)?;
+        if self.len > 0 {
+            // The pointer is only valid after allocating, since allocating can grow the memory
+            let ptr = current_plugin.memory_ptr();
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    ptr.add(self.block.offset as usize),
+                    ptr.add(block.offset as usize),
+                    self.len as usize,
+                );
+            }
+        }
+        if self.block.offset != 0 {
+            current_plugin.memory_free(self.block)?;
+        }
+        self.block = block;
+        Ok(())
+    }
+}
+
+impl Write for StreamingCall<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.reserve(buf.len() as u64)
+            .map_err(std::io::Error::other)?;
+        let dest = unsafe { MemoryHandle::new(self.block.offset + self.len, buf.len() as u64) };
+        self.plugin
+            .current_plugin_mut()
+            .memory_bytes_mut(dest)
+            .map_err(std::io::Error::other)?
+            .copy_from_slice(buf);
+        self.len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads the output of a `StreamingCall` from plugin memory in chunks, the output is invalidated by the next
+/// call
+pub struct OutputReader<'a> {
+    plugin: &'a mut Plugin,
+    offset: u64,
+    remaining: u64,
+}
+
+impl OutputReader<'_> {
+    /// Number of output bytes that haven't been read yet
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+}
+
+impl Read for OutputReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.remaining.min(buf.len() as u64);
+        if n == 0 {
+            return Ok(0);
+        }
+        let src = unsafe { MemoryHandle::new(self.offset, n) };
+        let data = self
+            .plugin
+            .current_plugin_mut()
+            .memory_bytes(src)
+            .map_err(std::io::Error::other)?;
+        buf[..n as usize].copy_from_slice(data);
+        self.offset += n;
+        self.remaining -= n;
+        Ok(n as usize)
+    }
+}
+
+impl Plugin {
+    /// Start a call to the function `name` with input that's written incrementally, this is useful for
+    /// inputs that are too large to keep a second copy of on the host. The plugin's input and output are
+    /// still stored in plugin memory.
+    ///
+    /// ```no_run
+    /// # fn example(plugin: &mut extism::Plugin) -> Result<(), extism::Error> {
+    /// let mut call = plugin.call_streaming("process")?;
+    /// std::io::copy(&mut std::fs::File::open("input.bin")?, &mut call)?;
+    /// let mut output = call.finish()?;
+    /// std::io::copy(&mut output, &mut std::io::stdout())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_streaming(&mut self, name: impl AsRef<str>) -> Result<StreamingCall<'_>, Error> {
+        #[cfg(unix)]
+        if self.helper.is_some() {
+            anyhow::bail!("streaming calls aren't supported by out-of-process plugins");
+        }
+        #[cfg(feature = "component-model")]
+        if self.component.is_some() {
+            anyhow::bail!("streaming calls aren't supported by component plugins");
+        }
+        self.initialize()?;
+
+        // The instance is created before any input is written, so it isn't replaced when the call starts
+        let lock = self.instance.clone();
+        let mut lock = lock
+            .try_lock()
+            .map_err(|_| Error::msg("cannot make reentrant calls into plugin"))?;
+        if let Some(fuel) = self.fuel {
+            self.store.set_fuel(fuel)?;
+        }
+        self.reset_store(&mut lock)?;
+        self.instantiate(&mut lock)?;
+        {
+            let store = &mut self.store as *mut _;
+            let linker = &mut self.linker as *mut _;
+            let current_plugin = self.current_plugin_mut();
+            current_plugin.store = store;
+            current_plugin.linker = linker;
+        }
+        self.reset_kernel()?;
+        Ok(StreamingCall {
+            plugin: self,
+            name: name.as_ref().to_string(),
+            block: MemoryHandle::null(),
+            len: 0,
+        })
+    }
+}
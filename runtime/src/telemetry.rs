@@ -0,0 +1,264 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use crate::*;
+
+/// A destination for telemetry data, each batch contains one or more newline-terminated JSON objects
+pub trait TelemetrySink: Send + 'static {
+    /// Write a batch of JSON lines
+    fn write_batch(&mut self, batch: &[u8]) -> Result<(), Error>;
+}
+
+/// Appends telemetry to a file
+pub struct FileSink {
+    file: std::fs::File,
+}
+
+impl FileSink {
+    /// Open the file at `path` for appending, it will be created if it doesn't exist
+    pub fn new(path: impl AsRef<std::path::Path>) -> Result<Self, Error> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(FileSink { file })
+    }
+}
+
+impl TelemetrySink for FileSink {
+    fn write_batch(&mut self, batch: &[u8]) -> Result<(), Error> {
+        self.file.write_all(batch)?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Sends telemetry over a TCP connection, the connection is re-established after errors
+pub struct TcpSink {
+    addr: String,
+    stream: Option<std::net::TcpStream>,
+}
+
+impl TcpSink {
+    /// Create a new `TcpSink`, the connection is opened when the first batch is written
+    pub fn new(addr: impl Into<String>) -> Self {
+        TcpSink {
+            addr: addr.into(),
+            stream: None,
+        }
+    }
+}
+
+impl TelemetrySink for TcpSink {
+    fn write_batch(&mut self, batch: &[u8]) -> Result<(), Error> {
+        let stream = match &mut self.stream {
+            Some(s) => s,
+            None => self
+                .stream
+                .insert(std::net::TcpStream::connect(&self.addr)?),
+        };
+
+        if let Err(e) = stream.write_all(batch) {
+            self.stream = None;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+/// Sends each batch to an HTTP endpoint using a `POST` request with an `application/x-ndjson` body
+#[cfg(feature = "http")]
+pub struct HttpSink {
+    url: String,
+    headers: BTreeMap<String, String>,
+}
+
+#[cfg(feature = "http")]
+impl HttpSink {
+    /// Create a new `HttpSink` for the given URL
+    pub fn new(url: impl Into<String>) -> Self {
+        HttpSink {
+            url: url.into(),
+            headers: BTreeMap::new(),
+        }
+    }
+
+    /// Add a header to each request
+    pub fn with_header(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(key.into(), value.into());
+        self
+    }
+}
+
+#[cfg(feature = "http")]
+impl TelemetrySink for HttpSink {
+    fn write_batch(&mut self, batch: &[u8]) -> Result<(), Error> {
+        let mut req = ureq::post(&self.url).header("content-type", "application/x-ndjson");
+        for (k, v) in self.headers.iter() {
+            req = req.header(k, v);
+        }
+        req.send(batch)?;
+        Ok(())
+    }
+}
+
+/// Configures how a `TelemetryExporter` batches events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TelemetryOptions {
+    /// Maximum number of events in a single batch
+    pub max_batch_size: usize,
+
+    /// Maximum amount of time an event is buffered before being written
+    pub flush_interval: Duration,
+}
+
+impl Default for TelemetryOptions {
+    fn default() -> Self {
+        TelemetryOptions {
+            max_batch_size: 100,
+            flush_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl TelemetryOptions {
+    /// Create `TelemetryOptions` with the default settings
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the maximum number of events in a single batch
+    pub fn with_max_batch_size(mut self, n: usize) -> Self {
+        self.max_batch_size = n.max(1);
+        self
+    }
+
+    /// Set the maximum amount of time an event is buffered before being written
+    pub fn with_flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+}
+
+#[derive(serde::Serialize)]
+struct Record<'a> {
+    timestamp_ms: u64,
+    #[serde(flatten)]
+    event: &'a PluginEvent,
+}
+
+#[derive(Default)]
+struct Batch {
+    data: Vec<u8>,
+    count: usize,
+}
+
+impl Batch {
+    fn push(&mut self, event: &PluginEvent) {
+        let record = Record {
+            timestamp_ms: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+            event,
+        };
+        if let Err(e) = serde_json::to_writer(&mut self.data, &record) {
+            warn!("unable to serialize telemetry event: {e:?}");
+            return;
+        }
+        self.data.push(b'\n');
+        self.count += 1;
+    }
+
+    fn flush(&mut self, sink: &mut impl TelemetrySink) {
+        if self.count == 0 {
+            return;
+        }
+        if let Err(e) = sink.write_batch(&self.data) {
+            warn!("unable to write {} telemetry events: {e:?}", self.count);
+        }
+        self.data.clear();
+        self.count = 0;
+    }
+}
+
+/// `TelemetryExporter` subscribes to an `EventBus` and writes every event to a `TelemetrySink` as JSON
+/// lines, using a background thread. Events are written in batches, any buffered events are flushed when
+/// the exporter is dropped.
+///
+/// Errors from the sink are logged and the batch is discarded, so a slow or unavailable sink never blocks
+/// plugin calls.
+pub struct TelemetryExporter {
+    stop: Arc<AtomicBool>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl TelemetryExporter {
+    /// Start exporting events from `events` to `sink`
+    pub fn new(events: &EventBus, sink: impl TelemetrySink, options: TelemetryOptions) -> Self {
+        let rx = events.subscribe();
+        let stop = Arc::new(AtomicBool::new(false));
+        let s = stop.clone();
+        let thread = std::thread::spawn(move || Self::run(rx, sink, options, s));
+        TelemetryExporter {
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    fn run(
+        rx: mpsc::Receiver<PluginEvent>,
+        mut sink: impl TelemetrySink,
+        options: TelemetryOptions,
+        stop: Arc<AtomicBool>,
+    ) {
+        let mut batch = Batch::default();
+        let mut deadline = Instant::now() + options.flush_interval;
+
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let done = match rx.recv_timeout(timeout) {
+                Ok(event) => {
+                    batch.push(&event);
+                    stop.load(Ordering::SeqCst)
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => stop.load(Ordering::SeqCst),
+                Err(mpsc::RecvTimeoutError::Disconnected) => true,
+            };
+
+            if done {
+                // Include any events that have already been sent before the final flush
+                while let Ok(event) = rx.try_recv() {
+                    batch.push(&event);
+                }
+                batch.flush(&mut sink);
+                return;
+            }
+
+            if batch.count >= options.max_batch_size || Instant::now() >= deadline {
+                batch.flush(&mut sink);
+                deadline = Instant::now() + options.flush_interval;
+            }
+        }
+    }
+
+    /// Stop the exporter, flushing any buffered events
+    pub fn shutdown(mut self) {
+        self.stop_thread();
+    }
+
+    fn stop_thread(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for TelemetryExporter {
+    fn drop(&mut self) {
+        self.stop_thread();
+    }
+}
@@ -0,0 +1,276 @@
+//! A mock host environment for testing plugins against the real runtime, without network or disk access
+//!
+//! ```no_run
+//! use extism::testing::{MockFunction, MockHost, MockHttpResponse};
+//! # use extism::*;
+//! # fn example(manifest: Manifest) -> Result<(), Error> {
+//!
+//! let host = MockHost::new()
+//!     .with_http_response("https://example.com/*", MockHttpResponse::new(200, "hello"))
+//!     .with_file("/data/input.txt", "some input")
+//!     .with_function(MockFunction::new("get_user").returns("alice").fails("no more users"));
+//! let mut plugin = PluginBuilder::new(manifest)
+//!     .with_wasi(true)
+//!     .with_mock_host(&host)
+//!     .build()?;
+//! plugin.call::<&str, &str>("run", "")?;
+//!
+//! assert_eq!(host.http_requests().len(), 1);
+//! assert_eq!(host.file("/data/output.txt").unwrap(), b"done");
+//! assert!(host.logs().iter().any(|x| x.message == "finished"));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::{Arc, Mutex};
+
+use crate::memory_fs::MemoryFs;
+use crate::*;
+
+/// A response returned for matching HTTP requests
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockHttpResponse {
+    pub status: u16,
+    pub headers: BTreeMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl MockHttpResponse {
+    /// Create a new response with the given status code and body
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        MockHttpResponse {
+            status,
+            headers: BTreeMap::new(),
+            body: body.into(),
+        }
+    }
+
+    /// Add a response header, these are only visible to the plugin when
+    /// `PluginBuilder::with_http_response_headers` is enabled
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+}
+
+/// An HTTP request made by a plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRecord {
+    pub method: String,
+    pub url: String,
+    pub headers: BTreeMap<String, String>,
+    pub body: Option<Vec<u8>>,
+}
+
+/// A message logged by a plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    pub level: tracing::Level,
+    pub message: String,
+}
+
+/// A scripted host function, each call takes the next response in order and the call fails once
+/// there are no responses left. Mock functions take a single memory handle as input and return one.
+#[derive(Debug, Clone)]
+pub struct MockFunction {
+    name: String,
+    namespace: Option<String>,
+    responses: Vec<Result<Vec<u8>, String>>,
+}
+
+impl MockFunction {
+    /// Create a new mock function in the `extism:host/user` namespace
+    pub fn new(name: impl Into<String>) -> Self {
+        MockFunction {
+            name: name.into(),
+            namespace: None,
+            responses: vec![],
+        }
+    }
+
+    /// Set the namespace
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    /// Return `output` from the next call
+    pub fn returns<'a>(mut self, output: impl ToBytes<'a>) -> Self {
+        let output = output
+            .to_bytes()
+            .map(|x| x.as_ref().to_vec())
+            .map_err(|e| e.to_string());
+        self.responses.push(output);
+        self
+    }
+
+    /// Fail the next call with the error `message`
+    pub fn fails(mut self, message: impl Into<String>) -> Self {
+        self.responses.push(Err(message.into()));
+        self
+    }
+}
+
+#[derive(Default)]
+struct FunctionState {
+    namespace: Option<String>,
+    responses: std::collections::VecDeque<Result<Vec<u8>, String>>,
+    calls: Vec<Vec<u8>>,
+}
+
+#[derive(Default)]
+struct State {
+    http: Vec<(String, MockHttpResponse)>,
+    requests: Vec<HttpRecord>,
+    logs: Vec<LogRecord>,
+    functions: BTreeMap<String, FunctionState>,
+}
+
+/// `MockHost` provides fake HTTP responses, an in-memory filesystem, scripted host functions and captures
+/// plugin logs, see `PluginBuilder::with_mock_host`.
+///
+/// - HTTP requests are matched against the mocked URL patterns in the order they were added, requests
+///   that don't match fail. The manifest's `allowed_hosts` still applies. This requires the `http` feature.
+/// - Files are preopened at `/` when WASI is enabled, instead of the manifest's `allowed_paths`
+/// - Logs are captured regardless of the log level
+///
+/// A `MockHost` can be cloned and shared between plugins, the state is shared by all clones.
+#[derive(Clone, Default)]
+pub struct MockHost {
+    state: Arc<Mutex<State>>,
+    fs: MemoryFs,
+}
+
+impl MockHost {
+    /// Create a new `MockHost`
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Respond to requests to URLs matching `url`, a glob pattern like `https://example.com/*`
+    pub fn with_http_response(self, url: impl Into<String>, response: MockHttpResponse) -> Self {
+        self.state.lock().unwrap().http.push((url.into(), response));
+        self
+    }
+
+    /// Add a file to the in-memory filesystem
+    pub fn with_file(self, path: impl AsRef<str>, data: impl Into<Vec<u8>>) -> Self {
+        self.fs.write(path.as_ref(), data.into());
+        self
+    }
+
+    /// Add a scripted host function
+    pub fn with_function(self, f: MockFunction) -> Self {
+        let state = FunctionState {
+            namespace: f.namespace,
+            responses: f.responses.into(),
+            calls: vec![],
+        };
+        self.state.lock().unwrap().functions.insert(f.name, state);
+        self
+    }
+
+    /// HTTP requests made by plugins, in order
+    pub fn http_requests(&self) -> Vec<HttpRecord> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Messages logged by plugins, in order
+    pub fn logs(&self) -> Vec<LogRecord> {
+        self.state.lock().unwrap().logs.clone()
+    }
+
+    /// Inputs passed to the mock host function `name`, in order
+    pub fn function_calls(&self, name: impl AsRef<str>) -> Vec<Vec<u8>> {
+        let state = self.state.lock().unwrap();
+        match state.functions.get(name.as_ref()) {
+            Some(f) => f.calls.clone(),
+            None => vec![],
+        }
+    }
+
+    /// Get the contents of a file in the in-memory filesystem
+    pub fn file(&self, path: impl AsRef<str>) -> Option<Vec<u8>> {
+        self.fs.read(path.as_ref())
+    }
+
+    /// Paths of all files in the in-memory filesystem, without a leading `/`
+    pub fn files(&self) -> Vec<String> {
+        self.fs.files()
+    }
+
+    pub(crate) fn memory_fs(&self) -> &MemoryFs {
+        &self.fs
+    }
+
+    /// Host functions for each `MockFunction`
+    pub(crate) fn functions(&self) -> Vec<Function> {
+        let state = self.state.lock().unwrap();
+        state
+            .functions
+            .iter()
+            .map(|(name, f)| {
+                let host = self.clone();
+                let name = name.clone();
+                let function = Function::new(
+                    name.clone(),
+                    [PTR],
+                    [PTR],
+                    UserData::new(()),
+                    move |plugin: &mut CurrentPlugin, inputs, outputs, _| {
+                        let input: Vec<u8> = plugin.memory_get_val(&inputs[0])?;
+                        let response = {
+                            let mut state = host.state.lock().unwrap();
+                            let f = state.functions.get_mut(&name).unwrap();
+                            f.calls.push(input);
+                            f.responses.pop_front()
+                        };
+                        match response {
+                            Some(Ok(output)) => plugin.memory_set_val(&mut outputs[0], output),
+                            Some(Err(e)) => Err(Error::msg(e)),
+                            None => {
+                                anyhow::bail!("mock host function {name} has no more responses")
+                            }
+                        }
+                    },
+                );
+                match &f.namespace {
+                    Some(ns) => function.with_namespace(ns),
+                    None => function,
+                }
+            })
+            .collect()
+    }
+
+    pub(crate) fn http_request(
+        &self,
+        req: &extism_manifest::HttpRequest,
+        body: Option<Vec<u8>>,
+    ) -> Result<MockHttpResponse, Error> {
+        let method = req.method.as_deref().unwrap_or("GET").to_uppercase();
+        let mut state = self.state.lock().unwrap();
+        state.requests.push(HttpRecord {
+            method: method.clone(),
+            url: req.url.clone(),
+            headers: req.headers.clone().into_iter().collect(),
+            body,
+        });
+        let response = state.http.iter().find(|(pattern, _)| {
+            glob::Pattern::new(pattern)
+                .map(|x| x.matches(&req.url))
+                .unwrap_or_else(|_| *pattern == req.url)
+        });
+        match response {
+            Some((_, res)) => Ok(res.clone()),
+            None => anyhow::bail!("no mock HTTP response for {method} {}", req.url),
+        }
+    }
+
+    pub(crate) fn log(&self, level: tracing::Level, message: String) {
+        self.state
+            .lock()
+            .unwrap()
+            .logs
+            .push(LogRecord { level, message });
+    }
+}
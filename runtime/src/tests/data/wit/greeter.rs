@@ -0,0 +1,109 @@
+// Generated by `extism::wit` from the `greeter` world, do not edit
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Level {
+    Info,
+    Warn,
+}
+
+/// A greeting request
+#[derive(Debug, Clone, PartialEq, ::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Request {
+    pub name: String,
+    pub excited: bool,
+    pub tags: Vec<String>,
+}
+
+/// Host functions imported by the `greeter` world, register them using `Greeter::add_imports`
+#[allow(clippy::too_many_arguments)]
+pub trait GreeterImports: Send + Sync + 'static {
+    fn logging_log(&self, level: Level, message: String) -> Result<(), ::extism::Error>;
+
+    fn lookup(&self, key: String) -> Result<Option<String>, ::extism::Error>;
+}
+
+/// Typed wrapper for plugins implementing the `greeter` world
+pub struct Greeter {
+    plugin: ::extism::Plugin,
+}
+
+#[allow(clippy::too_many_arguments)]
+impl Greeter {
+    /// Wrap an existing plugin
+    pub fn new(plugin: ::extism::Plugin) -> Self {
+        Greeter { plugin }
+    }
+
+    /// Get the underlying plugin
+    pub fn plugin(&mut self) -> &mut ::extism::Plugin {
+        &mut self.plugin
+    }
+
+    /// Register the host functions imported by the world
+    pub fn add_imports(
+        builder: ::extism::PluginBuilder,
+        imports: impl GreeterImports,
+    ) -> ::extism::PluginBuilder {
+        let imports: ::std::sync::Arc<dyn GreeterImports> = ::std::sync::Arc::new(imports);
+        let mut builder = builder;
+        {
+            let imports = imports.clone();
+            builder = builder.with_function_in_namespace(
+                "example:greeter/logging",
+                "log",
+                [::extism::PTR, ::extism::PTR] as [::extism::ValType; 2],
+                [] as [::extism::ValType; 0],
+                ::extism::UserData::new(()),
+                move |plugin: &mut ::extism::CurrentPlugin,
+                      inputs: &[::extism::Val],
+                      _outputs: &mut [::extism::Val],
+                      _: ::extism::UserData<()>| {
+                    let level = plugin.memory_get_val::<::extism::convert::Json<Level>>(&inputs[0])?.0;
+                    let message = plugin.memory_get_val::<String>(&inputs[1])?;
+                    imports.logging_log(level, message)?;
+                    Ok(())
+                },
+            );
+        }
+        {
+            let imports = imports.clone();
+            builder = builder.with_function_in_namespace(
+                "extism:host/user",
+                "lookup",
+                [::extism::PTR] as [::extism::ValType; 1],
+                [::extism::PTR] as [::extism::ValType; 1],
+                ::extism::UserData::new(()),
+                move |plugin: &mut ::extism::CurrentPlugin,
+                      inputs: &[::extism::Val],
+                      outputs: &mut [::extism::Val],
+                      _: ::extism::UserData<()>| {
+                    let key = plugin.memory_get_val::<String>(&inputs[0])?;
+                    let output = imports.lookup(key)?;
+                    plugin.memory_set_val(&mut outputs[0], ::extism::convert::Json(output))?;
+                    Ok(())
+                },
+            );
+        }
+        builder
+    }
+
+    /// Greet someone
+    pub fn greet(&mut self, name: &str) -> Result<String, ::extism::Error> {
+        let output: String = self.plugin.call("greet", name)?;
+        Ok(output)
+    }
+
+    /// Call `greet-request`
+    pub fn greet_request(&mut self, req: &Request) -> Result<Vec<String>, ::extism::Error> {
+        let output: ::extism::convert::Json<Vec<String>> = self.plugin.call("greet-request", ::extism::convert::Json(req))?;
+        Ok(output.0)
+    }
+
+    /// Call `add`
+    pub fn add(&mut self, a: i64, b: i64) -> Result<i64, ::extism::Error> {
+        let output: i64 = self.plugin.call("add", ::extism::convert::Json((a, b,)))?;
+        Ok(output)
+    }
+}
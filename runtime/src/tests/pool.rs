@@ -79,7 +79,7 @@ fn test_pool_with_captured_builder() {
              inputs: &[Val],
              outputs: &mut [Val],
              _user_data: UserData<String>| {
-                outputs[0] = inputs[0].clone();
+                outputs[0] = inputs[0];
                 Ok(())
             },
         );
@@ -94,3 +94,490 @@ fn test_pool_with_captured_builder() {
 
     handle.join().unwrap();
 }
+
+#[test]
+fn test_pool_warm() {
+    let data = include_bytes!("../../../wasm/code.wasm");
+    let source = move || {
+        extism::PluginBuilder::new(extism::Manifest::new([extism::Wasm::data(data)]))
+            .with_wasi(true)
+            .build()
+    };
+
+    let pool = PoolBuilder::new().with_max_instances(3).build(source);
+    assert_eq!(pool.warm(2).join().unwrap().unwrap(), 2);
+    assert_eq!(pool.count(), 2);
+    assert_eq!(pool.available(), 2);
+
+    // Warming is limited by `max_instances` and skips existing instances
+    let _plugin = pool.get(Duration::from_secs(1)).unwrap().unwrap();
+    assert_eq!(pool.warm_blocking(10).unwrap(), 1);
+    assert_eq!(pool.count(), 3);
+
+    let pool = PoolBuilder::new()
+        .with_max_instances(2)
+        .with_min_instances(2)
+        .build(source);
+    let start = std::time::Instant::now();
+    while pool.available() < 2 {
+        assert!(start.elapsed() < Duration::from_secs(30));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(pool.count(), 2);
+
+    // Failed instances don't count towards the pool size
+    let pool = Pool::new(|| anyhow::bail!("no plugin"));
+    assert!(pool.warm_blocking(1).is_err());
+    assert_eq!(pool.count(), 0);
+}
+
+#[test]
+fn test_pool_recycle() {
+    let data = include_bytes!("../../../wasm/code.wasm");
+    let source = move || {
+        extism::PluginBuilder::new(extism::Manifest::new([extism::Wasm::data(data)]))
+            .with_wasi(true)
+            .build()
+    };
+    let call = |pool: &Pool| {
+        let mut plugin = pool.get(Duration::from_secs(1)).unwrap().unwrap();
+        let _: String = plugin.call("count_vowels", "abc").unwrap();
+        plugin.id()
+    };
+
+    // Instances are replaced after two calls
+    let pool = PoolBuilder::new()
+        .with_max_instances(1)
+        .with_max_calls_per_instance(2)
+        .build(source);
+    let first = call(&pool);
+    assert_eq!(call(&pool), first);
+    assert_eq!(pool.count(), 0);
+    assert_ne!(call(&pool), first);
+
+    // Instances that fail the health check are discarded
+    let pool = PoolBuilder::new()
+        .with_max_instances(1)
+        .with_health_check(|plugin| {
+            if plugin.resource_report().calls > 0 {
+                anyhow::bail!("plugin has been called");
+            }
+            Ok(())
+        })
+        .build(source);
+    let plugin = pool.get(Duration::from_secs(1)).unwrap().unwrap();
+    let unused = plugin.id();
+    drop(plugin);
+    assert_eq!(pool.available(), 1);
+    assert_eq!(call(&pool), unused);
+    assert_eq!(pool.count(), 0);
+
+    // Expired instances aren't checked out
+    let pool = PoolBuilder::new()
+        .with_max_instances(1)
+        .with_max_instance_age(Duration::from_millis(200))
+        .build(source);
+    let plugin = pool.get(Duration::from_secs(1)).unwrap().unwrap();
+    let first = plugin.id();
+    drop(plugin);
+    assert_eq!(pool.available(), 1);
+    std::thread::sleep(Duration::from_millis(300));
+    assert_ne!(call(&pool), first);
+}
+
+#[test]
+fn test_pool_replace_source() {
+    let data = include_bytes!("../../../wasm/code.wasm");
+    let rollovers = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let r = rollovers.clone();
+    let pool = PoolBuilder::new()
+        .with_max_instances(2)
+        .with_rollover_callback(move |generation| r.lock().unwrap().push(generation))
+        .build(move || {
+            extism::PluginBuilder::new(extism::Manifest::new([extism::Wasm::data(data)]))
+                .with_wasi(true)
+                .build()
+        });
+    let timeout = Duration::from_secs(1);
+    let mut old = pool.get(timeout).unwrap().unwrap();
+    drop(pool.get(timeout).unwrap().unwrap());
+    assert_eq!(pool.available(), 1);
+    assert!(pool.function_exists("count_vowels", timeout).unwrap());
+
+    // Available instances are discarded, checked out instances are discarded once they're returned
+    let v2 = br#"(module (func (export "v2") (result i32) (i32.const 0)))"#;
+    let generation = pool.replace_source(move || Plugin::new(&v2[..], [], false));
+    assert_eq!(generation, 1);
+    assert_eq!(pool.generation(), 1);
+    assert_eq!(pool.available(), 0);
+    assert_eq!(pool.count(), 1);
+    assert_eq!(pool.draining(), 1);
+    assert!(!pool.function_exists("count_vowels", timeout).unwrap());
+    assert!(pool.function_exists("v2", timeout).unwrap());
+    assert!(rollovers.lock().unwrap().is_empty());
+
+    let _: String = old.call("count_vowels", "abc").unwrap();
+    drop(old);
+    assert_eq!(pool.draining(), 0);
+    assert_eq!(pool.count(), 1);
+    assert_eq!(*rollovers.lock().unwrap(), [1]);
+
+    // The rollover completes immediately when no instances are checked out
+    assert_eq!(
+        pool.replace_source(move || Plugin::new(&v2[..], [], false)),
+        2
+    );
+    assert_eq!(pool.count(), 0);
+    assert_eq!(*rollovers.lock().unwrap(), [1, 2]);
+}
+
+#[test]
+fn test_pool_priority() {
+    let data = include_bytes!("../../../wasm/code.wasm");
+    let build = |builder: PoolBuilder| {
+        builder.with_max_instances(1).build(move || {
+            extism::PluginBuilder::new(extism::Manifest::new([extism::Wasm::data(data)]))
+                .with_wasi(true)
+                .build()
+        })
+    };
+
+    // Each waiter is started while the only instance is checked out, 50ms after the previous one, and
+    // records its name once it gets the instance
+    let run = |pool: &Pool, waiters: &[(&'static str, i32)]| {
+        let order = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let plugin = pool.get(Duration::from_secs(1)).unwrap().unwrap();
+        let threads: Vec<_> = waiters
+            .iter()
+            .map(|&(name, priority)| {
+                let (pool, order) = (pool.clone(), order.clone());
+                let t = std::thread::spawn(move || {
+                    let plugin = pool
+                        .get_with_priority(Duration::from_secs(5), priority)
+                        .unwrap()
+                        .unwrap();
+                    order.lock().unwrap().push(name);
+                    drop(plugin);
+                });
+                std::thread::sleep(Duration::from_millis(50));
+                t
+            })
+            .collect();
+        drop(plugin);
+        for t in threads {
+            t.join().unwrap();
+        }
+        let order = order.lock().unwrap().clone();
+        order
+    };
+
+    // Higher priorities go first, callers with the same priority are served in order
+    let pool = build(
+        PoolBuilder::new()
+            .with_fair_checkout(true)
+            .with_priority_aging(None),
+    );
+    let order = run(&pool, &[("batch", -1), ("a", 0), ("b", 0), ("urgent", 5)]);
+    assert_eq!(order, ["urgent", "a", "b", "batch"]);
+
+    // Callers that have been waiting longer than the aging interval are raised above new callers
+    let pool = build(PoolBuilder::new().with_priority_aging(Some(Duration::from_millis(10))));
+    let order = run(&pool, &[("batch", 0), ("urgent", 2)]);
+    assert_eq!(order, ["batch", "urgent"]);
+}
+
+#[test]
+fn test_pool_metrics() {
+    #[derive(Default)]
+    struct Checkouts(std::sync::Mutex<Vec<bool>>);
+
+    impl Metrics for Checkouts {
+        fn pool_checkout(&self, _wait: Duration, success: bool) {
+            self.0.lock().unwrap().push(success);
+        }
+    }
+
+    let checkouts = std::sync::Arc::new(Checkouts::default());
+    let data = include_bytes!("../../../wasm/code.wasm");
+    let pool = PoolBuilder::new()
+        .with_max_instances(1)
+        .with_metrics(checkouts.clone())
+        .build(move || {
+            extism::PluginBuilder::new(extism::Manifest::new([extism::Wasm::data(data)]))
+                .with_wasi(true)
+                .build()
+        });
+    let plugin = pool.get(Duration::from_secs(1)).unwrap().unwrap();
+    assert!(pool.get(Duration::from_millis(10)).unwrap().is_none());
+    drop(plugin);
+    assert!(pool.get(Duration::from_secs(1)).unwrap().is_some());
+    assert_eq!(*checkouts.0.lock().unwrap(), [true, false, true]);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_pool_get_async() {
+    let pool = init(1);
+    let mut first = pool.get_async().await.unwrap();
+    let _: String = first.call("count_vowels", "abc").unwrap();
+    let id = first.id();
+
+    // The second task waits until the first instance is returned
+    let task = tokio::spawn({
+        let pool = pool.clone();
+        async move { pool.get_async().await.map(|x| x.id()) }
+    });
+    tokio::task::yield_now().await;
+    assert!(!task.is_finished());
+    assert!(pool.get(Duration::from_millis(10)).unwrap().is_none());
+    drop(first);
+    assert_eq!(task.await.unwrap().unwrap(), id);
+    assert_eq!(pool.count(), 1);
+}
+
+#[test]
+fn test_cron_expr() {
+    let expr = |s: &str| s.parse::<CronExpr>().unwrap();
+
+    // 2024-02-28 23:59:30 -> 2024-02-29 00:00:00
+    assert_eq!(expr("@daily").next_after(1709164770), Some(1709164800));
+    // Next Monday at 09:00, 2024-03-04
+    assert_eq!(expr("0 9 * * 1").next_after(1709164800), Some(1709542800));
+    assert_eq!(expr("0 0 1 * *").next_after(1709164800), Some(1709251200));
+    assert_eq!(expr("@yearly").next_after(1735689540), Some(1735689600));
+    assert_eq!(
+        expr("*/15 * * * *").next_after(1709164800),
+        Some(1709165700)
+    );
+    assert_eq!(expr("0 0 30 2 *").next_after(1709164800), None);
+
+    assert!("60 * * * *".parse::<CronExpr>().is_err());
+    assert!("* * *".parse::<CronExpr>().is_err());
+    assert!("*/0 * * * *".parse::<CronExpr>().is_err());
+}
+
+#[test]
+fn test_scheduler() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let tx = std::sync::Mutex::new(tx);
+    let scheduler = SchedulerBuilder::new()
+        .with_job(
+            Job::new(
+                "vowels",
+                "count_vowels",
+                Schedule::every(Duration::from_millis(50)),
+            )
+            .with_input("aaa"),
+        )
+        .with_job(
+            Job::new(
+                "missing",
+                "not_existing",
+                Schedule::every(Duration::from_millis(50)),
+            )
+            .with_error_backoff(Duration::from_secs(60), Duration::from_secs(60)),
+        )
+        .on_run(move |record| {
+            let _ = tx.lock().unwrap().send(record.clone());
+        })
+        .build(init(2));
+
+    let mut vowels = 0;
+    let mut missing = vec![];
+    let start = std::time::Instant::now();
+    while vowels < 2 && start.elapsed() < Duration::from_secs(30) {
+        let Ok(record) = rx.recv_timeout(Duration::from_secs(1)) else {
+            continue;
+        };
+        match record.job.as_str() {
+            "vowels" if record.outcome == RunOutcome::Success => vowels += 1,
+            // Runs are skipped while the first run is still in progress
+            "missing" if record.outcome != RunOutcome::Skipped => missing.push(record),
+            _ => (),
+        }
+    }
+    scheduler.shutdown();
+    assert_eq!(vowels, 2);
+
+    // The failing job is delayed by the backoff after its first run
+    assert_eq!(missing.len(), 1);
+    assert!(matches!(missing[0].outcome, RunOutcome::Error(_)));
+    assert_eq!(missing[0].failures, 1);
+}
+
+#[cfg(feature = "http-server")]
+#[tokio::test]
+async fn test_http_server() {
+    use axum::body::Body;
+    use axum::http::{Method, Request, StatusCode};
+    use tower::ServiceExt;
+
+    let wasm = br#"
+        (module
+            (import "extism:host/env" "input_offset" (func $input_offset (result i64)))
+            (import "extism:host/env" "input_length" (func $input_length (result i64)))
+            (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+            (import "extism:host/env" "error_set" (func $error_set (param i64)))
+            (func (export "echo") (result i32)
+                (call $output_set (call $input_offset) (call $input_length))
+                i32.const 0)
+            (func (export "not_found") (result i32)
+                (call $error_set (call $input_offset))
+                i32.const 404)
+        )
+    "#;
+    let pool = PoolBuilder::new()
+        .with_max_instances(1)
+        .build(move || PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())])).build());
+    let router = HttpServer::new(pool)
+        .route(Method::POST, "/echo", "echo")
+        .route(Method::PUT, "/echo", "not_found")
+        .route(Method::GET, "/missing", "missing")
+        .into_router()
+        .unwrap();
+
+    let request = |method, path, body: &'static str| {
+        Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::from(body))
+            .unwrap()
+    };
+    let read = |res: axum::response::Response| async move {
+        let status = res.status();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    };
+
+    let res = router
+        .clone()
+        .oneshot(request(Method::POST, "/echo", "hello"))
+        .await
+        .unwrap();
+    assert_eq!(read(res).await, (StatusCode::OK, "hello".to_string()));
+
+    // The exit code is used as the status when it's an HTTP error code
+    let res = router
+        .clone()
+        .oneshot(request(Method::PUT, "/echo", "no such thing"))
+        .await
+        .unwrap();
+    assert_eq!(
+        read(res).await,
+        (StatusCode::NOT_FOUND, "no such thing".to_string())
+    );
+
+    let res = router
+        .clone()
+        .oneshot(request(Method::GET, "/missing", ""))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let res = router
+        .oneshot(request(Method::DELETE, "/echo", ""))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+}
+
+#[cfg(feature = "grpc")]
+#[tokio::test]
+async fn test_grpc_server() {
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    let wasm = br#"
+        (module
+            (import "extism:host/env" "input_offset" (func $input_offset (result i64)))
+            (import "extism:host/env" "input_length" (func $input_length (result i64)))
+            (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+            (import "extism:host/env" "error_set" (func $error_set (param i64)))
+            (func (export "echo") (result i32)
+                (call $output_set (call $input_offset) (call $input_length))
+                i32.const 0)
+            (func (export "not_found") (result i32)
+                (call $error_set (call $input_offset))
+                i32.const 5)
+        )
+    "#;
+    let pool = PoolBuilder::new()
+        .with_max_instances(1)
+        .build(move || PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())])).build());
+    let routes = GrpcServer::new(pool)
+        .method("example.Echo", "Echo", "echo")
+        .method("example.Echo", "Find", "not_found")
+        .typed_method::<String, String>("example.Typed", "Echo", "echo")
+        .into_routes()
+        .await
+        .unwrap();
+
+    // Sends a single length-prefixed message, returning the `grpc-status` header and the body
+    let call = |path: &'static str, message: &'static [u8]| {
+        let routes = routes.clone();
+        async move {
+            let mut body = vec![0];
+            body.extend((message.len() as u32).to_be_bytes());
+            body.extend(message);
+            let req = Request::post(path)
+                .header("content-type", "application/grpc")
+                .body(Body::from(body))
+                .unwrap();
+            let res = routes.oneshot(req).await.unwrap();
+            let status = res
+                .headers()
+                .get("grpc-status")
+                .map(|x| x.to_str().unwrap().to_string());
+            let body = axum::body::to_bytes(Body::new(res.into_body()), usize::MAX)
+                .await
+                .unwrap();
+            (status, body.to_vec())
+        }
+    };
+
+    let (status, body) = call("/example.Echo/Echo", b"hello").await;
+    assert_eq!(status, None);
+    assert_eq!(body, b"\0\0\0\0\x05hello");
+
+    // The exit code is used as the gRPC status code
+    let (status, _) = call("/example.Echo/Find", b"missing").await;
+    assert_eq!(status.as_deref(), Some("5"));
+
+    let (status, _) = call("/example.Missing/Echo", b"").await;
+    assert_eq!(status.as_deref(), Some("12"));
+
+    // Typed methods reject invalid messages
+    let (status, body) = call("/example.Typed/Echo", b"\x0a\x02hi").await;
+    assert_eq!(status, None);
+    assert_eq!(body, b"\0\0\0\0\x04\x0a\x02hi");
+    let (status, _) = call("/example.Typed/Echo", b"\xff").await;
+    assert_eq!(status.as_deref(), Some("3"));
+
+    // Configured services are reported as serving by the health service
+    let (_, body) = call("/grpc.health.v1.Health/Check", b"\x0a\x0cexample.Echo").await;
+    assert_eq!(body, b"\0\0\0\0\x02\x08\x01");
+}
+
+#[cfg(feature = "tower")]
+#[tokio::test]
+async fn test_plugin_service() {
+    use tower::ServiceExt;
+
+    let service: PluginService<String, Json<serde_json::Value>> =
+        PluginService::new(init(1), "count_vowels");
+    let Json(output) = service.oneshot("aaa".to_string()).await.unwrap();
+    assert_eq!(output["count"], 3);
+
+    // Checkout happens inside the call, so a busy pool results in an error
+    let pool = init(1);
+    let _plugin = pool.get(Duration::from_secs(1)).unwrap().unwrap();
+    let err = PluginService::<String, String>::new(pool, "count_vowels")
+        .with_pool_timeout(Duration::from_millis(10))
+        .oneshot("aaa".to_string())
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("timed out"));
+}
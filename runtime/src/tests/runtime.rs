@@ -206,10 +206,13 @@ fn test_cancel() {
             std::thread::sleep(std::time::Duration::from_secs(1));
             assert!(h.cancel().is_ok());
         });
-        let _output: Result<&[u8], Error> = plugin.call("loop_forever", "abc123");
+        let output: Result<&[u8], Error> = plugin.call("loop_forever", "abc123");
         let end = std::time::Instant::now();
         let time = end - start;
         println!("Cancelled plugin ran for {time:?}");
+        let err = output.unwrap_err();
+        assert!(err.is::<Cancelled>(), "{err:?}");
+        assert_eq!(err.to_string(), "cancelled");
     }
 }
 
@@ -239,6 +242,68 @@ fn test_timeout() {
     assert!(err == "timeout");
 }
 
+#[test]
+fn test_call_with_timeout() {
+    let f = Function::new(
+        "hello_world",
+        [PTR],
+        [PTR],
+        UserData::default(),
+        hello_world,
+    );
+
+    let manifest = Manifest::new([extism_manifest::Wasm::data(WASM_LOOP)])
+        .with_timeout(std::time::Duration::from_secs(30));
+    let mut plugin = Plugin::new(manifest, [f], true).unwrap();
+
+    let start = std::time::Instant::now();
+    let output: Result<&[u8], Error> = plugin.call_with_timeout(
+        "loop_forever",
+        "abc123",
+        std::time::Duration::from_millis(100),
+    );
+    assert_eq!(output.unwrap_err().root_cause().to_string(), "timeout");
+    assert!(start.elapsed() < std::time::Duration::from_secs(10));
+
+    // The manifest timeout is restored after the call
+    assert_eq!(plugin.current_plugin().manifest.timeout_ms, Some(30_000));
+}
+
+#[test]
+fn test_set_deadline() {
+    let f = Function::new(
+        "hello_world",
+        [PTR],
+        [PTR],
+        UserData::default(),
+        hello_world,
+    );
+
+    let manifest = Manifest::new([extism_manifest::Wasm::data(WASM_LOOP)])
+        .with_timeout(std::time::Duration::from_secs(30));
+    let mut plugin = Plugin::new(manifest, [f], true).unwrap();
+
+    let start = std::time::Instant::now();
+    plugin.set_deadline(start + std::time::Duration::from_millis(100));
+    let output: Result<&[u8], Error> = plugin.call("loop_forever", "abc123");
+    assert_eq!(output.unwrap_err().root_cause().to_string(), "timeout");
+    assert!(start.elapsed() < std::time::Duration::from_secs(10));
+
+    // Calls made after the deadline are interrupted immediately
+    let output: Result<&[u8], Error> = plugin.call("loop_forever", "abc123");
+    assert_eq!(output.unwrap_err().root_cause().to_string(), "timeout");
+
+    // Only the timeout applies once the deadline is cleared
+    plugin.clear_deadline();
+    assert!(plugin.deadline().is_none());
+    let output: Result<&[u8], Error> = plugin.call_with_timeout(
+        "loop_forever",
+        "abc123",
+        std::time::Duration::from_millis(100),
+    );
+    assert_eq!(output.unwrap_err().root_cause().to_string(), "timeout");
+}
+
 #[test]
 fn test_fuel() {
     let manifest = Manifest::new([extism_manifest::Wasm::data(WASM_LOOP)]);
@@ -255,6 +320,29 @@ fn test_fuel() {
     }
 }
 
+#[test]
+fn test_call_with_fuel() {
+    let mut plugin = PluginBuilder::new(WASM_NO_FUNCTIONS)
+        .with_fuel_limit(1)
+        .build()
+        .unwrap();
+    assert!(plugin.call::<_, &[u8]>("count_vowels", "abc").is_err());
+
+    // The override only applies to a single call
+    let Json(count): Json<Count> = plugin
+        .call_with_fuel("count_vowels", "abc", 10_000_000)
+        .unwrap();
+    assert_eq!(count.count, 1);
+    assert!(plugin.fuel_consumed().unwrap() > 1);
+    let err = plugin.call::<_, &[u8]>("count_vowels", "abc").unwrap_err();
+    assert!(err.root_cause().to_string().contains("fuel"));
+
+    let mut plugin = Plugin::new(WASM_NO_FUNCTIONS, [], false).unwrap();
+    assert!(plugin
+        .call_with_fuel::<_, &[u8]>("count_vowels", "abc", 1000)
+        .is_err());
+}
+
 #[test]
 fn test_fuel_consumption() {
     let manifest = Manifest::new([extism_manifest::Wasm::data(WASM_LOOP)]);
@@ -454,6 +542,40 @@ fn test_memory_max() {
     assert!(output.is_ok());
 }
 
+#[test]
+fn test_memory_limit() {
+    let input = "a".repeat(65536 * 2);
+
+    // The builder limit applies when the manifest doesn't have one
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(WASM_NO_FUNCTIONS)]))
+        .with_memory_limit(16 * 65536)
+        .build()
+        .unwrap();
+    let err = plugin
+        .call::<&str, &[u8]>("count_vowels", &input)
+        .unwrap_err();
+    assert!(err.is::<OutOfMemory>(), "{err:?}");
+
+    // The lower of the builder and manifest limits is used
+    let manifest = Manifest::new([Wasm::data(WASM_NO_FUNCTIONS)]).with_memory_max(16);
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_memory_limit(64 * 65536)
+        .build()
+        .unwrap();
+    let err = plugin
+        .call::<&str, &[u8]>("count_vowels", &input)
+        .unwrap_err();
+    assert!(err.is::<OutOfMemory>(), "{err:?}");
+
+    let manifest = Manifest::new([Wasm::data(WASM_NO_FUNCTIONS)]).with_memory_max(64);
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_memory_limit(17 * 65536 + 100)
+        .build()
+        .unwrap();
+    let Json(count): Json<Count> = plugin.call("count_vowels", &input).unwrap();
+    assert_eq!(count.count, 65536 * 2);
+}
+
 fn hello_world_set_error(
     plugin: &mut CurrentPlugin,
     inputs: &[Val],
@@ -677,6 +799,48 @@ fn test_disable_cache() {
     assert!(t < t1);
 }
 
+#[test]
+fn test_cache_dir() {
+    let dir = std::env::temp_dir().join(format!("extism-cache-{}", uuid::Uuid::new_v4()));
+    let mut plugin = PluginBuilder::new(WASM_NO_FUNCTIONS)
+        .with_cache_dir(&dir)
+        .build()
+        .unwrap();
+    let Json(count): Json<Count> = plugin.call("count_vowels", "aaa").unwrap();
+    assert_eq!(count.count, 3);
+
+    let count_files = |dir: &std::path::Path| {
+        fn walk(dir: &std::path::Path) -> usize {
+            std::fs::read_dir(dir)
+                .map(|entries| {
+                    entries
+                        .flatten()
+                        .map(|x| {
+                            let path = x.path();
+                            if path.is_dir() {
+                                walk(&path)
+                            } else {
+                                1
+                            }
+                        })
+                        .sum()
+                })
+                .unwrap_or(0)
+        }
+        walk(dir)
+    };
+    assert!(count_files(&dir) > 0);
+
+    // A second plugin loads the compiled module from the same directory
+    let mut plugin = PluginBuilder::new(WASM_NO_FUNCTIONS)
+        .with_cache_dir(&dir)
+        .build()
+        .unwrap();
+    let Json(count): Json<Count> = plugin.call("count_vowels", "aa").unwrap();
+    assert_eq!(count.count, 2);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
 #[test]
 fn test_manifest_ptr_len() {
     let manifest = serde_json::json!({
@@ -718,6 +882,66 @@ fn test_no_vars() {
     assert!(output.is_ok());
 }
 
+#[test]
+fn test_var_store() {
+    // `set` stores the input using the input as the key, `get` returns the value for the input
+    let data = br#"
+(module
+    (import "extism:host/env" "var_set" (func $var_set (param i64 i64)))
+    (import "extism:host/env" "var_get" (func $var_get (param i64) (result i64)))
+    (import "extism:host/env" "input_offset" (func $input_offset (result i64)))
+    (import "extism:host/env" "length" (func $length (param i64) (result i64)))
+    (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+    (func (export "set") (result i32)
+        (call $var_set (call $input_offset) (call $input_offset))
+        (i32.const 0)
+    )
+    (func (export "get") (result i32)
+        (local $v i64)
+        (local.set $v (call $var_get (call $input_offset)))
+        (if (i64.ne (local.get $v) (i64.const 0))
+            (then (call $output_set (local.get $v) (call $length (local.get $v)))))
+        (i32.const 0)
+    )
+)
+    "#;
+    let kv = MemoryKvStore::new();
+    let build = || {
+        let manifest = Manifest::new([Wasm::data(data)])
+            .with_memory_options(MemoryOptions::new().with_max_var_bytes(8));
+        PluginBuilder::new(manifest)
+            .with_var_store(KvVarStore::new(kv.clone(), "vars/"))
+            .build()
+            .unwrap()
+    };
+    let mut plugin = build();
+    let _: () = plugin.call("set", "abcd").unwrap();
+    assert_eq!(kv.get("vars/abcd").unwrap().unwrap(), b"abcd");
+    assert!(plugin.current_plugin().vars().is_empty());
+
+    // Replacing a value doesn't count the old value towards the limit
+    let _: () = plugin.call("set", "abcd").unwrap();
+    assert!(plugin.call::<&str, ()>("set", "x").is_err());
+
+    // Variables outlive the plugin
+    plugin.reset().unwrap();
+    assert_eq!(plugin.call::<&str, &str>("get", "abcd").unwrap(), "abcd");
+    drop(plugin);
+    let mut plugin = build();
+    assert_eq!(plugin.call::<&str, &str>("get", "abcd").unwrap(), "abcd");
+    assert_eq!(plugin.call::<&str, &str>("get", "x").unwrap(), "");
+
+    let store = KvVarStore::new(kv, "vars/");
+    assert_eq!(store.list().unwrap(), ["abcd"]);
+    assert_eq!(store.size().unwrap(), 8);
+    store.set("xy", b"z").unwrap();
+    assert_eq!(store.size().unwrap(), 11);
+    store.delete("abcd").unwrap();
+    store.delete("abcd").unwrap();
+    assert_eq!(store.size().unwrap(), 3);
+    assert_eq!(plugin.call::<&str, &str>("get", "abcd").unwrap(), "");
+}
+
 #[test]
 fn test_linking() {
     let manifest = Manifest::new([
@@ -740,6 +964,7 @@ fn test_linking() {
             meta: WasmMetadata {
                 name: Some("commander".to_string()),
                 hash: None,
+                signature: None,
             },
         },
         Wasm::Data {
@@ -769,6 +994,7 @@ fn test_linking() {
             meta: WasmMetadata {
                 name: Some("main".to_string()),
                 hash: None,
+                signature: None,
             },
         },
     ]);
@@ -786,6 +1012,65 @@ fn test_linking() {
     }
 }
 
+#[test]
+fn test_linking_chain() {
+    let named = |name: &str, wat: &str| Wasm::Data {
+        data: wat.as_bytes().to_vec(),
+        meta: WasmMetadata {
+            name: Some(name.to_string()),
+            hash: None,
+            signature: None,
+        },
+    };
+    let math = named(
+        "math",
+        r#"(module (func (export "add") (param i32 i32) (result i32)
+            (i32.add (local.get 0) (local.get 1))))"#,
+    );
+    let util = named(
+        "util",
+        r#"(module
+            (import "math" "add" (func $add (param i32 i32) (result i32)))
+            (func (export "double") (param i32) (result i32)
+                (call $add (local.get 0) (local.get 0))))"#,
+    );
+    let main = named(
+        "main",
+        r#"(module
+            (import "util" "double" (func $double (param i32) (result i32)))
+            (func (export "run") (result i32)
+                (i32.ne (call $double (i32.const 21)) (i32.const 42))))"#,
+    );
+
+    // Modules are linked in dependency order, not manifest order
+    let manifest = Manifest::new([main.clone(), util.clone(), math]);
+    let mut plugin = Plugin::new(manifest, [], false).unwrap();
+    plugin.call::<(), ()>("run", ()).unwrap();
+
+    let err = Plugin::new(Manifest::new([main.clone(), util.clone()]), [], false).unwrap_err();
+    assert!(err.to_string().contains("math::add"), "{err}");
+
+    let math = named("math", r#"(module (func (export "sub")))"#);
+    let err =
+        Plugin::new(Manifest::new([main.clone(), util.clone(), math]), [], false).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "module util imports math::add, which isn't exported by math"
+    );
+
+    let math = named(
+        "math",
+        r#"(module
+            (import "util" "double" (func $double (param i32) (result i32)))
+            (func (export "add") (param i32 i32) (result i32) (local.get 0)))"#,
+    );
+    let err = Plugin::new(Manifest::new([main, util, math]), [], false).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "circular import between modules: main -> util -> math -> util"
+    );
+}
+
 #[test]
 fn test_readonly_dirs() {
     let wasm = Wasm::data(WASM_FS);
@@ -809,6 +1094,47 @@ fn test_readonly_dirs() {
     );
 }
 
+#[test]
+fn test_builder_allowed_paths() {
+    let manifest = Manifest::new([Wasm::data(WASM_FS)]).with_config_key("path", "/data/data.txt");
+    let mut plugin = PluginBuilder::new(&manifest)
+        .with_wasi(true)
+        .with_readonly_path("src/tests/data", "/data")
+        .build()
+        .unwrap();
+    let res = plugin.call::<&str, &str>("try_read", "").unwrap();
+    assert_eq!(res, "hello world!");
+    assert!(plugin.call::<&str, &str>("try_write", "x").is_err());
+
+    let manifest = Manifest::new([Wasm::data(WASM_FS)])
+        .with_readonly_path("src/tests/data", "/data")
+        .with_config_key("path", "/data/data.txt");
+    let mut plugin = PluginBuilder::new(&manifest)
+        .with_wasi(true)
+        .build()
+        .unwrap();
+    assert!(plugin.call::<&str, &str>("try_write", "x").is_err());
+
+    // Writable directories can be modified
+    let dir = std::env::temp_dir().join(format!("extism-paths-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("data.txt"), "hello world!").unwrap();
+    let manifest = Manifest::new([Wasm::data(WASM_FS)]).with_config_key("path", "/data/data.txt");
+    let mut plugin = PluginBuilder::new(&manifest)
+        .with_wasi(true)
+        .with_allowed_path(dir.display().to_string(), "/data")
+        .build()
+        .unwrap();
+    plugin
+        .call::<&str, &str>("try_write", "hello world 2")
+        .unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join("data.txt")).unwrap(),
+        "hello world!hello world 2"
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
 #[test]
 #[cfg(feature = "http")]
 fn test_http_response_headers() {
@@ -838,3 +1164,4299 @@ fn test_http_response_headers_disabled() {
     println!("{res:?}");
     assert!(res.is_empty());
 }
+
+#[test]
+fn test_host_function_limits() {
+    let wasm = br#"
+(module
+    (import "extism:host/user" "hello" (func $hello))
+    (import "extism:host/user" "goodbye" (func $goodbye))
+    (func (export "hello3") (result i32)
+        (call $hello)
+        (call $hello)
+        (call $hello)
+        (i32.const 0)
+    )
+    (func (export "goodbye3") (result i32)
+        (call $goodbye)
+        (call $goodbye)
+        (call $goodbye)
+        (i32.const 0)
+    )
+)
+    "#;
+    let build = |limits: HostFunctionLimits| {
+        PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+            .with_function("hello", [], [], UserData::new(()), |_, _, _, _| Ok(()))
+            .with_function("goodbye", [], [], UserData::new(()), |_, _, _, _| Ok(()))
+            .with_host_function_limits(limits)
+            .build()
+            .unwrap()
+    };
+
+    // Per-call limit is reset between calls
+    let mut plugin = build(HostFunctionLimits::new().with_max_calls_per_call(3));
+    for _ in 0..3 {
+        assert!(plugin.call::<(), ()>("hello3", ()).is_ok());
+    }
+
+    let mut plugin = build(HostFunctionLimits::new().with_max_calls_per_call(2));
+    let err = plugin.call::<(), ()>("hello3", ()).unwrap_err();
+    assert!(err
+        .root_cause()
+        .to_string()
+        .starts_with("host function quota exceeded: hello"));
+
+    // Per-second limit spans multiple calls
+    let mut plugin = build(HostFunctionLimits::new().with_max_calls_per_second(5));
+    assert!(plugin.call::<(), ()>("hello3", ()).is_ok());
+    assert!(plugin.call::<(), ()>("hello3", ()).is_err());
+
+    // Per-function limits only apply to the named function
+    let mut plugin = build(
+        HostFunctionLimits::new()
+            .with_function_limit("goodbye", HostFunctionLimit::new().with_max_per_call(1)),
+    );
+    assert!(plugin.call::<(), ()>("hello3", ()).is_ok());
+    assert!(plugin.call::<(), ()>("goodbye3", ()).is_err());
+}
+
+#[test]
+fn test_secrets_provider() {
+    let wasm = br#"
+(module
+    (import "extism:host/env" "input_offset" (func $input_offset (result i64)))
+    (import "extism:host/env" "config_get" (func $config_get (param i64) (result i64)))
+    (import "extism:host/env" "length" (func $length (param i64) (result i64)))
+    (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+    (import "extism:host/env" "error_set" (func $error_set (param i64)))
+    (func (export "get") (result i32)
+        (local $v i64)
+        (local.set $v (call $config_get (call $input_offset)))
+        (call $output_set (local.get $v) (call $length (local.get $v)))
+        (i32.const 0)
+    )
+    (func (export "leak") (result i32)
+        (call $error_set (call $config_get (call $input_offset)))
+        (i32.const 1)
+    )
+)
+    "#;
+    let manifest = Manifest::new([Wasm::data(wasm.to_vec())])
+        .with_config_key("plain", "hello")
+        .with_config_key("password", "secret://db_password")
+        .with_config_key("missing", "secret://missing");
+
+    // Resolved values are never written back into the manifest
+    let json = serde_json::to_string(&manifest).unwrap();
+    assert!(!json.contains("hunter2"));
+
+    let mut plugin = PluginBuilder::new(&manifest)
+        .with_secrets_provider(|name: &str| {
+            Ok((name == "db_password").then(|| "hunter2".to_string()))
+        })
+        .build()
+        .unwrap();
+    assert_eq!(plugin.call::<&str, &str>("get", "plain").unwrap(), "hello");
+    assert_eq!(
+        plugin.call::<&str, &str>("get", "password").unwrap(),
+        "hunter2"
+    );
+    assert_eq!(plugin.call::<&str, &str>("get", "missing").unwrap(), "");
+
+    let err = plugin.call::<&str, &str>("leak", "password").unwrap_err();
+    let err = format!("{err:?}");
+    assert!(!err.contains("hunter2"));
+    assert!(err.contains("[REDACTED]"));
+
+    // Without a provider accessing a secret fails
+    let mut plugin = Plugin::new(&manifest, [], false).unwrap();
+    assert!(plugin.call::<&str, &str>("get", "password").is_err());
+}
+
+#[test]
+fn test_module_policy() {
+    use sha2::Digest;
+    let hash = crate::manifest::hex(&sha2::Sha256::digest(WASM_NO_FUNCTIONS));
+
+    // Denied hashes are rejected, even when the module is passed in directly
+    let res = PluginBuilder::new(WASM_NO_FUNCTIONS)
+        .with_module_policy(ModulePolicy::new().with_denied_hash(&hash))
+        .build();
+    assert!(res.is_err());
+
+    let res = PluginBuilder::new(Manifest::new([Wasm::data(WASM_NO_FUNCTIONS)]))
+        .with_module_policy(ModulePolicy::new().with_allowed_hash("abcd"))
+        .build();
+    assert!(res.is_err());
+
+    let res = PluginBuilder::new(Manifest::new([Wasm::data(WASM_NO_FUNCTIONS)]))
+        .with_module_policy(ModulePolicy::new().with_allowed_hash(hash.to_uppercase()))
+        .build();
+    assert!(res.is_ok());
+
+    // The deny-list takes precedence
+    let res = PluginBuilder::new(WASM_NO_FUNCTIONS)
+        .with_module_policy(
+            ModulePolicy::new()
+                .with_allowed_hash(&hash)
+                .with_denied_hash(&hash),
+        )
+        .build();
+    assert!(res.is_err());
+}
+
+#[test]
+fn test_host_function_capabilities() {
+    let wasm = br#"(module
+        (import "extism:host/user" "read_secret" (func $read_secret (result i64)))
+        (func (export "run") (result i32)
+            (i32.wrap_i64 (call $read_secret))))
+    "#;
+    let functions = || {
+        [
+            Function::new(
+                "read_secret",
+                [],
+                [ValType::I64],
+                UserData::new(()),
+                |_, _, out, _| {
+                    out[0] = Val::I64(0);
+                    Ok(())
+                },
+            )
+            .with_capability("secrets"),
+            Function::new("unused", [], [], UserData::new(()), |_, _, _, _| Ok(()))
+                .with_capability("other"),
+        ]
+    };
+
+    let manifest = Manifest::new([Wasm::data(wasm.to_vec())]);
+    let err = Plugin::new(&manifest, functions(), false).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "module main imports extism:host/user::read_secret, which requires the `secrets` capability, it \
+        must be added to the manifest's `capabilities` to use this function"
+    );
+
+    let mut plugin = Plugin::new(manifest.with_capability("secrets"), functions(), false).unwrap();
+    let _: &[u8] = plugin.call("run", "").unwrap();
+}
+
+#[test]
+fn test_wasm_resolver() {
+    let requests = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let r = requests.clone();
+    let build = |wasm: Wasm| {
+        let r = r.clone();
+        PluginBuilder::new(Manifest::new([wasm]))
+            .with_wasi(true)
+            .with_wasm_resolver("S3", move |req: &HttpRequest| {
+                r.lock().unwrap().push(req.url.clone());
+                match req.url.as_str() {
+                    "s3://plugins/code.wasm" => Ok(WASM_NO_FUNCTIONS.to_vec()),
+                    _ => anyhow::bail!("object not found"),
+                }
+            })
+            .build()
+    };
+
+    let mut plugin = build(Wasm::url("s3://plugins/code.wasm")).unwrap();
+    let _: &[u8] = plugin.call("count_vowels", "abc").unwrap();
+    assert_eq!(*requests.lock().unwrap(), ["s3://plugins/code.wasm"]);
+
+    // The manifest hash is checked against the resolved module
+    let err = build(Wasm::url("s3://plugins/code.wasm").with_hash("00"))
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("Hash mismatch"));
+
+    let err = build(Wasm::url("S3://plugins/missing.wasm")).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "unable to resolve Wasm module S3://plugins/missing.wasm"
+    );
+    assert_eq!(err.root_cause().to_string(), "object not found");
+}
+
+#[test]
+#[cfg(feature = "signatures")]
+fn test_wasm_signature() {
+    use base64::Engine;
+    use ed25519_dalek::Signer;
+    use p256::pkcs8::EncodePublicKey;
+    use sha2::Digest;
+
+    let b64 = |x: &[u8]| base64::engine::general_purpose::STANDARD.encode(x);
+    let build = |signature: WasmSignature| {
+        Plugin::new(
+            Manifest::new([Wasm::data(WASM_NO_FUNCTIONS).with_signature(signature)]),
+            [],
+            false,
+        )
+    };
+    let invalid = |res: Result<Plugin, Error>| {
+        let err = res.err().unwrap();
+        err.downcast::<InvalidSignature>().unwrap().reason
+    };
+
+    let key = ed25519_dalek::SigningKey::from_bytes(&[1; 32]);
+    let public_key = b64(key.verifying_key().as_bytes());
+    let signature = b64(&key.sign(WASM_NO_FUNCTIONS).to_bytes());
+    assert!(build(WasmSignature::ed25519(&public_key, &signature)).is_ok());
+    let other = b64(&key.sign(WASM_LOOP).to_bytes());
+    assert_eq!(
+        invalid(build(WasmSignature::ed25519(&public_key, other))),
+        "signature doesn't match the module data"
+    );
+    assert_eq!(
+        invalid(build(WasmSignature::ed25519("abcd", &signature))),
+        "public key must be 32 bytes"
+    );
+
+    // Signatures are part of the manifest
+    let manifest = Manifest::new([Wasm::data(WASM_NO_FUNCTIONS)
+        .with_signature(WasmSignature::ed25519(&public_key, &signature))]);
+    let json = serde_json::to_string(&manifest).unwrap();
+    assert_eq!(serde_json::from_str::<Manifest>(&json).unwrap(), manifest);
+
+    let key = p256::ecdsa::SigningKey::from_slice(&[1; 32]).unwrap();
+    let public_key = key
+        .verifying_key()
+        .to_public_key_pem(p256::pkcs8::LineEnding::LF)
+        .unwrap();
+    let sig: p256::ecdsa::Signature = p256::ecdsa::signature::Signer::sign(&key, WASM_NO_FUNCTIONS);
+    let bundle = |data: &[u8], material: &str| {
+        format!(
+            r#"{{
+                "mediaType": "application/vnd.dev.sigstore.bundle.v0.3+json",
+                "verificationMaterial": {{{material}}},
+                "messageSignature": {{
+                    "messageDigest": {{"algorithm": "SHA2_256", "digest": "{}"}},
+                    "signature": "{}"
+                }}
+            }}"#,
+            b64(&sha2::Sha256::digest(data)),
+            b64(sig.to_der().as_bytes()),
+        )
+    };
+    let hint = r#""publicKey": {"hint": "abc"}"#;
+    assert!(build(WasmSignature::sigstore(
+        bundle(WASM_NO_FUNCTIONS, hint),
+        &public_key
+    ))
+    .is_ok());
+    assert_eq!(
+        invalid(build(WasmSignature::sigstore(
+            bundle(WASM_LOOP, hint),
+            &public_key
+        ))),
+        "Sigstore bundle digest doesn't match the module data"
+    );
+    assert_eq!(
+        invalid(build(WasmSignature::sigstore(
+            bundle(WASM_NO_FUNCTIONS, r#""certificate": {"rawBytes": "abc"}"#),
+            &public_key
+        ))),
+        "keyless Sigstore bundles aren't supported"
+    );
+}
+
+#[test]
+fn test_event_bus() {
+    let events = EventBus::new();
+    let rx = events.subscribe();
+
+    let wasm = br#"
+        (module
+            (memory (export "memory") 1)
+            (func (export "grow") (result i32)
+                (drop (memory.grow (i32.const 1)))
+                i32.const 0)
+            (func (export "trap") (result i32)
+                unreachable)
+        )
+    "#;
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_event_bus(events.clone())
+        .build()
+        .unwrap();
+    let id = plugin.id;
+    assert_eq!(rx.recv().unwrap(), PluginEvent::Created { plugin: id });
+
+    let _: &[u8] = plugin.call("grow", "").unwrap();
+    assert!(plugin.call::<&str, &[u8]>("trap", "").is_err());
+    drop(plugin);
+
+    let events: Vec<PluginEvent> = rx.try_iter().collect();
+    assert_eq!(
+        events[0],
+        PluginEvent::CallStarted {
+            plugin: id,
+            function: "grow".to_string()
+        }
+    );
+    assert!(events.iter().any(|e| matches!(
+        e,
+        PluginEvent::MemoryGrown {
+            from: 65536,
+            to: 131072,
+            ..
+        }
+    )));
+    assert!(events.iter().any(|e| matches!(
+        e,
+        PluginEvent::CallFinished { function, success: true, .. } if function == "grow"
+    )));
+    assert!(events.iter().any(|e| matches!(
+        e,
+        PluginEvent::Trap { function, .. } if function == "trap"
+    )));
+    assert!(events.iter().any(|e| matches!(
+        e,
+        PluginEvent::CallFinished { function, success: false, .. } if function == "trap"
+    )));
+    assert_eq!(events.last().unwrap(), &PluginEvent::Dropped { plugin: id });
+
+    // Timeouts and cancellation are reported separately
+    let rx = events_for_loop(|plugin| {
+        let _: Result<&[u8], Error> = plugin.call("loop_forever", "abc123");
+    });
+    assert!(rx.iter().any(|e| matches!(e, PluginEvent::Timeout { .. })));
+
+    let rx = events_for_loop(|plugin| {
+        let handle = plugin.cancel_handle();
+        std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            handle.cancel().unwrap();
+        });
+        let _: Result<&[u8], Error> = plugin.call("loop_forever", "abc123");
+    });
+    assert!(rx
+        .iter()
+        .any(|e| matches!(e, PluginEvent::Cancelled { .. })));
+}
+
+fn events_for_loop(f: impl FnOnce(&mut Plugin)) -> std::sync::mpsc::Receiver<PluginEvent> {
+    let func = Function::new(
+        "hello_world",
+        [PTR],
+        [PTR],
+        UserData::default(),
+        hello_world,
+    );
+    let events = EventBus::new();
+    let rx = events.subscribe();
+    let manifest = Manifest::new([extism_manifest::Wasm::data(WASM_LOOP)])
+        .with_timeout(std::time::Duration::from_secs(1));
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_wasi(true)
+        .with_functions([func])
+        .with_event_bus(events)
+        .build()
+        .unwrap();
+    f(&mut plugin);
+    rx
+}
+
+#[derive(Default)]
+struct RecordedMetrics(std::sync::Mutex<Vec<String>>);
+
+impl Metrics for RecordedMetrics {
+    fn call_started(&self, _plugin: uuid::Uuid, function: &str) {
+        self.0.lock().unwrap().push(format!("start {function}"));
+    }
+
+    fn call_finished(
+        &self,
+        _plugin: uuid::Uuid,
+        function: &str,
+        _duration: std::time::Duration,
+        success: bool,
+    ) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("finish {function} {success}"));
+    }
+
+    fn trap(&self, _plugin: uuid::Uuid, function: &str) {
+        self.0.lock().unwrap().push(format!("trap {function}"));
+    }
+
+    fn timeout(&self, _plugin: uuid::Uuid, function: &str) {
+        self.0.lock().unwrap().push(format!("timeout {function}"));
+    }
+
+    fn memory_grown(&self, _plugin: uuid::Uuid, from: usize, to: usize) {
+        self.0.lock().unwrap().push(format!("grow {from} {to}"));
+    }
+}
+
+#[test]
+fn test_metrics() {
+    let wasm = br#"
+        (module
+            (memory (export "memory") 1)
+            (func (export "grow") (result i32)
+                (drop (memory.grow (i32.const 1)))
+                i32.const 0)
+            (func (export "trap") (result i32)
+                unreachable)
+        )
+    "#;
+    let metrics = std::sync::Arc::new(RecordedMetrics::default());
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_metrics(metrics.clone())
+        .build()
+        .unwrap();
+    let _: &[u8] = plugin.call("grow", "").unwrap();
+    assert!(plugin.call::<&str, &[u8]>("trap", "").is_err());
+    let recorded = std::mem::take(&mut *metrics.0.lock().unwrap());
+    assert!(recorded.contains(&"grow 65536 131072".to_string()));
+    assert_eq!(
+        recorded
+            .into_iter()
+            .filter(|x| !x.starts_with("grow"))
+            .collect::<Vec<_>>(),
+        [
+            "start grow",
+            "finish grow true",
+            "start trap",
+            "trap trap",
+            "finish trap false"
+        ]
+    );
+
+    let manifest = Manifest::new([extism_manifest::Wasm::data(WASM_LOOP)])
+        .with_timeout(std::time::Duration::from_millis(100));
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_wasi(true)
+        .with_function(
+            "hello_world",
+            [PTR],
+            [PTR],
+            UserData::default(),
+            hello_world,
+        )
+        .with_metrics(metrics.clone())
+        .build()
+        .unwrap();
+    let _: Result<&[u8], Error> = plugin.call("loop_forever", "abc123");
+    let recorded = std::mem::take(&mut *metrics.0.lock().unwrap());
+    assert_eq!(
+        recorded
+            .into_iter()
+            .filter(|x| !x.starts_with("grow"))
+            .collect::<Vec<_>>(),
+        [
+            "start loop_forever",
+            "timeout loop_forever",
+            "finish loop_forever false"
+        ]
+    );
+}
+
+#[cfg(feature = "prometheus")]
+#[test]
+fn test_prometheus_metrics() {
+    let registry = prometheus::Registry::new();
+    let metrics = PrometheusMetrics::new(&registry).unwrap();
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(WASM_NO_FUNCTIONS)]))
+        .with_wasi(true)
+        .with_metrics(metrics)
+        .build()
+        .unwrap();
+    let _: &[u8] = plugin.call("count_vowels", "abc").unwrap();
+
+    let families = registry.gather();
+    let calls = families
+        .iter()
+        .find(|x| x.name() == "extism_calls_total")
+        .unwrap();
+    assert_eq!(calls.get_metric()[0].get_counter().get_value(), 1.0);
+    assert!(families
+        .iter()
+        .any(|x| x.name() == "extism_call_duration_seconds"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_hardening() {
+    let wasm = br#"
+(module
+    (import "extism:host/user" "probe" (func $probe))
+    (func (export "run") (result i32)
+        (call $probe)
+        (i32.const 0)
+    )
+)
+    "#;
+    let tmp = std::env::temp_dir();
+    let allowed = tmp.clone();
+    let manifest = Manifest::new([Wasm::data(wasm.to_vec())])
+        .with_allowed_path(format!("ro:{}", tmp.display()), "/tmp");
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_function("probe", [], [], UserData::new(()), move |_, _, _, _| {
+            if std::fs::read_dir("/").is_ok() {
+                anyhow::bail!("filesystem access was not restricted");
+            }
+            if std::net::UdpSocket::bind("127.0.0.1:0").is_ok() {
+                anyhow::bail!("socket creation was not restricted");
+            }
+            std::fs::read_dir(&allowed)?;
+            Ok(())
+        })
+        .with_hardening(Hardening::new())
+        .build()
+        .unwrap();
+
+    match plugin.call::<(), ()>("run", ()) {
+        Err(e) if format!("{e:?}").contains("not supported on this system") => {
+            println!("skipping hardening test: {e:?}")
+        }
+        res => res.unwrap(),
+    }
+
+    // Restrictions are only applied to the thread executing the plugin
+    assert!(std::fs::read_dir("/").is_ok());
+
+    // Allowed hosts can still be resolved and reached, `localhost` is resolved using `/etc/hosts`
+    #[cfg(feature = "http")]
+    {
+        use std::io::Read;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://localhost:{}/",
+            listener.local_addr().unwrap().port()
+        );
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).unwrap();
+                stream
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+                    )
+                    .unwrap();
+            }
+        });
+        let manifest = Manifest::new([Wasm::data(WASM_HTTP)]).with_allowed_host("localhost");
+        let mut plugin = PluginBuilder::new(manifest)
+            .with_wasi(true)
+            .with_hardening(Hardening::new())
+            .build()
+            .unwrap();
+        let input = format!(r#"{{"url": "{url}"}}"#);
+        match plugin.call::<&str, &str>("http_request", &input) {
+            Err(e) if format!("{e:?}").contains("not supported on this system") => (),
+            res => assert_eq!(res.unwrap(), "ok"),
+        }
+    }
+
+    // A `SqliteDatabase` opens its files lazily, from the restricted thread
+    #[cfg(feature = "sql-sqlite")]
+    {
+        let dir = std::env::temp_dir().join(format!("extism-sql-{}", uuid::Uuid::new_v4()));
+        let db = std::sync::Arc::new(SqliteDatabase::open(&dir).unwrap());
+        let d = db.clone();
+        let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+            .with_function("probe", [], [], UserData::new(()), move |_, _, _, _| {
+                d.execute("a", "CREATE TABLE t (x INTEGER)", &[])?;
+                Ok(())
+            })
+            .with_sql_database(db, "a")
+            .with_hardening(Hardening::new())
+            .build()
+            .unwrap();
+        match plugin.call::<(), ()>("run", ()) {
+            Err(e) if format!("{e:?}").contains("not supported on this system") => (),
+            res => res.unwrap(),
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+#[test]
+fn test_resource_report() {
+    let manifest = Manifest::new([Wasm::data(WASM_FS)])
+        .with_allowed_path("ro:src/tests/data".to_string(), "/data")
+        .with_config_key("path", "/data/data.txt");
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_wasi(true)
+        .with_fuel_limit(u64::MAX)
+        .build()
+        .unwrap();
+    assert_eq!(plugin.resource_report(), ResourceReport::default());
+
+    for _ in 0..2 {
+        let res = plugin.call::<&str, &str>("try_read", "").unwrap();
+        assert_eq!(res, "hello world!");
+    }
+
+    let report = plugin.resource_report();
+    assert_eq!(report.calls, 2);
+    assert_eq!(report.fs_bytes_read, 2 * "hello world!".len() as u64);
+    assert_eq!(report.fs_bytes_written, 0);
+    assert!(report.fuel_consumed > 0);
+    assert!(report.wall_time > std::time::Duration::ZERO);
+    assert!(report.peak_memory >= 65536);
+
+    plugin.reset_resource_report();
+    assert_eq!(plugin.resource_report(), ResourceReport::default());
+}
+
+#[cfg(feature = "guest-profiler")]
+#[test]
+fn test_guest_profiler() {
+    let f = Function::new(
+        "hello_world",
+        [PTR],
+        [PTR],
+        UserData::default(),
+        hello_world,
+    );
+
+    let manifest = Manifest::new([extism_manifest::Wasm::data(WASM_LOOP)])
+        .with_timeout(std::time::Duration::from_millis(300));
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_wasi(true)
+        .with_functions([f])
+        .with_profiling(Profiling::Guest {
+            interval: std::time::Duration::from_millis(1),
+        })
+        .build()
+        .unwrap();
+    assert!(plugin.take_profile().is_none());
+
+    // The timeout still interrupts the call while the profiler is sampling it
+    let err = plugin
+        .call::<_, &[u8]>("loop_forever", "abc123")
+        .unwrap_err();
+    assert_eq!(err.root_cause().to_string(), "timeout");
+
+    let profile = plugin.take_profile().unwrap();
+    let profile: serde_json::Value = serde_json::from_slice(&profile).unwrap();
+    let samples = profile["threads"][0]["samples"]["length"].as_u64().unwrap();
+    assert!(samples > 0);
+    assert!(plugin.take_profile().is_none());
+}
+
+#[test]
+fn test_call_with_stats() {
+    let f = Function::new(
+        "hello_world",
+        [PTR],
+        [PTR],
+        UserData::default(),
+        hello_world,
+    );
+    let mut plugin = PluginBuilder::new(WASM)
+        .with_wasi(true)
+        .with_functions([f])
+        .with_fuel_limit(u64::MAX)
+        .build()
+        .unwrap();
+
+    let (output, stats) = plugin
+        .call_with_stats::<&str, String>("count_vowels", "abc")
+        .unwrap();
+    assert_eq!(output, "{\"count\": 1}");
+    assert!(stats.wall_time > std::time::Duration::ZERO);
+    assert_eq!(stats.fuel_consumed, plugin.fuel_consumed().unwrap());
+    assert!(stats.memory_pages >= 2);
+    let host_calls: u64 = plugin.host_call_stats().iter().map(|x| x.calls).sum();
+    assert!(host_calls > 0);
+    assert_eq!(stats.host_calls, host_calls);
+
+    let err = plugin
+        .call_with_stats::<&str, &str>("missing", "")
+        .unwrap_err();
+    assert!(err.to_string().contains("missing"));
+}
+
+#[test]
+fn test_call_log() {
+    use sha2::Digest;
+    let plugin_hash = crate::manifest::hex(&sha2::Sha256::digest(WASM_NO_FUNCTIONS));
+
+    let log = CallLog::in_memory();
+    let mut plugin = PluginBuilder::new(WASM_NO_FUNCTIONS)
+        .with_call_log(log.clone())
+        .build()
+        .unwrap();
+    let output: String = plugin.call("count_vowels", "abc").unwrap();
+    assert!(plugin.call::<&str, &str>("missing", "abc").is_err());
+
+    let records = log.records().unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].plugin_hash, plugin_hash);
+    assert_eq!(records[0].function, "count_vowels");
+    assert_eq!(
+        records[0].output_hash.as_deref(),
+        Some(crate::manifest::hex(&sha2::Sha256::digest(output.as_bytes())).as_str())
+    );
+    assert_eq!(records[1].output_hash, None);
+    assert_eq!(records[1].prev_hash, records[0].hash);
+    CallLog::verify(&records).unwrap();
+
+    // Modified, removed and re-ordered records are detected
+    let mut modified = records.clone();
+    modified[0].function = "other".to_string();
+    assert!(CallLog::verify(&modified).is_err());
+    assert!(CallLog::verify(&records[1..]).is_err());
+    assert!(CallLog::verify(&[records[1].clone(), records[0].clone()]).is_err());
+
+    // File logs continue the existing chain when re-opened
+    let path = std::env::temp_dir().join(format!("extism-call-log-{}.jsonl", plugin.id));
+    for _ in 0..2 {
+        let mut plugin = PluginBuilder::new(WASM_NO_FUNCTIONS)
+            .with_call_log(CallLog::open(&path).unwrap())
+            .build()
+            .unwrap();
+        let _: String = plugin.call("count_vowels", "abc").unwrap();
+    }
+    let records = CallLog::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(records.len(), 2);
+    CallLog::verify(&records).unwrap();
+}
+
+#[test]
+fn test_sandbox_profile() {
+    assert_eq!(
+        "strict".parse::<SandboxProfile>().unwrap(),
+        SandboxProfile::Strict
+    );
+    assert!("unknown".parse::<SandboxProfile>().is_err());
+
+    // Limits fill in unset manifest values
+    let manifest = Manifest::new([Wasm::data(WASM_NO_FUNCTIONS)])
+        .with_timeout(std::time::Duration::from_secs(1));
+    let mut plugin = PluginBuilder::new(&manifest)
+        .with_sandbox_profile(SandboxProfile::Strict)
+        .build()
+        .unwrap();
+    let m = plugin.current_plugin().manifest.clone();
+    assert_eq!(m.timeout_ms, Some(1000));
+    assert_eq!(m.memory.max_pages, Some(256));
+    assert_eq!(m.memory.max_var_bytes, Some(64 * 1024));
+    assert!(plugin.current_plugin().wasi.is_none());
+    let _: String = plugin.call("count_vowels", "abc").unwrap();
+    assert!(plugin.fuel_consumed().unwrap() > 0);
+
+    // Builder methods override the profile
+    let plugin = PluginBuilder::new(&manifest)
+        .with_sandbox_profile(SandboxProfile::Strict)
+        .with_wasi(true)
+        .build()
+        .unwrap();
+    assert!(plugin.current_plugin().wasi.is_some());
+
+    let plugin = PluginBuilder::new(&manifest)
+        .with_sandbox_profile(SandboxProfile::Permissive)
+        .build()
+        .unwrap();
+    assert!(plugin.current_plugin().wasi.is_some());
+    assert_eq!(plugin.current_plugin().manifest.memory.max_pages, None);
+}
+
+#[test]
+fn test_host_function_usage() {
+    let f = Function::new(
+        "hello_world",
+        [PTR],
+        [PTR],
+        UserData::default(),
+        hello_world,
+    )
+    .with_deprecated("use hello_world2 instead");
+    assert_eq!(f.deprecated(), Some("use hello_world2 instead"));
+
+    let events = EventBus::new();
+    let rx = events.subscribe();
+    let mut plugin = PluginBuilder::new(WASM)
+        .with_wasi(true)
+        .with_functions([f])
+        .with_event_bus(events)
+        .build()
+        .unwrap();
+    assert!(plugin.host_function_usage().is_empty());
+
+    for _ in 0..3 {
+        let _: String = plugin.call("count_vowels", "abc").unwrap();
+    }
+
+    let usage = plugin.host_function_usage();
+    let hello = usage.iter().find(|x| x.name == "hello_world").unwrap();
+    assert_eq!(hello.calls, 3);
+    assert_eq!(
+        hello.deprecated.as_deref(),
+        Some("use hello_world2 instead")
+    );
+
+    // The deprecation event is only sent once
+    let deprecated: Vec<_> = rx
+        .try_iter()
+        .filter(|e| matches!(e, PluginEvent::DeprecatedHostFunction { .. }))
+        .collect();
+    assert_eq!(deprecated.len(), 1);
+
+    plugin.reset_host_function_usage();
+    assert!(plugin.host_function_usage().is_empty());
+}
+
+#[test]
+fn test_telemetry_exporter() {
+    let events = EventBus::new();
+    let path =
+        std::env::temp_dir().join(format!("extism-telemetry-{}.jsonl", uuid::Uuid::new_v4()));
+    let exporter = TelemetryExporter::new(
+        &events,
+        FileSink::new(&path).unwrap(),
+        TelemetryOptions::new().with_max_batch_size(2),
+    );
+
+    let mut plugin = PluginBuilder::new(WASM_NO_FUNCTIONS)
+        .with_event_bus(events.clone())
+        .build()
+        .unwrap();
+    let id = plugin.id;
+    let _: String = plugin.call("count_vowels", "abc").unwrap();
+    drop(plugin);
+    exporter.shutdown();
+
+    let data = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let records: Vec<serde_json::Value> = data
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let names: Vec<&str> = records
+        .iter()
+        .map(|r| r["event"].as_str().unwrap())
+        .filter(|name| *name != "memory_grown")
+        .collect();
+    assert_eq!(
+        names,
+        ["created", "call_started", "call_finished", "dropped"]
+    );
+    assert!(records
+        .iter()
+        .all(|r| r["plugin"] == id.to_string() && r["timestamp_ms"].as_u64().unwrap() > 0));
+    assert!(records
+        .iter()
+        .any(|r| r["event"] == "call_finished" && r["success"] == true));
+}
+
+#[test]
+fn test_input_written_into_memory() {
+    let mut plugin = PluginBuilder::new(WASM_NO_FUNCTIONS).build().unwrap();
+
+    let input = vec![b'a'; 16 * 1024 * 1024];
+    let Json(count) = plugin
+        .call::<_, Json<Count>>("count_vowels", &input)
+        .unwrap();
+    assert_eq!(count.count, input.len());
+    let Json(count) = plugin
+        .call::<_, Json<Count>>("count_vowels", String::from("aeiou"))
+        .unwrap();
+    assert_eq!(count.count, 5);
+    assert_eq!(plugin.output.input_length, 5);
+
+    // The encoded length has to match what's written
+    struct Short;
+    impl ToBytes<'_> for Short {
+        type Bytes = Vec<u8>;
+
+        fn to_bytes(&self) -> Result<Self::Bytes, Error> {
+            Ok(b"abc".to_vec())
+        }
+
+        fn encoded_len(&self) -> Option<usize> {
+            Some(4)
+        }
+    }
+    let err = plugin.call::<_, &str>("count_vowels", Short).unwrap_err();
+    assert!(err.to_string().contains("shorter than its encoded length"));
+}
+
+#[test]
+fn test_call_into() {
+    let mut plugin = PluginBuilder::new(WASM_NO_FUNCTIONS).build().unwrap();
+
+    let mut output = Vec::with_capacity(1024);
+    let ptr = output.as_ptr();
+    for input in ["a", "aeiou", "bcd"] {
+        plugin
+            .call_into("count_vowels", input, &mut output)
+            .unwrap();
+        let Json(count) = Json::<Count>::from_bytes(&output).unwrap();
+        assert_eq!(
+            count.count,
+            input.chars().filter(|x| "aeiou".contains(*x)).count()
+        );
+    }
+    assert_eq!(output.as_ptr(), ptr);
+
+    let mut buf = vec![];
+    let mut buf2 = vec![];
+    let a: Json<Count> = plugin
+        .call_with_buffer("count_vowels", "a", &mut buf)
+        .unwrap();
+    let b: Json<Count> = plugin
+        .call_with_buffer("count_vowels", "aa", &mut buf2)
+        .unwrap();
+    assert_eq!((a.0.count, b.0.count), (1, 2));
+
+    // The buffer is unchanged when the call fails
+    let before = buf.clone();
+    assert!(plugin.call_into("missing", "", &mut buf).is_err());
+    assert_eq!(buf, before);
+}
+
+#[test]
+fn test_pooling_allocator() {
+    // Each call to this plugin uses four slots, see `PoolingAllocator`
+    let compiled = CompiledPlugin::new(
+        PluginBuilder::new(WASM_NO_FUNCTIONS).with_pooling_allocator(
+            PoolingAllocator::new()
+                .with_max_instances(8)
+                .with_max_memory_size(64 * 1024 * 1024),
+        ),
+    )
+    .unwrap();
+
+    let mut a = Plugin::new_from_compiled(&compiled).unwrap();
+    let mut b = Plugin::new_from_compiled(&compiled).unwrap();
+    let _: &str = a.call("count_vowels", "abc").unwrap();
+    let _: &str = b.call("count_vowels", "abc").unwrap();
+    assert!(Plugin::new_from_compiled(&compiled)
+        .and_then(|mut c| c.call::<_, String>("count_vowels", "abc"))
+        .is_err());
+
+    // Slots are reused once a plugin is dropped
+    drop(a);
+    for _ in 0..10 {
+        let mut c = Plugin::new_from_compiled(&compiled).unwrap();
+        let _: &str = c.call("count_vowels", "abc").unwrap();
+    }
+}
+
+#[test]
+fn test_compile_all() {
+    let calls = std::sync::atomic::AtomicUsize::new(0);
+    let report = CompiledPlugin::compile_all_with_progress(
+        [
+            PluginBuilder::new(WASM_NO_FUNCTIONS),
+            PluginBuilder::new(&b"not wasm"[..]),
+            PluginBuilder::new(WASM_NO_FUNCTIONS).with_wasi(true),
+            PluginBuilder::new(&b"(module (func"[..]),
+        ],
+        |done, total| {
+            assert!(done <= total);
+            calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        },
+    );
+    assert_eq!(calls.into_inner(), 4);
+    assert_eq!(report.errors().map(|(i, _)| i).collect::<Vec<_>>(), [1, 3]);
+    let mut plugin = Plugin::new_from_compiled(report.plugins[2].as_ref().unwrap()).unwrap();
+    let _: &str = plugin.call("count_vowels", "abc").unwrap();
+    let err = report.into_result().err().unwrap().to_string();
+    assert!(err.starts_with("2 plugins failed to load\n  plugin 1: "));
+    assert!(err.contains("\n  plugin 3: "));
+
+    // Modules in a manifest are loaded in parallel and all errors are reported
+    let manifest = Manifest::new([
+        Wasm::data(b"bad".to_vec()),
+        Wasm::data(WASM_NO_FUNCTIONS.to_vec()).with_name("a"),
+        Wasm::data(b"bad".to_vec()),
+    ]);
+    let err = Plugin::new(&manifest, [], false).err().unwrap().to_string();
+    assert!(err.starts_with("2 modules failed to load\n  module 0: "));
+    assert!(err.contains("\n  module 2: "));
+
+    let manifest = Manifest::new([
+        Wasm::data(WASM_NO_FUNCTIONS.to_vec()).with_name("a"),
+        Wasm::data(WASM_NO_FUNCTIONS.to_vec()),
+    ]);
+    let mut plugin = Plugin::new(&manifest, [], false).unwrap();
+    let _: &str = plugin.call("count_vowels", "abc").unwrap();
+}
+
+#[test]
+fn test_lazy_instantiation() {
+    let events = EventBus::new();
+    let rx = events.subscribe();
+    let mut plugin = PluginBuilder::new(WASM_NO_FUNCTIONS)
+        .with_wasi(true)
+        .with_lazy_instantiation(true)
+        .with_event_bus(events.clone())
+        .build()
+        .unwrap();
+    let id = plugin.id;
+    let cancel = plugin.cancel_handle();
+
+    // Nothing is instantiated before the first call
+    assert!(plugin.pending.is_some());
+    assert!(plugin.current_plugin().wasi.is_none());
+    assert!(plugin
+        .linker
+        .get(&mut plugin.store, EXTISM_ENV_MODULE, "alloc")
+        .is_none());
+    assert!(plugin.has_wasi());
+    assert!(plugin.function_exists("count_vowels"));
+    plugin.reset().unwrap();
+
+    let Json(count) = plugin
+        .call::<_, Json<Count>>("count_vowels", "aaa")
+        .unwrap();
+    assert_eq!(count.count, 3);
+    assert!(plugin.pending.is_none());
+    assert!(plugin.current_plugin().wasi.is_some());
+    assert_eq!(plugin.id, id);
+    assert_eq!(plugin.cancel_handle().id, cancel.id);
+    drop(plugin);
+
+    let events: Vec<PluginEvent> = rx.try_iter().collect();
+    assert_eq!(events.first(), Some(&PluginEvent::Created { plugin: id }));
+    assert_eq!(events.last(), Some(&PluginEvent::Dropped { plugin: id }));
+    assert_eq!(
+        events
+            .iter()
+            .filter(|x| matches!(x, PluginEvent::Created { .. } | PluginEvent::Dropped { .. }))
+            .count(),
+        2
+    );
+
+    // Instantiation errors are returned by the first call, until instantiation succeeds
+    let wasm = br#"(module (import "extism:host/user" "missing" (func)) (func (export "run")))"#;
+    let mut plugin = PluginBuilder::new(&wasm[..])
+        .with_lazy_instantiation(true)
+        .build()
+        .unwrap();
+    assert!(plugin.call::<_, &str>("run", "").is_err());
+    assert!(plugin.call::<_, &str>("run", "").is_err());
+    assert!(plugin.pending.is_some());
+}
+
+#[test]
+fn test_copy_on_write_reset() {
+    let wasm = br#"
+        (module
+            (memory (export "memory") 1)
+            (data (i32.const 0) "\01")
+            (global (export "g") (mut i32) (i32.const 7))
+            (func (export "bump") (result i32)
+                (i32.store8 (i32.const 0) (i32.add (i32.load8_u (i32.const 0)) (i32.const 1)))
+                (global.set 0 (i32.const 9))
+                (drop (memory.grow (i32.const 1)))
+                (i32.store8 (i32.const 65536) (i32.const 5))
+                i32.const 0)
+            (func (export "set") (result i32)
+                (i32.store8 (i32.const 0) (i32.const 3))
+                (global.set 0 (i32.const 9))
+                i32.const 0)
+            (func (export "trap") (result i32)
+                unreachable)
+        )
+    "#;
+    fn state(plugin: &mut Plugin) -> (u8, u8, i32) {
+        let instance = plugin.instance.lock().unwrap().unwrap();
+        let mem = instance.get_memory(&mut plugin.store, "memory").unwrap();
+        let data = mem.data(&plugin.store);
+        let (a, b) = (data[0], data.get(65536).copied().unwrap_or_default());
+        let g = instance.get_global(&mut plugin.store, "g").unwrap();
+        (a, b, g.get(&mut plugin.store).unwrap_i32())
+    }
+
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_copy_on_write_reset(true)
+        .build()
+        .unwrap();
+    let _: &[u8] = plugin.call("set", "").unwrap();
+    assert_eq!(state(&mut plugin), (3, 0, 9));
+    plugin.reset().unwrap();
+    assert_eq!(state(&mut plugin), (1, 0, 7));
+
+    // Memory that has grown is replaced by a new instance
+    let instance = plugin.instance.lock().unwrap().unwrap();
+    let _: &[u8] = plugin.call("bump", "").unwrap();
+    assert_eq!(state(&mut plugin), (2, 5, 9));
+    plugin.reset().unwrap();
+    assert_eq!(state(&mut plugin), (1, 0, 7));
+    assert_ne!(plugin.instance.lock().unwrap().unwrap(), instance);
+    let mem = plugin
+        .instance
+        .lock()
+        .unwrap()
+        .unwrap()
+        .get_memory(&mut plugin.store, "memory")
+        .unwrap();
+    assert_eq!(mem.data_size(&plugin.store), 65536);
+    let _: &[u8] = plugin.call("bump", "").unwrap();
+    let _: &[u8] = plugin.call("bump", "").unwrap();
+    assert_eq!(state(&mut plugin), (3, 5, 9));
+    plugin.reset().unwrap();
+    assert_eq!(state(&mut plugin), (1, 0, 7));
+
+    // A failed call replaces the instance
+    assert!(plugin.call::<_, &[u8]>("trap", "").is_err());
+    assert!(plugin.store_needs_reset);
+    let _: &[u8] = plugin.call("bump", "").unwrap();
+    assert_eq!(state(&mut plugin), (2, 5, 9));
+    plugin.reset().unwrap();
+    assert_eq!(state(&mut plugin), (1, 0, 7));
+
+    // Plugins are reset when they're returned to a pool
+    let pool = Pool::new(move || {
+        PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+            .with_copy_on_write_reset(true)
+            .build()
+    });
+    for _ in 0..3 {
+        let mut plugin = pool
+            .get(std::time::Duration::from_secs(1))
+            .unwrap()
+            .unwrap();
+        let _: Vec<u8> = plugin.call("bump", "").unwrap();
+        assert_eq!(state(&mut plugin), (2, 5, 9));
+    }
+
+    // The pooling allocator can be used too
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_copy_on_write_reset(true)
+        .with_pooling_allocator(PoolingAllocator::new())
+        .build()
+        .unwrap();
+    let _: &[u8] = plugin.call("set", "").unwrap();
+    plugin.reset().unwrap();
+    assert_eq!(state(&mut plugin), (1, 0, 7));
+}
+
+#[test]
+fn test_snapshot() {
+    let wasm = br#"
+        (module
+            (memory (export "memory") 1)
+            (global (export "g") (mut i32) (i32.const 0))
+            (func (export "bump") (result i32)
+                (i32.store8 (i32.const 0) (i32.add (i32.load8_u (i32.const 0)) (i32.const 1)))
+                (global.set 0 (i32.add (global.get 0) (i32.const 1)))
+                i32.const 0)
+            (func (export "grow") (result i32)
+                (drop (memory.grow (i32.const 1)))
+                (i32.store8 (i32.const 65536) (i32.const 5))
+                i32.const 0)
+        )
+    "#;
+    fn state(plugin: &mut Plugin) -> (u8, u8, i32) {
+        let instance = plugin.instance.lock().unwrap().unwrap();
+        let mem = instance.get_memory(&mut plugin.store, "memory").unwrap();
+        let data = mem.data(&plugin.store);
+        let (a, b) = (data[0], data.get(65536).copied().unwrap_or_default());
+        let g = instance.get_global(&mut plugin.store, "g").unwrap();
+        (a, b, g.get(&mut plugin.store).unwrap_i32())
+    }
+    let compiled = CompiledPlugin::new(PluginBuilder::new(Manifest::new([Wasm::data(
+        wasm.to_vec(),
+    )])))
+    .unwrap();
+    let build = || Plugin::new_from_compiled(&compiled).unwrap();
+
+    let mut plugin = build();
+    for _ in 0..3 {
+        let _: &[u8] = plugin.call("bump", "").unwrap();
+    }
+    let snapshot = plugin.snapshot().unwrap();
+    assert_eq!(snapshot.memory_size(), 65536);
+    let _: &[u8] = plugin.call("bump", "").unwrap();
+    let _: &[u8] = plugin.call("grow", "").unwrap();
+    assert_eq!(state(&mut plugin), (4, 5, 4));
+    plugin.restore(&snapshot).unwrap();
+    assert_eq!(state(&mut plugin), (3, 0, 3));
+    let _: &[u8] = plugin.call("bump", "").unwrap();
+    assert_eq!(state(&mut plugin), (4, 0, 4));
+
+    // Snapshots can be restored into other instances of the same module
+    let mut other = build();
+    other.restore(&snapshot).unwrap();
+    assert_eq!(state(&mut other), (3, 0, 3));
+
+    let mut different = PluginBuilder::new(Manifest::new([Wasm::data(WASM_NO_FUNCTIONS)]))
+        .build()
+        .unwrap();
+    assert!(different.restore(&snapshot).is_err());
+
+    // Separately compiled plugins are compared by hash, which isn't computed unless it's needed
+    use sha2::Digest;
+    assert!(plugin.main_hash.is_none());
+    let hash = manifest::hex(&sha2::Sha256::digest(&wasm[..]));
+    let build_hashed = || {
+        PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec()).with_hash(&hash)]))
+            .build()
+            .unwrap()
+    };
+    let snapshot = build_hashed().snapshot().unwrap();
+    build_hashed().restore(&snapshot).unwrap();
+    assert!(
+        PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+            .build()
+            .unwrap()
+            .restore(&snapshot)
+            .is_err()
+    );
+}
+
+#[test]
+#[cfg(feature = "component-model")]
+fn test_component() {
+    let wasm = r#"
+        (component
+            (core module $m
+                (memory (export "memory") 1)
+                (global $next (mut i32) (i32.const 1024))
+                (data (i32.const 16) "failed")
+                (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+                    (local $p i32)
+                    (local.set $p (global.get $next))
+                    (global.set $next (i32.add (global.get $next) (local.get 3)))
+                    (local.get $p))
+                (func (export "reverse") (param $ptr i32) (param $len i32) (result i32)
+                    (local $i i32) (local $j i32) (local $t i32)
+                    (local.set $i (local.get $ptr))
+                    (local.set $j (i32.sub (i32.add (local.get $ptr) (local.get $len)) (i32.const 1)))
+                    (block $done (loop $l
+                        (br_if $done (i32.ge_s (local.get $i) (local.get $j)))
+                        (local.set $t (i32.load8_u (local.get $i)))
+                        (i32.store8 (local.get $i) (i32.load8_u (local.get $j)))
+                        (i32.store8 (local.get $j) (local.get $t))
+                        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                        (local.set $j (i32.sub (local.get $j) (i32.const 1)))
+                        (br $l)))
+                    (i32.store (i32.const 0) (local.get $ptr))
+                    (i32.store (i32.const 4) (local.get $len))
+                    (i32.const 0))
+                (func (export "fail") (result i32)
+                    (i32.store8 (i32.const 32) (i32.const 1))
+                    (i32.store (i32.const 36) (i32.const 16))
+                    (i32.store (i32.const 40) (i32.const 6))
+                    (i32.const 32))
+                (func (export "add") (param i32 i32) (result i32)
+                    (i32.add (local.get 0) (local.get 1)))
+            )
+            (core instance $i (instantiate $m))
+            (func (export "reverse") (param "input" (list u8)) (result (list u8))
+                (canon lift (core func $i "reverse") (memory (core memory $i "memory"))
+                    (realloc (core func $i "realloc"))))
+            (func (export "fail") (result (result string (error string)))
+                (canon lift (core func $i "fail") (memory (core memory $i "memory"))
+                    (realloc (core func $i "realloc")) string-encoding=utf8))
+            (func (export "add") (param "a" u32) (param "b" u32) (result u32)
+                (canon lift (core func $i "add")))
+        )
+    "#;
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm)]))
+        .build()
+        .unwrap();
+    assert!(plugin.function_exists("reverse"));
+    assert!(plugin.function_exists("fail"));
+    assert!(!plugin.function_exists("add"));
+    let res: &str = plugin.call("reverse", "hello").unwrap();
+    assert_eq!(res, "olleh");
+    let res: Vec<u8> = plugin.call("reverse", "").unwrap();
+    assert!(res.is_empty());
+    let err = plugin.call::<(), ()>("fail", ()).unwrap_err();
+    assert_eq!(err.to_string(), "failed");
+    assert!(plugin.call::<(), ()>("add", ()).is_err());
+
+    // Components can only be used as the main module
+    assert!(PluginBuilder::new(Manifest::new([
+        Wasm::data(wasm).with_name("lib"),
+        Wasm::data(WASM_NO_FUNCTIONS)
+    ]))
+    .build()
+    .is_err());
+}
+
+#[test]
+fn test_shared_host_linker() {
+    let f = Function::new(
+        "hello_world",
+        [PTR],
+        [PTR],
+        UserData::default(),
+        hello_world,
+    )
+    .with_namespace(EXTISM_USER_MODULE);
+    let g = Function::new(
+        "hello_world",
+        [PTR],
+        [PTR],
+        UserData::default(),
+        hello_world_panic,
+    )
+    .with_namespace("test");
+    let compiled = CompiledPlugin::new(
+        PluginBuilder::new(WASM)
+            .with_wasi(true)
+            .with_functions([f, g]),
+    )
+    .unwrap();
+
+    // Host functions are only defined once, each plugin gets a copy of the same linker
+    let mut a = Plugin::new_from_compiled(&compiled).unwrap();
+    let mut b = Plugin::new_from_compiled(&compiled).unwrap();
+    assert!(std::sync::Arc::ptr_eq(&a.host_linker, &b.host_linker));
+    for plugin in [&mut a, &mut b] {
+        let Json(count) = plugin
+            .call::<_, Json<Count>>("count_vowels", "aei")
+            .unwrap();
+        assert_eq!(count.count, 3);
+    }
+    drop(compiled);
+    b.store_needs_reset = true;
+    let Json(count) = b.call::<_, Json<Count>>("count_vowels", "aei").unwrap();
+    assert_eq!(count.count, 3);
+}
+
+#[test]
+fn test_store_reset_keeps_host_state() {
+    let wasm = br#"(module
+        (import "extism:host/user" "count" (func $count (param i64) (result i64)))
+        (func (export "_start")
+            (drop (call $count (i64.const 0)))))"#;
+    let count = Function::new(
+        "count",
+        [PTR],
+        [PTR],
+        UserData::new(()),
+        |_: &mut CurrentPlugin, inputs, outputs, _| {
+            outputs[0] = inputs[0];
+            Ok(())
+        },
+    );
+    let mut plugin = PluginBuilder::new(&wasm[..])
+        .with_functions([count])
+        .build()
+        .unwrap();
+    for i in 1..=3 {
+        let _: &[u8] = plugin.call("_start", "").unwrap();
+        assert!(plugin.store_needs_reset);
+        let usage = plugin.host_function_usage();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].calls, i);
+    }
+}
+
+#[test]
+fn test_var_keys_are_reused() {
+    // Sets a variable using the input as both the key and the value
+    let data = br#"(module
+        (import "extism:host/env" "var_set" (func $var_set (param i64 i64)))
+        (import "extism:host/env" "input_offset" (func $input_offset (result i64)))
+        (func (export "test") (result i32)
+            (call $input_offset)
+            (call $input_offset)
+            (call $var_set)
+            (i32.const 0)))"#;
+    let mut plugin = Plugin::new(&data[..], [], false).unwrap();
+    let key = |plugin: &Plugin| {
+        plugin
+            .current_plugin()
+            .vars()
+            .keys()
+            .next()
+            .unwrap()
+            .as_ptr()
+    };
+
+    let _: () = plugin.call("test", "a").unwrap();
+    let a = key(&plugin);
+
+    // Updating a variable keeps its key
+    let _: () = plugin.call("test", "a").unwrap();
+    assert_eq!(a, key(&plugin));
+    assert_eq!(plugin.current_plugin().vars()["a"], b"a");
+}
+
+#[test]
+fn test_config_is_interned() {
+    let wasm = br#"(module
+        (import "extism:host/env" "input_offset" (func $input_offset (result i64)))
+        (import "extism:host/env" "config_get" (func $config_get (param i64) (result i64)))
+        (import "extism:host/env" "length" (func $length (param i64) (result i64)))
+        (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+        (func (export "get") (result i32)
+            (local $v i64)
+            (local.set $v (call $config_get (call $input_offset)))
+            (call $output_set (local.get $v) (call $length (local.get $v)))
+            (i32.const 0)))"#;
+    let manifest = Manifest::new([Wasm::data(wasm.to_vec())]).with_config_key("a", "1");
+    let mut plugin = Plugin::new(&manifest, [], false).unwrap();
+
+    // Reading a value shares it with the interned config instead of copying it
+    let a = plugin.current_plugin().config.get("a").unwrap();
+    assert_eq!(plugin.call::<&str, &str>("get", "a").unwrap(), "1");
+    assert!(std::sync::Arc::ptr_eq(
+        &a,
+        &plugin.current_plugin().config.get("a").unwrap()
+    ));
+
+    // Updates through the C API are visible to `config_get`
+    let json = br#"{"a": null, "b": "2"}"#;
+    assert!(unsafe { sdk::extism_plugin_config(&mut plugin, json.as_ptr(), json.len() as u64) });
+    assert_eq!(plugin.call::<&str, &str>("get", "a").unwrap(), "");
+    assert_eq!(plugin.call::<&str, &str>("get", "b").unwrap(), "2");
+    assert_eq!(plugin.current_plugin().manifest().config["b"], "2");
+}
+
+#[test]
+fn test_concurrent_plugin() {
+    let compiled = CompiledPlugin::new(PluginBuilder::new(WASM_NO_FUNCTIONS)).unwrap();
+    let plugin = compiled.into_concurrent(2);
+    assert_eq!(plugin.instances(), 0);
+    assert!(plugin.function_exists("count_vowels"));
+    assert!(!plugin.function_exists("missing"));
+
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let plugin = plugin.clone();
+            std::thread::spawn(move || {
+                for _ in 0..5 {
+                    let Json(count): Json<Count> = plugin.call("count_vowels", "aaa").unwrap();
+                    assert_eq!(count.count, 3);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert!((1..=2).contains(&plugin.instances()));
+    assert_eq!(plugin.max_instances(), 2);
+
+    let res: Option<Json<Count>> = plugin
+        .call_timeout("count_vowels", "a", std::time::Duration::from_secs(1))
+        .unwrap();
+    assert_eq!(res.unwrap().0.count, 1);
+}
+
+#[test]
+fn test_build_background() {
+    let wasm = WASM_NO_FUNCTIONS.to_vec();
+    let mut plugin = PluginBuilder::new(&wasm[..]).build_background().unwrap();
+    drop(wasm);
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(plugin.ready())
+        .unwrap();
+    assert!(plugin.is_ready());
+    let Json(count): Json<Count> = plugin.call("count_vowels", "aaa").unwrap();
+    assert_eq!(count.count, 3);
+
+    // Calls before compilation finishes wait for it
+    let mut plugin = PluginBuilder::new(WASM_NO_FUNCTIONS)
+        .build_background()
+        .unwrap();
+    let Json(count): Json<Count> = plugin.call("count_vowels", "a").unwrap();
+    assert_eq!(count.count, 1);
+    let mut plugin = plugin.into_plugin().unwrap();
+    let Json(count): Json<Count> = plugin.call("count_vowels", "aa").unwrap();
+    assert_eq!(count.count, 2);
+
+    // Compilation errors are returned by each call
+    let mut plugin = PluginBuilder::new(&b"not wasm"[..])
+        .build_background()
+        .unwrap()
+        .with_fail_fast(true);
+    while !plugin.is_ready() {
+        assert!(plugin
+            .call::<_, Vec<u8>>("count_vowels", "")
+            .unwrap_err()
+            .to_string()
+            .contains("still compiling"));
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    assert!(plugin.wait().is_err());
+    assert!(plugin.call::<_, Vec<u8>>("count_vowels", "").is_err());
+    assert!(plugin.into_plugin().is_err());
+
+    // A panic while compiling is returned as an error instead of leaving callers waiting
+    let manifest = Manifest::new([Wasm::url("panic://plugin.wasm")]);
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_wasm_resolver("panic", |_: &HttpRequest| -> Result<Vec<u8>, Error> {
+            panic!("resolver panicked")
+        })
+        .build_background()
+        .unwrap();
+    let err = plugin.wait().unwrap_err();
+    assert!(err.to_string().contains("resolver panicked"), "{err:?}");
+    assert!(plugin.call::<_, Vec<u8>>("count_vowels", "").is_err());
+    assert!(plugin.into_plugin().is_err());
+}
+
+#[test]
+fn test_load_mapped_and_precompiled() {
+    use sha2::Digest;
+    let dir = std::env::temp_dir().join(format!("extism-mmap-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let wasm_path = dir.join("code.wasm");
+    std::fs::write(&wasm_path, WASM_NO_FUNCTIONS).unwrap();
+    let hash = crate::manifest::hex(&sha2::Sha256::digest(WASM_NO_FUNCTIONS));
+
+    // Files are hashed and compiled from the same copy of the data
+    let manifest = Manifest::new([Wasm::file(&wasm_path).with_hash(&hash)]);
+    let compiled = CompiledPlugin::new(PluginBuilder::new(manifest)).unwrap();
+    let mut plugin = Plugin::new_from_compiled(&compiled).unwrap();
+    let Json(count): Json<Count> = plugin.call("count_vowels", "aaa").unwrap();
+    assert_eq!(count.count, 3);
+    let bad = Manifest::new([Wasm::file(&wasm_path).with_hash("00")]);
+    assert!(Plugin::new(bad, [], false).is_err());
+
+    // Serialized modules are only loaded when enabled, without a hash they are mapped from the file
+    let cwasm_path = dir.join("code.cwasm");
+    std::fs::write(&cwasm_path, compiled.serialize_module("main").unwrap()).unwrap();
+    assert!(compiled.serialize_module("missing").is_err());
+    let manifest = Manifest::new([Wasm::file(&cwasm_path)]);
+    let err = PluginBuilder::new(&manifest).build().err().unwrap();
+    assert!(err.to_string().contains("with_precompiled_modules"));
+    let mut plugin = unsafe { PluginBuilder::new(&manifest).with_precompiled_modules(true) }
+        .build()
+        .unwrap();
+    let Json(count): Json<Count> = plugin.call("count_vowels", "aa").unwrap();
+    assert_eq!(count.count, 2);
+
+    // Precompiled files are checked against the hash before they're loaded
+    let bad = Manifest::new([Wasm::file(&cwasm_path).with_hash("00")]);
+    let err = unsafe { PluginBuilder::new(&bad).with_precompiled_modules(true) }
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("Hash mismatch"), "{err}");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_host_call_stats() {
+    let f = Function::new(
+        "hello_world",
+        [PTR],
+        [PTR],
+        UserData::new(()),
+        |plugin: &mut CurrentPlugin, inputs: &[Val], outputs: &mut [Val], _| {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            let input: Vec<u8> = plugin.memory_get_val(&inputs[0])?;
+            plugin.memory_set_val(&mut outputs[0], input)
+        },
+    );
+    let mut plugin = PluginBuilder::new(WASM)
+        .with_wasi(true)
+        .with_functions([f])
+        .build()
+        .unwrap();
+    assert!(plugin.host_call_stats().is_empty());
+
+    // Stats only cover the most recent call
+    for _ in 0..2 {
+        let _: String = plugin.call("count_vowels", "abc").unwrap();
+        let stats = plugin.host_call_stats();
+        let hello = stats.iter().find(|x| x.name == "hello_world").unwrap();
+        assert_eq!(hello.calls, 1);
+        assert!(hello.total_time >= std::time::Duration::from_millis(5));
+        assert_eq!(hello.max_time, hello.total_time);
+        assert!(hello.bytes_read > 0);
+        assert_eq!(hello.bytes_written, hello.bytes_read);
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_async_plugin() {
+    let plugin = PluginBuilder::new(WASM_NO_FUNCTIONS)
+        .build_async()
+        .await
+        .unwrap();
+    assert!(plugin.function_exists("count_vowels").await);
+    let Json(count): Json<Count> = plugin.call("count_vowels", "aaa").await.unwrap();
+    assert_eq!(count.count, 3);
+    assert!(plugin.into_plugin().is_ok());
+
+    // Dropping the future cancels the call
+    let wasm = br#"(module (func (export "loop") (loop (br 0))) (func (export "noop")))"#;
+    let plugin = Plugin::new(&wasm[..], [], false).unwrap().into_async();
+    let task = tokio::spawn({
+        let plugin = plugin.clone();
+        async move { plugin.call::<_, Vec<u8>>("loop", "").await }
+    });
+    tokio::task::yield_now().await;
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    task.abort();
+    assert!(task.await.unwrap_err().is_cancelled());
+    let output: Vec<u8> = plugin.call("noop", "").await.unwrap();
+    assert!(output.is_empty());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_async_plugin_drop_running_call() {
+    let wasm = br#"(module (func (export "loop") (loop (br 0))) (func (export "noop")))"#;
+    let manifest =
+        Manifest::new([Wasm::data(wasm.to_vec())]).with_timeout(std::time::Duration::from_secs(10));
+    let plugin = Plugin::new(manifest, [], false).unwrap().into_async();
+
+    // Poll the call once so it starts on the blocking thread pool, then drop the future
+    let mut call = Box::pin(plugin.call::<_, Vec<u8>>("loop", ""));
+    let pending = std::future::poll_fn(|cx| {
+        std::task::Poll::Ready(std::future::Future::poll(call.as_mut(), cx).is_pending())
+    })
+    .await;
+    assert!(pending);
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    let start = std::time::Instant::now();
+    drop(call);
+
+    // The next call only gets the plugin once the running call has been cancelled
+    let output: Vec<u8> = plugin.call("noop", "").await.unwrap();
+    assert!(output.is_empty());
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_async_plugin_drop_queued_call() {
+    let wasm = br#"
+        (module
+            (func (export "work") (result i32)
+                (local $i i64)
+                (loop $l
+                    (local.set $i (i64.add (local.get $i) (i64.const 1)))
+                    (br_if $l (i64.lt_u (local.get $i) (i64.const 2000000000))))
+                i32.const 0)
+            (func (export "noop"))
+        )
+    "#;
+    let a = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .build()
+        .unwrap()
+        .into_async();
+    let b = a.clone();
+    let running = tokio::spawn(async move { a.call::<_, Vec<u8>>("work", "").await });
+    tokio::task::yield_now().await;
+    std::thread::sleep(std::time::Duration::from_millis(50));
+
+    // Dropping a call that's waiting for the plugin doesn't cancel the call that's running
+    let queued = tokio::spawn(async move { b.call::<_, Vec<u8>>("noop", "").await });
+    tokio::task::yield_now().await;
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    queued.abort();
+    assert!(queued.await.unwrap_err().is_cancelled());
+    running.await.unwrap().unwrap();
+}
+
+#[test]
+fn test_call_streaming() {
+    use std::io::{Read, Write};
+
+    let mut plugin = Plugin::new(WASM_NO_FUNCTIONS, [], true).unwrap();
+
+    // The input grows past the initial block, so earlier chunks have to be moved
+    let mut call = plugin.call_streaming("count_vowels").unwrap();
+    for _ in 0..200 {
+        call.write_all(&[b'a'; 1000]).unwrap();
+    }
+    call.write_all(b"bcd").unwrap();
+    assert_eq!(call.input_len(), 200_003);
+    let mut output = call.finish().unwrap();
+    let mut chunk = [0u8; 4];
+    assert_eq!(output.read(&mut chunk).unwrap(), 4);
+    let mut rest = String::new();
+    output.read_to_string(&mut rest).unwrap();
+    assert_eq!(output.remaining(), 0);
+    let count: serde_json::Value =
+        serde_json::from_str(&format!("{}{rest}", std::str::from_utf8(&chunk).unwrap())).unwrap();
+    assert_eq!(count["count"], 200_000);
+
+    // Regular calls still work afterwards, and empty input is allowed
+    let Json(count): Json<Count> = plugin.call("count_vowels", "aei").unwrap();
+    assert_eq!(count.count, 3);
+    let mut output = plugin
+        .call_streaming("count_vowels")
+        .unwrap()
+        .finish()
+        .unwrap();
+    let mut s = String::new();
+    output.read_to_string(&mut s).unwrap();
+    assert!(s.contains("\"count\":0"));
+
+    assert!(plugin.call_streaming("missing").unwrap().finish().is_err());
+}
+
+#[test]
+fn test_typed_function() {
+    let mut plugin = PluginBuilder::new(WASM)
+        .with_wasi(true)
+        .with_typed_function("hello_world", |Json(count): Json<Count>| {
+            Ok(Json(Count {
+                count: count.count * 10,
+            }))
+        })
+        .build()
+        .unwrap();
+    let Json(count): Json<Count> = plugin.call("count_vowels", "aei").unwrap();
+    assert_eq!(count.count, 30);
+
+    // Conversion errors are returned from the call
+    let mut plugin = PluginBuilder::new(WASM)
+        .with_wasi(true)
+        .with_typed_function("hello_world", |_: Json<Vec<u8>>| Ok(""))
+        .build()
+        .unwrap();
+    assert!(plugin.call::<_, &[u8]>("count_vowels", "aei").is_err());
+}
+
+#[test]
+fn test_zeroize_memory() {
+    fn input_after_call(zeroize: bool) -> Vec<u8> {
+        let mut plugin = PluginBuilder::new(WASM_NO_FUNCTIONS)
+            .with_zeroize_memory(zeroize)
+            .build()
+            .unwrap();
+        let _: String = plugin.call("count_vowels", "secret value").unwrap();
+        let (offset, len) = (plugin.output.input_offset, 12);
+        let handle = plugin.current_plugin_mut().memory_handle(offset).unwrap();
+        assert!(handle.len() >= len);
+        plugin.current_plugin_mut().memory_bytes(handle).unwrap()[..len].to_vec()
+    }
+
+    assert_eq!(input_after_call(false), b"secret value");
+    assert_eq!(input_after_call(true), [0; 12]);
+
+    // Plugin memory and vars are cleared
+    let mut plugin = PluginBuilder::new(WASM_NO_FUNCTIONS)
+        .with_zeroize_memory(true)
+        .build()
+        .unwrap();
+    let _: String = plugin.call("count_vowels", "abc").unwrap();
+    plugin
+        .current_plugin_mut()
+        .vars
+        .insert("key".to_string(), b"password".to_vec());
+    let instance = *plugin.instance.lock().unwrap();
+    plugin.zeroize_memory(instance);
+    assert!(plugin.current_plugin().vars.is_empty());
+    let mem = instance
+        .unwrap()
+        .get_memory(&mut plugin.store, "memory")
+        .unwrap();
+    assert!(mem.data(&plugin.store).iter().all(|x| *x == 0));
+}
+
+#[test]
+fn test_zeroize_memory_on_reset() {
+    fn kernel_contains(plugin: &Plugin, needle: &[u8]) -> bool {
+        let mem = plugin.current_plugin().kernel.memory.unwrap();
+        mem.data(&plugin.store)
+            .windows(needle.len())
+            .any(|x| x == needle)
+    }
+
+    let mut plugin = PluginBuilder::new(WASM_NO_FUNCTIONS)
+        .with_zeroize_memory(true)
+        .build()
+        .unwrap();
+    let output: String = plugin.call("count_vowels", "aaaa").unwrap();
+    assert!(kernel_contains(&plugin, output.as_bytes()));
+
+    // The output of the last call is removed when the next call starts
+    let next: String = plugin.call("count_vowels", "b").unwrap();
+    assert_ne!(output, next);
+    assert!(!kernel_contains(&plugin, output.as_bytes()));
+
+    // And when the plugin is reset
+    plugin.reset().unwrap();
+    assert!(!kernel_contains(&plugin, next.as_bytes()));
+    let Json(count): Json<Count> = plugin.call("count_vowels", "aa").unwrap();
+    assert_eq!(count.count, 2);
+}
+
+#[test]
+fn test_log_handler() {
+    // Logs the input at the info level, after checking the log level
+    let wasm = br#"
+        (module
+            (import "extism:host/env" "log_info" (func $log_info (param i64)))
+            (import "extism:host/env" "get_log_level" (func $get_log_level (result i32)))
+            (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+            (import "extism:host/env" "input_length" (func $input_length (result i64)))
+            (import "extism:host/env" "input_load_u8" (func $input_load_u8 (param i64) (result i32)))
+            (import "extism:host/env" "store_u8" (func $store_u8 (param i64 i32)))
+            (func (export "run") (result i32)
+                (local $h i64) (local $i i64)
+                (if (i32.gt_s (call $get_log_level) (i32.const 2))
+                    (then (return (i32.const 0))))
+                (local.set $h (call $alloc (call $input_length)))
+                (block $done
+                    (loop $next
+                        (br_if $done (i64.ge_u (local.get $i) (call $input_length)))
+                        (call $store_u8
+                            (i64.add (local.get $h) (local.get $i))
+                            (call $input_load_u8 (local.get $i)))
+                        (local.set $i (i64.add (local.get $i) (i64.const 1)))
+                        (br $next)))
+                (call $log_info (local.get $h))
+                i32.const 0)
+        )
+    "#;
+    let records = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let r = records.clone();
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_log_handler(move |record: &LogRecord| {
+            r.lock().unwrap().push((
+                record.plugin,
+                record.function.to_string(),
+                record.level,
+                record.message.to_string(),
+            ));
+        })
+        .with_redactor(|_: RedactTarget, data: &str| data.replace("secret", "******"))
+        .build()
+        .unwrap();
+    plugin.call::<&str, ()>("run", "hello secret").unwrap();
+
+    let records = records.lock().unwrap();
+    assert_eq!(
+        *records,
+        vec![(
+            plugin.id,
+            "run".to_string(),
+            tracing::Level::INFO,
+            "hello ******".to_string()
+        )]
+    );
+}
+
+#[test]
+fn test_redactor() {
+    let wasm = br#"
+        (module
+            (import "extism:host/user" "charge" (func $charge))
+            (func (export "run") (result i32)
+                (call $charge)
+                i32.const 0)
+        )
+    "#;
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_function("charge", [], [], UserData::new(()), |_, _, _, _| {
+            anyhow::bail!("card 4111-1111-1111-1111 was declined")
+        })
+        .with_redactor(|target: RedactTarget, data: &str| {
+            assert!(matches!(target, RedactTarget::Error { function: "run" }));
+            data.replace("4111-1111-1111-1111", "****")
+        })
+        .build()
+        .unwrap();
+    let err = plugin
+        .call::<&str, &str>("run", "")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("card **** was declined"), "{err}");
+    assert!(!err.contains("4111"));
+}
+
+#[test]
+fn test_stall_watchdog() {
+    let wasm = br#"
+        (module
+            (func $spin (loop $l (br $l)))
+            (func (export "run") (result i32)
+                (call $spin)
+                i32.const 0)
+        )
+    "#;
+    let events = EventBus::new();
+    let rx = events.subscribe();
+    let manifest =
+        Manifest::new([Wasm::data(wasm.to_vec())]).with_timeout(std::time::Duration::from_secs(1));
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_stall_threshold(std::time::Duration::from_millis(100))
+        .with_fuel_limit(u64::MAX)
+        .with_event_bus(events)
+        .build()
+        .unwrap();
+
+    let start = std::time::Instant::now();
+    let err = plugin.call::<&str, &[u8]>("run", "").unwrap_err();
+    assert_eq!(err.root_cause().to_string(), "timeout");
+    assert!(start.elapsed() >= std::time::Duration::from_secs(1));
+
+    let events: Vec<PluginEvent> = rx.try_iter().collect();
+    let stalls: Vec<&PluginEvent> = events
+        .iter()
+        .filter(|e| matches!(e, PluginEvent::Stalled { .. }))
+        .collect();
+    assert_eq!(stalls.len(), 1);
+    let PluginEvent::Stalled {
+        function,
+        elapsed,
+        fuel_consumed,
+        backtrace,
+        ..
+    } = stalls[0]
+    else {
+        unreachable!()
+    };
+    assert_eq!(function, "run");
+    assert!(*elapsed >= std::time::Duration::from_millis(100));
+    assert!(fuel_consumed.unwrap() > 0);
+    assert!(backtrace.contains("spin"), "{backtrace}");
+    assert!(events
+        .iter()
+        .any(|e| matches!(e, PluginEvent::Timeout { .. })));
+
+    // Cancelling still interrupts the call
+    let handle = plugin.cancel_handle();
+    std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        handle.cancel().unwrap();
+    });
+    let err = plugin.call::<&str, &[u8]>("run", "").unwrap_err();
+    assert!(err.is::<Cancelled>());
+}
+
+#[cfg(feature = "wasi-nn")]
+#[test]
+fn test_wasi_nn() {
+    let wasm = br#"
+        (module
+            (import "wasi_ephemeral_nn" "load_by_name"
+                (func $load_by_name (param i32 i32 i32) (result i32)))
+            (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+            (import "extism:host/env" "store_u64" (func $store_u64 (param i64 i64)))
+            (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+            (memory (export "memory") 1)
+            (data (i32.const 0) "missing")
+            (func (export "run") (result i32)
+                (local $out i64)
+                (local.set $out (call $alloc (i64.const 8)))
+                (call $store_u64 (local.get $out) (i64.extend_i32_u
+                    (call $load_by_name (i32.const 0) (i32.const 7) (i32.const 16))))
+                (call $output_set (local.get $out) (i64.const 8))
+                i32.const 0)
+        )
+    "#;
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_wasi_nn(WasiNn::new())
+        .build()
+        .unwrap();
+    let errno: &[u8] = plugin.call("run", "").unwrap();
+    assert_ne!(u64::from_le_bytes(errno.try_into().unwrap()), 0);
+
+    // Without `with_wasi_nn` the import can't be satisfied
+    assert!(
+        PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+            .build()
+            .is_err()
+    );
+
+    assert!(
+        PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+            .with_wasi_nn(WasiNn::new().with_graph("unknown", "/tmp"))
+            .build()
+            .is_err()
+    );
+}
+
+#[test]
+fn test_kv_store() {
+    let wasm = br#"
+        (module
+            (import "extism:host/kv" "get" (func $get (param i64) (result i64)))
+            (import "extism:host/kv" "set" (func $set (param i64 i64 i64)))
+            (import "extism:host/kv" "delete" (func $delete (param i64) (result i32)))
+            (import "extism:host/kv" "list" (func $list (param i64) (result i64)))
+            (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+            (import "extism:host/env" "length" (func $length (param i64) (result i64)))
+            (import "extism:host/env" "store_u8" (func $store_u8 (param i64 i32)))
+            (import "extism:host/env" "input_offset" (func $input_offset (result i64)))
+            (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+            (func $key (param $c i32) (result i64)
+                (local $k i64)
+                (local.set $k (call $alloc (i64.const 1)))
+                (call $store_u8 (local.get $k) (local.get $c))
+                (local.get $k))
+            (func $output (param $h i64)
+                (call $output_set (local.get $h) (call $length (local.get $h))))
+            (func (export "set") (result i32)
+                (call $set (call $key (i32.const 97)) (call $input_offset) (i64.const 0))
+                i32.const 0)
+            (func (export "set_ttl") (result i32)
+                (call $set (call $key (i32.const 98)) (call $input_offset) (i64.const 50))
+                i32.const 0)
+            (func (export "get") (result i32)
+                (call $output (call $get (call $key (i32.const 97))))
+                i32.const 0)
+            (func (export "list") (result i32)
+                (call $output (call $list (call $key (i32.const 97))))
+                i32.const 0)
+            (func (export "delete") (result i32)
+                (call $delete (call $key (i32.const 97))))
+        )
+    "#;
+    let store = MemoryKvStore::new();
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_kv_store(store.clone())
+        .build()
+        .unwrap();
+
+    let _: &[u8] = plugin.call("set", "hello").unwrap();
+    let _: &[u8] = plugin.call("set_ttl", "temporary").unwrap();
+    assert_eq!(store.get("a").unwrap().unwrap(), b"hello");
+    assert_eq!(store.list("").unwrap(), ["a", "b"]);
+
+    // Values are shared between plugins using the same store
+    let mut other = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_kv_store(store.clone())
+        .build()
+        .unwrap();
+    let value: &str = other.call("get", "").unwrap();
+    assert_eq!(value, "hello");
+    let keys: Vec<String> = other.call::<&str, Json<Vec<String>>>("list", "").unwrap().0;
+    assert_eq!(keys, ["a"]);
+
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert!(store.get("b").unwrap().is_none());
+
+    // `delete` returns 1 when the key existed
+    let err = plugin.call::<&str, &[u8]>("delete", "").unwrap_err();
+    assert_eq!(err.to_string(), "Returned non-zero exit code: 1");
+    assert!(store.get("a").unwrap().is_none());
+
+    // Counters are stored as decimal strings
+    assert_eq!(store.increment("n", 2).unwrap(), 2);
+    assert_eq!(store.increment("n", 3).unwrap(), 5);
+    assert_eq!(store.get("n").unwrap().unwrap(), b"5");
+    store.set("n", b"x", None).unwrap();
+    assert!(store.increment("n", 1).is_err());
+
+    // The namespace is only available when a store is configured
+    assert!(
+        PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+            .build()
+            .is_err()
+    );
+}
+
+#[test]
+fn test_memory_kv_store_expiry() {
+    let store = MemoryKvStore::new();
+    let ttl = Some(std::time::Duration::from_millis(10));
+    for i in 0..1000 {
+        store.set(&format!("temp{i}"), b"x", ttl).unwrap();
+    }
+    store.set("kept", b"y", None).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(50));
+
+    // Expired values aren't returned, and are removed when they're accessed
+    assert!(store.get("temp0").unwrap().is_none());
+    assert!(!store.delete("temp1").unwrap());
+    assert_eq!(store.list("").unwrap(), ["kept"]);
+    assert_eq!(store.data.lock().unwrap().values.len(), 999);
+
+    // Expired values that are never accessed again are swept once the store grows
+    for i in 0..100 {
+        store.set(&format!("new{i}"), b"z", None).unwrap();
+    }
+    assert_eq!(store.data.lock().unwrap().values.len(), 101);
+    assert_eq!(store.get("kept").unwrap().unwrap(), b"y");
+}
+
+#[test]
+fn test_sql_database() {
+    let wasm = br#"
+        (module
+            (import "extism:host/sql" "query" (func $query (param i64) (result i64)))
+            (import "extism:host/sql" "execute" (func $execute (param i64) (result i64)))
+            (import "extism:host/env" "length" (func $length (param i64) (result i64)))
+            (import "extism:host/env" "input_offset" (func $input_offset (result i64)))
+            (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+            (func (export "query") (result i32)
+                (local $h i64)
+                (local.set $h (call $query (call $input_offset)))
+                (call $output_set (local.get $h) (call $length (local.get $h)))
+                i32.const 0)
+            (func (export "execute") (result i32)
+                (i32.wrap_i64 (call $execute (call $input_offset))))
+        )
+    "#;
+
+    struct Fake(std::sync::Mutex<Vec<(String, String, Vec<SqlValue>)>>);
+    impl SqlDatabase for Fake {
+        fn query(&self, namespace: &str, sql: &str, params: &[SqlValue]) -> Result<SqlRows, Error> {
+            self.0
+                .lock()
+                .unwrap()
+                .push((namespace.to_string(), sql.to_string(), params.to_vec()));
+            Ok(SqlRows {
+                columns: vec!["id".to_string(), "name".to_string()],
+                rows: vec![vec![SqlValue::Integer(1), SqlValue::Text("a".to_string())]],
+            })
+        }
+
+        fn execute(&self, _namespace: &str, _sql: &str, params: &[SqlValue]) -> Result<u64, Error> {
+            Ok(params.len() as u64)
+        }
+    }
+
+    let db = std::sync::Arc::new(Fake(Default::default()));
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_sql_database(db.clone(), "tenant_1")
+        .build()
+        .unwrap();
+
+    let rows: Json<SqlRows> = plugin
+        .call(
+            "query",
+            r#"{"sql": "SELECT * FROM t WHERE id = ?", "params": [1, "x", null, true, 1.5]}"#,
+        )
+        .unwrap();
+    assert_eq!(rows.0.columns, ["id", "name"]);
+    assert_eq!(
+        rows.0.rows,
+        [[SqlValue::Integer(1), SqlValue::Text("a".to_string())]]
+    );
+    assert_eq!(
+        db.0.lock().unwrap()[0],
+        (
+            "tenant_1".to_string(),
+            "SELECT * FROM t WHERE id = ?".to_string(),
+            vec![
+                SqlValue::Integer(1),
+                SqlValue::Text("x".to_string()),
+                SqlValue::Null,
+                SqlValue::Bool(true),
+                SqlValue::Real(1.5)
+            ]
+        )
+    );
+
+    // `execute` returns the number of rows affected
+    let err = plugin
+        .call::<&str, &[u8]>("execute", r#"{"sql": "DELETE FROM t", "params": [1, 2]}"#)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Returned non-zero exit code: 2");
+
+    // Namespaces are validated when the plugin is created
+    assert!(
+        PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+            .with_sql_database(db.clone(), "public; DROP SCHEMA x")
+            .build()
+            .is_err()
+    );
+}
+
+#[cfg(feature = "sql-sqlite")]
+#[test]
+fn test_sqlite_database() {
+    let db = std::sync::Arc::new(SqliteDatabase::in_memory());
+    db.execute("a", "CREATE TABLE t (id INTEGER, name TEXT)", &[])
+        .unwrap();
+    let n = db
+        .execute(
+            "a",
+            "INSERT INTO t VALUES (?, ?), (?, ?)",
+            &[
+                SqlValue::Integer(1),
+                SqlValue::Text("x".to_string()),
+                SqlValue::Integer(2),
+                SqlValue::Null,
+            ],
+        )
+        .unwrap();
+    assert_eq!(n, 2);
+
+    let rows = db
+        .query("a", "SELECT * FROM t WHERE id > ?", &[SqlValue::Integer(0)])
+        .unwrap();
+    assert_eq!(rows.columns, ["id", "name"]);
+    assert_eq!(
+        rows.rows,
+        [
+            [SqlValue::Integer(1), SqlValue::Text("x".to_string())],
+            [SqlValue::Integer(2), SqlValue::Null]
+        ]
+    );
+
+    // Other namespaces can't see the table
+    assert!(db.query("b", "SELECT * FROM t", &[]).is_err());
+}
+
+#[cfg(feature = "sql-sqlite")]
+#[test]
+fn test_sqlite_database_isolation() {
+    let dir = std::env::temp_dir().join(format!("extism-sql-{}", uuid::Uuid::new_v4()));
+    let db = SqliteDatabase::open(&dir).unwrap();
+    db.execute("b", "CREATE TABLE secret (x TEXT)", &[])
+        .unwrap();
+    db.execute("b", "INSERT INTO secret VALUES ('b')", &[])
+        .unwrap();
+
+    // Namespaces can't attach other databases, or run pragmas
+    let other = dir.join("b.db").display().to_string();
+    for sql in [
+        format!("ATTACH DATABASE '{other}' AS other"),
+        "ATTACH DATABASE ':memory:' AS other".to_string(),
+        "DETACH DATABASE main".to_string(),
+        "PRAGMA database_list".to_string(),
+        "PRAGMA journal_mode = OFF".to_string(),
+    ] {
+        assert!(db.execute("a", &sql, &[]).is_err(), "{sql}");
+        assert!(db.query("a", &sql, &[]).is_err(), "{sql}");
+    }
+    assert!(db.query("a", "SELECT * FROM other.secret", &[]).is_err());
+    assert_eq!(
+        db.query("b", "SELECT * FROM secret", &[]).unwrap().rows,
+        [[SqlValue::Text("b".to_string())]]
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "sql-postgres")]
+#[test]
+fn test_postgres_statement_check() {
+    use crate::sql::check_postgres_statement as check;
+
+    for sql in [
+        "SELECT * FROM t WHERE a = $1",
+        "INSERT INTO t VALUES ('a;b', 'it''s', $$ ; SET ROLE x $$, $x$;$x$)",
+        "UPDATE t SET a = 1 -- ; COMMIT",
+        "SELECT \"set;\" FROM t /* ; RESET ROLE /* nested */ ; */;",
+        "CREATE TABLE t (a INTEGER)",
+        "select e'\\'' from t",
+    ] {
+        assert!(check(sql).is_ok(), "{sql}");
+    }
+
+    for sql in [
+        "COMMIT; SELECT 1",
+        "SELECT 1; SELECT 2",
+        "SET search_path TO public",
+        "set role postgres",
+        "RESET ROLE",
+        "RESET search_path",
+        "  Begin",
+        "ROLLBACK",
+        "DO $$ BEGIN EXECUTE 'SET ROLE postgres'; END $$",
+        "SELECT set_config('role', 'postgres', true)",
+        "SELECT pg_catalog.SET_CONFIG('search_path', 'public', true)",
+        "CREATE OR REPLACE FUNCTION f() RETURNS void AS 'SET ROLE x' LANGUAGE sql",
+        "SELECT E'\\''; SET ROLE postgres; --'",
+        "SELECT $a$ x $a$; COMMIT",
+        "SELECT 'unterminated",
+    ] {
+        assert!(check(sql).is_err(), "{sql}");
+    }
+}
+
+#[test]
+fn test_object_store() {
+    let wasm = br#"
+        (module
+            (import "extism:host/object" "get" (func $get (param i64) (result i64)))
+            (import "extism:host/object" "get_range" (func $get_range (param i64 i64 i64) (result i64)))
+            (import "extism:host/object" "size" (func $size (param i64) (result i64)))
+            (import "extism:host/object" "put" (func $put (param i64 i64)))
+            (import "extism:host/object" "list" (func $list (param i64) (result i64)))
+            (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+            (import "extism:host/env" "length" (func $length (param i64) (result i64)))
+            (import "extism:host/env" "store_u8" (func $store_u8 (param i64 i32)))
+            (import "extism:host/env" "input_offset" (func $input_offset (result i64)))
+            (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+            (func $key (param $c i32) (result i64)
+                (local $k i64)
+                (local.set $k (call $alloc (i64.const 1)))
+                (call $store_u8 (local.get $k) (local.get $c))
+                (local.get $k))
+            (func $output (param $h i64)
+                (call $output_set (local.get $h) (call $length (local.get $h))))
+            (func (export "put") (result i32)
+                (call $put (call $key (i32.const 97)) (call $input_offset))
+                i32.const 0)
+            (func (export "get") (result i32)
+                (call $output (call $get (call $key (i32.const 97))))
+                i32.const 0)
+            (func (export "get_range") (result i32)
+                (call $output (call $get_range (call $key (i32.const 97)) (i64.const 2) (i64.const 3)))
+                i32.const 0)
+            (func (export "size") (result i32)
+                (i32.wrap_i64 (call $size (call $key (i32.const 97)))))
+            (func (export "list") (result i32)
+                (call $output (call $list (call $alloc (i64.const 0))))
+                i32.const 0)
+            (func (export "traversal") (result i32)
+                (call $output (call $get (call $input_offset)))
+                i32.const 0)
+        )
+    "#;
+    let store = MemoryObjectStore::new();
+    store.put("tenant-b/a", b"other").unwrap();
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_object_store(store.clone(), "tenant-a/")
+        .build()
+        .unwrap();
+
+    let _: &[u8] = plugin.call("put", "hello world").unwrap();
+    assert_eq!(store.get("tenant-a/a").unwrap().unwrap(), b"hello world");
+
+    let value: &str = plugin.call("get", "").unwrap();
+    assert_eq!(value, "hello world");
+    let value: &str = plugin.call("get_range", "").unwrap();
+    assert_eq!(value, "llo");
+    let err = plugin.call::<&str, &[u8]>("size", "").unwrap_err();
+    assert_eq!(err.to_string(), "Returned non-zero exit code: 11");
+
+    // Keys are relative to the plugin's prefix
+    let keys: Vec<String> = plugin
+        .call::<&str, Json<Vec<String>>>("list", "")
+        .unwrap()
+        .0;
+    assert_eq!(keys, ["a"]);
+    assert!(plugin
+        .call::<&str, &[u8]>("traversal", "../tenant-b/a")
+        .is_err());
+}
+
+#[test]
+fn test_message_broker() {
+    let wasm = br#"
+        (module
+            (import "extism:host/msg" "publish" (func $publish (param i64 i64)))
+            (import "extism:host/msg" "subscribe" (func $subscribe (param i64) (result i64)))
+            (import "extism:host/msg" "poll" (func $poll (param i64 i64) (result i64)))
+            (import "extism:host/msg" "unsubscribe" (func $unsubscribe (param i64)))
+            (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+            (import "extism:host/env" "length" (func $length (param i64) (result i64)))
+            (import "extism:host/env" "store_u8" (func $store_u8 (param i64 i32)))
+            (import "extism:host/env" "input_offset" (func $input_offset (result i64)))
+            (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+            (global $sub (mut i64) (i64.const 0))
+            (func $topic (result i64)
+                (local $k i64)
+                (local.set $k (call $alloc (i64.const 1)))
+                (call $store_u8 (local.get $k) (i32.const 116))
+                (local.get $k))
+            (func (export "subscribe") (result i32)
+                (global.set $sub (call $subscribe (call $topic)))
+                i32.const 0)
+            (func (export "publish") (result i32)
+                (call $publish (call $topic) (call $input_offset))
+                i32.const 0)
+            (func (export "poll") (result i32)
+                (local $h i64)
+                (local.set $h (call $poll (global.get $sub) (i64.const 0)))
+                (if (i64.eqz (local.get $h)) (then (return (i32.const 1))))
+                (call $output_set (local.get $h) (call $length (local.get $h)))
+                i32.const 0)
+            (func (export "unsubscribe") (result i32)
+                (call $unsubscribe (global.get $sub))
+                i32.const 0)
+            (func (export "wait") (result i32)
+                (drop (call $poll (global.get $sub) (i64.const 5000)))
+                i32.const 0)
+        )
+    "#;
+    let broker = MemoryBroker::new();
+    let mut host_sub = broker.subscribe("t").unwrap();
+    let mut a = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_message_broker(broker.clone())
+        .build()
+        .unwrap();
+    let mut b = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_message_broker(broker.clone())
+        .build()
+        .unwrap();
+
+    let _: &[u8] = b.call("subscribe", "").unwrap();
+    let _: &[u8] = a.call("publish", "hello").unwrap();
+    let msg: &str = b.call("poll", "").unwrap();
+    assert_eq!(msg, "hello");
+    assert!(b.call::<&str, &[u8]>("poll", "").is_err());
+
+    // The host can subscribe and publish using the same broker
+    let payload = host_sub.poll(std::time::Duration::ZERO).unwrap();
+    assert_eq!(payload.unwrap(), b"hello");
+    broker.publish("t", b"from host").unwrap();
+    let msg: &str = b.call("poll", "").unwrap();
+    assert_eq!(msg, "from host");
+
+    let _: &[u8] = b.call("unsubscribe", "").unwrap();
+    assert!(b.call::<&str, &[u8]>("poll", "").is_err());
+
+    // `poll` doesn't wait for longer than the manifest timeout, even when the guest timeout is longer
+    let manifest = Manifest::new([Wasm::data(wasm.to_vec())])
+        .with_timeout(std::time::Duration::from_millis(200));
+    let mut c = PluginBuilder::new(manifest)
+        .with_message_broker(broker.clone())
+        .build()
+        .unwrap();
+    let _: &[u8] = c.call("subscribe", "").unwrap();
+    let start = std::time::Instant::now();
+    let _ = c.call::<&str, &[u8]>("wait", "");
+    assert!(start.elapsed() < std::time::Duration::from_secs(2));
+}
+
+#[cfg(feature = "wit")]
+#[test]
+fn test_wit_generate() {
+    // Regenerate `data/wit/greeter.rs` when the generator output changes
+    let wit = include_str!("data/wit/greeter.wit");
+    let bindings = crate::wit::generate(wit, None).unwrap();
+    assert_eq!(bindings, include_str!("data/wit/greeter.rs"));
+
+    assert!(crate::wit::generate(wit, Some("missing")).is_err());
+    assert!(crate::wit::generate("package example:bad; world w { export f: func(", None).is_err());
+}
+
+#[cfg(feature = "wit")]
+#[allow(dead_code)]
+mod greeter {
+    include!("data/wit/greeter.rs");
+}
+
+#[cfg(feature = "wit")]
+#[test]
+fn test_wit_bindings() {
+    use greeter::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Host {
+        logs: Mutex<Vec<(Level, String)>>,
+    }
+
+    impl GreeterImports for Arc<Host> {
+        fn logging_log(&self, level: Level, message: String) -> Result<(), Error> {
+            self.logs.lock().unwrap().push((level, message));
+            Ok(())
+        }
+
+        fn lookup(&self, key: String) -> Result<Option<String>, Error> {
+            Ok((key == "bob").then(|| "Bob".to_string()))
+        }
+    }
+
+    let wasm = br#"
+        (module
+            (import "extism:host/user" "lookup" (func $lookup (param i64) (result i64)))
+            (import "example:greeter/logging" "log" (func $log (param i64 i64)))
+            (import "extism:host/env" "length" (func $length (param i64) (result i64)))
+            (import "extism:host/env" "input_offset" (func $input_offset (result i64)))
+            (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+            (func (export "greet") (result i32)
+                (local $h i64)
+                (local.set $h (call $lookup (call $input_offset)))
+                (call $output_set (local.get $h) (call $length (local.get $h)))
+                i32.const 0)
+            (func (export "log") (result i32)
+                (call $log (call $input_offset) (call $input_offset))
+                i32.const 0)
+        )
+    "#;
+    let host = Arc::new(Host::default());
+    let builder = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]));
+    let plugin = Greeter::add_imports(builder, host.clone()).build().unwrap();
+    let mut greeter = Greeter::new(plugin);
+
+    // `greet` returns the JSON encoded result of `lookup`
+    assert_eq!(greeter.greet("bob").unwrap(), "\"Bob\"");
+    assert_eq!(greeter.greet("alice").unwrap(), "null");
+
+    let _: &[u8] = greeter.plugin().call("log", "\"warn\"").unwrap();
+    assert_eq!(
+        *host.logs.lock().unwrap(),
+        [(Level::Warn, "\"warn\"".to_string())]
+    );
+}
+
+#[test]
+fn test_wasi_clock_and_random() {
+    // Each function outputs a little-endian u64: the realtime clock, the monotonic clock after sleeping
+    // for one second using `poll_oneoff` and 8 random bytes
+    let wasm = br#"
+        (module
+            (import "wasi_snapshot_preview1" "clock_time_get"
+                (func $clock_time_get (param i32 i64 i32) (result i32)))
+            (import "wasi_snapshot_preview1" "poll_oneoff"
+                (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+            (import "wasi_snapshot_preview1" "random_get"
+                (func $random_get (param i32 i32) (result i32)))
+            (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+            (import "extism:host/env" "store_u64" (func $store_u64 (param i64 i64)))
+            (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+            (memory (export "memory") 1)
+            (func $output (param $value i64) (result i32)
+                (local $h i64)
+                (local.set $h (call $alloc (i64.const 8)))
+                (call $store_u64 (local.get $h) (local.get $value))
+                (call $output_set (local.get $h) (i64.const 8))
+                i32.const 0)
+            (func $clock (param $id i32) (result i64)
+                (drop (call $clock_time_get (local.get $id) (i64.const 1) (i32.const 0)))
+                (i64.load (i32.const 0)))
+            (func (export "now") (result i32)
+                (call $output (call $clock (i32.const 0))))
+            (func (export "sleep") (result i32)
+                ;; A relative clock subscription on the monotonic clock
+                (i32.store8 (i32.const 112) (i32.const 0))
+                (i32.store (i32.const 120) (i32.const 1))
+                (i64.store (i32.const 128) (i64.const 1000000000))
+                (i64.store (i32.const 136) (i64.const 0))
+                (i32.store16 (i32.const 144) (i32.const 0))
+                (drop (call $poll_oneoff (i32.const 104) (i32.const 200) (i32.const 1) (i32.const 300)))
+                (call $output (call $clock (i32.const 1))))
+            (func (export "random") (result i32)
+                (drop (call $random_get (i32.const 0) (i32.const 8)))
+                (call $output (i64.load (i32.const 0))))
+        )
+    "#;
+    let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+    let build = |seed| {
+        PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+            .with_wasi(true)
+            .with_wasi_clock(FixedClock::new(time))
+            .with_wasi_random(SeededRandom::new(seed))
+            .build()
+            .unwrap()
+    };
+    let call = |plugin: &mut Plugin, name| {
+        let output: &[u8] = plugin.call(name, "").unwrap();
+        u64::from_le_bytes(output.try_into().unwrap())
+    };
+
+    let mut plugin = build(1);
+    assert_eq!(call(&mut plugin, "now"), 1_000_000_000_000_000);
+
+    // Sleeping advances the clock without blocking
+    let start = std::time::Instant::now();
+    assert_eq!(call(&mut plugin, "sleep"), 1_000_000_000);
+    assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    assert_eq!(call(&mut plugin, "now"), 1_000_001_000_000_000);
+
+    // The same seed produces the same random values
+    let random = call(&mut plugin, "random");
+    assert_eq!(call(&mut build(1), "random"), random);
+    assert_ne!(call(&mut build(2), "random"), random);
+}
+
+#[test]
+fn test_deterministic() {
+    // Each function outputs a little-endian u64: the realtime clock, 8 random bytes and the bits of
+    // 0.0 / 0.0 as an f32
+    let wasm = br#"
+        (module
+            (import "wasi_snapshot_preview1" "clock_time_get"
+                (func $clock_time_get (param i32 i64 i32) (result i32)))
+            (import "wasi_snapshot_preview1" "random_get"
+                (func $random_get (param i32 i32) (result i32)))
+            (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+            (import "extism:host/env" "store_u64" (func $store_u64 (param i64 i64)))
+            (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+            (memory (export "memory") 1)
+            (global $zero (mut f32) (f32.const 0))
+            (func $output (param $value i64) (result i32)
+                (local $h i64)
+                (local.set $h (call $alloc (i64.const 8)))
+                (call $store_u64 (local.get $h) (local.get $value))
+                (call $output_set (local.get $h) (i64.const 8))
+                i32.const 0)
+            (func (export "now") (result i32)
+                (drop (call $clock_time_get (i32.const 0) (i64.const 1) (i32.const 0)))
+                (call $output (i64.load (i32.const 0))))
+            (func (export "random") (result i32)
+                (drop (call $random_get (i32.const 0) (i32.const 8)))
+                (call $output (i64.load (i32.const 0))))
+            (func (export "nan") (result i32)
+                (call $output (i64.extend_i32_u (i32.reinterpret_f32
+                    (f32.div (global.get $zero) (global.get $zero))))))
+        )
+    "#;
+    let build = |manifest: Manifest| {
+        PluginBuilder::new(manifest)
+            .with_wasi(true)
+            .with_deterministic(true)
+            .build()
+    };
+    let call = |plugin: &mut Plugin, name| {
+        let output: &[u8] = plugin.call(name, "").unwrap();
+        u64::from_le_bytes(output.try_into().unwrap())
+    };
+
+    let manifest = Manifest::new([Wasm::data(wasm.to_vec())]);
+    let mut plugin = build(manifest.clone()).unwrap();
+    assert_eq!(call(&mut plugin, "now"), 0);
+    assert_eq!(call(&mut plugin, "nan"), 0x7fc00000);
+
+    // Every plugin gets the same random values
+    let random = call(&mut plugin, "random");
+    assert_eq!(
+        call(&mut build(manifest.clone()).unwrap(), "random"),
+        random
+    );
+
+    let err = build(manifest.with_allowed_host("example.com"))
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("allowed_hosts"));
+}
+
+#[cfg(feature = "wasi-http")]
+#[tokio::test]
+async fn test_wasi_http_handler() {
+    use http_body_util::{BodyExt, Full};
+
+    // Echoes the request body, with the method and path in the `x-method` and `x-path` headers
+    let wasm = include_bytes!("../../../wasm/http_handler.wasm");
+    let handler = HttpHandler::new(&Manifest::new([Wasm::data(wasm.to_vec())])).unwrap();
+
+    let request = |path: &str, body: &'static str| {
+        hyper::Request::post(format!("http://localhost{path}"))
+            .body(Full::new(hyper::body::Bytes::from(body)))
+            .unwrap()
+    };
+
+    let res = handler.handle(request("/echo?x=1", "hello")).await.unwrap();
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["x-method"], "Method::Post");
+    assert_eq!(res.headers()["x-path"], "/echo?x=1");
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "hello");
+
+    let res = handler.handle(request("/missing", "")).await.unwrap();
+    assert_eq!(res.status(), 404);
+
+    // `/clock` returns the time, a random number and how long a 1 second sleep took
+    let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+    let clock_handler = |seed| {
+        handler
+            .clone()
+            .with_clock(FixedClock::new(time))
+            .with_random(SeededRandom::new(seed))
+    };
+    let start = std::time::Instant::now();
+    let res = clock_handler(1)
+        .handle(request("/clock", ""))
+        .await
+        .unwrap();
+    assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    assert_eq!(res.headers()["x-now"], "1000000");
+    assert_eq!(res.headers()["x-slept"], "1000000000");
+    let random = res.headers()["x-random"].clone();
+    let res = clock_handler(1)
+        .handle(request("/clock", ""))
+        .await
+        .unwrap();
+    assert_eq!(res.headers()["x-random"], random);
+    let res = clock_handler(2)
+        .handle(request("/clock", ""))
+        .await
+        .unwrap();
+    assert_ne!(res.headers()["x-random"], random);
+
+    // Requests that run for longer than the timeout are stopped, even when the component never yields
+    let manifest = Manifest::new([Wasm::data(wasm.to_vec())])
+        .with_timeout(std::time::Duration::from_millis(100));
+    let limited = HttpHandler::new(&manifest).unwrap();
+    let start = std::time::Instant::now();
+    let err = limited.handle(request("/loop", "")).await.unwrap_err();
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    assert!(format!("{err:?}").contains("timeout"), "{err:?}");
+    assert_eq!(
+        limited.handle(request("/echo", "")).await.unwrap().status(),
+        200
+    );
+
+    // `/alloc` uses 64MiB of memory, which is more than `max_pages` allows
+    assert_eq!(
+        handler
+            .handle(request("/alloc", ""))
+            .await
+            .unwrap()
+            .status(),
+        200
+    );
+    let limited =
+        HttpHandler::new(&Manifest::new([Wasm::data(wasm.to_vec())]).with_memory_max(256)).unwrap();
+    assert!(limited.handle(request("/alloc", "")).await.is_err());
+    assert_eq!(
+        limited.handle(request("/echo", "")).await.unwrap().status(),
+        200
+    );
+
+    // Core modules aren't components
+    let wasm = br#"(module (func (export "handle")))"#;
+    assert!(HttpHandler::new(&Manifest::new([Wasm::data(wasm.to_vec())])).is_err());
+}
+
+#[cfg(feature = "wasi-keyvalue")]
+#[tokio::test]
+async fn test_wasi_keyvalue() {
+    use http_body_util::{BodyExt, Full};
+
+    // `/count` increments `hits` in the `counters` bucket, `/set` sets `key=value` from the body,
+    // `/keys` lists keys, `/batch` uses the batch functions and any other path gets that key
+    let wasm = include_bytes!("../../../wasm/keyvalue_handler.wasm");
+    let manifest = Manifest::new([Wasm::data(wasm.to_vec())]);
+    let store = MemoryKvStore::new();
+    let handler = HttpHandler::new(&manifest)
+        .unwrap()
+        .with_kv_store(store.clone());
+
+    let call = |path: &str, body: &'static str| {
+        let handler = handler.clone();
+        let req = hyper::Request::post(format!("http://localhost{path}"))
+            .body(Full::new(hyper::body::Bytes::from(body)))
+            .unwrap();
+        async move {
+            let res = handler.handle(req).await.unwrap();
+            let status = res.status();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        }
+    };
+
+    assert_eq!(call("/count", "").await.1, "1");
+    assert_eq!(call("/count", "").await.1, "2");
+    assert_eq!(store.get("counters/hits").unwrap(), Some(b"2".to_vec()));
+
+    // Keys in the default bucket are shared with `extism:host/kv` plugins using the same store
+    store.set("shared", b"from extism", None).unwrap();
+    assert_eq!(call("/shared", "").await.1, "from extism");
+    call("/set", "a=1").await;
+    assert_eq!(store.get("a").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(call("/keys", "").await.1, "a,counters/hits,shared");
+
+    assert_eq!(call("/batch", "").await.1, "x=1,,y=2;false");
+    assert_eq!(store.get("y").unwrap(), Some(b"2".to_vec()));
+
+    // Without a store the component gets `no-such-store`
+    let handler = HttpHandler::new(&manifest).unwrap();
+    let req = hyper::Request::get("http://localhost/count")
+        .body(Full::new(hyper::body::Bytes::new()))
+        .unwrap();
+    let res = handler.handle(req).await.unwrap();
+    assert_eq!(res.status(), 500);
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "Error::NoSuchStore");
+}
+
+#[test]
+fn test_interpreter_backend() {
+    assert_eq!(
+        "interpreter".parse::<Backend>().unwrap(),
+        Backend::Interpreter
+    );
+    assert!("unknown".parse::<Backend>().is_err());
+
+    let builder = PluginBuilder::new(Manifest::new([Wasm::data(WASM_NO_FUNCTIONS)]))
+        .with_wasi(true)
+        .with_backend(Backend::Interpreter);
+    if cfg!(not(feature = "interpreter")) {
+        let err = builder.build().err().unwrap();
+        assert!(err.to_string().contains("`interpreter` feature"));
+        return;
+    }
+
+    // The output matches the compiled plugin, including timeouts which use epoch interruption
+    let mut plugin = builder.build().unwrap();
+    let output: String = plugin.call("count_vowels", "hello world").unwrap();
+    assert_eq!(output, r#"{"count":3,"total":3,"vowels":"aeiouAEIOU"}"#);
+
+    let manifest =
+        Manifest::new([Wasm::data(WASM_LOOP)]).with_timeout(std::time::Duration::from_millis(100));
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_wasi(true)
+        .with_backend(Backend::Interpreter)
+        .build()
+        .unwrap();
+    assert!(plugin.call::<&str, &[u8]>("loop_forever", "").is_err());
+}
+
+#[test]
+fn test_sockets() {
+    use std::io::Read;
+
+    // Echo the first message received on each connection
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut buf = [0; 64];
+            let n = stream.read(&mut buf).unwrap();
+            stream.write_all(&buf[..n]).unwrap();
+        }
+    });
+
+    // Connects to the address passed as input, sends the address and outputs the response
+    let wasm = br#"
+        (module
+            (import "extism:host/net" "connect" (func $connect (param i64) (result i64)))
+            (import "extism:host/net" "send" (func $send (param i64 i64) (result i64)))
+            (import "extism:host/net" "recv" (func $recv (param i64 i64 i64) (result i64)))
+            (import "extism:host/net" "close" (func $close (param i64)))
+            (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+            (import "extism:host/env" "length" (func $length (param i64) (result i64)))
+            (import "extism:host/env" "input_length" (func $input_length (result i64)))
+            (import "extism:host/env" "input_offset" (func $input_offset (result i64)))
+            (import "extism:host/env" "input_load_u8" (func $input_load_u8 (param i64) (result i32)))
+            (import "extism:host/env" "store_u8" (func $store_u8 (param i64 i32)))
+            (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+            (func $copy_input (result i64)
+                (local $h i64) (local $i i64)
+                (local.set $h (call $alloc (call $input_length)))
+                (block $done
+                    (loop $next
+                        (br_if $done (i64.ge_u (local.get $i) (call $input_length)))
+                        (call $store_u8
+                            (i64.add (local.get $h) (local.get $i))
+                            (call $input_load_u8 (local.get $i)))
+                        (local.set $i (i64.add (local.get $i) (i64.const 1)))
+                        (br $next)))
+                (local.get $h))
+            (func (export "echo") (result i32)
+                (local $id i64) (local $res i64)
+                (local.set $id (call $connect (call $copy_input)))
+                (drop (call $send (local.get $id) (call $input_offset)))
+                (local.set $res (call $recv (local.get $id) (i64.const 64) (i64.const 5000)))
+                (call $close (local.get $id))
+                (call $output_set (local.get $res) (call $length (local.get $res)))
+                i32.const 0)
+        )
+    "#;
+    let manifest = Manifest::new([Wasm::data(wasm.to_vec())]).with_allowed_socket("127.0.0.1:*");
+    let mut plugin = Plugin::new(&manifest, [], false).unwrap();
+    let output: String = plugin.call("echo", &addr).unwrap();
+    assert_eq!(output, addr);
+
+    let err = plugin
+        .call::<&str, &str>("echo", "localhost:22")
+        .unwrap_err();
+    assert!(format!("{err:?}").contains("connection to localhost:22 is not allowed"));
+
+    // `recv` doesn't wait for longer than the manifest timeout, even when the guest timeout is longer
+    let silent = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let silent_addr = silent.local_addr().unwrap().to_string();
+    let mut plugin = Plugin::new(
+        manifest.with_timeout(std::time::Duration::from_millis(200)),
+        [],
+        false,
+    )
+    .unwrap();
+    let start = std::time::Instant::now();
+    let err = plugin.call::<&str, &str>("echo", &silent_addr).unwrap_err();
+    assert!(start.elapsed() < std::time::Duration::from_secs(2));
+    assert_eq!(ErrorKind::of(&err), ErrorKind::Timeout, "{err:?}");
+    drop(silent);
+
+    // The namespace is only available when `allowed_sockets` is set
+    assert!(Plugin::new(Manifest::new([Wasm::data(wasm.to_vec())]), [], false).is_err());
+}
+
+#[cfg(feature = "register-oci")]
+#[test]
+fn test_oci() {
+    use sha2::Digest;
+    use std::io::Read;
+
+    let dir = std::env::temp_dir().join(format!("extism-oci-{}", uuid::Uuid::new_v4()));
+    std::env::set_var("EXTISM_OCI_CACHE_DIR", &dir);
+
+    let digest = |data: &[u8]| {
+        format!(
+            "sha256:{}",
+            crate::manifest::hex(&sha2::Sha256::digest(data))
+        )
+    };
+    let layer = digest(WASM_NO_FUNCTIONS);
+    let oci_manifest = serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "layers": [{
+            "mediaType": "application/vnd.wasm.content.layer.v1+wasm",
+            "digest": layer,
+            "size": WASM_NO_FUNCTIONS.len(),
+        }],
+    })
+    .to_string();
+    let manifest_digest = digest(oci_manifest.as_bytes());
+
+    // A registry that requires a token from its token endpoint
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let requests = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let count = requests.clone();
+    let realm = format!("http://{addr}/token");
+    let blob = format!("/v2/org/plugin/blobs/{layer}");
+    let pinned = format!("/v2/org/plugin/manifests/{manifest_digest}");
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut req = vec![];
+            let mut buf = [0; 1024];
+            while !req.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                req.extend_from_slice(&buf[..n]);
+            }
+            count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let req = String::from_utf8_lossy(&req).to_lowercase();
+            let path = req.split(' ').nth(1).unwrap_or_default().to_string();
+            let authorized = req.contains("authorization: bearer secret");
+            let (status, headers, body): (&str, String, Vec<u8>) = if path.starts_with("/token") {
+                ("200 OK", String::new(), br#"{"token":"secret"}"#.to_vec())
+            } else if !authorized {
+                (
+                    "401 Unauthorized",
+                    format!(
+                        "www-authenticate: Bearer realm=\"{realm}\",service=\"test\",scope=\"repository:org/plugin:pull\"\r\n"
+                    ),
+                    vec![],
+                )
+            } else if path == "/v2/org/plugin/manifests/1.0" || path == pinned {
+                ("200 OK", String::new(), oci_manifest.as_bytes().to_vec())
+            } else if path == blob {
+                ("200 OK", String::new(), WASM_NO_FUNCTIONS.to_vec())
+            } else {
+                ("404 Not Found", String::new(), vec![])
+            };
+            let head = format!(
+                "HTTP/1.1 {status}\r\n{headers}content-length: {}\r\nconnection: close\r\n\r\n",
+                body.len()
+            );
+            stream.write_all(head.as_bytes()).unwrap();
+            stream.write_all(&body).unwrap();
+        }
+    });
+
+    let manifest = Manifest::new([Wasm::oci(format!("{addr}/org/plugin:1.0"))]);
+    let mut plugin = Plugin::new(&manifest, [], false).unwrap();
+    let Json(count): Json<Count> = plugin.call("count_vowels", "aaa").unwrap();
+    assert_eq!(count.count, 3);
+    assert!(dir.join("sha256").join(&layer[7..]).exists());
+
+    // A module pinned by an unknown digest fails
+    let manifest = Manifest::new([Wasm::oci(format!(
+        "{addr}/org/plugin@sha256:{}",
+        "0".repeat(64)
+    ))]);
+    assert!(Plugin::new(&manifest, [], false).is_err());
+
+    // The token is sent directly when it's configured, and pinned manifests are cached
+    let manifest = Manifest::new([
+        Wasm::oci(format!("{addr}/org/plugin@{manifest_digest}")).with_registry_token("secret")
+    ]);
+    let before = requests.load(std::sync::atomic::Ordering::SeqCst);
+    Plugin::new(&manifest, [], false).unwrap();
+    assert_eq!(
+        requests.load(std::sync::atomic::Ordering::SeqCst),
+        before + 1
+    );
+    Plugin::new(&manifest, [], false).unwrap();
+    assert_eq!(
+        requests.load(std::sync::atomic::Ordering::SeqCst),
+        before + 1
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(feature = "websocket")]
+#[test]
+fn test_websockets() {
+    // Echo each message, prefixed with `echo: `
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut ws = tungstenite::accept(stream.unwrap()).unwrap();
+            while let Ok(msg) = ws.read() {
+                if let tungstenite::Message::Text(s) = msg {
+                    ws.send(format!("echo: {s}").into()).unwrap();
+                }
+            }
+        }
+    });
+
+    // Connects to the URL passed as input, sends the URL and outputs the response
+    let wasm = br#"
+        (module
+            (import "extism:host/ws" "connect" (func $connect (param i64) (result i64)))
+            (import "extism:host/ws" "send" (func $send (param i64 i64 i32)))
+            (import "extism:host/ws" "recv" (func $recv (param i64 i64) (result i64)))
+            (import "extism:host/ws" "close" (func $close (param i64)))
+            (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+            (import "extism:host/env" "length" (func $length (param i64) (result i64)))
+            (import "extism:host/env" "input_length" (func $input_length (result i64)))
+            (import "extism:host/env" "input_offset" (func $input_offset (result i64)))
+            (import "extism:host/env" "input_load_u8" (func $input_load_u8 (param i64) (result i32)))
+            (import "extism:host/env" "store_u8" (func $store_u8 (param i64 i32)))
+            (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+            (func $copy_input (result i64)
+                (local $h i64) (local $i i64)
+                (local.set $h (call $alloc (call $input_length)))
+                (block $done
+                    (loop $next
+                        (br_if $done (i64.ge_u (local.get $i) (call $input_length)))
+                        (call $store_u8
+                            (i64.add (local.get $h) (local.get $i))
+                            (call $input_load_u8 (local.get $i)))
+                        (local.set $i (i64.add (local.get $i) (i64.const 1)))
+                        (br $next)))
+                (local.get $h))
+            (func (export "echo") (result i32)
+                (local $id i64) (local $res i64)
+                (local.set $id (call $connect (call $copy_input)))
+                (call $send (local.get $id) (call $input_offset) (i32.const 0))
+                (local.set $res (call $recv (local.get $id) (i64.const 5000)))
+                (call $close (local.get $id))
+                (call $output_set (local.get $res) (call $length (local.get $res)))
+                i32.const 0)
+        )
+    "#;
+    let manifest = Manifest::new([Wasm::data(wasm.to_vec())]).with_allowed_host("127.0.0.1");
+    let mut plugin = PluginBuilder::new(&manifest)
+        .with_websockets(WebSocketLimits::default())
+        .build()
+        .unwrap();
+    let output: String = plugin.call("echo", &url).unwrap();
+    assert_eq!(output, format!("echo: {url}"));
+
+    let err = plugin
+        .call::<&str, &str>("echo", "ws://example.com")
+        .unwrap_err();
+    assert!(format!("{err:?}").contains("connection to ws://example.com is not allowed"));
+
+    // Messages larger than the limit are rejected
+    let mut plugin = PluginBuilder::new(&manifest)
+        .with_websockets(WebSocketLimits {
+            max_message_bytes: 8,
+            ..Default::default()
+        })
+        .build()
+        .unwrap();
+    let err = plugin.call::<&str, &str>("echo", &url).unwrap_err();
+    assert!(format!("{err:?}").contains("exceeds the limit of 8 bytes"));
+
+    // `recv` doesn't wait for longer than the manifest timeout, even when the guest timeout is longer
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let silent = format!("ws://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        let mut sockets = vec![];
+        for stream in listener.incoming() {
+            sockets.push(tungstenite::accept(stream.unwrap()).unwrap());
+        }
+    });
+    let manifest = manifest.with_timeout(std::time::Duration::from_millis(200));
+    let mut plugin = PluginBuilder::new(&manifest)
+        .with_websockets(WebSocketLimits::default())
+        .build()
+        .unwrap();
+    let start = std::time::Instant::now();
+    let err = plugin.call::<&str, &str>("echo", &silent).unwrap_err();
+    assert!(start.elapsed() < std::time::Duration::from_secs(2));
+    assert_eq!(ErrorKind::of(&err), ErrorKind::Timeout, "{err:?}");
+}
+
+// Entry point for the helper processes started by `test_out_of_process`, this does nothing when the test
+// suite runs normally
+#[cfg(unix)]
+#[test]
+fn out_of_process_helper() {
+    OutOfProcess::serve_if_helper();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_out_of_process() {
+    let helper = OutOfProcess::current_exe().unwrap().with_args([
+        "--exact",
+        "tests::runtime::out_of_process_helper",
+        "--quiet",
+    ]);
+    let manifest = Manifest::new([Wasm::data(WASM_NO_FUNCTIONS)]);
+    let mut plugin = PluginBuilder::new(&manifest)
+        .with_wasi(true)
+        .with_out_of_process(helper.clone())
+        .build()
+        .unwrap();
+    let output = plugin.call("count_vowels", "abc123").unwrap();
+    let count: serde_json::Value = serde_json::from_slice(output).unwrap();
+    assert_eq!(count.get("count").unwrap().as_i64().unwrap(), 1);
+    assert!(plugin.function_exists("count_vowels"));
+    assert!(!plugin.function_exists("missing"));
+
+    let pid = plugin.helper_process_id().unwrap();
+    assert_ne!(pid, std::process::id());
+
+    // Killing the helper only fails the current call, the next call starts a new helper
+    unsafe { libc::kill(pid as i32, libc::SIGKILL) };
+    let err = plugin
+        .call::<&str, &str>("count_vowels", "abc123")
+        .unwrap_err();
+    assert!(err.to_string().contains("plugin helper exited"));
+    assert!(plugin.helper_process_id().is_none());
+    let output = plugin.call("count_vowels", "aaa").unwrap();
+    let count: serde_json::Value = serde_json::from_slice(output).unwrap();
+    assert_eq!(count.get("count").unwrap().as_i64().unwrap(), 3);
+    assert_ne!(plugin.helper_process_id().unwrap(), pid);
+
+    // Host functions can't be called from the helper
+    let f = Function::new(
+        "hello_world",
+        [PTR],
+        [PTR],
+        UserData::default(),
+        hello_world,
+    );
+    let err = PluginBuilder::new(&manifest)
+        .with_functions([f])
+        .with_out_of_process(helper)
+        .build()
+        .unwrap_err();
+    assert!(err.to_string().contains("host functions aren't supported"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_out_of_process_limits() {
+    let helper = OutOfProcess::current_exe().unwrap().with_args([
+        "--exact",
+        "tests::runtime::out_of_process_helper",
+        "--quiet",
+    ]);
+    let wasm = r#"
+        (module
+            (memory (export "memory") 1)
+            (func (export "grow") (result i32)
+                ;; Try to grow the memory by 1GiB
+                (if (i32.eq (memory.grow (i32.const 16384)) (i32.const -1))
+                    (then (return (i32.const 1))))
+                i32.const 0)
+            (func (export "noop") (result i32) i32.const 0)
+        )
+    "#;
+    let manifest = Manifest::new([Wasm::data(wasm)]);
+    let mut plugin = PluginBuilder::new(&manifest)
+        .with_out_of_process(helper.clone())
+        .build()
+        .unwrap();
+    plugin.call::<(), ()>("grow", ()).unwrap();
+
+    let mut plugin = PluginBuilder::new(&manifest)
+        .with_out_of_process(
+            helper
+                .clone()
+                .with_limits(ProcessLimits::new().with_memory_bytes(512 * 1024 * 1024)),
+        )
+        .build()
+        .unwrap();
+    assert!(plugin.call::<(), ()>("grow", ()).is_err());
+    plugin.call::<(), ()>("noop", ()).unwrap();
+
+    // CPU limits can't be enforced without a cgroup
+    let err = PluginBuilder::new(&manifest)
+        .with_out_of_process(
+            helper
+                .clone()
+                .with_limits(ProcessLimits::new().with_cpus(0.5)),
+        )
+        .build()
+        .unwrap_err();
+    assert!(err.to_string().contains("CPU limits require a cgroup"));
+
+    // The helper isn't restarted after it has exited too many times
+    let mut plugin = PluginBuilder::new(&manifest)
+        .with_out_of_process(helper.clone().with_max_restarts(0))
+        .build()
+        .unwrap();
+    let pid = plugin.helper_process_id().unwrap();
+    unsafe { libc::kill(pid as i32, libc::SIGKILL) };
+    assert!(plugin.call::<(), ()>("noop", ()).is_err());
+    let err = plugin.call::<(), ()>("noop", ()).unwrap_err();
+    assert!(err.to_string().contains("won't be restarted again"));
+
+    // A stalled helper is killed once the timeout has passed instead of blocking the host
+    let manifest = manifest.with_timeout(std::time::Duration::from_millis(100));
+    let mut plugin = PluginBuilder::new(&manifest)
+        .with_out_of_process(helper)
+        .build()
+        .unwrap();
+    let pid = plugin.helper_process_id().unwrap();
+    unsafe { libc::kill(pid as i32, libc::SIGSTOP) };
+    let err = plugin.call::<(), ()>("noop", ()).unwrap_err();
+    assert!(format!("{err:?}").contains("timed out waiting for plugin helper"));
+    plugin.call::<(), ()>("noop", ()).unwrap();
+}
+
+#[test]
+fn test_mock_host() {
+    use crate::testing::{MockFunction, MockHost, MockHttpResponse};
+
+    let host = MockHost::new()
+        .with_http_response(
+            "https://example.com/*",
+            MockHttpResponse::new(200, "mocked"),
+        )
+        .with_file("/data/data.txt", "hello from memory")
+        .with_function(
+            MockFunction::new("get_user")
+                .returns("alice")
+                .fails("no more users"),
+        );
+
+    // Host functions and logs
+    let wasm = r#"
+        (module
+            (import "extism:host/env" "input_length" (func $input_length (result i64)))
+            (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+            (import "extism:host/env" "input_load_u8" (func $input_load_u8 (param i64) (result i32)))
+            (import "extism:host/env" "store_u8" (func $store_u8 (param i64 i32)))
+            (import "extism:host/env" "length" (func $length (param i64) (result i64)))
+            (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+            (import "extism:host/env" "log_trace" (func $log_trace (param i64)))
+            (import "extism:host/user" "get_user" (func $get_user (param i64) (result i64)))
+            (memory (export "memory") 1)
+            (func (export "run") (result i32)
+                (local $n i64) (local $i i64) (local $p i64) (local $res i64)
+                (local.set $n (call $input_length))
+                (local.set $p (call $alloc (local.get $n)))
+                (block $done (loop $l
+                    (br_if $done (i64.ge_u (local.get $i) (local.get $n)))
+                    (call $store_u8 (i64.add (local.get $p) (local.get $i))
+                        (call $input_load_u8 (local.get $i)))
+                    (local.set $i (i64.add (local.get $i) (i64.const 1)))
+                    (br $l)))
+                (local.set $res (call $get_user (local.get $p)))
+                (call $log_trace (local.get $res))
+                (local.set $res (call $get_user (local.get $p)))
+                (call $output_set (local.get $res) (call $length (local.get $res)))
+                i32.const 0)
+        )
+    "#;
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm)]))
+        .with_mock_host(&host)
+        .build()
+        .unwrap();
+    let err = plugin.call::<&str, &str>("run", "user-1").unwrap_err();
+    assert_eq!(err.root_cause().to_string(), "no more users");
+    assert_eq!(host.function_calls("get_user"), [b"user-1", b"user-1"]);
+    let logs = host.logs();
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].level, tracing::Level::TRACE);
+    assert_eq!(logs[0].message, "alice");
+
+    // In-memory filesystem
+    let manifest = Manifest::new([Wasm::data(WASM_FS)]).with_config_key("path", "/data/data.txt");
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_wasi(true)
+        .with_mock_host(&host)
+        .build()
+        .unwrap();
+    let res = plugin.call::<&str, &str>("try_read", "").unwrap();
+    assert_eq!(res, "hello from memory");
+    plugin.call::<&str, &str>("try_write", " and more").unwrap();
+    assert_eq!(
+        host.file("/data/data.txt").unwrap(),
+        b"hello from memory and more"
+    );
+    assert_eq!(host.files(), ["data/data.txt"]);
+
+    // HTTP responses, the manifest's allowed hosts still apply
+    #[cfg(feature = "http")]
+    {
+        let manifest = Manifest::new([Wasm::data(WASM_HTTP)]).with_allowed_host("example.com");
+        let mut plugin = PluginBuilder::new(manifest)
+            .with_mock_host(&host)
+            .build()
+            .unwrap();
+        let res: String = plugin
+            .call("http_request", r#"{"url": "https://example.com/a"}"#)
+            .unwrap();
+        assert_eq!(res, "mocked");
+        assert!(plugin
+            .call::<&str, &str>("http_request", r#"{"url": "https://example.org/b"}"#)
+            .is_err());
+        let err = plugin
+            .call::<&str, &str>("http_request", r#"{"url": "http://example.com/b"}"#)
+            .unwrap_err();
+        assert!(err
+            .root_cause()
+            .to_string()
+            .contains("no mock HTTP response"));
+        let requests = host.http_requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[0].url, "https://example.com/a");
+    }
+}
+
+#[test]
+#[cfg(feature = "http")]
+fn test_http_policy() {
+    use crate::testing::{MockHost, MockHttpResponse};
+
+    let host = MockHost::new().with_http_response(
+        "https://*.example.com/*",
+        MockHttpResponse::new(200, "ok").with_header("set-cookie", "a=b"),
+    );
+    let manifest = Manifest::new([Wasm::data(WASM_HTTP)])
+        .with_allowed_host("*.example.com")
+        .with_http_policy(
+            "*.example.com",
+            HttpPolicy::new()
+                .with_allowed_methods(["GET", "POST"])
+                .with_max_request_bytes(8)
+                .with_stripped_header("Authorization"),
+        )
+        .with_http_policy("api.example.com", HttpPolicy::new());
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_mock_host(&host)
+        .build()
+        .unwrap();
+
+    let res: String = plugin
+        .call(
+            "http_request",
+            r#"{"url": "https://www.example.com/a", "headers": {"authorization": "secret", "x-id": "1"}}"#,
+        )
+        .unwrap();
+    assert_eq!(res, "ok");
+    let err = plugin
+        .call::<&str, &str>(
+            "http_request",
+            r#"{"url": "https://www.example.com/a", "method": "DELETE"}"#,
+        )
+        .unwrap_err();
+    assert!(err.root_cause().to_string().contains("is not allowed"));
+    let err = plugin
+        .call::<&str, &str>(
+            "http_request",
+            r#"{"url": "https://www.example.com/a", "method": "POST", "data": "too much data"}"#,
+        )
+        .unwrap_err();
+    assert!(err
+        .root_cause()
+        .to_string()
+        .contains("maximum number of bytes"));
+
+    // An exact match takes precedence over patterns
+    plugin
+        .call::<&str, &str>(
+            "http_request",
+            r#"{"url": "https://api.example.com/a", "method": "DELETE", "headers": {"authorization": "secret"}}"#,
+        )
+        .unwrap();
+
+    let requests = host.http_requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].headers.len(), 1);
+    assert_eq!(requests[0].headers["x-id"], "1");
+    assert_eq!(requests[1].method, "DELETE");
+    assert_eq!(requests[1].headers["authorization"], "secret");
+
+    // Stripped headers aren't visible in responses
+    let manifest = Manifest::new([Wasm::data(WASM_HTTP_HEADERS)])
+        .with_allowed_host("*.example.com")
+        .with_http_policy(
+            "*.example.com",
+            HttpPolicy::new().with_stripped_header("Set-Cookie"),
+        );
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_mock_host(&host)
+        .with_http_response_headers(true)
+        .build()
+        .unwrap();
+    let req = HttpRequest::new("https://www.example.com/b");
+    let Json(res): Json<HashMap<String, String>> = plugin.call("http_get", Json(req)).unwrap();
+    assert!(res.is_empty());
+}
+
+#[test]
+fn test_manifest_validate() {
+    let hash = "a".repeat(64);
+    let manifest = Manifest::new([
+        Wasm::data(WASM).with_name("a").with_hash(&hash),
+        Wasm::url("ftp://example.com/b.wasm").with_name("a"),
+        Wasm::file("c.wasm").with_hash(&hash),
+        Wasm::file("d.wasm").with_hash("ABC"),
+    ])
+    .with_allowed_socket("localhost")
+    .with_timeout(std::time::Duration::ZERO);
+    assert_eq!(
+        manifest.validate(),
+        vec![
+            ManifestError::DuplicateName {
+                index: 1,
+                name: "a".to_string()
+            },
+            ManifestError::InvalidUrl {
+                index: 1,
+                url: "ftp://example.com/b.wasm".to_string()
+            },
+            ManifestError::DuplicateHash {
+                index: 2,
+                hash: hash.clone()
+            },
+            ManifestError::InvalidHash {
+                index: 3,
+                hash: "ABC".to_string()
+            },
+            ManifestError::ZeroTimeout,
+            ManifestError::InvalidSocket("localhost".to_string()),
+        ]
+    );
+    assert_eq!(Manifest::default().validate(), vec![ManifestError::NoWasm]);
+
+    let errors = ManifestBuilder::new()
+        .with_wasm(Wasm::url("https://example.com:8080/plugin.wasm"))
+        .with_timeout(std::time::Duration::from_secs(1))
+        .with_timeout(std::time::Duration::from_secs(2))
+        .build()
+        .unwrap_err();
+    assert_eq!(
+        errors,
+        vec![ManifestError::ConflictingTimeout {
+            first_ms: 1000,
+            second_ms: 2000
+        }]
+    );
+
+    // Valid manifests are unchanged by a serde round-trip
+    let manifest = Manifest::builder()
+        .with_wasm(Wasm::data(WASM).with_name("main"))
+        .with_wasm(Wasm::url("https://example.com/a.wasm").with_hash(&hash))
+        .with_wasm(extism_manifest::OciReference::new("ghcr.io/org/plugin:1.0"))
+        .with_memory_options(MemoryOptions::new().with_max_var_bytes(16))
+        .with_allowed_host("*.example.com")
+        .with_http_policy("example.com", HttpPolicy::new())
+        .with_allowed_socket("db.internal:*")
+        .with_readonly_path("data", "/data")
+        .with_config_key("a", "b")
+        .with_timeout(std::time::Duration::from_secs(1))
+        .build()
+        .unwrap();
+    let json = serde_json::to_string(&manifest).unwrap();
+    assert_eq!(serde_json::from_str::<Manifest>(&json).unwrap(), manifest);
+    let toml = toml::to_string(&manifest).unwrap();
+    assert_eq!(toml::from_str::<Manifest>(&toml).unwrap(), manifest);
+}
+
+#[test]
+#[cfg(feature = "http")]
+fn test_with_http_client() {
+    use std::io::{BufRead, BufReader};
+
+    // Answers each connection with `response`, returning the request lines that were received. `CONNECT`
+    // requests are accepted, then the tunneled request is answered.
+    fn serve(response: &'static str, n: usize) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = std::thread::spawn(move || {
+            let mut requests = vec![];
+            for stream in listener.incoming().take(n) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    requests.push(line.trim_end().to_string());
+                    loop {
+                        let mut header = String::new();
+                        if reader.read_line(&mut header).unwrap() == 0 || header == "\r\n" {
+                            break;
+                        }
+                    }
+                    if !line.starts_with("CONNECT") {
+                        break;
+                    }
+                    stream
+                        .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                        .unwrap();
+                }
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            requests
+        });
+        (addr, handle)
+    }
+
+    // Redirects are returned to the plugin when they're disabled
+    let (addr, server) = serve(
+        "HTTP/1.1 302 Found\r\nLocation: http://127.0.0.1:1/\r\nContent-Length: 5\r\nConnection: close\r\n\r\nmoved",
+        1,
+    );
+    let manifest = Manifest::new([Wasm::data(WASM_HTTP)]).with_allowed_host("127.0.0.1");
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_http_client(HttpClientOptions::new().with_max_redirects(0))
+        .build()
+        .unwrap();
+    let req = format!(r#"{{"url": "http://{addr}/a"}}"#);
+    let res: String = plugin.call("http_request", req.as_str()).unwrap();
+    assert_eq!(res, "moved");
+    assert_eq!(server.join().unwrap(), ["GET /a HTTP/1.1"]);
+
+    // Requests are tunneled through the proxy
+    let (addr, server) = serve(
+        "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+        1,
+    );
+    let manifest = Manifest::new([Wasm::data(WASM_HTTP)]).with_allowed_host("example.com");
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_http_client(
+            HttpClientOptions::new()
+                .with_proxy(format!("http://{addr}"))
+                .with_connect_timeout(std::time::Duration::from_secs(5))
+                .with_read_timeout(std::time::Duration::from_secs(5)),
+        )
+        .build()
+        .unwrap();
+    let res: String = plugin
+        .call("http_request", r#"{"url": "http://example.com/b"}"#)
+        .unwrap();
+    assert_eq!(res, "ok");
+    assert_eq!(
+        server.join().unwrap(),
+        ["CONNECT example.com:80 HTTP/1.1", "GET /b HTTP/1.1"]
+    );
+
+    // Invalid settings are reported when the plugin is built
+    assert!(PluginBuilder::new(WASM_HTTP)
+        .with_http_client(HttpClientOptions::new().with_root_certificates("not a certificate"))
+        .build()
+        .is_err());
+    assert!(PluginBuilder::new(WASM_HTTP)
+        .with_http_client(HttpClientOptions::new().with_proxy("not a proxy://"))
+        .build()
+        .is_err());
+}
+
+#[test]
+#[cfg(feature = "http")]
+fn test_streaming_http_response() {
+    use crate::testing::{MockHost, MockHttpResponse};
+
+    // `stream` reads the response for the request in the input in 4 byte chunks, passing each chunk to
+    // `chunk`, and fails if the status code isn't 201
+    let wasm = br#"
+(module
+    (import "extism:host/env" "http_stream_open" (func $open (param i64 i64) (result i64)))
+    (import "extism:host/env" "http_stream_read" (func $read (param i64 i64) (result i64)))
+    (import "extism:host/env" "http_stream_close" (func $close (param i64)))
+    (import "extism:host/env" "http_status_code" (func $status (result i32)))
+    (import "extism:host/env" "input_offset" (func $input_offset (result i64)))
+    (import "extism:host/user" "chunk" (func $chunk (param i64)))
+    (func (export "stream") (result i32)
+        (local $stream i64)
+        (local $offs i64)
+        (local.set $stream (call $open (call $input_offset) (i64.const 0)))
+        (if (i64.ne (local.get $stream) (i64.const 0))
+            (then
+                (loop $l
+                    (local.set $offs (call $read (local.get $stream) (i64.const 4)))
+                    (if (i64.ne (local.get $offs) (i64.const 0))
+                        (then
+                            (call $chunk (local.get $offs))
+                            (br $l))))))
+        (i32.ne (call $status) (i32.const 201))
+    )
+    (func (export "open") (result i32)
+        (call $close (call $open (call $input_offset) (i64.const 0)))
+        (i32.const 0)
+    )
+)
+    "#;
+    let host = MockHost::new()
+        .with_http_response(
+            "https://example.com/a",
+            MockHttpResponse::new(201, "hello, world"),
+        )
+        .with_http_response("https://example.com/b", MockHttpResponse::new(200, "x"));
+    let chunks = UserData::new(Vec::<String>::new());
+    let manifest = Manifest::new([Wasm::data(wasm.to_vec())])
+        .with_allowed_host("example.com")
+        .with_memory_options(MemoryOptions::new().with_max_http_response_bytes(12));
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_mock_host(&host)
+        .with_function(
+            "chunk",
+            [PTR],
+            [],
+            chunks.clone(),
+            |plugin, inputs, _, chunks| {
+                let chunk: String = plugin.memory_get_val(&inputs[0])?;
+                chunks.get()?.lock().unwrap().push(chunk);
+                Ok(())
+            },
+        )
+        .build()
+        .unwrap();
+
+    let _: () = plugin
+        .call("stream", r#"{"url": "https://example.com/a"}"#)
+        .unwrap();
+    assert_eq!(
+        *chunks.get().unwrap().lock().unwrap(),
+        ["hell", "o, w", "orld"]
+    );
+
+    // Responses over the limit are an error once the limit is reached
+    let manifest = Manifest::new([Wasm::data(wasm.to_vec())])
+        .with_allowed_host("example.com")
+        .with_memory_options(MemoryOptions::new().with_max_http_response_bytes(6));
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_mock_host(&host)
+        .with_function("chunk", [PTR], [], UserData::new(()), |_, _, _, _| Ok(()))
+        .build()
+        .unwrap();
+    let err = plugin
+        .call::<&str, ()>("stream", r#"{"url": "https://example.com/a"}"#)
+        .unwrap_err();
+    assert!(err
+        .root_cause()
+        .to_string()
+        .contains("maximum number of bytes"));
+
+    // Streams that aren't read completely can be closed
+    let _: () = plugin
+        .call("open", r#"{"url": "https://example.com/b"}"#)
+        .unwrap();
+    assert_eq!(host.http_requests().len(), 3);
+}
+
+#[test]
+fn test_wasi_output_capture() {
+    // Writes "out" to stdout and "err" to stderr using `fd_write`
+    let wasm = br#"
+        (module
+            (import "wasi_snapshot_preview1" "fd_write"
+                (func $fd_write (param i32 i32 i32 i32) (result i32)))
+            (memory (export "memory") 1)
+            (data (i32.const 16) "outerr")
+            (func $write (param $fd i32) (param $offs i32)
+                (i32.store (i32.const 0) (local.get $offs))
+                (i32.store (i32.const 4) (i32.const 3))
+                (drop (call $fd_write (local.get $fd) (i32.const 0) (i32.const 1) (i32.const 8))))
+            (func (export "run") (result i32)
+                (call $write (i32.const 1) (i32.const 16))
+                (call $write (i32.const 2) (i32.const 19))
+                i32.const 0)
+        )
+    "#;
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_wasi(true)
+        .with_output_capture(true)
+        .build()
+        .unwrap();
+    plugin.call::<&str, &[u8]>("run", "").unwrap();
+    plugin.call::<&str, &[u8]>("run", "").unwrap();
+    assert_eq!(plugin.take_stdout(), b"out");
+    assert_eq!(plugin.take_stderr(), b"err");
+    assert!(plugin.take_stdout().is_empty());
+
+    // Output past the limit is dropped
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_wasi(true)
+        .with_output_capture(true)
+        .with_output_capture_limit(2)
+        .build()
+        .unwrap();
+    plugin.call::<&str, &[u8]>("run", "").unwrap();
+    assert_eq!(plugin.take_stdout(), b"ou");
+    assert_eq!(plugin.take_stderr(), b"er");
+
+    #[derive(Clone, Default)]
+    struct Shared(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+    impl std::io::Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    let out = Shared::default();
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_wasi(true)
+        .with_stdout(out.clone())
+        .build()
+        .unwrap();
+    plugin.call::<&str, &[u8]>("run", "").unwrap();
+    plugin.call::<&str, &[u8]>("run", "").unwrap();
+    assert_eq!(*out.0.lock().unwrap(), b"outout");
+    assert!(plugin.take_stdout().is_empty());
+}
+
+#[test]
+fn test_wasi_stdin() {
+    // Reads up to 64 bytes from stdin and returns them as the output
+    let wasm = br#"
+        (module
+            (import "wasi_snapshot_preview1" "fd_read"
+                (func $fd_read (param i32 i32 i32 i32) (result i32)))
+            (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+            (import "extism:host/env" "store_u8" (func $store_u8 (param i64 i32)))
+            (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+            (memory (export "memory") 1)
+            (func (export "run") (result i32)
+                (local $n i32) (local $i i32) (local $h i64)
+                (i32.store (i32.const 0) (i32.const 16))
+                (i32.store (i32.const 4) (i32.const 64))
+                (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+                (local.set $n (i32.load (i32.const 8)))
+                (local.set $h (call $alloc (i64.extend_i32_u (local.get $n))))
+                (block $done
+                    (loop $copy
+                        (br_if $done (i32.ge_u (local.get $i) (local.get $n)))
+                        (call $store_u8
+                            (i64.add (local.get $h) (i64.extend_i32_u (local.get $i)))
+                            (i32.load8_u (i32.add (i32.const 16) (local.get $i))))
+                        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                        (br $copy)))
+                (call $output_set (local.get $h) (i64.extend_i32_u (local.get $n)))
+                i32.const 0)
+        )
+    "#;
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_wasi(true)
+        .with_stdin("hello")
+        .build()
+        .unwrap();
+    for _ in 0..2 {
+        let output: String = plugin.call("run", "").unwrap();
+        assert_eq!(output, "hello");
+    }
+    let output: String = plugin.call_with_stdin("run", "", "override").unwrap();
+    assert_eq!(output, "override");
+    let output: String = plugin.call("run", "").unwrap();
+    assert_eq!(output, "hello");
+
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_wasi(true)
+        .with_stdin(Stdin::reader(std::io::Cursor::new(b"streamed".to_vec())))
+        .build()
+        .unwrap();
+    let output: String = plugin.call("run", "").unwrap();
+    assert_eq!(output, "streamed");
+    let output: String = plugin.call("run", "").unwrap();
+    assert_eq!(output, "");
+}
+
+#[test]
+fn test_call_batch() {
+    let mut plugin = Plugin::new(WASM_NO_FUNCTIONS, [], true).unwrap();
+    let outputs: Vec<Result<String, Error>> =
+        plugin.call_batch("count_vowels", ["a", "bbb", "aeiou", ""]);
+    let outputs: Vec<String> = outputs.into_iter().map(|x| x.unwrap()).collect();
+    assert_eq!(
+        outputs,
+        [
+            "{\"count\":1,\"total\":1,\"vowels\":\"aeiouAEIOU\"}",
+            "{\"count\":0,\"total\":1,\"vowels\":\"aeiouAEIOU\"}",
+            "{\"count\":5,\"total\":6,\"vowels\":\"aeiouAEIOU\"}",
+            "{\"count\":0,\"total\":6,\"vowels\":\"aeiouAEIOU\"}",
+        ]
+    );
+
+    let outputs: Vec<Result<String, Error>> = plugin.call_batch("missing", ["a", "b"]);
+    assert_eq!(outputs.len(), 2);
+    assert!(outputs.iter().all(|x| x.is_err()));
+    let output: String = plugin.call("count_vowels", "a").unwrap();
+    assert!(output.starts_with("{\"count\":1"));
+}
+
+#[test]
+fn test_typed_function_handle() {
+    let mut plugin = Plugin::new(WASM_NO_FUNCTIONS, [], true).unwrap();
+    let count_vowels = plugin
+        .typed_function::<&str, String>("count_vowels")
+        .unwrap();
+    assert_eq!(count_vowels.name(), "count_vowels");
+    for _ in 0..3 {
+        let output = count_vowels.call(&mut plugin, "aei").unwrap();
+        assert!(output.starts_with("{\"count\":3"));
+    }
+    assert!(plugin.typed_function::<&str, String>("missing").is_err());
+
+    let mut other = Plugin::new(WASM_NO_FUNCTIONS, [], true).unwrap();
+    let err = count_vowels.call(&mut other, "a").unwrap_err();
+    assert!(err.to_string().contains("was acquired from plugin"));
+}
+
+#[test]
+fn test_plugin_hooks() {
+    #[derive(Default)]
+    struct Recorder(std::sync::Mutex<Vec<String>>);
+    impl PluginHooks for Recorder {
+        fn on_instantiate(&self, _plugin: uuid::Uuid, reinstantiated: bool) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("instantiate {reinstantiated}"));
+        }
+
+        fn on_call_start(&self, _plugin: uuid::Uuid, function: &str) {
+            self.0.lock().unwrap().push(format!("start {function}"));
+        }
+
+        fn on_call_end(
+            &self,
+            _plugin: uuid::Uuid,
+            function: &str,
+            _duration: std::time::Duration,
+            success: bool,
+        ) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("end {function} {success}"));
+        }
+
+        fn on_trap(&self, _plugin: uuid::Uuid, function: &str, _error: &Error) {
+            self.0.lock().unwrap().push(format!("trap {function}"));
+        }
+
+        fn on_memory_reset(&self, _plugin: uuid::Uuid) {
+            self.0.lock().unwrap().push("reset".to_string());
+        }
+    }
+
+    let wasm = br#"
+        (module
+            (func (export "ok") (result i32) i32.const 0)
+            (func (export "crash") (result i32) unreachable)
+            (func (export "_start"))
+        )
+    "#;
+    let hooks = std::sync::Arc::new(Recorder::default());
+    let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]))
+        .with_hooks(hooks.clone())
+        .build()
+        .unwrap();
+    plugin.call::<&str, &[u8]>("ok", "").unwrap();
+    plugin.call::<&str, &[u8]>("crash", "").unwrap_err();
+    // Calling a WASI command's `_start` discards the instance
+    plugin.call::<&str, &[u8]>("_start", "").unwrap();
+    plugin.call::<&str, &[u8]>("ok", "").unwrap();
+    plugin.reset().unwrap();
+    assert_eq!(
+        *hooks.0.lock().unwrap(),
+        [
+            "start ok",
+            "instantiate false",
+            "end ok true",
+            "start crash",
+            "trap crash",
+            "end crash false",
+            "start _start",
+            "end _start true",
+            "start ok",
+            "instantiate true",
+            "end ok true",
+            "reset",
+        ]
+    );
+}
+
+#[test]
+fn test_kernel_memory_introspection() {
+    // Outputs memory_used and memory_remaining before and after allocating 1000 bytes
+    let wasm = br#"
+        (module
+            (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+            (import "extism:host/env" "memory_used" (func $used (result i64)))
+            (import "extism:host/env" "memory_remaining" (func $remaining (result i64)))
+            (import "extism:host/env" "store_u64" (func $store_u64 (param i64 i64)))
+            (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+            (memory (export "memory") 1)
+            (func (export "run") (result i32)
+                (local $used i64) (local $remaining i64) (local $h i64)
+                (local.set $used (call $used))
+                (local.set $remaining (call $remaining))
+                (drop (call $alloc (i64.const 1000)))
+                (local.set $h (call $alloc (i64.const 32)))
+                (call $store_u64 (local.get $h) (local.get $used))
+                (call $store_u64 (i64.add (local.get $h) (i64.const 8)) (local.get $remaining))
+                (call $store_u64 (i64.add (local.get $h) (i64.const 16)) (call $used))
+                (call $store_u64 (i64.add (local.get $h) (i64.const 24)) (call $remaining))
+                (call $output_set (local.get $h) (i64.const 32))
+                i32.const 0)
+        )
+    "#;
+    let manifest = Manifest::new([Wasm::data(wasm.to_vec())]).with_memory_max(4);
+    let mut plugin = Plugin::new(manifest, [], false).unwrap();
+    let read = |x: &[u8], i: usize| u64::from_le_bytes(x[i * 8..i * 8 + 8].try_into().unwrap());
+    for _ in 0..2 {
+        let output: Vec<u8> = plugin.call("run", "").unwrap();
+        let (used, remaining) = (read(&output, 0), read(&output, 1));
+        let (used_after, remaining_after) = (read(&output, 2), read(&output, 3));
+        // Only the (empty) input has been allocated when the call starts
+        assert!(used < 100);
+        assert!(used_after >= used + 1000);
+        assert_eq!(remaining - remaining_after, used_after - used);
+        // The rest of the kernel's first page can be used, along with up to 4 more pages
+        assert!(remaining > 4 * 65536 && remaining < 5 * 65536);
+    }
+}
+
+#[test]
+fn test_plugin_group() {
+    let wasm = br#"
+        (module
+            (func (export "ok") (result i32) i32.const 0)
+        )
+    "#;
+    let mut group = PluginGroup::new().with_max_parallelism(2);
+    for key in ["a", "b", "c"] {
+        let plugin = Plugin::new(WASM_NO_FUNCTIONS, [], true).unwrap();
+        assert!(group.insert(key, plugin).is_none());
+    }
+    group.insert(
+        "other",
+        Plugin::new(Manifest::new([Wasm::data(wasm.to_vec())]), [], false).unwrap(),
+    );
+    assert_eq!(group.len(), 4);
+
+    let results = group.broadcast::<_, String>("count_vowels", "aei");
+    let keys: Vec<&str> = results.iter().map(|(k, _)| k.as_str()).collect();
+    assert_eq!(keys, ["a", "b", "c", "other"]);
+    for (_, res) in &results[..3] {
+        assert!(res.as_ref().unwrap().starts_with("{\"count\":3"));
+    }
+    assert!(results[3].1.is_err());
+
+    // Each plugin keeps its own state between broadcasts
+    let results = group
+        .with_max_parallelism(1)
+        .broadcast::<_, String>("count_vowels", "a");
+    assert!(results[0].1.as_ref().unwrap().contains("\"total\":4"));
+}
+
+#[test]
+fn test_error_kind() {
+    let wasm = br#"
+        (module
+            (import "extism:host/user" "fail" (func $fail))
+            (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+            (import "extism:host/env" "store_u8" (func $store_u8 (param i64 i32)))
+            (import "extism:host/env" "error_set" (func $error_set (param i64)))
+            (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+            (memory (export "memory") 1)
+            (func (export "host") (result i32)
+                (call $fail)
+                i32.const 0)
+            (func (export "trap") (result i32) unreachable)
+            (func (export "guest") (result i32)
+                (local $h i64)
+                (local.set $h (call $alloc (i64.const 1)))
+                (call $store_u8 (local.get $h) (i32.const 120))
+                (call $error_set (local.get $h))
+                i32.const 1)
+            (func (export "spin") (result i32)
+                (loop $l (br $l))
+                i32.const 0)
+            (func (export "invalid_json") (result i32)
+                (local $h i64)
+                (local.set $h (call $alloc (i64.const 1)))
+                (call $store_u8 (local.get $h) (i32.const 123))
+                (call $output_set (local.get $h) (i64.const 1))
+                i32.const 0)
+        )
+    "#;
+    let manifest = Manifest::new([Wasm::data(wasm.to_vec())])
+        .with_timeout(std::time::Duration::from_millis(100));
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_function("fail", [], [], UserData::new(()), |_, _, _, _| {
+            anyhow::bail!("host failure")
+        })
+        .build()
+        .unwrap();
+    let mut kind = |name: &str| {
+        let err = plugin.call::<&str, &[u8]>(name, "").unwrap_err();
+        ErrorKind::of(&err)
+    };
+    assert_eq!(kind("host"), ErrorKind::HostFunction);
+    assert_eq!(kind("trap"), ErrorKind::Trap);
+    assert_eq!(kind("guest"), ErrorKind::Guest);
+    assert_eq!(kind("spin"), ErrorKind::Timeout);
+    assert_eq!(kind("missing"), ErrorKind::FunctionNotFound);
+    assert_eq!(ErrorKind::Timeout.code(), 1);
+
+    let err = plugin
+        .call::<&str, Json<serde_json::Value>>("invalid_json", "")
+        .unwrap_err();
+    assert_eq!(ErrorKind::of(&err), ErrorKind::Encoding);
+
+    // Host function errors are displayed the same as the original error
+    let err = plugin.call::<&str, &[u8]>("host", "").unwrap_err();
+    assert_eq!(err.root_cause().to_string(), "host failure");
+    let host = err
+        .chain()
+        .find_map(|x| x.downcast_ref::<HostFunctionError>())
+        .unwrap();
+    assert_eq!(host.function(), "fail");
+
+    let mut plugin = PluginBuilder::new(WASM_LOOP)
+        .with_fuel_limit(1000)
+        .build()
+        .unwrap();
+    let err = plugin.call::<&str, &[u8]>("loop_forever", "").unwrap_err();
+    assert_eq!(ErrorKind::of(&err), ErrorKind::OutOfFuel);
+}
+
+#[test]
+fn test_guest_backtrace() {
+    let wasm = br#"
+        (module
+            (func $inner (result i32) unreachable)
+            (func $outer (export "trap") (result i32) call $inner)
+            (func (export "ok") (result i32) i32.const 0)
+        )
+    "#;
+    let mut plugin = Plugin::new(Manifest::new([Wasm::data(wasm.to_vec())]), [], false).unwrap();
+    let err = plugin.call::<&str, &[u8]>("trap", "").unwrap_err();
+    let backtrace = GuestBacktrace::of(&err).unwrap();
+    assert!(backtrace.message().contains("unreachable"));
+
+    let frames: Vec<_> = backtrace
+        .frames()
+        .iter()
+        .map(|f| (f.module.as_deref(), f.function.as_deref()))
+        .collect();
+    assert_eq!(
+        frames,
+        [(Some("main"), Some("inner")), (Some("main"), Some("outer"))]
+    );
+
+    // The trap and the resolved frames are included in the error message
+    let msg = err.to_string();
+    assert!(msg.contains("unreachable"), "{msg}");
+    assert!(msg.contains("main!inner"), "{msg}");
+    assert_eq!(ErrorKind::of(&err), ErrorKind::Trap);
+
+    plugin.call::<&str, &[u8]>("ok", "").unwrap();
+    let err = plugin.call::<&str, &[u8]>("missing", "").unwrap_err();
+    assert!(GuestBacktrace::of(&err).is_none());
+}
+
+#[test]
+fn test_kernel_memory_policy() {
+    let wasm = br#"
+        (module
+            (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+            (memory (export "memory") 1)
+            (func (export "big") (result i32)
+                (drop (call $alloc (i64.const 4194304)))
+                i32.const 0)
+            (func (export "small") (result i32) i32.const 0)
+        )
+    "#;
+    let pages = |policy: Option<KernelMemoryPolicy>| {
+        let mut builder = PluginBuilder::new(Manifest::new([Wasm::data(wasm.to_vec())]));
+        if let Some(policy) = policy {
+            builder = builder.with_kernel_memory_policy(policy);
+        }
+        let mut plugin = builder.build().unwrap();
+        let (_, big) = plugin.call_with_stats::<&str, &[u8]>("big", "").unwrap();
+        let (_, small) = plugin.call_with_stats::<&str, &[u8]>("small", "").unwrap();
+        (big.memory_pages, small.memory_pages)
+    };
+
+    // Without a policy the kernel memory keeps its peak size
+    let (big, small) = pages(None);
+    assert!(big > 64);
+    assert_eq!(small, big);
+
+    let (big, small) = pages(Some(
+        KernelMemoryPolicy::new().with_shrink_threshold(2 * 1024 * 1024),
+    ));
+    assert!(big > 64);
+    assert!(small < 64, "{small}");
+
+    // Below the threshold the memory is kept
+    let (big, small) = pages(Some(
+        KernelMemoryPolicy::new().with_shrink_threshold(64 * 1024 * 1024),
+    ));
+    assert_eq!(small, big);
+
+    let (_, small) = pages(Some(KernelMemoryPolicy::new().with_reset_after_call(true)));
+    assert!(small < 64, "{small}");
+}
+
+#[test]
+fn test_quick_call() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../wasm/code.wasm");
+    let manifest = serde_json::json!({ "wasm": [{ "path": path }] }).to_string();
+    let output = crate::quick::call(&manifest, "count_vowels", "aaa").unwrap();
+    let count: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(count["count"], 3);
+
+    assert!(crate::quick::call("{", "count_vowels", "").is_err());
+    let err = crate::quick::call(&manifest, "missing", "").unwrap_err();
+    assert_eq!(ErrorKind::of(&err), ErrorKind::FunctionNotFound);
+
+    // Paths from the manifest aren't mounted
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../wasm/read_write.wasm");
+    let data = concat!(env!("CARGO_MANIFEST_DIR"), "/src/tests/data");
+    let manifest = serde_json::json!({
+        "wasm": [{ "path": path }],
+        "allowed_paths": { data: "/data" },
+        "config": { "path": "/data/data.txt" },
+    })
+    .to_string();
+    assert!(crate::quick::call(&manifest, "try_read", "").is_err());
+}
+
+#[test]
+fn test_wasm_threads() {
+    let wasm = br#"
+        (module
+            (import "wasi" "thread-spawn" (func $spawn (param i32) (result i32)))
+            (import "env" "memory" (memory 1 1 shared))
+            (func (export "wasi_thread_start") (param $tid i32) (param $arg i32)
+                (i32.atomic.store (i32.const 0) (local.get $arg))
+                (drop (memory.atomic.notify (i32.const 0) (i32.const 1))))
+            (func (export "run") (result i32)
+                (if (i32.lt_s (call $spawn (i32.const 42)) (i32.const 1))
+                    (then (return (i32.const 2))))
+                (drop (memory.atomic.wait32 (i32.const 0) (i32.const 0) (i64.const 5000000000)))
+                (if (result i32) (i32.eq (i32.atomic.load (i32.const 0)) (i32.const 42))
+                    (then (i32.const 0))
+                    (else (i32.const 1))))
+        )
+    "#;
+    let manifest = Manifest::new([Wasm::data(wasm.to_vec())]);
+
+    let err = PluginBuilder::new(manifest.clone()).build().err().unwrap();
+    assert!(err.to_string().contains("with_wasm_threads"), "{err}");
+
+    let err = PluginBuilder::new(manifest.clone())
+        .with_wasm_threads(true)
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("`threads` capability"), "{err}");
+
+    let manifest = manifest.with_capability("threads");
+    let mut plugin = PluginBuilder::new(manifest.clone())
+        .with_wasm_threads(true)
+        .build()
+        .unwrap();
+    plugin.call::<&str, &[u8]>("run", "").unwrap();
+
+    // `thread-spawn` fails once the thread limit is reached
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_wasm_threads(true)
+        .with_max_wasm_threads(0)
+        .build()
+        .unwrap();
+    let err = plugin.call::<&str, &[u8]>("run", "").unwrap_err();
+    assert_eq!(err.to_string(), "Returned non-zero exit code: 2");
+}
+
+#[test]
+fn test_wasm_threads_interrupted() {
+    let wasm = br#"
+        (module
+            (import "wasi" "thread-spawn" (func $spawn (param i32) (result i32)))
+            (import "env" "memory" (memory 1 1 shared))
+            (func (export "wasi_thread_start") (param $tid i32) (param $arg i32)
+                (loop $spin (br $spin)))
+            (func (export "spawn") (result i32)
+                (drop (call $spawn (i32.const 0)))
+                i32.const 0)
+            (func (export "spin") (result i32)
+                (drop (call $spawn (i32.const 0)))
+                (loop $spin (br $spin))
+                i32.const 0)
+        )
+    "#;
+    let manifest = Manifest::new([Wasm::data(wasm.to_vec())])
+        .with_capability("threads")
+        .with_timeout(std::time::Duration::from_millis(200));
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_wasm_threads(true)
+        .with_max_wasm_threads(1)
+        .build()
+        .unwrap();
+
+    // Threads that are still running are interrupted when the call returns, so the slot is free for the
+    // next call
+    plugin.call::<&str, &[u8]>("spawn", "").unwrap();
+    plugin.call::<&str, &[u8]>("spawn", "").unwrap();
+
+    let err = plugin.call::<&str, &[u8]>("spin", "").unwrap_err();
+    assert_eq!(ErrorKind::of(&err), ErrorKind::Timeout, "{err:?}");
+
+    let handle = plugin.cancel_handle();
+    let cancel = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        handle.cancel().unwrap();
+    });
+    let err = plugin.call::<&str, &[u8]>("spin", "").unwrap_err();
+    assert!(err.is::<Cancelled>(), "{err:?}");
+    cancel.join().unwrap();
+}
+
+#[test]
+fn test_wasm_threads_fuel() {
+    let wasm = br#"
+        (module
+            (import "wasi" "thread-spawn" (func $spawn (param i32) (result i32)))
+            (import "env" "memory" (memory 1 1 shared))
+            (func (export "wasi_thread_start") (param $tid i32) (param $arg i32)
+                (loop $spin (br $spin)))
+            (func (export "spawn") (result i32)
+                (drop (call $spawn (i32.const 0)))
+                i32.const 0)
+        )
+    "#;
+    let manifest = Manifest::new([Wasm::data(wasm.to_vec())]).with_capability("threads");
+    let mut plugin = PluginBuilder::new(manifest)
+        .with_wasm_threads(true)
+        .with_fuel_limit(1_000_000)
+        .build()
+        .unwrap();
+
+    // Spawned threads get half of the caller's remaining fuel
+    plugin.call::<&str, &[u8]>("spawn", "").unwrap();
+    assert!(plugin.fuel_consumed().unwrap() >= 500_000);
+}
+
+#[test]
+fn test_wasm_threads_shared_memory_limit() {
+    let wasm = br#"
+        (module
+            (import "env" "memory" (memory 1 16 shared))
+            (func (export "run") (result i32) i32.const 0)
+        )
+    "#;
+    let manifest = Manifest::new([Wasm::data(wasm.to_vec())]).with_capability("threads");
+    PluginBuilder::new(manifest.clone())
+        .with_wasm_threads(true)
+        .build()
+        .unwrap();
+
+    // Shared memories that can grow past the memory limit are rejected
+    let err = PluginBuilder::new(manifest.with_memory_max(8))
+        .with_wasm_threads(true)
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.is::<OutOfMemory>(), "{err:?}");
+}
@@ -0,0 +1,393 @@
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::plugin::HostLinker;
+use crate::*;
+
+/// The capability a manifest has to grant before modules that use shared memory or spawn threads are
+/// loaded, see `PluginBuilder::with_wasm_threads`
+pub(crate) const THREADS_CAPABILITY: &str = "threads";
+
+/// Shared memories imported by the plugin's modules, keyed by module and name. They're created when the
+/// plugin is linked and used by every thread it spawns.
+pub(crate) type SharedMemories = BTreeMap<(String, String), SharedMemory>;
+
+/// Limits the number of guest threads spawned using `wasi::thread-spawn` that can run at the same time, it's
+/// shared by a plugin and the threads it spawns
+#[derive(Debug)]
+pub(crate) struct ThreadPool {
+    max_threads: usize,
+    running: AtomicUsize,
+    next_id: AtomicI32,
+    stopping: AtomicBool,
+    cancelled: Mutex<Arc<AtomicBool>>,
+    handles: Mutex<Vec<std::thread::JoinHandle<()>>>,
+}
+
+impl ThreadPool {
+    /// `cancelled` is the flag set by the plugin's `CancelHandle`, spawned threads are interrupted when
+    /// it's set
+    pub(crate) fn new(max_threads: usize, cancelled: Arc<AtomicBool>) -> Self {
+        ThreadPool {
+            max_threads,
+            running: AtomicUsize::new(0),
+            next_id: AtomicI32::new(1),
+            stopping: AtomicBool::new(false),
+            cancelled: Mutex::new(cancelled),
+            handles: Default::default(),
+        }
+    }
+
+    /// Use the flag from another `CancelHandle`, lazy plugins keep the handle of the empty plugin that was
+    /// used before they were instantiated
+    pub(crate) fn set_cancelled(&self, cancelled: Arc<AtomicBool>) {
+        match self.cancelled.lock() {
+            Ok(mut x) => *x = cancelled,
+            Err(e) => *e.into_inner() = cancelled,
+        }
+    }
+
+    fn handles(&self) -> std::sync::MutexGuard<'_, Vec<std::thread::JoinHandle<()>>> {
+        match self.handles.lock() {
+            Ok(x) => x,
+            Err(e) => e.into_inner(),
+        }
+    }
+
+    // Returns `true` when threads should stop running guest code
+    fn interrupted(&self, deadline: Option<std::time::Instant>) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+            || match self.cancelled.lock() {
+                Ok(x) => x.load(Ordering::SeqCst),
+                Err(e) => e.into_inner().load(Ordering::SeqCst),
+            }
+            || deadline.is_some_and(|x| x <= std::time::Instant::now())
+    }
+
+    /// Interrupt the spawned threads that are still running and wait for them to exit, this is called when
+    /// a call returns and when the plugin is dropped so threads can't outlive the call that spawned them
+    pub(crate) fn stop(&self, engine: &Engine) {
+        if self.handles().is_empty() {
+            return;
+        }
+        self.stopping.store(true, Ordering::SeqCst);
+        // Threads can spawn more threads before they're interrupted, so keep going until there are none
+        // left
+        loop {
+            let handles = std::mem::take(&mut *self.handles());
+            if handles.is_empty() {
+                break;
+            }
+            engine.increment_epoch();
+            for handle in handles {
+                let _ = handle.join();
+            }
+        }
+        self.stopping.store(false, Ordering::SeqCst);
+    }
+
+    // Reserve a slot for a new thread and return its ID, IDs are positive to leave negative values for errors
+    fn acquire(self: &Arc<Self>) -> Option<(i32, ThreadSlot)> {
+        self.running
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < self.max_threads).then_some(n + 1)
+            })
+            .ok()?;
+        let id = self
+            .next_id
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                Some(if n >= 0x1FFF_FFFF { 1 } else { n + 1 })
+            })
+            .unwrap_or(1);
+        Some((id, ThreadSlot(self.clone())))
+    }
+}
+
+// Releases a reserved thread slot when it's dropped
+struct ThreadSlot(Arc<ThreadPool>);
+
+impl Drop for ThreadSlot {
+    fn drop(&mut self) {
+        self.0.running.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Returns `true` if `module` uses shared memory or imports `wasi::thread-spawn`
+fn uses_threads(module: &Module) -> bool {
+    let shared = |ty: ExternType| matches!(ty, ExternType::Memory(m) if m.is_shared());
+    module
+        .imports()
+        .any(|x| (x.module() == "wasi" && x.name() == "thread-spawn") || shared(x.ty()))
+        || module.exports().any(|x| shared(x.ty()))
+}
+
+/// Make sure modules that use threads are only loaded when threads are enabled and the manifest grants the
+/// `threads` capability
+pub(crate) fn check(
+    modules: &BTreeMap<String, Module>,
+    manifest: &Manifest,
+    enabled: bool,
+) -> Result<(), Error> {
+    for (name, module) in modules {
+        if name == EXTISM_ENV_MODULE || !uses_threads(module) {
+            continue;
+        }
+        if !enabled {
+            anyhow::bail!(
+                "module {name} uses shared memory or wasi::thread-spawn, wasm threads must be enabled using \
+                `PluginBuilder::with_wasm_threads`"
+            );
+        }
+        if !manifest
+            .capabilities
+            .iter()
+            .any(|x| x == THREADS_CAPABILITY)
+        {
+            anyhow::bail!(
+                "module {name} uses shared memory or wasi::thread-spawn, which requires the \
+                `{THREADS_CAPABILITY}` capability, it must be added to the manifest's `capabilities`"
+            );
+        }
+    }
+    Ok(())
+}
+
+// Shared memories aren't created by the store, so they aren't checked by its `ResourceLimiter`. Make sure a
+// shared memory can't grow past the plugin's memory limit instead
+fn check_shared_memory(
+    data: &CurrentPlugin,
+    import: &wasmtime::ImportType,
+    ty: &MemoryType,
+) -> Result<(), Error> {
+    let name = format!("{}::{}", import.module(), import.name());
+    let Some(max_pages) = ty.maximum() else {
+        anyhow::bail!("shared memory {name} must have a maximum size");
+    };
+    let limit = data.memory_limiter.as_ref().and_then(|x| x.limit());
+    if let Some(limit) = limit {
+        let max = max_pages.saturating_mul(ty.page_size());
+        if max > limit as u64 {
+            return Err(Error::new(OutOfMemory).context(format!(
+                "shared memory {name} can grow to {max} bytes, which is more than the plugin's \
+                memory limit of {limit} bytes"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Define the shared memories imported by `modules` that aren't exported by another module and
+/// `wasi::thread-spawn`, `memories` is set when linking a spawned thread so it uses the plugin's memories
+pub(crate) fn link(
+    store: &mut Store<CurrentPlugin>,
+    linker: &mut Linker<CurrentPlugin>,
+    host_linker: &Arc<HostLinker>,
+    modules: &BTreeMap<String, Module>,
+    memories: Option<SharedMemories>,
+) -> Result<(), Error> {
+    let Some(pool) = store.data().threads.clone() else {
+        return Ok(());
+    };
+
+    let memories = match memories {
+        Some(memories) => memories,
+        None => {
+            let mut memories = SharedMemories::new();
+            for (name, module) in modules {
+                if name == EXTISM_ENV_MODULE {
+                    continue;
+                }
+                for import in module.imports() {
+                    let ExternType::Memory(ty) = import.ty() else {
+                        continue;
+                    };
+                    let key = (import.module().to_string(), import.name().to_string());
+                    if !ty.is_shared()
+                        || modules.contains_key(import.module())
+                        || memories.contains_key(&key)
+                    {
+                        continue;
+                    }
+                    check_shared_memory(store.data(), &import, &ty)?;
+                    memories.insert(key, SharedMemory::new(store.engine(), ty)?);
+                }
+            }
+            memories
+        }
+    };
+    for ((module, name), memory) in &memories {
+        linker.define(&mut *store, module, name, memory.clone())?;
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    {
+        let thread = GuestThread {
+            host_linker: host_linker.clone(),
+            modules: modules.clone(),
+            memories,
+            manifest: store.data().manifest.clone(),
+            wasi: store.data().wasi.as_ref().map(|x| x.ctx.clone()),
+            id: store.data().id,
+            pool,
+        };
+        linker.func_wrap(
+            "wasi",
+            "thread-spawn",
+            move |mut caller: Caller<CurrentPlugin>, start_arg: i32| -> i32 {
+                thread.spawn(&mut caller, start_arg)
+            },
+        )?;
+    }
+    #[cfg(target_family = "wasm")]
+    let _ = (host_linker, pool);
+    Ok(())
+}
+
+// Everything needed to instantiate the plugin on a new thread
+#[cfg(not(target_family = "wasm"))]
+#[derive(Clone)]
+struct GuestThread {
+    host_linker: Arc<HostLinker>,
+    modules: BTreeMap<String, Module>,
+    memories: SharedMemories,
+    manifest: Manifest,
+    wasi: Option<wasi_common::WasiCtx>,
+    id: uuid::Uuid,
+    pool: Arc<ThreadPool>,
+}
+
+// The state of the call that spawned a thread, threads use the same timeout and deadline
+#[cfg(not(target_family = "wasm"))]
+struct ThreadStart {
+    arg: i32,
+    start_time: std::time::Instant,
+    deadline: Option<std::time::Instant>,
+    fuel: Option<u64>,
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl GuestThread {
+    // Start a thread that calls `wasi_thread_start`, returns the thread ID or `-1` if the thread limit has
+    // been reached
+    fn spawn(&self, caller: &mut Caller<CurrentPlugin>, arg: i32) -> i32 {
+        if self.pool.interrupted(None) {
+            return -1;
+        }
+        let Some((tid, slot)) = self.pool.acquire() else {
+            warn!(
+                plugin = self.id.to_string(),
+                "unable to spawn guest thread, the thread limit has been reached"
+            );
+            return -1;
+        };
+
+        // Threads share the fuel of the call that spawned them, the new thread gets half of the
+        // remaining fuel so the total can't be more than the plugin's limit
+        let fuel = caller.get_fuel().ok().map(|remaining| {
+            let fuel = remaining / 2;
+            let _ = caller.set_fuel(remaining - fuel);
+            fuel
+        });
+        let start = ThreadStart {
+            arg,
+            start_time: caller.data().start_time,
+            deadline: caller.data().deadline,
+            fuel,
+        };
+        let thread = self.clone();
+        let engine = caller.engine().clone();
+        let id = self.id;
+        let res = std::thread::Builder::new()
+            .name(format!("extism-{id}-{tid}"))
+            .spawn(move || {
+                let _slot = slot;
+                if let Err(e) = thread.run(&engine, tid, start) {
+                    error!(plugin = id.to_string(), "guest thread {tid} failed: {e:?}");
+                }
+            });
+        match res {
+            Ok(handle) => {
+                let mut handles = self.pool.handles();
+                handles.retain(|x| !x.is_finished());
+                handles.push(handle);
+                tid
+            }
+            Err(e) => {
+                if let (Some(fuel), Ok(remaining)) = (fuel, caller.get_fuel()) {
+                    let _ = caller.set_fuel(remaining + fuel);
+                }
+                error!(
+                    plugin = self.id.to_string(),
+                    "unable to spawn guest thread: {e}"
+                );
+                -1
+            }
+        }
+    }
+
+    fn run(self, engine: &Engine, tid: i32, start: ThreadStart) -> Result<(), Error> {
+        let mut data = CurrentPlugin::new(
+            self.manifest,
+            false,
+            Default::default(),
+            None,
+            false,
+            self.id,
+            Default::default(),
+        )?;
+        let timeout = data
+            .manifest
+            .timeout_ms
+            .map(|x| start.start_time + std::time::Duration::from_millis(x));
+        let deadline = match (timeout, start.deadline) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        data.start_time = start.start_time;
+        data.deadline = start.deadline;
+        data.wasi = self.wasi.map(|ctx| Wasi { ctx });
+        data.threads = Some(self.pool.clone());
+
+        // The plugin's timer increments the epoch when the call times out or is cancelled, and
+        // `ThreadPool::stop` increments it when the call returns
+        let mut store = Store::new(engine, data);
+        let pool = self.pool;
+        store.epoch_deadline_callback(move |_| {
+            if pool.interrupted(deadline) {
+                return Err(wasmtime::Trap::Interrupt.into());
+            }
+            Ok(UpdateDeadline::Continue(1))
+        });
+        store.set_epoch_deadline(1);
+        if let Some(fuel) = start.fuel {
+            store.set_fuel(fuel)?;
+        }
+        let (instance_pre, mut linker, _) = plugin::relink(
+            &mut store,
+            &self.host_linker,
+            &self.modules,
+            Some(self.memories),
+        )?;
+        let store_ptr = &mut store as *mut _;
+        let linker_ptr = &mut linker as *mut _;
+        store.data_mut().store = store_ptr;
+        store.data_mut().linker = linker_ptr;
+
+        let arg = start.arg;
+        let instance = instance_pre.instantiate(&mut store)?;
+        let start = instance.get_typed_func::<(i32, i32), ()>(&mut store, "wasi_thread_start")?;
+
+        // The epoch deadline is set before this check, so a thread that starts while the threads are being
+        // stopped is either stopped here or interrupted by the next epoch
+        if store
+            .data()
+            .threads
+            .as_ref()
+            .is_some_and(|x| x.interrupted(deadline))
+        {
+            return Ok(());
+        }
+        start.call(&mut store, (tid, arg))?;
+        Ok(())
+    }
+}
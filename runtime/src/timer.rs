@@ -1,10 +1,15 @@
 use crate::*;
 
+#[cfg_attr(target_family = "wasm", allow(dead_code))]
 pub(crate) enum TimerAction {
     Start {
         id: uuid::Uuid,
         engine: Engine,
         duration: Option<std::time::Duration>,
+        stall: Option<(
+            std::time::Duration,
+            std::sync::Arc<std::sync::atomic::AtomicBool>,
+        )>,
     },
     Stop {
         id: uuid::Uuid,
@@ -15,12 +20,29 @@ pub(crate) enum TimerAction {
     Shutdown,
 }
 
+/// Sends actions to the timer thread, there is no timer thread on wasm32 hosts so actions are ignored
+#[derive(Clone)]
+pub(crate) struct TimerTx(Option<std::sync::mpsc::Sender<TimerAction>>);
+
+impl TimerTx {
+    pub(crate) fn send(
+        &self,
+        action: TimerAction,
+    ) -> Result<(), std::sync::mpsc::SendError<TimerAction>> {
+        match &self.0 {
+            Some(tx) => tx.send(action),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
 pub(crate) struct Timer {
     pub tx: std::sync::mpsc::Sender<TimerAction>,
     pub thread: Option<std::thread::JoinHandle<()>>,
 }
 
-#[cfg(not(target_family = "windows"))]
+#[cfg(not(any(target_family = "windows", target_family = "wasm")))]
 extern "C" fn cleanup_timer() {
     let mut timer = match TIMER.lock() {
         Ok(x) => x,
@@ -29,10 +51,12 @@ extern "C" fn cleanup_timer() {
     drop(timer.take());
 }
 
+#[cfg(not(target_family = "wasm"))]
 static TIMER: std::sync::Mutex<Option<Timer>> = std::sync::Mutex::new(None);
 
+#[cfg(not(target_family = "wasm"))]
 impl Timer {
-    pub(crate) fn tx() -> std::sync::mpsc::Sender<TimerAction> {
+    pub(crate) fn tx() -> TimerTx {
         let mut timer = match TIMER.lock() {
             Ok(x) => x,
             Err(e) => e.into_inner(),
@@ -40,10 +64,10 @@ impl Timer {
 
         let timer = &mut *timer;
 
-        match timer {
+        TimerTx(Some(match timer {
             None => Timer::init(timer),
             Some(t) => t.tx.clone(),
-        }
+        }))
     }
 
     pub fn init(timer: &mut Option<Timer>) -> std::sync::mpsc::Sender<TimerAction> {
@@ -58,14 +82,17 @@ impl Timer {
                             id,
                             engine,
                             duration,
+                            stall,
                         } => {
-                            let timeout = duration.map(|x| std::time::Instant::now() + x);
+                            let now = std::time::Instant::now();
+                            let timeout = duration.map(|x| now + x);
+                            let stall = stall.map(|(x, flag)| (now + x, flag));
                             trace!(
                                 plugin = id.to_string(),
                                 "start event with timeout: {:?}",
                                 duration
                             );
-                            plugins.insert(id, (engine, timeout));
+                            plugins.insert(id, (engine, timeout, stall));
                         }
                         TimerAction::Stop { id } => {
                             trace!(plugin = id.to_string(), "handling stop event");
@@ -73,13 +100,13 @@ impl Timer {
                         }
                         TimerAction::Cancel { id } => {
                             trace!(plugin = id.to_string(), "handling cancel event");
-                            if let Some((engine, _)) = plugins.remove(&id) {
+                            if let Some((engine, _, _)) = plugins.remove(&id) {
                                 engine.increment_epoch();
                             }
                         }
                         TimerAction::Shutdown => {
                             trace!("Shutting down timer");
-                            for (id, (engine, _)) in plugins.iter() {
+                            for (id, (engine, _, _)) in plugins.iter() {
                                 trace!(plugin = id.to_string(), "handling shutdown event");
                                 engine.increment_epoch();
                             }
@@ -98,22 +125,37 @@ impl Timer {
 
                 let mut timeout: Option<std::time::Duration> = None;
 
-                plugins.retain(|_k, (engine, end)| {
+                let mut wait_until = |end: std::time::Instant, now: std::time::Instant| {
+                    let time_left = (end - now).saturating_sub(std::time::Duration::from_millis(1));
+                    if let Some(t) = &timeout {
+                        if time_left < *t {
+                            timeout = Some(time_left);
+                        }
+                    } else {
+                        timeout = Some(time_left);
+                    }
+                };
+
+                plugins.retain(|_k, (engine, end, stall)| {
+                    let now = std::time::Instant::now();
                     if let Some(end) = end {
-                        let now = std::time::Instant::now();
                         if *end <= now {
                             engine.increment_epoch();
                             return false;
                         } else {
-                            let time_left =
-                                (*end - now).saturating_sub(std::time::Duration::from_millis(1));
-                            if let Some(t) = &timeout {
-                                if time_left < *t {
-                                    timeout = Some(time_left);
-                                }
-                            } else {
-                                timeout = Some(time_left);
-                            }
+                            wait_until(*end, now);
+                        }
+                    }
+
+                    // Interrupt the plugin once when it passes the stall threshold, the epoch
+                    // callback captures a snapshot and lets the call continue
+                    if let Some((at, flag)) = stall {
+                        if *at <= now {
+                            flag.store(true, std::sync::atomic::Ordering::SeqCst);
+                            engine.increment_epoch();
+                            *stall = None;
+                        } else {
+                            wait_until(*at, now);
                         }
                     }
 
@@ -144,6 +186,7 @@ impl Timer {
     }
 }
 
+#[cfg(not(target_family = "wasm"))]
 impl Drop for Timer {
     fn drop(&mut self) {
         let _ = self.tx.send(TimerAction::Shutdown);
@@ -152,3 +195,14 @@ impl Drop for Timer {
         }
     }
 }
+
+// Plugins can't be interrupted without a timer thread, so timeouts aren't enforced on wasm32 hosts
+#[cfg(target_family = "wasm")]
+pub(crate) enum Timer {}
+
+#[cfg(target_family = "wasm")]
+impl Timer {
+    pub(crate) fn tx() -> TimerTx {
+        TimerTx(None)
+    }
+}
@@ -0,0 +1,78 @@
+use std::marker::PhantomData;
+
+use crate::*;
+
+/// A handle to an exported function that has already been checked, acquired using
+/// `Plugin::typed_function`. The input and output types are fixed when the handle is created, so the
+/// handle can be called repeatedly without naming them again.
+///
+/// ```no_run
+/// # fn example(mut plugin: extism::Plugin) -> Result<(), extism::Error> {
+/// let count_vowels = plugin.typed_function::<&str, String>("count_vowels")?;
+/// for input in ["a", "bb", "ccc"] {
+///     let output = count_vowels.call(&mut plugin, input)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct TypedFunction<I, O> {
+    name: String,
+    plugin: uuid::Uuid,
+    _types: PhantomData<fn(I) -> O>,
+}
+
+impl<I, O> Clone for TypedFunction<I, O> {
+    fn clone(&self) -> Self {
+        TypedFunction {
+            name: self.name.clone(),
+            plugin: self.plugin,
+            _types: PhantomData,
+        }
+    }
+}
+
+impl<I, O> std::fmt::Debug for TypedFunction<I, O> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TypedFunction")
+            .field("name", &self.name)
+            .field("plugin", &self.plugin)
+            .finish()
+    }
+}
+
+impl<I, O> TypedFunction<I, O> {
+    pub(crate) fn new(plugin: &Plugin, name: &str) -> Result<Self, Error> {
+        if !plugin.function_exists(name) {
+            anyhow::bail!(
+                "{name} isn't an exported function that takes no parameters and returns nothing or an i32"
+            );
+        }
+        Ok(TypedFunction {
+            name: name.to_string(),
+            plugin: plugin.id,
+            _types: PhantomData,
+        })
+    }
+
+    /// The name of the exported function
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Call the function, `plugin` must be the plugin the handle was acquired from
+    pub fn call<'a, 'b>(&self, plugin: &'b mut Plugin, input: I) -> Result<O, Error>
+    where
+        I: ToBytes<'a>,
+        O: FromBytes<'b>,
+    {
+        if plugin.id != self.plugin {
+            anyhow::bail!(
+                "function handle for {} was acquired from plugin {}, not {}",
+                self.name,
+                self.plugin,
+                plugin.id
+            );
+        }
+        plugin.call(&self.name, input)
+    }
+}
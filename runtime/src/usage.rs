@@ -0,0 +1,201 @@
+use std::sync::Arc;
+
+use crate::*;
+
+/// Usage information for a single host function, returned by `Plugin::host_function_usage`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostFunctionUsage {
+    /// Host function name
+    pub name: String,
+
+    /// Number of times the function has been called
+    pub calls: u64,
+
+    /// Deprecation message, if the function has been marked as deprecated
+    pub deprecated: Option<String>,
+}
+
+/// Host function names, each function is assigned an index when the host linker is created so calls
+/// can be tracked without hashing or comparing the name every time
+#[derive(Default, Debug)]
+pub(crate) struct HostFunctionNames(Vec<String>);
+
+impl HostFunctionNames {
+    /// Get the index for `name`, functions that shadow another function share its index
+    pub(crate) fn add(&mut self, name: &str) -> usize {
+        if let Some(index) = self.0.iter().position(|x| x == name) {
+            return index;
+        }
+        self.0.push(name.to_string());
+        self.0.len() - 1
+    }
+
+    pub(crate) fn get(&self, index: usize) -> &str {
+        self.0.get(index).map(String::as_str).unwrap_or_default()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+}
+
+/// Tracks which host functions a plugin calls
+#[derive(Default)]
+pub(crate) struct HostUsage {
+    names: Arc<HostFunctionNames>,
+    calls: Vec<u64>,
+    deprecated: Vec<Option<String>>,
+    warned: Vec<bool>,
+}
+
+impl HostUsage {
+    pub(crate) fn new(names: Arc<HostFunctionNames>, deprecated: BTreeMap<String, String>) -> Self {
+        HostUsage {
+            calls: vec![0; names.len()],
+            deprecated: names.iter().map(|x| deprecated.get(x).cloned()).collect(),
+            warned: vec![false; names.len()],
+            names,
+        }
+    }
+
+    pub(crate) fn name(&self, index: usize) -> &str {
+        self.names.get(index)
+    }
+
+    /// Record a call to a host function, the name and deprecation message are returned the first time a
+    /// deprecated function is called
+    pub(crate) fn record(&mut self, index: usize) -> Option<(&str, &str)> {
+        *self.calls.get_mut(index)? += 1;
+
+        let msg = self.deprecated[index].as_deref()?;
+        if self.warned[index] {
+            return None;
+        }
+        self.warned[index] = true;
+        Some((self.names.get(index), msg))
+    }
+
+    pub(crate) fn summary(&self) -> Vec<HostFunctionUsage> {
+        let mut summary: Vec<_> = self
+            .calls
+            .iter()
+            .enumerate()
+            .filter(|(_, calls)| **calls > 0)
+            .map(|(index, calls)| HostFunctionUsage {
+                name: self.names.get(index).to_string(),
+                calls: *calls,
+                deprecated: self.deprecated[index].clone(),
+            })
+            .collect();
+        summary.sort_by(|a, b| a.name.cmp(&b.name));
+        summary
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.calls.iter_mut().for_each(|x| *x = 0);
+    }
+}
+
+/// Host function statistics for the most recent call, returned by `Plugin::host_call_stats`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostFunctionStats {
+    /// Host function name
+    pub name: String,
+
+    /// Number of times the function was called
+    pub calls: u64,
+
+    /// Total time spent in the function
+    pub total_time: std::time::Duration,
+
+    /// Time spent in the slowest call to the function
+    pub max_time: std::time::Duration,
+
+    /// Number of bytes of plugin memory read by the function using `CurrentPlugin::memory_bytes`
+    pub bytes_read: u64,
+
+    /// Number of bytes of plugin memory allocated by the function, including memory used to return values
+    pub bytes_written: u64,
+}
+
+/// Tracks the time and memory used by each host function during a single call
+#[derive(Default)]
+pub(crate) struct HostCallStats {
+    names: Arc<HostFunctionNames>,
+    stats: Vec<HostFunctionStats>,
+    current: Option<(usize, std::time::Instant)>,
+}
+
+impl HostCallStats {
+    pub(crate) fn new(names: Arc<HostFunctionNames>) -> Self {
+        HostCallStats {
+            stats: vec![HostFunctionStats::default(); names.len()],
+            names,
+            current: None,
+        }
+    }
+
+    /// Start timing a call to a host function
+    pub(crate) fn begin(&mut self, index: usize) {
+        self.current = Some((index, std::time::Instant::now()));
+    }
+
+    /// Finish timing the host function call started by `begin`
+    pub(crate) fn end(&mut self) {
+        let Some((index, start)) = self.current.take() else {
+            return;
+        };
+        let elapsed = start.elapsed();
+        if let Some(stats) = self.stats.get_mut(index) {
+            stats.calls += 1;
+            stats.total_time += elapsed;
+            stats.max_time = stats.max_time.max(elapsed);
+        }
+    }
+
+    fn current(&mut self) -> Option<&mut HostFunctionStats> {
+        let (index, _) = self.current?;
+        self.stats.get_mut(index)
+    }
+
+    pub(crate) fn read(&mut self, n: u64) {
+        if let Some(stats) = self.current() {
+            stats.bytes_read += n;
+        }
+    }
+
+    pub(crate) fn written(&mut self, n: u64) {
+        if let Some(stats) = self.current() {
+            stats.bytes_written += n;
+        }
+    }
+
+    /// The number of host function calls made during the call
+    pub(crate) fn total_calls(&self) -> u64 {
+        self.stats.iter().map(|x| x.calls).sum()
+    }
+
+    pub(crate) fn summary(&self) -> Vec<HostFunctionStats> {
+        let mut summary: Vec<_> = self
+            .stats
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.calls > 0)
+            .map(|(index, x)| HostFunctionStats {
+                name: self.names.get(index).to_string(),
+                ..x.clone()
+            })
+            .collect();
+        summary.sort_by(|a, b| a.name.cmp(&b.name));
+        summary
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.stats.fill(HostFunctionStats::default());
+        self.current = None;
+    }
+}
@@ -0,0 +1,186 @@
+use std::sync::{Arc, Mutex};
+
+use crate::*;
+
+/// A `VarStore` stores the variables set using `extism:host/env::var_set`, in place of the in-memory map
+/// that's used by default, see `PluginBuilder::with_var_store`. Variables in a `VarStore` aren't cleared
+/// when a plugin is reset, so they can outlive the plugin.
+///
+/// The size of the store is limited by `memory.max_var_bytes` in the manifest, which is checked using
+/// `VarStore::size` before a variable is set.
+pub trait VarStore: Send + Sync {
+    /// Get the value for `key`, `Ok(None)` should be returned if the key doesn't exist
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Set the value for `key`
+    fn set(&self, key: &str, value: &[u8]) -> Result<(), Error>;
+
+    /// Remove `key`, removing a key that doesn't exist isn't an error
+    fn delete(&self, key: &str) -> Result<(), Error>;
+
+    /// List all keys
+    fn list(&self) -> Result<Vec<String>, Error>;
+
+    /// The number of bytes used by all keys and values, this is called every time a variable is set. The
+    /// default implementation reads every value, so larger stores should keep track of their size instead
+    fn size(&self) -> Result<u64, Error> {
+        let mut size = 0;
+        for key in self.list()? {
+            let value = self.get(&key)?.map(|x| x.len()).unwrap_or_default();
+            size += (key.len() + value) as u64;
+        }
+        Ok(size)
+    }
+
+    /// Files and directories the store reads and writes, they stay accessible to host functions when
+    /// `Hardening` restricts filesystem access
+    fn local_paths(&self) -> Vec<std::path::PathBuf> {
+        vec![]
+    }
+}
+
+impl<T: VarStore + ?Sized> VarStore for Arc<T> {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        (**self).get(key)
+    }
+
+    fn set(&self, key: &str, value: &[u8]) -> Result<(), Error> {
+        (**self).set(key, value)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Error> {
+        (**self).delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, Error> {
+        (**self).list()
+    }
+
+    fn size(&self) -> Result<u64, Error> {
+        (**self).size()
+    }
+
+    fn local_paths(&self) -> Vec<std::path::PathBuf> {
+        (**self).local_paths()
+    }
+}
+
+/// Stores variables in a `KvStore` under a key prefix, so a `SledKvStore` or `RedisKvStore` can be used to
+/// persist variables across restarts. Plugins that shouldn't share variables should use different
+/// prefixes.
+///
+/// The size of the variables is counted the first time it's needed and then updated as variables are set
+/// and deleted, so changes made to the underlying `KvStore` without going through this `KvVarStore`
+/// aren't included.
+///
+/// ```no_run
+/// # use extism::*;
+/// # fn example(manifest: Manifest, kv: impl KvStore + 'static) -> Result<(), Error> {
+/// let store = KvVarStore::new(kv, "my-plugin/");
+/// let plugin = PluginBuilder::new(manifest).with_var_store(store).build()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct KvVarStore {
+    store: Arc<dyn KvStore>,
+    prefix: String,
+    size: Mutex<Option<u64>>,
+}
+
+impl KvVarStore {
+    /// Create a new `KvVarStore`, keys are stored as `prefix` followed by the variable name
+    pub fn new(store: impl KvStore + 'static, prefix: impl Into<String>) -> Self {
+        KvVarStore {
+            store: Arc::new(store),
+            prefix: prefix.into(),
+            size: Mutex::new(None),
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{key}", self.prefix)
+    }
+
+    fn lock_size(&self) -> std::sync::MutexGuard<'_, Option<u64>> {
+        match self.size.lock() {
+            Ok(x) => x,
+            Err(e) => e.into_inner(),
+        }
+    }
+
+    // The number of bytes used by `key` and its value, if it exists
+    fn entry_size(&self, key: &str) -> Result<u64, Error> {
+        Ok(self
+            .store
+            .get(&self.key(key))?
+            .map(|x| (key.len() + x.len()) as u64)
+            .unwrap_or_default())
+    }
+}
+
+impl std::fmt::Debug for KvVarStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KvVarStore")
+            .field("prefix", &self.prefix)
+            .finish()
+    }
+}
+
+impl VarStore for KvVarStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        self.store.get(&self.key(key))
+    }
+
+    fn set(&self, key: &str, value: &[u8]) -> Result<(), Error> {
+        // The lock is held while the value is replaced so concurrent updates are counted correctly
+        let mut size = self.lock_size();
+        let existing = match *size {
+            Some(_) => self.entry_size(key)?,
+            None => 0,
+        };
+        self.store.set(&self.key(key), value, None)?;
+        if let Some(size) = size.as_mut() {
+            *size = size.saturating_sub(existing) + (key.len() + value.len()) as u64;
+        }
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Error> {
+        let mut size = self.lock_size();
+        let existing = match *size {
+            Some(_) => self.entry_size(key)?,
+            None => 0,
+        };
+        self.store.delete(&self.key(key))?;
+        if let Some(size) = size.as_mut() {
+            *size = size.saturating_sub(existing);
+        }
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, Error> {
+        Ok(self
+            .store
+            .list(&self.prefix)?
+            .into_iter()
+            .filter_map(|x| x.strip_prefix(&self.prefix).map(|x| x.to_string()))
+            .collect())
+    }
+
+    fn size(&self) -> Result<u64, Error> {
+        let mut size = self.lock_size();
+        if let Some(size) = *size {
+            return Ok(size);
+        }
+        let mut total = 0;
+        for key in self.list()? {
+            total += self.entry_size(&key)?;
+        }
+        *size = Some(total);
+        Ok(total)
+    }
+
+    fn local_paths(&self) -> Vec<std::path::PathBuf> {
+        self.store.local_paths()
+    }
+}
@@ -0,0 +1,415 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use http_body_util::BodyExt;
+use hyper::body::{Body, Bytes};
+use wasmtime::component::{Component, Resource, ResourceTable};
+use wasmtime_wasi::clocks::WasiClocksCtxView;
+use wasmtime_wasi::p2::bindings::clocks::monotonic_clock;
+use wasmtime_wasi::p2::{DynPollable, Pollable};
+use wasmtime_wasi::{HostMonotonicClock, HostWallClock, WasiCtx, WasiCtxView, WasiView};
+use wasmtime_wasi_http::io::TokioIo;
+use wasmtime_wasi_http::p2::bindings::http::types::{ErrorCode, Scheme};
+use wasmtime_wasi_http::p2::bindings::ProxyPre;
+use wasmtime_wasi_http::p2::body::HyperOutgoingBody;
+use wasmtime_wasi_http::p2::types::{HostFutureIncomingResponse, OutgoingRequestConfig};
+use wasmtime_wasi_http::p2::{
+    default_send_request, HttpResult, WasiHttpCtxView, WasiHttpHooks, WasiHttpView,
+};
+use wasmtime_wasi_http::WasiHttpCtx;
+
+use crate::current_plugin::MemoryLimiter;
+use crate::*;
+
+/// How often the engine epoch is incremented when there's a timeout, each increment lets running
+/// components yield so the timeout can be checked
+const EPOCH_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The response type returned by `HttpHandler::handle`
+pub type HttpHandlerResponse = hyper::Response<HyperOutgoingBody>;
+
+/// `HttpHandler` runs component plugins that export `wasi:http/incoming-handler`, so they can handle
+/// whole HTTP requests with streaming bodies
+///
+/// A new instance is created for each request. Outgoing requests made by the component using
+/// `wasi:http/outgoing-handler` are limited to the manifest's `allowed_hosts`, the manifest's `timeout_ms`
+/// applies to handling each request, including writing the response body, and `memory.max_pages` limits
+/// the component's memory. A tokio runtime is required.
+///
+/// The clock and random number generator can be replaced using `HttpHandler::with_clock` and
+/// `HttpHandler::with_random`.
+///
+/// With the `wasi-keyvalue` feature, `wasi:keyvalue` is also available to components, see
+/// `HttpHandler::with_kv_store`.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), extism::Error> {
+/// let manifest = extism::Manifest::new([extism::Wasm::file("handler.wasm")]);
+/// extism::HttpHandler::new(&manifest)?.serve("127.0.0.1:8080").await
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct HttpHandler {
+    pre: ProxyPre<HandlerState>,
+    allowed_hosts: Arc<Option<Vec<String>>>,
+    env: Arc<Vec<(String, String)>>,
+    timeout: Option<Duration>,
+    max_memory_bytes: Option<usize>,
+    wasi_sources: WasiSources,
+    #[cfg(feature = "wasi-keyvalue")]
+    kv_store: Option<Arc<dyn KvStore>>,
+}
+
+impl HttpHandler {
+    /// Compile the component from `manifest`, which should contain a single `Wasm::Data` or `Wasm::File`
+    pub fn new(manifest: &Manifest) -> Result<Self, Error> {
+        let wasm = match manifest.wasm.as_slice() {
+            [wasm] => wasm,
+            _ => anyhow::bail!("HttpHandler requires a manifest with exactly one component"),
+        };
+        let (data, hash) = match wasm {
+            Wasm::Data { data, meta } => (data.clone(), &meta.hash),
+            Wasm::File { path, meta } => (std::fs::read(path)?, &meta.hash),
+            Wasm::Url { .. } => anyhow::bail!("HttpHandler does not support loading from a URL"),
+            Wasm::Oci { .. } => {
+                anyhow::bail!("HttpHandler does not support loading from an OCI registry")
+            }
+        };
+        manifest::check_hash(hash, &data)?;
+
+        let engine = Engine::new(wasmtime::Config::new().epoch_interruption(true))?;
+        let component = Component::new(&engine, data)?;
+        let mut linker = wasmtime::component::Linker::new(&engine);
+        wasmtime_wasi::p2::add_to_linker_async(&mut linker)?;
+        wasmtime_wasi_http::p2::add_only_http_to_linker_async(&mut linker)?;
+        add_sleep_to_linker(&mut linker)?;
+        #[cfg(feature = "wasi-keyvalue")]
+        wasi_keyvalue::add_to_linker(&mut linker, |state: &mut HandlerState| {
+            wasi_keyvalue::WasiKeyValue {
+                store: state.kv_store.as_ref(),
+                table: &mut state.table,
+            }
+        })?;
+        let pre = ProxyPre::new(linker.instantiate_pre(&component)?)?;
+
+        // The epoch is incremented from its own thread, a component that never yields would keep a task
+        // on the same runtime from running. The thread exits once the engine has been dropped
+        if manifest.timeout_ms.is_some() {
+            let engine = engine.weak();
+            std::thread::spawn(move || {
+                while let Some(engine) = engine.upgrade() {
+                    engine.increment_epoch();
+                    drop(engine);
+                    std::thread::sleep(EPOCH_INTERVAL);
+                }
+            });
+        }
+        Ok(HttpHandler {
+            pre,
+            allowed_hosts: Arc::new(manifest.allowed_hosts.clone()),
+            env: Arc::new(manifest.config.clone().into_iter().collect()),
+            timeout: manifest.timeout_ms.map(Duration::from_millis),
+            max_memory_bytes: manifest.memory.max_pages.map(|x| x as usize * 65536),
+            wasi_sources: WasiSources::default(),
+            #[cfg(feature = "wasi-keyvalue")]
+            kv_store: None,
+        })
+    }
+
+    /// Use `clock` instead of the system clock for `wasi:clocks` and sleeps, for example a `FixedClock`
+    pub fn with_clock(mut self, clock: impl WasiClock + 'static) -> Self {
+        self.wasi_sources.clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// Use `random` instead of the system random number generator for `wasi:random`, for example a
+    /// `SeededRandom`
+    pub fn with_random(mut self, random: impl WasiRandom + 'static) -> Self {
+        self.wasi_sources.random = Some(Arc::new(random));
+        self
+    }
+
+    /// Implement `wasi:keyvalue` using the given `KvStore`, which can be shared with plugins using
+    /// `PluginBuilder::with_kv_store`. Without a store `open` returns `no-such-store`
+    ///
+    /// The bucket with an empty identifier uses the store's keys as-is, other buckets prefix keys with
+    /// `{identifier}/`. Counters used by `increment` are stored as decimal strings.
+    #[cfg(feature = "wasi-keyvalue")]
+    pub fn with_kv_store(mut self, store: impl KvStore + 'static) -> Self {
+        self.kv_store = Some(Arc::new(store));
+        self
+    }
+
+    /// Handle a single request, the manifest's `config` values are available to the component as
+    /// environment variables
+    pub async fn handle<B>(&self, req: hyper::Request<B>) -> Result<HttpHandlerResponse, Error>
+    where
+        B: Body<Data = Bytes> + Send + 'static,
+        B::Error: std::fmt::Display,
+    {
+        let req = req.map(|body| body.map_err(|e| ErrorCode::InternalError(Some(e.to_string()))));
+        let mut wasi = WasiCtx::builder();
+        wasi.envs(&self.env);
+        if let Some(clock) = &self.wasi_sources.clock {
+            wasi.wall_clock(Clock(clock.clone()))
+                .monotonic_clock(Clock(clock.clone()));
+        }
+        if let Some(r) = &self.wasi_sources.random {
+            let mut seed = [0; 16];
+            r.fill_bytes(&mut seed);
+            wasi.secure_random(random::Rng(r.clone()))
+                .insecure_random(random::Rng(r.clone()))
+                .insecure_random_seed(u128::from_le_bytes(seed));
+        }
+        let mut store = Store::new(
+            self.pre.engine(),
+            HandlerState {
+                wasi: wasi.build(),
+                clock: self.wasi_sources.clock.clone(),
+                http: WasiHttpCtx::new(),
+                table: ResourceTable::new(),
+                hooks: Hooks {
+                    allowed_hosts: self.allowed_hosts.clone(),
+                },
+                limiter: self.max_memory_bytes.map(MemoryLimiter::new),
+                #[cfg(feature = "wasi-keyvalue")]
+                kv_store: self.kv_store.clone(),
+            },
+        );
+        if store.data().limiter.is_some() {
+            store.limiter(|state| state.limiter.as_mut().unwrap());
+        }
+        // Yield to the runtime each time the epoch is incremented, so a component that never returns
+        // can't block the timeout
+        store.epoch_deadline_async_yield_and_update(1);
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        let req = store
+            .data_mut()
+            .http()
+            .new_incoming_request(Scheme::Http, req)?;
+        let out = store.data_mut().http().new_response_outparam(sender)?;
+
+        // The component keeps running after the response headers are sent to write the body
+        let pre = self.pre.clone();
+        let timeout = self.timeout;
+        let task = tokio::task::spawn(async move {
+            let call = async {
+                let proxy = pre.instantiate_async(&mut store).await?;
+                proxy
+                    .wasi_http_incoming_handler()
+                    .call_handle(store, req, out)
+                    .await
+            };
+            let Some(timeout) = timeout else {
+                return call.await;
+            };
+            match tokio::time::timeout(timeout, call).await {
+                Ok(res) => res,
+                Err(_) => Err(wasmtime::Error::new(Timeout)),
+            }
+        });
+
+        match receiver.await {
+            Ok(Ok(res)) => Ok(res),
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => {
+                let e = match task.await {
+                    Ok(Ok(())) => Error::msg("no response was set"),
+                    Ok(Err(e)) => e.into(),
+                    Err(e) => e.into(),
+                };
+                Err(e.context("wasi:http handler did not set a response"))
+            }
+        }
+    }
+
+    /// Listen on `addr` and handle HTTP/1.1 requests until an error occurs
+    pub async fn serve(self, addr: impl tokio::net::ToSocketAddrs) -> Result<(), Error> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        loop {
+            let (client, _) = listener.accept().await?;
+            let handler = self.clone();
+            tokio::task::spawn(async move {
+                let service = hyper::service::service_fn(move |req| {
+                    let handler = handler.clone();
+                    async move { handler.handle(req).await }
+                });
+                if let Err(e) = hyper::server::conn::http1::Builder::new()
+                    .keep_alive(true)
+                    .serve_connection(TokioIo::new(client), service)
+                    .await
+                {
+                    error!("error serving wasi:http connection: {e}");
+                }
+            });
+        }
+    }
+}
+
+struct HandlerState {
+    wasi: WasiCtx,
+    clock: Option<Arc<dyn WasiClock>>,
+    http: WasiHttpCtx,
+    table: ResourceTable,
+    hooks: Hooks,
+    limiter: Option<MemoryLimiter>,
+    #[cfg(feature = "wasi-keyvalue")]
+    kv_store: Option<Arc<dyn KvStore>>,
+}
+
+impl WasiView for HandlerState {
+    fn ctx(&mut self) -> WasiCtxView<'_> {
+        WasiCtxView {
+            ctx: &mut self.wasi,
+            table: &mut self.table,
+        }
+    }
+}
+
+impl WasiHttpView for HandlerState {
+    fn http(&mut self) -> WasiHttpCtxView<'_> {
+        WasiHttpCtxView {
+            ctx: &mut self.http,
+            table: &mut self.table,
+            hooks: &mut self.hooks,
+        }
+    }
+}
+
+/// Adapts a `WasiClock` to the `wasi:clocks` interfaces
+struct Clock(Arc<dyn WasiClock>);
+
+impl HostWallClock for Clock {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(1)
+    }
+
+    fn now(&self) -> Duration {
+        self.0.wall_clock()
+    }
+}
+
+impl HostMonotonicClock for Clock {
+    fn resolution(&self) -> u64 {
+        1
+    }
+
+    fn now(&self) -> u64 {
+        self.0.monotonic_clock().as_nanos() as u64
+    }
+}
+
+/// A pollable that sleeps using a `WasiClock`
+struct Sleep {
+    clock: Arc<dyn WasiClock>,
+    duration: Duration,
+    done: bool,
+}
+
+#[wasmtime_wasi::async_trait]
+impl Pollable for Sleep {
+    async fn ready(&mut self) {
+        if self.done {
+            return;
+        }
+
+        // `WasiClock::sleep` may block, so it's run outside of the async runtime
+        let clock = self.clock.clone();
+        let duration = self.duration;
+        let _ = tokio::task::spawn_blocking(move || clock.sleep(duration)).await;
+        self.done = true;
+    }
+}
+
+fn subscribe_sleep(
+    state: &mut HandlerState,
+    duration: impl FnOnce(&dyn WasiClock) -> Duration,
+) -> wasmtime::Result<Option<Resource<DynPollable>>> {
+    let Some(clock) = state.clock.clone() else {
+        return Ok(None);
+    };
+    let duration = duration(clock.as_ref());
+    let sleep = state.table.push(Sleep {
+        clock,
+        duration,
+        done: duration.is_zero(),
+    })?;
+    Ok(Some(wasmtime_wasi::p2::subscribe(&mut state.table, sleep)?))
+}
+
+/// Replace `wasi:clocks/monotonic-clock`, so sleeps use the handler's `WasiClock` when one is set
+fn add_sleep_to_linker(
+    linker: &mut wasmtime::component::Linker<HandlerState>,
+) -> Result<(), Error> {
+    linker.allow_shadowing(true);
+    let mut clock = linker.instance("wasi:clocks/monotonic-clock@0.2.6")?;
+    clock.func_wrap(
+        "now",
+        |mut store: wasmtime::StoreContextMut<'_, HandlerState>, (): ()| {
+            monotonic_clock::Host::now(&mut clocks_view(store.data_mut())).map(|x| (x,))
+        },
+    )?;
+    clock.func_wrap(
+        "resolution",
+        |mut store: wasmtime::StoreContextMut<'_, HandlerState>, (): ()| {
+            monotonic_clock::Host::resolution(&mut clocks_view(store.data_mut())).map(|x| (x,))
+        },
+    )?;
+    clock.func_wrap(
+        "subscribe-duration",
+        |mut store: wasmtime::StoreContextMut<'_, HandlerState>, (nanos,): (u64,)| {
+            let state = store.data_mut();
+            let duration = Duration::from_nanos(nanos);
+            match subscribe_sleep(state, |_| duration)? {
+                Some(x) => Ok((x,)),
+                None => {
+                    let mut view = clocks_view(state);
+                    monotonic_clock::Host::subscribe_duration(&mut view, nanos).map(|x| (x,))
+                }
+            }
+        },
+    )?;
+    clock.func_wrap(
+        "subscribe-instant",
+        |mut store: wasmtime::StoreContextMut<'_, HandlerState>, (when,): (u64,)| {
+            let state = store.data_mut();
+            let until = |clock: &dyn WasiClock| {
+                Duration::from_nanos(when).saturating_sub(clock.monotonic_clock())
+            };
+            match subscribe_sleep(state, until)? {
+                Some(x) => Ok((x,)),
+                None => {
+                    let mut view = clocks_view(state);
+                    monotonic_clock::Host::subscribe_instant(&mut view, when).map(|x| (x,))
+                }
+            }
+        },
+    )?;
+    linker.allow_shadowing(false);
+    Ok(())
+}
+
+fn clocks_view(state: &mut HandlerState) -> WasiClocksCtxView<'_> {
+    WasiClocksCtxView {
+        ctx: state.wasi.clocks(),
+        table: &mut state.table,
+    }
+}
+
+struct Hooks {
+    allowed_hosts: Arc<Option<Vec<String>>>,
+}
+
+impl WasiHttpHooks for Hooks {
+    fn send_request(
+        &mut self,
+        request: hyper::Request<HyperOutgoingBody>,
+        config: OutgoingRequestConfig,
+    ) -> HttpResult<HostFutureIncomingResponse> {
+        let host = request.uri().host().unwrap_or_default();
+        if !pdk::host_allowed(&self.allowed_hosts, host) {
+            return Err(ErrorCode::HttpRequestDenied.into());
+        }
+        Ok(default_send_request(request, config))
+    }
+}
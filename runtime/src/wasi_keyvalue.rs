@@ -0,0 +1,203 @@
+use std::sync::Arc;
+
+use wasmtime::component::{HasData, Resource, ResourceTable, ResourceTableError};
+
+use crate::*;
+
+mod bindings {
+    wasmtime::component::bindgen!({
+        path: "wit/keyvalue",
+        world: "wasi:keyvalue/imports",
+        imports: { default: trappable },
+        with: {
+            "wasi:keyvalue/store.bucket": crate::wasi_keyvalue::Bucket,
+        },
+        trappable_error_type: {
+            "wasi:keyvalue/store.error" => crate::wasi_keyvalue::KvError,
+        },
+    });
+}
+
+use bindings::wasi::keyvalue::{atomics, batch, store};
+
+/// Maximum number of keys returned by a single `list-keys` call
+const LIST_PAGE_SIZE: usize = 1000;
+
+// `KvError` and `Bucket` are used by the generated bindings, so they need to be `pub`
+pub enum KvError {
+    NoSuchStore,
+    Other(String),
+}
+
+impl From<Error> for KvError {
+    fn from(e: Error) -> Self {
+        KvError::Other(e.to_string())
+    }
+}
+
+impl From<ResourceTableError> for KvError {
+    fn from(e: ResourceTableError) -> Self {
+        KvError::Other(e.to_string())
+    }
+}
+
+/// An open `wasi:keyvalue` bucket, keys in a bucket are prefixed with `{identifier}/` in the
+/// `KvStore`, except for the empty identifier which uses the keys as-is
+pub struct Bucket {
+    prefix: String,
+}
+
+/// The `wasi:keyvalue` state for a component instance
+pub(crate) struct WasiKeyValue<'a> {
+    pub(crate) store: Option<&'a Arc<dyn KvStore>>,
+    pub(crate) table: &'a mut ResourceTable,
+}
+
+impl WasiKeyValue<'_> {
+    fn bucket(&self, bucket: &Resource<Bucket>) -> Result<(&dyn KvStore, String), KvError> {
+        let prefix = self.table.get(bucket)?.prefix.clone();
+        match self.store {
+            Some(store) => Ok((store.as_ref(), prefix)),
+            None => Err(KvError::NoSuchStore),
+        }
+    }
+}
+
+impl store::Host for WasiKeyValue<'_> {
+    fn open(&mut self, identifier: String) -> Result<Resource<Bucket>, KvError> {
+        if self.store.is_none() {
+            return Err(KvError::NoSuchStore);
+        }
+        let prefix = if identifier.is_empty() {
+            identifier
+        } else {
+            format!("{identifier}/")
+        };
+        Ok(self.table.push(Bucket { prefix })?)
+    }
+
+    fn convert_error(&mut self, err: KvError) -> wasmtime::Result<store::Error> {
+        Ok(match err {
+            KvError::NoSuchStore => store::Error::NoSuchStore,
+            KvError::Other(e) => store::Error::Other(e),
+        })
+    }
+}
+
+impl store::HostBucket for WasiKeyValue<'_> {
+    fn get(&mut self, bucket: Resource<Bucket>, key: String) -> Result<Option<Vec<u8>>, KvError> {
+        let (store, prefix) = self.bucket(&bucket)?;
+        Ok(store.get(&format!("{prefix}{key}"))?)
+    }
+
+    fn set(
+        &mut self,
+        bucket: Resource<Bucket>,
+        key: String,
+        value: Vec<u8>,
+    ) -> Result<(), KvError> {
+        let (store, prefix) = self.bucket(&bucket)?;
+        Ok(store.set(&format!("{prefix}{key}"), &value, None)?)
+    }
+
+    fn delete(&mut self, bucket: Resource<Bucket>, key: String) -> Result<(), KvError> {
+        let (store, prefix) = self.bucket(&bucket)?;
+        store.delete(&format!("{prefix}{key}"))?;
+        Ok(())
+    }
+
+    fn exists(&mut self, bucket: Resource<Bucket>, key: String) -> Result<bool, KvError> {
+        let (store, prefix) = self.bucket(&bucket)?;
+        Ok(store.get(&format!("{prefix}{key}"))?.is_some())
+    }
+
+    fn list_keys(
+        &mut self,
+        bucket: Resource<Bucket>,
+        cursor: Option<u64>,
+    ) -> Result<store::KeyResponse, KvError> {
+        let (store, prefix) = self.bucket(&bucket)?;
+        let keys = store.list(&prefix)?;
+
+        // The cursor is the index of the next key
+        let start = (cursor.unwrap_or_default() as usize).min(keys.len());
+        let end = (start + LIST_PAGE_SIZE).min(keys.len());
+        Ok(store::KeyResponse {
+            keys: keys[start..end]
+                .iter()
+                .map(|k| k[prefix.len()..].to_string())
+                .collect(),
+            cursor: (end < keys.len()).then_some(end as u64),
+        })
+    }
+
+    fn drop(&mut self, bucket: Resource<Bucket>) -> wasmtime::Result<()> {
+        self.table.delete(bucket)?;
+        Ok(())
+    }
+}
+
+impl atomics::Host for WasiKeyValue<'_> {
+    fn increment(
+        &mut self,
+        bucket: Resource<Bucket>,
+        key: String,
+        delta: u64,
+    ) -> Result<u64, KvError> {
+        let (store, prefix) = self.bucket(&bucket)?;
+        Ok(store.increment(&format!("{prefix}{key}"), delta)?)
+    }
+}
+
+impl batch::Host for WasiKeyValue<'_> {
+    fn get_many(
+        &mut self,
+        bucket: Resource<Bucket>,
+        keys: Vec<String>,
+    ) -> Result<Vec<Option<(String, Vec<u8>)>>, KvError> {
+        let (store, prefix) = self.bucket(&bucket)?;
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            let value = store.get(&format!("{prefix}{key}"))?;
+            values.push(value.map(|v| (key, v)));
+        }
+        Ok(values)
+    }
+
+    fn set_many(
+        &mut self,
+        bucket: Resource<Bucket>,
+        key_values: Vec<(String, Vec<u8>)>,
+    ) -> Result<(), KvError> {
+        let (store, prefix) = self.bucket(&bucket)?;
+        for (key, value) in key_values {
+            store.set(&format!("{prefix}{key}"), &value, None)?;
+        }
+        Ok(())
+    }
+
+    fn delete_many(&mut self, bucket: Resource<Bucket>, keys: Vec<String>) -> Result<(), KvError> {
+        let (store, prefix) = self.bucket(&bucket)?;
+        for key in keys {
+            store.delete(&format!("{prefix}{key}"))?;
+        }
+        Ok(())
+    }
+}
+
+struct HasWasiKeyValue;
+
+impl HasData for HasWasiKeyValue {
+    type Data<'a> = WasiKeyValue<'a>;
+}
+
+/// Add the `wasi:keyvalue/imports` interfaces to a component linker
+pub(crate) fn add_to_linker<T: Send + 'static>(
+    linker: &mut wasmtime::component::Linker<T>,
+    f: fn(&mut T) -> WasiKeyValue<'_>,
+) -> Result<(), Error> {
+    store::add_to_linker::<_, HasWasiKeyValue>(linker, f)?;
+    atomics::add_to_linker::<_, HasWasiKeyValue>(linker, f)?;
+    batch::add_to_linker::<_, HasWasiKeyValue>(linker, f)?;
+    Ok(())
+}
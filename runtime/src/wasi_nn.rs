@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::Context as _;
+use wasmtime_wasi_nn::wit::{ExecutionTarget, GraphEncoding};
+use wasmtime_wasi_nn::witx::WasiNnCtx;
+use wasmtime_wasi_nn::{backend, Graph, GraphRegistry};
+
+use crate::*;
+
+/// Configures the `wasi-nn` host functions, see `PluginBuilder::with_wasi_nn`
+///
+/// The available backends depend on which features are enabled: `wasi-nn-openvino` and `wasi-nn-onnx`.
+/// Backend libraries are loaded from the host system when a graph is loaded.
+#[derive(Debug, Default, Clone)]
+pub struct WasiNn {
+    graphs: Vec<(String, PathBuf)>,
+}
+
+impl WasiNn {
+    /// Create a new `WasiNn` config without any preloaded graphs, plugins can still load graphs
+    /// from Wasm memory using `load`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Preload a graph (model) from a directory when the plugin is compiled, plugins can access it by
+    /// calling `load_by_name` with the name of the directory. `encoding` is the name of the backend,
+    /// for example `openvino` or `onnx`.
+    pub fn with_graph(mut self, encoding: impl Into<String>, dir: impl Into<PathBuf>) -> Self {
+        self.graphs.push((encoding.into(), dir.into()));
+        self
+    }
+
+    pub(crate) fn load(&self) -> Result<Graphs, Error> {
+        let mut backends = backend::list();
+        let mut graphs = Graphs::default();
+        for (encoding, dir) in self.graphs.iter() {
+            let kind: GraphEncoding = encoding.parse()?;
+            let Some(backend) = backends.iter_mut().find(|b| b.encoding() == kind) else {
+                anyhow::bail!("wasi-nn backend is not enabled: {encoding}");
+            };
+            let Some(backend) = backend.as_dir_loadable() else {
+                anyhow::bail!(
+                    "wasi-nn backend {encoding} does not support loading from a directory"
+                );
+            };
+            let Some(name) = dir.file_name() else {
+                anyhow::bail!("invalid wasi-nn graph directory: {}", dir.display());
+            };
+            let graph = backend
+                .load_from_dir(dir, ExecutionTarget::Cpu)
+                .with_context(|| format!("unable to load wasi-nn graph: {}", dir.display()))?;
+            graphs.0.insert(name.to_string_lossy().into_owned(), graph);
+        }
+        Ok(graphs)
+    }
+}
+
+/// Graphs preloaded by `CompiledPlugin`, these are shared between all plugin instances
+#[derive(Default, Clone)]
+pub(crate) struct Graphs(HashMap<String, Graph>);
+
+impl GraphRegistry for Graphs {
+    fn get(&self, name: &str) -> Option<&Graph> {
+        self.0.get(name)
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut Graph> {
+        self.0.get_mut(name)
+    }
+}
+
+impl Graphs {
+    /// Create a new `wasi-nn` context for a plugin instance
+    pub(crate) fn ctx(&self) -> WasiNnCtx {
+        WasiNnCtx::new(backend::list(), self.clone().into())
+    }
+}
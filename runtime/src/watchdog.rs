@@ -0,0 +1,69 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::*;
+
+/// Detects calls that run longer than a soft threshold, see `PluginBuilder::with_stall_threshold`
+///
+/// When the threshold is reached the timer thread sets `stalled` and increments the engine epoch, the
+/// epoch callback installed by `Watchdog::install` then captures a backtrace and lets the call continue.
+pub(crate) struct Watchdog {
+    pub(crate) threshold: Duration,
+    pub(crate) stalled: Arc<AtomicBool>,
+}
+
+impl Watchdog {
+    pub(crate) fn new(threshold: Duration) -> Self {
+        Watchdog {
+            threshold,
+            stalled: Default::default(),
+        }
+    }
+
+    /// Install an epoch callback for the next call, this replaces `Store::epoch_deadline_trap` so calls
+    /// are only interrupted when they're cancelled or the timeout has passed
+    pub(crate) fn install(
+        &self,
+        store: &mut Store<CurrentPlugin>,
+        function: &str,
+        timeout: Option<Duration>,
+        cancelled: Arc<AtomicBool>,
+        fuel: Option<u64>,
+    ) {
+        let start = Instant::now();
+        let function = function.to_string();
+        let stalled = self.stalled.clone();
+        stalled.store(false, Ordering::SeqCst);
+
+        store.epoch_deadline_callback(move |mut ctx| {
+            if cancelled.load(Ordering::SeqCst) || timeout.is_some_and(|t| start.elapsed() >= t) {
+                return Err(wasmtime::Trap::Interrupt.into());
+            }
+
+            if stalled.swap(false, Ordering::SeqCst) {
+                let elapsed = start.elapsed();
+                let backtrace = WasmBacktrace::force_capture(&ctx).to_string();
+                let fuel_consumed =
+                    fuel.and_then(|f| ctx.get_fuel().ok().map(|x| f.saturating_sub(x)));
+                let data = ctx.data();
+                warn!(
+                    plugin = data.id.to_string(),
+                    "call to {function} has been running for {elapsed:?} (fuel consumed: {fuel_consumed:?})\n{backtrace}"
+                );
+                if let Some(events) = &data.events {
+                    events.emit(PluginEvent::Stalled {
+                        plugin: data.id,
+                        function: function.clone(),
+                        elapsed,
+                        fuel_consumed,
+                        backtrace,
+                    });
+                }
+            }
+
+            profiler::sample(&mut ctx);
+            Ok(UpdateDeadline::Continue(1))
+        });
+    }
+}
@@ -0,0 +1,545 @@
+//! Generate typed bindings from a WIT world
+//!
+//! `generate` produces Rust source for a world, usually from a build script:
+//!
+//! ```no_run
+//! // build.rs
+//! let out = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+//! let bindings = extism::wit::generate_file("wit", Some("greeter")).unwrap();
+//! std::fs::write(out.join("greeter.rs"), bindings).unwrap();
+//! println!("cargo:rerun-if-changed=wit");
+//! ```
+//!
+//! The output is then included using `include!(concat!(env!("OUT_DIR"), "/greeter.rs"))`, the generated
+//! types derive `serde::Serialize` and `serde::Deserialize`, so the crate needs to depend on `serde`.
+//!
+//! For a world named `greeter` the generated code contains:
+//!
+//! - A Rust type for every named WIT type used by the world
+//! - `GreeterImports`, a trait with a method for every imported function
+//! - `Greeter`, a wrapper around `Plugin` with a method for every exported function and
+//!   `Greeter::add_imports`, which registers the `GreeterImports` implementation with a `PluginBuilder`
+//!
+//! Functions imported directly by the world are registered in the `extism:host/user` namespace, functions
+//! from imported interfaces use the interface name (for example `example:app/logging`) as the namespace.
+//!
+//! Values are passed using the same encoding as `host_fn!` and `Plugin::call`: `string` and `list<u8>`
+//! are passed as raw bytes, `bool`, `u32`, `u64`, `s32`, `s64`, `f32` and `f64` use `ToBytes`, and all
+//! other types are encoded as JSON. Exported functions with more than one parameter receive their
+//! arguments as a JSON array. Resources, flags, maps, futures and streams are not supported.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use wit_parser::{Function, Resolve, Type, TypeDefKind, TypeId, WorldItem, WorldKey};
+
+use crate::{Error, EXTISM_USER_MODULE};
+
+/// Generate bindings for a world defined in a WIT source string, `world` can be omitted if the package
+/// only contains a single world
+pub fn generate(wit: &str, world: Option<&str>) -> Result<String, Error> {
+    let mut resolve = Resolve::default();
+    let pkg = resolve.push_source("bindings.wit", wit)?;
+    Generator::new(&resolve).world(pkg, world)
+}
+
+/// Generate bindings for a world defined in a WIT file or directory, dependencies are loaded from the
+/// `deps` directory
+pub fn generate_file(
+    path: impl AsRef<std::path::Path>,
+    world: Option<&str>,
+) -> Result<String, Error> {
+    let mut resolve = Resolve::default();
+    let (pkg, _) = resolve.push_path(path)?;
+    Generator::new(&resolve).world(pkg, world)
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "crate", "do", "dyn", "else",
+    "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop", "macro",
+    "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "static", "struct",
+    "super", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual",
+    "where", "while", "yield",
+];
+
+fn snake(name: &str) -> String {
+    let name = name.replace('-', "_");
+    if KEYWORDS.contains(&name.as_str()) {
+        format!("r#{name}")
+    } else {
+        name
+    }
+}
+
+fn camel(name: &str) -> String {
+    name.split('-')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(c) => c.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+struct Func<'a> {
+    // Import namespace
+    namespace: String,
+    rust_name: String,
+    func: &'a Function,
+}
+
+struct Generator<'a> {
+    resolve: &'a Resolve,
+    out: String,
+}
+
+impl<'a> Generator<'a> {
+    fn new(resolve: &'a Resolve) -> Self {
+        Generator {
+            resolve,
+            out: String::new(),
+        }
+    }
+
+    fn world(mut self, pkg: wit_parser::PackageId, world: Option<&str>) -> Result<String, Error> {
+        let world_id = self.resolve.select_world(&[pkg], world)?;
+        let world = &self.resolve.worlds[world_id];
+        let imports = self.functions(world.imports.iter())?;
+        let exports = self.functions(world.exports.iter())?;
+
+        // Collect the named types used by the world
+        let mut types = BTreeSet::new();
+        for item in world.imports.values().chain(world.exports.values()) {
+            match item {
+                WorldItem::Type { id, .. } => self.visit(&Type::Id(*id), &mut types),
+                WorldItem::Interface { id, .. } => {
+                    for id in self.resolve.interfaces[*id].types.values() {
+                        self.visit(&Type::Id(*id), &mut types);
+                    }
+                }
+                WorldItem::Function(_) => (),
+            }
+        }
+        for f in imports.iter().chain(exports.iter()) {
+            for param in f.func.params.iter() {
+                self.visit(&param.ty, &mut types);
+            }
+            if let Some(ty) = &f.func.result {
+                self.visit(ty, &mut types);
+            }
+        }
+
+        writeln!(
+            self.out,
+            "// Generated by `extism::wit` from the `{}` world, do not edit",
+            world.name
+        )?;
+        for id in types {
+            self.typedef(id)?;
+        }
+
+        if !imports.is_empty() {
+            self.imports_trait(&world.name, &imports)?;
+        }
+        self.plugin_struct(&world.name, &imports, &exports)?;
+        Ok(self.out)
+    }
+
+    fn functions(
+        &self,
+        items: impl Iterator<Item = (&'a WorldKey, &'a WorldItem)>,
+    ) -> Result<Vec<Func<'a>>, Error> {
+        let mut funcs = vec![];
+        for (key, item) in items {
+            match item {
+                WorldItem::Function(func) => funcs.push(Func {
+                    namespace: EXTISM_USER_MODULE.to_string(),
+                    rust_name: snake(&func.name),
+                    func,
+                }),
+                WorldItem::Interface { id, .. } => {
+                    let iface = &self.resolve.interfaces[*id];
+                    let namespace = self.resolve.name_world_key(key);
+                    let prefix = match (key, &iface.name) {
+                        (WorldKey::Name(name), _) | (_, Some(name)) => name.clone(),
+                        _ => anyhow::bail!("unnamed WIT interface: {namespace}"),
+                    };
+                    for func in iface.functions.values() {
+                        funcs.push(Func {
+                            namespace: namespace.clone(),
+                            rust_name: snake(&format!("{prefix}-{}", func.name)),
+                            func,
+                        });
+                    }
+                }
+                WorldItem::Type { .. } => (),
+            }
+        }
+        Ok(funcs)
+    }
+
+    fn visit(&self, ty: &Type, types: &mut BTreeSet<TypeId>) {
+        let Type::Id(id) = ty else {
+            return;
+        };
+        let def = &self.resolve.types[*id];
+        if def.name.is_some() && !types.insert(*id) {
+            return;
+        }
+        match &def.kind {
+            TypeDefKind::Record(r) => r.fields.iter().for_each(|f| self.visit(&f.ty, types)),
+            TypeDefKind::Variant(v) => v
+                .cases
+                .iter()
+                .filter_map(|c| c.ty.as_ref())
+                .for_each(|t| self.visit(t, types)),
+            TypeDefKind::Tuple(t) => t.types.iter().for_each(|t| self.visit(t, types)),
+            TypeDefKind::Option(t) | TypeDefKind::List(t) | TypeDefKind::Type(t) => {
+                self.visit(t, types)
+            }
+            TypeDefKind::FixedLengthList(t, _) => self.visit(t, types),
+            TypeDefKind::Result(r) => {
+                r.ok.iter()
+                    .chain(r.err.iter())
+                    .for_each(|t| self.visit(t, types));
+            }
+            _ => (),
+        }
+    }
+
+    fn docs(&mut self, docs: &wit_parser::Docs, indent: &str) -> Result<(), Error> {
+        if let Some(docs) = &docs.contents {
+            for line in docs.trim().lines() {
+                writeln!(self.out, "{indent}/// {}", line.trim_end())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn typedef(&mut self, id: TypeId) -> Result<(), Error> {
+        let def = &self.resolve.types[id];
+        let name = camel(def.name.as_deref().unwrap_or_default());
+        writeln!(self.out)?;
+        self.docs(&def.docs, "")?;
+
+        const DERIVE: &str = "#[derive(Debug, Clone, PartialEq, ::serde::Serialize, ::serde::Deserialize)]\n#[serde(rename_all = \"kebab-case\")]";
+        match &def.kind {
+            TypeDefKind::Record(r) => {
+                writeln!(self.out, "{DERIVE}\npub struct {name} {{")?;
+                for field in r.fields.iter() {
+                    self.docs(&field.docs, "    ")?;
+                    let ty = self.rust_type(&field.ty)?;
+                    writeln!(self.out, "    pub {}: {ty},", snake(&field.name))?;
+                }
+                writeln!(self.out, "}}")?;
+            }
+            TypeDefKind::Variant(v) => {
+                writeln!(self.out, "{DERIVE}\npub enum {name} {{")?;
+                for case in v.cases.iter() {
+                    self.docs(&case.docs, "    ")?;
+                    match &case.ty {
+                        Some(ty) => {
+                            let ty = self.rust_type(ty)?;
+                            writeln!(self.out, "    {}({ty}),", camel(&case.name))?
+                        }
+                        None => writeln!(self.out, "    {},", camel(&case.name))?,
+                    }
+                }
+                writeln!(self.out, "}}")?;
+            }
+            TypeDefKind::Enum(e) => {
+                writeln!(
+                    self.out,
+                    "#[derive(Debug, Clone, Copy, PartialEq, Eq, ::serde::Serialize, ::serde::Deserialize)]\n#[serde(rename_all = \"kebab-case\")]\npub enum {name} {{"
+                )?;
+                for case in e.cases.iter() {
+                    self.docs(&case.docs, "    ")?;
+                    writeln!(self.out, "    {},", camel(&case.name))?;
+                }
+                writeln!(self.out, "}}")?;
+            }
+            _ => {
+                let ty = self.rust_kind(&def.kind)?;
+                writeln!(self.out, "pub type {name} = {ty};")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn rust_type(&self, ty: &Type) -> Result<String, Error> {
+        Ok(match ty {
+            Type::Bool => "bool".into(),
+            Type::U8 => "u8".into(),
+            Type::U16 => "u16".into(),
+            Type::U32 => "u32".into(),
+            Type::U64 => "u64".into(),
+            Type::S8 => "i8".into(),
+            Type::S16 => "i16".into(),
+            Type::S32 => "i32".into(),
+            Type::S64 => "i64".into(),
+            Type::F32 => "f32".into(),
+            Type::F64 => "f64".into(),
+            Type::Char => "char".into(),
+            Type::String => "String".into(),
+            Type::ErrorContext => anyhow::bail!("unsupported WIT type: error-context"),
+            Type::Id(id) => {
+                let def = &self.resolve.types[*id];
+                match &def.name {
+                    Some(name) => camel(name),
+                    None => self.rust_kind(&def.kind)?,
+                }
+            }
+        })
+    }
+
+    fn rust_kind(&self, kind: &TypeDefKind) -> Result<String, Error> {
+        let opt = |ty: &Option<Type>| match ty {
+            Some(ty) => self.rust_type(ty),
+            None => Ok("()".to_string()),
+        };
+        Ok(match kind {
+            TypeDefKind::Type(ty) => self.rust_type(ty)?,
+            TypeDefKind::List(ty) | TypeDefKind::FixedLengthList(ty, _) => {
+                format!("Vec<{}>", self.rust_type(ty)?)
+            }
+            TypeDefKind::Option(ty) => format!("Option<{}>", self.rust_type(ty)?),
+            TypeDefKind::Result(r) => format!("Result<{}, {}>", opt(&r.ok)?, opt(&r.err)?),
+            TypeDefKind::Tuple(t) => {
+                let types = t
+                    .types
+                    .iter()
+                    .map(|t| self.rust_type(t))
+                    .collect::<Result<Vec<_>, _>>()?;
+                format!("({},)", types.join(", "))
+            }
+            TypeDefKind::Record(_) | TypeDefKind::Variant(_) | TypeDefKind::Enum(_) => {
+                anyhow::bail!("anonymous WIT types must be named")
+            }
+            kind => anyhow::bail!("unsupported WIT type: {}", kind.as_str()),
+        })
+    }
+
+    // `string`, `list<u8>` and types with `ToBytes`/`FromBytes` implementations are passed directly,
+    // everything else is JSON encoded
+    fn is_raw(&self, ty: &Type) -> bool {
+        match ty {
+            Type::String
+            | Type::Bool
+            | Type::U32
+            | Type::U64
+            | Type::S32
+            | Type::S64
+            | Type::F32
+            | Type::F64 => true,
+            Type::Id(id) => match &self.resolve.types[*id].kind {
+                TypeDefKind::Type(ty) => self.is_raw(ty),
+                TypeDefKind::List(Type::U8) => true,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn is_simple(&self, ty: &Type) -> bool {
+        self.is_raw(ty)
+            && self
+                .rust_type(ty)
+                .is_ok_and(|x| x != "String" && x != "Vec<u8>")
+    }
+
+    // The wire type used when reading a value
+    fn wire_type(&self, ty: &Type) -> Result<String, Error> {
+        let rust = self.rust_type(ty)?;
+        if self.is_raw(ty) {
+            Ok(rust)
+        } else {
+            Ok(format!("::extism::convert::Json<{rust}>"))
+        }
+    }
+
+    fn imports_trait(&mut self, world: &str, imports: &[Func]) -> Result<(), Error> {
+        let name = camel(world);
+        writeln!(self.out)?;
+        writeln!(
+            self.out,
+            "/// Host functions imported by the `{world}` world, register them using `{name}::add_imports`"
+        )?;
+        writeln!(self.out, "#[allow(clippy::too_many_arguments)]")?;
+        writeln!(
+            self.out,
+            "pub trait {name}Imports: Send + Sync + 'static {{"
+        )?;
+        for (i, f) in imports.iter().enumerate() {
+            if i > 0 {
+                writeln!(self.out)?;
+            }
+            self.docs(&f.func.docs, "    ")?;
+            let mut params = vec!["&self".to_string()];
+            for p in f.func.params.iter() {
+                params.push(format!("{}: {}", snake(&p.name), self.rust_type(&p.ty)?));
+            }
+            let ret = match &f.func.result {
+                Some(ty) => self.rust_type(ty)?,
+                None => "()".into(),
+            };
+            writeln!(
+                self.out,
+                "    fn {}({}) -> Result<{ret}, ::extism::Error>;",
+                f.rust_name,
+                params.join(", ")
+            )?;
+        }
+        writeln!(self.out, "}}")?;
+        Ok(())
+    }
+
+    fn plugin_struct(
+        &mut self,
+        world: &str,
+        imports: &[Func],
+        exports: &[Func],
+    ) -> Result<(), Error> {
+        let name = camel(world);
+        writeln!(self.out)?;
+        writeln!(
+            self.out,
+            "/// Typed wrapper for plugins implementing the `{world}` world\npub struct {name} {{\n    plugin: ::extism::Plugin,\n}}"
+        )?;
+        writeln!(self.out)?;
+        writeln!(self.out, "#[allow(clippy::too_many_arguments)]")?;
+        writeln!(self.out, "impl {name} {{")?;
+        writeln!(
+            self.out,
+            "    /// Wrap an existing plugin\n    pub fn new(plugin: ::extism::Plugin) -> Self {{\n        {name} {{ plugin }}\n    }}"
+        )?;
+        writeln!(self.out)?;
+        writeln!(
+            self.out,
+            "    /// Get the underlying plugin\n    pub fn plugin(&mut self) -> &mut ::extism::Plugin {{\n        &mut self.plugin\n    }}"
+        )?;
+
+        if !imports.is_empty() {
+            writeln!(self.out)?;
+            writeln!(
+                self.out,
+                "    /// Register the host functions imported by the world\n    pub fn add_imports(\n        builder: ::extism::PluginBuilder,\n        imports: impl {name}Imports,\n    ) -> ::extism::PluginBuilder {{"
+            )?;
+            writeln!(
+                self.out,
+                "        let imports: ::std::sync::Arc<dyn {name}Imports> = ::std::sync::Arc::new(imports);\n        let mut builder = builder;"
+            )?;
+            for f in imports {
+                self.import_function(f)?;
+            }
+            writeln!(self.out, "        builder\n    }}")?;
+        }
+
+        for f in exports {
+            self.export_function(f)?;
+        }
+        writeln!(self.out, "}}")?;
+        Ok(())
+    }
+
+    fn import_function(&mut self, f: &Func) -> Result<(), Error> {
+        let params = vec!["::extism::PTR"; f.func.params.len()].join(", ");
+        let results = if f.func.result.is_some() {
+            "::extism::PTR"
+        } else {
+            ""
+        };
+        writeln!(
+            self.out,
+            "        {{\n            let imports = imports.clone();\n            builder = builder.with_function_in_namespace(\n                {:?},\n                {:?},\n                [{params}] as [::extism::ValType; {}],\n                [{results}] as [::extism::ValType; {}],\n                ::extism::UserData::new(()),",
+            f.namespace,
+            f.func.name,
+            f.func.params.len(),
+            f.func.result.is_some() as usize,
+        )?;
+        let outputs = if f.func.result.is_some() {
+            "outputs"
+        } else {
+            "_outputs"
+        };
+        writeln!(
+            self.out,
+            "                move |plugin: &mut ::extism::CurrentPlugin,\n                      inputs: &[::extism::Val],\n                      {outputs}: &mut [::extism::Val],\n                      _: ::extism::UserData<()>| {{"
+        )?;
+        let mut args = vec![];
+        for (i, p) in f.func.params.iter().enumerate() {
+            let arg = snake(&p.name);
+            let wire = self.wire_type(&p.ty)?;
+            let unwrap = if self.is_raw(&p.ty) { "" } else { ".0" };
+            writeln!(
+                self.out,
+                "                    let {arg} = plugin.memory_get_val::<{wire}>(&inputs[{i}])?{unwrap};"
+            )?;
+            args.push(arg);
+        }
+        let call = format!("imports.{}({})?", f.rust_name, args.join(", "));
+        match &f.func.result {
+            Some(ty) if self.is_raw(ty) => writeln!(
+                self.out,
+                "                    let output = {call};\n                    plugin.memory_set_val(&mut outputs[0], output)?;"
+            )?,
+            Some(_) => writeln!(
+                self.out,
+                "                    let output = {call};\n                    plugin.memory_set_val(&mut outputs[0], ::extism::convert::Json(output))?;"
+            )?,
+            None => writeln!(self.out, "                    {call};")?,
+        }
+        writeln!(
+            self.out,
+            "                    Ok(())\n                }},\n            );\n        }}"
+        )?;
+        Ok(())
+    }
+
+    fn export_function(&mut self, f: &Func) -> Result<(), Error> {
+        writeln!(self.out)?;
+        self.docs(&f.func.docs, "    ")?;
+        if f.func.docs.contents.is_none() {
+            writeln!(self.out, "    /// Call `{}`", f.func.name)?;
+        }
+
+        let mut params = vec!["&mut self".to_string()];
+        let mut args = vec![];
+        for p in f.func.params.iter() {
+            let ty = self.rust_type(&p.ty)?;
+            let ty = match ty.as_str() {
+                "String" => "&str".to_string(),
+                "Vec<u8>" if self.is_raw(&p.ty) => "&[u8]".to_string(),
+                _ if self.is_simple(&p.ty) => ty,
+                _ => format!("&{ty}"),
+            };
+            params.push(format!("{}: {ty}", snake(&p.name)));
+            args.push(snake(&p.name));
+        }
+        let input = match f.func.params.as_slice() {
+            [] => "()".to_string(),
+            [p] if self.is_raw(&p.ty) => args[0].clone(),
+            [_] => format!("::extism::convert::Json({})", args[0]),
+            _ => format!("::extism::convert::Json(({},))", args.join(", ")),
+        };
+
+        let (ret, wire, unwrap) = match &f.func.result {
+            Some(ty) if self.is_raw(ty) => (self.rust_type(ty)?, self.rust_type(ty)?, ""),
+            Some(ty) => (self.rust_type(ty)?, self.wire_type(ty)?, ".0"),
+            None => ("()".to_string(), "()".to_string(), ""),
+        };
+        writeln!(
+            self.out,
+            "    pub fn {}({}) -> Result<{ret}, ::extism::Error> {{",
+            f.rust_name,
+            params.join(", ")
+        )?;
+        writeln!(
+            self.out,
+            "        let output: {wire} = self.plugin.call({:?}, {input})?;\n        Ok(output{unwrap})\n    }}",
+            f.func.name
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,281 @@
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+use crate::pdk::args;
+use crate::*;
+
+/// Namespace for the WebSocket host functions
+pub const EXTISM_WS_MODULE: &str = "extism:host/ws";
+
+/// Limits for the `extism:host/ws` functions, see `PluginBuilder::with_websockets`
+///
+/// Connections are only allowed to hosts in the manifest's `allowed_hosts`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WebSocketLimits {
+    /// Maximum number of open connections, the default is 16
+    pub max_connections: usize,
+
+    /// Maximum size of a single message in bytes, the default is 16MiB
+    pub max_message_bytes: usize,
+
+    /// Maximum number of bytes sent and received over a single connection, there is no limit by default
+    pub max_total_bytes: Option<u64>,
+}
+
+impl Default for WebSocketLimits {
+    fn default() -> Self {
+        WebSocketLimits {
+            max_connections: 16,
+            max_message_bytes: 16 * 1024 * 1024,
+            max_total_bytes: None,
+        }
+    }
+}
+
+struct Connection {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    bytes: u64,
+}
+
+impl Connection {
+    fn count(&mut self, n: usize, limits: &WebSocketLimits) -> Result<(), Error> {
+        self.bytes += n as u64;
+        if let Some(max) = limits.max_total_bytes {
+            if self.bytes > max {
+                anyhow::bail!("WebSocket connection exceeded the limit of {max} bytes");
+            }
+        }
+        Ok(())
+    }
+
+    fn tcp(&self) -> &TcpStream {
+        match self.socket.get_ref() {
+            MaybeTlsStream::Plain(s) => s,
+            MaybeTlsStream::Rustls(s) => s.get_ref(),
+            _ => unreachable!("unsupported WebSocket stream"),
+        }
+    }
+}
+
+/// Open WebSocket connections for a plugin
+pub(crate) struct WsState {
+    limits: WebSocketLimits,
+    connections: BTreeMap<u64, Connection>,
+    next_id: u64,
+}
+
+impl WsState {
+    pub(crate) fn new(limits: WebSocketLimits) -> Self {
+        WsState {
+            limits,
+            connections: BTreeMap::new(),
+            next_id: 1,
+        }
+    }
+
+    fn get(&mut self, id: u64) -> Result<(&mut Connection, &WebSocketLimits), Error> {
+        match self.connections.get_mut(&id) {
+            Some(x) => Ok((x, &self.limits)),
+            None => anyhow::bail!("invalid WebSocket ID: {id}"),
+        }
+    }
+}
+
+// Socket timeouts can't be zero, so the shortest timeout is used once the call is out of time
+const MIN_TIMEOUT: Duration = Duration::from_millis(1);
+
+// Connect to the first address that accepts the connection within the time left for the call
+fn connect_tcp(addrs: &[SocketAddr], timeout: Option<Duration>) -> Result<TcpStream, Error> {
+    let mut err = None;
+    for addr in addrs {
+        let res = match timeout {
+            Some(t) => TcpStream::connect_timeout(addr, t.max(MIN_TIMEOUT)),
+            None => TcpStream::connect(addr),
+        };
+        match res {
+            Ok(stream) => {
+                stream.set_read_timeout(timeout.map(|t| t.max(MIN_TIMEOUT)))?;
+                stream.set_write_timeout(timeout.map(|t| t.max(MIN_TIMEOUT)))?;
+                return Ok(stream);
+            }
+            Err(e) => err = Some(e),
+        }
+    }
+    match err {
+        Some(e) => Err(e.into()),
+        None => anyhow::bail!("no addresses to connect to"),
+    }
+}
+
+// Host functions, these are linked into the `extism:host/ws` namespace when WebSockets are enabled
+
+fn state(data: &mut CurrentPlugin) -> Result<&mut WsState, Error> {
+    match &mut data.ws {
+        Some(x) => Ok(x),
+        None => anyhow::bail!("WebSockets are not enabled"),
+    }
+}
+
+fn read_bytes(data: &mut CurrentPlugin, offset: u64, what: &str) -> Result<Vec<u8>, Error> {
+    let handle = match data.memory_handle(offset) {
+        Some(h) => h,
+        None => anyhow::bail!("invalid handle offset for {what}: {offset}"),
+    };
+    let bytes = data.memory_bytes(handle)?.to_vec();
+    data.memory_free(handle)?;
+    Ok(bytes)
+}
+
+/// Open a WebSocket connection
+/// Params: i64 (URL offset, `ws://` or `wss://`)
+/// Returns: i64 (connection ID)
+/// **Note**: this function takes ownership of the handle passed in
+/// the caller should not `free` this value
+pub(crate) fn connect(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let url = String::from_utf8(read_bytes(data, args!(input, 0, i64) as u64, "URL")?)?;
+    let parsed = match url::Url::parse(&url) {
+        Ok(u) => u,
+        Err(e) => return Err(Error::msg(format!("Invalid URL: {e:?}"))),
+    };
+    if !matches!(parsed.scheme(), "ws" | "wss") {
+        anyhow::bail!("invalid WebSocket URL: {url}");
+    }
+    let host = parsed.host_str().unwrap_or_default();
+    if !pdk::host_allowed(&data.manifest.allowed_hosts, host) {
+        anyhow::bail!("WebSocket connection to {url} is not allowed");
+    }
+
+    let timeout = data.time_remaining();
+    let state = state(data)?;
+    if state.connections.len() >= state.limits.max_connections {
+        anyhow::bail!("too many open WebSocket connections");
+    }
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(state.limits.max_message_bytes))
+        .max_frame_size(Some(state.limits.max_message_bytes));
+
+    // The connection and handshake are limited to the time left for the call, redirects aren't followed
+    // since they could point to a host that isn't allowed
+    let stream = connect_tcp(&parsed.socket_addrs(|| None)?, timeout)?;
+    let (socket, _) =
+        tungstenite::client_tls_with_config(url.as_str(), stream, Some(config), None)?;
+    let id = state.next_id;
+    state.next_id += 1;
+    state
+        .connections
+        .insert(id, Connection { socket, bytes: 0 });
+    output[0] = Val::I64(id as i64);
+    Ok(())
+}
+
+/// Send a message
+/// Params: i64 (connection ID), i64 (message offset), i32 (0 for a text message, 1 for binary)
+/// Returns: none
+/// **Note**: this function takes ownership of the handle passed in
+/// the caller should not `free` this value
+pub(crate) fn send(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    _output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let id = args!(input, 0, i64) as u64;
+    let payload = read_bytes(data, args!(input, 1, i64) as u64, "message")?;
+    let binary = args!(input, 2, i32) != 0;
+    let timeout = data.time_remaining();
+    let (conn, limits) = state(data)?.get(id)?;
+    conn.tcp()
+        .set_write_timeout(timeout.map(|t| t.max(MIN_TIMEOUT)))?;
+    if payload.len() > limits.max_message_bytes {
+        anyhow::bail!(
+            "WebSocket message exceeds the limit of {} bytes",
+            limits.max_message_bytes
+        );
+    }
+    conn.count(payload.len(), limits)?;
+    let message = if binary {
+        Message::Binary(payload.into())
+    } else {
+        Message::Text(String::from_utf8(payload)?.into())
+    };
+    conn.socket.send(message)?;
+    Ok(())
+}
+
+/// Receive the next text or binary message
+/// Params: i64 (connection ID), i64 (timeout in milliseconds, 0 to return immediately)
+/// Returns: i64 (message offset), 0 if the timeout elapsed or -1 if the connection was closed
+pub(crate) fn recv(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let id = args!(input, 0, i64) as u64;
+    let timeout = args!(input, 1, i64).max(0) as u64;
+    let clamped = data.clamp_timeout(Duration::from_millis(timeout));
+    let (conn, limits) = state(data)?.get(id)?;
+    conn.tcp().set_nonblocking(timeout == 0)?;
+    if timeout > 0 {
+        conn.tcp()
+            .set_read_timeout(Some(clamped.max(MIN_TIMEOUT)))?;
+    }
+
+    let payload = loop {
+        match conn.socket.read() {
+            Ok(Message::Text(s)) => break Some(s.as_bytes().to_vec()),
+            Ok(Message::Binary(b)) => break Some(b.to_vec()),
+            Ok(Message::Close(_)) => break None,
+            // Pings are answered automatically
+            Ok(_) => continue,
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                output[0] = Val::I64(0);
+                return Ok(());
+            }
+            Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => {
+                break None
+            }
+            Err(e) => return Err(e.into()),
+        }
+    };
+
+    output[0] = match payload {
+        Some(payload) => {
+            conn.count(payload.len(), limits)?;
+            Val::I64(data.memory_new(payload)?.offset() as i64)
+        }
+        None => Val::I64(-1),
+    };
+    Ok(())
+}
+
+/// Close a connection
+/// Params: i64 (connection ID)
+/// Returns: none
+pub(crate) fn close(
+    mut caller: Caller<CurrentPlugin>,
+    input: &[Val],
+    _output: &mut [Val],
+) -> Result<(), Error> {
+    let data: &mut CurrentPlugin = caller.data_mut();
+    let id = args!(input, 0, i64) as u64;
+    if let Some(mut conn) = state(data)?.connections.remove(&id) {
+        // The connection is dropped either way, so errors sending the close frame are ignored
+        let _ = conn.tcp().set_nonblocking(false);
+        let _ = conn.socket.close(None);
+        let _ = conn.socket.flush();
+    }
+    Ok(())
+}